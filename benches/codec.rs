@@ -0,0 +1,43 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rust_htmx::{
+    db::codec::{Codec, Keyring},
+    models::Todo,
+};
+
+// roughly the size of a todo with a few paragraphs of notes
+fn large_todo() -> Todo {
+    let title =
+        "Write down the meeting notes, follow up with everyone and file the tickets. ".repeat(32);
+    Todo::new(1, title)
+}
+
+fn codecs() -> Vec<(&'static str, Codec)> {
+    let keyring = || Keyring::from_keys(vec![vec![7; 32]]).unwrap();
+    vec![
+        ("plain", Codec::new()),
+        ("zstd", Codec::new().with_compression(512)),
+        ("encrypted", Codec::new().with_keyring(keyring())),
+        (
+            "zstd+encrypted",
+            Codec::new().with_compression(512).with_keyring(keyring()),
+        ),
+    ]
+}
+
+fn bench_codec(c: &mut Criterion) {
+    let todo = large_todo();
+    let context = b"tenant:bench\0todo:1";
+    for (name, codec) in codecs() {
+        let bytes = codec.encode(&todo, context).unwrap();
+        println!("{}: {} bytes on disk", name, bytes.len());
+        c.bench_function(&format!("encode/{}", name), |b| {
+            b.iter(|| codec.encode(black_box(&todo), context).unwrap())
+        });
+        c.bench_function(&format!("decode/{}", name), |b| {
+            b.iter(|| codec.decode::<Todo>(black_box(&bytes), context).unwrap())
+        });
+    }
+}
+
+criterion_group!(benches, bench_codec);
+criterion_main!(benches);
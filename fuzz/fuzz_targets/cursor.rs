@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_htmx::repository::query::{Sort, SortKey};
+
+// `?after=` and `?before=` come straight from api clients
+fuzz_target!(|cursor: &str| {
+    for sort in [Sort::Created, Sort::Due, Sort::Updated] {
+        if let Ok(key) = SortKey::parse(cursor, sort) {
+            assert_eq!(SortKey::parse(&key.cursor(sort), sort).ok(), Some(key));
+        }
+    }
+});
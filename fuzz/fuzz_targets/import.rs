@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_htmx::import;
+
+// a pasted export, then duplicate detection of it against itself
+fuzz_target!(|data: &str| {
+    if let Ok(todos) = import::parse_export(data) {
+        for todo in &todos {
+            let _ = import::normalize_title(&todo.title);
+            let _ = import::find_duplicate(&todos, todo);
+        }
+    }
+});
@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_htmx::previews;
+
+// pages fetched for link previews are whatever a third party serves
+fuzz_target!(|html: &str| {
+    let _ = previews::parse("https://example.com", html);
+});
@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_htmx::{models, previews};
+
+// everything a typed title goes through before it is stored
+fuzz_target!(|title: &str| {
+    let (rest, _) = models::parse_near(title);
+    let _ = previews::find_urls(&rest);
+    let _ = models::parse_due(title);
+});
@@ -0,0 +1,11 @@
+#!/bin/sh
+# Download the scripts `--features embed-assets` compiles into the binary, see src/assets.rs.
+set -eu
+dir="$(dirname "$0")/../assets"
+mkdir -p "$dir"
+cd "$dir"
+curl -fsSL -o htmx.min.js https://unpkg.com/htmx.org@1.9.10/dist/htmx.min.js
+curl -fsSL -o json-enc.js https://unpkg.com/htmx.org@1.9.10/dist/ext/json-enc.js
+curl -fsSL -o sse.js https://unpkg.com/htmx.org@1.9.10/dist/ext/sse.js
+curl -fsSL -o idiomorph-ext.min.js https://unpkg.com/idiomorph@0.3.0/dist/idiomorph-ext.min.js
+curl -fsSL -o tailwind.js https://cdn.tailwindcss.com/3.4.1
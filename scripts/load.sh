@@ -0,0 +1,41 @@
+#!/bin/sh
+# Load the request timeouts and the concurrency limit (src/limits.rs) with oha
+# (`cargo install oha`). A release build runs on its own port in a scratch directory with
+# CONCURRENCY_LIMIT set to `limit`, then:
+#
+#   1. reads at half the limit, every response should be a 200
+#   2. reads at four times the limit, the excess is shed with a 503 and Retry-After
+#   3. creates at the limit, none should run into WRITE_TIMEOUT_SECS (a 408)
+#
+# oha prints the latency histogram and the status code distribution of each run.
+#
+#     scripts/load.sh [limit] [seconds per run]
+set -eu
+limit="${1:-16}"
+duration="${2:-10}"
+port="${LOAD_PORT:-3999}"
+url="http://127.0.0.1:$port"
+root="$(cd "$(dirname "$0")/.." && pwd)"
+
+cargo build --release --manifest-path "$root/Cargo.toml"
+dir="$(mktemp -d)"
+cd "$dir"
+LISTEN_ADDR="127.0.0.1:$port" CONCURRENCY_LIMIT="$limit" \
+    READ_TIMEOUT_SECS="${READ_TIMEOUT_SECS:-10}" WRITE_TIMEOUT_SECS="${WRITE_TIMEOUT_SECS:-5}" \
+    "$root/target/release/rust-htmx" >server.log 2>&1 &
+server=$!
+trap 'kill $server; wait $server 2>/dev/null; rm -rf "$dir"' EXIT
+until curl -fs -o /dev/null "$url/"; do sleep 0.2; done
+
+# something to list
+for i in $(seq 200); do
+    curl -fs -o /dev/null -X PUT -H 'HX-Request: true' -d "title=Seeded todo $i" "$url/create_todo"
+done
+
+echo "== reads at $((limit / 2)) connections, below the limit of $limit"
+oha --no-tui -z "${duration}s" -c "$((limit / 2))" "$url/todos"
+echo "== reads at $((limit * 4)) connections, four times the limit of $limit"
+oha --no-tui -z "${duration}s" -c "$((limit * 4))" "$url/todos"
+echo "== creates at $limit connections"
+oha --no-tui -z "${duration}s" -c "$limit" -m PUT -H 'HX-Request: true' \
+    -T 'application/x-www-form-urlencoded' -d 'title=Load test' "$url/create_todo"
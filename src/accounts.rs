@@ -0,0 +1,283 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use axum::{
+    extract::{FromRequest, Request, State},
+    response::{IntoResponse, Redirect, Response},
+    Form,
+};
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+use maud::{html, Markup};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    admin::passkeys,
+    auth::{self, password, remember, session, throttle::LoginThrottle},
+    config::AuthMode,
+    db::driver::Db,
+    error::AppError,
+    routes,
+    services::auth::{normalize_name, AuthService, MIN_PASSWORD_LEN},
+    state::AppState,
+    views,
+};
+
+// accounts are stored in the default tree, `user:{name}`
+const USER_PREFIX: &str = "user:";
+
+pub const SESSION_COOKIE: &str = "user_session";
+const SESSION_LIFETIME: Duration = Duration::from_secs(12 * 60 * 60);
+
+// === Users ===
+// With `AUTH_MODE=users` visitors sign up with a name and a password, and every account works
+// in a workspace of its own nobody else sees. The name is the account's id. Its todos are keyed
+// under it in the default tree, `user:{name}:todo:{id}`, see `Db::for_tenant`.
+//
+// Signing in starts a session with a cookie of its own, `user_session`. Its sessions are kept
+// apart from the admin ones, so it never passes for an admin session and `/admin` stays with
+// the admin accounts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct User {
+    pub name: String,
+    // argon2 in PHC string format
+    pub password_hash: String,
+    pub created_at: u64,
+}
+
+pub fn get(db: &Db, name: &str) -> Result<Option<User>> {
+    db.get(format!("{}{}", USER_PREFIX, name))
+}
+pub fn create(db: &Db, name: &str, password: &str) -> Result<User> {
+    let user = User {
+        name: name.to_string(),
+        password_hash: password::hash(password)?,
+        created_at: db.clock().now_millis() / 1000,
+    };
+    db.insert(format!("{}{}", USER_PREFIX, name), &user)?;
+    Ok(user)
+}
+// the account `name` when `password` is its password
+pub fn authenticate(db: &Db, name: &str, password: &str) -> Result<Option<User>> {
+    Ok(get(db, name)?.filter(|user| password::verify(&user.password_hash, password)))
+}
+
+// the tenant id of the account's namespace, `user:{name}`
+pub fn workspace(name: &str) -> String {
+    format!("{}{}", USER_PREFIX, name)
+}
+
+// === Sessions ===
+// Start a session of `name`, see `auth::session`.
+pub fn session_cookie(key: &[u8], db: &Db, name: &str) -> Result<Cookie<'static>> {
+    let value = session::start(db, key, SESSION_COOKIE, name, SESSION_LIFETIME)?;
+    Ok(Cookie::build((SESSION_COOKIE, value))
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .build())
+}
+
+// the account signed in with `cookie`, as long as it still exists
+pub fn session_user(key: &[u8], db: &Db, cookie: Option<&str>) -> Result<Option<String>> {
+    let Some(name) = session::account(db, key, SESSION_COOKIE, cookie)? else {
+        return Ok(None);
+    };
+    Ok(get(db, &name)?.map(|user| user.name))
+}
+
+// === Components ===
+fn form_page(title: &str, action: &str, error: Option<&str>, switch: Markup) -> Markup {
+    views::page(
+        title,
+        html! {
+            div class="bg-white rounded-lg shadow-lg p-8 max-w-md mx-auto space-y-4" {
+                h1 class="text-2xl text-gray-700" { (title) }
+                form class="space-y-4" method="post" action=(action) {
+                    input class="w-full rounded p-2 border" type="text" name="name" placeholder="Name" pattern="[a-z0-9-]+" autocomplete="username" autofocus required;
+                    input class="w-full rounded p-2 border" type="password" name="password" placeholder="Password" minlength=(MIN_PASSWORD_LEN) required;
+                    @if let Some(error) = error {
+                        p class="text-red-500" { (error) }
+                    }
+                    button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" { (title) }
+                }
+                p class="text-gray-500 text-sm" { (switch) }
+            }
+        },
+    )
+}
+
+fn signup_page(error: Option<&str>) -> Markup {
+    form_page(
+        "Sign up",
+        &routes::AccountSignup::url(),
+        error,
+        html! {
+            "Already have an account? "
+            a class="text-blue-500 hover:text-blue-700" href=(routes::AccountLogin::url()) { "Sign in" }
+        },
+    )
+}
+
+fn login_page(error: Option<&str>) -> Markup {
+    form_page(
+        "Sign in",
+        &routes::AccountLogin::url(),
+        error,
+        html! {
+            "New here? "
+            a class="text-blue-500 hover:text-blue-700" href=(routes::AccountSignup::url()) { "Sign up" }
+        },
+    )
+}
+
+// the settings section of a signed-in account
+pub fn settings_html(name: &str) -> Markup {
+    html! {
+        section class="bg-white rounded-lg shadow-lg p-6 space-y-4" {
+            h2 class="text-2xl text-gray-700" { "Account" }
+            form class="flex justify-between items-center" method="post" action=(routes::AccountLogout::url()) {
+                p class="text-gray-700" { "Signed in as " strong { (name) } }
+                button class="bg-gray-500 hover:bg-gray-700 text-white font-bold py-2 px-4 rounded" type="submit" { "Sign out" }
+            }
+        }
+    }
+}
+
+// === Routes ===
+pub async fn show_signup(State(state): State<AppState>) -> Result<Markup, AppError> {
+    AuthService::enabled(&state.config)?;
+    Ok(signup_page(None))
+}
+
+pub async fn show_login(State(state): State<AppState>) -> Result<Markup, AppError> {
+    AuthService::enabled(&state.config)?;
+    Ok(login_page(None))
+}
+
+#[derive(Deserialize)]
+pub struct Credentials {
+    name: String,
+    password: String,
+}
+pub async fn signup(
+    State(mut state): State<AppState>,
+    jar: CookieJar,
+    Form(Credentials { name, password }): Form<Credentials>,
+) -> Result<Response, AppError> {
+    let secret_key = state.secret_key.clone();
+    let config = state.config.clone();
+    let db = state.write().await;
+    let user = match AuthService::new(&config, &db).signup(&name, &password)? {
+        Ok(user) => user,
+        Err(refusal) => return Ok(signup_page(Some(refusal)).into_response()),
+    };
+    let jar = jar.add(session_cookie(&secret_key, &db, &user.name)?);
+    Ok((jar, Redirect::to(&routes::Root::url())).into_response())
+}
+
+pub async fn login(
+    State(mut state): State<AppState>,
+    jar: CookieJar,
+    request: Request,
+) -> Result<Response, AppError> {
+    AuthService::enabled(&state.config)?;
+    let mut subjects = Vec::new();
+    if let Some(ip) = auth::client_ip(request.extensions()) {
+        subjects.push(format!("ip:{}", ip));
+    }
+    let Form(Credentials { name, password }) =
+        Form::<Credentials>::from_request(request, &state).await?;
+    subjects.push(format!("user:{}", normalize_name(&name)));
+    let throttle = LoginThrottle {
+        lockout_after: state.live.load().login_lockout_after,
+    };
+    let secret_key = state.secret_key.clone();
+    let config = state.config.clone();
+    let db = state.write().await;
+    let Some(user) =
+        AuthService::new(&config, &db).login(&throttle, &name, &password, &subjects)?
+    else {
+        return Ok(login_page(Some("That name and password don't match.")).into_response());
+    };
+    let jar = jar.add(session_cookie(&secret_key, &db, &user.name)?);
+    Ok((jar, Redirect::to(&routes::Root::url())).into_response())
+}
+
+// End the session, and forget this device if it was remembered.
+pub async fn logout(
+    State(mut state): State<AppState>,
+    jar: CookieJar,
+) -> Result<Response, AppError> {
+    let secret_key = state.secret_key.clone();
+    let db = state.write().await;
+    for cookie in [SESSION_COOKIE, passkeys::SESSION_COOKIE] {
+        if let Some(value) = jar.get(cookie) {
+            session::end(&db, &secret_key, cookie, value.value())?;
+        }
+    }
+    if let Some(cookie) = jar.get(passkeys::REMEMBER_COOKIE) {
+        remember::forget(&db, cookie.value())?;
+    }
+    drop(db);
+    let jar = jar
+        .remove(Cookie::build(SESSION_COOKIE).path("/"))
+        .remove(Cookie::build(passkeys::SESSION_COOKIE).path("/"))
+        .remove(Cookie::build(passkeys::REMEMBER_COOKIE).path("/"));
+    let to = match state.config.auth_mode {
+        AuthMode::Users => routes::AccountLogin::url(),
+        _ => routes::Root::url(),
+    };
+    Ok((jar, Redirect::to(&to)).into_response())
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::{TestDb, TodoFixture},
+        repository,
+    };
+
+    #[test]
+    fn test_accounts() -> Result<()> {
+        let db = TestDb::new("accounts")?;
+        assert!(get(&db, "ada")?.is_none());
+        create(&db, "ada", "correct horse battery")?;
+        assert!(authenticate(&db, "ada", "correct horse battery")?.is_some());
+        assert!(authenticate(&db, "ada", "correct horse")?.is_none());
+        assert!(authenticate(&db, "bob", "correct horse battery")?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_workspaces_are_apart() -> Result<()> {
+        let db = TestDb::new("account_workspaces")?;
+        create(&db, "ada", "correct horse battery")?;
+        create(&db, "bob", "correct horse battery")?;
+        TodoFixture::new().persist(&db.for_tenant(Some(&workspace("ada")))?)?;
+        let bob = db.for_tenant(Some(&workspace("bob")))?;
+        assert!(db.iter_keys("user:ada:todo:").next().is_some());
+        assert!(repository::todo::all(&bob)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sessions_are_not_admin_sessions() -> Result<()> {
+        let db = TestDb::new("account_sessions")?;
+        create(&db, "ada", "correct horse battery")?;
+        let cookie = session_cookie(b"key", &db, "ada")?;
+        assert_eq!(
+            session_user(b"key", &db, Some(cookie.value()))?,
+            Some("ada".into())
+        );
+        assert_eq!(
+            passkeys::session_account(b"key", &db, Some(cookie.value()))?,
+            None
+        );
+        // a signed-in account that is gone no longer counts
+        let stranger = session_cookie(b"key", &db, "bob")?;
+        assert_eq!(session_user(b"key", &db, Some(stranger.value()))?, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,507 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use axum::{
+    extract::{Query, Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::Response,
+};
+use maud::{html, Markup};
+use serde::{Deserialize, Serialize};
+use time::Date;
+
+use crate::{
+    auth::user::CurrentUser,
+    db::{driver::Db, queue::WriteOp},
+    domain::events::{self, DomainEvent},
+    error::AppError,
+    lists,
+    locale::Formatter,
+    models::{self, Todo},
+    repository::query::TodoQuery,
+    retention::Volume,
+    routes,
+    state::AppState,
+    tenant::Tenant,
+    views::{
+        self,
+        nav::{self, Nav},
+    },
+};
+
+// todos shown in the preview
+const PREVIEW_LEN: usize = 5;
+// `activity:{list}:{at}:{id}`, the list empty for todos without one, so a list's activity is a
+// prefix scan
+const ACTIVITY_PREFIX: &str = "activity:";
+// the same entries by todo, `todo_activity:{todo}:{at}:{id}`
+const TODO_ACTIVITY_PREFIX: &str = "todo_activity:";
+// entries the feed shows at most, the newest
+const MAX_ENTRIES: usize = 200;
+const FEED_ID: &str = "activity-feed";
+// the actor of changes made outside a signed-in request
+const NO_ACTOR: &str = "clients and jobs";
+
+fn list_prefix(list: Option<&str>) -> String {
+    format!("{}{}:", ACTIVITY_PREFIX, list.unwrap_or_default())
+}
+fn todo_prefix(todo: u64) -> String {
+    format!("{}{}:", TODO_ACTIVITY_PREFIX, todo)
+}
+
+// === Actors ===
+// Who the request being handled is for, so whatever it changes is logged under them without
+// every handler passing it along. Outside a request, e.g. in a job, there is nobody.
+tokio::task_local! {
+    static ACTOR: Option<String>;
+}
+
+pub fn actor() -> Option<String> {
+    ACTOR.try_with(Clone::clone).ok().flatten()
+}
+
+// Run the request as the signed-in account, or the owner. Goes inside `user::require`, which
+// resolves it.
+pub async fn layer(request: Request, next: Next) -> Response {
+    let actor = request
+        .extensions()
+        .get::<CurrentUser>()
+        .map(|user| user.account().unwrap_or("owner").to_string());
+    ACTOR.scope(actor, next.run(request)).await
+}
+
+// === Activity log ===
+// One change to one todo: who made it, what it was and the list the todo was in. Written with
+// the change itself, see `events::ops`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub id: u64,
+    // unix seconds
+    pub at: u64,
+    pub todo: u64,
+    // as it was then, the todo may be gone
+    pub title: String,
+    // the event name, `todo.completed`
+    pub action: String,
+    pub actor: Option<String>,
+    pub list: Option<String>,
+}
+impl Entry {
+    // `completed`
+    pub fn verb(&self) -> &str {
+        action_label(&self.action)
+    }
+    pub fn actor_label(&self) -> &str {
+        self.actor.as_deref().unwrap_or(NO_ACTOR)
+    }
+}
+
+fn action_label(action: &str) -> &str {
+    action.rsplit('.').next().unwrap_or(action)
+}
+
+// The writes logging `event`, under the actor of the current request. Events not about a todo
+// are left out.
+pub fn record_ops(db: &Db, event: &DomainEvent) -> Result<Vec<WriteOp>> {
+    record_ops_by(db, event, actor().as_deref())
+}
+
+// the same under `actor`, for changes a job makes on its own
+pub fn record_ops_by(db: &Db, event: &DomainEvent, actor: Option<&str>) -> Result<Vec<WriteOp>> {
+    let Some(todo) = event.todo() else {
+        return Ok(Vec::new());
+    };
+    let entry = Entry {
+        id: db.next_id()?,
+        at: db.clock().now_millis() / 1000,
+        todo: todo.id,
+        title: todo.title.clone(),
+        action: event.name().to_string(),
+        actor: actor.map(str::to_string),
+        list: todo.list.clone(),
+    };
+    let value = db.encode(&entry)?;
+    Ok(vec![
+        WriteOp::Insert {
+            key: format!(
+                "{}{:020}:{}",
+                list_prefix(entry.list.as_deref()),
+                entry.at,
+                entry.id
+            ),
+            value: value.clone(),
+        },
+        WriteOp::Insert {
+            key: format!("{}{:020}:{}", todo_prefix(entry.todo), entry.at, entry.id),
+            value,
+        },
+    ])
+}
+
+// what happened in `list`, oldest first
+pub fn in_list(db: &Db, list: &str) -> Result<Vec<Entry>> {
+    db.iter_prefix::<Entry>(&list_prefix(Some(list)))?
+        .map(|item| item.map(|(_, entry)| entry))
+        .collect()
+}
+
+// what happened to `todo`, newest first
+pub fn for_todo(db: &Db, todo: u64) -> Result<Vec<Entry>> {
+    let mut entries = db
+        .iter_prefix::<Entry>(&todo_prefix(todo))?
+        .map(|item| item.map(|(_, entry)| entry))
+        .collect::<Result<Vec<_>>>()?;
+    entries.reverse();
+    Ok(entries)
+}
+
+// === Filters ===
+// What the feed narrows down to, each left out when empty. Days are the workspace's and both
+// ends are included.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct Filter {
+    #[serde(default)]
+    pub actor: String,
+    #[serde(default)]
+    pub action: String,
+    #[serde(default)]
+    pub list: String,
+    #[serde(default)]
+    pub from: String,
+    #[serde(default)]
+    pub to: String,
+}
+impl Filter {
+    fn days(&self) -> Result<(Option<Date>, Option<Date>), AppError> {
+        let day = |value: &str| {
+            models::parse_due(value).map_err(|err| AppError::Invalid(err.to_string()))
+        };
+        Ok((day(&self.from)?, day(&self.to)?))
+    }
+}
+
+// The entries of a filter, newest first and at most `MAX_ENTRIES`, with how many there are per
+// actor and per action once list and days are applied, for the facets.
+pub struct Feed {
+    pub entries: Vec<Entry>,
+    pub actors: BTreeMap<String, usize>,
+    pub actions: BTreeMap<String, usize>,
+}
+
+pub fn feed(db: &Db, filter: &Filter, dates: &Formatter) -> Result<Feed, AppError> {
+    let (from, to) = filter.days()?;
+    let list = lists::parse(&filter.list)?;
+    // a list is one prefix, every list the whole log
+    let prefix = match &list {
+        Some(list) => list_prefix(Some(list)),
+        None => ACTIVITY_PREFIX.to_string(),
+    };
+    let mut feed = Feed {
+        entries: Vec::new(),
+        actors: BTreeMap::new(),
+        actions: BTreeMap::new(),
+    };
+    for item in db.iter_prefix::<Entry>(&prefix)? {
+        let (_, entry) = item?;
+        let day = dates.day_of(entry.at);
+        if from.is_some_and(|from| day < Some(from)) || to.is_some_and(|to| day > Some(to)) {
+            continue;
+        }
+        *feed
+            .actors
+            .entry(entry.actor_label().to_string())
+            .or_default() += 1;
+        *feed.actions.entry(entry.action.clone()).or_default() += 1;
+        if (filter.actor.is_empty() || entry.actor_label() == filter.actor)
+            && (filter.action.is_empty() || entry.action == filter.action)
+        {
+            feed.entries.push(entry);
+        }
+    }
+    // lists interleave, their keys only sort within one
+    feed.entries
+        .sort_by(|a, b| b.at.cmp(&a.at).then(b.id.cmp(&a.id)));
+    feed.entries.truncate(MAX_ENTRIES);
+    Ok(feed)
+}
+
+// === Retention ===
+// Drop the entries from before `cutoff`, in unix seconds, along with the versions of
+// `history::purge_before`. Returns how many went.
+pub fn purge_before(db: &Db, cutoff: u64) -> Result<usize> {
+    let mut old = Vec::new();
+    for prefix in [ACTIVITY_PREFIX, TODO_ACTIVITY_PREFIX] {
+        for item in db.iter_prefix::<Entry>(prefix)? {
+            let (key, entry) = item?;
+            if entry.at < cutoff {
+                old.push(key);
+            }
+        }
+    }
+    let purged = old.len();
+    db.remove_all(old)?;
+    Ok(purged)
+}
+
+pub fn volume(db: &Db) -> Result<Volume> {
+    let mut volume = Volume::of_prefix(db, ACTIVITY_PREFIX)?;
+    volume += Volume::of_prefix(db, TODO_ACTIVITY_PREFIX)?;
+    Ok(volume)
+}
+
+// === Components ===
+fn preview_html(todos: &[Todo], dates: &Formatter) -> Markup {
+    html! {
+        // refreshes itself whenever the workspace changes, on this page or another
+        section id="activity-preview" class="bg-white rounded-lg shadow-lg p-4 space-y-2"
+            hx-get=(routes::ActivityPreview::url()) hx-trigger={ "sse:" (events::CHANGED_EVENT) } hx-swap="outerHTML" {
+            div class="flex justify-between items-baseline" {
+                h2 class="text-xl text-gray-700" { "Recently changed" }
+                a class="text-sm text-blue-500 hover:text-blue-700" href=(routes::Activity::url()) { "All activity" }
+            }
+            @if todos.is_empty() {
+                p class="text-gray-500" { "Nothing yet." }
+            }
+            ul class="space-y-1" {
+                @for todo in todos {
+                    li class="flex justify-between text-sm" {
+                        a class={"hover:underline " @if todo.completed { "line-through text-gray-400" }} href=(routes::TodoDetail::url(todo.id)) { (todo.title) }
+                        span class="text-gray-400 ml-2 whitespace-nowrap" { (dates.ago(todo.updated_at)) }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// entries as the feed and the history tab list them
+pub fn entries_html(entries: &[Entry], dates: &Formatter) -> Markup {
+    html! {
+        ol class="space-y-1" {
+            @for entry in entries {
+                li class="flex justify-between text-sm" {
+                    span {
+                        span class="font-bold" { (entry.actor_label()) } " " (entry.verb()) " "
+                        a class="hover:underline" href=(routes::TodoDetail::url(entry.todo)) { (entry.title) }
+                        @if let Some(list) = &entry.list {
+                            span class="t-muted" { " in " (list) }
+                        }
+                    }
+                    span class="text-gray-400 ml-2 whitespace-nowrap" { (dates.ago(entry.at)) }
+                }
+            }
+        }
+    }
+}
+
+fn facet_html(
+    name: &str,
+    label: &str,
+    selected: &str,
+    counts: &BTreeMap<String, usize>,
+    value_label: fn(&str) -> &str,
+) -> Markup {
+    html! {
+        label class="flex flex-col text-sm text-gray-600" {
+            (label)
+            select class="rounded p-1 border" name=(name) {
+                option value="" { "Any" }
+                @for (value, count) in counts {
+                    option value=(value) selected[value == selected] { (value_label(value)) " (" (count) ")" }
+                }
+            }
+        }
+    }
+}
+
+fn feed_html(feed: &Feed, dates: &Formatter) -> Markup {
+    html! {
+        div id=(FEED_ID) {
+            @if feed.entries.is_empty() {
+                p class="text-gray-500" { "Nothing changed." }
+            }
+            (entries_html(&feed.entries, dates))
+        }
+    }
+}
+
+fn page_html(
+    filter: &Filter,
+    feed: &Feed,
+    lists: &BTreeMap<String, usize>,
+    dates: &Formatter,
+) -> Markup {
+    let url = routes::Activity::url();
+    html! {
+        (nav::navigation(&Nav::activity()))
+        section class="bg-white rounded-lg shadow-lg p-6 space-y-4" {
+            h1 class="text-2xl text-gray-700" { "Activity" }
+            form class="flex flex-wrap items-end gap-2" method="get" action=(url) hx-get=(url) hx-target={ "#" (FEED_ID) } hx-swap="outerHTML" hx-push-url="true" hx-trigger="change, submit" {
+                (facet_html("actor", "Who", &filter.actor, &feed.actors, |actor| actor))
+                (facet_html("action", "What", &filter.action, &feed.actions, action_label))
+                label class="flex flex-col text-sm text-gray-600" {
+                    "List"
+                    select class="rounded p-1 border" name="list" {
+                        option value="" { "Any" }
+                        @for list in lists.keys() {
+                            option value=(list) selected[*list == filter.list] { (list) }
+                        }
+                    }
+                }
+                label class="flex flex-col text-sm text-gray-600" {
+                    "From" input class="rounded p-1 border" type="date" name="from" value=(filter.from);
+                }
+                label class="flex flex-col text-sm text-gray-600" {
+                    "To" input class="rounded p-1 border" type="date" name="to" value=(filter.to);
+                }
+                noscript {
+                    button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded" type="submit" { "Filter" }
+                }
+            }
+            (feed_html(feed, dates))
+        }
+    }
+}
+
+// the most recently touched todos, newest first
+fn recent(mut todos: Vec<Todo>) -> Vec<Todo> {
+    todos.sort_by(|a, b| b.updated_at.cmp(&a.updated_at).then(b.id.cmp(&a.id)));
+    todos.truncate(PREVIEW_LEN);
+    todos
+}
+
+// === Routes ===
+// lazily loaded into the sidebar of the list, it scans every todo
+pub async fn preview(State(state): State<AppState>, tenant: Tenant) -> Result<Markup, AppError> {
+    let db = state.read().await.for_tenant(tenant.id())?;
+    let todos = TodoQuery::new().list(&db)?;
+    Ok(preview_html(&recent(todos), &Formatter::load(&db)?))
+}
+
+// Everything that changed, narrowed down by who, what, list and days: "what changed this week in
+// groceries".
+pub async fn index(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Query(filter): Query<Filter>,
+) -> Result<Markup, AppError> {
+    let db = state.read().await.for_tenant(tenant.id())?;
+    let dates = Formatter::load(&db)?;
+    let feed = feed(&db, &filter, &dates)?;
+    if views::wants_fragment(&headers) {
+        return Ok(feed_html(&feed, &dates));
+    }
+    let lists = lists::counts(&TodoQuery::new().list(&db)?);
+    Ok(views::page(
+        "Activity",
+        page_html(&filter, &feed, &lists, &dates),
+    ))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{fixtures::TestDb, views::fingerprint};
+
+    #[test]
+    fn test_recent() {
+        let todos: Vec<Todo> = (1..=7)
+            .map(|id| {
+                let mut todo = Todo::new(id, format!("todo {}", id));
+                todo.updated_at = 100 - id;
+                todo
+            })
+            .collect();
+        let ids: Vec<u64> = recent(todos).iter().map(|todo| todo.id).collect();
+        assert_eq!(ids, vec![1, 2, 3, 4, 5]);
+    }
+
+    fn filed(id: u64, list: &str) -> Todo {
+        let mut todo = Todo::new(id, format!("todo {}", id));
+        todo.list = Some(list.into());
+        todo
+    }
+
+    #[tokio::test]
+    async fn test_feed() -> Result<(), AppError> {
+        let db = TestDb::new("activity")?;
+        let milk = filed(1, "groceries");
+        let ops = ACTOR
+            .scope(Some("ana".into()), async {
+                record_ops(&db, &DomainEvent::TodoCreated(milk.clone()))
+            })
+            .await?;
+        db.apply_batch(ops)?;
+        db.clock.advance(Duration::from_secs(2 * 86_400));
+        db.apply_batch(record_ops(&db, &DomainEvent::toggled(milk.clone()))?)?;
+        db.apply_batch(record_ops(
+            &db,
+            &DomainEvent::TodoCreated(filed(2, "admin")),
+        )?)?;
+        let dates = Formatter::load(&db)?;
+
+        let all = feed(&db, &Filter::default(), &dates)?;
+        assert_eq!(all.entries.len(), 3);
+        assert_eq!(all.actors.get("ana"), Some(&1));
+        assert_eq!(all.actors.get(NO_ACTOR), Some(&2));
+
+        let groceries = Filter {
+            list: "groceries".into(),
+            ..Filter::default()
+        };
+        let actions: Vec<&str> = feed(&db, &groceries, &dates)?
+            .entries
+            .iter()
+            .map(Entry::verb)
+            .collect();
+        assert_eq!(actions, ["reopened", "created"]);
+
+        let by_ana = Filter {
+            actor: "ana".into(),
+            ..Filter::default()
+        };
+        assert_eq!(feed(&db, &by_ana, &dates)?.entries[0].title, "todo 1");
+        let today = Filter {
+            from: dates.today().to_string(),
+            ..groceries
+        };
+        assert_eq!(feed(&db, &today, &dates)?.entries.len(), 1);
+
+        // the history tab of a todo reads its own entries
+        assert_eq!(for_todo(&db, milk.id)?.len(), 2);
+        assert_eq!(
+            purge_before(
+                &db,
+                dates.today().midnight().assume_utc().unix_timestamp() as u64
+            )?,
+            2
+        );
+        Ok(())
+    }
+
+    // the same log renders to the same bytes, whichever run or database it is in
+    #[test]
+    fn test_feed_renders_the_same_every_run() -> Result<(), AppError> {
+        let render = |name: &str| -> Result<String, AppError> {
+            let db = TestDb::new(name)?;
+            for (id, list) in [(1, "groceries"), (2, "admin"), (3, "groceries")] {
+                db.apply_batch(record_ops(&db, &DomainEvent::TodoCreated(filed(id, list)))?)?;
+            }
+            let dates = Formatter::load(&db)?;
+            Ok(feed_html(&feed(&db, &Filter::default(), &dates)?, &dates).into_string())
+        };
+        let first = render("activity-render-a")?;
+        let second = render("activity-render-b")?;
+        assert_eq!(first, second);
+        assert_eq!(
+            fingerprint::hash(first.as_bytes()),
+            fingerprint::hash(second.as_bytes())
+        );
+        Ok(())
+    }
+}
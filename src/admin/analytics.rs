@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+
+use axum::extract::State;
+use maud::{html, Markup};
+
+use crate::{
+    analytics::{self, DailyCounts},
+    error::AppError,
+    state::AppState,
+    views,
+};
+
+// how far the page looks back
+const DAYS: i64 = 30;
+// the busiest routes listed
+const TOP_ROUTES: usize = 15;
+
+// the routes viewed over `days`, busiest first, each with its views per day
+fn by_route(days: &[DailyCounts]) -> Vec<(String, Vec<u64>)> {
+    let mut routes: BTreeMap<&str, Vec<u64>> = BTreeMap::new();
+    for (index, day) in days.iter().enumerate() {
+        for (route, views) in &day.views {
+            routes.entry(route).or_insert_with(|| vec![0; days.len()])[index] = *views;
+        }
+    }
+    let mut routes: Vec<(String, Vec<u64>)> = routes
+        .into_iter()
+        .map(|(route, views)| (route.to_string(), views))
+        .collect();
+    routes.sort_by_key(|(_, views)| std::cmp::Reverse(views.iter().sum::<u64>()));
+    routes.truncate(TOP_ROUTES);
+    routes
+}
+
+// === Routes ===
+pub async fn index(State(state): State<AppState>) -> Result<Markup, AppError> {
+    let days = analytics::series(&*state.read().await, state.clock.now().date(), DAYS)?;
+    let views: Vec<u64> = days.iter().map(DailyCounts::total_views).collect();
+    let sessions: Vec<u64> = days.iter().map(|day| day.sessions).collect();
+    Ok(views::page(
+        "Analytics",
+        html! {
+            h1 class="text-4xl text-center text-gray-700 mb-6" { "Analytics" }
+            @if state.analytics.is_none() {
+                p class="bg-white rounded-lg shadow-lg p-4 mb-6 text-gray-600" {
+                    "Counting is off, set ANALYTICS=true to start. Counts from before are still shown."
+                }
+            }
+            p class="text-gray-600 mb-4" {
+                "The last " (DAYS) " days. Counts stay on this server, visitors are told apart by a hash of their cookie that changes every day."
+            }
+            div class="grid grid-cols-2 gap-4 mb-6" {
+                div class="bg-white rounded-lg shadow-lg p-4" {
+                    h2 class="text-xl text-gray-700" { "Page views" }
+                    p class="text-3xl" { (views.iter().sum::<u64>()) }
+                    (analytics::sparkline_svg(&views, "Page views per day"))
+                }
+                div class="bg-white rounded-lg shadow-lg p-4" {
+                    h2 class="text-xl text-gray-700" { "Daily sessions" }
+                    p class="text-3xl" { (sessions.last().copied().unwrap_or(0)) " today" }
+                    (analytics::sparkline_svg(&sessions, "Sessions per day"))
+                }
+            }
+            table class="w-full bg-white rounded-lg shadow-lg" {
+                thead {
+                    tr {
+                        th class="py-2 px-4 text-left" { "Route" }
+                        th class="py-2 px-4 text-right" { "Views" }
+                        th class="py-2 px-4" {}
+                    }
+                }
+                tbody {
+                    @for (route, views) in by_route(&days) {
+                        tr class="border-t" {
+                            td class="py-2 px-4 font-mono" { (route) }
+                            td class="py-2 px-4 text-right" { (views.iter().sum::<u64>()) }
+                            td class="py-2 px-4" { (analytics::sparkline_svg(&views, &format!("Views of {} per day", route))) }
+                        }
+                    }
+                }
+            }
+        },
+    ))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::date;
+
+    #[test]
+    fn test_by_route_fills_missing_days() {
+        let mut first = DailyCounts::new(date!(2024 - 03 - 01));
+        first.views.insert("/stats".into(), 1);
+        let mut second = DailyCounts::new(date!(2024 - 03 - 02));
+        second.views.insert("/todos".into(), 3);
+        second.views.insert("/stats".into(), 1);
+        let routes = by_route(&[first, second]);
+        assert_eq!(
+            routes,
+            [
+                ("/todos".to_string(), vec![0, 3]),
+                ("/stats".to_string(), vec![1, 1]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,140 @@
+use axum::{
+    extract::{Path, State},
+    response::Redirect,
+};
+use maud::{html, Markup};
+use time::OffsetDateTime;
+
+use crate::{
+    error::AppError,
+    maintenance,
+    restore::{self, Report},
+    routes,
+    state::AppState,
+    views,
+};
+
+// === Components ===
+fn at(secs: u64) -> String {
+    OffsetDateTime::from_unix_timestamp(secs as i64)
+        .map(|at| format!("{} {:02}:{:02} UTC", at.date(), at.hour(), at.minute()))
+        .unwrap_or_default()
+}
+
+fn restored(title: &str, report: Report) -> Markup {
+    views::page(
+        title,
+        html! {
+            h1 class="text-4xl text-center text-gray-700 mb-6" { (title) }
+            dl class="grid grid-cols-2 gap-2 bg-white rounded-lg shadow-lg p-4 mb-6 text-gray-700" {
+                dt { "Trees" } dd { (report.trees) }
+                dt { "Keys" } dd { (report.keys) }
+                dt { "Todos" } dd { (report.todos) }
+            }
+            p class="text-center text-gray-500" {
+                "Maintenance mode is still on. "
+                a class="text-blue-500 hover:underline" href=(routes::Maintenance::url()) { "End it" }
+                " once everything looks right."
+            }
+        },
+    )
+}
+
+// === Routes ===
+pub async fn index(State(state): State<AppState>) -> Result<Markup, AppError> {
+    let backups = restore::list(&state.config)?;
+    let rollback = restore::previous(&state.config);
+    let on = maintenance::is_on(&state);
+    Ok(views::page(
+        "Backups",
+        html! {
+            h1 class="text-4xl text-center text-gray-700 mb-6" { "Backups" }
+            form class="flex justify-between items-center bg-white rounded-lg shadow-lg p-4 mb-6" method="post" action=(routes::Backups::url()) {
+                p { "Backups are kept in " span class="font-mono" { (state.config.backups_dir().display()) } "." }
+                button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" { "Back up now" }
+            }
+            @if !on {
+                p class="text-gray-500 mb-2" {
+                    "Restoring needs "
+                    a class="text-blue-500 hover:underline" href=(routes::Maintenance::url()) { "maintenance mode" }
+                    " to be on."
+                }
+            }
+            table class="w-full bg-white rounded-lg shadow-lg mb-6" {
+                thead {
+                    tr {
+                        th class="py-2 px-4 text-left" { "Name" }
+                        th class="py-2 px-4 text-left" { "Taken" }
+                        th {}
+                    }
+                }
+                tbody {
+                    @for backup in &backups {
+                        tr class="border-t" {
+                            td class="py-2 px-4 font-mono" { (backup.name) }
+                            td class="py-2 px-4" { (at(backup.created_at)) }
+                            td class="py-2 px-4 text-right" {
+                                form method="post" action=(routes::BackupRestore::url(&backup.name)) {
+                                    button class="bg-red-500 hover:bg-red-700 text-white font-bold py-1 px-2 rounded disabled:opacity-50" type="submit" disabled[!on]
+                                        onclick="return confirm('Replace all data with this backup?')" { "Restore" }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            @if let Some(rollback) = rollback {
+                form class="flex justify-between items-center bg-white rounded-lg shadow-lg p-4" method="post" action=(routes::BackupRollback::url()) {
+                    p { "The data from before the last restore is in " span class="font-mono" { (rollback.display()) } "." }
+                    button class="bg-red-500 hover:bg-red-700 text-white font-bold py-2 px-4 rounded disabled:opacity-50" type="submit" disabled[!on] { "Roll back" }
+                }
+            }
+        },
+    ))
+}
+
+pub async fn create(State(state): State<AppState>) -> Result<Redirect, AppError> {
+    let to = state
+        .config
+        .backups_dir()
+        .join(format!("db-{}", state.clock.now_millis() / 1000));
+    state.read().await.backup(&to)?;
+    tracing::info!(to = %to.display(), "backed up");
+    Ok(Redirect::to(&routes::Backups::url()))
+}
+
+// Only in maintenance, so nothing is written to the old data while it is swapped out.
+fn require_maintenance(state: &AppState) -> Result<(), AppError> {
+    if !maintenance::is_on(state) {
+        return Err(AppError::Invalid(
+            "Turn on maintenance mode before restoring.".into(),
+        ));
+    }
+    Ok(())
+}
+
+pub async fn restore(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Markup, AppError> {
+    require_maintenance(&state)?;
+    let staged = restore::stage(&state.config, &*state.clock, &name)
+        .map_err(|err| AppError::Invalid(format!("{:#}", err)))?;
+    restore::activate(&state.config, &staged.path)?;
+    let old = state.swap_db(staged.db).await;
+    old.flush()?;
+    tracing::info!(name, path = %staged.path.display(), "restored backup");
+    Ok(restored("Restored", staged.report))
+}
+
+pub async fn rollback(State(state): State<AppState>) -> Result<Markup, AppError> {
+    require_maintenance(&state)?;
+    let (path, db) = restore::stage_rollback(&state.config)
+        .map_err(|err| AppError::Invalid(format!("{:#}", err)))?;
+    let report = restore::verify(&db)?;
+    restore::activate(&state.config, &path)?;
+    let old = state.swap_db(db).await;
+    old.flush()?;
+    tracing::info!(path = %path.display(), "rolled back restore");
+    Ok(restored("Rolled back", report))
+}
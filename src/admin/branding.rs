@@ -0,0 +1,129 @@
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{header, HeaderMap},
+    response::Redirect,
+    Form,
+};
+use maud::{html, Markup};
+use serde::Deserialize;
+
+use crate::{
+    branding::{self, Branding, Logo},
+    error::AppError,
+    method_override::METHOD_FIELD,
+    routes,
+    state::AppState,
+    views,
+};
+
+const LOGO_RESULT_ID: &str = "logo-result";
+
+// === Components ===
+fn logo_html(branding: &Branding) -> Markup {
+    html! {
+        section class="bg-white rounded-lg shadow-lg p-6 space-y-4" {
+            h2 class="text-2xl text-gray-700" { "Logo" }
+            @if branding.has_logo {
+                img class="h-16 w-auto" src=(routes::BrandLogo::url()) alt="The current logo";
+                form method="post" action=(routes::BrandingLogo::url()) {
+                    input type="hidden" name=(METHOD_FIELD) value="DELETE";
+                    button class="bg-red-500 hover:bg-red-700 text-white font-bold py-1 px-2 rounded" type="submit" { "Remove" }
+                }
+            } @else {
+                p class="text-gray-600" { "Without a logo pages show the app icon." }
+            }
+            // the image is the request body, like a backup upload
+            label class="block text-gray-600" {
+                "Upload a logo, png, jpeg, gif, webp or svg up to " (branding::MAX_LOGO_BYTES / 1024) " KiB "
+                input type="file" accept=(branding::LOGO_TYPES.join(",")) data-url=(routes::BrandingLogo::url())
+                    onchange={ "const file = this.files[0]; if (!file) return;
+                        fetch(this.dataset.url, { method: 'POST', body: file, headers: { 'Content-Type': file.type } })
+                            .then((response) => response.ok ? location.reload() : response.text().then((text) => { document.getElementById('" (LOGO_RESULT_ID) "').textContent = text; }));" };
+            }
+            p id=(LOGO_RESULT_ID) class="t-danger" role="alert" {}
+        }
+    }
+}
+
+// === Routes ===
+pub async fn index(State(state): State<AppState>) -> Result<Markup, AppError> {
+    let branding = branding::get(&*state.read().await)?;
+    Ok(views::page(
+        "Branding",
+        html! {
+            h1 class="text-4xl text-center text-gray-700 mb-6" { "Branding" }
+            div class="space-y-6" {
+                form class="bg-white rounded-lg shadow-lg p-6 space-y-4" method="post" action=(routes::Branding::url()) {
+                    label class="block text-gray-600" {
+                        "Name, shown in the header and as the title of the list"
+                        input class="w-full rounded p-2 border" type="text" name="name" value=[branding.name.as_deref()]
+                            placeholder="The name from setup";
+                    }
+                    label class="block text-gray-600" {
+                        "Accent color, for the default theme"
+                        input class="w-full rounded p-2 border font-mono" type="text" name="accent" value=[branding.accent.as_deref()]
+                            placeholder="#rrggbb";
+                    }
+                    label class="block text-gray-600" {
+                        "Welcome text above the list, blank lines start a new paragraph"
+                        textarea class="w-full rounded p-2 border" name="welcome" rows="5" {
+                            (branding.welcome.as_deref().unwrap_or_default())
+                        }
+                    }
+                    button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" { "Save" }
+                }
+                (logo_html(&branding))
+            }
+        },
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct Edit {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    accent: String,
+    #[serde(default)]
+    welcome: String,
+}
+pub async fn save(
+    State(mut state): State<AppState>,
+    Form(Edit {
+        name,
+        accent,
+        welcome,
+    }): Form<Edit>,
+) -> Result<Redirect, AppError> {
+    let db = state.write().await;
+    let branding = branding::edited(&branding::get(&db)?, &name, &accent, &welcome)?;
+    branding::set(&db, &branding)?;
+    tracing::info!(name = ?branding.name, accent = ?branding.accent, "changed branding");
+    Ok(Redirect::to(&routes::Branding::url()))
+}
+
+pub async fn upload_logo(
+    State(mut state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Redirect, AppError> {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    branding::validate_logo(content_type, &body)?;
+    let logo = Logo {
+        content_type: content_type.to_string(),
+        bytes: body.to_vec(),
+    };
+    branding::set_logo(&*state.write().await, &logo)?;
+    tracing::info!(content_type, bytes = logo.bytes.len(), "uploaded logo");
+    Ok(Redirect::to(&routes::Branding::url()))
+}
+
+pub async fn remove_logo(State(mut state): State<AppState>) -> Result<Redirect, AppError> {
+    branding::remove_logo(&*state.write().await)?;
+    tracing::info!("removed logo");
+    Ok(Redirect::to(&routes::Branding::url()))
+}
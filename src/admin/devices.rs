@@ -0,0 +1,63 @@
+use axum::{
+    extract::{Path, State},
+    Extension,
+};
+use maud::{html, Markup};
+
+use super::AdminAccount;
+use crate::{
+    auth::remember::{self, Device},
+    error::AppError,
+    routes,
+    state::AppState,
+};
+
+// === Components ===
+pub fn devices_html(devices: &[Device]) -> Markup {
+    html! {
+        @if devices.is_empty() {
+            p class="text-gray-700" { "No remembered devices. Tick \"Remember this device\" when signing in with a passkey." }
+        } @else {
+            ul class="divide-y" {
+                @for device in devices {
+                    li class="flex items-center justify-between py-2" {
+                        div {
+                            p class="text-gray-700 truncate max-w-md" { (device.label) }
+                            p class="text-xs text-gray-400" { "Last used " (format_time(device.last_used_at)) }
+                        }
+                        button class="bg-red-500 hover:bg-red-700 text-white font-bold py-1 px-3 rounded"
+                            hx-post=(routes::DeviceRevoke::url(&device.id)) hx-target="closest li" hx-swap="outerHTML"
+                            hx-confirm="Sign this device out?" { "Revoke" }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// seconds since the epoch as `YYYY-MM-DD HH:MM UTC`
+fn format_time(secs: u64) -> String {
+    time::OffsetDateTime::from_unix_timestamp(secs as i64)
+        .map(|time| {
+            format!(
+                "{:04}-{:02}-{:02} {:02}:{:02} UTC",
+                time.year(),
+                time.month() as u8,
+                time.day(),
+                time.hour(),
+                time.minute()
+            )
+        })
+        .unwrap_or_default()
+}
+
+// === Routes ===
+pub async fn revoke(
+    State(mut state): State<AppState>,
+    Extension(AdminAccount(account)): Extension<AdminAccount>,
+    Path(device): Path<String>,
+) -> Result<Markup, AppError> {
+    remember::revoke(&*state.write().await, &account, &device)?;
+    tracing::info!(account = %account, "revoked a remembered device");
+    Ok(html! {})
+}
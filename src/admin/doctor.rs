@@ -0,0 +1,51 @@
+use axum::extract::State;
+use maud::{html, Markup};
+
+use crate::{
+    doctor::{self, Check, Outcome},
+    error::AppError,
+    state::AppState,
+    views,
+};
+
+// === Components ===
+fn check_row(check: &Check) -> Markup {
+    let class = match check.outcome {
+        Outcome::Ok => "text-green-600",
+        Outcome::Warning => "text-yellow-600",
+        Outcome::Failed => "text-red-600",
+    };
+    html! {
+        tr class="border-t" {
+            td class={ "py-2 px-4 font-mono " (class) } { (check.outcome) }
+            td class="py-2 px-4" { (check.name) }
+            td class="py-2 px-4 text-gray-600" { (check.detail) }
+        }
+    }
+}
+
+// === Routes ===
+// the same report as `--doctor`, against the database this server holds
+pub async fn index(State(state): State<AppState>) -> Result<Markup, AppError> {
+    let config = state.live.load();
+    let db = state.read().await.clone();
+    let report = tokio::task::spawn_blocking(move || doctor::diagnose(&config, Some(&db))).await?;
+    Ok(views::page(
+        "Doctor",
+        html! {
+            h1 class="text-4xl text-center text-gray-700 mb-6" { "Doctor" }
+            table class="w-full bg-white rounded-lg shadow-lg" {
+                thead {
+                    tr {
+                        th class="py-2 px-4 text-left" { "Result" }
+                        th class="py-2 px-4 text-left" { "Check" }
+                        th class="py-2 px-4 text-left" { "Details" }
+                    }
+                }
+                tbody {
+                    @for check in &report.checks { (check_row(check)) }
+                }
+            }
+        },
+    ))
+}
@@ -0,0 +1,120 @@
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    response::Response,
+};
+use maud::{html, Markup};
+use time::OffsetDateTime;
+
+use crate::{
+    db::driver::Db,
+    error::AppError,
+    routes,
+    scheduler::{self, Job, LastRun},
+    state::AppState,
+    views,
+};
+
+// === Components ===
+pub(super) fn at(unix: u64) -> String {
+    OffsetDateTime::from_unix_timestamp(unix as i64)
+        .map(|at| format!("{} {:02}:{:02} UTC", at.date(), at.hour(), at.minute()))
+        .unwrap_or_default()
+}
+
+// when `job` runs next, `None` until it first ran at startup
+pub(super) fn next_run(job: &Job, last: Option<&LastRun>) -> Option<u64> {
+    last.and_then(|last| OffsetDateTime::from_unix_timestamp(last.started_at as i64).ok())
+        .map(|last| job.schedule.next_after(last).unix_timestamp() as u64)
+}
+
+fn job_row(job: &Job, last: Option<&LastRun>) -> Markup {
+    let next = next_run(job, last);
+    html! {
+        tr class="border-t" {
+            td class="py-2 px-4 font-mono" { (job.name) }
+            td class="py-2 px-4" { (job.schedule) }
+            td class="py-2 px-4" {
+                @match last {
+                    Some(last) => { (at(last.started_at)) " (" (last.finished_at - last.started_at) "s)" }
+                    None => { span class="text-gray-400" { "never" } }
+                }
+            }
+            td class="py-2 px-4" {
+                @if job.is_running() {
+                    span class="text-yellow-600" { "Running" }
+                } @else {
+                    @match last.map(|last| &last.error) {
+                        Some(Some(error)) => { span class="text-red-600" title=(error) { "Failed" } }
+                        Some(None) => { span class="text-green-600" { "Ok" } }
+                        None => {}
+                    }
+                }
+            }
+            td class="py-2 px-4" {
+                @match next {
+                    Some(next) => { (at(next)) }
+                    None => { "at startup" }
+                }
+            }
+            td class="py-2 px-4 text-right" {
+                button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded disabled:opacity-50"
+                    hx-post=(routes::JobRun::url(job.name)) hx-target="closest tr" hx-swap="outerHTML"
+                    disabled[job.is_running()] { "Run now" }
+            }
+        }
+    }
+}
+
+fn rows(db: &Db, jobs: &[std::sync::Arc<Job>]) -> Result<Markup, AppError> {
+    let mut rows = Vec::new();
+    for job in jobs {
+        rows.push(job_row(job, scheduler::last_run(db, job.name)?.as_ref()));
+    }
+    Ok(html! { @for row in rows { (row) } })
+}
+
+// === Routes ===
+pub async fn index(State(state): State<AppState>) -> Result<Markup, AppError> {
+    let rows = rows(&*state.read().await, state.scheduler.jobs())?;
+    Ok(views::page(
+        "Jobs",
+        html! {
+            h1 class="text-4xl text-center text-gray-700 mb-6" { "Jobs" }
+            table class="w-full bg-white rounded-lg shadow-lg" {
+                thead {
+                    tr {
+                        th class="py-2 px-4 text-left" { "Job" }
+                        th class="py-2 px-4 text-left" { "Schedule" }
+                        th class="py-2 px-4 text-left" { "Last run" }
+                        th class="py-2 px-4 text-left" { "Result" }
+                        th class="py-2 px-4 text-left" { "Next run" }
+                        th {}
+                    }
+                }
+                tbody { (rows) }
+            }
+        },
+    ))
+}
+
+// starts the job in the background, the row shows it running
+pub async fn run_now(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+) -> Result<Response, AppError> {
+    state.scheduler.run_now(&name).ok_or(AppError::NotFound)?;
+    let job = state
+        .scheduler
+        .jobs()
+        .iter()
+        .find(|job| job.name == name)
+        .ok_or(AppError::NotFound)?;
+    let last = scheduler::last_run(&*state.read().await, job.name)?;
+    Ok(views::fragment_or_redirect(
+        &headers,
+        job_row(job, last.as_ref()),
+        &routes::Jobs::url(),
+    ))
+}
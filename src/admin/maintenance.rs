@@ -0,0 +1,46 @@
+use axum::{extract::State, response::Redirect, Form};
+use maud::{html, Markup};
+use serde::Deserialize;
+
+use crate::{error::AppError, maintenance, routes, state::AppState, views};
+
+// === Routes ===
+pub async fn index(State(state): State<AppState>) -> Markup {
+    let on = maintenance::is_on(&state);
+    let forced = maintenance::is_forced(&state.live.load());
+    views::page(
+        "Maintenance",
+        html! {
+            h1 class="text-4xl text-center text-gray-700 mb-6" { "Maintenance" }
+            form class="flex justify-between items-center bg-white rounded-lg shadow-lg p-4 mb-6" method="post" action=(routes::Maintenance::url()) {
+                p {
+                    @if on { "The app is read-only, only the admin pages accept changes." } @else { "The app is open for changes." }
+                    @if forced {
+                        span class="text-gray-400 ml-2" { "Set by MAINTENANCE_MODE." }
+                    }
+                }
+                @if !forced {
+                    input type="hidden" name="on" value=(!on);
+                    button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" {
+                        @if on { "End maintenance" } @else { "Start maintenance" }
+                    }
+                }
+            }
+        },
+    )
+}
+
+#[derive(Deserialize)]
+pub struct Switch {
+    on: bool,
+}
+pub async fn switch(
+    State(mut state): State<AppState>,
+    Form(Switch { on }): Form<Switch>,
+) -> Result<Redirect, AppError> {
+    let handle = state.clone();
+    let db = state.write().await;
+    maintenance::switch(&handle, &db, on)?;
+    tracing::info!(on, "switched maintenance mode");
+    Ok(Redirect::to(&routes::Maintenance::url()))
+}
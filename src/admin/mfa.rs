@@ -0,0 +1,224 @@
+use axum::{
+    extract::{FromRequest, Request, State},
+    response::{IntoResponse, Redirect, Response},
+    Extension, Form,
+};
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+use maud::{html, Markup, PreEscaped};
+use qrcode::{render::svg, QrCode};
+use serde::Deserialize;
+
+use super::{devices, passkeys, AdminAccount};
+use crate::{
+    auth::{self, mfa, remember, throttle::LoginThrottle, totp},
+    clock::Clock,
+    error::AppError,
+    routes,
+    state::AppState,
+    views,
+};
+
+pub const MFA_COOKIE: &str = "admin_mfa";
+// how long a passed second step is remembered
+const MFA_COOKIE_SECS: u64 = 12 * 60 * 60;
+const ISSUER: &str = "Magical To-Do";
+
+// whether `cookie` proves `account` passed the second step recently
+pub fn is_verified(key: &[u8], clock: &dyn Clock, account: &str, cookie: Option<&str>) -> bool {
+    let Some(value) = cookie.and_then(|cookie| auth::verify_signed(key, MFA_COOKIE, cookie)) else {
+        return false;
+    };
+    let Some((verified_account, expires_at)) = value.rsplit_once(':') else {
+        return false;
+    };
+    let expires_at = expires_at.parse::<u64>().unwrap_or_default();
+    verified_account == account && expires_at > clock.now_millis() / 1000
+}
+
+// === Components ===
+fn status_html(enabled: bool, recovery_codes_left: usize) -> Markup {
+    html! {
+        div id="mfa" class="space-y-4" {
+            @if enabled {
+                p class="text-gray-700" { "Two-factor authentication is on. " (recovery_codes_left) " recovery codes left." }
+                button class="bg-red-500 hover:bg-red-700 text-white font-bold py-2 px-4 rounded"
+                    hx-post=(routes::MfaDisable::url()) hx-target="#mfa" hx-swap="outerHTML"
+                    hx-confirm="Turn off two-factor authentication?" { "Turn off" }
+            } @else {
+                p class="text-gray-700" { "Two-factor authentication is off." }
+                button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded"
+                    hx-post=(routes::MfaEnroll::url()) hx-target="#mfa" hx-swap="outerHTML" { "Set up" }
+            }
+        }
+    }
+}
+
+fn enroll_html(uri: &str, qr: &str, codes: &[String], error: Option<&str>) -> Markup {
+    html! {
+        div id="mfa" class="space-y-4" {
+            p class="text-gray-700" { "Scan the code with your authenticator app, then enter the code it shows." }
+            div class="w-48" { (PreEscaped(qr)) }
+            p class="text-xs text-gray-400 break-all" { (uri) }
+            p class="text-gray-700" { "Recovery codes, each works once. Keep them somewhere safe, they are not shown again:" }
+            ul class="font-mono grid grid-cols-2 gap-1" {
+                @for code in codes { li { (code) } }
+            }
+            (confirm_form_html(error))
+        }
+    }
+}
+
+fn confirm_form_html(error: Option<&str>) -> Markup {
+    html! {
+        form id="mfa-confirm" class="flex items-center" hx-post=(routes::MfaConfirm::url()) hx-target="this" hx-swap="outerHTML" {
+            input class="rounded p-2 mr-4" type="text" name="code" inputmode="numeric" autocomplete="one-time-code" placeholder="123456" required;
+            button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" { "Confirm" }
+            @if let Some(error) = error {
+                p class="text-red-500 ml-4" { (error) }
+            }
+        }
+    }
+}
+
+// the second login step, shown instead of any admin page until passed
+pub fn verify_page(error: Option<&str>) -> Markup {
+    views::page(
+        "Two-factor authentication",
+        html! {
+            div class="bg-white rounded-lg shadow-lg p-8 max-w-md mx-auto" {
+                h1 class="text-2xl text-gray-700 mb-4" { "Two-factor authentication" }
+                form class="space-y-4" method="post" action=(routes::MfaVerify::url()) {
+                    p class="text-gray-600" { "Enter the code from your authenticator app or one of your recovery codes." }
+                    input class="w-full rounded p-2 border" type="text" name="code" autocomplete="one-time-code" autofocus required;
+                    @if let Some(error) = error {
+                        p class="text-red-500" { (error) }
+                    }
+                    button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" { "Verify" }
+                }
+            }
+        },
+    )
+}
+
+// === Routes ===
+pub async fn index(
+    State(state): State<AppState>,
+    Extension(AdminAccount(account)): Extension<AdminAccount>,
+) -> Result<Markup, AppError> {
+    let db = state.read().await;
+    let record = mfa::get(&db, &account)?;
+    let devices = remember::devices(&db, &account)?;
+    drop(db);
+    let enabled = record.as_ref().is_some_and(|record| record.enabled);
+    let codes_left = record.map_or(0, |record| record.recovery_hashes.len());
+    Ok(views::page(
+        "Security",
+        html! {
+            h1 class="text-4xl text-center text-gray-700 mb-6" { "Security" }
+            section class="bg-white rounded-lg shadow-lg p-6 mb-6" {
+                h2 class="text-2xl text-gray-700 mb-4" { "Two-factor authentication" }
+                (status_html(enabled, codes_left))
+            }
+            section class="bg-white rounded-lg shadow-lg p-6 mb-6" {
+                h2 class="text-2xl text-gray-700 mb-4" { "Passkeys" }
+                (passkeys::register_html())
+            }
+            section class="bg-white rounded-lg shadow-lg p-6" {
+                h2 class="text-2xl text-gray-700 mb-4" { "Remembered devices" }
+                (devices::devices_html(&devices))
+            }
+        },
+    ))
+}
+
+pub async fn enroll(
+    State(mut state): State<AppState>,
+    Extension(AdminAccount(account)): Extension<AdminAccount>,
+) -> Result<Markup, AppError> {
+    let (record, codes) = mfa::enroll(&*state.write().await, &account)?;
+    let uri = totp::provisioning_uri(&record.secret, &account, ISSUER);
+    let qr = QrCode::new(uri.as_bytes())?
+        .render::<svg::Color>()
+        .min_dimensions(192, 192)
+        .build();
+    Ok(enroll_html(&uri, &qr, &codes, None))
+}
+
+#[derive(Deserialize)]
+pub struct Code {
+    code: String,
+}
+pub async fn confirm(
+    State(mut state): State<AppState>,
+    Extension(AdminAccount(account)): Extension<AdminAccount>,
+    jar: CookieJar,
+    Form(Code { code }): Form<Code>,
+) -> Result<Response, AppError> {
+    if !mfa::confirm(&*state.write().await, &account, &code)? {
+        return Ok(confirm_form_html(Some("That code did not match, try again.")).into_response());
+    }
+    // the enrolling session already proved the second factor
+    let jar = jar.add(verified_cookie(&state.secret_key, &*state.clock, &account));
+    Ok((
+        jar,
+        status_html(true, mfa_codes_left(&state, &account).await?),
+    )
+        .into_response())
+}
+
+pub async fn disable(
+    State(mut state): State<AppState>,
+    Extension(AdminAccount(account)): Extension<AdminAccount>,
+) -> Result<Markup, AppError> {
+    mfa::disable(&*state.write().await, &account)?;
+    Ok(status_html(false, 0))
+}
+
+pub async fn show_verify() -> Markup {
+    verify_page(None)
+}
+
+// Failed codes back off like failed passwords do, per account and per client.
+pub async fn verify(
+    State(mut state): State<AppState>,
+    Extension(AdminAccount(account)): Extension<AdminAccount>,
+    jar: CookieJar,
+    request: Request,
+) -> Result<Response, AppError> {
+    let mut subjects = vec![format!("mfa:{}", account)];
+    if let Some(ip) = auth::client_ip(request.extensions()) {
+        subjects.push(format!("ip:{}", ip));
+    }
+    let Form(Code { code }) = Form::<Code>::from_request(request, &state).await?;
+    let throttle = LoginThrottle {
+        lockout_after: state.live.load().login_lockout_after,
+    };
+    let db = state.write().await;
+    let has_failures = throttle.check(&db, &subjects)?;
+    if !mfa::verify(&db, &account, &code)? {
+        tracing::warn!(account = %account, "failed second factor");
+        throttle.record_failure(&db, &subjects)?;
+        return Ok(verify_page(Some("That code did not match, try again.")).into_response());
+    }
+    if has_failures {
+        throttle.reset(&db, &subjects)?;
+    }
+    drop(db);
+    let jar = jar.add(verified_cookie(&state.secret_key, &*state.clock, &account));
+    Ok((jar, Redirect::to(&routes::Tenants::url())).into_response())
+}
+
+fn verified_cookie(key: &[u8], clock: &dyn Clock, account: &str) -> Cookie<'static> {
+    let expires_at = clock.now_millis() / 1000 + MFA_COOKIE_SECS;
+    let value = auth::sign(key, MFA_COOKIE, &format!("{}:{}", account, expires_at));
+    Cookie::build((MFA_COOKIE, value))
+        .path("/admin")
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .build()
+}
+
+async fn mfa_codes_left(state: &AppState, account: &str) -> Result<usize, AppError> {
+    let record = mfa::get(&*state.read().await, account)?;
+    Ok(record.map_or(0, |record| record.recovery_hashes.len()))
+}
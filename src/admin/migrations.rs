@@ -0,0 +1,72 @@
+use axum::extract::State;
+use maud::{html, Markup};
+use time::OffsetDateTime;
+
+use crate::{
+    db::migrations::{self, State as MigrationState, TreeStatus},
+    error::AppError,
+    state::AppState,
+    views,
+};
+
+// === Components ===
+fn applied_at(unix: u64) -> String {
+    OffsetDateTime::from_unix_timestamp(unix as i64)
+        .map(|at| format!("{} {:02}:{:02} UTC", at.date(), at.hour(), at.minute()))
+        .unwrap_or_default()
+}
+
+fn tree_html(tree: &TreeStatus) -> Markup {
+    html! {
+        section class="mb-6" {
+            h2 class="text-2xl text-gray-700 mb-2" {
+                (tree.tenant.as_deref().unwrap_or("Default workspace"))
+                span class="text-gray-400 text-base ml-2" { "version " (tree.version) }
+            }
+            table class="w-full bg-white rounded-lg shadow-lg" {
+                thead {
+                    tr {
+                        th class="py-2 px-4 text-left" { "Version" }
+                        th class="py-2 px-4 text-left" { "Migration" }
+                        th class="py-2 px-4 text-left" { "State" }
+                        th class="py-2 px-4 text-right" { "Rewritten" }
+                    }
+                }
+                tbody {
+                    @for entry in &tree.entries {
+                        tr class="border-t" {
+                            td class="py-2 px-4 font-mono" { (entry.version) }
+                            td class="py-2 px-4" { (entry.name) }
+                            @match &entry.state {
+                                MigrationState::Applied(applied) => {
+                                    td class="py-2 px-4 text-green-600" { "Applied " (applied_at(applied.applied_at)) }
+                                    td class="py-2 px-4 text-right" { (applied.rewritten) }
+                                }
+                                MigrationState::Untracked => {
+                                    td class="py-2 px-4 text-green-600" { "Applied" }
+                                    td class="py-2 px-4 text-right text-gray-400" { "unknown" }
+                                }
+                                MigrationState::Pending => {
+                                    td class="py-2 px-4 text-yellow-600" { "Pending" }
+                                    td {}
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// === Routes ===
+pub async fn index(State(state): State<AppState>) -> Result<Markup, AppError> {
+    let trees = migrations::status(&state.read().await)?;
+    Ok(views::page(
+        "Migrations",
+        html! {
+            h1 class="text-4xl text-center text-gray-700 mb-6" { "Migrations" }
+            @for tree in &trees { (tree_html(tree)) }
+        },
+    ))
+}
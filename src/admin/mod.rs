@@ -0,0 +1,218 @@
+pub mod analytics;
+pub mod backups;
+pub mod branding;
+pub mod devices;
+pub mod doctor;
+pub mod jobs;
+pub mod maintenance;
+pub mod mfa;
+pub mod migrations;
+pub mod passkeys;
+pub mod queue;
+pub mod registration;
+pub mod retention;
+pub mod tenants;
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
+    Router,
+};
+use axum_extra::extract::cookie::CookieJar;
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use crate::{
+    auth::{self, password::AdminCredentials, throttle::LoginThrottle},
+    error::{AppError, ErrorReport},
+    routes,
+    state::AppState,
+};
+
+// the basic auth user name of the current admin request
+#[derive(Debug, Clone)]
+pub struct AdminAccount(pub String);
+
+// everything under `/admin`, behind HTTP basic auth with the configured admin password
+pub fn router(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route(routes::Mfa::PATH, get(mfa::index))
+        .route(routes::MfaEnroll::PATH, post(mfa::enroll))
+        .route(routes::MfaConfirm::PATH, post(mfa::confirm))
+        .route(routes::MfaDisable::PATH, post(mfa::disable))
+        .route(
+            routes::MfaVerify::PATH,
+            get(mfa::show_verify).post(mfa::verify),
+        )
+        .route(
+            routes::Tenants::PATH,
+            get(tenants::index).post(tenants::create),
+        )
+        .route(routes::TenantExport::PATH, get(tenants::export))
+        .route(routes::TenantErase::PATH, post(tenants::erase))
+        .route(routes::TenantMembers::PATH, post(tenants::add_member))
+        .route(
+            routes::TenantMemberRemove::PATH,
+            post(tenants::remove_member),
+        )
+        .route(routes::Migrations::PATH, get(migrations::index))
+        .route(routes::Jobs::PATH, get(jobs::index))
+        .route(routes::Retention::PATH, get(retention::index))
+        .route(routes::Analytics::PATH, get(analytics::index))
+        .route(routes::Doctor::PATH, get(doctor::index))
+        .route(routes::JobRun::PATH, post(jobs::run_now))
+        .route(routes::Queue::PATH, get(queue::index))
+        .route(
+            routes::Maintenance::PATH,
+            get(maintenance::index).post(maintenance::switch),
+        )
+        .route(routes::QueueMetrics::PATH, get(queue::metrics))
+        .route(routes::DeadTask::PATH, delete(queue::discard))
+        .route(routes::DeadTaskRetry::PATH, post(queue::retry))
+        .route(
+            routes::Backups::PATH,
+            get(backups::index).post(backups::create),
+        )
+        .route(routes::BackupRestore::PATH, post(backups::restore))
+        .route(routes::BackupRollback::PATH, post(backups::rollback))
+        .route(
+            routes::Registration::PATH,
+            get(registration::index).post(registration::set_open),
+        )
+        .route(
+            routes::Branding::PATH,
+            get(branding::index).post(branding::save),
+        )
+        .route(
+            routes::BrandingLogo::PATH,
+            post(branding::upload_logo).delete(branding::remove_logo),
+        )
+        .route(routes::Invites::PATH, post(registration::create_invite))
+        .route(
+            routes::InviteRevoke::PATH,
+            post(registration::revoke_invite),
+        )
+        .route(routes::DeviceRevoke::PATH, post(devices::revoke))
+        .route(
+            routes::PasskeyRegisterStart::PATH,
+            post(passkeys::start_registration),
+        )
+        .route(
+            routes::PasskeyRegisterFinish::PATH,
+            post(passkeys::finish_registration),
+        )
+        .route_layer(middleware::from_fn_with_state(state, require_admin))
+        // passkey sign-in happens before there are any credentials
+        .route(routes::Login::PATH, get(passkeys::login_page))
+        .route(routes::LoginStart::PATH, post(passkeys::start_login))
+        .route(routes::LoginFinish::PATH, post(passkeys::finish_login))
+}
+
+async fn require_admin(
+    State(mut state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    // without a password or an account from `/setup` the admin area does not exist
+    let password = match state.config.admin_password.clone() {
+        Some(password) => AdminPassword::Configured(password),
+        None => match auth::password::admin(&*state.read().await)? {
+            Some(credentials) => AdminPassword::Stored(credentials),
+            None => return Err(AppError::NotFound),
+        },
+    };
+    let secret_key = state.secret_key.clone();
+    // a passkey session stands in for both the password and the second factor
+    let jar = CookieJar::from_headers(request.headers());
+    let session = jar
+        .get(passkeys::SESSION_COOKIE)
+        .map(|cookie| cookie.value());
+    let account = passkeys::session_account(&secret_key, &*state.read().await, session)?;
+    if let Some(account) = account {
+        request.extensions_mut().insert(AdminAccount(account));
+        return Ok(next.run(request).await);
+    }
+    // remembered devices get a new session and a rotated refresh token
+    if let Some((account, jar)) = passkeys::renew(&mut state, jar.clone()).await? {
+        request.extensions_mut().insert(AdminAccount(account));
+        return Ok((jar, next.run(request).await).into_response());
+    }
+    let credentials = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|encoded| STANDARD.decode(encoded).ok())
+        .and_then(|decoded| String::from_utf8(decoded).ok());
+    let Some((username, given)) = credentials
+        .as_deref()
+        .and_then(|credentials| credentials.split_once(':'))
+    else {
+        return Ok(unauthorized());
+    };
+
+    let mut subjects = vec![format!("admin:{}", username)];
+    if let Some(ip) = auth::client_ip(request.extensions()) {
+        subjects.push(format!("ip:{}", ip));
+    }
+    let throttle = LoginThrottle {
+        lockout_after: state.live.load().login_lockout_after,
+    };
+    // every admin request comes through here, the write lock is only taken to change the
+    // throttle and the password is checked without holding any
+    let has_failures = throttle.check(&*state.read().await, &subjects)?;
+    if !password.check(username, given) {
+        throttle.record_failure(&*state.write().await, &subjects)?;
+        return Ok(unauthorized());
+    }
+    if has_failures {
+        throttle.reset(&*state.write().await, &subjects)?;
+    }
+
+    // accounts with two-factor enabled have to pass the second step first
+    let account = username.to_string();
+    let db = state.read().await;
+    if auth::mfa::is_enforced(&db, &account)?
+        && !request.uri().path().ends_with(routes::MfaVerify::PATH)
+    {
+        let cookie = jar.get(mfa::MFA_COOKIE).map(|cookie| cookie.value());
+        if !mfa::is_verified(&secret_key, &**db.clock(), &account, cookie) {
+            return Ok(mfa::verify_page(None).into_response());
+        }
+    }
+    drop(db);
+    request.extensions_mut().insert(AdminAccount(account));
+    Ok(next.run(request).await)
+}
+
+// what basic auth credentials are checked against
+enum AdminPassword {
+    // `ADMIN_PASSWORD`, any username goes
+    Configured(String),
+    Stored(AdminCredentials),
+}
+impl AdminPassword {
+    fn check(&self, username: &str, given: &str) -> bool {
+        match self {
+            AdminPassword::Configured(password) => {
+                auth::constant_time_eq(given.as_bytes(), password.as_bytes())
+            }
+            AdminPassword::Stored(credentials) => credentials.check(username, given),
+        }
+    }
+}
+
+fn unauthorized() -> Response {
+    let mut response = ErrorReport::new(
+        StatusCode::UNAUTHORIZED,
+        "Admin credentials required, or sign in with a passkey at /admin/login.",
+    )
+    .into_response();
+    response.headers_mut().insert(
+        header::WWW_AUTHENTICATE,
+        HeaderValue::from_static("Basic realm=\"admin\""),
+    );
+    response
+}
@@ -0,0 +1,282 @@
+use anyhow::Result;
+use axum::{
+    extract::State,
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+use maud::{html, Markup, PreEscaped};
+use serde::{Deserialize, Serialize};
+use time::Duration;
+use webauthn_rs::prelude::{
+    CreationChallengeResponse, PublicKeyCredential, RegisterPublicKeyCredential,
+    RequestChallengeResponse,
+};
+
+use super::AdminAccount;
+use crate::{
+    auth::{passkey, password, remember, session},
+    db::driver::Db,
+    error::AppError,
+    state::AppState,
+    views,
+};
+
+pub const SESSION_COOKIE: &str = "admin_session";
+// the long-lived refresh token of a remembered device
+pub const REMEMBER_COOKIE: &str = "admin_remember";
+const SESSION_LIFETIME: std::time::Duration = std::time::Duration::from_secs(12 * 60 * 60);
+
+// the browser half of the ceremonies, webauthn json uses base64url for binary fields
+const PASSKEY_SCRIPT: &str = r#"
+const b64 = {
+    decode: (s) => Uint8Array.from(atob(s.replace(/-/g, "+").replace(/_/g, "/")), (c) => c.charCodeAt(0)),
+    encode: (b) => btoa(String.fromCharCode(...new Uint8Array(b))).replace(/\+/g, "-").replace(/\//g, "_").replace(/=/g, ""),
+};
+const postJson = (url, body) => fetch(url, {
+    method: "POST",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify(body),
+}).then((response) => {
+    if (!response.ok) throw new Error("request failed");
+    return response.json();
+});
+async function registerPasskey() {
+    const options = await postJson("/admin/passkeys/register/start", {});
+    options.publicKey.challenge = b64.decode(options.publicKey.challenge);
+    options.publicKey.user.id = b64.decode(options.publicKey.user.id);
+    (options.publicKey.excludeCredentials || []).forEach((c) => c.id = b64.decode(c.id));
+    const credential = await navigator.credentials.create(options);
+    await postJson("/admin/passkeys/register/finish", {
+        id: credential.id,
+        rawId: b64.encode(credential.rawId),
+        type: credential.type,
+        extensions: credential.getClientExtensionResults(),
+        response: {
+            attestationObject: b64.encode(credential.response.attestationObject),
+            clientDataJSON: b64.encode(credential.response.clientDataJSON),
+        },
+    });
+    document.getElementById("passkey-status").textContent = "Passkey added.";
+}
+async function signInWithPasskey(account, remember) {
+    const { ceremony, options } = await postJson("/admin/login/start", { account });
+    options.publicKey.challenge = b64.decode(options.publicKey.challenge);
+    (options.publicKey.allowCredentials || []).forEach((c) => c.id = b64.decode(c.id));
+    const credential = await navigator.credentials.get(options);
+    await postJson("/admin/login/finish", {
+        ceremony,
+        remember,
+        credential: {
+            id: credential.id,
+            rawId: b64.encode(credential.rawId),
+            type: credential.type,
+            extensions: credential.getClientExtensionResults(),
+            response: {
+                authenticatorData: b64.encode(credential.response.authenticatorData),
+                clientDataJSON: b64.encode(credential.response.clientDataJSON),
+                signature: b64.encode(credential.response.signature),
+                userHandle: credential.response.userHandle ? b64.encode(credential.response.userHandle) : null,
+            },
+        },
+    });
+    window.location = "/admin/tenants";
+}
+"#;
+
+// Whether `cookie` is a signed-in session of an admin account, returns the account. A live
+// session alone is not enough, the account has to still be one of the admin store.
+pub fn session_account(key: &[u8], db: &Db, cookie: Option<&str>) -> Result<Option<String>> {
+    let Some(account) = session::account(db, key, SESSION_COOKIE, cookie)? else {
+        return Ok(None);
+    };
+    Ok(is_admin(db, &account)?.then_some(account))
+}
+
+// The admin store: the account from `/setup` and the accounts with passkeys, which are only
+// registered from inside `/admin`.
+pub fn is_admin(db: &Db, account: &str) -> Result<bool> {
+    let stored = password::admin(db)?.is_some_and(|credentials| credentials.username == account);
+    Ok(stored || !passkey::passkeys(db, account)?.is_empty())
+}
+
+// Start a new session from the remember cookie once the session cookie expired. Returns the
+// account and the jar with the fresh session and the rotated refresh token.
+pub async fn renew(
+    state: &mut AppState,
+    jar: CookieJar,
+) -> Result<Option<(String, CookieJar)>, AppError> {
+    let Some(cookie) = jar
+        .get(REMEMBER_COOKIE)
+        .map(|cookie| cookie.value().to_string())
+    else {
+        return Ok(None);
+    };
+    let db = state.write().await;
+    let Some((account, token)) = remember::rotate(&db, &cookie)? else {
+        return Ok(None);
+    };
+    if !is_admin(&db, &account)? {
+        return Ok(None);
+    }
+    let fresh = session_cookie(&state.secret_key, &db, &account)?;
+    drop(db);
+    let jar = jar.add(fresh).add(remember_cookie(token));
+    Ok(Some((account, jar)))
+}
+
+pub fn session_cookie(key: &[u8], db: &Db, account: &str) -> Result<Cookie<'static>> {
+    let value = session::start(db, key, SESSION_COOKIE, account, SESSION_LIFETIME)?;
+    // sent to every page, with `AUTH_MODE=accounts` they need it too
+    Ok(Cookie::build((SESSION_COOKIE, value))
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .build())
+}
+fn remember_cookie(token: String) -> Cookie<'static> {
+    Cookie::build((REMEMBER_COOKIE, token))
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .max_age(Duration::seconds(
+            remember::REMEMBER_LIFETIME.as_secs() as i64
+        ))
+        .build()
+}
+
+// === Components ===
+pub fn register_html() -> Markup {
+    html! {
+        div class="space-y-2" {
+            p id="passkey-status" class="text-gray-700" { "Sign in without a password using a passkey on this device." }
+            button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="button"
+                onclick="registerPasskey().catch(() => document.getElementById('passkey-status').textContent = 'Adding the passkey failed.')" { "Add a passkey" }
+            script { (PreEscaped(PASSKEY_SCRIPT)) }
+        }
+    }
+}
+
+// === Routes ===
+pub async fn login_page() -> Markup {
+    views::page(
+        "Sign in",
+        html! {
+            div class="bg-white rounded-lg shadow-lg p-8 max-w-md mx-auto space-y-4" {
+                h1 class="text-2xl text-gray-700" { "Sign in with a passkey" }
+                form class="space-y-4" onsubmit="event.preventDefault(); signInWithPasskey(this.account.value, this.remember.checked).catch(() => document.getElementById('passkey-status').textContent = 'Signing in failed.')" {
+                    input class="w-full rounded p-2 border" type="text" name="account" placeholder="Account" autocomplete="username webauthn" required;
+                    label class="flex items-center text-gray-700" {
+                        input class="mr-2" type="checkbox" name="remember";
+                        "Remember this device for 30 days"
+                    }
+                    p id="passkey-status" class="text-red-500" {}
+                    button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" { "Sign in" }
+                }
+                script { (PreEscaped(PASSKEY_SCRIPT)) }
+            }
+        },
+    )
+}
+
+pub async fn start_registration(
+    State(mut state): State<AppState>,
+    Extension(AdminAccount(account)): Extension<AdminAccount>,
+) -> Result<Json<CreationChallengeResponse>, AppError> {
+    let webauthn = passkey::webauthn(&state.config)?;
+    let challenge = passkey::start_registration(&webauthn, &*state.write().await, &account)?;
+    Ok(Json(challenge))
+}
+
+pub async fn finish_registration(
+    State(mut state): State<AppState>,
+    Extension(AdminAccount(account)): Extension<AdminAccount>,
+    Json(credential): Json<RegisterPublicKeyCredential>,
+) -> Result<Json<()>, AppError> {
+    let webauthn = passkey::webauthn(&state.config)?;
+    passkey::finish_registration(&webauthn, &*state.write().await, &account, &credential)?;
+    tracing::info!(account = %account, "registered a passkey");
+    Ok(Json(()))
+}
+
+#[derive(Deserialize)]
+pub struct StartLogin {
+    account: String,
+}
+#[derive(Serialize)]
+pub struct LoginChallenge {
+    ceremony: String,
+    options: RequestChallengeResponse,
+}
+pub async fn start_login(
+    State(mut state): State<AppState>,
+    Json(StartLogin { account }): Json<StartLogin>,
+) -> Result<Json<LoginChallenge>, AppError> {
+    let webauthn = passkey::webauthn(&state.config)?;
+    let started = passkey::start_authentication(&webauthn, &*state.write().await, &account)?;
+    let (ceremony, options) = started.ok_or(AppError::NotFound)?;
+    Ok(Json(LoginChallenge { ceremony, options }))
+}
+
+#[derive(Deserialize)]
+pub struct FinishLogin {
+    ceremony: String,
+    #[serde(default)]
+    remember: bool,
+    credential: PublicKeyCredential,
+}
+pub async fn finish_login(
+    State(mut state): State<AppState>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    Json(FinishLogin {
+        ceremony,
+        remember,
+        credential,
+    }): Json<FinishLogin>,
+) -> Result<Response, AppError> {
+    let webauthn = passkey::webauthn(&state.config)?;
+    let secret_key = state.secret_key.clone();
+    let db = state.write().await;
+    let account = passkey::finish_authentication(&webauthn, &db, &ceremony, &credential)?;
+    tracing::info!(account = %account, remember, "signed in with a passkey");
+    let mut jar = jar.add(session_cookie(&secret_key, &db, &account)?);
+    if remember {
+        let label = headers
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("Unknown device");
+        jar = jar.add(remember_cookie(remember::issue(&db, &account, label)?));
+    }
+    Ok((jar, Json(())).into_response())
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{auth::password::AdminCredentials, fixtures::TestDb};
+
+    #[test]
+    fn test_sessions_need_an_admin_account() -> Result<()> {
+        let db = TestDb::new("admin_sessions")?;
+        password::set_admin(
+            &db,
+            &AdminCredentials::new("root", "correct horse battery")?,
+        )?;
+        let admin = session_cookie(b"key", &db, "root")?;
+        assert_eq!(
+            session_account(b"key", &db, Some(admin.value()))?,
+            Some("root".to_string())
+        );
+        // signed by this server, but not for anyone in the admin store
+        let stranger = session_cookie(b"key", &db, "ada")?;
+        assert_eq!(session_account(b"key", &db, Some(stranger.value()))?, None);
+        assert_eq!(
+            session_account(b"other key", &db, Some(admin.value()))?,
+            None
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+};
+use maud::{html, Markup};
+use time::OffsetDateTime;
+
+use crate::{
+    error::AppError,
+    routes,
+    state::AppState,
+    tasks::{self, Task, TaskKind},
+    views,
+};
+
+// === Components ===
+fn at(millis: u64) -> String {
+    OffsetDateTime::from_unix_timestamp((millis / 1000) as i64)
+        .map(|at| format!("{} {:02}:{:02} UTC", at.date(), at.hour(), at.minute()))
+        .unwrap_or_default()
+}
+
+fn target(kind: &TaskKind) -> &str {
+    match kind {
+        TaskKind::Webhook { url, .. } | TaskKind::LinkPreview { url, .. } => url,
+        // the url is looked up when it runs
+        TaskKind::Integration { list, .. } => list,
+    }
+}
+
+fn dead_row(task: &Task) -> Markup {
+    html! {
+        tr class="border-t" {
+            td class="py-2 px-4" { (task.kind.label()) }
+            td class="py-2 px-4 font-mono break-all" { (target(&task.kind)) }
+            td class="py-2 px-4" { (at(task.run_at)) }
+            td class="py-2 px-4 text-red-600" { (task.last_error.as_deref().unwrap_or_default()) }
+            td class="py-2 px-4 text-right whitespace-nowrap" {
+                button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded mr-2"
+                    hx-post=(routes::DeadTaskRetry::url(task.id)) hx-target="closest tr" hx-swap="outerHTML" { "Retry" }
+                button class="bg-red-500 hover:bg-red-700 text-white font-bold py-1 px-2 rounded"
+                    hx-delete=(routes::DeadTask::url(task.id)) hx-target="closest tr" hx-swap="outerHTML" { "Discard" }
+            }
+        }
+    }
+}
+
+// === Routes ===
+pub async fn index(State(state): State<AppState>) -> Result<Markup, AppError> {
+    let db = state.read().await;
+    let pending = tasks::pending(&db)?;
+    let dead = tasks::dead(&db)?;
+    Ok(views::page(
+        "Queue",
+        html! {
+            h1 class="text-4xl text-center text-gray-700 mb-6" { "Queue" }
+            dl class="grid grid-cols-2 gap-2 bg-white rounded-lg shadow-lg p-4 mb-6 text-gray-700" {
+                dt { "Waiting" } dd { (pending.len()) }
+                dt { "Running" } dd { (state.tasks.running()) }
+                dt { "Dead" } dd { (dead.len()) }
+            }
+            h2 class="text-2xl text-gray-700 mb-2" { "Dead letters" }
+            p class="text-gray-500 mb-2" { "Tasks that failed " (tasks::MAX_ATTEMPTS) " times in a row." }
+            table class="w-full bg-white rounded-lg shadow-lg" {
+                thead {
+                    tr {
+                        th class="py-2 px-4 text-left" { "Kind" }
+                        th class="py-2 px-4 text-left" { "Target" }
+                        th class="py-2 px-4 text-left" { "Last attempt" }
+                        th class="py-2 px-4 text-left" { "Error" }
+                        th {}
+                    }
+                }
+                tbody {
+                    @for task in &dead { (dead_row(task)) }
+                }
+            }
+        },
+    ))
+}
+
+pub async fn metrics(State(state): State<AppState>) -> Result<impl IntoResponse, AppError> {
+    let metrics = tasks::metrics(&state.read().await, state.tasks.running())?;
+    Ok((
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics,
+    ))
+}
+
+// both drop the row, a retried task is back with the waiting ones
+pub async fn retry(
+    State(mut state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<Markup, AppError> {
+    if !tasks::retry_dead(&state.write().await, id)? {
+        return Err(AppError::NotFound);
+    }
+    state.tasks.notify();
+    Ok(html! {})
+}
+
+pub async fn discard(
+    State(mut state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<u64>,
+) -> Result<Response, AppError> {
+    if !tasks::discard_dead(&state.write().await, id)? {
+        return Err(AppError::NotFound);
+    }
+    Ok(views::deleted(&headers, &routes::Queue::url()))
+}
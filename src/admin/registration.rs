@@ -0,0 +1,168 @@
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, State},
+    response::Redirect,
+    Form,
+};
+use maud::{html, Markup};
+use serde::Deserialize;
+use time::OffsetDateTime;
+
+use crate::{
+    error::AppError,
+    registration::{self, Invite},
+    routes,
+    state::AppState,
+    views,
+};
+
+// === Components ===
+fn date(unix: u64) -> String {
+    OffsetDateTime::from_unix_timestamp(unix as i64)
+        .map(|at| at.date().to_string())
+        .unwrap_or_default()
+}
+
+fn invite_row(invite: &Invite, now: u64) -> Markup {
+    html! {
+        tr class="border-t" {
+            td class="py-2 px-4 font-mono" { (invite.code) }
+            td class="py-2 px-4 text-right" {
+                (invite.uses) " / "
+                @match invite.max_uses {
+                    Some(max) => { (max) }
+                    None => { "∞" }
+                }
+            }
+            td class="py-2 px-4" {
+                @match invite.expires_at {
+                    Some(at) => { (date(at)) }
+                    None => { "never" }
+                }
+            }
+            td class="py-2 px-4" {
+                @if invite.is_usable(now) {
+                    span class="text-green-600" { "Usable" }
+                } @else {
+                    span class="text-gray-400" { "Used up" }
+                }
+            }
+            td class="py-2 px-4 text-right" {
+                button class="bg-red-500 hover:bg-red-700 text-white font-bold py-1 px-2 rounded"
+                    hx-post=(routes::InviteRevoke::url(&invite.code)) hx-target="closest tr" hx-swap="outerHTML" { "Revoke" }
+            }
+        }
+    }
+}
+
+fn new_invite_html() -> Markup {
+    html! {
+        form class="flex justify-between items-center mb-6" hx-post=(routes::Invites::url()) hx-target="#invites tbody" hx-swap="afterbegin" "hx-on::after-request"="this.reset()" {
+            input class="w-full rounded p-2 mr-4" type="number" name="max_uses" min="1" placeholder="Uses (unlimited when empty)";
+            input class="w-full rounded p-2 mr-4" type="number" name="valid_days" min="1" placeholder="Valid for days (forever when empty)";
+            button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" { "Create" }
+        }
+    }
+}
+
+// === Routes ===
+pub async fn index(State(state): State<AppState>) -> Result<Markup, AppError> {
+    let config = state.live.load();
+    let db = state.read().await;
+    let open = registration::is_open(&config, &db)?;
+    let invites = registration::list_invites(&db)?;
+    let now = db.clock().now_millis() / 1000;
+    Ok(views::page(
+        "Registration",
+        html! {
+            h1 class="text-4xl text-center text-gray-700 mb-6" { "Registration" }
+            form class="flex justify-between items-center bg-white rounded-lg shadow-lg p-4 mb-6" method="post" action=(routes::Registration::url()) {
+                p {
+                    @if open { "Anyone can sign up for a workspace." } @else { "Signing up needs an invite code." }
+                    @if config.registration_open.is_some() {
+                        span class="text-gray-400 ml-2" { "Set by REGISTRATION_OPEN." }
+                    }
+                }
+                @if config.registration_open.is_none() {
+                    input type="hidden" name="open" value=(!open);
+                    button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" {
+                        @if open { "Close" } @else { "Open" }
+                    }
+                }
+            }
+            h2 class="text-2xl text-gray-700 mb-2" { "Invites" }
+            (new_invite_html())
+            table id="invites" class="w-full bg-white rounded-lg shadow-lg" {
+                thead {
+                    tr {
+                        th class="py-2 px-4 text-left" { "Code" }
+                        th class="py-2 px-4 text-right" { "Uses" }
+                        th class="py-2 px-4 text-left" { "Expires" }
+                        th class="py-2 px-4 text-left" { "State" }
+                        th {}
+                    }
+                }
+                tbody {
+                    @for invite in &invites { (invite_row(invite, now)) }
+                }
+            }
+        },
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct SetOpen {
+    open: bool,
+}
+pub async fn set_open(
+    State(mut state): State<AppState>,
+    Form(SetOpen { open }): Form<SetOpen>,
+) -> Result<Redirect, AppError> {
+    let db = state.write().await;
+    registration::set_open(&db, open)?;
+    tracing::info!(open, "changed registration");
+    Ok(Redirect::to(&routes::Registration::url()))
+}
+
+#[derive(Deserialize)]
+pub struct CreateInvite {
+    // empty inputs arrive as empty strings
+    #[serde(default)]
+    max_uses: String,
+    #[serde(default)]
+    valid_days: String,
+}
+pub async fn create_invite(
+    State(mut state): State<AppState>,
+    Form(CreateInvite {
+        max_uses,
+        valid_days,
+    }): Form<CreateInvite>,
+) -> Result<Markup, AppError> {
+    let max_uses = optional_number(&max_uses)?;
+    let valid_for = optional_number(&valid_days)?
+        .map(|days| Duration::from_secs(u64::from(days) * 24 * 60 * 60));
+    let db = state.write().await;
+    let invite = registration::create_invite(&db, max_uses, valid_for)?;
+    Ok(invite_row(&invite, db.clock().now_millis() / 1000))
+}
+
+fn optional_number(value: &str) -> Result<Option<u32>, AppError> {
+    match value.trim() {
+        "" => Ok(None),
+        value => match value.parse() {
+            Ok(0) | Err(_) => Err(anyhow::anyhow!("`{}` is not a positive number", value).into()),
+            Ok(number) => Ok(Some(number)),
+        },
+    }
+}
+
+pub async fn revoke_invite(
+    State(mut state): State<AppState>,
+    Path(code): Path<String>,
+) -> Result<Markup, AppError> {
+    let db = state.write().await;
+    registration::revoke_invite(&db, &code)?;
+    Ok(html! {})
+}
@@ -0,0 +1,128 @@
+use std::time::Duration;
+
+use axum::extract::State;
+use maud::{html, Markup};
+
+use super::jobs;
+use crate::{
+    error::AppError,
+    retention::{self, Keyspace, Volume},
+    scheduler,
+    state::AppState,
+    views,
+};
+
+// === Components ===
+fn kept_for(keep: Duration) -> String {
+    let hours = keep.as_secs() / (60 * 60);
+    if hours % 24 == 0 {
+        format!("{} days", hours / 24)
+    } else {
+        format!("{} hours", hours)
+    }
+}
+
+fn size(bytes: usize) -> String {
+    match bytes {
+        0..=1023 => format!("{} B", bytes),
+        1024..=1_048_575 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MiB", bytes as f64 / 1_048_576.0),
+    }
+}
+
+// `next` is `None` without a purge job, `Some(None)` before its first run at startup
+fn keyspace_row(
+    keyspace: Keyspace,
+    keep: Option<Duration>,
+    volume: Volume,
+    next: Option<Option<u64>>,
+) -> Markup {
+    html! {
+        tr class="border-t" {
+            td class="py-2 px-4" {
+                div { (keyspace.label()) }
+                div class="text-sm text-gray-500" { (keyspace.description()) }
+            }
+            td class="py-2 px-4" {
+                @match keep {
+                    Some(keep) => { (kept_for(keep)) }
+                    None => { span class="text-gray-400" { "for good" } }
+                }
+            }
+            td class="py-2 px-4 text-right" { (volume.keys) }
+            td class="py-2 px-4 text-right" { (size(volume.bytes)) }
+            td class="py-2 px-4" {
+                @match next {
+                    Some(Some(next)) => { (jobs::at(next)) }
+                    Some(None) => { "at startup" }
+                    None => { span class="text-gray-400" { "never" } }
+                }
+            }
+        }
+    }
+}
+
+// === Routes ===
+pub async fn index(State(state): State<AppState>) -> Result<Markup, AppError> {
+    let mut rows = Vec::new();
+    {
+        let db = state.read().await;
+        for keyspace in Keyspace::ALL {
+            let volume = retention::volume(&db, keyspace, state.recorder.as_ref())?;
+            let job = state
+                .scheduler
+                .jobs()
+                .iter()
+                .find(|job| job.name == keyspace.job_name());
+            let next = match job {
+                Some(job) => Some(jobs::next_run(
+                    job,
+                    scheduler::last_run(&db, job.name)?.as_ref(),
+                )),
+                None => None,
+            };
+            rows.push(keyspace_row(
+                keyspace,
+                keyspace.policy(&state.config),
+                volume,
+                next,
+            ));
+        }
+    }
+    Ok(views::page(
+        "Retention",
+        html! {
+            h1 class="text-4xl text-center text-gray-700 mb-6" { "Retention" }
+            p class="text-gray-500 mb-4" {
+                "How long each keyspace is kept is set with RETENTION_ACTIVITY_DAYS, RETENTION_NOTIFICATIONS_DAYS, "
+                "RETENTION_DELETED_TODOS_DAYS and RETENTION_RECORDINGS_HOURS, the purge jobs show up under jobs."
+            }
+            table class="w-full bg-white rounded-lg shadow-lg" {
+                thead {
+                    tr {
+                        th class="py-2 px-4 text-left" { "Keyspace" }
+                        th class="py-2 px-4 text-left" { "Kept" }
+                        th class="py-2 px-4 text-right" { "Keys" }
+                        th class="py-2 px-4 text-right" { "Size" }
+                        th class="py-2 px-4 text-left" { "Next purge" }
+                    }
+                }
+                tbody { @for row in rows { (row) } }
+            }
+        },
+    ))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kept_for_and_size() {
+        assert_eq!(kept_for(Duration::from_secs(30 * 86_400)), "30 days");
+        assert_eq!(kept_for(Duration::from_secs(6 * 3_600)), "6 hours");
+        assert_eq!(size(512), "512 B");
+        assert_eq!(size(1536), "1.5 KiB");
+    }
+}
@@ -0,0 +1,176 @@
+use axum::{
+    extract::{Path, State},
+    http::header,
+    response::IntoResponse,
+    Extension, Form, Json,
+};
+use maud::{html, Markup};
+use serde::Deserialize;
+
+use super::AdminAccount;
+use crate::{
+    db::driver::Db,
+    error::AppError,
+    repository::query::TodoQuery,
+    routes,
+    state::AppState,
+    tenant::{self, TenantRecord},
+    views,
+};
+
+// === Components ===
+fn tenant_row(record: &TenantRecord, keys: usize, members: &[String]) -> Markup {
+    html! {
+        tr class="border-t" {
+            td class="py-2 px-4 font-mono" { (record.id) }
+            td class="py-2 px-4" { (record.name) }
+            td class="py-2 px-4 text-right" { (keys) }
+            td class="py-2 px-4" {
+                @for member in members {
+                    span class="inline-block bg-gray-100 rounded px-2 mr-1 mb-1" {
+                        (member) " "
+                        button class="text-red-500 hover:text-red-700" title={"Remove " (member)}
+                            hx-post=(routes::TenantMemberRemove::url(&record.id, member)) hx-target="closest tr" hx-swap="outerHTML" { "×" }
+                    }
+                }
+                form class="inline" hx-post=(routes::TenantMembers::url(&record.id)) hx-target="closest tr" hx-swap="outerHTML" {
+                    input class="rounded p-1 w-32" type="text" name="account" placeholder="account" required;
+                }
+            }
+            td class="py-2 px-4 text-right" {
+                a class="text-blue-500 hover:text-blue-700 mr-4" href=(routes::TenantExport::url(&record.id)) { "Export" }
+                button class="bg-red-500 hover:bg-red-700 text-white font-bold py-1 px-2 rounded"
+                    hx-post=(routes::TenantErase::url(&record.id)) hx-target="closest tr" hx-swap="outerHTML"
+                    hx-confirm={"Erase every record of " (record.id) "? This cannot be undone."} { "Erase" }
+            }
+        }
+    }
+}
+
+// the row of a registered tenant as it is stored now
+fn stored_row(db: &Db, id: &str) -> Result<Markup, AppError> {
+    let record = tenant::get(db, id)?.ok_or(AppError::NotFound)?;
+    let keys = db.for_tenant(Some(id))?.len();
+    Ok(tenant_row(&record, keys, &tenant::members(db, id)?))
+}
+
+fn new_tenant_html() -> Markup {
+    html! {
+        form class="flex justify-between items-center mb-6" hx-post=(routes::Tenants::url()) hx-target="#tenants tbody" hx-swap="beforeend" "hx-on::after-request"="this.reset()" {
+            input class="rounded p-2 mr-4" type="text" name="id" placeholder="subdomain" pattern="[a-z0-9-]+" required;
+            input class="w-full rounded p-2 mr-4" type="text" name="name" placeholder="Workspace name" required;
+            button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" { "Create" }
+        }
+    }
+}
+
+// === Routes ===
+pub async fn index(State(state): State<AppState>) -> Result<Markup, AppError> {
+    let db = state.read().await;
+    let mut rows = Vec::new();
+    for record in tenant::list(&db)? {
+        rows.push(stored_row(&db, &record.id)?);
+    }
+    Ok(views::page(
+        "Tenants",
+        html! {
+            h1 class="text-4xl text-center text-gray-700 mb-6" { "Tenants" }
+            (new_tenant_html())
+            table id="tenants" class="w-full bg-white rounded-lg shadow-lg" {
+                thead {
+                    tr {
+                        th class="py-2 px-4 text-left" { "Id" }
+                        th class="py-2 px-4 text-left" { "Name" }
+                        th class="py-2 px-4 text-right" { "Keys" }
+                        th class="py-2 px-4 text-left" { "Members" }
+                        th {}
+                    }
+                }
+                tbody {
+                    @for row in rows { (row) }
+                }
+            }
+        },
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct CreateTenant {
+    id: String,
+    name: String,
+}
+// the admin creating the tenant is its first member
+pub async fn create(
+    State(mut state): State<AppState>,
+    Extension(AdminAccount(admin)): Extension<AdminAccount>,
+    Form(CreateTenant { id, name }): Form<CreateTenant>,
+) -> Result<Markup, AppError> {
+    if !tenant::is_valid_id(&id) {
+        return Err(anyhow::anyhow!("tenant ids may only contain a-z, 0-9 and -").into());
+    }
+    let db = state.write().await;
+    let record = TenantRecord { id, name };
+    tenant::register(&db, &record)?;
+    tenant::add_member(&db, &record.id, &admin)?;
+    Ok(tenant_row(&record, 0, &[admin]))
+}
+
+// every todo of the tenant as a json download
+pub async fn export(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let db = state.read().await;
+    if tenant::get(&db, &id)?.is_none() {
+        return Err(AppError::NotFound);
+    }
+    let todos = TodoQuery::new()
+        .including_archived()
+        .including_someday()
+        .list(&db.for_tenant(Some(&id))?)?;
+    let disposition = format!("attachment; filename=\"{}-export.json\"", id);
+    Ok(([(header::CONTENT_DISPOSITION, disposition)], Json(todos)))
+}
+
+pub async fn erase(
+    State(mut state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Markup, AppError> {
+    let db = state.write().await;
+    if tenant::get(&db, &id)?.is_none() {
+        return Err(AppError::NotFound);
+    }
+    tenant::erase(&db, &id)?;
+    tracing::info!(tenant = %id, "erased tenant");
+    Ok(html! {})
+}
+
+#[derive(Deserialize)]
+pub struct AddMember {
+    account: String,
+}
+pub async fn add_member(
+    State(mut state): State<AppState>,
+    Path(id): Path<String>,
+    Form(AddMember { account }): Form<AddMember>,
+) -> Result<Markup, AppError> {
+    let account = account.trim();
+    if account.is_empty() {
+        return Err(AppError::Invalid("Name the account to add.".into()));
+    }
+    let db = state.write().await;
+    if tenant::get(&db, &id)?.is_none() {
+        return Err(AppError::NotFound);
+    }
+    tenant::add_member(&db, &id, account)?;
+    stored_row(&db, &id)
+}
+
+pub async fn remove_member(
+    State(mut state): State<AppState>,
+    Path((id, account)): Path<(String, String)>,
+) -> Result<Markup, AppError> {
+    let db = state.write().await;
+    tenant::remove_member(&db, &id, &account)?;
+    stored_row(&db, &id)
+}
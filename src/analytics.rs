@@ -0,0 +1,263 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::Result;
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{header, Method},
+    middleware::Next,
+    response::Response,
+};
+use maud::{html, Markup};
+use serde::{Deserialize, Serialize};
+use time::Date;
+
+use crate::{
+    auth::{self, visitor::Visitor},
+    db::driver::Db,
+    scheduler::{Job, Schedule},
+    state::AppState,
+    views,
+};
+
+// `analytics:{yyyy-mm-dd}`, iso dates so the keys sort by day
+const DAY_PREFIX: &str = "analytics:";
+// `analytics_seen:{yyyy-mm-dd}:{hash}`, who was counted for a day, gone the day after
+const SEEN_PREFIX: &str = "analytics_seen:";
+const SEEN_TTL: Duration = Duration::from_secs(2 * 24 * 60 * 60);
+const FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+const SPARKLINE_WIDTH: usize = 120;
+const SPARKLINE_HEIGHT: usize = 24;
+
+// === Counts ===
+// What a day looked like, with nothing that points back at a person: page views per route, as
+// declared in `routes` rather than the url visited, and how many visitors there were.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DailyCounts {
+    pub day: Date,
+    pub views: BTreeMap<String, u64>,
+    // distinct visitor cookies
+    pub sessions: u64,
+}
+impl DailyCounts {
+    pub fn new(day: Date) -> Self {
+        Self {
+            day,
+            views: BTreeMap::new(),
+            sessions: 0,
+        }
+    }
+    pub fn total_views(&self) -> u64 {
+        self.views.values().sum()
+    }
+}
+
+fn day_key(day: Date) -> String {
+    format!("{}{}", DAY_PREFIX, day)
+}
+
+// The visitor cookie stands for a session only for a day and only here, hashed with the secret
+// key and the day so days cannot be linked.
+pub fn visitor_hash(key: &[u8], day: Date, visitor: &str) -> String {
+    let signed = auth::sign(key, "visitor_day", &format!("{}:{}", day, visitor));
+    let (_, signature) = signed.rsplit_once('.').unwrap_or_default();
+    signature[..16.min(signature.len())].to_string()
+}
+
+// the stored days from `from` on, oldest first
+pub fn since(db: &Db, from: Date) -> Result<Vec<DailyCounts>> {
+    let start = day_key(from);
+    db.iter_prefix::<DailyCounts>(DAY_PREFIX)?
+        .filter(|item| item.as_ref().map_or(true, |(key, _)| *key >= start))
+        .map(|item| item.map(|(_, counts)| counts))
+        .collect()
+}
+
+// `days` days up to `today`, days without a visit included as zeros
+pub fn series(db: &Db, today: Date, days: i64) -> Result<Vec<DailyCounts>> {
+    let from = today - time::Duration::days(days - 1);
+    let mut stored: HashMap<Date, DailyCounts> = since(db, from)?
+        .into_iter()
+        .map(|counts| (counts.day, counts))
+        .collect();
+    Ok((0..days)
+        .map(|offset| from + time::Duration::days(offset))
+        .map(|day| stored.remove(&day).unwrap_or_else(|| DailyCounts::new(day)))
+        .collect())
+}
+
+// === Counter ===
+// Views are counted in memory and added to the day keys once a minute, a page view costs no
+// write of its own.
+#[derive(Debug, Clone, Default)]
+pub struct Counter {
+    pending: Arc<Mutex<Pending>>,
+}
+
+#[derive(Debug, Default)]
+pub struct Pending {
+    views: HashMap<(Date, String), u64>,
+    visitors: HashSet<(Date, String)>,
+}
+
+impl Counter {
+    pub fn view(&self, day: Date, route: &str, visitor: Option<String>) {
+        let mut pending = self.pending.lock().expect("analytics lock poisoned");
+        *pending.views.entry((day, route.to_string())).or_default() += 1;
+        if let Some(visitor) = visitor {
+            pending.visitors.insert((day, visitor));
+        }
+    }
+
+    pub fn take(&self) -> Pending {
+        std::mem::take(&mut *self.pending.lock().expect("analytics lock poisoned"))
+    }
+}
+
+// Add what was counted to the day keys. A visitor already counted for the day, before a restart
+// too, is not counted again.
+pub fn flush(db: &Db, pending: Pending) -> Result<()> {
+    let mut days: BTreeMap<Date, DailyCounts> = BTreeMap::new();
+    for ((day, route), views) in pending.views {
+        *days
+            .entry(day)
+            .or_insert_with(|| DailyCounts::new(day))
+            .views
+            .entry(route)
+            .or_default() += views;
+    }
+    for (day, visitor) in pending.visitors {
+        let seen = format!("{}{}:{}", SEEN_PREFIX, day, visitor);
+        if db.get::<bool, _>(&seen)?.is_some() {
+            continue;
+        }
+        db.insert_with_ttl(&seen, &true, SEEN_TTL)?;
+        days.entry(day)
+            .or_insert_with(|| DailyCounts::new(day))
+            .sessions += 1;
+    }
+    for (day, counted) in days {
+        db.update(day_key(day), |stored: Option<DailyCounts>| {
+            let mut stored = stored.unwrap_or_else(|| DailyCounts::new(day));
+            for (route, views) in &counted.views {
+                *stored.views.entry(route.clone()).or_default() += views;
+            }
+            stored.sessions += counted.sessions;
+            Some(stored)
+        })?;
+    }
+    Ok(())
+}
+
+pub fn flush_job(counter: Counter) -> Job {
+    Job::new("analytics", Schedule::Every(FLUSH_INTERVAL), move |db| {
+        let pending = counter.take();
+        Box::pin(async move { flush(&*db.read().await, pending) })
+    })
+}
+
+// === Middleware ===
+// Count full page views that succeeded. Fragments, assets and the api are left out, and so is
+// anything outside the declared routes.
+pub async fn count(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let Some(counter) = state.analytics.clone() else {
+        return next.run(request).await;
+    };
+    if request.method() != Method::GET || views::wants_fragment(request.headers()) {
+        return next.run(request).await;
+    }
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string());
+    let visitor = request
+        .extensions()
+        .get::<Visitor>()
+        .map(|Visitor(visitor)| visitor.clone());
+    let response = next.run(request).await;
+    let is_page = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/html"));
+    if let Some(route) = route.filter(|_| is_page && response.status().is_success()) {
+        let day = state.clock.now().date();
+        let visitor = visitor.map(|visitor| visitor_hash(&state.secret_key, day, &visitor));
+        counter.view(day, &route, visitor);
+    }
+    response
+}
+
+// === Components ===
+// a line over the days, scaled to the busiest one
+pub fn sparkline_svg(values: &[u64], label: &str) -> Markup {
+    let max = values.iter().copied().max().unwrap_or(0).max(1) as usize;
+    let step = SPARKLINE_WIDTH / values.len().saturating_sub(1).max(1);
+    let points: Vec<String> = values
+        .iter()
+        .enumerate()
+        .map(|(index, value)| {
+            format!(
+                "{},{}",
+                index * step,
+                SPARKLINE_HEIGHT - *value as usize * SPARKLINE_HEIGHT / max
+            )
+        })
+        .collect();
+    html! {
+        svg class="w-32 h-6 t-accent" viewBox={ "0 0 " (SPARKLINE_WIDTH) " " (SPARKLINE_HEIGHT) } preserveAspectRatio="none"
+            role="img" aria-label=(label) {
+            polyline points=(points.join(" ")) fill="none" stroke="currentColor" stroke-width="2" {}
+        }
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::TestDb;
+    use time::macros::date;
+
+    #[test]
+    fn test_flush_adds_up_days() -> Result<()> {
+        let db = TestDb::new("analytics")?;
+        let day = date!(2024 - 03 - 01);
+        let counter = Counter::default();
+        let alice = visitor_hash(b"key", day, "alice");
+        counter.view(day, "/todos", Some(alice.clone()));
+        counter.view(day, "/todos", Some(alice.clone()));
+        counter.view(day, "/stats", None);
+        flush(&db, counter.take())?;
+
+        // a second flush, e.g. after a restart, adds to the day and knows alice already
+        counter.view(day, "/todos", Some(alice));
+        counter.view(day, "/todos", Some(visitor_hash(b"key", day, "bob")));
+        flush(&db, counter.take())?;
+
+        let counts = &since(&db, day)?[0];
+        assert_eq!(counts.views["/todos"], 4);
+        assert_eq!(counts.total_views(), 5);
+        assert_eq!(counts.sessions, 2);
+
+        let series = series(&db, day.next_day().unwrap(), 3)?;
+        let views: Vec<u64> = series.iter().map(DailyCounts::total_views).collect();
+        assert_eq!(views, [0, 5, 0]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_visitor_hash_changes_every_day() {
+        let day = date!(2024 - 03 - 01);
+        let today = visitor_hash(b"key", day, "alice");
+        assert_eq!(today.len(), 16);
+        assert_eq!(today, visitor_hash(b"key", day, "alice"));
+        assert_ne!(
+            today,
+            visitor_hash(b"key", day.next_day().unwrap(), "alice")
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::{config::Config, state::AppState};
+
+// what other sites may send when the config leaves it open
+const DEFAULT_METHODS: &str = "GET, POST, PUT, PATCH, DELETE";
+const DEFAULT_HEADERS: &str = "content-type";
+// how long browsers may keep a preflight answer, in seconds
+const MAX_AGE: &str = "600";
+
+// only the json api, the pages stay same-site only
+fn applies(path: &str) -> bool {
+    path.starts_with("/api/")
+}
+
+// === Policy ===
+// The `Access-Control-Allow-Origin` for `origin`, `None` when it is not trusted. Listed origins
+// are echoed and may send the visitor's cookies, `*` lets any site in without them.
+fn allowed_origin(config: &Config, origin: &str) -> Option<HeaderValue> {
+    let origins = &config.cors_allowed_origins;
+    if origins.iter().any(|allowed| allowed == origin) {
+        HeaderValue::from_str(origin).ok()
+    } else if origins.iter().any(|allowed| allowed == "*") {
+        Some(HeaderValue::from_static("*"))
+    } else {
+        None
+    }
+}
+
+// Whether a request to `path` from `origin` comes from a listed origin, one allowed the
+// visitor's cookies. `*` does not count, see `csrf::protect`.
+pub fn is_trusted(config: &Config, path: &str, origin: &str) -> bool {
+    applies(path)
+        && config
+            .cors_allowed_origins
+            .iter()
+            .any(|allowed| allowed == origin)
+}
+
+fn joined(items: &[String], default: &'static str) -> HeaderValue {
+    if items.is_empty() {
+        return HeaderValue::from_static(default);
+    }
+    HeaderValue::from_str(&items.join(", ")).unwrap_or(HeaderValue::from_static(default))
+}
+
+fn allow(headers: &mut HeaderMap, origin: HeaderValue) {
+    if origin != "*" {
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+            HeaderValue::from_static("true"),
+        );
+    }
+    headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+    headers.append(header::VARY, HeaderValue::from_static("Origin"));
+}
+
+// === Middleware ===
+// Let the trusted origins of the live config call the api from their pages, a reload changes
+// them for the next request. Preflights are answered here, before sign-in could turn them away.
+pub async fn layer(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    if !applies(request.uri().path()) {
+        return next.run(request).await;
+    }
+    let config = state.live.load();
+    let origin = request
+        .headers()
+        .get(header::ORIGIN)
+        .and_then(|origin| origin.to_str().ok())
+        .and_then(|origin| allowed_origin(&config, origin));
+    let Some(origin) = origin else {
+        return next.run(request).await;
+    };
+    let is_preflight = request.method() == Method::OPTIONS
+        && request
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+    if is_preflight {
+        let mut response = StatusCode::NO_CONTENT.into_response();
+        let headers = response.headers_mut();
+        allow(headers, origin);
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_METHODS,
+            joined(&config.cors_allowed_methods, DEFAULT_METHODS),
+        );
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_HEADERS,
+            joined(&config.cors_allowed_headers, DEFAULT_HEADERS),
+        );
+        headers.insert(
+            header::ACCESS_CONTROL_MAX_AGE,
+            HeaderValue::from_static(MAX_AGE),
+        );
+        return response;
+    }
+    let mut response = next.run(request).await;
+    allow(response.headers_mut(), origin);
+    response
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allowed_origin() {
+        let mut config = Config::default();
+        assert!(allowed_origin(&config, "https://app.example.com").is_none());
+
+        config.cors_allowed_origins = vec!["https://app.example.com".into()];
+        assert_eq!(
+            allowed_origin(&config, "https://app.example.com").unwrap(),
+            "https://app.example.com"
+        );
+        assert!(allowed_origin(&config, "https://evil.com").is_none());
+
+        config.cors_allowed_origins.push("*".into());
+        assert_eq!(allowed_origin(&config, "https://evil.com").unwrap(), "*");
+        // listed origins keep their cookies
+        let mut headers = HeaderMap::new();
+        allow(
+            &mut headers,
+            allowed_origin(&config, "https://app.example.com").unwrap(),
+        );
+        assert!(headers.contains_key(header::ACCESS_CONTROL_ALLOW_CREDENTIALS));
+        let mut headers = HeaderMap::new();
+        allow(&mut headers, HeaderValue::from_static("*"));
+        assert!(!headers.contains_key(header::ACCESS_CONTROL_ALLOW_CREDENTIALS));
+    }
+
+    #[test]
+    fn test_is_trusted() {
+        let mut config = Config::default();
+        config.cors_allowed_origins = vec!["https://app.example.com".into(), "*".into()];
+        assert!(is_trusted(
+            &config,
+            "/api/v1/todos",
+            "https://app.example.com"
+        ));
+        assert!(!is_trusted(&config, "/todos", "https://app.example.com"));
+        assert!(!is_trusted(&config, "/api/v1/todos", "https://evil.com"));
+    }
+
+    #[test]
+    fn test_joined() {
+        assert_eq!(joined(&[], DEFAULT_METHODS), DEFAULT_METHODS);
+        let methods = ["GET".to_string(), "POST".to_string()];
+        assert_eq!(joined(&methods, DEFAULT_METHODS), "GET, POST");
+        assert!(applies("/api/v1/todos") && !applies("/todos"));
+    }
+}
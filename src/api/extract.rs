@@ -0,0 +1,243 @@
+use axum::{
+    async_trait,
+    extract::{
+        path::ErrorKind,
+        rejection::{JsonRejection, PathRejection, QueryRejection},
+        FromRequest, FromRequestParts, Path, Query, Request,
+    },
+    http::{request::Parts, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+// === Errors ===
+// The body of every rejected API request, so clients get JSON they can act on instead of
+// axum's plain text:
+// `{"code": "invalid_body", "message": "...", "fields": [{"field": "title", "message": "..."}]}`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ApiError {
+    #[serde(skip)]
+    pub status: StatusCode,
+    pub code: &'static str,
+    pub message: String,
+    pub fields: Vec<FieldError>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            message: message.into(),
+            fields: Vec::new(),
+        }
+    }
+    fn with_fields(mut self, fields: Vec<FieldError>) -> Self {
+        self.fields = fields;
+        self
+    }
+}
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(self)).into_response()
+    }
+}
+
+// The failing field of a serde error, `title: invalid type: ...` or `missing field `title``
+// with the path in front when nested. Messages without one give no field.
+fn field_errors(detail: &str) -> Vec<FieldError> {
+    let (path, message) = match detail.split_once(": ") {
+        Some((path, message)) if !path.contains(char::is_whitespace) => (Some(path), message),
+        _ => (None, detail),
+    };
+    // serde appends the position in the document, it means little per field
+    let message = message
+        .rsplit_once(" at line ")
+        .map_or(message, |(message, _)| message);
+    let missing = message
+        .strip_prefix("missing field `")
+        .and_then(|rest| rest.split_once('`'))
+        .map(|(field, _)| field);
+    let (field, message) = match (path, missing) {
+        (Some(path), Some(field)) => (format!("{}.{}", path, field), "is required".to_string()),
+        (None, Some(field)) => (field.to_string(), "is required".to_string()),
+        (Some(path), None) => (path.to_string(), message.to_string()),
+        (None, None) => return Vec::new(),
+    };
+    vec![FieldError { field, message }]
+}
+
+// the part after axum's `Failed to deserialize ...: ` prefix
+fn detail(body_text: &str) -> &str {
+    body_text
+        .split_once(": ")
+        .map_or(body_text, |(_, detail)| detail)
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        let (status, text) = (rejection.status(), rejection.body_text());
+        match rejection {
+            JsonRejection::JsonDataError(_) => ApiError::new(status, "invalid_body", detail(&text))
+                .with_fields(field_errors(detail(&text))),
+            JsonRejection::JsonSyntaxError(_) => {
+                ApiError::new(status, "malformed_json", detail(&text))
+            }
+            JsonRejection::MissingJsonContentType(_) => ApiError::new(
+                status,
+                "unsupported_media_type",
+                "Send the body as `Content-Type: application/json`.",
+            ),
+            _ => ApiError::new(status, "unreadable_body", text),
+        }
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        let text = rejection.body_text();
+        ApiError::new(rejection.status(), "invalid_query", detail(&text))
+            .with_fields(field_errors(detail(&text)))
+    }
+}
+
+impl From<PathRejection> for ApiError {
+    fn from(rejection: PathRejection) -> Self {
+        let text = rejection.body_text();
+        let fields = match &rejection {
+            PathRejection::FailedToDeserializePathParams(err) => match err.kind() {
+                ErrorKind::ParseErrorAtKey {
+                    key,
+                    value,
+                    expected_type,
+                } => vec![FieldError {
+                    field: key.clone(),
+                    message: format!("`{}` is not a valid {}", value, expected_type),
+                }],
+                _ => Vec::new(),
+            },
+            _ => Vec::new(),
+        };
+        ApiError::new(rejection.status(), "invalid_path", detail(&text)).with_fields(fields)
+    }
+}
+
+// === Extractors ===
+// `Json`, `Query` and `Path` for the API routes, rejecting with an `ApiError`.
+pub struct ApiJson<T>(pub T);
+pub struct ApiQuery<T>(pub T);
+pub struct ApiPath<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for ApiJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(request, state).await?;
+        Ok(Self(value))
+    }
+}
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for ApiQuery<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(value) = Query::<T>::from_request_parts(parts, state).await?;
+        Ok(Self(value))
+    }
+}
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for ApiPath<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Path(value) = Path::<T>::from_request_parts(parts, state).await?;
+        Ok(Self(value))
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::header};
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Fields {
+        title: String,
+        done: Option<bool>,
+    }
+
+    async fn reject(content_type: &str, body: &'static str) -> ApiError {
+        let request = Request::post("/api/v1/todos/1")
+            .header(header::CONTENT_TYPE, content_type)
+            .body(Body::from(body))
+            .unwrap();
+        match ApiJson::<Fields>::from_request(request, &()).await {
+            Ok(_) => panic!("{} was accepted", body),
+            Err(err) => err,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_json_rejections() {
+        let err = reject("application/json", r#"{"title": "Ship", "done": "yes"}"#).await;
+        assert_eq!(err.status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(err.code, "invalid_body");
+        assert_eq!(err.fields[0].field, "done");
+
+        let err = reject("application/json", r#"{"done": true}"#).await;
+        assert_eq!(
+            err.fields,
+            [FieldError {
+                field: "title".into(),
+                message: "is required".into()
+            }]
+        );
+
+        let err = reject("application/json", "{").await;
+        assert_eq!(
+            (err.status, err.code),
+            (StatusCode::BAD_REQUEST, "malformed_json")
+        );
+        let err = reject("text/plain", "{}").await;
+        assert_eq!(err.code, "unsupported_media_type");
+    }
+
+    #[test]
+    fn test_field_errors() {
+        assert_eq!(
+            field_errors("items[0]: missing field `text` at line 1 column 9")[0].field,
+            "items[0].text"
+        );
+        assert_eq!(
+            field_errors("page_size: invalid digit found in string")[0].message,
+            "invalid digit found in string"
+        );
+        assert!(field_errors("expected value at line 1 column 1").is_empty());
+    }
+}
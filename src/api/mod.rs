@@ -0,0 +1,156 @@
+pub mod cors;
+pub mod extract;
+pub mod rate_limit;
+pub mod typescript;
+pub mod v1;
+
+use axum::{
+    http::{HeaderName, HeaderValue},
+    Router,
+};
+use time::Date;
+use tower_http::set_header::SetResponseHeaderLayer;
+
+use crate::{
+    config::{ApiDeprecation, Config},
+    state::AppState,
+};
+
+// === Versions ===
+// Every version of the JSON API is served side by side under `/api/{version}`, each from its own
+// module. A breaking change goes into a new version, clients keep the one they were written for
+// until it is sunset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiVersion {
+    V1,
+}
+impl ApiVersion {
+    pub const ALL: [ApiVersion; 1] = [ApiVersion::V1];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ApiVersion::V1 => "v1",
+        }
+    }
+    fn prefix(self) -> String {
+        format!("/api/{}", self.as_str())
+    }
+    fn reads(self) -> Router<AppState> {
+        match self {
+            ApiVersion::V1 => v1::reads(),
+        }
+    }
+    fn writes(self) -> Router<AppState> {
+        match self {
+            ApiVersion::V1 => v1::writes(),
+        }
+    }
+}
+
+// the read-only routes of every version
+pub fn reads(config: &Config) -> Router<AppState> {
+    // checked here only, `writes` is built from the same config
+    for deprecation in &config.api_deprecations {
+        if !ApiVersion::ALL
+            .iter()
+            .any(|version| version.as_str() == deprecation.version)
+        {
+            tracing::warn!(version = %deprecation.version, "deprecation of an unknown api version");
+        }
+    }
+    versioned(config, ApiVersion::reads)
+}
+// the mutating routes of every version
+pub fn writes(config: &Config) -> Router<AppState> {
+    versioned(config, ApiVersion::writes)
+}
+
+fn versioned(config: &Config, routes: fn(ApiVersion) -> Router<AppState>) -> Router<AppState> {
+    ApiVersion::ALL
+        .into_iter()
+        .fold(Router::new(), |router, version| {
+            let deprecation = config
+                .api_deprecations
+                .iter()
+                .find(|deprecation| deprecation.version == version.as_str());
+            router.nest(&version.prefix(), with_policy(routes(version), deprecation))
+        })
+}
+
+// === Deprecation ===
+// Responses of a deprecated version carry `Deprecation` (RFC 9745) and, once a date is set,
+// `Sunset` (RFC 8594), so clients notice before the version is gone.
+fn with_policy(router: Router<AppState>, deprecation: Option<&ApiDeprecation>) -> Router<AppState> {
+    let Some(deprecation) = deprecation else {
+        return router;
+    };
+    let (deprecated, sunset) = deprecation_headers(deprecation);
+    let router = router.layer(SetResponseHeaderLayer::overriding(
+        HeaderName::from_static("deprecation"),
+        deprecated,
+    ));
+    match sunset {
+        Some(sunset) => router.layer(SetResponseHeaderLayer::overriding(
+            HeaderName::from_static("sunset"),
+            sunset,
+        )),
+        None => router,
+    }
+}
+
+fn deprecation_headers(deprecation: &ApiDeprecation) -> (HeaderValue, Option<HeaderValue>) {
+    let deprecated = HeaderValue::from_str(&format!(
+        "@{}",
+        deprecation
+            .deprecated
+            .midnight()
+            .assume_utc()
+            .unix_timestamp()
+    ))
+    .expect("a unix timestamp is a valid header value");
+    let sunset = deprecation.sunset.map(|sunset| {
+        HeaderValue::from_str(&http_date(sunset)).expect("an http date is a valid header value")
+    });
+    (deprecated, sunset)
+}
+
+// `Tue, 01 Jul 2025 00:00:00 GMT`
+fn http_date(date: Date) -> String {
+    let weekday = date.weekday().to_string();
+    let month = date.month().to_string();
+    format!(
+        "{}, {:02} {} {} 00:00:00 GMT",
+        &weekday[..3],
+        date.day(),
+        &month[..3],
+        date.year(),
+    )
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use time::macros::date;
+
+    use super::*;
+
+    #[test]
+    fn test_deprecation_headers() {
+        let deprecation: ApiDeprecation = "v1=2025-01-01/2025-07-01".parse().unwrap();
+        let (deprecated, sunset) = deprecation_headers(&deprecation);
+        assert_eq!(deprecated, "@1735689600");
+        assert_eq!(sunset.unwrap(), "Tue, 01 Jul 2025 00:00:00 GMT");
+
+        let deprecation: ApiDeprecation = "v1=2025-01-01".parse().unwrap();
+        assert_eq!(deprecation.sunset, None);
+        assert!("v1".parse::<ApiDeprecation>().is_err());
+    }
+
+    #[test]
+    fn test_http_date() {
+        assert_eq!(
+            http_date(date!(2024 - 02 - 29)),
+            "Thu, 29 Feb 2024 00:00:00 GMT"
+        );
+    }
+}
@@ -0,0 +1,203 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use maud::{html, Markup};
+use serde::{Deserialize, Serialize};
+
+use crate::{auth::user::CurrentUser, db::driver::Db, error::AppError, state::AppState};
+
+// the requests of a user in the current window, `api_quota:{user}` in the default tree, gone
+// once the window is over
+const QUOTA_PREFIX: &str = "api_quota:";
+
+pub const LIMIT_HEADER: HeaderName = HeaderName::from_static("x-ratelimit-limit");
+pub const REMAINING_HEADER: HeaderName = HeaderName::from_static("x-ratelimit-remaining");
+// unix seconds the window ends at
+pub const RESET_HEADER: HeaderName = HeaderName::from_static("x-ratelimit-reset");
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Window {
+    // unix seconds
+    started_at: u64,
+    used: u32,
+}
+
+// Where a user stands in the current window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quota {
+    pub limit: u32,
+    pub remaining: u32,
+    // unix seconds
+    pub reset_at: u64,
+    // this request went over the limit and was refused
+    pub exceeded: bool,
+}
+impl Quota {
+    fn headers(&self, headers: &mut HeaderMap) {
+        headers.insert(LIMIT_HEADER, HeaderValue::from(self.limit));
+        headers.insert(REMAINING_HEADER, HeaderValue::from(self.remaining));
+        headers.insert(RESET_HEADER, HeaderValue::from(self.reset_at));
+    }
+}
+
+// every account counts on its own, without accounts the owner does
+fn subject(user: &CurrentUser) -> String {
+    match user {
+        CurrentUser::Owner => "owner".into(),
+        CurrentUser::Account(account) => format!("account:{}", account),
+    }
+}
+
+fn key(user: &CurrentUser) -> String {
+    format!("{}{}", QUOTA_PREFIX, subject(user))
+}
+
+// the window `user` is in now, a fresh one once the last ran out
+fn current(db: &Db, user: &CurrentUser, window: Duration, now: u64) -> Result<Window> {
+    Ok(db
+        .get::<Window, _>(key(user))?
+        .filter(|current| now < current.started_at + window.as_secs())
+        .unwrap_or(Window {
+            started_at: now,
+            used: 0,
+        }))
+}
+
+// === Quotas ===
+// Count a request of `user` against `limit` requests per `window`. Refused requests are not
+// counted, the counter is stored so it holds across restarts.
+pub fn consume(db: &Db, user: &CurrentUser, limit: u32, window: Duration) -> Result<Quota> {
+    let now = db.clock().now_millis() / 1000;
+    let mut current = current(db, user, window, now)?;
+    let reset_at = current.started_at + window.as_secs();
+    let exceeded = current.used >= limit;
+    if !exceeded {
+        current.used += 1;
+        db.insert_with_ttl(
+            key(user),
+            &current,
+            Duration::from_secs(reset_at.saturating_sub(now)),
+        )?;
+    }
+    Ok(Quota {
+        limit,
+        remaining: limit.saturating_sub(current.used),
+        reset_at,
+        exceeded,
+    })
+}
+
+// where `user` stands without counting a request, for the settings page
+pub fn peek(db: &Db, user: &CurrentUser, limit: u32, window: Duration) -> Result<Quota> {
+    let now = db.clock().now_millis() / 1000;
+    let current = current(db, user, window, now)?;
+    Ok(Quota {
+        limit,
+        remaining: limit.saturating_sub(current.used),
+        reset_at: current.started_at + window.as_secs(),
+        exceeded: false,
+    })
+}
+
+// === Middleware ===
+// Hold api requests to `API_RATE_LIMIT` per user, every response says how many are left.
+// Inside `user::require`, which tells whose request it is.
+pub async fn layer(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let Some(limit) = state.config.api_rate_limit else {
+        return next.run(request).await;
+    };
+    if !request.uri().path().starts_with("/api/") {
+        return next.run(request).await;
+    }
+    let Some(user) = request.extensions().get::<CurrentUser>().cloned() else {
+        return next.run(request).await;
+    };
+    let quota = {
+        let db = state.read().await;
+        consume(&db, &user, limit, state.config.api_rate_window)
+    };
+    let quota = match quota {
+        Ok(quota) => quota,
+        Err(err) => return AppError::Internal(err).into_response(),
+    };
+    let mut response = if quota.exceeded {
+        let now = state.clock.now_millis() / 1000;
+        tracing::warn!(user = %subject(&user), "api rate limit reached");
+        AppError::TooManyRequests {
+            retry_after: quota.reset_at.saturating_sub(now).max(1),
+        }
+        .into_response()
+    } else {
+        next.run(request).await
+    };
+    quota.headers(response.headers_mut());
+    response
+}
+
+// === Components ===
+pub fn settings_html(quota: &Quota, now: u64) -> Markup {
+    let minutes = quota.reset_at.saturating_sub(now).div_ceil(60);
+    html! {
+        section class="bg-white rounded-lg shadow-lg p-6 space-y-2" {
+            h2 class="text-2xl text-gray-700" { "API usage" }
+            p {
+                (quota.limit - quota.remaining) " of " (quota.limit) " requests used, "
+                "the count starts over in " (minutes) (if minutes == 1 { " minute." } else { " minutes." })
+            }
+            p class="t-muted text-sm" {
+                "Responses carry X-RateLimit-Limit, X-RateLimit-Remaining and X-RateLimit-Reset."
+            }
+        }
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::TestDb;
+
+    #[test]
+    fn test_consume() -> Result<()> {
+        let db = TestDb::new("rate_limit")?;
+        let alice = CurrentUser::Account("alice".into());
+        let window = Duration::from_secs(60);
+        let first = consume(&db, &alice, 2, window)?;
+        assert_eq!((first.remaining, first.exceeded), (1, false));
+        consume(&db, &alice, 2, window)?;
+        let refused = consume(&db, &alice, 2, window)?;
+        assert_eq!((refused.remaining, refused.exceeded), (0, true));
+        assert_eq!(refused.reset_at, first.reset_at);
+        // others count on their own
+        assert!(!consume(&db, &CurrentUser::Owner, 2, window)?.exceeded);
+        assert_eq!(peek(&db, &alice, 2, window)?.remaining, 0);
+
+        // a new window starts once this one is over
+        db.clock.advance(window);
+        let fresh = consume(&db, &alice, 2, window)?;
+        assert_eq!((fresh.remaining, fresh.exceeded), (1, false));
+        assert_eq!(fresh.reset_at, first.reset_at + 60);
+        Ok(())
+    }
+
+    #[test]
+    fn test_headers() {
+        let quota = Quota {
+            limit: 100,
+            remaining: 42,
+            reset_at: 1_700_000_000,
+            exceeded: false,
+        };
+        let mut headers = HeaderMap::new();
+        quota.headers(&mut headers);
+        assert_eq!(headers[&LIMIT_HEADER], "100");
+        assert_eq!(headers[&REMAINING_HEADER], "42");
+        assert_eq!(headers[&RESET_HEADER], "1700000000");
+    }
+}
@@ -0,0 +1,265 @@
+use std::fmt::Write;
+
+use crate::{
+    models::{Priority, Status},
+    routes,
+};
+
+// === TypeScript ===
+// The v1 resources and a small fetch client as one TypeScript module, for a SPA or mobile
+// companion:
+//
+//     rust-htmx gen-ts [--out api.ts]
+//
+// The declarations are written next to the structs they mirror rather than derived, the tests
+// compare them with what serde produces so neither side drifts.
+
+// (field, type), `?` on the name for fields that may be left out
+type Fields = &'static [(&'static str, &'static str)];
+
+const TODO: Fields = &[
+    ("id", "number"),
+    ("title", "string"),
+    ("completed", "boolean"),
+    ("status", "Status"),
+    ("due", "string | null"),
+    ("scheduled_for", "string | null"),
+    ("archived", "boolean"),
+    ("estimate_minutes", "number | null"),
+    ("color", "string | null"),
+    ("version", "number"),
+    ("tags", "string[]"),
+    ("priority", "Priority"),
+    ("list", "string | null"),
+    ("resolution", "\"done\" | \"cancelled\" | null"),
+    ("cancel_reason", "string | null"),
+    ("fields", "Record<string, string>"),
+];
+const TODO_FIELDS: Fields = &[
+    ("title?", "string"),
+    ("completed?", "boolean"),
+    ("status?", "Status"),
+    ("due?", "string"),
+    ("scheduled_for?", "string"),
+    ("archived?", "boolean"),
+];
+const LIST_PARAMS: Fields = &[
+    ("page_size?", "number"),
+    ("after?", "string"),
+    ("before?", "string"),
+    ("sort?", "string"),
+    ("completed?", "boolean"),
+];
+const OPERATION_RESULT: Fields = &[("id?", "number"), ("todo?", "Todo"), ("error?", "string")];
+const BATCH_RESPONSE: Fields = &[
+    ("applied", "boolean"),
+    ("results", "OperationResult[]"),
+    ("ids", "Record<string, number>"),
+];
+const API_ERROR: Fields = &[
+    ("code", "string"),
+    ("message", "string"),
+    ("fields", "FieldError[]"),
+];
+const FIELD_ERROR: Fields = &[("field", "string"), ("message", "string")];
+
+const INTERFACES: &[(&str, Fields)] = &[
+    ("Todo", TODO),
+    ("TodoFields", TODO_FIELDS),
+    ("ListParams", LIST_PARAMS),
+    ("OperationResult", OPERATION_RESULT),
+    ("BatchResponse", BATCH_RESPONSE),
+    ("FieldError", FIELD_ERROR),
+    ("ApiError", API_ERROR),
+];
+
+const CLIENT: &str = r#"
+export class ApiRequestError extends Error {
+  constructor(public status: number, public error: ApiError | null) {
+    super(error?.message ?? `request failed with ${status}`);
+  }
+}
+
+// `base` is the origin of the server, empty on the same origin
+export class ApiClient {
+  constructor(private base = "", private init: RequestInit = {}) {}
+
+  private async request<T>(method: string, path: string, body?: unknown, ifMatch?: string): Promise<T> {
+    const headers: Record<string, string> = { Accept: "application/json" };
+    if (body !== undefined) headers["Content-Type"] = "application/json";
+    if (ifMatch !== undefined) headers["If-Match"] = ifMatch;
+    const response = await fetch(this.base + path, {
+      ...this.init,
+      method,
+      headers: { ...headers, ...(this.init.headers as Record<string, string> | undefined) },
+      body: body === undefined ? undefined : JSON.stringify(body),
+    });
+    if (!response.ok) {
+      const error = await response.json().catch(() => null);
+      // a batch that was not applied still answers with its results
+      if (response.status === 422 && error && "applied" in error) return error;
+      throw new ApiRequestError(response.status, error);
+    }
+    if (response.status === 204) return undefined as T;
+    return response.json();
+  }
+"#;
+
+fn interface(out: &mut String, name: &str, fields: Fields) {
+    let _ = writeln!(out, "export interface {} {{", name);
+    for (field, ty) in fields {
+        let _ = writeln!(out, "  {}: {};", field, ty);
+    }
+    let _ = writeln!(out, "}}\n");
+}
+
+pub fn generate() -> String {
+    let mut out = String::from("// Generated by `rust-htmx gen-ts`, do not edit.\n\n");
+    let statuses: Vec<String> = Status::ALL
+        .iter()
+        .map(|status| format!("\"{}\"", status))
+        .collect();
+    let _ = writeln!(out, "export type Status = {};\n", statuses.join(" | "));
+    let priorities: Vec<String> = Priority::ALL
+        .iter()
+        .map(|priority| format!("\"{}\"", priority))
+        .collect();
+    let _ = writeln!(out, "export type Priority = {};\n", priorities.join(" | "));
+    for (name, fields) in INTERFACES {
+        interface(&mut out, name, fields);
+    }
+    out.push_str("// an id, or the temporary id an earlier create in the same batch gave it\n");
+    out.push_str("export type TodoRef = number | string;\n\n");
+    out.push_str(
+        "export type Operation =\n  \
+         | ({ op: \"create\"; temp_id?: string } & TodoFields)\n  \
+         | ({ op: \"update\"; id: TodoRef } & TodoFields)\n  \
+         | { op: \"delete\"; id: TodoRef };\n",
+    );
+    out.push_str(CLIENT);
+
+    let todos = routes::ApiTodos::url();
+    let todo = format!("`{}/${{id}}`", todos);
+    let methods = [
+        format!(
+            "listTodos(params: ListParams = {{}}): Promise<Todo[]> {{\n    \
+             const query = new URLSearchParams(Object.entries(params).map(([k, v]) => [k, String(v)]));\n    \
+             return this.request(\"GET\", `{}?${{query}}`);\n  }}",
+            todos
+        ),
+        format!(
+            "getTodo(id: number): Promise<Todo> {{\n    return this.request(\"GET\", {});\n  }}",
+            todo
+        ),
+        format!(
+            "putTodo(id: number, fields: TodoFields, ifMatch?: string): Promise<Todo> {{\n    \
+             return this.request(\"PUT\", {}, fields, ifMatch);\n  }}",
+            todo
+        ),
+        format!(
+            "patchTodo(id: number, fields: TodoFields, ifMatch?: string): Promise<Todo> {{\n    \
+             return this.request(\"PATCH\", {}, fields, ifMatch);\n  }}",
+            todo
+        ),
+        format!(
+            "deleteTodo(id: number, ifMatch?: string): Promise<void> {{\n    \
+             return this.request(\"DELETE\", {}, undefined, ifMatch);\n  }}",
+            todo
+        ),
+        format!(
+            "// a failed batch resolves with `applied: false` and the reason per operation\n  \
+             batch(operations: Operation[]): Promise<BatchResponse> {{\n    \
+             return this.request(\"POST\", \"{}\", operations);\n  }}",
+            routes::ApiBatch::url()
+        ),
+    ];
+    for method in methods {
+        let _ = writeln!(out, "\n  {}", method);
+    }
+    out.push_str("}\n");
+    out
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+    use crate::{
+        api::{
+            extract::{ApiError, FieldError},
+            v1::{BatchResponse, OperationResult, TodoResource},
+        },
+        models::Todo,
+    };
+
+    fn names(fields: Fields) -> Vec<&'static str> {
+        fields
+            .iter()
+            .map(|(name, _)| name.trim_end_matches('?'))
+            .collect()
+    }
+
+    fn keys(value: impl serde::Serialize) -> Vec<String> {
+        match serde_json::to_value(value).unwrap() {
+            Value::Object(map) => {
+                let mut keys: Vec<String> = map.keys().cloned().collect();
+                keys.sort_unstable();
+                keys
+            }
+            other => panic!("{} is not an object", other),
+        }
+    }
+
+    fn assert_same(fields: Fields, value: impl serde::Serialize) {
+        let mut declared = names(fields);
+        declared.sort_unstable();
+        assert_eq!(declared, keys(value));
+    }
+
+    #[test]
+    fn test_declarations_match_serde() {
+        let todo = TodoResource::from(&Todo::new(1, "Ship".into()));
+        assert_same(TODO, &todo);
+        assert_same(
+            OPERATION_RESULT,
+            OperationResult {
+                id: Some(1),
+                todo: Some(todo),
+                error: Some("nope".into()),
+            },
+        );
+        assert_same(
+            BATCH_RESPONSE,
+            BatchResponse {
+                applied: true,
+                results: Vec::new(),
+                ids: Default::default(),
+            },
+        );
+        assert_same(
+            FIELD_ERROR,
+            FieldError {
+                field: "title".into(),
+                message: "is required".into(),
+            },
+        );
+        assert_same(
+            API_ERROR,
+            ApiError::new(axum::http::StatusCode::BAD_REQUEST, "invalid_query", "nope"),
+        );
+    }
+
+    #[test]
+    fn test_generate() {
+        let ts = generate();
+        assert!(ts.contains(
+            "export type Status = \"backlog\" | \"in_progress\" | \"done\" | \"someday\";"
+        ));
+        assert!(ts.contains("export type Priority = \"low\" | \"normal\" | \"high\";"));
+        assert!(ts.contains("  estimate_minutes: number | null;"));
+        assert!(ts.contains("`/api/v1/todos/${id}`"));
+        assert!(ts.contains("\"/api/v1/batch\""));
+    }
+}
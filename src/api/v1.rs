@@ -0,0 +1,624 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::Duration,
+};
+
+use anyhow::Result;
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post, put},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::extract::{ApiJson, ApiPath, ApiQuery},
+    clock::Clock,
+    db::{driver::Db, queue::WriteOp},
+    error::AppError,
+    models::{self, Priority, Status, Todo},
+    repository::{
+        entity::Repository,
+        query::{Page, Sort, SortKey, TodoQuery},
+        todo::{remove_ops, todo_key},
+    },
+    routes,
+    services::todo::TodoService,
+    state::AppState,
+    tenant::Tenant,
+};
+
+// The first version of the JSON API, served under `/api/v1`.
+
+// operations a single batch may hold
+const MAX_OPERATIONS: usize = 500;
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 200;
+// how many todos match the listing, over all its pages
+const TOTAL_COUNT: &str = "x-total-count";
+// applied batches by the key the client sent, `idempotency:{key}`
+const IDEMPOTENCY_KEY: &str = "idempotency-key";
+const IDEMPOTENCY_PREFIX: &str = "idempotency:";
+const IDEMPOTENCY_LIFETIME: Duration = Duration::from_secs(24 * 60 * 60);
+const MAX_IDEMPOTENCY_KEY_LEN: usize = 255;
+
+// the read-only routes, relative to `/api/v1`
+pub fn reads() -> Router<AppState> {
+    Router::new()
+        .route(routes::ApiTodos::PATH, get(list_todos))
+        .route(routes::ApiTodo::PATH, get(get_todo))
+}
+// the mutating routes, relative to `/api/v1`
+pub fn writes() -> Router<AppState> {
+    Router::new()
+        .route(routes::ApiBatch::PATH, post(batch))
+        .route(
+            routes::ApiTodo::PATH,
+            put(put_todo).patch(patch_todo).delete(delete_todo),
+        )
+}
+
+// === Resources ===
+// A todo as API clients see it, dates as `yyyy-mm-dd`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TodoResource {
+    pub id: u64,
+    pub title: String,
+    pub completed: bool,
+    pub status: Status,
+    pub due: Option<String>,
+    pub scheduled_for: Option<String>,
+    pub archived: bool,
+    pub estimate_minutes: Option<u32>,
+    pub color: Option<String>,
+    pub version: u64,
+    pub tags: Vec<String>,
+    pub priority: Priority,
+    pub list: Option<String>,
+    // `done` or `cancelled` once the todo is completed
+    pub resolution: Option<String>,
+    pub cancel_reason: Option<String>,
+    // the custom fields of its list, see `fields`
+    #[serde(default)]
+    pub fields: BTreeMap<String, String>,
+}
+impl From<&Todo> for TodoResource {
+    fn from(todo: &Todo) -> Self {
+        Self {
+            id: todo.id,
+            title: todo.title.clone(),
+            completed: todo.completed,
+            status: todo.status,
+            due: todo.due.map(|due| due.to_string()),
+            scheduled_for: todo.scheduled_for.map(|day| day.to_string()),
+            archived: todo.archived,
+            estimate_minutes: todo.estimate_minutes,
+            color: todo.color.clone(),
+            version: todo.version,
+            tags: todo.tags.clone(),
+            priority: todo.priority,
+            list: todo.list.clone(),
+            resolution: todo
+                .resolution
+                .as_ref()
+                .map(|resolution| resolution.to_string()),
+            cancel_reason: todo
+                .resolution
+                .as_ref()
+                .and_then(|resolution| resolution.reason())
+                .map(str::to_string),
+            fields: todo.fields.clone(),
+        }
+    }
+}
+
+// === Conditional requests ===
+// a strong etag changing with every stored version of the todo
+pub fn etag(todo: &Todo) -> String {
+    format!("\"{}-{}\"", todo.id, todo.version)
+}
+
+// Check `If-Match` against the todo as stored. Without the header the write goes through, so
+// clients opt in to optimistic concurrency.
+fn check_if_match(headers: &HeaderMap, todo: &Todo) -> Result<(), AppError> {
+    let Some(value) = headers.get(header::IF_MATCH) else {
+        return Ok(());
+    };
+    let current = etag(todo);
+    let matches = value
+        .to_str()
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        // weak etags never match, `If-Match` compares strongly
+        .any(|tag| tag == "*" || tag == current);
+    if matches {
+        Ok(())
+    } else {
+        Err(AppError::PreconditionFailed)
+    }
+}
+
+// the todo as json with its etag
+fn todo_response(todo: &Todo) -> Result<Response, AppError> {
+    let etag = HeaderValue::from_str(&etag(todo)).map_err(anyhow::Error::from)?;
+    Ok(([(header::ETAG, etag)], Json(TodoResource::from(todo))).into_response())
+}
+
+// Fields a client may set, missing ones are left as they are. An empty `due` or `scheduled_for`
+// clears it.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TodoFields {
+    pub title: Option<String>,
+    pub completed: Option<bool>,
+    pub status: Option<Status>,
+    pub due: Option<String>,
+    pub scheduled_for: Option<String>,
+    pub archived: Option<bool>,
+}
+impl TodoFields {
+    pub fn apply(&self, todo: &mut Todo, clock: &dyn Clock) -> Result<(), String> {
+        if let Some(title) = &self.title {
+            if title.trim().is_empty() {
+                return Err("a todo needs a title".to_string());
+            }
+            todo.title = title.trim().to_string();
+        }
+        if let Some(due) = &self.due {
+            todo.due = models::parse_due(due).map_err(|err| err.to_string())?;
+        }
+        if let Some(day) = &self.scheduled_for {
+            todo.scheduled_for = models::parse_due(day).map_err(|err| err.to_string())?;
+        }
+        if let Some(archived) = self.archived {
+            todo.archived = archived;
+        }
+        // the status is the more precise of the two when both are sent
+        if let Some(completed) = self.completed {
+            todo.set_completed(completed);
+        }
+        if let Some(status) = self.status {
+            todo.set_status(status);
+        }
+        todo.touch(clock);
+        Ok(())
+    }
+}
+
+// === Listing ===
+#[derive(Debug, Default, Deserialize)]
+pub struct ListParams {
+    page_size: Option<usize>,
+    // cursors from the `Link` header of a previous page
+    after: Option<String>,
+    before: Option<String>,
+    sort: Option<String>,
+    completed: Option<bool>,
+}
+
+// `Link` header pointing at the neighbouring pages
+fn link_header<T>(page: &Page<T>, params: &ListParams, sort: Sort) -> Option<String> {
+    let url = |cursor: (&str, &str)| {
+        let mut query = vec![format!("sort={}", sort.as_str())];
+        if let Some(page_size) = params.page_size {
+            query.push(format!("page_size={}", page_size));
+        }
+        if let Some(completed) = params.completed {
+            query.push(format!("completed={}", completed));
+        }
+        // title cursors carry the title
+        query.push(format!("{}={}", cursor.0, urlencoding::encode(cursor.1)));
+        format!("{}?{}", routes::ApiTodos::url(), query.join("&"))
+    };
+    let mut links = Vec::new();
+    if let Some(cursor) = &page.prev_cursor {
+        links.push(format!("<{}>; rel=\"prev\"", url(("before", cursor))));
+    }
+    if let Some(cursor) = &page.next_cursor {
+        links.push(format!("<{}>; rel=\"next\"", url(("after", cursor))));
+    }
+    (!links.is_empty()).then(|| links.join(", "))
+}
+
+// === Batch ===
+// A todo by its id, or by the temporary id an earlier create in the same batch gave it.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum TodoRef {
+    Id(u64),
+    Temp(String),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Operation {
+    Create {
+        temp_id: Option<String>,
+        #[serde(flatten)]
+        fields: TodoFields,
+    },
+    Update {
+        id: TodoRef,
+        #[serde(flatten)]
+        fields: TodoFields,
+    },
+    Delete {
+        id: TodoRef,
+    },
+}
+
+// what happened to one operation, `error` set when it failed
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct OperationResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub todo: Option<TodoResource>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// `applied` is false when any operation failed, nothing was written then
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchResponse {
+    pub applied: bool,
+    pub results: Vec<OperationResult>,
+    // temporary ids to the ids they were given
+    pub ids: BTreeMap<String, u64>,
+}
+
+// Run `operations` against a staged copy of the todos they touch, returning the results and
+// the writes that make them real.
+fn plan(db: &Db, operations: &[Operation]) -> Result<(BatchResponse, Vec<WriteOp>)> {
+    // `None` once deleted
+    let mut staged: BTreeMap<u64, Option<Todo>> = BTreeMap::new();
+    let mut ids: HashMap<String, u64> = HashMap::new();
+    let mut results = Vec::with_capacity(operations.len());
+    for operation in operations {
+        let result = match operation {
+            Operation::Create { temp_id, fields } => {
+                let mut todo = Todo::created(db.next_id()?, String::new(), &**db.clock());
+                let applied = match &fields.title {
+                    None => Err("a todo needs a title".to_string()),
+                    Some(_) => fields.apply(&mut todo, &**db.clock()),
+                };
+                match (applied, temp_id) {
+                    (Err(err), _) => Err(err),
+                    (Ok(()), Some(temp_id)) if ids.contains_key(temp_id) => {
+                        Err(format!("temporary id `{}` is used twice", temp_id))
+                    }
+                    (Ok(()), temp_id) => {
+                        if let Some(temp_id) = temp_id {
+                            ids.insert(temp_id.clone(), todo.id);
+                        }
+                        staged.insert(todo.id, Some(todo.clone()));
+                        Ok((todo.id, Some(todo)))
+                    }
+                }
+            }
+            Operation::Update { id, fields } => match resolve(db, &staged, &ids, id)? {
+                Ok(mut todo) => fields.apply(&mut todo, &**db.clock()).map(|()| {
+                    staged.insert(todo.id, Some(todo.clone()));
+                    (todo.id, Some(todo))
+                }),
+                Err(err) => Err(err),
+            },
+            Operation::Delete { id } => match resolve(db, &staged, &ids, id)? {
+                Ok(todo) => {
+                    staged.insert(todo.id, None);
+                    Ok((todo.id, None))
+                }
+                Err(err) => Err(err),
+            },
+        };
+        results.push(match result {
+            Ok((id, todo)) => OperationResult {
+                id: Some(id),
+                todo: todo.as_ref().map(TodoResource::from),
+                error: None,
+            },
+            Err(err) => OperationResult {
+                error: Some(err),
+                ..OperationResult::default()
+            },
+        });
+    }
+    let applied = results.iter().all(|result| result.error.is_none());
+    let mut ops = Vec::new();
+    for (id, todo) in &staged {
+        match todo {
+            Some(todo) => ops.extend(Repository::new(db).put_ops(todo)?),
+            None => ops.extend(remove_ops(db, *id)?),
+        }
+    }
+    let response = BatchResponse {
+        applied,
+        results,
+        ids: ids.into_iter().collect(),
+    };
+    Ok((response, ops))
+}
+
+// the current state of a referenced todo, including earlier operations of the batch
+fn resolve(
+    db: &Db,
+    staged: &BTreeMap<u64, Option<Todo>>,
+    ids: &HashMap<String, u64>,
+    reference: &TodoRef,
+) -> Result<Result<Todo, String>> {
+    let id = match reference {
+        TodoRef::Id(id) => *id,
+        TodoRef::Temp(temp_id) => match ids.get(temp_id) {
+            Some(id) => *id,
+            None => return Ok(Err(format!("no todo was created as `{}`", temp_id))),
+        },
+    };
+    let todo = match staged.get(&id) {
+        Some(todo) => todo.clone(),
+        None => db.get::<Todo, _>(todo_key(id))?,
+    };
+    Ok(todo.ok_or_else(|| format!("todo {} does not exist", id)))
+}
+
+// === Routes ===
+// A page of todos, with `Link` headers to the pages before and after it.
+pub async fn list_todos(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    ApiQuery(params): ApiQuery<ListParams>,
+) -> Result<Response, AppError> {
+    let sort = match &params.sort {
+        Some(sort) => sort.parse()?,
+        None => Sort::default(),
+    };
+    let page_size = params
+        .page_size
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    // archived and someday todos are listed too, clients see the flag and status
+    let mut query = TodoQuery::new()
+        .including_archived()
+        .including_someday()
+        .sort(sort)
+        .limit(page_size);
+    if let Some(completed) = params.completed {
+        query = query.completed(completed);
+    }
+    if let Some(after) = &params.after {
+        query = query.after_cursor(after)?;
+    }
+    if let Some(before) = &params.before {
+        query = query.before(SortKey::parse(before, sort)?);
+    }
+    let page = query
+        .run(&state.read().await.for_tenant(tenant.id())?)?
+        .map(|todo| TodoResource::from(&todo));
+    let link = link_header(&page, &params, sort);
+    let total = page.total_hint;
+    let mut response = Json(page.items).into_response();
+    if let Some(link) = link {
+        response.headers_mut().insert(
+            header::LINK,
+            HeaderValue::from_str(&link).map_err(anyhow::Error::from)?,
+        );
+    }
+    if let Some(total) = total {
+        response
+            .headers_mut()
+            .insert(TOTAL_COUNT, HeaderValue::from(total));
+    }
+    Ok(response)
+}
+
+pub async fn get_todo(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    ApiPath(id): ApiPath<u64>,
+) -> Result<Response, AppError> {
+    let todo = state
+        .read()
+        .await
+        .for_tenant(tenant.id())?
+        .get::<Todo, _>(todo_key(id))?
+        .ok_or(AppError::NotFound)?;
+    todo_response(&todo)
+}
+
+// Replace a todo, fields left out go back to their defaults.
+pub async fn put_todo(
+    State(mut app_state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    ApiPath(id): ApiPath<u64>,
+    ApiJson(fields): ApiJson<TodoFields>,
+) -> Result<Response, AppError> {
+    if fields.title.is_none() {
+        return Err(AppError::Invalid("a todo needs a title".to_string()));
+    }
+    let state = app_state.clone();
+    let guard = app_state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    let todos = TodoService::new(&state, &db, tenant.id());
+    check_if_match(&headers, &todos.get(id)?)?;
+    let todo = todos
+        .update(id, None, |todo| {
+            let mut replaced = Todo::created(id, String::new(), &**db.clock());
+            fields
+                .apply(&mut replaced, &**db.clock())
+                .map_err(AppError::Invalid)?;
+            *todo = replaced;
+            Ok(())
+        })
+        .await?;
+    todo_response(&todo)
+}
+
+// Change only the fields sent.
+pub async fn patch_todo(
+    State(mut app_state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    ApiPath(id): ApiPath<u64>,
+    ApiJson(fields): ApiJson<TodoFields>,
+) -> Result<Response, AppError> {
+    let state = app_state.clone();
+    let guard = app_state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    let todos = TodoService::new(&state, &db, tenant.id());
+    check_if_match(&headers, &todos.get(id)?)?;
+    let todo = todos
+        .update(id, None, |todo| {
+            fields.apply(todo, &**db.clock()).map_err(AppError::Invalid)
+        })
+        .await?;
+    todo_response(&todo)
+}
+
+pub async fn delete_todo(
+    State(mut app_state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    ApiPath(id): ApiPath<u64>,
+) -> Result<StatusCode, AppError> {
+    let state = app_state.clone();
+    let guard = app_state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    let todos = TodoService::new(&state, &db, tenant.id());
+    check_if_match(&headers, &todos.get(id)?)?;
+    todos.remove(id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Apply a list of operations all together or not at all, for clients syncing offline changes.
+// A batch sent with an `Idempotency-Key` is applied once, sending it again within a day gets
+// the response of the first time back instead of applying it twice.
+pub async fn batch(
+    State(mut app_state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    ApiJson(operations): ApiJson<Vec<Operation>>,
+) -> Result<Response, AppError> {
+    if operations.len() > MAX_OPERATIONS {
+        return Err(AppError::Invalid(format!(
+            "a batch holds at most {} operations",
+            MAX_OPERATIONS
+        )));
+    }
+    let idempotency_key = idempotency_key(&headers)?;
+    let writes = app_state.writes.clone();
+    let guard = app_state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    if let Some(key) = &idempotency_key {
+        if let Some(body) = db.get::<String, _>(key)? {
+            return Ok(([(header::CONTENT_TYPE, "application/json")], body).into_response());
+        }
+    }
+    let (response, ops) = plan(&db, &operations)?;
+    if !response.applied {
+        return Ok((StatusCode::UNPROCESSABLE_ENTITY, Json(response)).into_response());
+    }
+    writes.submit(&db, ops).await?;
+    if let Some(key) = idempotency_key {
+        // kept as json, the response skips unset fields and the codec can't read that back
+        let body = serde_json::to_string(&response)?;
+        db.insert_with_ttl(key, &body, IDEMPOTENCY_LIFETIME)?;
+    }
+    Ok(Json(response).into_response())
+}
+
+// the key an applied batch is kept under, `None` without an `Idempotency-Key` header
+fn idempotency_key(headers: &HeaderMap) -> Result<Option<String>, AppError> {
+    let Some(value) = headers.get(IDEMPOTENCY_KEY) else {
+        return Ok(None);
+    };
+    match value.to_str() {
+        Ok(key) if !key.is_empty() && key.len() <= MAX_IDEMPOTENCY_KEY_LEN => {
+            Ok(Some(format!("{}{}", IDEMPOTENCY_PREFIX, key)))
+        }
+        _ => Err(AppError::Invalid(format!(
+            "Idempotency-Key takes 1 to {} visible characters",
+            MAX_IDEMPOTENCY_KEY_LEN
+        ))),
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clock::SystemClock, fixtures::TestDb};
+
+    fn operations(json: &str) -> Vec<Operation> {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_if_match() {
+        let mut todo = Todo::new(3, "Milk".into());
+        let mut headers = HeaderMap::new();
+        assert!(check_if_match(&headers, &todo).is_ok());
+
+        headers.insert(header::IF_MATCH, etag(&todo).parse().unwrap());
+        assert!(check_if_match(&headers, &todo).is_ok());
+        todo.touch(&SystemClock);
+        assert!(check_if_match(&headers, &todo).is_err());
+
+        headers.insert(header::IF_MATCH, "*".parse().unwrap());
+        assert!(check_if_match(&headers, &todo).is_ok());
+    }
+
+    #[test]
+    fn test_idempotency_key() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(idempotency_key(&headers).unwrap(), None);
+        headers.insert(IDEMPOTENCY_KEY, "sync-41".parse().unwrap());
+        assert_eq!(
+            idempotency_key(&headers).unwrap().as_deref(),
+            Some("idempotency:sync-41")
+        );
+        headers.insert(IDEMPOTENCY_KEY, "".parse().unwrap());
+        assert!(idempotency_key(&headers).is_err());
+        headers.insert(IDEMPOTENCY_KEY, "k".repeat(256).parse().unwrap());
+        assert!(idempotency_key(&headers).is_err());
+    }
+
+    #[test]
+    fn test_batch_resolves_temp_ids() -> Result<()> {
+        let db = TestDb::new("api")?;
+        let (response, ops) = plan(
+            &db,
+            &operations(
+                r#"[
+                    {"op": "create", "temp_id": "a", "title": "Milk", "due": "2024-05-01"},
+                    {"op": "update", "id": "a", "completed": true}
+                ]"#,
+            ),
+        )?;
+        assert!(response.applied);
+        let id = response.ids["a"];
+        db.apply_batch(ops)?;
+        let todo = db.get::<Todo, _>(todo_key(id))?.unwrap();
+        assert!(todo.completed);
+        assert_eq!(
+            response.results[1].todo.as_ref().unwrap().due.as_deref(),
+            Some("2024-05-01")
+        );
+
+        // one bad operation keeps the whole batch from being applied
+        let (response, _) = plan(
+            &db,
+            &operations(
+                r#"[
+                    {"op": "create", "title": "Eggs"},
+                    {"op": "update", "id": 9999, "title": "gone"}
+                ]"#,
+            ),
+        )?;
+        assert!(!response.applied);
+        assert!(response.results[0].error.is_none());
+        assert!(response.results[1].error.is_some());
+        Ok(())
+    }
+}
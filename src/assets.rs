@@ -0,0 +1,77 @@
+#[cfg(feature = "embed-assets")]
+use axum::{
+    extract::Path,
+    http::header,
+    response::{IntoResponse, Response},
+};
+
+#[cfg(feature = "embed-assets")]
+use crate::error::AppError;
+use crate::routes;
+
+// === Assets ===
+// The scripts every page loads. Built with `embed-assets` they are compiled into the binary from
+// `assets/` (run `scripts/fetch-assets.sh` first) and served under `/assets`, so a release needs
+// nothing but the binary. Otherwise pages load them from their CDNs.
+pub struct Asset {
+    pub name: &'static str,
+    cdn: &'static str,
+    #[cfg(feature = "embed-assets")]
+    bytes: &'static [u8],
+}
+
+macro_rules! asset {
+    ($name:literal, $cdn:literal) => {
+        Asset {
+            name: $name,
+            cdn: $cdn,
+            #[cfg(feature = "embed-assets")]
+            bytes: include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/", $name)),
+        }
+    };
+}
+
+pub const SCRIPTS: [Asset; 5] = [
+    asset!("htmx.min.js", "https://unpkg.com/htmx.org@1.9.10"),
+    asset!(
+        "json-enc.js",
+        "https://unpkg.com/htmx.org@1.9.10/dist/ext/json-enc.js"
+    ),
+    asset!(
+        "sse.js",
+        "https://unpkg.com/htmx.org@1.9.10/dist/ext/sse.js"
+    ),
+    // `hx-swap="morph"`, see `views::hx::Swap::Morph`
+    asset!(
+        "idiomorph-ext.min.js",
+        "https://unpkg.com/idiomorph@0.3.0/dist/idiomorph-ext.min.js"
+    ),
+    asset!("tailwind.js", "https://cdn.tailwindcss.com/3.4.1"),
+];
+
+impl Asset {
+    pub fn url(&self) -> String {
+        if cfg!(feature = "embed-assets") {
+            routes::Asset::url(self.name)
+        } else {
+            self.cdn.to_string()
+        }
+    }
+}
+
+// the embedded file, cached for a year since a new release is a new binary anyway
+#[cfg(feature = "embed-assets")]
+pub async fn serve(Path(name): Path<String>) -> Result<Response, AppError> {
+    let asset = SCRIPTS
+        .iter()
+        .find(|asset| asset.name == name)
+        .ok_or(AppError::NotFound)?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/javascript; charset=utf-8"),
+            (header::CACHE_CONTROL, "public, max-age=31536000"),
+        ],
+        asset.bytes,
+    )
+        .into_response())
+}
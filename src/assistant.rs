@@ -0,0 +1,305 @@
+use std::{collections::HashMap, fmt, future::Future, pin::Pin, time::Duration};
+
+use anyhow::{anyhow, Result};
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    response::Response,
+    Form,
+};
+use maud::{html, Markup};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    db::{driver::Db, queue::WriteOp},
+    error::AppError,
+    models::Todo,
+    repository::{self, entity::Repository, todo::todo_key},
+    routes,
+    state::AppState,
+    tenant::Tenant,
+    views,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(60);
+// suggestions beyond this are dropped, a breakdown into dozens of steps is no help
+const MAX_STEPS: usize = 8;
+const MAX_STEP_LEN: usize = 200;
+const BREAKDOWN_PROMPT: &str = "You split a todo into the concrete steps needed to finish it. \
+Answer with one short step per line and nothing else, at most 8 steps.";
+
+pub type CompletionFuture<'a> = Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>>;
+
+// A chat model answering `prompt` under the `system` instructions.
+pub trait LanguageModel: fmt::Debug + Send + Sync {
+    fn complete<'a>(&'a self, system: &'a str, prompt: &'a str) -> CompletionFuture<'a>;
+}
+
+// === OpenAI-compatible ===
+// `/chat/completions` of OpenAI or anything speaking its api, e.g. llama.cpp, Ollama or vLLM
+#[derive(Debug)]
+pub struct OpenAiCompatible {
+    client: reqwest::Client,
+    base_url: String,
+    model: String,
+    api_key: Option<String>,
+}
+impl OpenAiCompatible {
+    pub fn new(base_url: &str, model: &str, api_key: Option<String>) -> Result<Self> {
+        Ok(Self {
+            client: reqwest::Client::builder().timeout(TIMEOUT).build()?,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            model: model.to_string(),
+            api_key,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct Completion {
+    choices: Vec<Choice>,
+}
+#[derive(Deserialize)]
+struct Choice {
+    message: Message,
+}
+#[derive(Deserialize)]
+struct Message {
+    content: String,
+}
+
+impl LanguageModel for OpenAiCompatible {
+    fn complete<'a>(&'a self, system: &'a str, prompt: &'a str) -> CompletionFuture<'a> {
+        Box::pin(async move {
+            let mut request = self
+                .client
+                .post(format!("{}/chat/completions", self.base_url))
+                .json(&json!({
+                    "model": self.model,
+                    "messages": [
+                        { "role": "system", "content": system },
+                        { "role": "user", "content": prompt },
+                    ],
+                }));
+            if let Some(key) = &self.api_key {
+                request = request.bearer_auth(key);
+            }
+            let completion: Completion = request.send().await?.error_for_status()?.json().await?;
+            completion
+                .choices
+                .into_iter()
+                .next()
+                .map(|choice| choice.message.content)
+                .ok_or_else(|| anyhow!("the model returned no answer"))
+        })
+    }
+}
+
+// === Breakdown ===
+// The steps of a model's answer, one per line with any list markers taken off.
+pub fn parse_steps(answer: &str) -> Vec<String> {
+    answer
+        .lines()
+        .map(|line| {
+            line.trim()
+                .trim_start_matches(|c: char| c.is_ascii_digit())
+                .trim_start_matches(['.', ')', '-', '*', '•'])
+                .trim()
+        })
+        .filter(|step| !step.is_empty())
+        .map(|step| step.chars().take(MAX_STEP_LEN).collect())
+        .take(MAX_STEPS)
+        .collect()
+}
+
+pub async fn breakdown(model: &dyn LanguageModel, title: &str) -> Result<Vec<String>> {
+    Ok(parse_steps(&model.complete(BREAKDOWN_PROMPT, title).await?))
+}
+
+// The writes adding `steps` as new todos that `parent` waits for, in one batch.
+fn plan(db: &Db, parent: &Todo, steps: &[String]) -> Result<Vec<WriteOp>> {
+    let mut blockers = repository::todo::blockers(db, parent.id)?;
+    let mut ops = Vec::new();
+    for step in steps {
+        let mut todo = Todo::created(db.next_id()?, step.clone(), &**db.clock());
+        // sub-tasks belong where the todo does
+        todo.tags = parent.tags.clone();
+        blockers.push(todo.id);
+        ops.extend(Repository::new(db).put_ops(&todo)?);
+    }
+    ops.push(WriteOp::Insert {
+        key: repository::todo::blocked_by_key(parent.id),
+        value: db.encode(&blockers)?,
+    });
+    Ok(ops)
+}
+
+// === Components ===
+// on the detail view, only when a model is configured
+pub fn breakdown_html(id: u64) -> Markup {
+    html! {
+        div id="breakdown" {
+            button class="text-blue-500 hover:text-blue-700" type="button"
+                hx-post=(routes::TodoBreakdown::url(id)) hx-target="#breakdown" hx-swap="outerHTML"
+                hx-indicator="this" { "Break down into steps" }
+        }
+    }
+}
+
+// the suggestions as editable fields, nothing is saved until they are accepted
+fn review_html(id: u64, steps: &[String]) -> Markup {
+    let url = routes::TodoSteps::url(id);
+    html! {
+        form id="breakdown" class="space-y-2" method="post" action=(url) hx-post=(url) hx-target="#breakdown" hx-swap="outerHTML" {
+            h2 class="text-xl text-gray-700" { "Suggested steps" }
+            @if steps.is_empty() {
+                p class="text-gray-500" { "No steps came back, add your own." }
+            }
+            @for (index, step) in steps.iter().enumerate() {
+                div class="flex items-center space-x-2" {
+                    input type="checkbox" name={ "accept-" (index) } value="true" checked aria-label="Keep this step";
+                    input class="flex-grow rounded border p-1" type="text" name={ "step-" (index) } value=(step) maxlength=(MAX_STEP_LEN);
+                }
+            }
+            div class="flex items-center space-x-2" {
+                input type="checkbox" name={ "accept-" (steps.len()) } value="true" checked aria-label="Keep this step";
+                input class="flex-grow rounded border p-1" type="text" name={ "step-" (steps.len()) } placeholder="Another step" maxlength=(MAX_STEP_LEN);
+            }
+            button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" { "Add as todos" }
+        }
+    }
+}
+
+// the kept, non-empty steps of a submitted review, in order
+fn accepted_steps(form: &HashMap<String, String>) -> Vec<String> {
+    let mut steps: Vec<(usize, String)> = form
+        .iter()
+        .filter_map(|(name, value)| {
+            let index: usize = name.strip_prefix("step-")?.parse().ok()?;
+            let kept = form.contains_key(&format!("accept-{}", index));
+            let step = value.trim();
+            (kept && !step.is_empty()).then(|| (index, step.chars().take(MAX_STEP_LEN).collect()))
+        })
+        .collect();
+    steps.sort_by_key(|(index, _)| *index);
+    steps
+        .into_iter()
+        .map(|(_, step)| step)
+        .take(MAX_STEPS + 1)
+        .collect()
+}
+
+// === Routes ===
+pub async fn suggest(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    Path(id): Path<u64>,
+) -> Result<Markup, AppError> {
+    let model = state.assistant.clone().ok_or(AppError::NotFound)?;
+    let todo = state
+        .read()
+        .await
+        .for_tenant(tenant.id())?
+        .get::<Todo, _>(todo_key(id))?
+        .ok_or(AppError::NotFound)?;
+    // outside the lock, the model may take a while
+    let steps = breakdown(model.as_ref(), &todo.title).await?;
+    Ok(review_html(id, &steps))
+}
+
+pub async fn accept(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Path(id): Path<u64>,
+    Form(form): Form<HashMap<String, String>>,
+) -> Result<Response, AppError> {
+    let steps = accepted_steps(&form);
+    let writes = state.writes.clone();
+    let guard = state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    let parent = db.get::<Todo, _>(todo_key(id))?.ok_or(AppError::NotFound)?;
+    if !steps.is_empty() {
+        writes.submit(&db, plan(&db, &parent, &steps)?).await?;
+    }
+    Ok(views::fragment_or_redirect(
+        &headers,
+        html! {
+            p id="breakdown" class="text-gray-600" {
+                @match steps.len() {
+                    0 => { "No steps added." }
+                    1 => { "Added 1 step, this todo waits for it now." }
+                    count => { "Added " (count) " steps, this todo waits for them now." }
+                }
+            }
+        },
+        &routes::TodoDetail::url(id),
+    ))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::TestDb;
+
+    #[derive(Debug)]
+    struct Canned(&'static str);
+    impl LanguageModel for Canned {
+        fn complete<'a>(&'a self, _system: &'a str, _prompt: &'a str) -> CompletionFuture<'a> {
+            Box::pin(async { Ok(self.0.to_string()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_breakdown() -> Result<()> {
+        let answer = "1. Pick a date\n2) Book the venue\n\n- Send invites\n* Order cake";
+        let steps = breakdown(&Canned(answer), "Plan the party").await?;
+        assert_eq!(
+            steps,
+            [
+                "Pick a date",
+                "Book the venue",
+                "Send invites",
+                "Order cake"
+            ]
+        );
+        assert_eq!(parse_steps(&"step\n".repeat(20)).len(), MAX_STEPS);
+        Ok(())
+    }
+
+    #[test]
+    fn test_accepted_steps() {
+        let form: HashMap<String, String> = [
+            ("step-0", "Pick a date"),
+            ("accept-0", "true"),
+            ("step-1", "Book the venue"),
+            ("step-2", " "),
+            ("accept-2", "true"),
+            ("step-10", "Order cake"),
+            ("accept-10", "true"),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+        assert_eq!(accepted_steps(&form), ["Pick a date", "Order cake"]);
+    }
+
+    #[test]
+    fn test_plan() -> Result<()> {
+        let db = TestDb::new("assistant")?;
+        let parent = Todo::new(db.next_id()?, "Plan the party".into());
+        db.insert(todo_key(parent.id), &parent)?;
+
+        db.apply_batch(plan(
+            &db,
+            &parent,
+            &["Book the venue".into(), "Order cake".into()],
+        )?)?;
+        let blockers = repository::todo::blockers(&db, parent.id)?;
+        assert_eq!(blockers.len(), 2);
+        assert!(repository::todo::is_blocked(&db, parent.id)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,614 @@
+use std::time::Duration;
+
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use maud::{html, Markup, PreEscaped};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth,
+    config::Config,
+    db::{driver::Db, queue::WriteOp},
+    error::{AppError, ErrorReport},
+    models::Todo,
+    repository, routes,
+    state::AppState,
+    tenant::Tenant,
+};
+
+// `attachment:{todo}:{id}`, what the detail view lists
+const ATTACHMENT_PREFIX: &str = "attachment:";
+// `attachment_data:{id}`, the file itself
+const DATA_PREFIX: &str = "attachment_data:";
+// how long a download link works, links are signed again on every render
+pub const URL_TTL: Duration = Duration::from_secs(15 * 60);
+// the element the list is swapped into
+pub const ATTACHMENTS_ID: &str = "attachments";
+// the header carrying the file name of an upload, url-encoded
+pub const FILENAME_HEADER: &str = "x-filename";
+// images a browser shows inline, svg is left out as it can carry scripts
+const INLINE_IMAGES: [&str; 4] = ["image/png", "image/jpeg", "image/gif", "image/webp"];
+
+// === Attachments ===
+// A file uploaded to a todo, stored in the todo's workspace.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attachment {
+    pub id: u64,
+    pub todo: u64,
+    pub name: String,
+    pub content_type: String,
+    pub size: u64,
+    // unix seconds
+    pub created_at: u64,
+}
+
+impl Attachment {
+    pub fn is_image(&self) -> bool {
+        INLINE_IMAGES.contains(&self.content_type.as_str())
+    }
+}
+
+fn attachment_key(todo: u64, id: u64) -> String {
+    format!("{}{}:{}", ATTACHMENT_PREFIX, todo, id)
+}
+fn data_key(id: u64) -> String {
+    format!("{}{}", DATA_PREFIX, id)
+}
+
+// the attachments of `todo`, oldest first
+pub fn for_todo(db: &Db, todo: u64) -> anyhow::Result<Vec<Attachment>> {
+    db.iter_prefix::<Attachment>(&format!("{}{}:", ATTACHMENT_PREFIX, todo))?
+        .map(|item| item.map(|(_, attachment)| attachment))
+        .collect()
+}
+
+pub fn get(db: &Db, todo: u64, id: u64) -> anyhow::Result<Option<Attachment>> {
+    db.get(attachment_key(todo, id))
+}
+
+pub fn data(db: &Db, id: u64) -> anyhow::Result<Option<Vec<u8>>> {
+    db.get(data_key(id))
+}
+
+// the writes storing `attachment` and its bytes together
+pub fn add_ops(db: &Db, attachment: &Attachment, bytes: &[u8]) -> anyhow::Result<Vec<WriteOp>> {
+    Ok(vec![
+        WriteOp::Insert {
+            key: data_key(attachment.id),
+            value: db.encode(&bytes.to_vec())?,
+        },
+        WriteOp::Insert {
+            key: attachment_key(attachment.todo, attachment.id),
+            value: db.encode(attachment)?,
+        },
+    ])
+}
+
+// The writes handing the attachments of `from` to `to`, for merging the todos. Their files stay
+// where they are.
+pub fn move_ops(db: &Db, from: u64, to: u64) -> anyhow::Result<Vec<WriteOp>> {
+    let mut ops = Vec::new();
+    for mut attachment in for_todo(db, from)? {
+        ops.push(WriteOp::Remove {
+            key: attachment_key(from, attachment.id),
+        });
+        attachment.todo = to;
+        ops.push(WriteOp::Insert {
+            key: attachment_key(to, attachment.id),
+            value: db.encode(&attachment)?,
+        });
+    }
+    Ok(ops)
+}
+
+// The write listing `attachment` under todo `to` again, for a todo restored under a new id.
+// `None` when its file is gone.
+pub fn relink_op(db: &Db, attachment: &Attachment, to: u64) -> anyhow::Result<Option<WriteOp>> {
+    if db.get_payload(data_key(attachment.id))?.is_none() {
+        return Ok(None);
+    }
+    let attachment = Attachment {
+        todo: to,
+        ..attachment.clone()
+    };
+    Ok(Some(WriteOp::Insert {
+        key: attachment_key(to, attachment.id),
+        value: db.encode(&attachment)?,
+    }))
+}
+
+// === Policy ===
+// What may be attached, from `ATTACHMENT_TYPES` and `ATTACHMENT_MAX_BYTES`. The upload zone checks
+// it before sending, the upload handler again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Policy {
+    // content types, `image/*` for a whole family, any type when empty
+    pub types: Vec<String>,
+    pub max_bytes: usize,
+}
+impl Policy {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            types: config.attachment_types.clone(),
+            max_bytes: config.attachment_max_bytes,
+        }
+    }
+
+    pub fn allows_type(&self, content_type: &str) -> bool {
+        self.types.is_empty()
+            || self
+                .types
+                .iter()
+                .any(|allowed| match allowed.strip_suffix('*') {
+                    Some(family) => content_type.starts_with(family),
+                    None => allowed == content_type,
+                })
+    }
+
+    pub fn check(&self, content_type: &str, size: usize) -> Result<(), String> {
+        if !self.allows_type(content_type) {
+            return Err(format!(
+                "Files of type {} cannot be attached.",
+                content_type
+            ));
+        }
+        if size > self.max_bytes {
+            return Err(format!(
+                "The file is larger than {}.",
+                format_size(self.max_bytes as u64)
+            ));
+        }
+        Ok(())
+    }
+}
+
+// === Signed URLs ===
+// Download links carry an expiry and a signature over it, the workspace and the attachment, so
+// they cannot be guessed or kept around.
+const SIGNED_PURPOSE: &str = "attachment";
+
+fn payload(tenant: Option<&str>, todo: u64, id: u64, expires: u64) -> String {
+    format!("{}:{}:{}:{}", tenant.unwrap_or_default(), todo, id, expires)
+}
+
+// `now` and `expires` in unix seconds
+pub fn signed_url(key: &[u8], tenant: Option<&str>, attachment: &Attachment, now: u64) -> String {
+    let expires = now + URL_TTL.as_secs();
+    let signed = auth::sign(
+        key,
+        SIGNED_PURPOSE,
+        &payload(tenant, attachment.todo, attachment.id, expires),
+    );
+    let (_, signature) = signed.rsplit_once('.').unwrap_or_default();
+    format!(
+        "{}?expires={}&signature={}",
+        routes::TodoAttachment::url(attachment.todo, attachment.id),
+        expires,
+        signature
+    )
+}
+
+// for showing an image on the page rather than downloading it
+pub fn inline_url(key: &[u8], tenant: Option<&str>, attachment: &Attachment, now: u64) -> String {
+    format!("{}&inline=true", signed_url(key, tenant, attachment, now))
+}
+
+pub fn verify(key: &[u8], tenant: Option<&str>, todo: u64, id: u64, link: &Link, now: u64) -> bool {
+    let signed = format!(
+        "{}.{}",
+        payload(tenant, todo, id, link.expires),
+        link.signature
+    );
+    now < link.expires && auth::verify_signed(key, SIGNED_PURPOSE, &signed).is_some()
+}
+
+// === Components ===
+fn format_size(bytes: u64) -> String {
+    match bytes {
+        0..=1023 => format!("{} B", bytes),
+        1024..=1_048_575 => format!("{:.1} KB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MB", bytes as f64 / 1_048_576.0),
+    }
+}
+
+// Uploads picked, dropped or pasted files one by one as the request body and swaps in the list
+// the server answers with. Registered once on the document, the section is swapped on every
+// upload. The indicator named by the zone's `hx-indicator` shows while a file is sent.
+const UPLOAD_SCRIPT: &str = r##"
+(function () {
+    if (window.attachmentUploads) return;
+    window.attachmentUploads = true;
+    const zone = () => document.querySelector("[data-upload-zone]");
+    function allowed(zone, file) {
+        const types = zone.dataset.types ? zone.dataset.types.split(",") : [];
+        const type = file.type || "application/octet-stream";
+        const typeOk = types.length === 0 || types.some((t) => t === type || (t.endsWith("/*") && type.startsWith(t.slice(0, -1))));
+        if (!typeOk) return file.name + " is not a type that can be attached.";
+        if (file.size > Number(zone.dataset.maxBytes)) return file.name + " is too large to attach.";
+        return null;
+    }
+    function upload(zone, file) {
+        return new Promise(function (resolve) {
+            const error = allowed(zone, file);
+            const message = zone.querySelector("[role=alert]");
+            if (error) { message.textContent = error; return resolve(); }
+            const indicator = document.querySelector(zone.getAttribute("hx-indicator"));
+            const progress = indicator.querySelector("progress");
+            const xhr = new XMLHttpRequest();
+            xhr.open("POST", zone.dataset.url);
+            xhr.setRequestHeader("Content-Type", file.type || "application/octet-stream");
+            xhr.setRequestHeader("X-Filename", encodeURIComponent(file.name));
+            xhr.setRequestHeader("HX-Request", "true");
+            xhr.upload.onprogress = function (e) { if (e.lengthComputable) progress.value = e.loaded / e.total; };
+            indicator.classList.add("htmx-request");
+            xhr.onloadend = function () {
+                indicator.classList.remove("htmx-request");
+                if (xhr.status === 200) {
+                    document.getElementById(zone.dataset.target).outerHTML = xhr.responseText;
+                    htmx.process(document.getElementById(zone.dataset.target));
+                } else {
+                    message.textContent = "Could not attach " + file.name + ".";
+                }
+                resolve();
+            };
+            xhr.send(file);
+        });
+    }
+    async function uploadAll(files) {
+        for (const file of files) {
+            const current = zone();
+            if (current) await upload(current, file);
+        }
+    }
+    document.addEventListener("change", function (e) {
+        if (e.target.matches("[data-upload-zone] input[type=file]")) uploadAll(e.target.files);
+    });
+    document.addEventListener("dragover", function (e) {
+        const target = e.target.closest("[data-upload-zone]");
+        if (!target) return;
+        e.preventDefault();
+        target.classList.add("ring-2");
+    });
+    document.addEventListener("dragleave", function (e) {
+        const target = e.target.closest("[data-upload-zone]");
+        if (target) target.classList.remove("ring-2");
+    });
+    document.addEventListener("drop", function (e) {
+        const target = e.target.closest("[data-upload-zone]");
+        if (!target) return;
+        e.preventDefault();
+        target.classList.remove("ring-2");
+        uploadAll(e.dataTransfer.files);
+    });
+    // pasting a file anywhere while a todo is open attaches it, pasted text is left alone
+    document.addEventListener("paste", function (e) {
+        const files = e.clipboardData ? e.clipboardData.files : [];
+        if (!zone() || files.length === 0) return;
+        e.preventDefault();
+        uploadAll(files);
+    });
+})();
+"##;
+
+// the attachments of a todo with freshly signed download links
+pub fn list_html(
+    key: &[u8],
+    tenant: Option<&str>,
+    todo: &Todo,
+    attachments: &[Attachment],
+    policy: &Policy,
+    now: u64,
+) -> Markup {
+    let progress_id = format!("{}-progress", ATTACHMENTS_ID);
+    html! {
+        section id=(ATTACHMENTS_ID) class="space-y-2" {
+            h2 class="text-xl text-gray-700" { "Attachments" }
+            @if attachments.is_empty() {
+                p class="text-gray-500" { "No files yet." }
+            }
+            @let images: Vec<_> = attachments.iter().filter(|attachment| attachment.is_image()).collect();
+            @if !images.is_empty() {
+                div class="flex flex-wrap gap-2" {
+                    @for image in images {
+                        button type="button" aria-label={ "View " (image.name) }
+                            hx-get=(routes::TodoAttachmentView::url(todo.id, image.id)) hx-target="body" hx-swap="beforeend" {
+                            img class="h-20 w-20 object-cover rounded" loading="lazy" alt=(image.name)
+                                src=(inline_url(key, tenant, image, now));
+                        }
+                    }
+                }
+            }
+            ul class="space-y-1" {
+                @for attachment in attachments {
+                    li class="flex items-center justify-between" {
+                        a class="text-blue-500 hover:text-blue-700" href=(signed_url(key, tenant, attachment, now)) { (attachment.name) }
+                        span class="text-sm text-gray-500" { (format_size(attachment.size)) }
+                    }
+                }
+            }
+            div class="rounded border-2 border-dashed border-gray-300 p-4 text-center text-gray-500" data-upload-zone
+                data-url=(routes::TodoAttachments::url(todo.id)) data-target=(ATTACHMENTS_ID)
+                data-types=(policy.types.join(",")) data-max-bytes=(policy.max_bytes)
+                hx-indicator={ "#" (progress_id) } {
+                p { "Drop files here, paste them, or " }
+                input class="text-sm" type="file" multiple aria-label="Attach files"
+                    accept=[(!policy.types.is_empty()).then(|| policy.types.join(","))];
+                div id=(progress_id) class="htmx-indicator" { progress class="w-full" value="0" max="1" {} }
+                p class="text-red-500" role="alert" {}
+            }
+            script { (PreEscaped(UPLOAD_SCRIPT)) }
+        }
+    }
+}
+
+// the section of the detail view, signed again every time it is shown
+pub async fn load(state: &AppState, tenant: &Tenant, todo: &Todo) -> Result<Markup, AppError> {
+    let db = state.read().await.for_tenant(tenant.id())?;
+    let attachments = for_todo(&db, todo.id)?;
+    let now = db.clock().now_millis() / 1000;
+    Ok(list_html(
+        &state.secret_key,
+        tenant.id(),
+        todo,
+        &attachments,
+        &Policy::from_config(&state.config),
+        now,
+    ))
+}
+
+// An image over the page, closed by clicking anywhere or escape.
+pub fn lightbox_html(image: &Attachment, src: &str) -> Markup {
+    html! {
+        dialog class="max-w-none max-h-none bg-transparent p-0 backdrop:bg-black/80" aria-label=(image.name)
+            "hx-on::load"="this.showModal()" onclick="this.close()" onclose="this.remove()" {
+            img class="max-w-[90vw] max-h-[90vh]" src=(src) alt=(image.name);
+        }
+    }
+}
+
+// === Routes ===
+// The file is the request body, named by `X-Filename`. Answers with the updated list.
+pub async fn upload(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Path(id): Path<u64>,
+    body: Bytes,
+) -> Result<Response, AppError> {
+    if body.is_empty() {
+        return Err(AppError::Invalid("The file is empty.".into()));
+    }
+    let name = headers
+        .get(FILENAME_HEADER)
+        .and_then(|name| name.to_str().ok())
+        .and_then(|name| urlencoding::decode(name).ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "file".to_string());
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("application/octet-stream")
+        .to_string();
+    Policy::from_config(&state.config)
+        .check(&content_type, body.len())
+        .map_err(AppError::Invalid)?;
+    let writes = state.writes.clone();
+    let guard = state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    let todo = db
+        .get::<Todo, _>(repository::todo::todo_key(id))?
+        .ok_or(AppError::NotFound)?;
+    let attachment = Attachment {
+        id: db.next_id()?,
+        todo: id,
+        name,
+        content_type,
+        size: body.len() as u64,
+        created_at: db.clock().now_millis() / 1000,
+    };
+    writes
+        .submit(&db, add_ops(&db, &attachment, &body)?)
+        .await?;
+    drop(guard);
+    Ok(load(&state, &tenant, &todo).await?.into_response())
+}
+
+// the image in a lightbox, appended to the page
+pub async fn view(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    Path((todo, id)): Path<(u64, u64)>,
+) -> Result<Markup, AppError> {
+    let db = state.read().await.for_tenant(tenant.id())?;
+    let image = get(&db, todo, id)?
+        .filter(Attachment::is_image)
+        .ok_or(AppError::NotFound)?;
+    let now = db.clock().now_millis() / 1000;
+    Ok(lightbox_html(
+        &image,
+        &inline_url(&state.secret_key, tenant.id(), &image, now),
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct Link {
+    expires: u64,
+    signature: String,
+    // shown on the page, only for images
+    #[serde(default)]
+    inline: bool,
+}
+// the file, for a link signed recently enough
+pub async fn download(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    Path((todo, id)): Path<(u64, u64)>,
+    Query(link): Query<Link>,
+) -> Result<Response, AppError> {
+    let db = state.read().await.for_tenant(tenant.id())?;
+    let now = db.clock().now_millis() / 1000;
+    if !verify(&state.secret_key, tenant.id(), todo, id, &link, now) {
+        return Ok(ErrorReport::new(
+            StatusCode::FORBIDDEN,
+            "This download link expired, open the todo again for a fresh one.",
+        )
+        .into_response());
+    }
+    let attachment = get(&db, todo, id)?.ok_or(AppError::NotFound)?;
+    let bytes = data(&db, id)?.ok_or(AppError::NotFound)?;
+    let disposition = format!(
+        "{}; filename=\"{}\"",
+        if link.inline && attachment.is_image() {
+            "inline"
+        } else {
+            "attachment"
+        },
+        attachment.name.replace(['"', '\\'], "_")
+    );
+    Ok((
+        [
+            (header::CONTENT_TYPE, attachment.content_type),
+            (header::CONTENT_DISPOSITION, disposition),
+            (
+                header::CACHE_CONTROL,
+                format!("private, max-age={}", link.expires.saturating_sub(now)),
+            ),
+        ],
+        bytes,
+    )
+        .into_response())
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::TestDb;
+
+    fn link(url: &str) -> Link {
+        let query = url.split_once('?').unwrap().1;
+        let (expires, signature) = query.split_once('&').unwrap();
+        Link {
+            expires: expires.trim_start_matches("expires=").parse().unwrap(),
+            signature: signature.trim_start_matches("signature=").to_string(),
+            inline: false,
+        }
+    }
+
+    #[test]
+    fn test_signed_urls() {
+        let attachment = Attachment {
+            id: 3,
+            todo: 7,
+            name: "notes.txt".into(),
+            content_type: "text/plain".into(),
+            size: 5,
+            created_at: 0,
+        };
+        let now = 1_700_000_000;
+        let url = signed_url(b"key", Some("acme"), &attachment, now);
+        assert!(url.starts_with("/todos/7/attachments/3?expires="));
+        let given = link(&url);
+        assert!(verify(b"key", Some("acme"), 7, 3, &given, now));
+        // another workspace, another file, a forged expiry, too late
+        assert!(!verify(b"key", None, 7, 3, &given, now));
+        assert!(!verify(b"key", Some("acme"), 7, 4, &given, now));
+        let extended = Link {
+            expires: given.expires + 3600,
+            signature: given.signature.clone(),
+            inline: false,
+        };
+        assert!(!verify(b"key", Some("acme"), 7, 3, &extended, now));
+        assert!(!verify(
+            b"key",
+            Some("acme"),
+            7,
+            3,
+            &given,
+            now + URL_TTL.as_secs()
+        ));
+    }
+
+    #[test]
+    fn test_signature_is_not_a_session() {
+        let attachment = Attachment {
+            id: 3,
+            todo: 7,
+            name: "notes.txt".into(),
+            content_type: "text/plain".into(),
+            size: 5,
+            created_at: 0,
+        };
+        let given = link(&signed_url(b"key", Some("acme"), &attachment, 0));
+        // `{payload}.{signature}` is what a session cookie looks like
+        let cookie = format!(
+            "{}.{}",
+            payload(Some("acme"), 7, 3, given.expires),
+            given.signature
+        );
+        assert!(auth::verify_signed(b"key", SIGNED_PURPOSE, &cookie).is_some());
+        assert_eq!(
+            auth::verify_signed(b"key", crate::admin::passkeys::SESSION_COOKIE, &cookie),
+            None
+        );
+    }
+
+    #[test]
+    fn test_policy() {
+        let policy = Policy {
+            types: vec!["image/*".into(), "application/pdf".into()],
+            max_bytes: 1024,
+        };
+        assert!(policy.check("image/png", 10).is_ok());
+        assert!(policy.check("application/pdf", 1024).is_ok());
+        assert!(policy.check("application/pdf", 1025).is_err());
+        assert!(policy.check("text/html", 10).is_err());
+        let open = Policy {
+            types: Vec::new(),
+            max_bytes: 1024,
+        };
+        assert!(open.allows_type("text/html"));
+    }
+
+    #[test]
+    fn test_images_inline() {
+        let mut image = Attachment {
+            id: 3,
+            todo: 7,
+            name: "plan.png".into(),
+            content_type: "image/png".into(),
+            size: 5,
+            created_at: 0,
+        };
+        assert!(image.is_image());
+        let lightbox = lightbox_html(&image, "/src").into_string();
+        assert!(lightbox.contains(r#"<img class="max-w-[90vw] max-h-[90vh]" src="/src""#));
+        let url = inline_url(b"key", None, &image, 0);
+        assert!(url.ends_with("&inline=true"));
+        image.content_type = "image/svg+xml".into();
+        assert!(!image.is_image());
+    }
+
+    #[test]
+    fn test_storage() -> anyhow::Result<()> {
+        let db = TestDb::new("attachments")?;
+        let attachment = Attachment {
+            id: 1,
+            todo: 2,
+            name: "a.txt".into(),
+            content_type: "text/plain".into(),
+            size: 2,
+            created_at: 0,
+        };
+        db.apply_batch(add_ops(&db, &attachment, b"hi")?)?;
+        assert_eq!(for_todo(&db, 2)?, [attachment.clone()]);
+        assert!(for_todo(&db, 22)?.is_empty());
+        assert_eq!(data(&db, 1)?, Some(b"hi".to_vec()));
+        Ok(())
+    }
+}
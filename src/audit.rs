@@ -0,0 +1,137 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Context, Result};
+use serde_json::json;
+
+use crate::{activity, domain::events::DomainEvent};
+
+// === Sink ===
+// With `AUDIT_LOG`, every domain event is appended to a file as one json line, next to the
+// activity log in the database, for shipping to storage this server can't change:
+//
+//     {"at": 1700000000, "tenant": null, "event": "todo.completed", "actor": "ana", "todo": {..}}
+//
+// Lines are only ever appended. Past `AUDIT_LOG_MAX_BYTES` the file is renamed to `{path}.1`,
+// moving the older ones up to `{path}.2` and so on, and a new one is started, nothing is deleted.
+#[derive(Debug, Clone)]
+pub struct AuditSink {
+    path: PathBuf,
+    max_bytes: u64,
+    file: Arc<Mutex<File>>,
+}
+impl AuditSink {
+    pub fn open(path: &Path, max_bytes: u64) -> Result<Self> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            max_bytes,
+            file: Arc::new(Mutex::new(append_to(path)?)),
+        })
+    }
+
+    pub fn line(at: u64, tenant: Option<&str>, actor: Option<&str>, event: &DomainEvent) -> String {
+        let mut line = json!({
+            "at": at,
+            "tenant": tenant,
+            "event": event.name(),
+            "actor": actor,
+            "todo": event.todo(),
+        });
+        if let DomainEvent::TodoMerged { merged, .. } = event {
+            line["merged"] = json!(merged.id);
+        }
+        line.to_string()
+    }
+
+    // Failing to write is logged and not returned, the change itself is stored by now.
+    pub fn record(&self, at: u64, tenant: Option<&str>, event: &DomainEvent) {
+        let line = Self::line(at, tenant, activity::actor().as_deref(), event);
+        if let Err(err) = self.append(&line) {
+            tracing::error!(path = %self.path.display(), "failed to write the audit log: {:#}", err);
+        }
+    }
+
+    fn append(&self, line: &str) -> Result<()> {
+        let mut file = self.file.lock().expect("audit lock poisoned");
+        if file.metadata()?.len() + line.len() as u64 + 1 > self.max_bytes {
+            self.rotate()?;
+            *file = append_to(&self.path)?;
+        }
+        writeln!(file, "{}", line)?;
+        file.sync_data()?;
+        Ok(())
+    }
+
+    fn rotate(&self) -> Result<()> {
+        let mut last = 1;
+        while rotated(&self.path, last).exists() {
+            last += 1;
+        }
+        for n in (1..last).rev() {
+            fs::rename(rotated(&self.path, n), rotated(&self.path, n + 1))?;
+        }
+        // an empty file has nothing to keep, a single line over the limit still gets written
+        if fs::metadata(&self.path)?.len() > 0 {
+            fs::rename(&self.path, rotated(&self.path, 1))?;
+        }
+        Ok(())
+    }
+}
+
+fn append_to(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("could not open the audit log {}", path.display()))
+}
+
+fn rotated(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+    use crate::models::Todo;
+
+    #[test]
+    fn test_appends_and_rotates() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("rust-htmx-audit-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir)?;
+        let path = dir.join("audit.ndjson");
+        let event = DomainEvent::TodoCreated(Todo::new(1, "Milk".into()));
+        let size = AuditSink::line(0, Some("acme"), None, &event).len() as u64 + 1;
+        let sink = AuditSink::open(&path, size * 2)?;
+
+        for _ in 0..5 {
+            sink.record(0, Some("acme"), &event);
+        }
+        let lines = |path: &Path| -> Result<Vec<Value>> {
+            fs::read_to_string(path)?
+                .lines()
+                .map(|line| Ok(serde_json::from_str(line)?))
+                .collect()
+        };
+        let current = lines(&path)?;
+        assert_eq!(current.len(), 1);
+        assert_eq!(current[0]["event"], "todo.created");
+        assert_eq!(current[0]["tenant"], "acme");
+        assert_eq!(current[0]["todo"]["title"], "Milk");
+        assert_eq!(lines(&rotated(&path, 1))?.len(), 2);
+        assert_eq!(lines(&rotated(&path, 2))?.len(), 2);
+        assert!(!rotated(&path, 3).exists());
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
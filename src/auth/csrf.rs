@@ -0,0 +1,120 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderName, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::{
+    api::cors,
+    auth::{self, visitor::Visitor},
+    error::ErrorReport,
+    routes,
+    state::AppState,
+};
+
+// readable by the page's script, which sends it back in the header on every htmx request
+pub const CSRF_COOKIE: &str = "csrf";
+pub const CSRF_HEADER: HeaderName = HeaderName::from_static("x-csrf-token");
+
+pub const CSRF_SCRIPT: &str = r#"
+document.body.addEventListener("htmx:configRequest", function (evt) {
+    const token = document.cookie.split("; ").find((cookie) => cookie.startsWith("csrf="));
+    if (token) {
+        evt.detail.headers["X-CSRF-Token"] = token.slice("csrf=".length);
+    }
+});
+"#;
+
+// The token of a visitor, bound to their signed cookie so another site cannot make one up.
+pub fn token(key: &[u8], visitor: &str) -> String {
+    hex::encode(auth::signature(key, "csrf", visitor))
+}
+
+// Refuse mutating requests from other sites. htmx sends the token; plain form posts, which work
+// without javascript, and the json api are let through when their `Origin` is this host, or for
+// the api one of the origins it trusts with cookies. Clients outside a browser send `Origin` too.
+// Inbound webhooks are signed by their sender instead, see `inbound`.
+pub async fn protect(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let is_safe = matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS
+    );
+    if is_safe || request.uri().path() == routes::InboundTodos::PATH {
+        return next.run(request).await;
+    }
+    let has_token = match (
+        request.extensions().get::<Visitor>(),
+        request
+            .headers()
+            .get(&CSRF_HEADER)
+            .and_then(|value| value.to_str().ok()),
+    ) {
+        (Some(Visitor(visitor)), Some(given)) => auth::constant_time_eq(
+            given.as_bytes(),
+            token(&state.secret_key, visitor).as_bytes(),
+        ),
+        _ => false,
+    };
+    let is_trusted = request
+        .headers()
+        .get(header::ORIGIN)
+        .and_then(|origin| origin.to_str().ok())
+        .is_some_and(|origin| cors::is_trusted(&state.live.load(), request.uri().path(), origin));
+    if has_token || is_same_origin(request.headers()) || is_trusted {
+        return next.run(request).await;
+    }
+    ErrorReport::new(
+        StatusCode::FORBIDDEN,
+        "This request came from another site, reload the page and try again.",
+    )
+    .into_response()
+}
+
+// Browsers send `Origin` with every post, a request without one is refused.
+fn is_same_origin(headers: &HeaderMap) -> bool {
+    let Some(origin) = headers.get(header::ORIGIN) else {
+        return false;
+    };
+    let host = headers
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok());
+    let origin_host = origin
+        .to_str()
+        .ok()
+        .and_then(|origin| origin.split_once("://"))
+        .map(|(_, host)| host);
+    host.is_some() && origin_host == host
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn test_is_same_origin() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::HOST, HeaderValue::from_static("todos.example.com"));
+        headers.insert(
+            header::ORIGIN,
+            HeaderValue::from_static("https://todos.example.com"),
+        );
+        assert!(is_same_origin(&headers));
+        headers.insert(header::ORIGIN, HeaderValue::from_static("https://evil.com"));
+        assert!(!is_same_origin(&headers));
+        headers.insert(header::ORIGIN, HeaderValue::from_static("null"));
+        assert!(!is_same_origin(&headers));
+        headers.remove(header::ORIGIN);
+        assert!(!is_same_origin(&headers));
+    }
+
+    #[test]
+    fn test_token() {
+        assert_eq!(token(b"key", "a"), token(b"key", "a"));
+        assert_ne!(token(b"key", "a"), token(b"key", "b"));
+        assert_ne!(token(b"key", "a"), token(b"other", "a"));
+    }
+}
@@ -0,0 +1,156 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use super::totp;
+use crate::db::driver::Db;
+
+// second factor settings per account, `mfa:{account}`
+const MFA_PREFIX: &str = "mfa:";
+// an enrollment waiting for its first code, `mfa_pending:{account}`
+const PENDING_PREFIX: &str = "mfa_pending:";
+// the time step of the last accepted code, `mfa_step:{account}`
+const STEP_PREFIX: &str = "mfa_step:";
+const RECOVERY_CODES: usize = 8;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MfaRecord {
+    pub secret: Vec<u8>,
+    pub recovery_hashes: Vec<String>,
+    // enrollment is only active once a first code was confirmed
+    pub enabled: bool,
+}
+
+pub fn get(db: &Db, account: &str) -> Result<Option<MfaRecord>> {
+    db.get(key(account))
+}
+// whether `account` has to pass the second login step
+pub fn is_enforced(db: &Db, account: &str) -> Result<bool> {
+    Ok(get(db, account)?.is_some_and(|record| record.enabled))
+}
+
+// Start (or restart) enrollment. Returns the record and the plain recovery codes to show once.
+// The new secret waits aside until confirmed, an enabled one keeps working until then.
+pub fn enroll(db: &Db, account: &str) -> Result<(MfaRecord, Vec<String>)> {
+    let codes = totp::generate_recovery_codes(RECOVERY_CODES);
+    let record = MfaRecord {
+        secret: totp::generate_secret(),
+        recovery_hashes: codes
+            .iter()
+            .map(|code| totp::hash_recovery_code(code))
+            .collect(),
+        enabled: false,
+    };
+    db.insert(pending_key(account), &record)?;
+    Ok((record, codes))
+}
+// Activate a pending enrollment once the user proved their app produces valid codes.
+pub fn confirm(db: &Db, account: &str, code: &str) -> Result<bool> {
+    let Some(mut record) = db.get::<MfaRecord, _>(pending_key(account))? else {
+        return Ok(false);
+    };
+    let now = db.clock().now_millis() / 1000;
+    let Some(step) = totp::matching_step(&record.secret, code, now) else {
+        return Ok(false);
+    };
+    record.enabled = true;
+    db.insert(key(account), &record)?;
+    db.insert(step_key(account), &step)?;
+    db.remove(pending_key(account))?;
+    Ok(true)
+}
+pub fn disable(db: &Db, account: &str) -> Result<()> {
+    db.remove_all([key(account), pending_key(account), step_key(account)])
+}
+
+// Check a login code, falling back to (and using up) a recovery code. A code is only good once,
+// codes of the step of the last accepted one or an earlier step are refused.
+pub fn verify(db: &Db, account: &str, code: &str) -> Result<bool> {
+    let Some(mut record) = get(db, account)? else {
+        return Ok(false);
+    };
+    let now = db.clock().now_millis() / 1000;
+    if let Some(step) = totp::matching_step(&record.secret, code, now) {
+        let last = db.get::<u64, _>(step_key(account))?;
+        if last.is_some_and(|last| step <= last) {
+            tracing::warn!(account, "replayed a second factor code");
+            return Ok(false);
+        }
+        db.insert(step_key(account), &step)?;
+        return Ok(true);
+    }
+    let hash = totp::hash_recovery_code(code);
+    let Some(index) = record
+        .recovery_hashes
+        .iter()
+        .position(|known| *known == hash)
+    else {
+        return Ok(false);
+    };
+    record.recovery_hashes.remove(index);
+    db.insert(key(account), &record)?;
+    tracing::info!(account, "used a recovery code");
+    Ok(true)
+}
+
+fn key(account: &str) -> String {
+    format!("{}{}", MFA_PREFIX, account)
+}
+fn pending_key(account: &str) -> String {
+    format!("{}{}", PENDING_PREFIX, account)
+}
+fn step_key(account: &str) -> String {
+    format!("{}{}", STEP_PREFIX, account)
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::fixtures::TestDb;
+
+    fn code_now(db: &Db, secret: &[u8]) -> String {
+        format!(
+            "{:06}",
+            totp::code_at(secret, db.clock().now_millis() / 1000)
+        )
+    }
+
+    #[test]
+    fn test_codes_work_once() -> Result<()> {
+        let db = TestDb::new("mfa_replay")?;
+        let (record, _) = enroll(&db, "root")?;
+        let confirming = code_now(&db, &record.secret);
+        assert!(confirm(&db, "root", &confirming)?);
+        // the confirming code cannot sign in, nor can a code used for a sign-in
+        assert!(!verify(&db, "root", &confirming)?);
+        db.clock.advance(Duration::from_secs(30));
+        let code = code_now(&db, &record.secret);
+        assert!(verify(&db, "root", &code)?);
+        assert!(!verify(&db, "root", &code)?);
+        db.clock.advance(Duration::from_secs(30));
+        assert!(verify(&db, "root", &code_now(&db, &record.secret))?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reenrolling_keeps_the_old_secret_until_confirmed() -> Result<()> {
+        let db = TestDb::new("mfa_reenroll")?;
+        let (old, _) = enroll(&db, "root")?;
+        assert!(confirm(&db, "root", &code_now(&db, &old.secret))?);
+        let (new, _) = enroll(&db, "root")?;
+        assert!(is_enforced(&db, "root")?);
+        assert_eq!(
+            get(&db, "root")?.map(|record| record.secret),
+            Some(old.secret)
+        );
+        db.clock.advance(Duration::from_secs(30));
+        assert!(confirm(&db, "root", &code_now(&db, &new.secret))?);
+        assert_eq!(
+            get(&db, "root")?.map(|record| record.secret),
+            Some(new.secret)
+        );
+        Ok(())
+    }
+}
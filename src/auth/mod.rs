@@ -0,0 +1,77 @@
+pub mod csrf;
+pub mod mfa;
+pub mod passkey;
+pub mod password;
+pub mod policy;
+pub mod remember;
+pub mod session;
+pub mod throttle;
+pub mod totp;
+pub mod user;
+pub mod visitor;
+
+use std::net::{IpAddr, SocketAddr};
+
+use axum::{extract::ConnectInfo, http::Extensions};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+// the peer address of the connection, `None` when served without connect info (e.g. in tests)
+pub fn client_ip(extensions: &Extensions) -> Option<IpAddr> {
+    extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip())
+}
+
+// compare secrets without leaking where they differ through timing
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// === Signed Values ===
+// `{value}.{signature}`, for cookies the client must not be able to forge. The `purpose` is
+// signed along with the value, so a value signed for one use (say a download link) is not
+// accepted for another (say a session).
+pub fn sign(key: &[u8], purpose: &str, value: &str) -> String {
+    format!("{}.{}", value, hex::encode(signature(key, purpose, value)))
+}
+// the value of a string produced by `sign` for `purpose`, `None` when it was tampered with
+pub fn verify_signed<'a>(key: &[u8], purpose: &str, signed: &'a str) -> Option<&'a str> {
+    let (value, given) = signed.rsplit_once('.')?;
+    let given = hex::decode(given).ok()?;
+    constant_time_eq(&given, &signature(key, purpose, value)).then_some(value)
+}
+fn signature(key: &[u8], purpose: &str, value: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac accepts keys of any length");
+    // purposes are fixed names without a `:`, so the two parts cannot run into each other
+    mac.update(purpose.as_bytes());
+    mac.update(b":");
+    mac.update(value.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signed_values() {
+        let signed = sign(b"key", "admin_session", "admin:1700000000");
+        assert_eq!(
+            verify_signed(b"key", "admin_session", &signed),
+            Some("admin:1700000000")
+        );
+        assert_eq!(verify_signed(b"other key", "admin_session", &signed), None);
+        let forged = signed.replace("admin:1700000000", "admin:1900000000");
+        assert_eq!(verify_signed(b"key", "admin_session", &forged), None);
+    }
+
+    #[test]
+    fn test_signatures_are_bound_to_their_purpose() {
+        let signed = sign(b"key", "attachment", "admin:1700000000");
+        assert_eq!(verify_signed(b"key", "admin_session", &signed), None);
+        assert_eq!(verify_signed(b"key", "user_session", &signed), None);
+        assert!(verify_signed(b"key", "attachment", &signed).is_some());
+    }
+}
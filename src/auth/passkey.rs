@@ -0,0 +1,128 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::{de::DeserializeOwned, Serialize};
+use webauthn_rs::prelude::{
+    CreationChallengeResponse, Passkey, PasskeyAuthentication, PasskeyRegistration,
+    PublicKeyCredential, RegisterPublicKeyCredential, RequestChallengeResponse, Url, Uuid,
+    Webauthn, WebauthnBuilder,
+};
+
+use crate::{config::Config, db::driver::Db};
+
+// registered passkeys per account, `passkey:{account}`
+const PASSKEY_PREFIX: &str = "passkey:";
+// ceremony state between the start and finish requests
+const REGISTRATION_PREFIX: &str = "passkey_registration:";
+const AUTHENTICATION_PREFIX: &str = "passkey_authentication:";
+const CEREMONY_TTL: Duration = Duration::from_secs(5 * 60);
+const RP_NAME: &str = "Magical To-Do";
+
+// the relying party is the configured public url of the instance
+pub fn webauthn(config: &Config) -> Result<Webauthn> {
+    let origin = Url::parse(&config.public_url).context("PUBLIC_URL is not a valid url")?;
+    let rp_id = origin
+        .host_str()
+        .context("PUBLIC_URL has no host")?
+        .to_string();
+    Ok(WebauthnBuilder::new(&rp_id, &origin)?
+        .rp_name(RP_NAME)
+        .build()?)
+}
+
+// webauthn types rely on self-describing formats, store them as json inside the codec
+fn get_json<T: DeserializeOwned>(db: &Db, key: &str) -> Result<Option<T>> {
+    db.get::<String, _>(key)?
+        .map(|json| serde_json::from_str(&json).map_err(Into::into))
+        .transpose()
+}
+fn to_json<T: Serialize>(value: &T) -> Result<String> {
+    Ok(serde_json::to_string(value)?)
+}
+
+pub fn passkeys(db: &Db, account: &str) -> Result<Vec<Passkey>> {
+    Ok(get_json(db, &format!("{}{}", PASSKEY_PREFIX, account))?.unwrap_or_default())
+}
+fn save_passkeys(db: &Db, account: &str, passkeys: &[Passkey]) -> Result<()> {
+    db.insert(
+        format!("{}{}", PASSKEY_PREFIX, account),
+        &to_json(&passkeys)?,
+    )
+}
+
+// === Registration ===
+pub fn start_registration(
+    webauthn: &Webauthn,
+    db: &Db,
+    account: &str,
+) -> Result<CreationChallengeResponse> {
+    let existing = passkeys(db, account)?
+        .iter()
+        .map(|passkey| passkey.cred_id().clone())
+        .collect::<Vec<_>>();
+    let user_id = Uuid::new_v5(&Uuid::NAMESPACE_OID, account.as_bytes());
+    let (challenge, registration) =
+        webauthn.start_passkey_registration(user_id, account, account, Some(existing))?;
+    db.insert_with_ttl(
+        format!("{}{}", REGISTRATION_PREFIX, account),
+        &to_json(&registration)?,
+        CEREMONY_TTL,
+    )?;
+    Ok(challenge)
+}
+pub fn finish_registration(
+    webauthn: &Webauthn,
+    db: &Db,
+    account: &str,
+    credential: &RegisterPublicKeyCredential,
+) -> Result<()> {
+    let key = format!("{}{}", REGISTRATION_PREFIX, account);
+    let registration: PasskeyRegistration =
+        get_json(db, &key)?.context("passkey registration expired, please start again")?;
+    db.remove(&key)?;
+    let passkey = webauthn.finish_passkey_registration(credential, &registration)?;
+    let mut passkeys = passkeys(db, account)?;
+    passkeys.push(passkey);
+    save_passkeys(db, account, &passkeys)
+}
+
+// === Authentication ===
+// Returns the ceremony id the client has to send back along with the challenge response.
+pub fn start_authentication(
+    webauthn: &Webauthn,
+    db: &Db,
+    account: &str,
+) -> Result<Option<(String, RequestChallengeResponse)>> {
+    let passkeys = passkeys(db, account)?;
+    if passkeys.is_empty() {
+        return Ok(None);
+    }
+    let (challenge, authentication) = webauthn.start_passkey_authentication(&passkeys)?;
+    let ceremony = Uuid::new_v4().to_string();
+    db.insert_with_ttl(
+        format!("{}{}", AUTHENTICATION_PREFIX, ceremony),
+        &to_json(&(account, authentication))?,
+        CEREMONY_TTL,
+    )?;
+    Ok(Some((ceremony, challenge)))
+}
+// Returns the account that signed in.
+pub fn finish_authentication(
+    webauthn: &Webauthn,
+    db: &Db,
+    ceremony: &str,
+    credential: &PublicKeyCredential,
+) -> Result<String> {
+    let key = format!("{}{}", AUTHENTICATION_PREFIX, ceremony);
+    let (account, authentication): (String, PasskeyAuthentication) =
+        get_json(db, &key)?.context("passkey sign-in expired, please start again")?;
+    db.remove(&key)?;
+    let result = webauthn.finish_passkey_authentication(credential, &authentication)?;
+    // keep the signature counters current so cloned authenticators are detected
+    let mut passkeys = passkeys(db, &account)?;
+    for passkey in passkeys.iter_mut() {
+        passkey.update_credential(&result);
+    }
+    save_passkeys(db, &account, &passkeys)?;
+    Ok(account)
+}
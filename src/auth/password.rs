@@ -0,0 +1,68 @@
+use anyhow::{anyhow, Result};
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::db::driver::Db;
+
+// the admin account created at `/setup`, used when `ADMIN_PASSWORD` is not set
+const ADMIN_KEY: &str = "admin_credentials";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdminCredentials {
+    pub username: String,
+    // argon2 in PHC string format
+    pub password_hash: String,
+}
+impl AdminCredentials {
+    pub fn new(username: &str, password: &str) -> Result<Self> {
+        Ok(Self {
+            username: username.to_string(),
+            password_hash: hash(password)?,
+        })
+    }
+    pub fn check(&self, username: &str, password: &str) -> bool {
+        username == self.username && verify(&self.password_hash, password)
+    }
+}
+
+pub fn admin(db: &Db) -> Result<Option<AdminCredentials>> {
+    db.get(ADMIN_KEY)
+}
+pub fn set_admin(db: &Db, credentials: &AdminCredentials) -> Result<()> {
+    db.insert(ADMIN_KEY, credentials)
+}
+
+pub fn hash(password: &str) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|err| anyhow!("failed to hash password: {}", err))?;
+    Ok(hash.to_string())
+}
+pub fn verify(hash: &str, password: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|hash| {
+        Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok()
+    })
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_admin_credentials() -> Result<()> {
+        let credentials = AdminCredentials::new("admin", "correct horse battery")?;
+        assert!(credentials.check("admin", "correct horse battery"));
+        assert!(!credentials.check("admin", "correct horse"));
+        assert!(!credentials.check("root", "correct horse battery"));
+        // salted, the same password hashes differently every time
+        assert_ne!(hash("secret")?, hash("secret")?);
+        Ok(())
+    }
+}
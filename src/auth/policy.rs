@@ -0,0 +1,296 @@
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Request, State},
+    http::{request::Parts, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::user::CurrentUser,
+    error::{AppError, ErrorReport},
+    kiosk,
+    models::Todo,
+    routes,
+    state::AppState,
+    tenant::Tenant,
+};
+
+// === Areas ===
+// Which rules a path falls under. Only the workspace goes through the policy, the admin area
+// checks its own credentials and shared links carry their own token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Area {
+    // has to work before anyone can sign in
+    Public,
+    Admin,
+    // kiosks, embeds and calendar feeds
+    Shared,
+    Workspace,
+}
+
+pub fn area(path: &str) -> Area {
+    if path.starts_with("/admin") {
+        return Area::Admin;
+    }
+    if path.starts_with("/kiosk/") || path.starts_with("/embed/") || path.starts_with("/feeds/") {
+        return Area::Shared;
+    }
+    let is_public = path.starts_with(&routes::Setup::url())
+        || path.starts_with(&routes::Signup::url())
+        || path.starts_with("/account/")
+        || path.starts_with("/assets/")
+        // signed by the sender instead, see `inbound`
+        || path.starts_with("/hooks/")
+        || path == routes::ThemeCss::url()
+        // fetched by the browser without cookies
+        || path == routes::Manifest::url()
+        || path == routes::AppIcon::url()
+        || path == routes::MaskableIcon::url();
+    if is_public {
+        Area::Public
+    } else {
+        Area::Workspace
+    }
+}
+
+// === Roles ===
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Role {
+    // single-user mode
+    Owner,
+    // signed in with an account
+    Member(String),
+    // a visitor in their own guest workspace
+    Guest,
+    // holds a kiosk or embed link, allowed what the link allows
+    Viewer(Capability),
+}
+impl Role {
+    pub fn of(user: Option<&CurrentUser>, tenant: &Tenant) -> Self {
+        match user {
+            _ if tenant.is_guest() => Role::Guest,
+            Some(CurrentUser::Owner) => Role::Owner,
+            Some(CurrentUser::Account(account)) => Role::Member(account.clone()),
+            None => Role::Viewer(Capability::View),
+        }
+    }
+}
+
+// What a shared link allows besides looking: ticking items off, e.g. a shopping list for a
+// family member, or adding them, a suggestion box.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Capability {
+    #[default]
+    View,
+    Complete,
+    Add,
+}
+impl Capability {
+    pub const ALL: [Capability; 3] = [Capability::View, Capability::Complete, Capability::Add];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Capability::View => "view",
+            Capability::Complete => "complete",
+            Capability::Add => "add",
+        }
+    }
+    pub fn label(self) -> &'static str {
+        match self {
+            Capability::View => "View only",
+            Capability::Complete => "Can tick off",
+            Capability::Add => "Can add",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    View,
+    Edit,
+    // the edits a shared link may be allowed on its own
+    Complete,
+    Add,
+}
+impl Action {
+    // reads only look, everything else changes something
+    pub fn of(method: &Method) -> Self {
+        match *method {
+            Method::GET | Method::HEAD | Method::OPTIONS => Action::View,
+            _ => Action::Edit,
+        }
+    }
+    // under a shared link, ticking an item off and adding one are told apart from other edits
+    pub fn of_shared(method: &Method, path: &str) -> Self {
+        match Action::of(method) {
+            Action::Edit if path.ends_with("/toggle") => Action::Complete,
+            Action::Edit if path.ends_with("/todos") => Action::Add,
+            action => action,
+        }
+    }
+}
+
+// === Policy ===
+// The single place deciding who may do what. Everyone working in a workspace may change it, a
+// shared link shows it and does at most the one thing it was made for.
+pub fn can(role: &Role, action: Action) -> bool {
+    match (role, action) {
+        (Role::Owner | Role::Member(_) | Role::Guest, _) => true,
+        (Role::Viewer(_), Action::View) => true,
+        (Role::Viewer(Capability::Complete), Action::Complete) => true,
+        (Role::Viewer(Capability::Add), Action::Add) => true,
+        (Role::Viewer(_), _) => false,
+    }
+}
+
+pub fn can_view(role: &Role, _todo: &Todo) -> bool {
+    can(role, Action::View)
+}
+
+pub fn can_edit(role: &Role, todo: &Todo) -> bool {
+    can_view(role, todo) && can(role, Action::Edit)
+}
+
+fn forbidden() -> Response {
+    ErrorReport::new(
+        StatusCode::FORBIDDEN,
+        "You don't have permission to do that.",
+    )
+    .into_response()
+}
+
+// The role of the request for handlers that check a single todo, resolved once by `enforce`.
+#[async_trait]
+impl FromRequestParts<AppState> for Role {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        if let Some(role) = parts.extensions.get::<Role>() {
+            return Ok(role.clone());
+        }
+        let tenant = Tenant::from_request_parts(parts, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        Ok(Role::of(parts.extensions.get::<CurrentUser>(), &tenant))
+    }
+}
+
+// Check every workspace and shared request against the policy, after `user::require` resolved
+// who is asking.
+pub async fn enforce(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let path = request.uri().path();
+    let area = area(path);
+    if matches!(area, Area::Public | Area::Admin) {
+        return next.run(request).await;
+    }
+    let (action, shared) = match area {
+        Area::Shared => {
+            // the link speaks for whoever holds it, signed in or not, and a kiosk link allows
+            // what it was created with
+            let capability = match kiosk::token_of(path) {
+                Some(token) => match kiosk::capability(&*state.read().await, token) {
+                    Ok(capability) => capability,
+                    Err(err) => return AppError::from(err).into_response(),
+                },
+                None => Capability::View,
+            };
+            let action = Action::of_shared(request.method(), path);
+            (action, Some(Role::Viewer(capability)))
+        }
+        _ => (Action::of(request.method()), None),
+    };
+    let (mut parts, body) = request.into_parts();
+    let role = match shared {
+        Some(role) => role,
+        None => match Role::from_request_parts(&mut parts, &state).await {
+            Ok(role) => role,
+            Err(rejection) => return rejection,
+        },
+    };
+    if !can(&role, action) {
+        return forbidden();
+    }
+    parts.extensions.insert(role);
+    next.run(Request::from_parts(parts, body)).await
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_role_action_matrix() {
+        let matrix = [
+            (Role::Owner, [true, true]),
+            (Role::Member("ada".into()), [true, true]),
+            (Role::Guest, [true, true]),
+            (Role::Viewer(Capability::View), [true, false]),
+        ];
+        let todo = Todo::new(1, "Ship".into());
+        for (role, [view, edit]) in matrix {
+            assert_eq!(can(&role, Action::View), view, "{:?} view", role);
+            assert_eq!(can(&role, Action::Edit), edit, "{:?} edit", role);
+            assert_eq!(can_view(&role, &todo), view, "{:?} view todo", role);
+            assert_eq!(can_edit(&role, &todo), edit, "{:?} edit todo", role);
+        }
+    }
+
+    #[test]
+    fn test_shared_capabilities() {
+        let post = Method::POST;
+        let toggle = Action::of_shared(&post, "/kiosk/abc/todos/4/toggle");
+        let add = Action::of_shared(&post, "/kiosk/abc/todos");
+        assert_eq!((toggle, add), (Action::Complete, Action::Add));
+        assert_eq!(
+            Action::of_shared(&Method::GET, "/kiosk/abc/todos"),
+            Action::View
+        );
+        for (capability, [complete, add, edit]) in [
+            (Capability::View, [false, false, false]),
+            (Capability::Complete, [true, false, false]),
+            (Capability::Add, [false, true, false]),
+        ] {
+            let role = Role::Viewer(capability);
+            assert!(can(&role, Action::View));
+            assert_eq!(can(&role, Action::Complete), complete, "{:?}", capability);
+            assert_eq!(can(&role, Action::Add), add, "{:?}", capability);
+            assert_eq!(can(&role, Action::Edit), edit, "{:?}", capability);
+        }
+    }
+
+    #[test]
+    fn test_roles_and_actions_of_requests() {
+        let owner = CurrentUser::Owner;
+        let ada = CurrentUser::Account("ada".into());
+        assert_eq!(Role::of(Some(&owner), &Tenant(None)), Role::Owner);
+        assert_eq!(
+            Role::of(Some(&ada), &Tenant(None)),
+            Role::Member("ada".into())
+        );
+        assert_eq!(
+            Role::of(None, &Tenant(None)),
+            Role::Viewer(Capability::View)
+        );
+        assert_eq!(Action::of(&Method::HEAD), Action::View);
+        assert_eq!(Action::of(&Method::DELETE), Action::Edit);
+    }
+
+    #[test]
+    fn test_areas() {
+        assert_eq!(area("/admin/login"), Area::Admin);
+        assert_eq!(area("/kiosk/abc/today"), Area::Shared);
+        assert_eq!(area("/feeds/abc/calendar.ics"), Area::Shared);
+        assert_eq!(area("/assets/app.js"), Area::Public);
+        assert_eq!(area(&routes::InboundTodos::url()), Area::Public);
+        assert_eq!(area("/setup/instance"), Area::Public);
+        assert_eq!(area(&routes::AccountLogin::url()), Area::Public);
+        assert_eq!(area("/todos/1"), Area::Workspace);
+    }
+}
@@ -0,0 +1,125 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::db::driver::Db;
+
+// remembered devices per account, `remember:{account}:{token hash}`
+const REMEMBER_PREFIX: &str = "remember:";
+pub const REMEMBER_LIFETIME: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+const LABEL_LEN: usize = 120;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Device {
+    // the token hash, safe to show since the token itself is never stored
+    pub id: String,
+    pub label: String,
+    pub created_at: u64,
+    pub last_used_at: u64,
+    pub expires_at: u64,
+}
+
+// Remember a new device. Returns the cookie value, `{account}:{token}`.
+pub fn issue(db: &Db, account: &str, label: &str) -> Result<String> {
+    let now = db.clock().now_millis() / 1000;
+    let device = Device {
+        id: String::new(),
+        label: label.chars().take(LABEL_LEN).collect(),
+        created_at: now,
+        last_used_at: now,
+        expires_at: 0,
+    };
+    save(db, account, device)
+}
+
+// Trade a remember cookie for a fresh one, the old token stops working.
+// Returns the account and the new cookie value, `None` for unknown or expired tokens.
+pub fn rotate(db: &Db, cookie: &str) -> Result<Option<(String, String)>> {
+    let Some((account, token)) = cookie.rsplit_once(':') else {
+        return Ok(None);
+    };
+    let key = key(account, &hash(token));
+    let Some(device) = db.get::<Device, _>(&key)? else {
+        return Ok(None);
+    };
+    db.remove(&key)?;
+    let now = db.clock().now_millis() / 1000;
+    if device.expires_at <= now {
+        return Ok(None);
+    }
+    let cookie = save(
+        db,
+        account,
+        Device {
+            last_used_at: now,
+            ..device
+        },
+    )?;
+    Ok(Some((account.to_string(), cookie)))
+}
+
+pub fn devices(db: &Db, account: &str) -> Result<Vec<Device>> {
+    let mut devices = db
+        .iter_prefix::<Device>(&key(account, ""))?
+        .map(|item| item.map(|(_, device)| device))
+        .collect::<Result<Vec<_>>>()?;
+    devices.sort_by_key(|device| std::cmp::Reverse(device.last_used_at));
+    Ok(devices)
+}
+pub fn revoke(db: &Db, account: &str, id: &str) -> Result<()> {
+    db.remove(key(account, id))
+}
+// the device of a remember cookie, on sign out
+pub fn forget(db: &Db, cookie: &str) -> Result<()> {
+    match cookie.rsplit_once(':') {
+        Some((account, token)) => revoke(db, account, &hash(token)),
+        None => Ok(()),
+    }
+}
+
+// store `device` under a new token, every rotation extends the lifetime
+fn save(db: &Db, account: &str, device: Device) -> Result<String> {
+    let mut token = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut token);
+    let token = hex::encode(token);
+    let device = Device {
+        id: hash(&token),
+        expires_at: db.clock().now_millis() / 1000 + REMEMBER_LIFETIME.as_secs(),
+        ..device
+    };
+    db.insert_with_ttl(key(account, &device.id), &device, REMEMBER_LIFETIME)?;
+    Ok(format!("{}:{}", account, token))
+}
+
+fn hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+fn key(account: &str, id: &str) -> String {
+    format!("{}{}:{}", REMEMBER_PREFIX, account, id)
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::TestDb;
+
+    #[test]
+    fn test_rotation() -> Result<()> {
+        let db = TestDb::new("remember")?;
+        let first = issue(&db, "admin", "Firefox")?;
+        let (account, second) = rotate(&db, &first)?.expect("a fresh token rotates");
+        assert_eq!(account, "admin");
+        assert!(rotate(&db, &first)?.is_none());
+        let devices = devices(&db, "admin")?;
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].label, "Firefox");
+
+        revoke(&db, "admin", &devices[0].id)?;
+        assert!(rotate(&db, &second)?.is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use crate::{auth, db::driver::Db};
+
+// signed-in sessions, `session:{cookie}:{id}`
+const SESSION_PREFIX: &str = "session:";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Session {
+    account: String,
+}
+
+// === Sessions ===
+// A session is kept in the database for its lifetime and the ttl sweeper removes it after, the
+// cookie only carries its signed id. Sessions are kept per cookie, the id of one kind is never
+// looked up as another. Signing out ends the session, a copy of the cookie stops working too.
+
+// Start a session of `account`. Returns the value of the `cookie` it is signed in with.
+pub fn start(
+    db: &Db,
+    key: &[u8],
+    cookie: &str,
+    account: &str,
+    lifetime: Duration,
+) -> Result<String> {
+    let mut id = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut id);
+    let id = hex::encode(id);
+    let session = Session {
+        account: account.to_string(),
+    };
+    db.insert_with_ttl(session_key(cookie, &id), &session, lifetime)?;
+    Ok(auth::sign(key, cookie, &id))
+}
+
+// the account signed in with the `cookie` holding `value`, `None` once the session ended
+pub fn account(db: &Db, key: &[u8], cookie: &str, value: Option<&str>) -> Result<Option<String>> {
+    let Some(id) = value.and_then(|value| auth::verify_signed(key, cookie, value)) else {
+        return Ok(None);
+    };
+    Ok(db
+        .get::<Session, _>(session_key(cookie, id))?
+        .map(|session| session.account))
+}
+
+pub fn end(db: &Db, key: &[u8], cookie: &str, value: &str) -> Result<()> {
+    match auth::verify_signed(key, cookie, value) {
+        Some(id) => db.remove(session_key(cookie, id)),
+        None => Ok(()),
+    }
+}
+
+fn session_key(cookie: &str, id: &str) -> String {
+    format!("{}{}:{}", SESSION_PREFIX, cookie, id)
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::TestDb;
+
+    #[test]
+    fn test_sessions_end_and_expire() -> Result<()> {
+        let db = TestDb::new("sessions")?;
+        let hour = Duration::from_secs(60 * 60);
+        let value = start(&db, b"key", "user_session", "ada", hour)?;
+        assert_eq!(
+            account(&db, b"key", "user_session", Some(&value))?,
+            Some("ada".to_string())
+        );
+        // another cookie, another key, nothing at all
+        assert_eq!(account(&db, b"key", "admin_session", Some(&value))?, None);
+        assert_eq!(account(&db, b"other", "user_session", Some(&value))?, None);
+        assert_eq!(account(&db, b"key", "user_session", None)?, None);
+
+        end(&db, b"key", "user_session", &value)?;
+        assert_eq!(account(&db, b"key", "user_session", Some(&value))?, None);
+
+        let value = start(&db, b"key", "user_session", "ada", hour)?;
+        db.clock.advance(hour);
+        assert_eq!(account(&db, b"key", "user_session", Some(&value))?, None);
+        db.sweep_expired(db.clock().now_millis())?;
+        assert!(db.iter_prefix::<Session>(SESSION_PREFIX)?.next().is_none());
+        Ok(())
+    }
+}
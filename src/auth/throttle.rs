@@ -0,0 +1,139 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{db::driver::Db, error::AppError};
+
+// failures are tracked per subject, `login_failure:{subject}`
+const FAILURE_PREFIX: &str = "login_failure:";
+// failures before any backoff kicks in
+const FREE_ATTEMPTS: u32 = 3;
+// failures are forgotten after this long without a new one
+const FAILURE_WINDOW: Duration = Duration::from_secs(15 * 60);
+const MAX_BACKOFF_SECS: u64 = 15 * 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Failures {
+    count: u32,
+    last_at: u64,
+}
+
+// === Login Throttle ===
+// Failed logins back off exponentially per subject (an account name or a client ip), and with
+// `lockout_after` set a subject is locked for the whole failure window once it reaches that many.
+#[derive(Debug, Clone, Copy)]
+pub struct LoginThrottle {
+    pub lockout_after: Option<u32>,
+}
+impl LoginThrottle {
+    // Refuse the attempt while any subject is backing off or locked out. Returns whether there
+    // are failures on record, so callers know to `reset` after a success.
+    pub fn check(&self, db: &Db, subjects: &[String]) -> Result<bool, AppError> {
+        let now = db.clock().now_millis();
+        let mut any = false;
+        for subject in subjects {
+            let Some(failures) = db.get::<Failures, _>(key(subject))? else {
+                continue;
+            };
+            any = true;
+            let wait = self.wait_secs(&failures);
+            let ready_at = failures.last_at + wait * 1000;
+            if now < ready_at {
+                return Err(AppError::TooManyRequests {
+                    retry_after: (ready_at - now).div_ceil(1000),
+                });
+            }
+        }
+        Ok(any)
+    }
+
+    pub fn record_failure(&self, db: &Db, subjects: &[String]) -> Result<()> {
+        let now = db.clock().now_millis();
+        for subject in subjects {
+            let key = key(subject);
+            let count = db
+                .get::<Failures, _>(&key)?
+                .map_or(0, |failures| failures.count);
+            let failures = Failures {
+                count: count + 1,
+                last_at: now,
+            };
+            tracing::warn!(subject = %subject, failures = failures.count, "failed login");
+            db.insert_with_ttl(&key, &failures, FAILURE_WINDOW)?;
+        }
+        Ok(())
+    }
+
+    pub fn reset(&self, db: &Db, subjects: &[String]) -> Result<()> {
+        db.remove_all(subjects.iter().map(|subject| key(subject)))
+    }
+
+    fn wait_secs(&self, failures: &Failures) -> u64 {
+        if let Some(lockout_after) = self.lockout_after {
+            if failures.count >= lockout_after {
+                return FAILURE_WINDOW.as_secs();
+            }
+        }
+        if failures.count < FREE_ATTEMPTS {
+            return 0;
+        }
+        let exponent = (failures.count - FREE_ATTEMPTS).min(16);
+        (1u64 << exponent).min(MAX_BACKOFF_SECS)
+    }
+}
+
+fn key(subject: &str) -> String {
+    format!("{}{}", FAILURE_PREFIX, subject)
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::TestDb;
+
+    fn failures(count: u32) -> Failures {
+        Failures { count, last_at: 0 }
+    }
+
+    #[test]
+    fn test_backoff_grows_exponentially() {
+        let throttle = LoginThrottle {
+            lockout_after: None,
+        };
+        assert_eq!(throttle.wait_secs(&failures(1)), 0);
+        assert_eq!(throttle.wait_secs(&failures(3)), 1);
+        assert_eq!(throttle.wait_secs(&failures(4)), 2);
+        assert_eq!(throttle.wait_secs(&failures(8)), 32);
+        assert_eq!(throttle.wait_secs(&failures(40)), MAX_BACKOFF_SECS);
+    }
+
+    #[test]
+    fn test_lockout() {
+        let throttle = LoginThrottle {
+            lockout_after: Some(5),
+        };
+        assert_eq!(throttle.wait_secs(&failures(4)), 2);
+        assert_eq!(throttle.wait_secs(&failures(5)), FAILURE_WINDOW.as_secs());
+    }
+
+    #[test]
+    fn test_backoff_runs_on_the_db_clock() -> Result<()> {
+        let db = TestDb::new("throttle_clock")?;
+        let throttle = LoginThrottle {
+            lockout_after: None,
+        };
+        let subjects = ["ip:127.0.0.1".to_string()];
+        for _ in 0..4 {
+            throttle.record_failure(&db, &subjects)?;
+        }
+        assert!(matches!(
+            throttle.check(&db, &subjects),
+            Err(AppError::TooManyRequests { retry_after: 2 })
+        ));
+        db.clock.advance(Duration::from_secs(2));
+        assert!(matches!(throttle.check(&db, &subjects), Ok(true)));
+        Ok(())
+    }
+}
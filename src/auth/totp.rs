@@ -0,0 +1,129 @@
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use rand::{distributions::Alphanumeric, Rng, RngCore};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+// RFC 6238 defaults, what every authenticator app expects
+const STEP_SECS: u64 = 30;
+const DIGITS: u32 = 6;
+const SECRET_LEN: usize = 20;
+const RECOVERY_CODE_LEN: usize = 10;
+
+pub fn generate_secret() -> Vec<u8> {
+    let mut secret = vec![0; SECRET_LEN];
+    rand::thread_rng().fill_bytes(&mut secret);
+    secret
+}
+
+// `otpauth://` uri for authenticator apps, usually shown as a qr code
+pub fn provisioning_uri(secret: &[u8], account: &str, issuer: &str) -> String {
+    let label = format!("{}:{}", issuer, account);
+    format!(
+        "otpauth://totp/{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        urlencoding::encode(&label),
+        BASE32_NOPAD.encode(secret),
+        urlencoding::encode(issuer),
+        DIGITS,
+        STEP_SECS
+    )
+}
+
+pub fn code_at(secret: &[u8], unix_secs: u64) -> u32 {
+    let counter = unix_secs / STEP_SECS;
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("hmac accepts keys of any length");
+    mac.update(&counter.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+    // dynamic truncation, RFC 4226 section 5.3
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+    binary % 10u32.pow(DIGITS)
+}
+
+// accept the current code and the ones right before and after it to allow for clock drift
+pub fn verify(secret: &[u8], code: &str, unix_secs: u64) -> bool {
+    matching_step(secret, code, unix_secs).is_some()
+}
+
+// The time step `code` belongs to, so a code that was used once can be refused after. Checks
+// the same window as `verify`.
+pub fn matching_step(secret: &[u8], code: &str, unix_secs: u64) -> Option<u64> {
+    let code = code.trim();
+    if code.len() != DIGITS as usize {
+        return None;
+    }
+    let code = code.parse::<u32>().ok()?;
+    [
+        unix_secs.saturating_sub(STEP_SECS),
+        unix_secs,
+        unix_secs + STEP_SECS,
+    ]
+    .iter()
+    .fold(None, |matched, at| {
+        let hit = code_at(secret, *at) == code;
+        matched.or(hit.then_some(at / STEP_SECS))
+    })
+}
+
+// === Recovery Codes ===
+pub fn generate_recovery_codes(count: usize) -> Vec<String> {
+    (0..count)
+        .map(|_| {
+            rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(RECOVERY_CODE_LEN)
+                .map(|c| char::from(c).to_ascii_lowercase())
+                .collect()
+        })
+        .collect()
+}
+
+// only hashes are stored, the codes themselves are shown once
+pub fn hash_recovery_code(code: &str) -> String {
+    hex::encode(Sha256::digest(code.trim().to_ascii_lowercase().as_bytes()))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 6238 appendix B, truncated to six digits
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn test_rfc_vectors() {
+        assert_eq!(code_at(RFC_SECRET, 59), 287082);
+        assert_eq!(code_at(RFC_SECRET, 1111111109), 81804);
+        assert_eq!(code_at(RFC_SECRET, 1234567890), 5924);
+        assert_eq!(code_at(RFC_SECRET, 2000000000), 279037);
+    }
+
+    #[test]
+    fn test_verify_allows_one_step_of_drift() {
+        assert!(verify(RFC_SECRET, "081804", 1111111109));
+        assert!(verify(RFC_SECRET, "081804", 1111111109 + 30));
+        assert!(!verify(RFC_SECRET, "081804", 1111111109 + 90));
+        assert!(!verify(RFC_SECRET, "81804", 1111111109));
+        assert!(!verify(RFC_SECRET, "abcdef", 1111111109));
+        assert_eq!(
+            matching_step(RFC_SECRET, "081804", 1111111109 + 30),
+            Some(1111111109 / 30)
+        );
+    }
+
+    #[test]
+    fn test_recovery_code_hash_ignores_case() {
+        let code = &generate_recovery_codes(1)[0];
+        assert_eq!(code.len(), RECOVERY_CODE_LEN);
+        assert_eq!(
+            hash_recovery_code(code),
+            hash_recovery_code(&code.to_ascii_uppercase())
+        );
+    }
+}
@@ -0,0 +1,113 @@
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Request, State},
+    http::{request::Parts, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Redirect, Response},
+};
+use axum_extra::extract::cookie::CookieJar;
+
+use crate::{
+    accounts,
+    admin::passkeys,
+    auth::policy::{self, Area},
+    config::AuthMode,
+    error::{AppError, ErrorReport},
+    routes,
+    state::AppState,
+    telemetry,
+    tenant::Tenant,
+};
+
+// === Current User ===
+// Who is using the todo pages. In single-user mode that is the owner, whoever holds the signed
+// visitor cookie, and there is nothing to sign in to. With passkey accounts it is the account of
+// the passkey session, with password accounts the one of the `user_session` cookie.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CurrentUser {
+    Owner,
+    Account(String),
+}
+impl CurrentUser {
+    pub fn account(&self) -> Option<&str> {
+        match self {
+            CurrentUser::Owner => None,
+            CurrentUser::Account(account) => Some(account),
+        }
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for CurrentUser {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        if let Some(user) = parts.extensions.get::<CurrentUser>() {
+            return Ok(user.clone());
+        }
+        if state.config.auth_mode == AuthMode::SingleUser {
+            return Ok(CurrentUser::Owner);
+        }
+        let jar = CookieJar::from_headers(&parts.headers);
+        // password accounts sign in on their own page, passkey accounts on the admin one
+        let (cookie, login) = match state.config.auth_mode {
+            AuthMode::Users => (accounts::SESSION_COOKIE, routes::AccountLogin::url()),
+            _ => (passkeys::SESSION_COOKIE, routes::Login::url()),
+        };
+        let session = jar.get(cookie).map(|cookie| cookie.value());
+        let db = state.read().await;
+        let account = match state.config.auth_mode {
+            AuthMode::Users => accounts::session_user(&state.secret_key, &db, session),
+            _ => passkeys::session_account(&state.secret_key, &db, session),
+        }
+        .map_err(|err| AppError::from(err).into_response())?;
+        drop(db);
+        match account {
+            Some(account) => Ok(CurrentUser::Account(account)),
+            None if parts.method == Method::GET => Err(Redirect::to(&login).into_response()),
+            None => Err(ErrorReport::new(
+                StatusCode::UNAUTHORIZED,
+                format!("Sign in at {} to continue.", login),
+            )
+            .into_response()),
+        }
+    }
+}
+
+// Resolve the `CurrentUser` of every request outside `/admin`, which checks its own
+// credentials, and the pages that have to work before anyone can sign in.
+pub async fn require(State(mut state): State<AppState>, request: Request, next: Next) -> Response {
+    if policy::area(request.uri().path()) != Area::Workspace {
+        return next.run(request).await;
+    }
+    let (mut parts, body) = request.into_parts();
+    let user = match CurrentUser::from_request_parts(&mut parts, &state).await {
+        Ok(user) => user,
+        // password accounts have no remembered devices
+        Err(rejection) if state.config.auth_mode == AuthMode::Users => return rejection,
+        Err(rejection) => {
+            // an expired passkey session is renewed from a remembered device
+            let jar = CookieJar::from_headers(&parts.headers);
+            return match passkeys::renew(&mut state, jar).await {
+                Ok(Some((account, jar))) => {
+                    identified(&mut parts, &state, CurrentUser::Account(account)).await;
+                    (jar, next.run(Request::from_parts(parts, body)).await).into_response()
+                }
+                Ok(None) => rejection,
+                Err(err) => err.into_response(),
+            };
+        }
+    };
+    identified(&mut parts, &state, user).await;
+    next.run(Request::from_parts(parts, body)).await
+}
+
+// Hand `user` to the handlers and put it, and the workspace it resolves to, on the request span.
+async fn identified(parts: &mut Parts, state: &AppState, user: CurrentUser) {
+    parts.extensions.insert(user.clone());
+    let tenant = Tenant::from_request_parts(parts, state).await.ok();
+    telemetry::identify(&user, tenant.as_ref().and_then(Tenant::id));
+}
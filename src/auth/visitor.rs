@@ -0,0 +1,67 @@
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+use time::Duration;
+
+use crate::{
+    auth::{self, csrf},
+    state::AppState,
+};
+
+const VISITOR_COOKIE: &str = "visitor";
+const VISITOR_LIFETIME_DAYS: i64 = 365;
+
+// An anonymous, stable id per browser. There are no user accounts, this is what per-person
+// data like reactions is keyed by.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Visitor(pub String);
+
+// the visitor of a signed cookie, for routes outside of `ensure`
+pub fn from_jar(key: &[u8], jar: &CookieJar) -> Option<Visitor> {
+    jar.get(VISITOR_COOKIE)
+        .and_then(|cookie| auth::verify_signed(key, VISITOR_COOKIE, cookie.value()))
+        .map(|id| Visitor(id.to_string()))
+}
+
+// Put the `Visitor` into the request extensions, handing out a signed cookie on the first visit.
+pub async fn ensure(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    mut request: Request,
+    next: Next,
+) -> (CookieJar, Response) {
+    let (id, jar) = match from_jar(&state.secret_key, &jar) {
+        Some(Visitor(id)) => (id, jar),
+        None => {
+            let id = uuid::Uuid::new_v4().to_string();
+            let cookie = Cookie::build((
+                VISITOR_COOKIE,
+                auth::sign(&state.secret_key, VISITOR_COOKIE, &id),
+            ))
+            .path("/")
+            .http_only(true)
+            .same_site(SameSite::Lax)
+            .max_age(Duration::days(VISITOR_LIFETIME_DAYS))
+            .build();
+            (id.clone(), jar.add(cookie))
+        }
+    };
+    // the page's script reads the token from here
+    let token = csrf::token(&state.secret_key, &id);
+    let jar = if jar.get(csrf::CSRF_COOKIE).map(|cookie| cookie.value()) == Some(token.as_str()) {
+        jar
+    } else {
+        jar.add(
+            Cookie::build((csrf::CSRF_COOKIE, token))
+                .path("/")
+                .same_site(SameSite::Strict)
+                .max_age(Duration::days(VISITOR_LIFETIME_DAYS))
+                .build(),
+        )
+    };
+    request.extensions_mut().insert(Visitor(id));
+    (jar, next.run(request).await)
+}
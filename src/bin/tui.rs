@@ -0,0 +1,333 @@
+// A terminal client for the todo list, built with `--features tui`.
+//
+//     tui [--url http://localhost:3000]   talk to a running server through the JSON API
+//     tui --local                         open the database directly, the server must be stopped
+//
+// j/k or the arrows move, space toggles, a adds (with the same `near:` quick-add syntax as the
+// web form), r reloads and q quits.
+
+use std::{io::Stdout, time::Duration};
+
+use anyhow::{anyhow, bail, Context, Result};
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEventKind},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
+};
+use reqwest::header;
+use rust_htmx::{
+    api::v1::TodoResource,
+    config::Config,
+    db::driver::Db,
+    models::{self, Location, Todo},
+    repository::{entity::Repository, query::TodoQuery},
+    routes, state,
+};
+use serde_json::json;
+
+// === Backends ===
+enum Backend {
+    Api {
+        client: reqwest::Client,
+        runtime: tokio::runtime::Runtime,
+        url: String,
+    },
+    Local(Db),
+}
+impl Backend {
+    fn from_args(args: &[String]) -> Result<Self> {
+        match args {
+            [] => Self::api("http://localhost:3000"),
+            [flag, url] if flag == "--url" => Self::api(url),
+            [flag] if flag == "--local" => {
+                let config = Config::from_env()?;
+                let db = state::open_db(&config)
+                    .context("could not open the database, is the server still running?")?;
+                Ok(Self::Local(db))
+            }
+            _ => bail!("usage: tui [--url <url> | --local]"),
+        }
+    }
+    fn api(url: &str) -> Result<Self> {
+        Ok(Self::Api {
+            client: reqwest::Client::new(),
+            runtime: tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()?,
+            url: url.trim_end_matches('/').to_string(),
+        })
+    }
+
+    // every open and done todo, in creation order
+    fn list(&self) -> Result<Vec<TodoResource>> {
+        match self {
+            Backend::Api {
+                client,
+                runtime,
+                url,
+            } => runtime.block_on(async {
+                let mut todos = Vec::new();
+                let mut next = Some(format!("{}{}?page_size=200", url, routes::ApiTodos::url()));
+                while let Some(page) = next {
+                    let response = client.get(&page).send().await?.error_for_status()?;
+                    next = response
+                        .headers()
+                        .get(header::LINK)
+                        .and_then(|link| link.to_str().ok())
+                        .and_then(next_link)
+                        .map(|path| format!("{}{}", url, path));
+                    todos.extend(response.json::<Vec<TodoResource>>().await?);
+                }
+                Ok(todos)
+            }),
+            Backend::Local(db) => Ok(TodoQuery::new()
+                .list(db)?
+                .iter()
+                .map(TodoResource::from)
+                .collect()),
+        }
+    }
+
+    fn toggle(&self, todo: &TodoResource) -> Result<()> {
+        match self {
+            Backend::Api {
+                client,
+                runtime,
+                url,
+            } => runtime.block_on(async {
+                // fails with 412 when the todo changed since it was listed
+                let response = client
+                    .patch(format!("{}{}", url, routes::ApiTodo::url(todo.id)))
+                    .header(
+                        header::IF_MATCH,
+                        format!("\"{}-{}\"", todo.id, todo.version),
+                    )
+                    .json(&json!({ "completed": !todo.completed }))
+                    .send()
+                    .await?;
+                if response.status() == reqwest::StatusCode::PRECONDITION_FAILED {
+                    bail!("\"{}\" changed in the meantime, reloaded", todo.title);
+                }
+                response.error_for_status()?;
+                Ok(())
+            }),
+            Backend::Local(db) => {
+                let todos = Repository::<Todo>::new(db);
+                let mut stored = todos
+                    .get(todo.id)?
+                    .ok_or_else(|| anyhow!("\"{}\" was removed", todo.title))?;
+                stored.set_completed(!stored.completed);
+                stored.touch(&**db.clock());
+                todos.put(&stored)
+            }
+        }
+    }
+
+    fn add(&self, input: &str) -> Result<()> {
+        match self {
+            // the server's own form handler, so `near:` is geocoded like from the browser
+            Backend::Api {
+                client,
+                runtime,
+                url,
+            } => runtime.block_on(async {
+                client
+                    .put(format!("{}{}", url, routes::CreateTodo::url()))
+                    .json(&json!({ "title": input }))
+                    .send()
+                    .await?
+                    .error_for_status()?;
+                Ok(())
+            }),
+            Backend::Local(db) => {
+                let (title, near) = models::parse_near(input);
+                let mut todo = Todo::created(db.next_id()?, title, &**db.clock());
+                todo.location = near.map(|text| Location { text, coords: None });
+                Repository::new(db).put(&todo)
+            }
+        }
+    }
+}
+
+// the target of `rel="next"` in a `Link` header
+fn next_link(link: &str) -> Option<String> {
+    link.split(',').find_map(|part| {
+        let (target, params) = part.trim().split_once(';')?;
+        params.contains("rel=\"next\"").then(|| {
+            target
+                .trim()
+                .trim_start_matches('<')
+                .trim_end_matches('>')
+                .to_string()
+        })
+    })
+}
+
+// === App ===
+enum Mode {
+    Browse,
+    Adding(String),
+}
+
+struct App {
+    backend: Backend,
+    todos: Vec<TodoResource>,
+    list: ListState,
+    mode: Mode,
+    message: Option<String>,
+}
+impl App {
+    fn reload(&mut self) {
+        match self.backend.list() {
+            Ok(todos) => self.todos = todos,
+            Err(err) => self.message = Some(err.to_string()),
+        }
+        let selected = self.list.selected().unwrap_or(0);
+        self.list.select(match self.todos.len() {
+            0 => None,
+            len => Some(selected.min(len - 1)),
+        });
+    }
+    fn select(&mut self, offset: isize) {
+        if self.todos.is_empty() {
+            return;
+        }
+        let selected = self.list.selected().unwrap_or(0) as isize + offset;
+        self.list.select(Some(
+            selected.clamp(0, self.todos.len() as isize - 1) as usize
+        ));
+    }
+    fn report(&mut self, result: Result<()>) {
+        self.message = result.err().map(|err| err.to_string());
+        self.reload();
+    }
+
+    // false once the app should quit
+    fn handle(&mut self, key: KeyCode) -> bool {
+        match &mut self.mode {
+            Mode::Browse => match key {
+                KeyCode::Char('q') | KeyCode::Esc => return false,
+                KeyCode::Char('j') | KeyCode::Down => self.select(1),
+                KeyCode::Char('k') | KeyCode::Up => self.select(-1),
+                KeyCode::Char('r') => {
+                    self.message = None;
+                    self.reload();
+                }
+                KeyCode::Char('a') => self.mode = Mode::Adding(String::new()),
+                KeyCode::Char(' ') | KeyCode::Enter => {
+                    if let Some(todo) = self.list.selected().and_then(|i| self.todos.get(i)) {
+                        let result = self.backend.toggle(todo);
+                        self.report(result);
+                    }
+                }
+                _ => {}
+            },
+            Mode::Adding(input) => match key {
+                KeyCode::Esc => self.mode = Mode::Browse,
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Char(c) => input.push(c),
+                KeyCode::Enter => {
+                    let input = std::mem::take(input);
+                    self.mode = Mode::Browse;
+                    if !input.trim().is_empty() {
+                        let result = self.backend.add(&input);
+                        self.report(result);
+                        self.list.select(self.todos.len().checked_sub(1));
+                    }
+                }
+                _ => {}
+            },
+        }
+        true
+    }
+}
+
+fn ui(frame: &mut Frame, app: &mut App) {
+    let [list, footer] =
+        Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.size());
+    let open = app.todos.iter().filter(|todo| !todo.completed).count();
+    let items: Vec<ListItem> = app
+        .todos
+        .iter()
+        .map(|todo| {
+            let check = if todo.completed { "[x] " } else { "[ ] " };
+            let mut line = vec![Span::raw(check), Span::raw(todo.title.as_str())];
+            if let Some(due) = &todo.due {
+                line.push(Span::raw(format!("  due {}", due)).dark_gray());
+            }
+            let line = Line::from(line);
+            ListItem::new(if todo.completed {
+                line.crossed_out().dark_gray()
+            } else {
+                line
+            })
+        })
+        .collect();
+    let title = format!(" Todos ({} open) ", open);
+    frame.render_stateful_widget(
+        List::new(items)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .highlight_symbol("> ")
+            .highlight_style(Style::new().reversed()),
+        list,
+        &mut app.list,
+    );
+    let footer_text = match (&app.mode, &app.message) {
+        (Mode::Adding(input), _) => Line::from(format!("New todo: {}_", input)),
+        (Mode::Browse, Some(message)) => Line::from(message.as_str()).red(),
+        (Mode::Browse, None) => {
+            Line::from("j/k move  space toggle  a add  r reload  q quit").dark_gray()
+        }
+    };
+    frame.render_widget(Paragraph::new(footer_text), footer);
+}
+
+// === Terminal ===
+// puts the terminal back the way it was, also when the app errors out
+struct Screen(Terminal<CrosstermBackend<Stdout>>);
+impl Screen {
+    fn enter() -> Result<Self> {
+        enable_raw_mode()?;
+        execute!(std::io::stdout(), EnterAlternateScreen)?;
+        Ok(Self(Terminal::new(CrosstermBackend::new(
+            std::io::stdout(),
+        ))?))
+    }
+}
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = disable_raw_mode();
+        let _ = execute!(std::io::stdout(), LeaveAlternateScreen);
+    }
+}
+
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut app = App {
+        backend: Backend::from_args(&args)?,
+        todos: Vec::new(),
+        list: ListState::default(),
+        mode: Mode::Browse,
+        message: None,
+    };
+    app.reload();
+
+    let mut screen = Screen::enter()?;
+    loop {
+        screen.0.draw(|frame| ui(frame, &mut app))?;
+        if !event::poll(Duration::from_millis(250))? {
+            continue;
+        }
+        if let Event::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Press && !app.handle(key.code) {
+                return Ok(());
+            }
+        }
+    }
+}
@@ -0,0 +1,113 @@
+// Project workflows in one place, for developers and deployment scripts alike:
+//
+//     cargo xtask seed [--todos <n>]        sample todos in the database of `DATA_DIR`
+//     cargo xtask gen-ts [--out <file>]     the TypeScript API client, see `api::typescript`
+//     cargo xtask tailwind                  download tailwind and the other embedded assets
+//     cargo xtask backup [--to <dir>]       a backup of the database, like `backup now`
+//     cargo xtask loadtest                  the load test in `tests/load.rs`, in release mode
+//
+// `cargo xtask` is an alias from `.cargo/config.toml`. Commands touching the database need the
+// server stopped, sled locks it.
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{bail, Context, Result};
+use rust_htmx::{cli, config::Config, models::Todo, repository::entity::Repository, state, tags};
+
+const DEFAULT_SEEDED: usize = 50;
+
+// the repository root, commands shelling out run from there
+fn root() -> &'static Path {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+}
+
+fn run(program: &str, args: &[&str]) -> Result<()> {
+    let status = Command::new(program)
+        .args(args)
+        .current_dir(root())
+        .status()
+        .with_context(|| format!("could not run {}", program))?;
+    if !status.success() {
+        bail!("`{} {}` failed with {}", program, args.join(" "), status);
+    }
+    Ok(())
+}
+
+// Sample todos spread over tags, due dates and states, so every page has something to show.
+fn seed(config: &Config, count: usize) -> Result<()> {
+    let db = state::open_db(config)?;
+    let todos = Repository::<Todo>::new(&db);
+    let today = db.clock().now().date();
+    let words = [
+        "Water", "Call", "Write", "Fix", "Plan", "Buy", "Read", "Clean",
+    ];
+    let things = [
+        "the plants",
+        "mom",
+        "the report",
+        "the bike",
+        "the trip",
+        "milk",
+    ];
+    let tag_names = ["home", "work", "errands"];
+    for i in 0..count {
+        let title = format!(
+            "{} {} #{}",
+            words[i % words.len()],
+            things[i % things.len()],
+            tag_names[i % tag_names.len()]
+        );
+        let (title, tags) = tags::parse_tags(&title);
+        let mut todo = Todo::new(db.next_id()?, title);
+        todo.tags = tags;
+        if i % 3 == 0 {
+            todo.due = Some(today + time::Duration::days(i as i64 % 14 - 3));
+        }
+        if i % 5 == 0 {
+            todo.set_completed(true);
+        }
+        todos.put(&todo)?;
+    }
+    println!("Seeded {} todos into {}", count, config.db_path().display());
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let config = Config::from_env()?;
+    let option = |name: &str| {
+        args.iter()
+            .position(|arg| arg == name)
+            .and_then(|at| args.get(at + 1))
+    };
+    match args.first().map(String::as_str) {
+        Some("seed") => {
+            let count = match option("--todos") {
+                Some(count) => count.parse().context("--todos takes a number")?,
+                None => DEFAULT_SEEDED,
+            };
+            seed(&config, count)
+        }
+        Some("gen-ts") => cli::run(
+            cli::Command::GenTs {
+                out: option("--out").map(PathBuf::from),
+            },
+            &config,
+        ),
+        Some("tailwind") => run("sh", &["scripts/fetch-assets.sh"]),
+        Some("backup") => cli::run(
+            cli::Command::BackupNow {
+                to: option("--to").map(PathBuf::from),
+            },
+            &config,
+        ),
+        Some("loadtest") => run(
+            "cargo",
+            &["test", "--release", "--test", "load", "--", "--ignored"],
+        ),
+        _ => bail!("usage: cargo xtask <seed | gen-ts | tailwind | backup | loadtest>"),
+    }
+}
@@ -0,0 +1,152 @@
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+};
+use maud::{html, Markup, PreEscaped};
+use serde::Deserialize;
+
+use crate::{
+    error::AppError,
+    extract::FormOrJson,
+    history,
+    models::{Status, Todo},
+    repository::{entity::Repository, query::TodoQuery},
+    routes,
+    sorting::{self, View},
+    state::AppState,
+    tenant::Tenant,
+    views::{
+        self, dom,
+        nav::{self, Nav},
+    },
+};
+
+// dragging a card onto a column posts its new status, the response moves the card over
+const BOARD_SCRIPT: &str = r##"
+document.addEventListener("dragstart", function (evt) {
+    const card = evt.target.closest && evt.target.closest("[data-status-url]");
+    if (card) evt.dataTransfer.setData("text/plain", JSON.stringify({ id: card.id, url: card.dataset.statusUrl }));
+});
+function dropCard(evt, status) {
+    evt.preventDefault();
+    const data = evt.dataTransfer.getData("text/plain");
+    if (!data) return;
+    const card = JSON.parse(data);
+    htmx.ajax("POST", card.url, { target: "#" + card.id, swap: "outerHTML", values: { status: status } });
+}
+"##;
+
+fn column_id(status: Status) -> String {
+    format!("column-{}", status)
+}
+
+// === Components ===
+fn card_html(todo: &Todo) -> Markup {
+    let card_id = dom::card(todo.id);
+    html! {
+        div id=(card_id) class="bg-white rounded-lg shadow p-3 cursor-move space-y-2" draggable="true"
+            data-status-url=(routes::TodoStatus::url(todo.id)) {
+            a class="text-gray-700 hover:underline" href=(routes::TodoDetail::url(todo.id)) { (todo.title) }
+            // moving without dragging, for keyboards and without javascript
+            form method="post" action=(routes::TodoStatus::url(todo.id))
+                hx-post=(routes::TodoStatus::url(todo.id)) hx-trigger="change" hx-target={ "#" (card_id) } hx-swap="outerHTML" {
+                select class="text-sm rounded border p-1" name="status" aria-label="Status" {
+                    @for status in Status::COLUMNS {
+                        option value=(status) selected[status == todo.status] { (status.label()) }
+                    }
+                }
+                noscript {
+                    button class="ml-2 text-sm text-blue-500 hover:text-blue-700" type="submit" { "Move" }
+                }
+            }
+        }
+    }
+}
+
+fn column_html(status: Status) -> Markup {
+    html! {
+        section class="bg-gray-200 rounded-lg p-4 flex-1" {
+            h2 class="text-xl text-gray-700 mb-4" { (status.label()) }
+            div id=(column_id(status)) class="space-y-2 min-h-[4rem]"
+                hx-get=(routes::BoardColumn::url(status)) hx-trigger={ "load, " (sorting::SORTED_EVENT) " from:body" }
+                ondragover="event.preventDefault()" ondrop={ "dropCard(event, '" (status) "')" } {
+                p class="text-gray-500" { "Loading…" }
+            }
+        }
+    }
+}
+
+// === Routes ===
+pub async fn board(State(state): State<AppState>, tenant: Tenant) -> Result<Markup, AppError> {
+    let order = sorting::get(&state.read().await.for_tenant(tenant.id())?, View::Board)?;
+    Ok(views::page(
+        "Board",
+        html! {
+            (nav::navigation(&Nav::board()))
+            h1 class="text-4xl text-center text-gray-700 mb-6" { "Board" }
+            div class="mb-4" { (sorting::dropdown_html(View::Board, order)) }
+            noscript {
+                p class="text-center text-gray-600 mb-4" {
+                    "The board needs JavaScript, your todos are also on the "
+                    a class="text-blue-500" href=(routes::Root::url()) { "list" } "."
+                }
+            }
+            div class="flex space-x-4" {
+                @for status in Status::COLUMNS {
+                    (column_html(status))
+                }
+            }
+            script { (PreEscaped(BOARD_SCRIPT)) }
+        },
+    ))
+}
+
+// the cards of one column, loaded once the column is on the page
+pub async fn column(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    Path(status): Path<Status>,
+) -> Result<Markup, AppError> {
+    let db = state.read().await.for_tenant(tenant.id())?;
+    let mut todos = TodoQuery::new().status(status).list(&db)?;
+    sorting::apply(&mut todos, sorting::get(&db, View::Board)?);
+    Ok(html! {
+        @for todo in &todos {
+            (card_html(todo))
+        }
+    })
+}
+
+#[derive(Deserialize)]
+pub struct SetStatus {
+    status: Status,
+}
+// Move a todo to another column. The card is removed where it was and appended out of band
+// to its new column.
+pub async fn set_status(
+    State(mut app_state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Path(id): Path<u64>,
+    FormOrJson(SetStatus { status }): FormOrJson<SetStatus>,
+) -> Result<Response, AppError> {
+    let app_state = app_state.write().await;
+    let db = app_state.for_tenant(tenant.id())?;
+    let todos = Repository::<Todo>::new(&db);
+    let todo = db.transaction(|tx| {
+        let Some(mut todo) = todos.get_in(tx, id)? else {
+            return Ok(None);
+        };
+        tx.apply_batch(history::record_ops(&db, &todo)?)?;
+        todo.set_status(status);
+        todo.touch(&**db.clock());
+        todos.put_in(tx, &todo)?;
+        Ok(Some(todo))
+    })?;
+    let todo = todo.ok_or(AppError::NotFound)?;
+    let fragment = html! {
+        div hx-swap-oob={ "beforeend:#" (column_id(status)) } { (card_html(&todo)) }
+    };
+    Ok(views::fragment_or_redirect(&headers, fragment, &routes::Board::url()).into_response())
+}
@@ -0,0 +1,289 @@
+use axum::{
+    extract::State,
+    http::header,
+    response::{IntoResponse, Response},
+};
+use maud::{html, Markup};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    colors, db::driver::Db, error::AppError, pwa, routes, setup, state::AppState, tenant::Tenant,
+    theme::Theme,
+};
+
+// set from the admin page, on the root tree like the instance from `/setup`
+const KEY: &str = "branding";
+// kept apart so pages don't load the image to read the name
+const LOGO_KEY: &str = "branding:logo";
+pub const MAX_LOGO_BYTES: usize = 512 * 1024;
+pub const LOGO_TYPES: [&str; 5] = [
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/webp",
+    "image/svg+xml",
+];
+const MAX_NAME_LEN: usize = 80;
+const MAX_WELCOME_LEN: usize = 2000;
+const DEFAULT_NAME: &str = "Magical Axum + Maud + Htmx To-Do";
+
+// === Branding ===
+// How a deployment presents itself, instead of editing the templates. Everything is optional,
+// unset fields fall back to the instance from `/setup` and the stock look.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Branding {
+    pub name: Option<String>,
+    // `#rrggbb`, replaces the accent of the default theme
+    pub accent: Option<String>,
+    // plain text above the list, blank lines separate paragraphs
+    pub welcome: Option<String>,
+    pub has_logo: bool,
+}
+impl Branding {
+    // whether pages get the header with the logo and the name
+    pub fn has_header(&self) -> bool {
+        self.has_logo || self.name.is_some()
+    }
+
+    pub fn paragraphs(&self) -> Vec<&str> {
+        self.welcome
+            .as_deref()
+            .unwrap_or_default()
+            .split("\n\n")
+            .map(str::trim)
+            .filter(|paragraph| !paragraph.is_empty())
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Logo {
+    pub content_type: String,
+    pub bytes: Vec<u8>,
+}
+
+pub fn get(db: &Db) -> anyhow::Result<Branding> {
+    Ok(db.get(KEY)?.unwrap_or_default())
+}
+pub fn set(db: &Db, branding: &Branding) -> anyhow::Result<()> {
+    db.insert(KEY, branding)
+}
+
+// The name pages are titled with: the branded one, the one from `/setup`, or the stock one.
+pub fn name(db: &Db) -> anyhow::Result<String> {
+    if let Some(name) = get(db)?.name {
+        return Ok(name);
+    }
+    Ok(setup::instance(db)?
+        .map(|instance| instance.name)
+        .unwrap_or_else(|| DEFAULT_NAME.to_string()))
+}
+
+// the accent the default theme is drawn with, a picked theme keeps its own
+pub fn accent(db: &Db, theme: Theme) -> anyhow::Result<Option<String>> {
+    if theme != Theme::Default {
+        return Ok(None);
+    }
+    Ok(get(db)?.accent)
+}
+
+// `current` with what the admin form sends, empty fields unset
+pub fn edited(
+    current: &Branding,
+    name: &str,
+    accent: &str,
+    welcome: &str,
+) -> Result<Branding, AppError> {
+    let name = name.trim();
+    if name.chars().count() > MAX_NAME_LEN {
+        return Err(AppError::Invalid(format!(
+            "A name has at most {} characters.",
+            MAX_NAME_LEN
+        )));
+    }
+    let welcome = welcome.trim().replace("\r\n", "\n");
+    if welcome.chars().count() > MAX_WELCOME_LEN {
+        return Err(AppError::Invalid(format!(
+            "The welcome text has at most {} characters.",
+            MAX_WELCOME_LEN
+        )));
+    }
+    Ok(Branding {
+        name: (!name.is_empty()).then(|| name.to_string()),
+        accent: colors::parse_color(accent)?,
+        welcome: (!welcome.is_empty()).then_some(welcome),
+        has_logo: current.has_logo,
+    })
+}
+
+// === Logo ===
+pub fn logo(db: &Db) -> anyhow::Result<Option<Logo>> {
+    db.get(LOGO_KEY)
+}
+
+pub fn validate_logo(content_type: &str, bytes: &[u8]) -> Result<(), AppError> {
+    if !LOGO_TYPES.contains(&content_type) {
+        return Err(AppError::Invalid(
+            "A logo is a png, jpeg, gif, webp or svg image.".into(),
+        ));
+    }
+    if bytes.is_empty() || bytes.len() > MAX_LOGO_BYTES {
+        return Err(AppError::Invalid(format!(
+            "A logo is at most {} KiB.",
+            MAX_LOGO_BYTES / 1024
+        )));
+    }
+    Ok(())
+}
+
+// the caller checks it with `validate_logo`
+pub fn set_logo(db: &Db, logo: &Logo) -> anyhow::Result<()> {
+    db.insert(LOGO_KEY, logo)?;
+    db.update(KEY, |branding: Option<Branding>| {
+        Some(Branding {
+            has_logo: true,
+            ..branding.unwrap_or_default()
+        })
+    })?;
+    Ok(())
+}
+
+pub fn remove_logo(db: &Db) -> anyhow::Result<()> {
+    db.remove(LOGO_KEY)?;
+    db.update(KEY, |branding: Option<Branding>| {
+        Some(Branding {
+            has_logo: false,
+            ..branding.unwrap_or_default()
+        })
+    })?;
+    Ok(())
+}
+
+// === Components ===
+// on every page, replaced by the header once loaded, gone when nothing is branded
+pub fn header_slot() -> Markup {
+    html! {
+        div hx-get=(routes::BrandHeader::url()) hx-trigger="load" hx-swap="outerHTML" {}
+    }
+}
+
+pub fn header_html(branding: &Branding, name: &str) -> Markup {
+    html! {
+        @if branding.has_header() {
+            header class="container mx-auto px-8 pt-4" {
+                a class="inline-flex items-center space-x-2 t-text hover:underline" href=(routes::Root::url()) {
+                    @if branding.has_logo {
+                        img class="h-8 w-auto" src=(routes::BrandLogo::url()) alt="";
+                    }
+                    span class="font-bold" { (name) }
+                }
+            }
+        }
+    }
+}
+
+// the copy above the list on the landing page
+pub fn welcome_html(branding: &Branding) -> Markup {
+    html! {
+        @let paragraphs = branding.paragraphs();
+        @if !paragraphs.is_empty() {
+            div class="bg-white rounded-lg shadow-lg p-4 mb-6 space-y-2 t-text" {
+                @for paragraph in paragraphs {
+                    p class="whitespace-pre-line" { (paragraph) }
+                }
+            }
+        }
+    }
+}
+
+// === Routes ===
+pub async fn header(State(state): State<AppState>) -> Result<Markup, AppError> {
+    let db = state.read().await;
+    Ok(header_html(&get(&db)?, &name(&db)?))
+}
+
+// The uploaded logo, or the app icon without one. The policy keeps an svg logo opened on its
+// own from running scripts.
+pub async fn show_logo(
+    State(state): State<AppState>,
+    tenant: Tenant,
+) -> Result<Response, AppError> {
+    let logo = logo(&*state.read().await)?;
+    let Some(logo) = logo else {
+        return pwa::app_icon(State(state), tenant).await;
+    };
+    Ok((
+        [
+            (header::CONTENT_TYPE, logo.content_type),
+            (header::CACHE_CONTROL, "no-cache".to_string()),
+            (
+                header::CONTENT_SECURITY_POLICY,
+                "default-src 'none'; style-src 'unsafe-inline'; sandbox".to_string(),
+            ),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+        ],
+        logo.bytes,
+    )
+        .into_response())
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::TestDb;
+
+    #[test]
+    fn test_falls_back_to_the_instance() -> anyhow::Result<()> {
+        let db = TestDb::new("branding")?;
+        assert_eq!(name(&db)?, DEFAULT_NAME);
+        assert!(!get(&db)?.has_header());
+
+        set(
+            &db,
+            &Branding {
+                name: Some("Acme tasks".into()),
+                accent: Some("#ff6600".into()),
+                ..Branding::default()
+            },
+        )?;
+        assert_eq!(name(&db)?, "Acme tasks");
+        assert_eq!(accent(&db, Theme::Default)?.as_deref(), Some("#ff6600"));
+        assert_eq!(accent(&db, Theme::HighContrast)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_logo() -> anyhow::Result<()> {
+        let db = TestDb::new("branding_logo")?;
+        let png = Logo {
+            content_type: "image/png".into(),
+            bytes: vec![1, 2, 3],
+        };
+        set_logo(&db, &png)?;
+        assert!(get(&db)?.has_logo);
+        assert_eq!(logo(&db)?, Some(png));
+        remove_logo(&db)?;
+        assert!(!get(&db)?.has_logo);
+        assert_eq!(logo(&db)?, None);
+
+        assert!(validate_logo("text/html", b"<script>").is_err());
+        assert!(validate_logo("image/png", &vec![0; MAX_LOGO_BYTES + 1]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_edited() {
+        let current = Branding {
+            name: Some("Acme".into()),
+            has_logo: true,
+            ..Branding::default()
+        };
+        let branding = edited(&current, " ", "#FF6600", "Hello\r\n\r\nthere ").unwrap();
+        assert_eq!(branding.name, None);
+        assert_eq!(branding.accent.as_deref(), Some("#ff6600"));
+        assert!(branding.has_logo);
+        assert_eq!(branding.paragraphs(), ["Hello", "there"]);
+        assert!(edited(&current, "", "orange", "").is_err());
+    }
+}
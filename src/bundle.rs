@@ -0,0 +1,347 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+    Json,
+};
+use maud::{html, Markup};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    attachments::{self, Attachment},
+    auth,
+    db::{driver::Db, queue::WriteOp},
+    error::AppError,
+    export,
+    models::Todo,
+    repository::{self, entity::Repository},
+    routes,
+    state::AppState,
+    tags,
+    tenant::Tenant,
+    views,
+};
+
+// bumped when the contents change shape, older instances refuse newer bundles
+const VERSION: u32 = 1;
+const RESULT_ID: &str = "bundle-result";
+// what the digest is signed as, see `auth::sign`
+const SIGNED_PURPOSE: &str = "bundle";
+
+// === Bundle ===
+// The todos under one tag, to hand to someone else or another instance:
+//
+//     {"contents": {"version": 1, "tag": "trip", "todos": [..]}, "digest": "..", "signature": ".."}
+//
+// `digest` is the sha256 of the contents as serialized, an edited or truncated bundle is refused.
+// `signature` is an hmac of the digest with this instance's key, so a bundle coming back tells
+// whether it was made here. Ids are remapped on import, dependencies between bundled todos
+// follow. Attachments travel as metadata only, their files stay where they were uploaded.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bundle {
+    pub contents: Contents,
+    pub digest: String,
+    pub signature: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Contents {
+    pub version: u32,
+    pub tag: String,
+    // unix seconds
+    pub exported_at: u64,
+    pub todos: Vec<BundledTodo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundledTodo {
+    pub todo: Todo,
+    // ids of other todos in the bundle
+    pub blocked_by: Vec<u64>,
+    pub attachments: Vec<Attachment>,
+}
+
+// what an import did
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Report {
+    pub todos: usize,
+    // listed in the bundle but without their files
+    pub attachments_left: usize,
+    pub signed_here: bool,
+}
+
+fn digest(contents: &Contents) -> Result<String> {
+    Ok(hex::encode(Sha256::digest(serde_json::to_string(
+        contents,
+    )?)))
+}
+
+// `todos` with their attachments, and the dependencies among them
+fn bundle_todos(db: &Db, todos: Vec<Todo>) -> Result<Vec<BundledTodo>> {
+    let ids: HashSet<u64> = todos.iter().map(|todo| todo.id).collect();
+    let mut bundled = Vec::new();
+    for todo in todos {
+        let mut blocked_by = repository::todo::blockers(db, todo.id)?;
+        blocked_by.retain(|blocker| ids.contains(blocker));
+        bundled.push(BundledTodo {
+            attachments: attachments::for_todo(db, todo.id)?,
+            blocked_by,
+            todo,
+        });
+    }
+    Ok(bundled)
+}
+
+// everything tagged `tag`, archived and someday todos too
+pub fn contents(db: &Db, tag: &str) -> Result<Contents> {
+    contents_of(db, tag, repository::todo::tagged(db, tag)?)
+}
+
+pub fn contents_of(db: &Db, tag: &str, todos: Vec<Todo>) -> Result<Contents> {
+    Ok(Contents {
+        version: VERSION,
+        tag: tag.to_string(),
+        exported_at: db.clock().now_millis() / 1000,
+        todos: bundle_todos(db, todos)?,
+    })
+}
+
+pub fn seal(contents: Contents, key: &[u8]) -> Result<Bundle> {
+    let digest = digest(&contents)?;
+    let signed = auth::sign(key, SIGNED_PURPOSE, &digest);
+    let (_, signature) = signed.rsplit_once('.').unwrap_or_default();
+    Ok(Bundle {
+        signature: signature.to_string(),
+        digest,
+        contents,
+    })
+}
+
+// the contents of `json`, and whether this instance signed them
+pub fn open(json: &str, key: &[u8]) -> Result<(Contents, bool), AppError> {
+    let bundle: Bundle = serde_json::from_str(json)
+        .map_err(|err| AppError::Invalid(format!("not a bundle: {}", err)))?;
+    if bundle.contents.version > VERSION {
+        return Err(AppError::Invalid(format!(
+            "The bundle is version {}, this instance reads up to {}.",
+            bundle.contents.version, VERSION
+        )));
+    }
+    if !auth::constant_time_eq(
+        digest(&bundle.contents)?.as_bytes(),
+        bundle.digest.as_bytes(),
+    ) {
+        return Err(AppError::Invalid(
+            "The bundle was changed after it was made.".into(),
+        ));
+    }
+    let signed_here = auth::verify_signed(
+        key,
+        SIGNED_PURPOSE,
+        &format!("{}.{}", bundle.digest, bundle.signature),
+    )
+    .is_some();
+    Ok((bundle.contents, signed_here))
+}
+
+// The writes recreating `contents` under new ids. Nothing is written unless every todo is valid.
+// With `relink`, for contents taken from this workspace, attachments whose files are still here
+// are listed under the new todos.
+pub fn restore_ops(
+    db: &Db,
+    contents: Contents,
+    relink: bool,
+) -> Result<(Vec<WriteOp>, Report), AppError> {
+    let mut ids = HashMap::new();
+    for (index, bundled) in contents.todos.iter().enumerate() {
+        export::validate(&bundled.todo)
+            .map_err(|err| AppError::Invalid(format!("Todo {}: {}.", index + 1, err)))?;
+        ids.insert(bundled.todo.id, db.next_id()?);
+    }
+    let todos = Repository::<Todo>::new(db);
+    let mut report = Report::default();
+    let mut ops = Vec::new();
+    for BundledTodo {
+        mut todo,
+        blocked_by,
+        attachments: listed,
+    } in contents.todos
+    {
+        todo.id = ids[&todo.id];
+        todo.version = 1;
+        ops.extend(todos.put_ops(&todo)?);
+        let blocked_by: Vec<u64> = blocked_by
+            .iter()
+            .filter_map(|blocker| ids.get(blocker).copied())
+            .collect();
+        if !blocked_by.is_empty() {
+            ops.push(WriteOp::Insert {
+                key: repository::todo::blocked_by_key(todo.id),
+                value: db.encode(&blocked_by)?,
+            });
+        }
+        report.todos += 1;
+        for attachment in &listed {
+            let relinked = if relink {
+                attachments::relink_op(db, attachment, todo.id)?
+            } else {
+                None
+            };
+            match relinked {
+                Some(op) => ops.push(op),
+                None => report.attachments_left += 1,
+            }
+        }
+    }
+    Ok((ops, report))
+}
+
+// === Components ===
+pub fn settings_html() -> Markup {
+    html! {
+        section class="bg-white rounded-lg shadow-lg p-6 space-y-4" {
+            h2 class="text-2xl text-gray-700" { "Bundles" }
+            p class="text-gray-600" {
+                "A bundle holds the todos under one tag, download one from the "
+                a class="text-blue-500 hover:text-blue-700" href=(routes::Tags::url()) { "tags" }
+                ". Importing one adds its todos here as new ones."
+            }
+            label class="block text-gray-600" {
+                "Import a bundle "
+                input type="file" accept=".json,application/json" data-url=(routes::ImportBundle::url())
+                    onchange={ "const file = this.files[0]; if (!file) return;
+                        fetch(this.dataset.url, { method: 'POST', body: file, headers: { 'Content-Type': 'application/json', 'HX-Request': 'true' } })
+                            .then((response) => response.text())
+                            .then((text) => { document.getElementById('" (RESULT_ID) "').innerHTML = text; });" };
+            }
+            p id=(RESULT_ID) class="text-gray-700" role="status" {}
+        }
+    }
+}
+
+fn report_html(report: &Report) -> Markup {
+    html! {
+        (report.todos) " todos imported"
+        @if !report.signed_here { " from another instance" }
+        "."
+        @if report.attachments_left > 0 {
+            " " (report.attachments_left) " attachments stayed behind, their files are on the instance the bundle came from."
+        }
+    }
+}
+
+// === Routes ===
+pub async fn download(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    Path(tag): Path<String>,
+) -> Result<Response, AppError> {
+    let tag = tags::normalize(&tag).ok_or(AppError::NotFound)?;
+    let db = state.read().await.for_tenant(tenant.id())?;
+    let contents = contents(&db, &tag)?;
+    if contents.todos.is_empty() {
+        return Err(AppError::NotFound);
+    }
+    let bundle = seal(contents, &state.secret_key)?;
+    Ok((
+        [(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}-bundle.json\"", tag),
+        )],
+        Json(bundle),
+    )
+        .into_response())
+}
+
+// The bundle is the request body, like a backup upload.
+pub async fn upload(
+    State(mut app_state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, AppError> {
+    let state = app_state.clone();
+    let text = std::str::from_utf8(&body)
+        .map_err(|_| AppError::Invalid("The upload is not utf-8 text.".into()))?;
+    let (contents, signed_here) = open(text, &state.secret_key)?;
+    let guard = app_state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    let tag = contents.tag.clone();
+    let (ops, report) = restore_ops(&db, contents, false)?;
+    let report = Report {
+        signed_here,
+        ..report
+    };
+    state.writes.submit(&db, ops).await?;
+    tracing::info!(%tag, todos = report.todos, signed_here, "imported bundle");
+    if views::wants_json(&headers) {
+        return Ok(Json(report).into_response());
+    }
+    Ok(views::fragment_or_redirect(
+        &headers,
+        report_html(&report),
+        &routes::Root::url(),
+    ))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::TestDb;
+
+    fn tagged(db: &Db, title: &str, tag: &str) -> Result<Todo> {
+        let mut todo = Todo::new(db.next_id()?, title.into());
+        todo.tags = vec![tag.into()];
+        db.apply_batch(Repository::new(db).put_ops(&todo)?)?;
+        Ok(todo)
+    }
+
+    #[test]
+    fn test_round_trip_remaps_ids() -> Result<(), AppError> {
+        let from = TestDb::new("bundle-from")?;
+        let book = tagged(&from, "Book flights", "trip")?;
+        let pack = tagged(&from, "Pack", "trip")?;
+        let other = tagged(&from, "Unrelated", "work")?;
+        repository::todo::add_blocker(&from, pack.id, book.id)?;
+        repository::todo::add_blocker(&from, pack.id, other.id)?;
+
+        let bundle = seal(contents(&from, "trip")?, b"here")?;
+        let json = serde_json::to_string(&bundle)?;
+        assert!(open(&json, b"here")?.1);
+
+        let to = TestDb::new("bundle-to")?;
+        tagged(&to, "Already here", "trip")?;
+        let (contents, signed_here) = open(&json, b"elsewhere")?;
+        assert!(!signed_here);
+        let (ops, report) = restore_ops(&to, contents, false)?;
+        to.apply_batch(ops)?;
+        assert_eq!(report.todos, 2);
+
+        let trip = repository::todo::tagged(&to, "trip")?;
+        assert_eq!(trip.len(), 3);
+        let pack = trip.iter().find(|todo| todo.title == "Pack").unwrap();
+        let book = trip
+            .iter()
+            .find(|todo| todo.title == "Book flights")
+            .unwrap();
+        // the dependency outside the bundle is left out
+        assert_eq!(repository::todo::blockers(&to, pack.id)?, [book.id]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tampered_bundles_are_refused() -> Result<(), AppError> {
+        let db = TestDb::new("bundle-tampered")?;
+        tagged(&db, "Pack", "trip")?;
+        let mut bundle = seal(contents(&db, "trip")?, b"key")?;
+        bundle.contents.todos[0].todo.title = "Unpack".into();
+        assert!(open(&serde_json::to_string(&bundle)?, b"key").is_err());
+        Ok(())
+    }
+}
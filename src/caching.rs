@@ -0,0 +1,227 @@
+use axum::{
+    body::{to_bytes, Body},
+    extract::{MatchedPath, Request},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::{error::AppError, routes, views::fingerprint};
+
+// how long a cache may serve a shared view before asking again
+const SHARED_MAX_AGE: &str = "public, max-age=30";
+const PRIVATE: &str = "private, no-cache";
+// fragments, full pages and json share urls, and most pages are someone's own
+const PRIVATE_VARY: [&str; 3] = ["Cookie", "HX-Request", "Accept"];
+
+// === Cacheability ===
+// Who a response may be cached for. Only assets and the read-only views behind a share token
+// look the same to everyone, the rest depends on the visitor's cookies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cacheability {
+    // the same for every release, handlers set how long
+    Static,
+    // the same for everyone holding the link
+    Shared,
+    Private,
+}
+
+pub fn classify(route: Option<&str>) -> Cacheability {
+    match route {
+        Some(routes::Asset::PATH) => Cacheability::Static,
+        Some(routes::Embed::PATH | routes::Kiosk::PATH | routes::KioskPanel::PATH) => {
+            Cacheability::Shared
+        }
+        _ => Cacheability::Private,
+    }
+}
+
+// Set `Cache-Control` and `Vary` for `cacheability`. A handler's own `Cache-Control` is kept,
+// but made private where the response is.
+pub fn apply(headers: &mut HeaderMap, cacheability: Cacheability) {
+    match cacheability {
+        Cacheability::Static => {}
+        Cacheability::Shared => {
+            if !headers.contains_key(header::CACHE_CONTROL) {
+                headers.insert(
+                    header::CACHE_CONTROL,
+                    HeaderValue::from_static(SHARED_MAX_AGE),
+                );
+            }
+            add_vary(headers, &["HX-Request"]);
+        }
+        Cacheability::Private => {
+            let control = match headers
+                .get(header::CACHE_CONTROL)
+                .and_then(|value| value.to_str().ok())
+            {
+                None => PRIVATE.to_string(),
+                Some(control) => private(control),
+            };
+            if let Ok(control) = HeaderValue::from_str(&control) {
+                headers.insert(header::CACHE_CONTROL, control);
+            }
+            add_vary(headers, &PRIVATE_VARY);
+        }
+    }
+}
+
+// `control` with `public` swapped for `private`, `no-store` is private enough already
+fn private(control: &str) -> String {
+    let directives: Vec<&str> = control
+        .split(',')
+        .map(str::trim)
+        .filter(|directive| !directive.eq_ignore_ascii_case("public"))
+        .collect();
+    let already = directives.iter().any(|directive| {
+        directive.eq_ignore_ascii_case("private") || directive.eq_ignore_ascii_case("no-store")
+    });
+    if already {
+        return directives.join(", ");
+    }
+    ["private"]
+        .into_iter()
+        .chain(directives)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// add the header names `Vary` does not list yet
+fn add_vary(headers: &mut HeaderMap, names: &[&str]) {
+    let listed: Vec<String> = headers
+        .get_all(header::VARY)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|name| name.trim().to_ascii_lowercase())
+        .collect();
+    for name in names {
+        if !listed.contains(&name.to_ascii_lowercase()) {
+            headers.append(header::VARY, HeaderValue::from_static(name));
+        }
+    }
+}
+
+// === Middleware ===
+// On every routed response, so no handler can forget it.
+pub async fn headers(request: Request, next: Next) -> Response {
+    let cacheability = classify(
+        request
+            .extensions()
+            .get::<MatchedPath>()
+            .map(MatchedPath::as_str),
+    );
+    let mut response = next.run(request).await;
+    apply(response.headers_mut(), cacheability);
+    response
+}
+
+// whether `If-None-Match` lists `etag`
+fn matches(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+fn is_html(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/html"))
+}
+
+// Give html pages and fragments an `ETag` from their bytes, and answer a revalidation with the
+// same bytes with a bodiless 304. Private responses are `no-cache`, browsers ask every time.
+pub async fn etag(request: Request, next: Next) -> Result<Response, AppError> {
+    if !matches!(*request.method(), Method::GET | Method::HEAD) {
+        return Ok(next.run(request).await);
+    }
+    let conditional = request.headers().clone();
+    let response = next.run(request).await;
+    if response.status() != StatusCode::OK
+        || !is_html(response.headers())
+        || response.headers().contains_key(header::ETAG)
+    {
+        return Ok(response);
+    }
+    let (mut parts, body) = response.into_parts();
+    let body = to_bytes(body, usize::MAX)
+        .await
+        .map_err(|err| AppError::Invalid(err.to_string()))?;
+    let etag = fingerprint::etag(&body);
+    if let Ok(value) = HeaderValue::from_str(&etag) {
+        parts.headers.insert(header::ETAG, value);
+    }
+    if matches(&conditional, &etag) {
+        parts.status = StatusCode::NOT_MODIFIED;
+        parts.headers.remove(header::CONTENT_TYPE);
+        parts.headers.remove(header::CONTENT_LENGTH);
+        return Ok(Response::from_parts(parts, Body::empty()));
+    }
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vary(headers: &HeaderMap) -> Vec<&str> {
+        headers
+            .get_all(header::VARY)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect()
+    }
+
+    #[test]
+    fn test_private_by_default() {
+        assert_eq!(classify(Some(routes::Root::PATH)), Cacheability::Private);
+        assert_eq!(classify(None), Cacheability::Private);
+        assert_eq!(classify(Some(routes::Embed::PATH)), Cacheability::Shared);
+
+        let mut headers = HeaderMap::new();
+        apply(&mut headers, Cacheability::Private);
+        assert_eq!(headers[header::CACHE_CONTROL], PRIVATE);
+        assert_eq!(vary(&headers), ["Cookie", "HX-Request", "Accept"]);
+
+        // a handler's revalidation is kept, and nothing is listed twice
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+        headers.insert(header::VARY, HeaderValue::from_static("hx-request"));
+        apply(&mut headers, Cacheability::Private);
+        assert_eq!(headers[header::CACHE_CONTROL], "private, no-cache");
+        assert_eq!(vary(&headers), ["hx-request", "Cookie", "Accept"]);
+    }
+
+    #[test]
+    fn test_public_made_private() {
+        assert_eq!(private("public, max-age=60"), "private, max-age=60");
+        assert_eq!(private("no-store"), "no-store");
+        assert_eq!(private("private, max-age=5"), "private, max-age=5");
+    }
+
+    #[test]
+    fn test_if_none_match() {
+        let mut headers = HeaderMap::new();
+        assert!(!matches(&headers, "\"abc\""));
+        headers.insert(
+            header::IF_NONE_MATCH,
+            HeaderValue::from_static("\"old\", W/\"abc\""),
+        );
+        assert!(matches(&headers, "\"abc\""));
+        assert!(!matches(&headers, "\"new\""));
+    }
+
+    #[test]
+    fn test_shared() {
+        let mut headers = HeaderMap::new();
+        apply(&mut headers, Cacheability::Shared);
+        assert_eq!(headers[header::CACHE_CONTROL], SHARED_MAX_AGE);
+        assert_eq!(vary(&headers), ["HX-Request"]);
+    }
+}
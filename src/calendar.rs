@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+};
+use maud::{html, Markup};
+use time::{Date, Month};
+
+use crate::{
+    error::AppError,
+    method_override,
+    models::Todo,
+    repository::query::TodoQuery,
+    routes,
+    state::AppState,
+    tenant::Tenant,
+    views::{
+        self,
+        nav::{self, Nav},
+    },
+};
+
+const CALENDAR_ID: &str = "calendar";
+// the create form slot above the grid, a day click fills it
+const NEW_TODO_ID: &str = "calendar-new-todo";
+const NEW_TODO_FORM_ID: &str = "calendar-new-todo-form";
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+// Whether a todo was created from the calendar form, which wants the todo placed on its day
+// instead of a list item.
+pub fn is_calendar_request(headers: &HeaderMap) -> bool {
+    headers
+        .get("hx-target")
+        .is_some_and(|target| target == NEW_TODO_FORM_ID)
+}
+
+fn day_id(date: Date) -> String {
+    format!("day-{}", date)
+}
+
+fn month_url(year: i32, month: Month) -> String {
+    routes::CalendarMonth::url(year, month as u8)
+}
+
+// the month before or after `month`, across years
+fn shift(year: i32, month: Month, forward: bool) -> (i32, Month) {
+    match (forward, month) {
+        (true, Month::December) => (year + 1, Month::January),
+        (false, Month::January) => (year - 1, Month::December),
+        (true, month) => (year, month.next()),
+        (false, month) => (year, month.previous()),
+    }
+}
+
+// blank cells before the 1st so it lands under its weekday, and the days in the month
+fn grid(year: i32, month: Month) -> Result<(u8, u8), AppError> {
+    let first = Date::from_calendar_date(year, month, 1).map_err(|_| AppError::NotFound)?;
+    Ok((
+        first.weekday().number_days_from_monday(),
+        time::util::days_in_year_month(year, month),
+    ))
+}
+
+fn parse_month(year: i32, month: u8) -> Result<Month, AppError> {
+    let month = Month::try_from(month).map_err(|_| AppError::NotFound)?;
+    grid(year, month)?;
+    Ok(month)
+}
+
+async fn todos_by_day(
+    state: &AppState,
+    tenant: &Tenant,
+    year: i32,
+    month: Month,
+) -> Result<HashMap<Date, Vec<Todo>>, AppError> {
+    let first = Date::from_calendar_date(year, month, 1)?;
+    let last = Date::from_calendar_date(year, month, time::util::days_in_year_month(year, month))?;
+    let todos = TodoQuery::new()
+        .due_between(first, last)
+        .list(&state.read().await.for_tenant(tenant.id())?)?;
+    let mut days: HashMap<Date, Vec<Todo>> = HashMap::new();
+    for todo in todos {
+        if let Some(due) = todo.due {
+            days.entry(due).or_default().push(todo);
+        }
+    }
+    Ok(days)
+}
+
+// === Components ===
+fn entry_html(todo: &Todo) -> Markup {
+    html! {
+        li class={"text-sm truncate " @if todo.completed { "line-through text-gray-400" } @else { "text-gray-700" }} {
+            (todo.title)
+        }
+    }
+}
+
+// a todo created from the calendar, appended to its day out of band
+pub fn entry_oob_html(todo: &Todo) -> Markup {
+    html! {
+        @if let Some(due) = todo.due {
+            div hx-swap-oob={ "beforeend:#" (day_id(due)) } { (entry_html(todo)) }
+            (views::notice_toast_oob(&format!("\"{}\" is due on {}.", todo.title, due)))
+        }
+    }
+}
+
+fn new_todo_html(due: Date) -> Markup {
+    html! {
+        form id=(NEW_TODO_FORM_ID) class="flex justify-between items-center space-x-4" method="post" action=(routes::CreateTodo::url())
+            hx-put=(routes::CreateTodo::url()) hx-swap="none" "hx-on::after-request"="if (event.detail.successful) this.reset()" {
+            input type="hidden" name=(method_override::METHOD_FIELD) value="PUT";
+            input class="w-full rounded p-2" type="text" name="title" placeholder="New Todo" required autofocus;
+            input class="rounded p-2" type="date" name="due" value=(due) required;
+            button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" { "Add" }
+        }
+    }
+}
+
+fn day_html(date: Date, today: Date, todos: &[Todo]) -> Markup {
+    let url = routes::CalendarDay::url(date.year(), date.month() as u8, date.day());
+    html! {
+        div class={"bg-white rounded p-2 min-h-[6rem] cursor-pointer hover:bg-blue-50 " @if date == today { "ring-2 ring-blue-500" }}
+            hx-get=(url) hx-target={ "#" (NEW_TODO_ID) } {
+            // the link is the way in without javascript, with it the cell handles the click
+            a class="text-sm text-gray-500" href=(url) hx-get=(url) hx-target={ "#" (NEW_TODO_ID) } hx-trigger="click consume" { (date.day()) }
+            ul id=(day_id(date)) {
+                @for todo in todos {
+                    (entry_html(todo))
+                }
+            }
+        }
+    }
+}
+
+fn month_html(
+    year: i32,
+    month: Month,
+    days: &HashMap<Date, Vec<Todo>>,
+    new_todo: Option<Date>,
+    today: Date,
+) -> Result<Markup, AppError> {
+    let (offset, count) = grid(year, month)?;
+    let (prev_year, prev_month) = shift(year, month, false);
+    let (next_year, next_month) = shift(year, month, true);
+    let prev = month_url(prev_year, prev_month);
+    let next = month_url(next_year, next_month);
+    Ok(html! {
+        div id=(CALENDAR_ID) class="space-y-4" {
+            div class="flex items-center justify-between" {
+                a class="text-blue-500 hover:text-blue-700" href=(prev) hx-get=(prev) hx-target={ "#" (CALENDAR_ID) }
+                    hx-swap="outerHTML" hx-push-url="true" { "← " (prev_month) }
+                h2 class="text-2xl text-gray-700" { (month) " " (year) }
+                a class="text-blue-500 hover:text-blue-700" href=(next) hx-get=(next) hx-target={ "#" (CALENDAR_ID) }
+                    hx-swap="outerHTML" hx-push-url="true" { (next_month) " →" }
+            }
+            div id=(NEW_TODO_ID) {
+                @if let Some(due) = new_todo {
+                    (new_todo_html(due))
+                }
+            }
+            div class="grid grid-cols-7 gap-1" {
+                @for weekday in WEEKDAYS {
+                    div class="text-center text-sm text-gray-500" { (weekday) }
+                }
+                @for _ in 0..offset {
+                    div {}
+                }
+                @for day in 1..=count {
+                    @let date = Date::from_calendar_date(year, month, day).map_err(|_| AppError::NotFound)?;
+                    (day_html(date, today, days.get(&date).map(Vec::as_slice).unwrap_or_default()))
+                }
+            }
+        }
+    })
+}
+
+async fn month_page(
+    state: &AppState,
+    tenant: &Tenant,
+    headers: &HeaderMap,
+    year: i32,
+    month: Month,
+    new_todo: Option<Date>,
+) -> Result<Markup, AppError> {
+    let days = todos_by_day(state, tenant, year, month).await?;
+    let calendar = month_html(year, month, &days, new_todo, state.clock.now().date())?;
+    if views::wants_fragment(headers) {
+        return Ok(calendar);
+    }
+    Ok(views::page(
+        "Calendar",
+        html! {
+            (nav::navigation(&Nav::calendar()))
+            h1 class="text-4xl text-center text-gray-700 mb-6" { "Calendar" }
+            (calendar)
+        },
+    ))
+}
+
+// === Routes ===
+pub async fn calendar(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+) -> Result<Markup, AppError> {
+    let today = state.clock.now().date();
+    month_page(&state, &tenant, &headers, today.year(), today.month(), None).await
+}
+
+pub async fn month(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Path((year, month)): Path<(i32, u8)>,
+) -> Result<Markup, AppError> {
+    let month = parse_month(year, month)?;
+    month_page(&state, &tenant, &headers, year, month, None).await
+}
+
+// the create form for a day, or without javascript its month with the form filled in
+pub async fn day(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Path((year, month, day)): Path<(i32, u8, u8)>,
+) -> Result<Markup, AppError> {
+    let month = parse_month(year, month)?;
+    let date = Date::from_calendar_date(year, month, day).map_err(|_| AppError::NotFound)?;
+    if views::wants_fragment(&headers) {
+        return Ok(new_todo_html(date));
+    }
+    month_page(&state, &tenant, &headers, year, month, Some(date)).await
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shift_crosses_years() {
+        assert_eq!(shift(2024, Month::December, true), (2025, Month::January));
+        assert_eq!(shift(2024, Month::January, false), (2023, Month::December));
+        assert_eq!(shift(2024, Month::March, true), (2024, Month::April));
+    }
+
+    #[test]
+    fn test_grid() {
+        // 2024-02-01 is a thursday, and 2024 is a leap year
+        assert_eq!(grid(2024, Month::February).unwrap(), (3, 29));
+        assert_eq!(grid(2023, Month::February).unwrap(), (2, 28));
+    }
+}
@@ -0,0 +1,136 @@
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    response::Response,
+    Extension,
+};
+use maud::{html, Markup};
+use serde::Deserialize;
+
+use crate::{
+    auth::visitor::Visitor, error::AppError, extract::FormOrJson, models::Todo, routes,
+    services::todo::TodoService, state::AppState, tenant::Tenant, views,
+};
+
+const CANCEL_ID: &str = "cancel";
+const MAX_REASON_LEN: usize = 200;
+
+// === Cancelling ===
+// A todo that will not be done is cancelled rather than checked off, so stats can tell the two
+// apart. It leaves the open todos like a done one and is reopened the same way.
+
+// the reason of a form field, empty for none
+pub fn parse_reason(value: &str) -> Result<Option<String>, AppError> {
+    let reason = value.trim();
+    if reason.chars().count() > MAX_REASON_LEN {
+        return Err(AppError::Invalid(format!(
+            "Keep the reason under {} characters.",
+            MAX_REASON_LEN
+        )));
+    }
+    Ok((!reason.is_empty()).then(|| reason.to_string()))
+}
+
+// === Components ===
+// the mark of a cancelled item, its reason on hover
+pub fn badge_html(todo: &Todo) -> Markup {
+    html! {
+        @if todo.is_cancelled() {
+            span class="ml-2 text-xs t-muted rounded border px-2 py-1" title=[todo.resolution.as_ref().and_then(|resolution| resolution.reason())] {
+                "✕ Cancelled"
+            }
+        }
+    }
+}
+
+// Cancel a todo with a reason from its detail view, or see why it was.
+pub fn section_html(todo: &Todo) -> Markup {
+    let url = routes::TodoCancel::url(todo.id);
+    html! {
+        section id=(CANCEL_ID) class="space-y-2" {
+            @if todo.is_cancelled() {
+                h2 class="text-xl text-gray-700" { "Cancelled" }
+                p class="text-gray-600" {
+                    @match todo.resolution.as_ref().and_then(|resolution| resolution.reason()) {
+                        Some(reason) => { (reason) }
+                        None => { "No reason was given." }
+                    }
+                }
+            } @else if !todo.completed {
+                h2 class="text-xl text-gray-700" { "Won't do" }
+                form class="flex items-center" method="post" action=(url) hx-post=(url) hx-target={ "#" (CANCEL_ID) } hx-swap="outerHTML" {
+                    input class="flex-grow rounded p-2 mr-2 border" type="text" name="reason" maxlength=(MAX_REASON_LEN)
+                        placeholder="Why not, optional" aria-label="Reason";
+                    button class="bg-gray-500 hover:bg-gray-700 text-white font-bold py-1 px-2 rounded" type="submit" { "Cancel todo" }
+                }
+            }
+        }
+    }
+}
+
+// === Routes ===
+#[derive(Deserialize)]
+pub struct CancelForm {
+    #[serde(default)]
+    reason: String,
+}
+
+// Cancel todo `id`, it counts as finished but not as done.
+pub async fn cancel(
+    State(mut app_state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Extension(Visitor(visitor)): Extension<Visitor>,
+    Path(id): Path<u64>,
+    FormOrJson(CancelForm { reason }): FormOrJson<CancelForm>,
+) -> Result<Response, AppError> {
+    let reason = parse_reason(&reason)?;
+    let state = app_state.clone();
+    let guard = app_state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    let todo = TodoService::new(&state, &db, tenant.id())
+        .update(id, Some(visitor.as_str()), |todo| {
+            todo.cancel(reason);
+            Ok(())
+        })
+        .await?;
+    let fragment = html! {
+        (section_html(&todo))
+        (views::notice_toast_oob(&format!("\"{}\" is cancelled.", todo.title)))
+    };
+    Ok(views::fragment_or_redirect(
+        &headers,
+        fragment,
+        &routes::TodoDetail::url(todo.id),
+    ))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_reason() {
+        assert_eq!(
+            parse_reason(" not needed ").unwrap(),
+            Some("not needed".to_string())
+        );
+        assert_eq!(parse_reason("  ").unwrap(), None);
+        assert!(parse_reason(&"x".repeat(MAX_REASON_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn test_section_html() {
+        let mut todo = Todo::new(1, "Learn the banjo".into());
+        assert!(section_html(&todo)
+            .into_string()
+            .contains(r#"action="/todos/1/cancel""#));
+        todo.cancel(Some("no time".into()));
+        let html = section_html(&todo).into_string();
+        assert!(html.contains("no time") && !html.contains("<form"));
+        assert!(badge_html(&todo)
+            .into_string()
+            .contains(r#"title="no time""#));
+    }
+}
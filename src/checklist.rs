@@ -0,0 +1,202 @@
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    response::Response,
+};
+use maud::{html, Markup};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::AppError,
+    extract::FormOrJson,
+    models::{ChecklistItem, Todo},
+    repository::entity::Repository,
+    routes,
+    state::AppState,
+    tenant::Tenant,
+    views::{
+        self,
+        hx::{Hx, Swap, Target},
+    },
+};
+
+const CHECKLIST_ID: &str = "checklist";
+const MAX_ITEMS: usize = 50;
+const MAX_TEXT: usize = 200;
+
+// === Components ===
+// "3/5" with a bar, on the list item of a todo with a checklist
+pub fn progress_html(todo: &Todo) -> Markup {
+    html! {
+        @if let Some((done, total)) = todo.checklist_progress() {
+            span class="ml-2 inline-flex items-center text-xs t-muted" title="Checklist" {
+                progress class="w-12 h-1.5 mr-1" value=(done) max=(total) {}
+                (done) "/" (total)
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AddItem {
+    text: String,
+}
+
+// the checklist on the detail view, every change answers with it again
+pub fn checklist_html(todo: &Todo) -> Markup {
+    let add = Hx::post(routes::TodoChecklist::url(todo.id))
+        .target(Target::Id(CHECKLIST_ID))
+        .swap(Swap::OuterHtml);
+    html! {
+        section id=(CHECKLIST_ID) class="space-y-2" {
+            h2 class="text-xl text-gray-700" {
+                "Checklist"
+                @if let Some((done, total)) = todo.checklist_progress() {
+                    span class="ml-2 text-sm t-muted" { (done) "/" (total) }
+                }
+            }
+            ul class="space-y-1" {
+                @for (index, item) in todo.checklist.iter().enumerate() {
+                    @let url = routes::TodoChecklistItem::url(todo.id, index);
+                    li class="flex items-center" {
+                        form class="flex-grow" method="post" action=(url) {
+                            label {
+                                input type="checkbox" class="mr-2" checked[item.done]
+                                    hx-post=(url) hx-target={ "#" (CHECKLIST_ID) } hx-swap="outerHTML";
+                                span class=[item.done.then_some("line-through t-muted")] { (item.text) }
+                            }
+                            noscript { button class="ml-2 text-blue-500" type="submit" { "Toggle" } }
+                        }
+                        button class="ml-2 text-red-500 hover:text-red-700" type="button" aria-label={ "Remove " (item.text) }
+                            hx-delete=(url) hx-target={ "#" (CHECKLIST_ID) } hx-swap="outerHTML" { "×" }
+                    }
+                }
+            }
+            @if todo.checklist.len() < MAX_ITEMS {
+                form class="flex" method="post" action=(routes::TodoChecklist::url(todo.id))
+                    hx-post=[add.post_path()] hx-target=[add.target_attr()] hx-swap=[add.swap_attr()] {
+                    input class="flex-grow rounded p-1 mr-2" type="text" name="text" maxlength=(MAX_TEXT)
+                        required placeholder="Add an item" aria-label="Checklist item";
+                    button class="text-blue-500 hover:text-blue-700" type="submit" { "Add" }
+                }
+            }
+        }
+    }
+}
+
+// === Routes ===
+// Load the todo, change its checklist with `change` and store it.
+async fn update(
+    state: &mut AppState,
+    tenant: &Tenant,
+    id: u64,
+    change: impl FnOnce(&mut Vec<ChecklistItem>) -> Result<(), AppError>,
+) -> Result<Todo, AppError> {
+    let writes = state.writes.clone();
+    let guard = state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    let todos = Repository::new(&db);
+    let mut todo: Todo = todos.get(id)?.ok_or(AppError::NotFound)?;
+    change(&mut todo.checklist)?;
+    todo.touch(&**db.clock());
+    writes.submit(&db, todos.put_ops(&todo)?).await?;
+    Ok(todo)
+}
+
+pub async fn add(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Path(id): Path<u64>,
+    FormOrJson(AddItem { text }): FormOrJson<AddItem>,
+) -> Result<Response, AppError> {
+    let text = text.trim().to_string();
+    if text.is_empty() || text.chars().count() > MAX_TEXT {
+        return Err(AppError::Invalid(format!(
+            "A checklist item needs 1 to {} characters.",
+            MAX_TEXT
+        )));
+    }
+    let todo = update(&mut state, &tenant, id, |checklist| {
+        if checklist.len() >= MAX_ITEMS {
+            return Err(AppError::Invalid(format!(
+                "A checklist holds at most {} items.",
+                MAX_ITEMS
+            )));
+        }
+        checklist.push(ChecklistItem { text, done: false });
+        Ok(())
+    })
+    .await?;
+    Ok(views::fragment_or_redirect(
+        &headers,
+        checklist_html(&todo),
+        &routes::TodoDetail::url(id),
+    ))
+}
+
+pub async fn toggle(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Path((id, index)): Path<(u64, usize)>,
+) -> Result<Response, AppError> {
+    let todo = update(&mut state, &tenant, id, |checklist| {
+        let item = checklist.get_mut(index).ok_or(AppError::NotFound)?;
+        item.done = !item.done;
+        Ok(())
+    })
+    .await?;
+    Ok(views::fragment_or_redirect(
+        &headers,
+        checklist_html(&todo),
+        &routes::TodoDetail::url(id),
+    ))
+}
+
+pub async fn remove(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Path((id, index)): Path<(u64, usize)>,
+) -> Result<Response, AppError> {
+    let todo = update(&mut state, &tenant, id, |checklist| {
+        if index >= checklist.len() {
+            return Err(AppError::NotFound);
+        }
+        checklist.remove(index);
+        Ok(())
+    })
+    .await?;
+    Ok(views::fragment_or_redirect(
+        &headers,
+        checklist_html(&todo),
+        &routes::TodoDetail::url(id),
+    ))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress() {
+        let mut todo = Todo::new(1, "Pack".into());
+        assert_eq!(todo.checklist_progress(), None);
+        assert!(progress_html(&todo).into_string().is_empty());
+        todo.checklist = ["passport", "charger", "socks"]
+            .into_iter()
+            .enumerate()
+            .map(|(i, text)| ChecklistItem {
+                text: text.into(),
+                done: i < 2,
+            })
+            .collect();
+        assert_eq!(todo.checklist_progress(), Some((2, 3)));
+        assert!(progress_html(&todo).into_string().contains("2/3"));
+        let checklist = checklist_html(&todo).into_string();
+        assert!(checklist.contains(r#"hx-post="/todos/1/checklist/2""#));
+        assert!(checklist.contains(r#"hx-delete="/todos/1/checklist/0""#));
+    }
+}
@@ -0,0 +1,412 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::{
+    api::typescript,
+    clock::SystemClock,
+    config::Config,
+    db::{driver::Db, lock::ReadOnlyCopy, migrations},
+    doctor,
+    models::Todo,
+    restore, state,
+};
+
+// === Commands ===
+// One-off admin tasks run against the database instead of starting the server, so a deployment
+// can be looked after over SSH. sled locks the database, stop the server first or look at a copy
+// with `--read-only`.
+//
+//     rust-htmx db stats
+//     rust-htmx db get <key> [--tenant <id>]
+//     rust-htmx db dump [--prefix <prefix>] [--tenant <id>]
+//     rust-htmx backup now [--to <dir>]      (defaults to `backups/` in the data dir)
+//     rust-htmx backup list
+//     rust-htmx backup restore <name>        (a backup in `backups/`, the next start uses it)
+//     rust-htmx backup rollback              (back to the database before the last restore)
+//     rust-htmx --migrate-dry-run
+//     rust-htmx --reencrypt
+//     rust-htmx --doctor                     (what keeps the instance from starting, see `doctor`)
+//     rust-htmx gen-ts [--out <file>]         (the TypeScript API client, printed without --out)
+//
+// `--data-dir <dir>` picks the database for the server and every command alike. `--read-only`
+// runs the commands that only read (db, --migrate-dry-run, backup now --to) against a copy of the
+// database, so they work while the server holds it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    // rewrite existing values with the newest encryption key
+    Reencrypt,
+    // list the migrations the next start would apply
+    MigrateDryRun,
+    DbStats,
+    DbGet {
+        tenant: Option<String>,
+        key: String,
+    },
+    DbDump {
+        tenant: Option<String>,
+        prefix: String,
+    },
+    BackupNow {
+        to: Option<PathBuf>,
+    },
+    BackupList,
+    BackupRestore {
+        name: String,
+    },
+    BackupRollback,
+    GenTs {
+        out: Option<PathBuf>,
+    },
+    Doctor,
+}
+impl Command {
+    // the command `args` (without the program name) ask for, `None` starts the server
+    pub fn parse(args: &[String]) -> Result<Option<Self>> {
+        if args.iter().any(|arg| arg == "--reencrypt") {
+            return Ok(Some(Self::Reencrypt));
+        }
+        if args.iter().any(|arg| arg == "--migrate-dry-run") {
+            return Ok(Some(Self::MigrateDryRun));
+        }
+        if args.iter().any(|arg| arg == "--doctor") {
+            return Ok(Some(Self::Doctor));
+        }
+        let mut positional = Vec::new();
+        let mut options = BTreeMap::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.strip_prefix("--") {
+                Some(name) => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| anyhow!("--{} needs a value", name))?;
+                    options.insert(name, value.clone());
+                }
+                None => positional.push(arg.as_str()),
+            }
+        }
+        let mut option = |name: &str| options.remove(name);
+        let command = match positional.as_slice() {
+            [] => return Ok(None),
+            ["db", "stats"] => Self::DbStats,
+            ["db", "get", key] => Self::DbGet {
+                tenant: option("tenant"),
+                key: key.to_string(),
+            },
+            ["db", "dump"] => Self::DbDump {
+                tenant: option("tenant"),
+                prefix: option("prefix").unwrap_or_default(),
+            },
+            ["backup", "now"] => Self::BackupNow {
+                to: option("to").map(PathBuf::from),
+            },
+            ["backup", "list"] => Self::BackupList,
+            ["backup", "restore", name] => Self::BackupRestore {
+                name: name.to_string(),
+            },
+            ["backup", "rollback"] => Self::BackupRollback,
+            ["gen-ts"] => Self::GenTs {
+                out: option("out").map(PathBuf::from),
+            },
+            other => bail!("unknown command `{}`", other.join(" ")),
+        };
+        if let Some(name) = options.keys().next() {
+            bail!("`{}` does not take --{}", positional.join(" "), name);
+        }
+        Ok(Some(command))
+    }
+
+    // the command leaves the database and the data directory as they are
+    pub fn reads_only(&self) -> bool {
+        match self {
+            Self::MigrateDryRun | Self::DbStats | Self::DbGet { .. } | Self::DbDump { .. } => true,
+            // a backup to the data directory would end up in the copy
+            Self::BackupNow { to } => to.is_some(),
+            _ => false,
+        }
+    }
+}
+
+// Take `name <value>` out of `args`, for options that apply whether or not a command is given.
+pub fn take_option(args: &mut Vec<String>, name: &str) -> Result<Option<String>> {
+    let Some(at) = args.iter().position(|arg| arg == name) else {
+        return Ok(None);
+    };
+    if at + 1 >= args.len() {
+        bail!("{} needs a value", name);
+    }
+    let value = args.remove(at + 1);
+    args.remove(at);
+    Ok(Some(value))
+}
+
+// Run `command` against a copy of the database, which another process may hold.
+pub fn run_read_only(command: Command, config: &Config) -> Result<()> {
+    if !command.reads_only() {
+        bail!("--read-only works with db stats, db get, db dump, --migrate-dry-run and backup now --to");
+    }
+    let copy = ReadOnlyCopy::new(&config.db_path())?;
+    let config = Config {
+        data_dir: copy.data_dir().to_path_buf(),
+        ..config.clone()
+    };
+    run(command, &config)
+}
+
+pub fn run(command: Command, config: &Config) -> Result<()> {
+    match command {
+        Command::Reencrypt => {
+            // migrations would read values that may not be encrypted yet
+            let db = state::open_db_unmigrated(config)?;
+            let rewritten = db.reencode_all()?;
+            println!("Re-encrypted {} values", rewritten);
+        }
+        Command::MigrateDryRun => {
+            let db = state::open_db_unmigrated(config)?;
+            let mut pending = 0;
+            for tree in migrations::status(&db)? {
+                let name = tree.tenant.as_deref().unwrap_or("default workspace");
+                for entry in tree.pending() {
+                    println!("{}: {} {}", name, entry.version, entry.name);
+                    pending += 1;
+                }
+            }
+            println!("{} pending migrations", pending);
+        }
+        Command::DbStats => stats(&state::open_db(config)?)?,
+        Command::DbGet { tenant, key } => {
+            let db = state::open_db(config)?.for_tenant(tenant.as_deref())?;
+            match render(&db, &key)? {
+                Some(value) => println!("{}", value),
+                None => bail!("no value at `{}`", key),
+            }
+        }
+        Command::DbDump { tenant, prefix } => {
+            let db = state::open_db(config)?.for_tenant(tenant.as_deref())?;
+            for key in db.iter_keys(&prefix).collect::<Result<Vec<_>>>()? {
+                if let Some(value) = render(&db, &key)? {
+                    println!("{}\t{}", key, value);
+                }
+            }
+        }
+        Command::BackupNow { to } => {
+            let db = state::open_db(config)?;
+            let to = match to {
+                Some(to) => to,
+                None => config
+                    .backups_dir()
+                    .join(format!("db-{}", db.clock().now_millis() / 1000)),
+            };
+            db.backup(&to)?;
+            println!("Backed up to {}", to.display());
+        }
+        Command::BackupList => {
+            for backup in restore::list(config)? {
+                println!("{}", backup.name);
+            }
+        }
+        Command::BackupRestore { name } => {
+            let staged = restore::stage(config, &SystemClock, &name)?;
+            restore::activate(config, &staged.path)?;
+            println!(
+                "Restored `{}` to {}, {} todos in {} trees",
+                name,
+                staged.path.display(),
+                staged.report.todos,
+                staged.report.trees
+            );
+        }
+        Command::BackupRollback => {
+            let (path, _) = restore::stage_rollback(config)?;
+            restore::activate(config, &path)?;
+            println!("Rolled back to {}", path.display());
+        }
+        Command::GenTs { out } => {
+            let ts = typescript::generate();
+            match out {
+                Some(out) => {
+                    std::fs::write(&out, ts)?;
+                    println!("Wrote {}", out.display());
+                }
+                None => print!("{}", ts),
+            }
+        }
+        Command::Doctor => {
+            let report = doctor::diagnose(config, None);
+            print!("{}", report);
+            if report.failures() > 0 {
+                bail!("{} checks failed", report.failures());
+            }
+        }
+    }
+    Ok(())
+}
+
+// === Inspection ===
+// keys per tree and per prefix, the part of a key up to its first `:`
+fn stats(db: &Db) -> Result<()> {
+    println!("{} bytes on disk", db.size_on_disk()?);
+    let mut tenants = vec![None];
+    tenants.extend(db.tenant_ids()?.into_iter().map(Some));
+    for tenant in tenants {
+        let tree = db.for_tenant(tenant.as_deref())?;
+        println!(
+            "{}: {} keys",
+            tenant.as_deref().unwrap_or("default workspace"),
+            tree.len()
+        );
+        for (prefix, keys) in count_prefixes(tree.iter_keys(""))? {
+            println!("  {:<24} {}", prefix, keys);
+        }
+    }
+    Ok(())
+}
+
+fn count_prefixes(keys: impl Iterator<Item = Result<String>>) -> Result<BTreeMap<String, usize>> {
+    let mut counts = BTreeMap::new();
+    for key in keys {
+        let key = key?;
+        let prefix = match key.split_once(':') {
+            Some((prefix, _)) => format!("{}:", prefix),
+            None => key,
+        };
+        *counts.entry(prefix).or_default() += 1;
+    }
+    Ok(counts)
+}
+
+// Values are stored without their type, todos are shown as json, strings quoted and anything
+// else as the hex of its payload.
+fn render(db: &Db, key: &str) -> Result<Option<String>> {
+    if key.starts_with("todo:") {
+        if let Ok(Some(todo)) = db.get::<Todo, _>(key) {
+            return Ok(Some(serde_json::to_string(&todo)?));
+        }
+    }
+    if let Ok(Some(text)) = db.get::<String, _>(key) {
+        return Ok(Some(format!("{:?}", text)));
+    }
+    Ok(db.get_payload(key)?.map(hex::encode))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{TestDb, TestDir};
+
+    fn parse(args: &str) -> Result<Option<Command>> {
+        let args: Vec<String> = args.split_whitespace().map(String::from).collect();
+        Command::parse(&args)
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("").unwrap(), None);
+        assert_eq!(parse("--reencrypt").unwrap(), Some(Command::Reencrypt));
+        assert_eq!(parse("--doctor").unwrap(), Some(Command::Doctor));
+        assert_eq!(parse("db stats").unwrap(), Some(Command::DbStats));
+        assert_eq!(
+            parse("db get todo:1 --tenant acme").unwrap(),
+            Some(Command::DbGet {
+                tenant: Some("acme".into()),
+                key: "todo:1".into(),
+            })
+        );
+        assert_eq!(
+            parse("db dump --prefix goal:").unwrap(),
+            Some(Command::DbDump {
+                tenant: None,
+                prefix: "goal:".into(),
+            })
+        );
+        assert_eq!(
+            parse("backup now").unwrap(),
+            Some(Command::BackupNow { to: None })
+        );
+        assert_eq!(
+            parse("backup restore db-1700000000").unwrap(),
+            Some(Command::BackupRestore {
+                name: "db-1700000000".into(),
+            })
+        );
+        assert_eq!(
+            parse("backup rollback").unwrap(),
+            Some(Command::BackupRollback)
+        );
+        assert_eq!(
+            parse("gen-ts --out web/api.ts").unwrap(),
+            Some(Command::GenTs {
+                out: Some("web/api.ts".into()),
+            })
+        );
+        assert!(parse("db drop").is_err());
+        assert!(parse("db stats --prefix todo:").is_err());
+        assert!(parse("db dump --prefix").is_err());
+    }
+
+    #[test]
+    fn test_reads_only() {
+        assert!(Command::DbStats.reads_only());
+        assert!(Command::MigrateDryRun.reads_only());
+        assert!(!Command::BackupNow { to: None }.reads_only());
+        assert!(Command::BackupNow {
+            to: Some("/mnt/backups/db".into())
+        }
+        .reads_only());
+        assert!(!Command::Reencrypt.reads_only());
+        assert!(!Command::BackupRollback.reads_only());
+    }
+
+    #[test]
+    fn test_take_option() -> Result<()> {
+        let mut args: Vec<String> = ["db", "stats", "--data-dir", "/srv/todos"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(
+            take_option(&mut args, "--data-dir")?.as_deref(),
+            Some("/srv/todos")
+        );
+        assert_eq!(args, ["db", "stats"]);
+        assert_eq!(take_option(&mut args, "--data-dir")?, None);
+        let mut args = vec!["--data-dir".to_string()];
+        assert!(take_option(&mut args, "--data-dir").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_count_prefixes() -> Result<()> {
+        let keys = ["todo:1", "todo:2", "goal:1", "schema_version"]
+            .into_iter()
+            .map(|key| Ok(key.to_string()));
+        let counts = count_prefixes(keys)?;
+        assert_eq!(counts["todo:"], 2);
+        assert_eq!(counts["goal:"], 1);
+        assert_eq!(counts["schema_version"], 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_and_backup() -> Result<()> {
+        let db = TestDb::new("cli")?;
+        db.insert("todo:1", &Todo::new(1, "Milk".into()))?;
+        db.insert("greeting", &"hello".to_string())?;
+        db.insert("count", &7u32)?;
+
+        assert!(render(&db, "todo:1")?
+            .unwrap()
+            .contains("\"title\":\"Milk\""));
+        assert_eq!(render(&db, "greeting")?.unwrap(), "\"hello\"");
+        assert_eq!(render(&db, "count")?.unwrap(), "07");
+        assert_eq!(render(&db, "missing")?, None);
+
+        let backup = TestDir::new("cli_backup")?;
+        db.backup(std::path::Path::new(backup.path()))?;
+        let copy = Db::new_with_path(backup.path())?;
+        assert_eq!(copy.get::<Todo, _>("todo:1")?.unwrap().title, "Milk");
+        // never overwrites an earlier backup
+        assert!(db.backup(std::path::Path::new(backup.path())).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,68 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use time::OffsetDateTime;
+
+// === Clock ===
+// Where time-based features read the time from, so tests can fast-forward it instead of
+// sleeping. The database carries one, for ttls and anything computed from stored data.
+pub trait Clock: fmt::Debug + Send + Sync {
+    fn now(&self) -> OffsetDateTime;
+
+    // milliseconds since the unix epoch
+    fn now_millis(&self) -> u64 {
+        (self.now().unix_timestamp_nanos() / 1_000_000).max(0) as u64
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+impl Clock for SystemClock {
+    fn now(&self) -> OffsetDateTime {
+        OffsetDateTime::now_utc()
+    }
+}
+
+// Stands still until moved, clones share the same time.
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    now: Arc<Mutex<OffsetDateTime>>,
+}
+impl MockClock {
+    pub fn new(now: OffsetDateTime) -> Self {
+        Self {
+            now: Arc::new(Mutex::new(now)),
+        }
+    }
+    pub fn set(&self, now: OffsetDateTime) {
+        *self.now.lock().expect("clock lock poisoned") = now;
+    }
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().expect("clock lock poisoned") += by;
+    }
+}
+impl Clock for MockClock {
+    fn now(&self) -> OffsetDateTime {
+        *self.now.lock().expect("clock lock poisoned")
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+
+    #[test]
+    fn test_mock_clock_advances() {
+        let clock = MockClock::new(datetime!(2024-03-06 23:30 UTC));
+        let shared = clock.clone();
+        shared.advance(Duration::from_secs(3600));
+        assert_eq!(clock.now(), datetime!(2024-03-07 00:30 UTC));
+        assert_eq!(clock.now_millis(), 1_709_771_400_000);
+    }
+}
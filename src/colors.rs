@@ -0,0 +1,191 @@
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    response::Response,
+};
+use maud::{html, Markup};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    db::driver::Db,
+    error::AppError,
+    extract::FormOrJson,
+    method_override, routes,
+    state::AppState,
+    tenant::Tenant,
+    views::{self, dom},
+};
+
+// swatches added in settings, `swatch:{rrggbb}`
+const SWATCH_PREFIX: &str = "swatch:";
+const LABEL_LEN: usize = 40;
+// the palette every workspace starts with
+const DEFAULT_SWATCHES: &[(&str, &str)] = &[
+    ("#ef4444", "Red"),
+    ("#f59e0b", "Amber"),
+    ("#10b981", "Green"),
+    ("#3b82f6", "Blue"),
+    ("#8b5cf6", "Violet"),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Swatch {
+    // `#rrggbb`
+    pub color: String,
+    pub label: String,
+}
+
+fn swatch_key(color: &str) -> String {
+    format!("{}{}", SWATCH_PREFIX, color.trim_start_matches('#'))
+}
+
+// Parse a `#rrggbb` color, lowercased. An empty value means no color.
+pub fn parse_color(value: &str) -> Result<Option<String>, AppError> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(None);
+    }
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(AppError::Invalid(format!(
+            "`{}` is not a #rrggbb color",
+            value
+        )));
+    }
+    Ok(Some(format!("#{}", hex.to_ascii_lowercase())))
+}
+
+pub fn custom(db: &Db) -> anyhow::Result<Vec<Swatch>> {
+    db.iter_prefix::<Swatch>(SWATCH_PREFIX)?
+        .map(|item| item.map(|(_, swatch)| swatch))
+        .collect()
+}
+
+// the defaults followed by the workspace's own swatches
+pub fn palette(db: &Db) -> anyhow::Result<Vec<Swatch>> {
+    let mut swatches: Vec<Swatch> = DEFAULT_SWATCHES
+        .iter()
+        .map(|(color, label)| Swatch {
+            color: color.to_string(),
+            label: label.to_string(),
+        })
+        .collect();
+    swatches.extend(custom(db)?);
+    Ok(swatches)
+}
+
+// === Components ===
+fn swatch_dot(color: &str) -> Markup {
+    html! {
+        span class="inline-block w-5 h-5 rounded-full border" style={ "background-color: " (color) } {}
+    }
+}
+
+// The swatches a todo's color is picked from, swapped in next to the todo.
+pub fn palette_html(todo: u64, swatches: &[Swatch], current: Option<&str>) -> Markup {
+    let url = routes::TodoColor::url(todo);
+    let target = dom::selector(&dom::todo(todo));
+    html! {
+        form class="flex items-center space-x-1" method="post" action=(url) hx-post=(url) hx-target=(target) hx-swap="outerHTML" {
+            @for swatch in swatches {
+                button class={ "rounded-full " @if current == Some(swatch.color.as_str()) { "ring-2 ring-gray-700" } }
+                    type="submit" name="color" value=(swatch.color) title=(swatch.label) aria-label=(swatch.label) {
+                    (swatch_dot(&swatch.color))
+                }
+            }
+            button class="text-xs text-gray-500 hover:text-gray-700" type="submit" name="color" value="" { "None" }
+        }
+    }
+}
+
+// palette management, a section of the settings page
+pub fn settings_html(custom: &[Swatch]) -> Markup {
+    html! {
+        section id="swatches" class="bg-white rounded-lg shadow-lg p-6 space-y-4" {
+            h2 class="text-2xl text-gray-700" { "Colors" }
+            div class="flex space-x-2" {
+                @for (color, label) in DEFAULT_SWATCHES {
+                    span title=(label) { (swatch_dot(color)) }
+                }
+            }
+            ul class="space-y-2" {
+                @for swatch in custom {
+                    li class="flex items-center space-x-2" {
+                        (swatch_dot(&swatch.color))
+                        span class="flex-grow text-gray-700" { (swatch.label) }
+                        form method="post" action=(routes::Swatch::url(swatch.color.trim_start_matches('#'))) {
+                            input type="hidden" name=(method_override::METHOD_FIELD) value="DELETE";
+                            button class="text-red-500 hover:text-red-700" type="submit"
+                                hx-delete=(routes::Swatch::url(swatch.color.trim_start_matches('#'))) hx-target="#swatches" hx-swap="outerHTML" { "Remove" }
+                        }
+                    }
+                }
+            }
+            form class="flex items-center space-x-2" method="post" action=(routes::Swatches::url())
+                hx-post=(routes::Swatches::url()) hx-target="#swatches" hx-swap="outerHTML" {
+                input type="color" name="color" value="#64748b" aria-label="Color";
+                input class="flex-grow rounded border p-2" type="text" name="label" placeholder="Label" maxlength=(LABEL_LEN) required;
+                button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" { "Add color" }
+            }
+        }
+    }
+}
+
+// === Routes ===
+#[derive(Deserialize)]
+pub struct AddSwatch {
+    color: String,
+    label: String,
+}
+pub async fn add_swatch(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    FormOrJson(AddSwatch { color, label }): FormOrJson<AddSwatch>,
+) -> Result<Response, AppError> {
+    let color = parse_color(&color)?.ok_or_else(|| AppError::Invalid("Pick a color.".into()))?;
+    let guard = state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    let swatch = Swatch {
+        label: label.trim().chars().take(LABEL_LEN).collect(),
+        color,
+    };
+    db.insert(swatch_key(&swatch.color), &swatch)?;
+    Ok(views::fragment_or_redirect(
+        &headers,
+        settings_html(&custom(&db)?),
+        &routes::Settings::url(),
+    ))
+}
+
+// Todos keep a removed color, it just cannot be picked anymore.
+pub async fn remove_swatch(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Path(color): Path<String>,
+) -> Result<Response, AppError> {
+    let guard = state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    db.remove(swatch_key(&color))?;
+    Ok(views::fragment_or_redirect(
+        &headers,
+        settings_html(&custom(&db)?),
+        &routes::Settings::url(),
+    ))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("#FF00aa").unwrap(), Some("#ff00aa".to_string()));
+        assert_eq!(parse_color("ff00aa").unwrap(), Some("#ff00aa".to_string()));
+        assert_eq!(parse_color(" ").unwrap(), None);
+        assert!(parse_color("#ff00").is_err());
+        assert!(parse_color("red;}").is_err());
+    }
+}
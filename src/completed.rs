@@ -0,0 +1,36 @@
+use crate::{db::driver::Db, sorting::View};
+
+// the section the completed todos are collapsed into
+pub const COMPLETED_ID: &str = "completed";
+
+// === Completed Section ===
+// Completed todos sit below the open ones in a section that is collapsed unless opened. Whether
+// it is open is remembered per person and per list, `completed_open:{view}:{visitor}`.
+fn key(view: View, visitor: &str) -> String {
+    format!("completed_open:{}:{}", view.as_str(), visitor)
+}
+
+pub fn is_open(db: &Db, view: View, visitor: &str) -> anyhow::Result<bool> {
+    Ok(db.get(key(view, visitor))?.unwrap_or(false))
+}
+pub fn set_open(db: &Db, view: View, visitor: &str, open: bool) -> anyhow::Result<()> {
+    db.insert(key(view, visitor), &open)
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::TestDb;
+
+    #[test]
+    fn test_per_visitor() -> anyhow::Result<()> {
+        let db = TestDb::new("completed")?;
+        assert!(!is_open(&db, View::List, "a")?);
+        set_open(&db, View::List, "a", true)?;
+        assert!(is_open(&db, View::List, "a")?);
+        assert!(!is_open(&db, View::List, "b")?);
+        assert!(!is_open(&db, View::Board, "a")?);
+        Ok(())
+    }
+}
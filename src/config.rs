@@ -0,0 +1,526 @@
+use std::{net::SocketAddr, path::PathBuf, str::FromStr, time::Duration};
+
+use anyhow::{anyhow, Context, Result};
+use time::{macros::format_description, Date};
+
+use crate::{db::queue::WriteMode, reload};
+
+// === Config ===
+#[derive(Debug, Clone)]
+pub struct Config {
+    // where the server listens, `LISTEN_ADDR`
+    pub listen_addr: SocketAddr,
+    // the database and backups live here, `DATA_DIR` or `--data-dir`
+    pub data_dir: PathBuf,
+    // wait this long for another process to let go of the database before giving up, `None` (0)
+    // gives up right away
+    pub db_lock_wait: Option<Duration>,
+    pub log_format: LogFormat,
+    // `tracing` directives like `debug,sled=warn`, `RUST_LOG` or `info` when unset
+    pub log_level: Option<String>,
+    // `KEY=VALUE` lines overriding the reloadable settings, watched while running, see `reload`
+    pub config_file: Option<PathBuf>,
+    // errors and panics are reported to sentry when set (requires the `sentry` feature)
+    pub sentry_dsn: Option<String>,
+    // how long read-only routes may take before answering with a timeout
+    pub read_timeout: Duration,
+    // how long mutating routes may take before answering with a timeout
+    pub write_timeout: Duration,
+    // how long a shutdown waits for open connections, e.g. event streams, before closing anyway
+    pub shutdown_grace: Duration,
+    // requests handled at once, anything above is shed with a 503
+    pub concurrency_limit: usize,
+    // Connection tuning, see `server::Tuning`. HTTP/2 is spoken to clients asking for it, e.g. a
+    // proxy in front, and carries every event stream of a browser over one connection.
+    pub http2: bool,
+    // streams on one HTTP/2 connection, each open page holds one for its events
+    pub http2_max_concurrent_streams: u32,
+    // ping idle HTTP/2 connections this often, `None` (0) never
+    pub http2_keep_alive_interval: Option<Duration>,
+    // close a connection whose ping went unanswered this long
+    pub http2_keep_alive_timeout: Duration,
+    // reuse HTTP/1.1 connections for further requests
+    pub keep_alive: bool,
+    // send event stream messages right away instead of waiting to fill a packet
+    pub tcp_nodelay: bool,
+    // open event streams in total and per person, see `events::Connections`
+    pub sse_max_connections: usize,
+    pub sse_max_per_user: usize,
+    // how often an idle event stream sends a comment to keep it open
+    pub sse_heartbeat: Duration,
+    // apply mutations in the handler or through the background write queue
+    pub write_mode: WriteMode,
+    // values are encrypted at rest with the keys in this file when set
+    pub encryption_keyfile: Option<PathBuf>,
+    // values at least this many bytes long are zstd compressed when set
+    pub compression_threshold: Option<usize>,
+    // how requests are mapped to isolated workspaces
+    pub tenancy: Tenancy,
+    // password for the `/admin` pages, when unset the account created at `/setup` is used
+    pub admin_password: Option<String>,
+    // lock a login subject out for the failure window after this many failures
+    pub login_lockout_after: Option<u32>,
+    // hex key signing cookies, a random one is generated per start when unset
+    pub secret_key: Option<String>,
+    // the url browsers reach the instance at, passkeys are bound to its host
+    pub public_url: String,
+    // open todos untouched for this long come up in the weekly review
+    pub review_stale_after: Duration,
+    // someday todos put aside this many months ago come up in the monthly aging report
+    pub someday_stale_months: u32,
+    // tags none of whose todos changed for this long are archived, off when unset
+    pub tag_archive_after: Option<Duration>,
+    // how long the purge jobs keep todo history, subscription digests, the history of removed
+    // todos and dev mode request recordings, each kept for good when unset, see `retention`
+    pub retention_activity: Option<Duration>,
+    pub retention_notifications: Option<Duration>,
+    pub retention_deleted_todos: Option<Duration>,
+    pub retention_recordings: Option<Duration>,
+    // minutes of estimated work a day holds, creating todos past it warns
+    pub daily_capacity_minutes: Option<u32>,
+    // a Nominatim instance to geocode `near:` locations with, they are kept as text when unset
+    pub nominatim_url: Option<String>,
+    // how long a kiosk display shows one panel before moving on, it refreshes at the same time
+    pub kiosk_rotate_secs: u64,
+    // the `frame-ancestors` sources allowed to frame embeds, e.g. `https://*.notion.so`
+    pub embed_frame_ancestors: String,
+    // html files standing in for built-in components, read at startup, see `views::overrides`
+    pub template_dir: Option<PathBuf>,
+    // fetch OpenGraph previews of links in todos, off unless set since it reaches out to them
+    pub link_previews: bool,
+    // api versions on their way out, their responses announce it with `Deprecation`/`Sunset`
+    pub api_deprecations: Vec<ApiDeprecation>,
+    // api requests a user may make per window, unlimited when unset, see `api::rate_limit`
+    pub api_rate_limit: Option<u32>,
+    pub api_rate_window: Duration,
+    // whether visitors may sign up for a workspace, overrides the admin setting when set
+    pub registration_open: Option<bool>,
+    // who may use the todo pages, `AUTH_MODE`
+    pub auth_mode: AuthMode,
+    // list refreshes only send the items that changed since the session's last render
+    pub list_diffing: bool,
+    // a whisper.cpp server (or an OpenAI-compatible transcription url) for adding todos by voice
+    pub transcription_url: Option<String>,
+    // an OpenAI-compatible api for breaking todos down into steps, off when unset
+    pub assistant_url: Option<String>,
+    pub assistant_model: String,
+    pub assistant_api_key: Option<String>,
+    // queued outbound tasks (webhooks, link previews) attempted at the same time
+    pub queue_workers: usize,
+    // todo changes are posted here as json, through the task queue, for lists without a webhook
+    // of their own in the settings
+    pub webhook_url: Option<String>,
+    // the secret senders sign `/hooks/todos` requests with, the endpoint is off when unset, see
+    // `inbound`
+    pub inbound_webhook_secret: Option<String>,
+    // every domain event is appended to this file as a json line, off when unset, see `audit`
+    pub audit_log: Option<PathBuf>,
+    // the audit file is rotated to `{path}.1` past this size
+    pub audit_log_max_bytes: u64,
+    // keep the app read-only whatever the admin page says, see `maintenance`
+    pub maintenance_mode: bool,
+    // with `TENANT_DOMAIN`, visitors of the base domain get a workspace of their own before
+    // signing up, see `guest`
+    pub guest_mode: bool,
+    // keep the latest requests and responses for `/dev/requests` and show the request toolbar,
+    // not for production
+    pub dev_mode: bool,
+    // count page views and daily visitors for `/admin/analytics`, kept here and never sent
+    // anywhere
+    pub analytics: bool,
+    // content types that may be attached to todos, `image/*` for a family, any when empty
+    pub attachment_types: Vec<String>,
+    // the largest file that may be attached
+    pub attachment_max_bytes: usize,
+    // other sites whose scripts may call `/api/*` with the visitor's cookies, `*` for any site
+    // without them, see `api::cors`
+    pub cors_allowed_origins: Vec<String>,
+    // what they may send, the defaults of `api::cors` when empty
+    pub cors_allowed_methods: Vec<String>,
+    pub cors_allowed_headers: Vec<String>,
+}
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            listen_addr: SocketAddr::from(([0, 0, 0, 0], 3000)),
+            data_dir: PathBuf::from("."),
+            db_lock_wait: None,
+            log_format: LogFormat::default(),
+            log_level: None,
+            config_file: None,
+            sentry_dsn: None,
+            read_timeout: Duration::from_secs(10),
+            write_timeout: Duration::from_secs(5),
+            shutdown_grace: Duration::from_secs(10),
+            concurrency_limit: 64,
+            http2: true,
+            http2_max_concurrent_streams: 200,
+            http2_keep_alive_interval: Some(Duration::from_secs(30)),
+            http2_keep_alive_timeout: Duration::from_secs(20),
+            keep_alive: true,
+            tcp_nodelay: true,
+            sse_max_connections: 1000,
+            sse_max_per_user: 10,
+            sse_heartbeat: Duration::from_secs(15),
+            write_mode: WriteMode::default(),
+            encryption_keyfile: None,
+            compression_threshold: None,
+            tenancy: Tenancy::default(),
+            admin_password: None,
+            login_lockout_after: None,
+            secret_key: None,
+            public_url: "http://localhost:3000".to_string(),
+            review_stale_after: Duration::from_secs(14 * 24 * 60 * 60),
+            someday_stale_months: 3,
+            tag_archive_after: None,
+            retention_activity: None,
+            retention_notifications: None,
+            retention_deleted_todos: None,
+            retention_recordings: None,
+            daily_capacity_minutes: None,
+            nominatim_url: None,
+            kiosk_rotate_secs: 30,
+            embed_frame_ancestors: "*".to_string(),
+            template_dir: None,
+            link_previews: false,
+            api_deprecations: Vec::new(),
+            api_rate_limit: None,
+            api_rate_window: Duration::from_secs(60 * 60),
+            registration_open: None,
+            auth_mode: AuthMode::default(),
+            list_diffing: false,
+            transcription_url: None,
+            assistant_url: None,
+            assistant_model: "gpt-4o-mini".to_string(),
+            assistant_api_key: None,
+            queue_workers: 4,
+            webhook_url: None,
+            inbound_webhook_secret: None,
+            audit_log: None,
+            audit_log_max_bytes: 64 * 1024 * 1024,
+            maintenance_mode: false,
+            guest_mode: false,
+            dev_mode: false,
+            analytics: false,
+            attachment_types: ["image/*", "application/pdf", "text/plain"]
+                .map(String::from)
+                .to_vec(),
+            attachment_max_bytes: 10 * 1024 * 1024,
+            cors_allowed_origins: Vec::new(),
+            cors_allowed_methods: Vec::new(),
+            cors_allowed_headers: Vec::new(),
+        }
+    }
+}
+impl Config {
+    // read the configuration from the process environment
+    pub fn from_env() -> Result<Self> {
+        let mut config = Self::default();
+        if let Some(addr) = env_parse("LISTEN_ADDR")? {
+            config.listen_addr = addr;
+        }
+        if let Some(dir) = env_parse("DATA_DIR")? {
+            config.data_dir = dir;
+        }
+        if let Some(secs) = env_parse::<u64>("DB_LOCK_WAIT_SECS")? {
+            config.db_lock_wait = (secs > 0).then(|| Duration::from_secs(secs));
+        }
+        if let Some(format) = env_parse("LOG_FORMAT")? {
+            config.log_format = format;
+        }
+        config.log_level = env_parse("LOG_LEVEL")?;
+        config.sentry_dsn = std::env::var("SENTRY_DSN")
+            .ok()
+            .filter(|dsn| !dsn.is_empty());
+        if let Some(secs) = env_parse("READ_TIMEOUT_SECS")? {
+            config.read_timeout = Duration::from_secs(secs);
+        }
+        if let Some(secs) = env_parse("WRITE_TIMEOUT_SECS")? {
+            config.write_timeout = Duration::from_secs(secs);
+        }
+        if let Some(secs) = env_parse("SHUTDOWN_GRACE_SECS")? {
+            config.shutdown_grace = Duration::from_secs(secs);
+        }
+        if let Some(limit) = env_parse("CONCURRENCY_LIMIT")? {
+            config.concurrency_limit = limit;
+        }
+        if let Some(enabled) = env_parse("HTTP2")? {
+            config.http2 = enabled;
+        }
+        if let Some(streams) = env_parse("HTTP2_MAX_CONCURRENT_STREAMS")? {
+            config.http2_max_concurrent_streams = streams;
+        }
+        if let Some(secs) = env_parse::<u64>("HTTP2_KEEP_ALIVE_SECS")? {
+            config.http2_keep_alive_interval = (secs > 0).then(|| Duration::from_secs(secs));
+        }
+        if let Some(secs) = env_parse("HTTP2_KEEP_ALIVE_TIMEOUT_SECS")? {
+            config.http2_keep_alive_timeout = Duration::from_secs(secs);
+        }
+        if let Some(enabled) = env_parse("KEEP_ALIVE")? {
+            config.keep_alive = enabled;
+        }
+        if let Some(enabled) = env_parse("TCP_NODELAY")? {
+            config.tcp_nodelay = enabled;
+        }
+        if let Some(max) = env_parse("SSE_MAX_CONNECTIONS")? {
+            config.sse_max_connections = max;
+        }
+        if let Some(max) = env_parse("SSE_MAX_PER_USER")? {
+            config.sse_max_per_user = max;
+        }
+        if let Some(secs) = env_parse("SSE_HEARTBEAT_SECS")? {
+            config.sse_heartbeat = Duration::from_secs(secs);
+        }
+        if let Some(mode) = env_parse("WRITE_MODE")? {
+            config.write_mode = mode;
+        }
+        config.encryption_keyfile = env_parse("ENCRYPTION_KEYFILE")?;
+        config.compression_threshold = env_parse("COMPRESSION_THRESHOLD")?;
+        if let Some(base_domain) = env_parse("TENANT_DOMAIN")? {
+            config.tenancy = Tenancy::Subdomain { base_domain };
+        }
+        config.admin_password = env_parse("ADMIN_PASSWORD")?;
+        config.login_lockout_after = env_parse("LOGIN_LOCKOUT_AFTER")?;
+        config.secret_key = env_parse("SECRET_KEY")?;
+        if let Some(public_url) = env_parse("PUBLIC_URL")? {
+            config.public_url = public_url;
+        }
+        if let Some(days) = env_parse::<u64>("REVIEW_STALE_DAYS")? {
+            config.review_stale_after = Duration::from_secs(days * 24 * 60 * 60);
+        }
+        if let Some(months) = env_parse("SOMEDAY_STALE_MONTHS")? {
+            config.someday_stale_months = months;
+        }
+        config.tag_archive_after = env_parse::<u64>("TAG_ARCHIVE_AFTER_DAYS")?
+            .map(|days| Duration::from_secs(days * 24 * 60 * 60));
+        let days = |name: &str| -> Result<Option<Duration>> {
+            Ok(env_parse::<u64>(name)?.map(|days| Duration::from_secs(days * 24 * 60 * 60)))
+        };
+        config.retention_activity = days("RETENTION_ACTIVITY_DAYS")?;
+        config.retention_notifications = days("RETENTION_NOTIFICATIONS_DAYS")?;
+        config.retention_deleted_todos = days("RETENTION_DELETED_TODOS_DAYS")?;
+        config.retention_recordings = env_parse::<u64>("RETENTION_RECORDINGS_HOURS")?
+            .map(|hours| Duration::from_secs(hours * 60 * 60));
+        config.daily_capacity_minutes = env_parse("DAILY_CAPACITY_MINUTES")?;
+        config.nominatim_url = env_parse("NOMINATIM_URL")?;
+        if let Some(secs) = env_parse("KIOSK_ROTATE_SECS")? {
+            config.kiosk_rotate_secs = secs;
+        }
+        if let Some(sources) = env_parse("EMBED_FRAME_ANCESTORS")? {
+            config.embed_frame_ancestors = sources;
+        }
+        config.template_dir = env_parse("TEMPLATE_DIR")?;
+        if let Some(enabled) = env_parse("LINK_PREVIEWS")? {
+            config.link_previews = enabled;
+        }
+        if let Some(deprecations) = env_parse::<String>("API_DEPRECATIONS")? {
+            config.api_deprecations = deprecations
+                .split(',')
+                .map(|deprecation| deprecation.trim().parse())
+                .collect::<Result<_>>()
+                .context("invalid value for API_DEPRECATIONS")?;
+        }
+        config.api_rate_limit = env_parse("API_RATE_LIMIT")?;
+        if let Some(secs) = env_parse("API_RATE_WINDOW_SECS")? {
+            config.api_rate_window = Duration::from_secs(secs);
+        }
+        config.registration_open = env_parse("REGISTRATION_OPEN")?;
+        if let Some(mode) = env_parse("AUTH_MODE")? {
+            config.auth_mode = mode;
+        }
+        if let Some(enabled) = env_parse("LIST_DIFFING")? {
+            config.list_diffing = enabled;
+        }
+        config.transcription_url = env_parse("TRANSCRIPTION_URL")?;
+        config.assistant_url = env_parse("ASSISTANT_URL")?;
+        if let Some(model) = env_parse("ASSISTANT_MODEL")? {
+            config.assistant_model = model;
+        }
+        config.assistant_api_key = env_parse("ASSISTANT_API_KEY")?;
+        if let Some(workers) = env_parse("QUEUE_WORKERS")? {
+            config.queue_workers = workers;
+        }
+        config.webhook_url = env_parse("WEBHOOK_URL")?;
+        config.inbound_webhook_secret = env_parse("INBOUND_WEBHOOK_SECRET")?;
+        config.audit_log = env_parse("AUDIT_LOG")?;
+        if let Some(bytes) = env_parse("AUDIT_LOG_MAX_BYTES")? {
+            config.audit_log_max_bytes = bytes;
+        }
+        if let Some(enabled) = env_parse("MAINTENANCE_MODE")? {
+            config.maintenance_mode = enabled;
+        }
+        if let Some(enabled) = env_parse("GUEST_MODE")? {
+            config.guest_mode = enabled;
+        }
+        if let Some(enabled) = env_parse("DEV_MODE")? {
+            config.dev_mode = enabled;
+        }
+        if let Some(enabled) = env_parse("ANALYTICS")? {
+            config.analytics = enabled;
+        }
+        if let Some(types) = env_parse::<String>("ATTACHMENT_TYPES")? {
+            config.attachment_types = types
+                .split(',')
+                .map(str::trim)
+                .filter(|kind| !kind.is_empty() && *kind != "*")
+                .map(String::from)
+                .collect();
+        }
+        if let Some(bytes) = env_parse("ATTACHMENT_MAX_BYTES")? {
+            config.attachment_max_bytes = bytes;
+        }
+        let list = |name: &str| -> Result<Vec<String>> {
+            Ok(parse_list(&env_parse::<String>(name)?.unwrap_or_default()))
+        };
+        config.cors_allowed_origins = list("CORS_ALLOWED_ORIGINS")?;
+        config.cors_allowed_methods = list("CORS_ALLOWED_METHODS")?;
+        config.cors_allowed_headers = list("CORS_ALLOWED_HEADERS")?;
+        config.config_file = env_parse("CONFIG_FILE")?;
+        if let Some(path) = &config.config_file {
+            let text = std::fs::read_to_string(path)
+                .with_context(|| format!("could not read {}", path.display()))?;
+            reload::apply_file(&mut config, &text)?;
+        }
+        Ok(config)
+    }
+}
+
+impl Config {
+    // `db`, unless a restore made another directory the live one, see `restore`
+    pub fn db_path(&self) -> PathBuf {
+        let active = std::fs::read_to_string(self.active_db_file()).ok();
+        match active.as_deref().map(str::trim) {
+            Some(name) if !name.is_empty() => self.data_dir.join(name),
+            _ => self.data_dir.join("db"),
+        }
+    }
+    pub fn active_db_file(&self) -> PathBuf {
+        self.data_dir.join("db.active")
+    }
+    pub fn backups_dir(&self) -> PathBuf {
+        self.data_dir.join("backups")
+    }
+}
+
+// parse an environment variable, unset or empty variables are `None`
+fn env_parse<T>(name: &str) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: Into<anyhow::Error>,
+{
+    parse_setting(name, &std::env::var(name).unwrap_or_default())
+}
+
+// the same for a value from elsewhere, like the config file
+pub fn parse_setting<T>(name: &str, value: &str) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: Into<anyhow::Error>,
+{
+    if value.is_empty() {
+        return Ok(None);
+    }
+    value
+        .parse()
+        .map(Some)
+        .map_err(Into::into)
+        .with_context(|| format!("invalid value for {}", name))
+}
+
+// the items of a comma separated setting
+pub fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+// how requests are mapped to workspaces
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Tenancy {
+    // everyone shares the default workspace
+    #[default]
+    Single,
+    // `{tenant}.{base_domain}` selects the tenant, `base_domain` itself the default workspace
+    Subdomain {
+        base_domain: String,
+    },
+}
+
+// who gets past the login, see `auth::user::CurrentUser`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AuthMode {
+    // a personal deployment, no login, only the signed visitor cookie
+    #[default]
+    SingleUser,
+    // every page needs a signed-in account
+    Accounts,
+    // like `Accounts`, and visitors sign up with a password for a todo list of their own, see
+    // `accounts`
+    Users,
+}
+impl FromStr for AuthMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "single-user" => Ok(Self::SingleUser),
+            "accounts" => Ok(Self::Accounts),
+            "users" => Ok(Self::Users),
+            other => Err(anyhow!("unknown auth mode `{}`", other)),
+        }
+    }
+}
+
+// how log lines are written to stdout
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    // human readable, for local development
+    #[default]
+    Pretty,
+    // one json object per line, for shipping logs to Loki/ELK
+    Json,
+}
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "pretty" => Ok(Self::Pretty),
+            "json" => Ok(Self::Json),
+            other => Err(anyhow!("unknown log format `{}`", other)),
+        }
+    }
+}
+
+// `v1=2025-01-01/2025-07-01`: `v1` is deprecated since the first date and goes away after the
+// second, which is optional
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiDeprecation {
+    pub version: String,
+    pub deprecated: Date,
+    pub sunset: Option<Date>,
+}
+impl FromStr for ApiDeprecation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let format = format_description!("[year]-[month]-[day]");
+        let (version, dates) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("expected `version=deprecated[/sunset]`, got `{}`", s))?;
+        let (deprecated, sunset) = match dates.split_once('/') {
+            Some((deprecated, sunset)) => (deprecated, Some(sunset)),
+            None => (dates, None),
+        };
+        Ok(Self {
+            version: version.to_string(),
+            deprecated: Date::parse(deprecated, format)?,
+            sunset: sunset
+                .map(|sunset| Date::parse(sunset, format))
+                .transpose()?,
+        })
+    }
+}
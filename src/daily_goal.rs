@@ -0,0 +1,200 @@
+use axum::{extract::State, http::HeaderMap, response::Response, Extension};
+use maud::{html, Markup};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::visitor::Visitor, db::driver::Db, error::AppError, extract::FormOrJson,
+    repository::query::TodoQuery, rollup, routes, state::AppState, tenant::Tenant, views,
+};
+
+// the ring in the navigation, swapped out of band whenever a todo is checked off
+const RING_ID: &str = "daily-goal";
+const SETTINGS_ID: &str = "daily-goal-settings";
+const MAX_TARGET: u32 = 100;
+// circumference of the ring, r = 10
+const RING_LENGTH: f64 = 62.83;
+
+// === Daily goal ===
+// How many todos someone means to finish a day. The ring in the navigation fills up as todos
+// of the workspace are done, reaching the goal celebrates once. Per visitor,
+// `daily_goal:{visitor}` in the workspace's tree, so everyone sharing it sets their own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DailyGoal {
+    pub enabled: bool,
+    pub target: u32,
+}
+impl Default for DailyGoal {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            target: 3,
+        }
+    }
+}
+
+fn goal_key(visitor: &str) -> String {
+    format!("daily_goal:{}", visitor)
+}
+
+pub fn get(db: &Db, visitor: &str) -> anyhow::Result<DailyGoal> {
+    Ok(db.get(goal_key(visitor))?.unwrap_or_default())
+}
+pub fn set(db: &Db, visitor: &str, goal: DailyGoal) -> anyhow::Result<()> {
+    db.insert(goal_key(visitor), &goal)
+}
+
+// todos finished today
+pub fn done_today(db: &Db) -> anyhow::Result<usize> {
+    let today = db.clock().now().date();
+    Ok(rollup::compute(&TodoQuery::new().list(db)?, today).completed)
+}
+
+// Checking off a todo just now reached the goal, unchecking and checking it again celebrates
+// again, which is fine.
+pub fn reached(goal: DailyGoal, done: usize, completed: bool) -> bool {
+    goal.enabled && completed && done == goal.target as usize
+}
+
+// === Components ===
+fn ring_html(goal: DailyGoal, done: usize, oob: bool, celebrate: bool) -> Markup {
+    let progress = (done as f64 / goal.target.max(1) as f64).min(1.0);
+    html! {
+        li id=(RING_ID) hx-swap-oob=[oob.then_some("true")] {
+            @if goal.enabled {
+                a class={ "flex items-center text-xs t-muted " @if celebrate { "confetti" } } href=(routes::Settings::url())
+                    title={ (done) " of " (goal.target) " done today" } {
+                    svg class="w-6 h-6 mr-1 -rotate-90" viewBox="0 0 24 24" aria-hidden="true" {
+                        circle cx="12" cy="12" r="10" fill="none" stroke="currentColor" stroke-opacity="0.2" stroke-width="3" {}
+                        circle class="t-success" cx="12" cy="12" r="10" fill="none" stroke="currentColor" stroke-width="3"
+                            stroke-dasharray=(RING_LENGTH) stroke-dashoffset=(format!("{:.2}", RING_LENGTH * (1.0 - progress))) {}
+                    }
+                    (done) "/" (goal.target)
+                }
+            }
+        }
+    }
+}
+
+// The ring for the navigation, and the celebration when `completed` reached the goal.
+pub fn progress_oob(db: &Db, visitor: &str, completed: bool) -> Result<Markup, AppError> {
+    let goal = get(db, visitor)?;
+    let done = done_today(db)?;
+    let celebrate = reached(goal, done, completed);
+    Ok(html! {
+        (ring_html(goal, done, true, celebrate))
+        @if celebrate {
+            (views::notice_toast_oob(&format!("🎉 Daily goal reached, {} todos done today!", done)))
+        }
+    })
+}
+
+pub fn settings_html(goal: DailyGoal) -> Markup {
+    html! {
+        section id=(SETTINGS_ID) class="bg-white rounded-lg shadow-lg p-6 space-y-4" {
+            h2 class="text-2xl text-gray-700" { "Daily goal" }
+            form class="space-y-2" method="post" action=(routes::DailyGoalSetting::url())
+                hx-post=(routes::DailyGoalSetting::url()) hx-trigger="change" hx-target={ "#" (SETTINGS_ID) } hx-swap="outerHTML" {
+                label class="flex items-center space-x-2" {
+                    input type="checkbox" name="enabled" value="true" checked[goal.enabled];
+                    span { "Show my progress and celebrate reaching the goal" }
+                }
+                label class="flex items-center space-x-2" {
+                    span class="w-24" { "Todos a day" }
+                    input class="w-24 rounded p-2 border" type="number" name="target" min="1" max=(MAX_TARGET) value=(goal.target);
+                }
+                noscript {
+                    button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" { "Save" }
+                }
+            }
+        }
+    }
+}
+
+// === Routes ===
+// the ring, loaded into the navigation
+pub async fn ring(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    Extension(Visitor(visitor)): Extension<Visitor>,
+) -> Result<Markup, AppError> {
+    let db = state.read().await.for_tenant(tenant.id())?;
+    Ok(ring_html(
+        get(&db, &visitor)?,
+        done_today(&db)?,
+        false,
+        false,
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct SetGoal {
+    // an unchecked checkbox is left out of the form
+    #[serde(default)]
+    enabled: bool,
+    target: u32,
+}
+pub async fn set_goal(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    Extension(Visitor(visitor)): Extension<Visitor>,
+    headers: HeaderMap,
+    FormOrJson(SetGoal { enabled, target }): FormOrJson<SetGoal>,
+) -> Result<Response, AppError> {
+    if !(1..=MAX_TARGET).contains(&target) {
+        return Err(AppError::Invalid(format!(
+            "The daily goal is between 1 and {} todos.",
+            MAX_TARGET
+        )));
+    }
+    let goal = DailyGoal { enabled, target };
+    let guard = state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    set(&db, &visitor, goal)?;
+    let fragment = html! {
+        (settings_html(goal))
+        (ring_html(goal, done_today(&db)?, true, false))
+    };
+    Ok(views::fragment_or_redirect(
+        &headers,
+        fragment,
+        &routes::Settings::url(),
+    ))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::TestDb;
+
+    #[test]
+    fn test_reached_once() {
+        let goal = DailyGoal {
+            enabled: true,
+            target: 2,
+        };
+        assert!(!reached(goal, 1, true));
+        assert!(reached(goal, 2, true));
+        // past it, or unchecking back to it, is no news
+        assert!(!reached(goal, 3, true));
+        assert!(!reached(goal, 2, false));
+        let off = DailyGoal {
+            enabled: false,
+            ..goal
+        };
+        assert!(!reached(off, 2, true));
+    }
+
+    #[test]
+    fn test_goal_per_visitor() -> anyhow::Result<()> {
+        let db = TestDb::new("daily_goal")?;
+        let goal = DailyGoal {
+            enabled: false,
+            target: 5,
+        };
+        set(&db, "a", goal)?;
+        assert_eq!(get(&db, "a")?, goal);
+        assert_eq!(get(&db, "b")?, DailyGoal::default());
+        Ok(())
+    }
+}
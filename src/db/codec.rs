@@ -0,0 +1,380 @@
+use std::{borrow::Cow, path::Path, sync::Arc};
+
+use anyhow::{anyhow, bail, Context, Result};
+use bincode::{
+    config::{BigEndian, WithOtherEndian},
+    DefaultOptions, Options,
+};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+// The layout values are written in, the database records it, see `Db::open`. Format 1 values
+// were plain bincode, they are only read to upgrade them.
+pub const FORMAT_VERSION: u8 = 2;
+const FLAG_ENCRYPTED: u8 = 0b0000_0001;
+const FLAG_COMPRESSED: u8 = 0b0000_0010;
+const NONCE_LEN: usize = 24;
+const COMPRESSION_LEVEL: i32 = 3;
+
+// === Codec ===
+// Turns values into the bytes stored in sled and back.
+//
+// Frame layout: `[flags]` followed by, when encrypted, `[key id][nonce][ciphertext]` or the body
+// itself otherwise. Compression happens before encryption. The `context` of a value, the tree and
+// key it is stored under, is authenticated along with it, so an encrypted value copied to another
+// key fails to decrypt.
+#[derive(Clone)]
+pub struct Codec {
+    options: WithOtherEndian<DefaultOptions, BigEndian>,
+    keyring: Option<Arc<Keyring>>,
+    // values at least this many bytes long are zstd compressed
+    compression_threshold: Option<usize>,
+}
+impl Default for Codec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Codec {
+    pub fn new() -> Self {
+        Self {
+            options: bincode::options().with_big_endian(),
+            keyring: None,
+            compression_threshold: None,
+        }
+    }
+    pub fn with_keyring(mut self, keyring: Keyring) -> Self {
+        self.keyring = Some(Arc::new(keyring));
+        self
+    }
+    pub fn with_compression(mut self, threshold: usize) -> Self {
+        self.compression_threshold = Some(threshold);
+        self
+    }
+
+    pub fn encode<T: Serialize>(&self, value: &T, context: &[u8]) -> Result<Vec<u8>> {
+        self.seal(self.serialize(value)?, context)
+    }
+    pub fn decode<T: DeserializeOwned>(&self, bytes: &[u8], context: &[u8]) -> Result<T> {
+        let payload = self.open(bytes, context)?;
+        let value = self.options.deserialize(&payload)?;
+        Ok(value)
+    }
+    // The serialized value before it is framed, for writes whose key is only known later.
+    pub fn serialize<T: Serialize>(&self, value: &T) -> Result<Vec<u8>> {
+        Ok(self.options.serialize(value)?)
+    }
+
+    // Whether `bytes` were not written with the current settings (e.g. with a retired key).
+    pub fn is_stale(&self, bytes: &[u8]) -> bool {
+        let Some((flags, body)) = bytes.split_first() else {
+            return true;
+        };
+        match &self.keyring {
+            None => flags & FLAG_ENCRYPTED != 0,
+            Some(keyring) => {
+                flags & FLAG_ENCRYPTED == 0 || body.first() != Some(&keyring.current_id())
+            }
+        }
+    }
+    // The serialized value inside `bytes`, decrypted and decompressed, for inspecting values
+    // whose type is not known.
+    pub fn payload(&self, bytes: &[u8], context: &[u8]) -> Result<Vec<u8>> {
+        Ok(self.open(bytes, context)?.into_owned())
+    }
+    // Re-frame `bytes` with the current settings without knowing the value type, bound to `to`
+    // instead of `from` when the value moves.
+    pub fn rewrite(&self, bytes: &[u8], from: &[u8], to: &[u8]) -> Result<Vec<u8>> {
+        let payload = self.open(bytes, from)?.into_owned();
+        self.seal(payload, to)
+    }
+    // `bytes` re-framed with the current settings where they are, for `Db::reencode_all`. The
+    // only read that takes a plain value while a keyring is set, encrypting them after the first
+    // key is added is what it is for.
+    pub fn reencode(&self, bytes: &[u8], context: &[u8]) -> Result<Vec<u8>> {
+        let payload = self.open_frame(bytes, context, true)?.into_owned();
+        self.seal(payload, context)
+    }
+    // A format 1 value in the current format.
+    pub fn upgrade(&self, bytes: &[u8], context: &[u8]) -> Result<Vec<u8>> {
+        self.seal(bytes.to_vec(), context)
+    }
+
+    pub fn seal(&self, payload: Vec<u8>, context: &[u8]) -> Result<Vec<u8>> {
+        let mut flags = 0;
+        let mut body = payload;
+        if let Some(threshold) = self.compression_threshold {
+            if body.len() >= threshold {
+                body = zstd::bulk::compress(&body, COMPRESSION_LEVEL)?;
+                flags |= FLAG_COMPRESSED;
+            }
+        }
+        let Some(keyring) = &self.keyring else {
+            let mut frame = Vec::with_capacity(1 + body.len());
+            frame.push(flags);
+            frame.extend_from_slice(&body);
+            return Ok(frame);
+        };
+        flags |= FLAG_ENCRYPTED;
+        let (id, cipher) = keyring.current();
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let aad = associated_data(flags, context);
+        let ciphertext = cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: &body,
+                    aad: &aad,
+                },
+            )
+            .map_err(|_| anyhow!("failed to encrypt value"))?;
+        let mut frame = Vec::with_capacity(2 + NONCE_LEN + ciphertext.len());
+        frame.extend_from_slice(&[flags, id]);
+        frame.extend_from_slice(&nonce);
+        frame.extend_from_slice(&ciphertext);
+        Ok(frame)
+    }
+    // With a keyring every value is encrypted, a plain one was not written by this codec and is
+    // refused rather than trusted.
+    fn open<'a>(&self, bytes: &'a [u8], context: &[u8]) -> Result<Cow<'a, [u8]>> {
+        self.open_frame(bytes, context, false)
+    }
+    fn open_frame<'a>(
+        &self,
+        bytes: &'a [u8],
+        context: &[u8],
+        allow_plain: bool,
+    ) -> Result<Cow<'a, [u8]>> {
+        let (flags, body) = bytes.split_first().context("value is empty")?;
+        if flags & FLAG_ENCRYPTED == 0 && self.keyring.is_some() && !allow_plain {
+            bail!("value is not encrypted, run `--reencrypt` to encrypt it");
+        }
+        let body = if flags & FLAG_ENCRYPTED != 0 {
+            Cow::Owned(self.decrypt(body, &associated_data(*flags, context))?)
+        } else {
+            Cow::Borrowed(body)
+        };
+        if flags & FLAG_COMPRESSED != 0 {
+            let payload = zstd::stream::decode_all(body.as_ref())?;
+            return Ok(Cow::Owned(payload));
+        }
+        Ok(body)
+    }
+    fn decrypt(&self, body: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        let keyring = self
+            .keyring
+            .as_ref()
+            .context("value is encrypted but no encryption key is configured")?;
+        if body.len() < 1 + NONCE_LEN {
+            bail!("encrypted value is truncated");
+        }
+        let (id, rest) = (body[0], &body[1..]);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        let cipher = keyring
+            .get(id)
+            .with_context(|| format!("value is encrypted with unknown key {}", id))?;
+        cipher
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad,
+                },
+            )
+            .map_err(|_| anyhow!("failed to decrypt value with key {}", id))
+    }
+}
+
+// the flags and where the value is stored, authenticated but not encrypted
+fn associated_data(flags: u8, context: &[u8]) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(1 + context.len());
+    aad.push(flags);
+    aad.extend_from_slice(context);
+    aad
+}
+
+// === Keyring ===
+// Encryption keys by id, new values are always written with the newest one.
+pub struct Keyring {
+    keys: Vec<(u8, XChaCha20Poly1305)>,
+}
+impl Keyring {
+    // One hex-encoded 32 byte key per line, the line number is the key id. Rotate by appending a
+    // new key and running `--reencrypt`, retired keys must stay in the file until then.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read keyfile {}", path.display()))?;
+        let keys = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| hex::decode(line).context("keyfile contains an invalid hex key"))
+            .collect::<Result<Vec<_>>>()?;
+        Self::from_keys(keys)
+    }
+    pub fn from_keys(keys: Vec<Vec<u8>>) -> Result<Self> {
+        if keys.is_empty() {
+            bail!("keyring needs at least one key");
+        }
+        if keys.len() > u8::MAX as usize {
+            bail!("keyring supports at most {} keys", u8::MAX);
+        }
+        let keys = keys
+            .iter()
+            .enumerate()
+            .map(|(index, key)| {
+                let cipher = XChaCha20Poly1305::new_from_slice(key)
+                    .map_err(|_| anyhow!("encryption keys must be 32 bytes"))?;
+                Ok((index as u8 + 1, cipher))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { keys })
+    }
+
+    fn current(&self) -> (u8, &XChaCha20Poly1305) {
+        let (id, cipher) = self.keys.last().expect("keyring is never empty");
+        (*id, cipher)
+    }
+    fn current_id(&self) -> u8 {
+        self.current().0
+    }
+    fn get(&self, id: u8) -> Option<&XChaCha20Poly1305> {
+        self.keys
+            .iter()
+            .find(|(key_id, _)| *key_id == id)
+            .map(|(_, cipher)| cipher)
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Test {
+        id: u64,
+        name: String,
+    }
+
+    fn test_value() -> Test {
+        Test {
+            id: 42,
+            name: "test".to_string(),
+        }
+    }
+    fn keyring(keys: &[u8]) -> Keyring {
+        Keyring::from_keys(keys.iter().map(|byte| vec![*byte; 32]).collect()).unwrap()
+    }
+
+    #[test]
+    fn test_plain_round_trip() -> Result<()> {
+        let codec = Codec::new();
+        let bytes = codec.encode(&test_value(), b"test")?;
+        assert_eq!(bytes[0], 0);
+        assert_eq!(codec.decode::<Test>(&bytes, b"test")?, test_value());
+        Ok(())
+    }
+
+    #[test]
+    fn test_encrypted_round_trip() -> Result<()> {
+        let codec = Codec::new().with_keyring(keyring(&[1]));
+        let bytes = codec.encode(&test_value(), b"test")?;
+        assert_eq!(bytes[0], FLAG_ENCRYPTED);
+        assert_eq!(codec.decode::<Test>(&bytes, b"test")?, test_value());
+        assert!(Codec::new().decode::<Test>(&bytes, b"test").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_encrypted_values_are_bound_to_their_context() -> Result<()> {
+        let codec = Codec::new().with_keyring(keyring(&[1]));
+        let bytes = codec.encode(&test_value(), b"tenant:acme\0todo:1")?;
+        assert!(codec
+            .decode::<Test>(&bytes, b"tenant:other\0todo:1")
+            .is_err());
+        assert!(codec
+            .decode::<Test>(&bytes, b"tenant:acme\0todo:2")
+            .is_err());
+        let moved = codec.rewrite(&bytes, b"tenant:acme\0todo:1", b"tenant:acme\0todo:2")?;
+        assert_eq!(
+            codec.decode::<Test>(&moved, b"tenant:acme\0todo:2")?,
+            test_value()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_plain_values_are_refused_with_a_keyring() -> Result<()> {
+        let plain = Codec::new().encode(&test_value(), b"test")?;
+        let codec = Codec::new().with_keyring(keyring(&[1]));
+        assert!(codec.decode::<Test>(&plain, b"test").is_err());
+        assert!(codec.rewrite(&plain, b"test", b"test").is_err());
+        // until they are re-encrypted
+        assert!(codec.is_stale(&plain));
+        let bytes = codec.reencode(&plain, b"test")?;
+        assert!(!codec.is_stale(&bytes));
+        assert_eq!(codec.decode::<Test>(&bytes, b"test")?, test_value());
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_one_values_are_upgraded() -> Result<()> {
+        let codec = Codec::new().with_keyring(keyring(&[1]));
+        let plain = codec.serialize(&test_value())?;
+        let bytes = codec.upgrade(&plain, b"test")?;
+        assert_eq!(codec.decode::<Test>(&bytes, b"test")?, test_value());
+        Ok(())
+    }
+
+    #[test]
+    fn test_compressed_round_trip() -> Result<()> {
+        let value = Test {
+            id: 42,
+            name: "compress me ".repeat(64),
+        };
+        let codec = Codec::new().with_compression(128);
+        let bytes = codec.encode(&value, b"test")?;
+        assert_eq!(bytes[0], FLAG_COMPRESSED);
+        assert!(bytes.len() < value.name.len());
+        assert_eq!(codec.decode::<Test>(&bytes, b"test")?, value);
+        // small values are left alone
+        assert_eq!(codec.encode(&test_value(), b"test")?[0], 0);
+        // reading compressed values does not depend on the threshold
+        assert_eq!(Codec::new().decode::<Test>(&bytes, b"test")?, value);
+        Ok(())
+    }
+
+    #[test]
+    fn test_compressed_and_encrypted_round_trip() -> Result<()> {
+        let value = Test {
+            id: 42,
+            name: "compress me ".repeat(64),
+        };
+        let codec = Codec::new()
+            .with_compression(128)
+            .with_keyring(keyring(&[1]));
+        let bytes = codec.encode(&value, b"test")?;
+        assert_eq!(bytes[0], FLAG_COMPRESSED | FLAG_ENCRYPTED);
+        assert_eq!(codec.decode::<Test>(&bytes, b"test")?, value);
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_rotation() -> Result<()> {
+        let old = Codec::new().with_keyring(keyring(&[1]));
+        let bytes = old.encode(&test_value(), b"test")?;
+        let rotated = Codec::new().with_keyring(keyring(&[1, 2]));
+        assert!(rotated.is_stale(&bytes));
+        let bytes = rotated.rewrite(&bytes, b"test", b"test")?;
+        assert!(!rotated.is_stale(&bytes));
+        assert_eq!(rotated.decode::<Test>(&bytes, b"test")?, test_value());
+        assert!(old.decode::<Test>(&bytes, b"test").is_err());
+        Ok(())
+    }
+}
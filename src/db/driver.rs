@@ -1,25 +1,288 @@
-use anyhow::Result;
-use bincode::{
-    config::{BigEndian, WithOtherEndian},
-    DefaultOptions, Options,
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
+
+use anyhow::{bail, Result};
 use serde::{de::DeserializeOwned, Serialize};
-use sled::Db as Sled;
+use sled::{
+    transaction::{
+        ConflictableTransactionError, TransactionError, TransactionResult, Transactional,
+        TransactionalTree, UnabortableTransactionError,
+    },
+    Db as Sled, Tree,
+};
+
+use super::{
+    codec::{self, Codec},
+    loader, lock,
+    queue::WriteOp,
+    snapshot::Snapshot,
+    ttl,
+};
+use crate::clock::{Clock, SystemClock};
 
+// tenant data lives in its own sled tree, `tenant:{id}`
+const TENANT_TREE_PREFIX: &str = "tenant:";
+// Tenant ids with a `:` are no tree of their own but a key prefix in the default tree,
+// `{id}:{key}`, e.g. the todos of an account are `user:{name}:todo:{id}`. Each one is listed
+// under `namespace:{id}`.
+const NAMESPACE_PREFIX: &str = "namespace:";
+// the tree recording which `codec::FORMAT_VERSION` the values are in, under `FORMAT_KEY`
+const FORMAT_TREE: &str = "format";
+const FORMAT_KEY: &str = "version";
+// Where the write generation of the next database opened starts. Each one starts far from the
+// others, so what a request kept from a database swapped out is never taken for the new one's.
+static OPENED: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Clone)]
 pub struct Db {
     handle: Sled,
-    encoder: WithOtherEndian<DefaultOptions, BigEndian>,
+    // the tree every key-value operation goes to, the default tree unless scoped to a tenant
+    tree: Tree,
+    // put in front of every key, `{id}:` for a namespace and empty otherwise
+    prefix: String,
+    codec: Codec,
+    // what ttls are measured against
+    clock: Arc<dyn Clock>,
+    // moved on by every write to any tree, see `loader`
+    generation: Arc<AtomicU64>,
 }
 impl Db {
     pub fn new() -> Result<Self> {
-        let handle = sled::open("db")?;
-        let encoder = bincode::options().with_big_endian();
-        Ok(Self { handle, encoder })
+        Self::open("db", Codec::new())
     }
     pub fn new_with_path(path: &str) -> Result<Self> {
-        let handle = sled::open(path)?;
-        let encoder = bincode::options().with_big_endian();
-        Ok(Self { handle, encoder })
+        Self::open(path, Codec::new())
+    }
+    pub fn open(path: impl AsRef<Path>, codec: Codec) -> Result<Self> {
+        let path = path.as_ref();
+        let handle = sled::open(path).map_err(|err| lock::classify(err, path))?;
+        let tree = Tree::clone(&handle);
+        let db = Self {
+            handle,
+            tree,
+            prefix: String::new(),
+            codec,
+            clock: Arc::new(SystemClock),
+            generation: Arc::new(AtomicU64::new(OPENED.fetch_add(1 << 32, Ordering::Relaxed))),
+        };
+        db.upgrade_format()?;
+        Ok(db)
+    }
+    // Bring every value to `codec::FORMAT_VERSION`. A database without a recorded format is
+    // from before it was recorded, its values are rewritten once and the format recorded. Each
+    // tree is marked done along with its values, an interrupted upgrade carries on where it was.
+    fn upgrade_format(&self) -> Result<()> {
+        let format = self.handle.open_tree(FORMAT_TREE)?;
+        match format.get(FORMAT_KEY)? {
+            Some(version) if *version == [codec::FORMAT_VERSION] => return Ok(()),
+            Some(version) => bail!("database is stored in unknown format {:?}", version),
+            None => {}
+        }
+        let mut upgraded_trees = Vec::new();
+        for name in self.handle.tree_names() {
+            if *name == *FORMAT_TREE.as_bytes() {
+                continue;
+            }
+            if format.contains_key(&name)? {
+                upgraded_trees.push(name);
+                continue;
+            }
+            let tree = self.handle.open_tree(&name)?;
+            let values = tree
+                .iter()
+                .map(|item| {
+                    let (key, value) = item?;
+                    let value = self.codec.upgrade(&value, &context(&name, &key))?;
+                    Ok((key, value))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let upgraded: TransactionResult<()> = (&tree, &format).transaction(|(tree, format)| {
+                for (key, value) in &values {
+                    tree.insert(key, value.clone())?;
+                }
+                format.insert(&name, vec![codec::FORMAT_VERSION])?;
+                Ok(())
+            });
+            upgraded.map_err(|err| anyhow::anyhow!("upgrading values failed: {:?}", err))?;
+            upgraded_trees.push(name);
+        }
+        format.insert(FORMAT_KEY, vec![codec::FORMAT_VERSION])?;
+        let mut markers = sled::Batch::default();
+        for name in upgraded_trees {
+            markers.remove(name);
+        }
+        format.apply_batch(markers)?;
+        self.handle.flush()?;
+        Ok(())
+    }
+    // the same database reading the time from `clock`
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+    pub fn clock(&self) -> &Arc<dyn Clock> {
+        &self.clock
+    }
+
+    // Tenants
+    // The same database scoped to the tree of `tenant`, or the default tree for `None`.
+    pub fn for_tenant(&self, tenant: Option<&str>) -> Result<Self> {
+        let Some(tenant) = tenant else {
+            return Ok(self.clone());
+        };
+        if tenant.contains(':') {
+            let root = Tree::clone(&self.handle);
+            let marker = format!("{}{}", NAMESPACE_PREFIX, tenant);
+            if !root.contains_key(&marker)? {
+                let value = self
+                    .codec
+                    .encode(&(), &context(&root.name(), marker.as_bytes()))?;
+                root.insert(marker, value)?;
+            }
+            return Ok(Self {
+                handle: self.handle.clone(),
+                tree: root,
+                prefix: format!("{}:", tenant),
+                codec: self.codec.clone(),
+                clock: self.clock.clone(),
+                generation: self.generation.clone(),
+            });
+        }
+        let tree = self
+            .handle
+            .open_tree(format!("{}{}", TENANT_TREE_PREFIX, tenant))?;
+        Ok(Self {
+            handle: self.handle.clone(),
+            tree,
+            prefix: String::new(),
+            codec: self.codec.clone(),
+            clock: self.clock.clone(),
+            generation: self.generation.clone(),
+        })
+    }
+    // Erase every key of `tenant`.
+    pub fn drop_tenant(&self, tenant: &str) -> Result<()> {
+        if tenant.contains(':') {
+            let root = Tree::clone(&self.handle);
+            let mut batch = sled::Batch::default();
+            for key in root.scan_prefix(format!("{}:", tenant)).keys() {
+                batch.remove(key?);
+            }
+            batch.remove(format!("{}{}", NAMESPACE_PREFIX, tenant).as_bytes());
+            root.apply_batch(batch)?;
+        } else {
+            self.handle
+                .drop_tree(format!("{}{}", TENANT_TREE_PREFIX, tenant))?;
+        }
+        self.written();
+        Ok(())
+    }
+    // Move every key of this tree into `target` in one transaction, keys already there are
+    // overwritten. Returns how many keys moved.
+    pub fn move_into(&self, target: &Db) -> Result<usize> {
+        let entries = self
+            .scan_raw("")
+            .map(|item| {
+                let (key, value) = item?;
+                let key = self.unprefixed(&key)?.to_string();
+                // values are bound to where they are stored, seal them again for the target
+                let value =
+                    self.codec
+                        .rewrite(&value, &self.context(&key), &target.context(&key))?;
+                Ok((key, value))
+            })
+            .collect::<Result<Vec<(String, Vec<u8>)>>>()?;
+        if self.tree.name() == target.tree.name() {
+            // namespaces of the same tree, sled can't take a tree twice in a transaction
+            let mut batch = sled::Batch::default();
+            for (key, value) in &entries {
+                batch.remove(self.key(key).as_bytes());
+                batch.insert(target.key(key).as_bytes(), value.clone());
+            }
+            self.tree.apply_batch(batch)?;
+        } else {
+            let moved: TransactionResult<()> =
+                (&self.tree, &target.tree).transaction(|(from, to)| {
+                    for (key, value) in &entries {
+                        to.insert(target.key(key).as_bytes(), value.clone())?;
+                        from.remove(self.key(key).as_bytes())?;
+                    }
+                    Ok(())
+                });
+            moved.map_err(|err| anyhow::anyhow!("moving keys failed: {:?}", err))?;
+        }
+        self.written();
+        Ok(entries.len())
+    }
+    // Ids of every tenant that has a tree or a namespace.
+    pub fn tenant_ids(&self) -> Result<Vec<String>> {
+        let mut ids = Vec::new();
+        for name in self.handle.tree_names() {
+            if let Some(id) = name.strip_prefix(TENANT_TREE_PREFIX.as_bytes()) {
+                ids.push(String::from_utf8(id.to_vec())?);
+            }
+        }
+        for key in Tree::clone(&self.handle)
+            .scan_prefix(NAMESPACE_PREFIX)
+            .keys()
+        {
+            let key = String::from_utf8(key?.to_vec())?;
+            ids.push(key[NAMESPACE_PREFIX.len()..].to_string());
+        }
+        Ok(ids)
+    }
+    // The default tree followed by every tenant tree.
+    pub fn all_trees(&self) -> Result<Vec<Self>> {
+        let mut trees = vec![self.for_tenant(None)?];
+        for id in self.tenant_ids()? {
+            trees.push(self.for_tenant(Some(&id))?);
+        }
+        Ok(trees)
+    }
+    // the tree, followed by the namespace for one
+    pub fn tree_name(&self) -> Vec<u8> {
+        let mut name = self.tree.name().to_vec();
+        name.extend_from_slice(self.prefix.as_bytes());
+        name
+    }
+    pub fn len(&self) -> usize {
+        if self.prefix.is_empty() {
+            return self.tree.len();
+        }
+        self.tree.scan_prefix(&self.prefix).count()
+    }
+    pub fn is_empty(&self) -> bool {
+        if self.prefix.is_empty() {
+            return self.tree.is_empty();
+        }
+        self.tree.scan_prefix(&self.prefix).next().is_none()
+    }
+
+    // the key in the tree for `key` of this namespace
+    fn key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+    fn unprefixed<'a>(&self, key: &'a [u8]) -> Result<&'a str> {
+        let key = std::str::from_utf8(key)?;
+        Ok(key.strip_prefix(self.prefix.as_str()).unwrap_or(key))
+    }
+    // what a value stored under `key` is bound to, see `Codec`
+    fn context(&self, key: &str) -> Vec<u8> {
+        context(&self.tree.name(), self.key(key).as_bytes())
+    }
+    fn scan_raw(&self, prefix: &str) -> sled::Iter {
+        self.tree.scan_prefix(self.key(prefix))
+    }
+    // after every write, what requests read before is outdated
+    fn written(&self) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        loader::invalidate();
     }
 
     // CRUD
@@ -29,50 +292,474 @@ impl Db {
     }
     pub fn insert<T: Serialize, K: AsRef<str>>(&self, key: K, value: &T) -> Result<()> {
         let key = key.as_ref();
-        let value = self.encoder.serialize(value)?;
-        self.handle.insert(key, value)?;
+        let value = self.codec.encode(value, &self.context(key))?;
+        let mut batch = sled::Batch::default();
+        batch.insert(self.key(key).as_bytes(), value);
+        // a plain insert makes the key permanent again
+        batch.remove(self.key(&ttl::expiry_key(key)).as_bytes());
+        self.tree.apply_batch(batch)?;
+        self.written();
         Ok(())
     }
+    // Insert a value the sweeper removes once `ttl` has passed.
+    pub fn insert_with_ttl<T: Serialize, K: AsRef<str>>(
+        &self,
+        key: K,
+        value: &T,
+        ttl: Duration,
+    ) -> Result<()> {
+        self.apply_batch(self.ttl_ops(key.as_ref(), value, ttl)?)
+    }
     pub fn get<T: DeserializeOwned, K: AsRef<str>>(&self, key: K) -> Result<Option<T>> {
         let key = key.as_ref();
-        let value = self.handle.get(key)?;
+        let value = self.read_live(key)?;
         let value = match value {
             Some(value) => value,
             None => return Ok(None),
         };
-        let value = self.encoder.deserialize(&value)?;
+        let value = self.codec.decode(&value, &self.context(key))?;
         Ok(Some(value))
     }
+    // the serialized value of `key` without decoding it into a type
+    pub fn get_payload<K: AsRef<str>>(&self, key: K) -> Result<Option<Vec<u8>>> {
+        let key = key.as_ref();
+        match self.read_live(key)? {
+            Some(value) => Ok(Some(self.codec.payload(&value, &self.context(key))?)),
+            None => Ok(None),
+        }
+    }
+    // A key whose ttl ran out reads as absent, whether or not the sweeper got to it yet.
+    fn read_live(&self, key: &str) -> Result<Option<sled::IVec>> {
+        let Some(value) = self.read(key)? else {
+            return Ok(None);
+        };
+        let expiry_key = ttl::expiry_key(key);
+        if let Some(expires_at) = self.read(&expiry_key)? {
+            let expires_at: u64 = self.codec.decode(&expires_at, &self.context(&expiry_key))?;
+            if expires_at <= self.clock.now_millis() {
+                return Ok(None);
+            }
+        }
+        Ok(Some(value))
+    }
+    // a get within a request is read from sled once, see `loader`
+    fn read(&self, key: &str) -> Result<Option<sled::IVec>> {
+        let key = self.key(key);
+        let generation = self.generation.load(Ordering::Acquire);
+        Ok(loader::load(&self.tree.name(), &key, generation, || {
+            self.tree.get(&key)
+        })?)
+    }
     pub fn remove<K: AsRef<str>>(&self, key: K) -> Result<()> {
         let key = key.as_ref();
-        self.handle.remove(key)?;
+        let mut batch = sled::Batch::default();
+        batch.remove(self.key(key).as_bytes());
+        batch.remove(self.key(&ttl::expiry_key(key)).as_bytes());
+        self.tree.apply_batch(batch)?;
+        self.written();
+        Ok(())
+    }
+
+    // Insert `entries` in a single atomic batch.
+    pub fn insert_all<'v, T, I, K>(&self, entries: I) -> Result<()>
+    where
+        T: Serialize + 'v,
+        I: IntoIterator<Item = (K, &'v T)>,
+        K: AsRef<str>,
+    {
+        let mut ops = Vec::new();
+        for (key, value) in entries {
+            ops.push(WriteOp::Insert {
+                key: key.as_ref().to_string(),
+                value: self.codec.serialize(value)?,
+            });
+        }
+        self.apply_batch(ops)
+    }
+    // Remove `keys` in a single atomic batch.
+    pub fn remove_all<I, K>(&self, keys: I) -> Result<()>
+    where
+        I: IntoIterator<Item = K>,
+        K: AsRef<str>,
+    {
+        let mut batch = sled::Batch::default();
+        for key in keys {
+            let key = key.as_ref();
+            batch.remove(self.key(key).as_bytes());
+            batch.remove(self.key(&ttl::expiry_key(key)).as_bytes());
+        }
+        self.tree.apply_batch(batch)?;
+        self.written();
         Ok(())
     }
 
+    // Batches
+    // the value of a `WriteOp::Insert`, framed for its key once the batch is applied
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>> {
+        self.codec.serialize(value)
+    }
+    // the writes of `insert_with_ttl`, the expiry after the value so its insert doesn't clear it
+    pub fn ttl_ops<T: Serialize>(
+        &self,
+        key: &str,
+        value: &T,
+        ttl: Duration,
+    ) -> Result<Vec<WriteOp>> {
+        let expires_at = self.clock.now_millis() + ttl.as_millis() as u64;
+        Ok(vec![
+            WriteOp::Insert {
+                key: key.to_string(),
+                value: self.encode(value)?,
+            },
+            WriteOp::Insert {
+                key: ttl::expiry_key(key),
+                value: self.encode(&expires_at)?,
+            },
+            WriteOp::Insert {
+                key: ttl::index_key(expires_at, key),
+                value: self.encode(&key)?,
+            },
+        ])
+    }
+    pub fn apply_batch<I: IntoIterator<Item = WriteOp>>(&self, ops: I) -> Result<()> {
+        self.tree
+            .apply_batch(batch(&self.codec, &self.tree.name(), &self.prefix, ops)?)?;
+        self.written();
+        Ok(())
+    }
+
+    // Transactions
+    // Run `f` against this tree atomically: what it reads through `tx` stays as it was until its
+    // writes land, all at once or not at all. sled runs `f` again when another writer got in
+    // between, so it must not change anything except through `tx`. An error from `f` aborts.
+    pub fn transaction<R>(&self, f: impl Fn(&Tx) -> Result<R>) -> Result<R> {
+        let name = self.tree.name();
+        let result: TransactionResult<R, anyhow::Error> = self.tree.transaction(|tree| {
+            let tx = Tx {
+                tree,
+                name: &name,
+                prefix: &self.prefix,
+                codec: &self.codec,
+            };
+            f(&tx).map_err(|err| match err.downcast::<UnabortableTransactionError>() {
+                Ok(UnabortableTransactionError::Conflict) => ConflictableTransactionError::Conflict,
+                Ok(UnabortableTransactionError::Storage(err)) => {
+                    ConflictableTransactionError::Storage(err)
+                }
+                Err(err) => ConflictableTransactionError::Abort(err),
+            })
+        });
+        self.written();
+        result.map_err(|err| match err {
+            TransactionError::Abort(err) => err,
+            TransactionError::Storage(err) => err.into(),
+        })
+    }
+    // Read-modify-write `key` without losing a concurrent change. `f` gets the stored value and
+    // returns the one to store, `None` removes it. It runs again whenever the value changed
+    // before it could be swapped in. Returns what was stored.
+    pub fn update<T, K, F>(&self, key: K, mut f: F) -> Result<Option<T>>
+    where
+        T: Serialize + DeserializeOwned,
+        K: AsRef<str>,
+        F: FnMut(Option<T>) -> Option<T>,
+    {
+        let logical = key.as_ref();
+        let key = self.key(logical);
+        let context = self.context(logical);
+        loop {
+            let current = self.tree.get(&key)?;
+            let decoded = match &current {
+                Some(value) => Some(self.codec.decode(value, &context)?),
+                None => None,
+            };
+            let next = f(decoded);
+            let encoded = match &next {
+                Some(value) => Some(self.codec.encode(value, &context)?),
+                None => None,
+            };
+            if self.tree.compare_and_swap(&key, current, encoded)?.is_ok() {
+                // like a plain insert, the key is permanent again
+                self.tree
+                    .remove(self.key(&ttl::expiry_key(logical)).as_bytes())?;
+                self.written();
+                return Ok(next);
+            }
+        }
+    }
+
     // Iterators
     pub fn iter<'a, T: DeserializeOwned + 'a>(
         &'a self,
     ) -> Result<impl Iterator<Item = Result<(String, T)>> + 'a> {
-        let iter = self.handle.iter().map(move |item| {
+        let iter = self.scan_raw("").map(move |item| {
             let (key, value) = item?;
-            let key = String::from_utf8(key.to_vec())?;
-            let value = self.encoder.deserialize(&value)?;
+            let value = self
+                .codec
+                .decode(&value, &context(&self.tree.name(), &key))?;
+            let key = self.unprefixed(&key)?.to_string();
             Ok((key, value))
         });
         Ok(iter)
     }
+    pub fn iter_keys<'a>(&'a self, prefix: &str) -> impl Iterator<Item = Result<String>> + 'a {
+        self.scan_raw(prefix).map(|item| {
+            let (key, _) = item?;
+            Ok(self.unprefixed(&key)?.to_string())
+        })
+    }
     pub fn iter_prefix<'a, T: DeserializeOwned + 'a>(
         &'a self,
         prefix: &str,
     ) -> Result<impl Iterator<Item = Result<(String, T)>> + 'a> {
-        let iter = self.handle.scan_prefix(prefix).map(move |item| {
+        let iter = self.scan_raw(prefix).map(move |item| {
             let (key, value) = item?;
-            let key = String::from_utf8(key.to_vec())?;
-            let value = self.encoder.deserialize(&value)?;
+            let value = self
+                .codec
+                .decode(&value, &context(&self.tree.name(), &key))?;
+            let key = self.unprefixed(&key)?.to_string();
             Ok((key, value))
         });
         Ok(iter)
     }
+
+    // Export and import
+    // Every value under `prefix` with its key, decoded as it is read. It holds on to the tree
+    // rather than borrowing `self`, so a response body can stream it.
+    pub fn export_prefix<T: DeserializeOwned + 'static>(
+        &self,
+        prefix: &str,
+    ) -> impl Iterator<Item = Result<(String, T)>> + Send + 'static {
+        let codec = self.codec.clone();
+        let tree = self.tree.name();
+        let namespace = self.prefix.clone();
+        self.scan_raw(prefix).map(move |item| {
+            let (key, value) = item?;
+            let value = codec.decode(&value, &context(&tree, &key))?;
+            let key = String::from_utf8(key.to_vec())?;
+            let key = key
+                .strip_prefix(namespace.as_str())
+                .unwrap_or(&key)
+                .to_string();
+            Ok((key, value))
+        })
+    }
+    // Write `entries` back, replacing what is stored under their keys, in one atomic batch.
+    // Every key has to be under `prefix`, an import can't reach into the rest of the tree.
+    // Returns how many were written.
+    pub fn import<T, I, K>(&self, prefix: &str, entries: I) -> Result<usize>
+    where
+        T: Serialize,
+        I: IntoIterator<Item = (K, T)>,
+        K: AsRef<str>,
+    {
+        let mut ops = Vec::new();
+        for (key, value) in entries {
+            let key = key.as_ref();
+            if !key.starts_with(prefix) {
+                bail!("`{}` is not under `{}`", key, prefix);
+            }
+            ops.push(WriteOp::Insert {
+                key: key.to_string(),
+                value: self.codec.serialize(&value)?,
+            });
+        }
+        let imported = ops.len();
+        self.apply_batch(ops)?;
+        Ok(imported)
+    }
+
+    // Expiry
+    // Remove every key whose ttl ran out before `now` (milliseconds since the epoch). Index
+    // entries left behind by a later plain insert or remove are cleaned up without touching the key.
+    pub fn sweep_expired(&self, now: u64) -> Result<usize> {
+        let mut batch = sled::Batch::default();
+        let mut expired = 0;
+        for item in self.scan_raw(ttl::INDEX_PREFIX) {
+            let (index_key, value) = item?;
+            let key: String = self
+                .codec
+                .decode(&value, &context(&self.tree.name(), &index_key))?;
+            let expires_at = ttl::parse_index_key(&index_key[self.prefix.len()..])?;
+            if expires_at > now {
+                break;
+            }
+            batch.remove(index_key);
+            let expiry_key = self.key(&ttl::expiry_key(&key));
+            let current = self.tree.get(&expiry_key)?;
+            let current = match current {
+                Some(current) => self
+                    .codec
+                    .decode::<u64>(&current, &context(&self.tree.name(), expiry_key.as_bytes()))?,
+                None => continue,
+            };
+            if current == expires_at {
+                batch.remove(self.key(&key).as_bytes());
+                batch.remove(expiry_key.as_bytes());
+                expired += 1;
+            }
+        }
+        self.tree.apply_batch(batch)?;
+        self.written();
+        Ok(expired)
+    }
+
+    // Snapshots
+    // Copy every entry under `prefix` into memory. Writes only happen behind the `AppState` write
+    // lock, so taking this while holding the read lock yields a consistent view.
+    pub fn snapshot(&self, prefix: &str) -> Result<Snapshot> {
+        let mut entries = std::collections::BTreeMap::new();
+        for item in self.scan_raw(prefix) {
+            let (key, value) = item?;
+            entries.insert(self.unprefixed(&key)?.to_string(), value);
+        }
+        Ok(Snapshot::new(
+            entries,
+            self.codec.clone(),
+            self.tree.name(),
+            self.prefix.clone(),
+        ))
+    }
+    // Put every entry under `prefix` back the way `snapshot` saw it, in one atomic batch. Keys
+    // written since are removed again.
+    pub fn restore(&self, prefix: &str, snapshot: &Snapshot) -> Result<()> {
+        let mut batch = sled::Batch::default();
+        for item in self.scan_raw(prefix) {
+            let (key, _) = item?;
+            if !snapshot.entries().contains_key(self.unprefixed(&key)?) {
+                batch.remove(key);
+            }
+        }
+        for (key, value) in snapshot.entries() {
+            batch.insert(self.key(key).as_bytes(), value.clone());
+        }
+        self.tree.apply_batch(batch)?;
+        self.written();
+        Ok(())
+    }
+
+    // Maintenance
+    pub fn flush(&self) -> Result<()> {
+        self.handle.flush()?;
+        Ok(())
+    }
+    pub fn size_on_disk(&self) -> Result<u64> {
+        Ok(self.handle.size_on_disk()?)
+    }
+    // Copy every tree into a new database at `path`, values stay encoded as they are.
+    pub fn backup(&self, path: &Path) -> Result<()> {
+        anyhow::ensure!(!path.exists(), "{} already exists", path.display());
+        let target = sled::open(path)?;
+        target.import(self.handle.export());
+        target.flush()?;
+        Ok(())
+    }
+    // Rewrite every value not stored with the current codec settings, e.g. after adding a new
+    // encryption key. Returns how many values were rewritten.
+    pub fn reencode_all(&self) -> Result<usize> {
+        let mut rewritten = 0;
+        for db in self.all_trees()? {
+            for item in db.scan_raw("") {
+                let (key, value) = item?;
+                if !db.codec.is_stale(&value) {
+                    continue;
+                }
+                let value = db.codec.reencode(&value, &context(&db.tree.name(), &key))?;
+                db.tree.insert(key, value)?;
+                rewritten += 1;
+            }
+        }
+        self.written();
+        self.handle.flush()?;
+        Ok(rewritten)
+    }
+}
+
+// What a value is bound to: the tree and the full key it is stored under.
+pub(super) fn context(tree: &[u8], key: &[u8]) -> Vec<u8> {
+    let mut context = Vec::with_capacity(tree.len() + 1 + key.len());
+    context.extend_from_slice(tree);
+    context.push(0);
+    context.extend_from_slice(key);
+    context
+}
+
+// the sled batch of `ops` under `prefix` of `tree`, writes make keys permanent again
+fn batch<I: IntoIterator<Item = WriteOp>>(
+    codec: &Codec,
+    tree: &[u8],
+    prefix: &str,
+    ops: I,
+) -> Result<sled::Batch> {
+    let mut batch = sled::Batch::default();
+    for op in ops {
+        let key = match op {
+            WriteOp::Insert { key, value } => {
+                let stored = format!("{}{}", prefix, key);
+                let value = codec.seal(value, &context(tree, stored.as_bytes()))?;
+                batch.insert(stored.as_bytes(), value);
+                key
+            }
+            WriteOp::Remove { key } => {
+                batch.remove(format!("{}{}", prefix, key).as_bytes());
+                key
+            }
+        };
+        batch.remove(format!("{}{}", prefix, ttl::expiry_key(&key)).as_bytes());
+    }
+    Ok(batch)
+}
+
+// === Transactions ===
+// The typed view of a tree inside `Db::transaction`.
+pub struct Tx<'a> {
+    tree: &'a TransactionalTree,
+    // the name of `tree`, which values are bound to
+    name: &'a [u8],
+    prefix: &'a str,
+    codec: &'a Codec,
+}
+impl Tx<'_> {
+    pub fn get<T: DeserializeOwned, K: AsRef<str>>(&self, key: K) -> Result<Option<T>> {
+        let key = self.key(key.as_ref());
+        match self.tree.get(key.as_bytes())? {
+            Some(value) => Ok(Some(
+                self.codec
+                    .decode(&value, &context(self.name, key.as_bytes()))?,
+            )),
+            None => Ok(None),
+        }
+    }
+    pub fn insert<T: Serialize, K: AsRef<str>>(&self, key: K, value: &T) -> Result<()> {
+        let key = key.as_ref();
+        let stored = self.key(key);
+        let value = self
+            .codec
+            .encode(value, &context(self.name, stored.as_bytes()))?;
+        self.tree.insert(stored.as_bytes(), value)?;
+        self.tree
+            .remove(self.key(&ttl::expiry_key(key)).as_bytes())?;
+        Ok(())
+    }
+    pub fn remove<K: AsRef<str>>(&self, key: K) -> Result<()> {
+        let key = key.as_ref();
+        self.tree.remove(self.key(key).as_bytes())?;
+        self.tree
+            .remove(self.key(&ttl::expiry_key(key)).as_bytes())?;
+        Ok(())
+    }
+    fn key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>> {
+        self.codec.serialize(value)
+    }
+    // write `ops` as part of the transaction, like `Db::apply_batch`
+    pub fn apply_batch<I: IntoIterator<Item = WriteOp>>(&self, ops: I) -> Result<()> {
+        self.tree
+            .apply_batch(&batch(self.codec, self.name, self.prefix, ops)?)?;
+        Ok(())
+    }
 }
 
 // Required Debug implementation for `Db`
@@ -88,8 +775,9 @@ mod tests {
     use serde::Deserialize;
 
     use super::*;
+    use crate::clock::MockClock;
 
-    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[derive(Debug, Clone, Default, Serialize, Deserialize)]
     struct Test {
         id: u64,
         name: String,
@@ -175,6 +863,34 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_apply_batch() -> Result<()> {
+        let (path, db) = setup()?;
+        let test = Test {
+            id: 0,
+            name: "test".to_string(),
+        };
+        db.insert("test", &test)?;
+        let test = Test {
+            id: 1,
+            name: "test2".to_string(),
+        };
+        let ops = vec![
+            WriteOp::Remove {
+                key: "test".to_string(),
+            },
+            WriteOp::Insert {
+                key: "test2".to_string(),
+                value: db.encode(&test)?,
+            },
+        ];
+        db.apply_batch(ops)?;
+        assert!(db.get::<Test, _>("test")?.is_none());
+        assert_eq!(db.get::<Test, _>("test2")?.unwrap().name, "test2");
+        teardown((path, db))?;
+        Ok(())
+    }
+
     #[test]
     fn test_iter() -> Result<()> {
         let (path, db) = setup()?;
@@ -249,4 +965,236 @@ mod tests {
         teardown((path, db))?;
         Ok(())
     }
+
+    #[test]
+    fn test_insert_with_ttl_and_sweep() -> Result<()> {
+        let (path, db) = setup()?;
+        let test = Test {
+            id: 0,
+            name: "test".to_string(),
+        };
+        db.insert_with_ttl("test", &test, Duration::from_secs(60))?;
+        db.insert_with_ttl("test2", &test, Duration::from_secs(3600))?;
+        let now = ttl::now_millis();
+        assert_eq!(db.sweep_expired(now)?, 0);
+        assert!(db.get::<Test, _>("test")?.is_some());
+        assert_eq!(db.sweep_expired(now + 120_000)?, 1);
+        assert!(db.get::<Test, _>("test")?.is_none());
+        assert!(db.get::<Test, _>("test2")?.is_some());
+        teardown((path, db))?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_ttl_follows_the_clock() -> Result<()> {
+        let (path, db) = setup()?;
+        let clock = MockClock::new(time::macros::datetime!(2024-03-06 12:00 UTC));
+        let db = db.with_clock(Arc::new(clock.clone()));
+        db.insert_with_ttl("lock", &1u8, Duration::from_secs(60))?;
+        assert_eq!(db.sweep_expired(db.clock().now_millis())?, 0);
+        clock.advance(Duration::from_secs(61));
+        // gone for readers before the sweeper gets to it
+        assert_eq!(db.get::<u8, _>("lock")?, None);
+        assert_eq!(db.sweep_expired(db.clock().now_millis())?, 1);
+        teardown((path, db))?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_clears_ttl() -> Result<()> {
+        let (path, db) = setup()?;
+        let test = Test {
+            id: 0,
+            name: "test".to_string(),
+        };
+        db.insert_with_ttl("test", &test, Duration::from_secs(60))?;
+        db.insert("test", &test)?;
+        assert_eq!(db.sweep_expired(ttl::now_millis() + 120_000)?, 0);
+        assert!(db.get::<Test, _>("test")?.is_some());
+        teardown((path, db))?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_is_stable() -> Result<()> {
+        let (path, db) = setup()?;
+        let test = Test {
+            id: 0,
+            name: "test".to_string(),
+        };
+        db.insert("test", &test)?;
+        let snapshot = db.snapshot("test")?;
+        let test = Test {
+            id: 1,
+            name: "test2".to_string(),
+        };
+        db.insert("test2", &test)?;
+        db.remove("test")?;
+        {
+            assert_eq!(snapshot.len(), 1);
+            let mut iter = snapshot.iter::<Test>();
+            let (key, value) = iter.next().unwrap()?;
+            assert_eq!(key, "test");
+            assert_eq!(value.name, "test");
+            assert!(iter.next().is_none());
+        }
+        teardown((path, db))?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_transaction() -> Result<()> {
+        let (path, db) = setup()?;
+        let test = Test {
+            id: 0,
+            name: "test".to_string(),
+        };
+        db.insert("a", &test)?;
+        let moved = db.transaction(|tx| {
+            let test = tx.get::<Test, _>("a")?;
+            tx.remove("a")?;
+            if let Some(test) = &test {
+                tx.insert("b", test)?;
+            }
+            Ok(test.is_some())
+        })?;
+        assert!(moved);
+        assert!(db.get::<Test, _>("a")?.is_none());
+        assert_eq!(db.get::<Test, _>("b")?.unwrap().name, "test");
+        // an error rolls everything back
+        let failed = db.transaction(|tx| -> Result<()> {
+            tx.remove("b")?;
+            Err(anyhow::anyhow!("changed my mind"))
+        });
+        assert_eq!(failed.unwrap_err().to_string(), "changed my mind");
+        assert!(db.get::<Test, _>("b")?.is_some());
+        teardown((path, db))?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_update() -> Result<()> {
+        let (path, db) = setup()?;
+        let created = db.update::<Test, _, _>("test", |test| {
+            assert!(test.is_none());
+            Some(Test {
+                id: 0,
+                name: "test".to_string(),
+            })
+        })?;
+        assert_eq!(created.unwrap().name, "test");
+        let updated = db.update::<Test, _, _>("test", |test| {
+            test.map(|test| Test {
+                name: format!("{}2", test.name),
+                ..test
+            })
+        })?;
+        assert_eq!(updated.unwrap().name, "test2");
+        assert_eq!(db.get::<Test, _>("test")?.unwrap().name, "test2");
+        assert!(db.update::<Test, _, _>("test", |_| None)?.is_none());
+        assert!(db.get::<Test, _>("test")?.is_none());
+        teardown((path, db))?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_all() -> Result<()> {
+        let (path, db) = setup()?;
+        let tests: Vec<Test> = (0..3)
+            .map(|id| Test {
+                id,
+                name: format!("test{}", id),
+            })
+            .collect();
+        db.insert_all(tests.iter().map(|test| (format!("test:{}", test.id), test)))?;
+        assert_eq!(db.get::<Test, _>("test:2")?.unwrap().name, "test2");
+        db.remove_all(["test:0", "test:1", "test:2"])?;
+        assert!(db.get::<Test, _>("test:0")?.is_none());
+        teardown((path, db))?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_import_round_trip() -> Result<()> {
+        let (path, db) = setup()?;
+        for id in 0..3 {
+            let name = format!("test{}", id);
+            db.insert(format!("test:{}", id), &Test { id, name })?;
+        }
+        db.insert("other", &Test::default())?;
+        let exported: Vec<(String, Test)> = db.export_prefix("test:").collect::<Result<_>>()?;
+        assert_eq!(exported.len(), 3);
+        db.remove_all(exported.iter().map(|(key, _)| key))?;
+        assert_eq!(db.import("test:", exported)?, 3);
+        assert_eq!(db.get::<Test, _>("test:1")?.unwrap().name, "test1");
+        // nothing is written when a key is out of reach
+        let entries = [("test:9", Test::default()), ("other", Test::default())];
+        assert!(db.import("test:", entries).is_err());
+        assert!(db.get::<Test, _>("test:9")?.is_none());
+        teardown((path, db))?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_namespaces() -> Result<()> {
+        let (path, db) = setup()?;
+        let ada = db.for_tenant(Some("user:ada"))?;
+        let bob = db.for_tenant(Some("user:bob"))?;
+        ada.insert("todo:1", &Test::default())?;
+        ada.insert_with_ttl("session", &1u8, Duration::from_secs(60))?;
+        bob.insert("todo:2", &Test::default())?;
+        // the keys sit side by side in the default tree, each namespace sees its own
+        assert!(db.get::<Test, _>("user:ada:todo:1")?.is_some());
+        assert_eq!(
+            ada.iter_keys("todo:").collect::<Result<Vec<_>>>()?,
+            ["todo:1"]
+        );
+        assert!(bob.get::<Test, _>("todo:1")?.is_none());
+        assert_eq!(db.tenant_ids()?, ["user:ada", "user:bob"]);
+        // ttls and the default tree's sweep stay apart
+        assert_eq!(db.sweep_expired(ttl::now_millis() + 120_000)?, 0);
+        assert_eq!(ada.sweep_expired(ttl::now_millis() + 120_000)?, 1);
+        assert!(ada.get::<u8, _>("session")?.is_none());
+        // moving between namespaces and dropping one
+        let carol = db.for_tenant(Some("user:carol"))?;
+        assert_eq!(bob.move_into(&carol)?, 1);
+        assert!(carol.get::<Test, _>("todo:2")?.is_some());
+        assert!(bob.is_empty());
+        db.drop_tenant("user:ada")?;
+        assert!(ada.is_empty());
+        assert_eq!(db.tenant_ids()?, ["user:bob", "user:carol"]);
+        teardown((path, db))?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_old_databases_are_upgraded() -> Result<()> {
+        let tick = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let path = format!("test_db_upgrade_{}", tick);
+        let test = Test {
+            id: 1,
+            name: "test".to_string(),
+        };
+        {
+            // values as they were written before the format was recorded, plain bincode
+            let sled = sled::open(&path)?;
+            let plain = Codec::new().serialize(&test)?;
+            sled.insert("test", plain.clone())?;
+            sled.open_tree("tenant:acme")?.insert("test", plain)?;
+            sled.flush()?;
+        }
+        let keyring = super::super::codec::Keyring::from_keys(vec![vec![1; 32]])?;
+        let db = Db::open(&path, Codec::new().with_keyring(keyring))?;
+        assert_eq!(db.get::<Test, _>("test")?.unwrap().name, "test");
+        let acme = db.for_tenant(Some("acme"))?;
+        assert_eq!(acme.get::<Test, _>("test")?.unwrap().name, "test");
+        // encrypted values are bound to their key, moving them seals them again
+        let other = db.for_tenant(Some("other"))?;
+        assert_eq!(acme.move_into(&other)?, 1);
+        assert_eq!(other.get::<Test, _>("test")?.unwrap().name, "test");
+        teardown((path, db))?;
+        Ok(())
+    }
 }
@@ -0,0 +1,189 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use axum::{extract::Request, middleware::Next, response::Response};
+use sled::IVec;
+
+// === Request-scoped reads ===
+// A response rendering a list, its counters and a toast reads the same keys several times over.
+// While a request runs, `Db::get` keeps what it read for the rest of the request and serves a
+// repeated get from there. Any write through `Db` in the request starts over, so nothing read
+// after it is stale. What was kept is also tagged with the database's write generation, a write
+// by another request or a background task in between (say while the lock was let go) makes it
+// read again. The counts end up on the request span.
+tokio::task_local! {
+    static LOADER: Loader;
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    // by tree name and key, with the generation they were read at
+    values: Mutex<HashMap<(IVec, String), (u64, Option<IVec>)>>,
+    reads: AtomicU64,
+    hits: AtomicU64,
+    writes: AtomicU64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Loader(Arc<Inner>);
+impl Loader {
+    // gets that went to sled
+    pub fn reads(&self) -> u64 {
+        self.0.reads.load(Ordering::Relaxed)
+    }
+    // gets answered from what the request read before
+    pub fn hits(&self) -> u64 {
+        self.0.hits.load(Ordering::Relaxed)
+    }
+    // writes applied or queued in the request
+    pub fn writes(&self) -> u64 {
+        self.0.writes.load(Ordering::Relaxed)
+    }
+    // run `future` with this loader in place
+    pub async fn scope<F: std::future::Future>(&self, future: F) -> F::Output {
+        LOADER.scope(self.clone(), future).await
+    }
+
+    fn load(
+        &self,
+        tree: &IVec,
+        key: &str,
+        generation: u64,
+        read: impl FnOnce() -> sled::Result<Option<IVec>>,
+    ) -> sled::Result<Option<IVec>> {
+        let cached = (tree.clone(), key.to_string());
+        if let Some((read_at, value)) = self.values().get(&cached) {
+            if *read_at == generation {
+                self.0.hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(value.clone());
+            }
+        }
+        let value = read()?;
+        self.0.reads.fetch_add(1, Ordering::Relaxed);
+        self.values().insert(cached, (generation, value.clone()));
+        Ok(value)
+    }
+    fn clear(&self) {
+        self.values().clear();
+        self.0.writes.fetch_add(1, Ordering::Relaxed);
+    }
+    fn values(&self) -> std::sync::MutexGuard<'_, HashMap<(IVec, String), (u64, Option<IVec>)>> {
+        self.0.values.lock().expect("loader lock poisoned")
+    }
+}
+
+// `key` of `tree` through the loader of the current request, straight from `read` outside one.
+// `generation` is the database's, taken before reading.
+pub(crate) fn load(
+    tree: &IVec,
+    key: &str,
+    generation: u64,
+    read: impl FnOnce() -> sled::Result<Option<IVec>>,
+) -> sled::Result<Option<IVec>> {
+    match LOADER.try_with(Loader::clone) {
+        Ok(loader) => loader.load(tree, key, generation, read),
+        Err(_) => read(),
+    }
+}
+
+// something was written, what the request read may be outdated
+pub(crate) fn invalidate() {
+    let _ = LOADER.try_with(Loader::clear);
+}
+
+// A write handed to the queue, applied elsewhere. The request starts over all the same, the
+// writer moves the generation on once it is in.
+pub(crate) fn queued() {
+    invalidate();
+}
+
+// === Middleware ===
+// Give every request a loader, also as an extension, and record its counts on the request span.
+pub async fn layer(mut request: Request, next: Next) -> Response {
+    let loader = Loader::default();
+    request.extensions_mut().insert(loader.clone());
+    let response = loader.scope(next.run(request)).await;
+    let span = tracing::Span::current();
+    span.record("db_reads", loader.reads());
+    span.record("db_cache_hits", loader.hits());
+    response
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::TestDb;
+
+    #[tokio::test]
+    async fn test_repeated_gets_are_read_once() -> anyhow::Result<()> {
+        let db = TestDb::new("loader")?;
+        db.insert("greeting", &"hello".to_string())?;
+        let loader = Loader::default();
+        loader
+            .scope(async {
+                for _ in 0..3 {
+                    assert_eq!(db.get::<String, _>("greeting")?.as_deref(), Some("hello"));
+                }
+                assert!(db.get::<String, _>("missing")?.is_none());
+                assert!(db.get::<String, _>("missing")?.is_none());
+                anyhow::Ok(())
+            })
+            .await?;
+        assert_eq!((loader.reads(), loader.hits()), (2, 3));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_writes_start_over() -> anyhow::Result<()> {
+        let db = TestDb::new("loader-writes")?;
+        db.insert("greeting", &"hello".to_string())?;
+        let loader = Loader::default();
+        loader
+            .scope(async {
+                assert_eq!(db.get::<String, _>("greeting")?.as_deref(), Some("hello"));
+                db.insert("greeting", &"goodbye".to_string())?;
+                assert_eq!(db.get::<String, _>("greeting")?.as_deref(), Some("goodbye"));
+                anyhow::Ok(())
+            })
+            .await?;
+        assert_eq!((loader.reads(), loader.hits(), loader.writes()), (2, 0, 1));
+        // outside a request nothing is kept
+        assert_eq!(db.get::<String, _>("greeting")?.as_deref(), Some("goodbye"));
+        assert_eq!(loader.reads(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_writes_of_other_requests_start_over() -> anyhow::Result<()> {
+        let db = TestDb::new("loader-concurrent")?;
+        db.insert("greeting", &"hello".to_string())?;
+        let (read, has_read) = tokio::sync::oneshot::channel();
+        let (wrote, has_written) = tokio::sync::oneshot::channel();
+        // the first reads, lets go and reads again after the second wrote in between
+        let first = Loader::default();
+        let reading = first.scope(async {
+            assert_eq!(db.get::<String, _>("greeting")?.as_deref(), Some("hello"));
+            read.send(()).unwrap();
+            has_written.await?;
+            assert_eq!(db.get::<String, _>("greeting")?.as_deref(), Some("goodbye"));
+            anyhow::Ok(())
+        });
+        let writing = Loader::default().scope(async {
+            has_read.await?;
+            db.insert("greeting", &"goodbye".to_string())?;
+            wrote.send(()).unwrap();
+            anyhow::Ok(())
+        });
+        let (reading, writing) = tokio::join!(reading, writing);
+        reading?;
+        writing?;
+        assert_eq!((first.reads(), first.hits()), (2, 0));
+        Ok(())
+    }
+}
@@ -0,0 +1,163 @@
+use std::{
+    fmt, fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+
+// how often a start waiting for the lock tries again
+const RETRY_INTERVAL: Duration = Duration::from_millis(500);
+
+// === Lock ===
+// sled holds an exclusive lock on the database while it is open, a second server or a command
+// run next to a running server cannot open it.
+#[derive(Debug)]
+pub struct Locked {
+    pub path: PathBuf,
+}
+impl fmt::Display for Locked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the database at {} is in use by another process, most likely a running server. \
+             Stop it first, set DB_LOCK_WAIT_SECS to wait for it, or run the command with \
+             --read-only to look at a copy",
+            self.path.display()
+        )
+    }
+}
+impl std::error::Error for Locked {}
+
+// sled reports the lock as an io error, `WouldBlock` or a message of its own by platform
+pub fn is_lock_error(err: &sled::Error) -> bool {
+    match err {
+        sled::Error::Io(err) => {
+            err.kind() == ErrorKind::WouldBlock
+                || err.to_string().contains("could not acquire lock")
+        }
+        _ => false,
+    }
+}
+
+// `err` from opening `path`, as `Locked` when it was the lock
+pub fn classify(err: sled::Error, path: &Path) -> anyhow::Error {
+    if is_lock_error(&err) {
+        Locked {
+            path: path.to_path_buf(),
+        }
+        .into()
+    } else {
+        err.into()
+    }
+}
+
+// Try `open` until it gets past the lock or `wait` is up. Any other error ends it right away.
+pub fn retry<T>(wait: Option<Duration>, mut open: impl FnMut() -> Result<T>) -> Result<T> {
+    let deadline = wait.map(|wait| Instant::now() + wait);
+    let mut waiting = false;
+    loop {
+        match open() {
+            Err(err)
+                if err.is::<Locked>()
+                    && deadline.is_some_and(|deadline| Instant::now() < deadline) =>
+            {
+                if !waiting {
+                    tracing::warn!(%err, "waiting for the database lock");
+                    waiting = true;
+                }
+                thread::sleep(RETRY_INTERVAL);
+            }
+            result => return result,
+        }
+    }
+}
+
+// === Read-only copies ===
+// The database copied to a temporary directory, for looking at one a server holds. sled knows
+// no shared lock, so this is the database as it was on disk when copied, and anything written
+// to the copy goes away with it.
+pub struct ReadOnlyCopy {
+    dir: PathBuf,
+}
+impl ReadOnlyCopy {
+    pub fn new(db_path: &Path) -> Result<Self> {
+        anyhow::ensure!(
+            db_path.is_dir(),
+            "no database at {} to copy",
+            db_path.display()
+        );
+        let dir = std::env::temp_dir().join(format!("rust-htmx-read-only-{}", std::process::id()));
+        let copy = Self { dir };
+        copy_dir(db_path, &copy.db_path())
+            .with_context(|| format!("could not copy {}", db_path.display()))?;
+        Ok(copy)
+    }
+
+    // a data directory holding the copy as its database
+    pub fn data_dir(&self) -> &Path {
+        &self.dir
+    }
+    pub fn db_path(&self) -> PathBuf {
+        self.dir.join("db")
+    }
+}
+impl Drop for ReadOnlyCopy {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_dir_all(&self.dir) {
+            tracing::warn!(%err, dir = %self.dir.display(), "could not remove the read-only copy");
+        }
+    }
+}
+
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        db::{codec::Codec, driver::Db},
+        fixtures::TestDb,
+    };
+
+    #[test]
+    fn test_a_held_database_is_locked() -> Result<()> {
+        let db = TestDb::new("lock")?;
+        let path = db.path();
+        db.insert("greeting", &"hello".to_string())?;
+        db.flush()?;
+
+        let err = Db::new_with_path(path).err().expect("the lock is held");
+        assert!(err.is::<Locked>());
+        // waiting gives up once the time is up
+        let err = retry(Some(Duration::from_millis(10)), || Db::new_with_path(path))
+            .err()
+            .expect("the lock is still held");
+        assert!(err.to_string().contains("--read-only"));
+
+        // a copy opens while the lock is held
+        let copy = ReadOnlyCopy::new(Path::new(path))?;
+        let read = Db::open(copy.db_path(), Codec::new())?;
+        assert_eq!(read.get::<String, _>("greeting")?.as_deref(), Some("hello"));
+        drop(read);
+        let dir = copy.data_dir().to_path_buf();
+        drop(copy);
+        assert!(!dir.exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,810 @@
+use anyhow::Result;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use time::Date;
+
+use super::{driver::Db, queue::WriteOp};
+use crate::{
+    models::{ChecklistItem, Location, Priority, Resolution, Status, Todo},
+    repository::entity::Repository,
+};
+
+// the last migration applied to a tree, `schema_version`
+const VERSION_KEY: &str = "schema_version";
+
+// A change to the shape of stored values. Migrations run in order, once per tree, and must
+// tolerate values that already have the new shape.
+struct Migration {
+    version: u32,
+    name: &'static str,
+    // returns how many values it rewrote
+    run: fn(&Db) -> Result<usize>,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "add todo status",
+        run: add_todo_status,
+    },
+    Migration {
+        version: 2,
+        name: "add todo due date",
+        run: add_todo_due,
+    },
+    Migration {
+        version: 3,
+        name: "add todo review fields",
+        run: add_todo_review_fields,
+    },
+    Migration {
+        version: 4,
+        name: "add todo estimate",
+        run: add_todo_estimate,
+    },
+    Migration {
+        version: 5,
+        name: "add todo location",
+        run: add_todo_location,
+    },
+    Migration {
+        version: 6,
+        name: "add todo color",
+        run: add_todo_color,
+    },
+    Migration {
+        version: 7,
+        name: "add todo version",
+        run: add_todo_version,
+    },
+    Migration {
+        version: 8,
+        name: "add todo tags",
+        run: add_todo_tags,
+    },
+    Migration {
+        version: 9,
+        name: "add todo scheduled date",
+        run: add_todo_scheduled_for,
+    },
+    Migration {
+        version: 10,
+        name: "add todo checklist",
+        run: add_todo_checklist,
+    },
+    Migration {
+        version: 11,
+        name: "index todos",
+        run: index_todos,
+    },
+    Migration {
+        version: 12,
+        name: "add todo priority",
+        run: add_todo_priority,
+    },
+    Migration {
+        version: 13,
+        name: "add todo list",
+        run: add_todo_list,
+    },
+    Migration {
+        version: 14,
+        name: "add todo resolution",
+        run: add_todo_resolution,
+    },
+    Migration {
+        version: 15,
+        name: "add todo custom fields",
+        run: add_todo_fields,
+    },
+];
+
+// Bring every tree up to the latest version, called once at startup.
+pub fn run(db: &Db) -> Result<()> {
+    for tree in db.all_trees()? {
+        run_tree(&tree, MIGRATIONS)?;
+    }
+    Ok(())
+}
+
+// Apply the pending `migrations` of one tree. A migration that fails has its writes undone and
+// stops the run, the ones before it stay applied.
+fn run_tree(db: &Db, migrations: &[Migration]) -> Result<()> {
+    let current = db.get::<u32, _>(VERSION_KEY)?.unwrap_or(0);
+    let tree = String::from_utf8_lossy(&db.tree_name()).into_owned();
+    for migration in migrations.iter().filter(|m| m.version > current) {
+        let before = db.snapshot("")?;
+        let rewritten = match (migration.run)(db) {
+            Ok(rewritten) => rewritten,
+            Err(err) => {
+                db.restore("", &before)?;
+                tracing::error!(
+                    tree,
+                    version = migration.version,
+                    migration = migration.name,
+                    "migration failed, rolled back"
+                );
+                return Err(err.context(format!(
+                    "migration {} ({}) failed and was rolled back",
+                    migration.version, migration.name
+                )));
+            }
+        };
+        let applied = Applied {
+            name: migration.name.to_string(),
+            applied_at: db.clock().now_millis() / 1000,
+            rewritten: rewritten as u64,
+        };
+        db.apply_batch([
+            WriteOp::Insert {
+                key: VERSION_KEY.to_string(),
+                value: db.encode(&migration.version)?,
+            },
+            WriteOp::Insert {
+                key: applied_key(migration.version),
+                value: db.encode(&applied)?,
+            },
+        ])?;
+        tracing::info!(
+            tree,
+            version = migration.version,
+            migration = migration.name,
+            rewritten,
+            "applied migration"
+        );
+    }
+    Ok(())
+}
+
+// === Status ===
+// the `migration:{version}` table, one row per migration applied to a tree
+const APPLIED_PREFIX: &str = "migration:";
+
+fn applied_key(version: u32) -> String {
+    format!("{}{:08}", APPLIED_PREFIX, version)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Applied {
+    pub name: String,
+    // unix seconds
+    pub applied_at: u64,
+    pub rewritten: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum State {
+    Pending,
+    Applied(Applied),
+    // applied before the table existed, only `schema_version` remembers it
+    Untracked,
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub version: u32,
+    pub name: &'static str,
+    pub state: State,
+}
+
+// where one tree stands, `None` is the default workspace
+#[derive(Debug, Clone)]
+pub struct TreeStatus {
+    pub tenant: Option<String>,
+    pub version: u32,
+    pub entries: Vec<Entry>,
+}
+impl TreeStatus {
+    pub fn pending(&self) -> impl Iterator<Item = &Entry> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.state == State::Pending)
+    }
+}
+
+// The state of every migration in every tree, without changing anything. Backs both
+// `--migrate-dry-run` and the admin page.
+pub fn status(db: &Db) -> Result<Vec<TreeStatus>> {
+    let mut tenants = vec![None];
+    tenants.extend(db.tenant_ids()?.into_iter().map(Some));
+    tenants
+        .into_iter()
+        .map(|tenant| {
+            let tree = db.for_tenant(tenant.as_deref())?;
+            tree_status(&tree, tenant, MIGRATIONS)
+        })
+        .collect()
+}
+
+fn tree_status(db: &Db, tenant: Option<String>, migrations: &[Migration]) -> Result<TreeStatus> {
+    let version = db.get::<u32, _>(VERSION_KEY)?.unwrap_or(0);
+    let mut entries = Vec::new();
+    for migration in migrations {
+        let state = match db.get::<Applied, _>(applied_key(migration.version))? {
+            Some(applied) => State::Applied(applied),
+            None if migration.version <= version => State::Untracked,
+            None => State::Pending,
+        };
+        entries.push(Entry {
+            version: migration.version,
+            name: migration.name,
+            state,
+        });
+    }
+    Ok(TreeStatus {
+        tenant,
+        version,
+        entries,
+    })
+}
+
+// Rewrite every todo that decodes as `Old` into the current shape. The codec rejects trailing
+// bytes, so todos that already have the newer fields do not decode and are left alone.
+fn rewrite_todos<Old: DeserializeOwned>(db: &Db, upgrade: fn(Old) -> Todo) -> Result<usize> {
+    let mut rewritten = 0;
+    for key in db.iter_keys("todo:").collect::<Result<Vec<_>>>()? {
+        let Ok(Some(old)) = db.get::<Old, _>(&key) else {
+            continue;
+        };
+        db.insert(&key, &upgrade(old))?;
+        rewritten += 1;
+    }
+    Ok(rewritten)
+}
+
+// === 1: todo status ===
+// todos before the board existed
+#[derive(Deserialize)]
+struct TodoV0 {
+    id: u64,
+    title: String,
+    completed: bool,
+}
+
+fn add_todo_status(db: &Db) -> Result<usize> {
+    rewrite_todos(db, |old: TodoV0| {
+        let mut todo = Todo::new(old.id, old.title);
+        todo.set_completed(old.completed);
+        todo
+    })
+}
+
+// === 2: todo due date ===
+// todos before the calendar existed
+#[derive(Deserialize)]
+struct TodoV1 {
+    id: u64,
+    title: String,
+    // implied by the status
+    #[allow(dead_code)]
+    completed: bool,
+    status: Status,
+}
+
+fn add_todo_due(db: &Db) -> Result<usize> {
+    rewrite_todos(db, |old: TodoV1| {
+        let mut todo = Todo::new(old.id, old.title);
+        todo.set_status(old.status);
+        todo
+    })
+}
+
+// === 3: todo review fields ===
+// todos before the weekly review, they count as touched when migrated
+#[derive(Deserialize)]
+struct TodoV2 {
+    id: u64,
+    title: String,
+    #[allow(dead_code)]
+    completed: bool,
+    status: Status,
+    due: Option<Date>,
+}
+
+fn add_todo_review_fields(db: &Db) -> Result<usize> {
+    rewrite_todos(db, |old: TodoV2| {
+        let mut todo = Todo::new(old.id, old.title);
+        todo.set_status(old.status);
+        todo.due = old.due;
+        todo
+    })
+}
+
+// === 4: todo estimate ===
+#[derive(Deserialize)]
+struct TodoV3 {
+    id: u64,
+    title: String,
+    #[allow(dead_code)]
+    completed: bool,
+    status: Status,
+    due: Option<Date>,
+    updated_at: u64,
+    archived: bool,
+}
+
+fn add_todo_estimate(db: &Db) -> Result<usize> {
+    rewrite_todos(db, |old: TodoV3| {
+        let mut todo = Todo::new(old.id, old.title);
+        todo.set_status(old.status);
+        todo.due = old.due;
+        todo.updated_at = old.updated_at;
+        todo.archived = old.archived;
+        todo
+    })
+}
+
+// === 5: todo location ===
+#[derive(Deserialize)]
+struct TodoV4 {
+    id: u64,
+    title: String,
+    #[allow(dead_code)]
+    completed: bool,
+    status: Status,
+    due: Option<Date>,
+    updated_at: u64,
+    archived: bool,
+    estimate_minutes: Option<u32>,
+}
+
+fn add_todo_location(db: &Db) -> Result<usize> {
+    rewrite_todos(db, |old: TodoV4| {
+        let mut todo = Todo::new(old.id, old.title);
+        todo.set_status(old.status);
+        todo.due = old.due;
+        todo.updated_at = old.updated_at;
+        todo.archived = old.archived;
+        todo.estimate_minutes = old.estimate_minutes;
+        todo
+    })
+}
+
+// === 6: todo color ===
+#[derive(Deserialize)]
+struct TodoV5 {
+    id: u64,
+    title: String,
+    #[allow(dead_code)]
+    completed: bool,
+    status: Status,
+    due: Option<Date>,
+    updated_at: u64,
+    archived: bool,
+    estimate_minutes: Option<u32>,
+    location: Option<Location>,
+}
+
+fn add_todo_color(db: &Db) -> Result<usize> {
+    rewrite_todos(db, |old: TodoV5| {
+        let mut todo = Todo::new(old.id, old.title);
+        todo.set_status(old.status);
+        todo.due = old.due;
+        todo.updated_at = old.updated_at;
+        todo.archived = old.archived;
+        todo.estimate_minutes = old.estimate_minutes;
+        todo.location = old.location;
+        todo
+    })
+}
+
+// === 7: todo version ===
+#[derive(Deserialize)]
+struct TodoV6 {
+    id: u64,
+    title: String,
+    #[allow(dead_code)]
+    completed: bool,
+    status: Status,
+    due: Option<Date>,
+    updated_at: u64,
+    archived: bool,
+    estimate_minutes: Option<u32>,
+    location: Option<Location>,
+    color: Option<String>,
+}
+
+fn add_todo_version(db: &Db) -> Result<usize> {
+    rewrite_todos(db, |old: TodoV6| {
+        let mut todo = Todo::new(old.id, old.title);
+        todo.set_status(old.status);
+        // the first version of every todo that predates versions
+        todo.version = 1;
+        todo.due = old.due;
+        todo.updated_at = old.updated_at;
+        todo.archived = old.archived;
+        todo.estimate_minutes = old.estimate_minutes;
+        todo.location = old.location;
+        todo.color = old.color;
+        todo
+    })
+}
+
+// === 8: todo tags ===
+#[derive(Deserialize)]
+struct TodoV7 {
+    id: u64,
+    title: String,
+    #[allow(dead_code)]
+    completed: bool,
+    status: Status,
+    due: Option<Date>,
+    updated_at: u64,
+    archived: bool,
+    estimate_minutes: Option<u32>,
+    location: Option<Location>,
+    color: Option<String>,
+    version: u64,
+}
+
+fn add_todo_tags(db: &Db) -> Result<usize> {
+    rewrite_todos(db, |old: TodoV7| {
+        let mut todo = Todo::new(old.id, old.title);
+        todo.set_status(old.status);
+        todo.due = old.due;
+        todo.updated_at = old.updated_at;
+        todo.archived = old.archived;
+        todo.estimate_minutes = old.estimate_minutes;
+        todo.location = old.location;
+        todo.color = old.color;
+        todo.version = old.version;
+        todo
+    })
+}
+
+// === 9: todo scheduled date ===
+#[derive(Deserialize)]
+struct TodoV8 {
+    id: u64,
+    title: String,
+    #[allow(dead_code)]
+    completed: bool,
+    status: Status,
+    due: Option<Date>,
+    updated_at: u64,
+    archived: bool,
+    estimate_minutes: Option<u32>,
+    location: Option<Location>,
+    color: Option<String>,
+    version: u64,
+    tags: Vec<String>,
+}
+
+fn add_todo_scheduled_for(db: &Db) -> Result<usize> {
+    rewrite_todos(db, |old: TodoV8| {
+        let mut todo = Todo::new(old.id, old.title);
+        todo.set_status(old.status);
+        todo.due = old.due;
+        todo.updated_at = old.updated_at;
+        todo.archived = old.archived;
+        todo.estimate_minutes = old.estimate_minutes;
+        todo.location = old.location;
+        todo.color = old.color;
+        todo.version = old.version;
+        todo.tags = old.tags;
+        todo
+    })
+}
+
+// === 10: todo checklist ===
+#[derive(Deserialize)]
+struct TodoV9 {
+    id: u64,
+    title: String,
+    #[allow(dead_code)]
+    completed: bool,
+    status: Status,
+    due: Option<Date>,
+    updated_at: u64,
+    archived: bool,
+    estimate_minutes: Option<u32>,
+    location: Option<Location>,
+    color: Option<String>,
+    version: u64,
+    tags: Vec<String>,
+    scheduled_for: Option<Date>,
+}
+
+fn add_todo_checklist(db: &Db) -> Result<usize> {
+    rewrite_todos(db, |old: TodoV9| {
+        let mut todo = Todo::new(old.id, old.title);
+        todo.set_status(old.status);
+        todo.due = old.due;
+        todo.updated_at = old.updated_at;
+        todo.archived = old.archived;
+        todo.estimate_minutes = old.estimate_minutes;
+        todo.location = old.location;
+        todo.color = old.color;
+        todo.version = old.version;
+        todo.tags = old.tags;
+        todo.scheduled_for = old.scheduled_for;
+        todo
+    })
+}
+
+// === 11: todo indexes ===
+// the secondary indexes of todos, written since by every change going through `Repository`
+fn index_todos(db: &Db) -> Result<usize> {
+    Repository::<Todo>::new(db).reindex()
+}
+
+// === 12: todo priority ===
+#[derive(Deserialize)]
+struct TodoV10 {
+    id: u64,
+    title: String,
+    #[allow(dead_code)]
+    completed: bool,
+    status: Status,
+    due: Option<Date>,
+    updated_at: u64,
+    archived: bool,
+    estimate_minutes: Option<u32>,
+    location: Option<Location>,
+    color: Option<String>,
+    version: u64,
+    tags: Vec<String>,
+    scheduled_for: Option<Date>,
+    checklist: Vec<ChecklistItem>,
+}
+
+fn add_todo_priority(db: &Db) -> Result<usize> {
+    rewrite_todos(db, |old: TodoV10| {
+        let mut todo = Todo::new(old.id, old.title);
+        todo.set_status(old.status);
+        todo.due = old.due;
+        todo.updated_at = old.updated_at;
+        todo.archived = old.archived;
+        todo.estimate_minutes = old.estimate_minutes;
+        todo.location = old.location;
+        todo.color = old.color;
+        todo.version = old.version;
+        todo.tags = old.tags;
+        todo.scheduled_for = old.scheduled_for;
+        todo.checklist = old.checklist;
+        todo
+    })
+}
+
+// === 13: todo list ===
+#[derive(Deserialize)]
+struct TodoV12 {
+    id: u64,
+    title: String,
+    #[allow(dead_code)]
+    completed: bool,
+    status: Status,
+    due: Option<Date>,
+    updated_at: u64,
+    archived: bool,
+    estimate_minutes: Option<u32>,
+    location: Option<Location>,
+    color: Option<String>,
+    version: u64,
+    tags: Vec<String>,
+    scheduled_for: Option<Date>,
+    checklist: Vec<ChecklistItem>,
+    priority: Priority,
+}
+
+fn add_todo_list(db: &Db) -> Result<usize> {
+    rewrite_todos(db, |old: TodoV12| {
+        let mut todo = Todo::new(old.id, old.title);
+        todo.set_status(old.status);
+        todo.due = old.due;
+        todo.updated_at = old.updated_at;
+        todo.archived = old.archived;
+        todo.estimate_minutes = old.estimate_minutes;
+        todo.location = old.location;
+        todo.color = old.color;
+        todo.version = old.version;
+        todo.tags = old.tags;
+        todo.scheduled_for = old.scheduled_for;
+        todo.checklist = old.checklist;
+        todo.priority = old.priority;
+        todo
+    })
+}
+
+// === 14: todo resolution ===
+#[derive(Deserialize)]
+struct TodoV13 {
+    id: u64,
+    title: String,
+    #[allow(dead_code)]
+    completed: bool,
+    status: Status,
+    due: Option<Date>,
+    updated_at: u64,
+    archived: bool,
+    estimate_minutes: Option<u32>,
+    location: Option<Location>,
+    color: Option<String>,
+    version: u64,
+    tags: Vec<String>,
+    scheduled_for: Option<Date>,
+    checklist: Vec<ChecklistItem>,
+    priority: Priority,
+    list: Option<String>,
+}
+
+// todos done so far were done, none of them was cancelled
+fn add_todo_resolution(db: &Db) -> Result<usize> {
+    rewrite_todos(db, |old: TodoV13| {
+        let mut todo = Todo::new(old.id, old.title);
+        todo.set_status(old.status);
+        todo.due = old.due;
+        todo.updated_at = old.updated_at;
+        todo.archived = old.archived;
+        todo.estimate_minutes = old.estimate_minutes;
+        todo.location = old.location;
+        todo.color = old.color;
+        todo.version = old.version;
+        todo.tags = old.tags;
+        todo.scheduled_for = old.scheduled_for;
+        todo.checklist = old.checklist;
+        todo.priority = old.priority;
+        todo.list = old.list;
+        todo
+    })
+}
+
+// === 15: todo custom fields ===
+#[derive(Deserialize)]
+struct TodoV14 {
+    id: u64,
+    title: String,
+    #[allow(dead_code)]
+    completed: bool,
+    status: Status,
+    due: Option<Date>,
+    updated_at: u64,
+    archived: bool,
+    estimate_minutes: Option<u32>,
+    location: Option<Location>,
+    color: Option<String>,
+    version: u64,
+    tags: Vec<String>,
+    scheduled_for: Option<Date>,
+    checklist: Vec<ChecklistItem>,
+    priority: Priority,
+    list: Option<String>,
+    resolution: Option<Resolution>,
+}
+
+fn add_todo_fields(db: &Db) -> Result<usize> {
+    rewrite_todos(db, |old: TodoV14| {
+        let mut todo = Todo::new(old.id, old.title);
+        todo.set_status(old.status);
+        todo.due = old.due;
+        todo.updated_at = old.updated_at;
+        todo.archived = old.archived;
+        todo.estimate_minutes = old.estimate_minutes;
+        todo.location = old.location;
+        todo.color = old.color;
+        todo.version = old.version;
+        todo.tags = old.tags;
+        todo.scheduled_for = old.scheduled_for;
+        todo.checklist = old.checklist;
+        todo.priority = old.priority;
+        todo.list = old.list;
+        todo.resolution = old.resolution;
+        todo
+    })
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use serde::Serialize;
+
+    use super::*;
+    use crate::fixtures::TestDb;
+
+    #[derive(Serialize)]
+    struct OldTodo {
+        id: u64,
+        title: String,
+        completed: bool,
+    }
+
+    #[test]
+    fn test_add_todo_status() -> Result<()> {
+        let db = TestDb::new("migrations")?;
+        let old = OldTodo {
+            id: 1,
+            title: "old".into(),
+            completed: true,
+        };
+        db.insert("todo:1", &old)?;
+        db.insert("todo:2", &Todo::new(2, "new".into()))?;
+
+        run(&db)?;
+        let migrated = db.get::<Todo, _>("todo:1")?.unwrap();
+        assert_eq!(migrated.status, Status::Done);
+        assert!(migrated.completed);
+        assert_eq!(
+            db.get::<Todo, _>("todo:2")?.unwrap().status,
+            Status::Backlog
+        );
+        assert_eq!(migrated.due, None);
+        assert!(!migrated.archived);
+        assert_eq!(migrated.estimate_minutes, None);
+        assert_eq!(migrated.location, None);
+        assert_eq!(migrated.color, None);
+        assert_eq!(migrated.priority, crate::models::Priority::Normal);
+        assert_eq!(migrated.resolution, Some(crate::models::Resolution::Done));
+        assert!(migrated.version > 0);
+        assert_eq!(
+            db.get::<u32, _>(VERSION_KEY)?,
+            MIGRATIONS.last().map(|migration| migration.version)
+        );
+
+        // a second start has nothing left to do
+        run(&db)?;
+        assert_eq!(db.get::<Todo, _>("todo:1")?.unwrap().status, Status::Done);
+        let status = status(&db)?;
+        assert_eq!(status[0].pending().count(), 0);
+        assert!(matches!(status[0].entries[0].state, State::Applied(_)));
+        Ok(())
+    }
+
+    fn rename_todo(db: &Db) -> Result<usize> {
+        db.insert("todo:1", &Todo::new(1, "renamed".into()))?;
+        Ok(1)
+    }
+    fn half_done(db: &Db) -> Result<usize> {
+        db.insert("todo:2", &Todo::new(2, "half".into()))?;
+        db.remove("todo:1")?;
+        anyhow::bail!("out of disk")
+    }
+
+    #[test]
+    fn test_failed_migration_rolls_back() -> Result<()> {
+        let db = TestDb::new("migrations_rollback")?;
+        db.insert(VERSION_KEY, &7u32)?;
+        db.insert("todo:1", &Todo::new(1, "original".into()))?;
+        let migrations = [
+            Migration {
+                version: 8,
+                name: "rename",
+                run: rename_todo,
+            },
+            Migration {
+                version: 9,
+                name: "half done",
+                run: half_done,
+            },
+        ];
+
+        assert!(run_tree(&db, &migrations).is_err());
+        // the migration before the failing one stays applied, the failing one left no trace
+        assert_eq!(db.get::<Todo, _>("todo:1")?.unwrap().title, "renamed");
+        assert!(db.get::<Todo, _>("todo:2")?.is_none());
+        assert_eq!(db.get::<u32, _>(VERSION_KEY)?, Some(8));
+
+        let status = tree_status(&db, None, &migrations)?;
+        assert!(matches!(status.entries[0].state, State::Applied(_)));
+        assert_eq!(status.entries[1].state, State::Pending);
+        Ok(())
+    }
+
+    #[test]
+    fn test_status_of_untracked_versions() -> Result<()> {
+        let db = TestDb::new("migrations_status")?;
+        // migrated before the table existed
+        db.insert(VERSION_KEY, &3u32)?;
+
+        let trees = status(&db)?;
+        let status = &trees[0];
+        assert_eq!(status.version, 3);
+        assert_eq!(status.entries[2].state, State::Untracked);
+        assert_eq!(
+            status
+                .pending()
+                .map(|entry| entry.version)
+                .collect::<Vec<_>>(),
+            [4, 5, 6, 7]
+        );
+        Ok(())
+    }
+}
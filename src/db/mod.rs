@@ -1 +1,8 @@
+pub mod codec;
 pub mod driver;
+pub mod loader;
+pub mod lock;
+pub mod migrations;
+pub mod queue;
+pub mod snapshot;
+pub mod ttl;
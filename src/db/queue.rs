@@ -0,0 +1,239 @@
+use std::{
+    collections::VecDeque,
+    str::FromStr,
+    sync::{Arc, Mutex, MutexGuard, Weak},
+};
+
+use anyhow::{anyhow, Result};
+use tokio::sync::{oneshot, Notify, RwLock};
+
+use super::{driver::Db, loader};
+
+// commands waiting for the writer, past this a submit applies them in place
+const QUEUE_CAPACITY: usize = 1024;
+// commands applied to sled in a single batch
+const MAX_BATCH: usize = 256;
+
+// a single mutation, values are serialized by `Db::encode` and framed for their key when applied
+#[derive(Debug, Clone)]
+pub enum WriteOp {
+    Insert { key: String, value: Vec<u8> },
+    Remove { key: String },
+}
+
+// whether mutations are applied before the handler responds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WriteMode {
+    // apply in the handler, reads always see the handler's own writes
+    #[default]
+    Sync,
+    // hand off to the writer task and respond with an optimistic fragment
+    Queued,
+}
+impl FromStr for WriteMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "sync" => Ok(Self::Sync),
+            "queued" => Ok(Self::Queued),
+            other => Err(anyhow!("unknown write mode `{}`", other)),
+        }
+    }
+}
+
+// what the writer task is sent, a flush answers once everything sent before it is applied
+enum Command {
+    Write(Db, Vec<WriteOp>),
+    Flush(oneshot::Sender<()>),
+}
+
+// Commands waiting for the writer, oldest first. They are only taken out under the `AppState`
+// write lock, so whoever holds it can apply everything that is still waiting itself.
+struct Pending {
+    commands: Mutex<VecDeque<Command>>,
+    // wakes the writer for new commands, and to stop once the last queue handle is gone
+    arrived: Arc<Notify>,
+}
+impl Pending {
+    fn commands(&self) -> MutexGuard<'_, VecDeque<Command>> {
+        self.commands.lock().expect("write queue lock poisoned")
+    }
+}
+impl Drop for Pending {
+    fn drop(&mut self) {
+        self.arrived.notify_one();
+    }
+}
+
+// === Write Queue ===
+// Mutations go through here so bursts are smoothed into batched sled writes. In queued mode a
+// read-modify-write may observe data from before a pending batch, use sync mode when that matters.
+#[derive(Clone)]
+pub struct WriteQueue {
+    pending: Arc<Pending>,
+    mode: WriteMode,
+}
+impl WriteQueue {
+    // start the writer task, it runs until every queue handle is dropped, commands still waiting
+    // then are dropped with them, see `flush`
+    pub fn spawn(lock: Arc<RwLock<Db>>, mode: WriteMode) -> Self {
+        let arrived = Arc::new(Notify::new());
+        let pending = Arc::new(Pending {
+            commands: Mutex::default(),
+            arrived: arrived.clone(),
+        });
+        tokio::spawn(run_writer(lock, Arc::downgrade(&pending), arrived));
+        Self { pending, mode }
+    }
+
+    pub fn mode(&self) -> WriteMode {
+        self.mode
+    }
+
+    // Apply `ops` atomically to `db`, either right away or through the writer task. Callers hold
+    // the `AppState` write lock, which the writer needs too, so a full queue is never waited on:
+    // what is waiting is applied here, in order, followed by `ops`.
+    pub async fn submit(&self, db: &Db, ops: Vec<WriteOp>) -> Result<()> {
+        match self.mode {
+            WriteMode::Sync => db.apply_batch(ops),
+            WriteMode::Queued => {
+                loader::queued();
+                let mut commands = self.pending.commands();
+                if commands.len() < QUEUE_CAPACITY {
+                    commands.push_back(Command::Write(db.clone(), ops));
+                    drop(commands);
+                    self.pending.arrived.notify_one();
+                    return Ok(());
+                }
+                let waiting = commands.drain(..).collect::<Vec<_>>();
+                drop(commands);
+                tracing::debug!(
+                    commands = waiting.len(),
+                    "write queue is full, applying in place"
+                );
+                apply_all(waiting);
+                db.apply_batch(ops)
+            }
+        }
+    }
+
+    // wait until everything submitted so far is applied, e.g. before shutting down
+    pub async fn flush(&self) -> Result<()> {
+        if self.mode == WriteMode::Sync {
+            return Ok(());
+        }
+        let (done, applied) = oneshot::channel();
+        self.pending.commands().push_back(Command::Flush(done));
+        self.pending.arrived.notify_one();
+        applied.await.map_err(|_| anyhow!("write queue is closed"))
+    }
+}
+impl std::fmt::Debug for WriteQueue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WriteQueue")
+            .field("mode", &self.mode)
+            .finish()
+    }
+}
+
+async fn run_writer(lock: Arc<RwLock<Db>>, pending: Weak<Pending>, arrived: Arc<Notify>) {
+    loop {
+        arrived.notified().await;
+        // the lock keeps snapshots consistent, and commands are only taken out while holding it
+        let guard = lock.write().await;
+        let Some(queue) = pending.upgrade() else {
+            break;
+        };
+        loop {
+            let commands = {
+                let mut waiting = queue.commands();
+                let count = waiting.len().min(MAX_BATCH);
+                waiting.drain(..count).collect::<Vec<_>>()
+            };
+            if commands.is_empty() {
+                break;
+            }
+            let count = commands.len();
+            apply_all(commands);
+            tracing::debug!(commands = count, "applied queued writes");
+        }
+        drop(guard);
+    }
+}
+
+// Apply `commands` in order, those for the same tree in a row share a sled batch. Flushes are
+// answered once everything before them is in.
+fn apply_all(commands: Vec<Command>) {
+    let mut pending: Option<(Db, Vec<WriteOp>)> = None;
+    let mut flushes = Vec::new();
+    for command in commands {
+        let (db, ops) = match command {
+            Command::Write(db, ops) => (db, ops),
+            Command::Flush(done) => {
+                flushes.push(done);
+                continue;
+            }
+        };
+        match &mut pending {
+            Some((current, batch)) if current.tree_name() == db.tree_name() => batch.extend(ops),
+            _ => {
+                if let Some((current, batch)) = pending.replace((db, ops)) {
+                    apply(&current, batch);
+                }
+            }
+        }
+    }
+    if let Some((current, batch)) = pending {
+        apply(&current, batch);
+    }
+    for done in flushes {
+        let _ = done.send(());
+    }
+}
+
+fn apply(db: &Db, ops: Vec<WriteOp>) {
+    if let Err(err) = db.apply_batch(ops) {
+        tracing::error!(error = %err, "failed to apply queued writes");
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::fixtures::TestDb;
+
+    #[tokio::test]
+    async fn test_a_full_queue_is_applied_in_place() -> Result<()> {
+        let db = TestDb::new("queue_full")?;
+        let lock = Arc::new(RwLock::new(db.for_tenant(None)?));
+        let writes = WriteQueue::spawn(lock.clone(), WriteMode::Queued);
+        let guard = lock.write().await;
+        let submitted = async {
+            for count in 0..=QUEUE_CAPACITY as u64 {
+                let ops = vec![WriteOp::Insert {
+                    key: "count".to_string(),
+                    value: guard.encode(&count)?,
+                }];
+                writes.submit(&guard, ops).await?;
+            }
+            anyhow::Ok(())
+        };
+        tokio::time::timeout(Duration::from_secs(5), submitted).await??;
+        // the last write is applied after every one waiting before it
+        assert_eq!(guard.get::<u64, _>("count")?, Some(QUEUE_CAPACITY as u64));
+        drop(guard);
+
+        let handle = lock.read().await.clone();
+        let ops = vec![WriteOp::Remove {
+            key: "count".to_string(),
+        }];
+        writes.submit(&handle, ops).await?;
+        writes.flush().await?;
+        assert_eq!(db.get::<u64, _>("count")?, None);
+        Ok(())
+    }
+}
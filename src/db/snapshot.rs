@@ -0,0 +1,65 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use sled::IVec;
+
+use super::{codec::Codec, driver::context};
+
+// === Snapshot ===
+// An in-memory copy of a key range, later writes to the `Db` are not visible through it.
+pub struct Snapshot {
+    entries: BTreeMap<String, IVec>,
+    codec: Codec,
+    // the tree and namespace the entries were copied from, their values are bound to it
+    tree: IVec,
+    prefix: String,
+}
+impl Snapshot {
+    pub(super) fn new(
+        entries: BTreeMap<String, IVec>,
+        codec: Codec,
+        tree: IVec,
+        prefix: String,
+    ) -> Self {
+        Self {
+            entries,
+            codec,
+            tree,
+            prefix,
+        }
+    }
+
+    pub(super) fn entries(&self) -> &BTreeMap<String, IVec> {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get<T: DeserializeOwned, K: AsRef<str>>(&self, key: K) -> Result<Option<T>> {
+        let key = key.as_ref();
+        let value = match self.entries.get(key) {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        let value = self.codec.decode(value, &self.context(key))?;
+        Ok(Some(value))
+    }
+    pub fn iter<'a, T: DeserializeOwned + 'a>(
+        &'a self,
+    ) -> impl Iterator<Item = Result<(String, T)>> + 'a {
+        self.entries.iter().map(move |(key, value)| {
+            let value = self.codec.decode(value, &self.context(key))?;
+            Ok((key.clone(), value))
+        })
+    }
+
+    fn context(&self, key: &str) -> Vec<u8> {
+        context(&self.tree, format!("{}{}", self.prefix, key).as_bytes())
+    }
+}
@@ -0,0 +1,87 @@
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use tokio::sync::RwLock;
+
+use super::driver::Db;
+
+// `ttl:{expires_at}:{key}`, zero padded so the index sorts by expiry
+pub const INDEX_PREFIX: &str = "ttl:";
+// `expiry:{key}`, the current expiry of a key
+const EXPIRY_PREFIX: &str = "expiry:";
+
+pub fn index_key(expires_at: u64, key: &str) -> String {
+    format!("{}{:020}:{}", INDEX_PREFIX, expires_at, key)
+}
+pub fn expiry_key(key: &str) -> String {
+    format!("{}{}", EXPIRY_PREFIX, key)
+}
+pub fn parse_index_key(index_key: &[u8]) -> Result<u64> {
+    let start = INDEX_PREFIX.len();
+    let timestamp = index_key
+        .get(start..start + 20)
+        .ok_or_else(|| anyhow!("malformed ttl index key"))?;
+    Ok(std::str::from_utf8(timestamp)?.parse()?)
+}
+
+// milliseconds since the unix epoch
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+// === Sweeper ===
+fn sweep_all(db: &Db) -> Result<usize> {
+    let now = db.clock().now_millis();
+    let mut expired = 0;
+    for tree in db.all_trees()? {
+        expired += tree.sweep_expired(now)?;
+    }
+    Ok(expired)
+}
+
+// `Db::get` treats expired keys as gone right away, scans and iterations still see them until
+// the next sweep, up to `interval` later.
+pub fn spawn_sweeper(db: Arc<RwLock<Db>>, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let db = db.write().await;
+            match sweep_all(&db) {
+                Ok(0) => {}
+                Ok(expired) => tracing::debug!(expired, "swept expired keys"),
+                Err(err) => tracing::error!(error = %err, "failed to sweep expired keys"),
+            }
+        }
+    });
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn test_index_keys_sort_by_expiry(
+            a in any::<u64>(),
+            b in any::<u64>(),
+            key_a in "[a-z0-9:]{1,20}",
+            key_b in "[a-z0-9:]{1,20}",
+        ) {
+            let (index_a, index_b) = (index_key(a, &key_a), index_key(b, &key_b));
+            if a != b {
+                prop_assert_eq!(index_a.cmp(&index_b), a.cmp(&b));
+            }
+            prop_assert_eq!(parse_index_key(index_a.as_bytes()).unwrap(), a);
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use axum::http::{HeaderMap, HeaderName};
+use maud::{html, Markup};
+
+use crate::views::fingerprint;
+
+// the digest of the list a page shows, sent back on refreshes
+pub const DIGEST_HEADER: HeaderName = HeaderName::from_static("x-list-digest");
+pub const DIGEST_ID: &str = "list-digest";
+// sessions remembered at most, all are forgotten at once past it (they fall back to full renders)
+const MAX_SESSIONS: usize = 10_000;
+
+// === Digests ===
+// What a rendered list looked like: its items in order and a hash of each item's html.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListDigest {
+    pub id: u64,
+    order: Vec<u64>,
+    hashes: HashMap<u64, u64>,
+}
+impl ListDigest {
+    pub fn new<'a>(items: impl IntoIterator<Item = (u64, &'a str)>) -> Self {
+        let mut order = Vec::new();
+        let mut hashes = HashMap::new();
+        let mut list = Vec::new();
+        for (id, html) in items {
+            let hash = fingerprint::hash(html.as_bytes());
+            list.extend(id.to_be_bytes());
+            list.extend(hash.to_be_bytes());
+            order.push(id);
+            hashes.insert(id, hash);
+        }
+        Self {
+            id: fingerprint::hash(&list),
+            order,
+            hashes,
+        }
+    }
+}
+
+// what a refresh has to send
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Patch {
+    // the client's list is unknown or items moved, render all of it
+    Full,
+    // only these items changed, in list order
+    Items(Vec<u64>),
+}
+
+// Compare the list the client says it has with the one about to be rendered.
+pub fn diff(previous: Option<&ListDigest>, client: Option<u64>, next: &ListDigest) -> Patch {
+    let Some(previous) = previous else {
+        return Patch::Full;
+    };
+    // the page changed the list on its own since (an added or removed item), or another tab of
+    // the session rendered last
+    if client != Some(previous.id) || previous.order != next.order {
+        return Patch::Full;
+    }
+    Patch::Items(
+        next.order
+            .iter()
+            .copied()
+            .filter(|id| previous.hashes.get(id) != next.hashes.get(id))
+            .collect(),
+    )
+}
+
+// the digest a refresh request carries
+pub fn client_digest(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(&DIGEST_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+}
+
+// === Sessions ===
+// The last list rendered per session, see `LIST_DIFFING`.
+#[derive(Debug, Clone, Default)]
+pub struct RenderCache {
+    sessions: Arc<Mutex<HashMap<String, ListDigest>>>,
+}
+impl RenderCache {
+    // remember `digest` for `session`, returns what was rendered before it
+    pub fn swap(&self, session: String, digest: ListDigest) -> Option<ListDigest> {
+        let mut sessions = self.sessions.lock().expect("render cache lock poisoned");
+        if sessions.len() >= MAX_SESSIONS && !sessions.contains_key(&session) {
+            sessions.clear();
+        }
+        sessions.insert(session, digest)
+    }
+}
+
+// === Components ===
+// holds the digest for the next refresh, swapped out of band along with patches
+pub fn digest_html(digest: &ListDigest, oob: bool) -> Markup {
+    html! {
+        span id=(DIGEST_ID) hidden data-digest=(digest.id) hx-swap-oob=[oob.then_some("true")] {}
+    }
+}
+
+// `hx-headers` sending the digest of the list on the page
+pub fn digest_headers() -> String {
+    format!(
+        "js:{{\"{}\": document.getElementById(\"{}\")?.dataset.digest || \"\"}}",
+        DIGEST_HEADER, DIGEST_ID
+    )
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let before = ListDigest::new([(1, "<li>a</li>"), (2, "<li>b</li>")]);
+        let changed = ListDigest::new([(1, "<li>a</li>"), (2, "<li>B</li>")]);
+        assert_eq!(diff(None, None, &changed), Patch::Full);
+        assert_eq!(
+            diff(Some(&before), Some(before.id), &changed),
+            Patch::Items(vec![2])
+        );
+        assert_eq!(
+            diff(Some(&before), Some(before.id), &before),
+            Patch::Items(vec![])
+        );
+        // a client that has something else falls back to a full render
+        assert_eq!(diff(Some(&before), Some(42), &changed), Patch::Full);
+        assert_eq!(diff(Some(&before), None, &changed), Patch::Full);
+        let reordered = ListDigest::new([(2, "<li>b</li>"), (1, "<li>a</li>")]);
+        assert_eq!(
+            diff(Some(&before), Some(before.id), &reordered),
+            Patch::Full
+        );
+        let added = ListDigest::new([(1, "<li>a</li>"), (2, "<li>b</li>"), (3, "<li>c</li>")]);
+        assert_eq!(diff(Some(&before), Some(before.id), &added), Patch::Full);
+    }
+
+    #[test]
+    fn test_render_cache() {
+        let cache = RenderCache::default();
+        let digest = ListDigest::new([(1, "<li>a</li>")]);
+        assert_eq!(cache.swap("a".into(), digest.clone()), None);
+        assert_eq!(cache.swap("a".into(), digest.clone()), Some(digest));
+    }
+}
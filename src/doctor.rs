@@ -0,0 +1,368 @@
+use std::{
+    fmt,
+    fs::{self, OpenOptions},
+    net::{TcpStream, ToSocketAddrs},
+    path::Path,
+    process::Command,
+    time::{Duration, SystemTime},
+};
+
+use time::OffsetDateTime;
+
+use crate::{
+    clock::{Clock, SystemClock},
+    config::Config,
+    db::{driver::Db, lock::Locked, migrations},
+    state,
+};
+
+// how long an outbound service gets to accept a connection
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+// less free space than this next to the database is worth a warning
+const LOW_DISK_BYTES: u64 = 512 * 1024 * 1024;
+// 2024-01-01, a clock before it was never set
+const EARLIEST_PLAUSIBLE: u64 = 1_704_067_200;
+
+// === Report ===
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Ok,
+    Warning,
+    Failed,
+}
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Outcome::Ok => "ok",
+            Outcome::Warning => "warn",
+            Outcome::Failed => "FAIL",
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: String,
+    pub outcome: Outcome,
+    pub detail: String,
+}
+impl Check {
+    fn new(name: impl Into<String>, outcome: Outcome, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            outcome,
+            detail: detail.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub checks: Vec<Check>,
+}
+impl Report {
+    pub fn failures(&self) -> usize {
+        self.checks
+            .iter()
+            .filter(|check| check.outcome == Outcome::Failed)
+            .count()
+    }
+}
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            writeln!(
+                f,
+                "[{:<4}] {:<20} {}",
+                check.outcome, check.name, check.detail
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// === Diagnosis ===
+// Everything that commonly keeps an instance from starting or working, checked at once:
+//
+//     rust-htmx --doctor
+//
+// and at `/admin/doctor`. The running server passes its open database, from the command line
+// the database is opened here, which also tells whether another process holds its lock.
+// Blocks on the network, call it off the async runtime.
+pub fn diagnose(config: &Config, db: Option<&Db>) -> Report {
+    let mut checks = vec![data_dir(&config.data_dir)];
+    match db {
+        Some(_) => checks.push(Check::new(
+            "database lock",
+            Outcome::Ok,
+            "held by this server",
+        )),
+        None => checks.extend(open_database(config)),
+    }
+    checks.push(disk_space(&config.data_dir));
+    let now = match db {
+        Some(db) => db.clock().now(),
+        None => SystemClock.now(),
+    };
+    checks.push(clock(&config.db_path(), now));
+    checks.extend(secrets(config));
+    let services = [
+        ("webhooks", &config.webhook_url),
+        ("geocoder", &config.nominatim_url),
+        ("transcription", &config.transcription_url),
+        ("assistant", &config.assistant_url),
+        ("sentry", &config.sentry_dsn),
+    ];
+    for (name, url) in services {
+        if let Some(url) = url {
+            checks.push(reachable(name, url));
+        }
+    }
+    if let Some(db) = db {
+        checks.push(migrations_check(db));
+    }
+    Report { checks }
+}
+
+fn data_dir(dir: &Path) -> Check {
+    const NAME: &str = "data directory";
+    if !dir.is_dir() {
+        return Check::new(
+            NAME,
+            Outcome::Failed,
+            format!("{} does not exist or is not a directory", dir.display()),
+        );
+    }
+    let probe = dir.join(".doctor-probe");
+    let written = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&probe);
+    let _ = fs::remove_file(&probe);
+    match written {
+        Ok(_) => Check::new(NAME, Outcome::Ok, format!("{} is writable", dir.display())),
+        Err(err) => Check::new(
+            NAME,
+            Outcome::Failed,
+            format!("can't write to {}: {}", dir.display(), err),
+        ),
+    }
+}
+
+// sled takes a lock on the directory, failing to open usually means the server is running
+fn open_database(config: &Config) -> Vec<Check> {
+    const NAME: &str = "database lock";
+    let path = config.db_path();
+    if !path.exists() {
+        return vec![Check::new(
+            NAME,
+            Outcome::Warning,
+            format!(
+                "no database at {} yet, the first start creates it",
+                path.display()
+            ),
+        )];
+    }
+    match state::open_db_unmigrated(config) {
+        Ok(db) => vec![
+            Check::new(NAME, Outcome::Ok, format!("{} opens", path.display())),
+            migrations_check(&db),
+        ],
+        Err(err) if err.is::<Locked>() => {
+            vec![Check::new(NAME, Outcome::Failed, err.to_string())]
+        }
+        Err(err) => vec![Check::new(
+            NAME,
+            Outcome::Failed,
+            format!("can't open {}: {:#}", path.display(), err),
+        )],
+    }
+}
+
+fn migrations_check(db: &Db) -> Check {
+    const NAME: &str = "migrations";
+    match migrations::status(db) {
+        Ok(trees) => {
+            let pending: usize = trees.iter().map(|tree| tree.pending().count()).sum();
+            if pending == 0 {
+                Check::new(NAME, Outcome::Ok, "up to date")
+            } else {
+                Check::new(
+                    NAME,
+                    Outcome::Warning,
+                    format!("{} pending, applied on the next start", pending),
+                )
+            }
+        }
+        Err(err) => Check::new(NAME, Outcome::Failed, format!("{:#}", err)),
+    }
+}
+
+// `df` in POSIX format, there is no portable way to ask the standard library
+fn available_bytes(dir: &Path) -> Option<u64> {
+    let output = Command::new("df").arg("-Pk").arg(dir).output().ok()?;
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    parse_df(&stdout)
+}
+
+fn parse_df(output: &str) -> Option<u64> {
+    let line = output.lines().nth(1)?;
+    let kilobytes: u64 = line.split_whitespace().nth(3)?.parse().ok()?;
+    Some(kilobytes * 1024)
+}
+
+fn disk_space(dir: &Path) -> Check {
+    const NAME: &str = "disk space";
+    match available_bytes(dir) {
+        Some(bytes) if bytes < LOW_DISK_BYTES => Check::new(
+            NAME,
+            Outcome::Warning,
+            format!("only {} MiB free", bytes / 1024 / 1024),
+        ),
+        Some(bytes) => Check::new(
+            NAME,
+            Outcome::Ok,
+            format!("{} MiB free", bytes / 1024 / 1024),
+        ),
+        None => Check::new(NAME, Outcome::Warning, "could not be determined"),
+    }
+}
+
+// A clock that was never set, or went back behind what the database last wrote.
+fn clock(db_path: &Path, now: OffsetDateTime) -> Check {
+    const NAME: &str = "clock";
+    let unix = now.unix_timestamp().max(0) as u64;
+    if unix < EARLIEST_PLAUSIBLE {
+        return Check::new(
+            NAME,
+            Outcome::Failed,
+            "the system time is before 2024, is the clock set?",
+        );
+    }
+    let written = fs::metadata(db_path).and_then(|metadata| metadata.modified());
+    match written
+        .ok()
+        .and_then(|at| at.duration_since(SystemTime::from(now)).ok())
+    {
+        Some(ahead) if ahead > Duration::from_secs(60) => Check::new(
+            NAME,
+            Outcome::Warning,
+            format!(
+                "the database was written {}s in the future, the clock went back",
+                ahead.as_secs()
+            ),
+        ),
+        _ => Check::new(NAME, Outcome::Ok, format!("unix time {}", unix)),
+    }
+}
+
+fn secrets(config: &Config) -> Vec<Check> {
+    let mut checks = vec![match &config.secret_key {
+        Some(_) => Check::new("secret key", Outcome::Ok, "set"),
+        None => Check::new(
+            "secret key",
+            Outcome::Warning,
+            "SECRET_KEY is unset, everyone is signed out on each restart",
+        ),
+    }];
+    if let Some(path) = &config.encryption_keyfile {
+        checks.push(match fs::read(path) {
+            Ok(_) => Check::new("encryption keys", Outcome::Ok, "readable"),
+            Err(err) => Check::new(
+                "encryption keys",
+                Outcome::Failed,
+                format!("can't read {}: {}", path.display(), err),
+            ),
+        });
+    }
+    checks
+}
+
+// Whether the host of `url` accepts a connection, any answer at all would do.
+fn reachable(name: &str, url: &str) -> Check {
+    let address = reqwest::Url::parse(url).ok().and_then(|url| {
+        let host = url.host_str()?.to_string();
+        Some((host, url.port_or_known_default()?))
+    });
+    let Some((host, port)) = address else {
+        return Check::new(name, Outcome::Failed, format!("`{}` is not a url", url));
+    };
+    let connected = (host.as_str(), port)
+        .to_socket_addrs()
+        .map_err(|err| err.to_string())
+        .and_then(|mut addrs| addrs.next().ok_or_else(|| "no address".to_string()))
+        .and_then(|addr| {
+            TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT).map_err(|err| err.to_string())
+        });
+    match connected {
+        Ok(_) => Check::new(name, Outcome::Ok, format!("{}:{} is reachable", host, port)),
+        Err(err) => Check::new(
+            name,
+            Outcome::Failed,
+            format!("can't reach {}:{}: {}", host, port, err),
+        ),
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+    use crate::fixtures::{self, TestDb};
+
+    #[test]
+    fn test_parse_df() {
+        let output = "Filesystem 1024-blocks Used Available Capacity Mounted on\n\
+                      /dev/sda1 102400 51200 51200 50% /\n";
+        assert_eq!(parse_df(output), Some(51200 * 1024));
+        assert_eq!(parse_df(""), None);
+    }
+
+    #[test]
+    fn test_data_dir() {
+        let dir = std::env::temp_dir();
+        assert_eq!(data_dir(&dir).outcome, Outcome::Ok);
+        assert!(!dir.join(".doctor-probe").exists());
+        assert_eq!(
+            data_dir(&dir.join("does-not-exist")).outcome,
+            Outcome::Failed
+        );
+    }
+
+    #[test]
+    fn test_clock() {
+        let missing = std::env::temp_dir().join("does-not-exist");
+        assert_eq!(clock(&missing, fixtures::NOW).outcome, Outcome::Ok);
+        let unset = datetime!(1970-01-02 0:00 UTC);
+        assert_eq!(clock(&missing, unset).outcome, Outcome::Failed);
+    }
+
+    #[test]
+    fn test_diagnose_with_the_server_database() -> anyhow::Result<()> {
+        let db = TestDb::new("doctor")?;
+        migrations::run(&db)?;
+        let config = Config {
+            data_dir: std::env::temp_dir(),
+            webhook_url: Some("not a url".into()),
+            ..Config::default()
+        };
+        let report = diagnose(&config, Some(&*db));
+        let outcome = |name: &str| {
+            report
+                .checks
+                .iter()
+                .find(|check| check.name == name)
+                .map(|check| check.outcome)
+        };
+        assert_eq!(outcome("database lock"), Some(Outcome::Ok));
+        assert_eq!(outcome("migrations"), Some(Outcome::Ok));
+        assert_eq!(outcome("secret key"), Some(Outcome::Warning));
+        assert_eq!(outcome("webhooks"), Some(Outcome::Failed));
+        assert_eq!(report.failures(), 1);
+        assert!(report.to_string().contains("[FAIL] webhooks"));
+        Ok(())
+    }
+}
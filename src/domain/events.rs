@@ -0,0 +1,126 @@
+use anyhow::Result;
+
+use crate::{
+    activity,
+    db::{driver::Db, queue::WriteOp},
+    integrations,
+    models::Todo,
+    state::AppState,
+};
+
+// sent to the open pages of a workspace after any change, e.g. the recent activity refreshes on it
+pub const CHANGED_EVENT: &str = "workspace-changed";
+
+// === Events ===
+// What happened in a workspace, told without a request or a response. Handlers say what they did
+// and the consumers below decide what follows from it, so a new side effect is added here once
+// instead of in every handler.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DomainEvent {
+    TodoCreated(Todo),
+    // its title, dates, priority or tags changed
+    TodoUpdated(Todo),
+    TodoCompleted(Todo),
+    // completed as won't do
+    TodoCancelled(Todo),
+    TodoReopened(Todo),
+    TodoRemoved(Todo),
+    // put out of the list by a list's auto-archive policy
+    TodoArchived(Todo),
+    // `merged` was folded into `into` and is gone
+    TodoMerged { into: Todo, merged: Todo },
+    // a kiosk link to the list was made
+    ListShared { token: String },
+}
+impl DomainEvent {
+    // completed, cancelled or reopened, whichever `todo` is now
+    pub fn toggled(todo: Todo) -> Self {
+        if todo.is_cancelled() {
+            DomainEvent::TodoCancelled(todo)
+        } else if todo.completed {
+            DomainEvent::TodoCompleted(todo)
+        } else {
+            DomainEvent::TodoReopened(todo)
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DomainEvent::TodoCreated(_) => "todo.created",
+            DomainEvent::TodoUpdated(_) => "todo.updated",
+            DomainEvent::TodoCompleted(_) => "todo.completed",
+            DomainEvent::TodoCancelled(_) => "todo.cancelled",
+            DomainEvent::TodoReopened(_) => "todo.reopened",
+            DomainEvent::TodoRemoved(_) => "todo.removed",
+            DomainEvent::TodoArchived(_) => "todo.archived",
+            DomainEvent::TodoMerged { .. } => "todo.merged",
+            DomainEvent::ListShared { .. } => "list.shared",
+        }
+    }
+
+    pub fn todo(&self) -> Option<&Todo> {
+        match self {
+            DomainEvent::TodoCreated(todo)
+            | DomainEvent::TodoUpdated(todo)
+            | DomainEvent::TodoCompleted(todo)
+            | DomainEvent::TodoCancelled(todo)
+            | DomainEvent::TodoReopened(todo)
+            | DomainEvent::TodoRemoved(todo)
+            | DomainEvent::TodoArchived(todo)
+            | DomainEvent::TodoMerged { into: todo, .. } => Some(todo),
+            DomainEvent::ListShared { .. } => None,
+        }
+    }
+}
+
+// === Consumers ===
+// What goes into the batch of the change itself, so it is stored exactly when the change is:
+// deliveries to the integrations of the list, the link previews of a new todo and the entry in
+// the activity log.
+pub fn ops(
+    state: &AppState,
+    db: &Db,
+    tenant: Option<&str>,
+    event: &DomainEvent,
+) -> Result<Vec<WriteOp>> {
+    let mut ops = integrations::ops(state, db, tenant, event)?;
+    ops.extend(activity::record_ops(db, event)?);
+    if let (DomainEvent::TodoCreated(todo), Some(previews)) = (event, &state.previews) {
+        ops.extend(previews.ops(db, tenant, todo)?);
+    }
+    Ok(ops)
+}
+
+// What follows once the change is stored: the queued deliveries are picked up, the event goes to
+// the audit log and the open pages of the workspace hear about it.
+pub fn committed(state: &AppState, tenant: Option<&str>, event: &DomainEvent) {
+    state.tasks.notify();
+    if let Some(audit) = &state.audit {
+        audit.record(state.clock.now().unix_timestamp() as u64, tenant, event);
+    }
+    state.events.publish(tenant, CHANGED_EVENT, event.name());
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toggled() {
+        let mut todo = Todo::new(1, "Water the plants".into());
+        assert_eq!(DomainEvent::toggled(todo.clone()).name(), "todo.reopened");
+        todo.set_completed(true);
+        let event = DomainEvent::toggled(todo);
+        assert_eq!(event.name(), "todo.completed");
+        assert_eq!(event.todo().map(|todo| todo.id), Some(1));
+        let mut cancelled = Todo::new(2, "Learn the banjo".into());
+        cancelled.cancel(None);
+        assert_eq!(DomainEvent::toggled(cancelled).name(), "todo.cancelled");
+
+        let shared = DomainEvent::ListShared {
+            token: "abc".into(),
+        };
+        assert_eq!(shared.todo(), None);
+    }
+}
@@ -0,0 +1,274 @@
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, State},
+    Extension,
+};
+use maud::{html, Markup};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::{user::CurrentUser, visitor::Visitor},
+    db::{driver::Db, queue::WriteOp},
+    error::AppError,
+    events::Events,
+    extract::FormOrJson,
+    history,
+    models::Todo,
+    repository::{entity::Repository, todo::todo_key},
+    routes,
+    state::AppState,
+    tenant::Tenant,
+    views,
+};
+
+// how long a lock outlives the last heartbeat of its editor
+const LOCK_TTL: Duration = Duration::from_secs(60);
+// the open editor renews its lock this often
+const HEARTBEAT_SECS: u64 = 20;
+
+// === Edit Locks ===
+// Opening the title editor locks the todo for everyone else until it is saved, cancelled or the
+// editor stops sending heartbeats. Stored with a ttl in the workspace's tree, `edit_lock:{todo}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EditLock {
+    // the visitor holding it
+    pub holder: String,
+    // shown to everyone else, "X is editing"
+    pub name: String,
+    pub expires_at: u64,
+}
+
+fn lock_key(todo: u64) -> String {
+    format!("edit_lock:{}", todo)
+}
+
+// in seconds, what locks expire against
+fn now(db: &Db) -> u64 {
+    db.clock().now_millis() / 1000
+}
+
+// the lock on `todo`, if it has not run out yet (the sweeper may not have removed it)
+pub fn lock(db: &Db, todo: u64) -> anyhow::Result<Option<EditLock>> {
+    let lock = db.get::<EditLock, _>(lock_key(todo))?;
+    Ok(lock.filter(|lock| lock.expires_at > now(db)))
+}
+
+// Take or renew the lock for `holder`, the lock of whoever else holds it otherwise.
+pub fn acquire(
+    db: &Db,
+    todo: u64,
+    holder: &str,
+    name: &str,
+) -> anyhow::Result<Result<EditLock, EditLock>> {
+    if let Some(lock) = lock(db, todo)? {
+        if lock.holder != holder {
+            return Ok(Err(lock));
+        }
+    }
+    let lock = EditLock {
+        holder: holder.to_string(),
+        name: name.to_string(),
+        expires_at: now(db) + LOCK_TTL.as_secs(),
+    };
+    db.insert_with_ttl(lock_key(todo), &lock, LOCK_TTL)?;
+    Ok(Ok(lock))
+}
+
+fn display_name(user: &CurrentUser) -> String {
+    user.account().unwrap_or("Someone").to_string()
+}
+
+fn event_name(todo: u64) -> String {
+    format!("edit-lock-{}", todo)
+}
+
+fn publish(events: &Events, tenant: &Tenant, db: &Db, todo: u64, lock: Option<&EditLock>) {
+    events.publish(
+        tenant.id(),
+        event_name(todo),
+        badge_html(todo, lock, now(db)).into_string(),
+    );
+}
+
+// === Components ===
+// where the live "X is editing" badge goes, for lists and the detail page alike
+pub fn badge_slot(todo: u64, badge: Markup) -> Markup {
+    html! {
+        span id=(event_name(todo)) sse-swap=(event_name(todo)) { (badge) }
+    }
+}
+
+fn badge_html(todo: u64, lock: Option<&EditLock>, now: u64) -> Markup {
+    html! {
+        @if let Some(lock) = lock {
+            // looks again once the lock would have run out, an abandoned editor sends no event
+            @let left = lock.expires_at.saturating_sub(now) + 1;
+            span class="ml-2 text-xs font-bold bg-purple-100 text-purple-800 rounded px-2 py-1"
+                hx-get=(routes::TodoEditLock::url(todo)) hx-trigger={ "load delay:" (left) "s" } hx-swap="outerHTML" {
+                "✎ " (lock.name) " is editing"
+            }
+        }
+    }
+}
+
+// the heading of the detail page, with the button opening the editor
+pub fn title_html(todo: &Todo, lock: Option<&EditLock>, now: u64) -> Markup {
+    html! {
+        div id="todo-title" class="flex items-center" {
+            h1 class={"text-3xl text-gray-700 " @if todo.completed { "line-through" }} { (todo.title) }
+            button class="ml-4 text-blue-500 hover:text-blue-700" type="button"
+                hx-post=(routes::TodoEdit::url(todo.id)) hx-target="#todo-title" hx-swap="outerHTML" { "Edit" }
+            (badge_slot(todo.id, badge_html(todo.id, lock, now)))
+        }
+    }
+}
+
+fn editor_html(todo: &Todo) -> Markup {
+    html! {
+        form id="todo-title" class="flex items-center" hx-post=(routes::TodoTitle::url(todo.id)) hx-target="this" hx-swap="outerHTML" {
+            input class="flex-grow text-3xl rounded p-2 mr-2 border" type="text" name="title" value=(todo.title) autofocus required;
+            button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded mr-2" type="submit" { "Save" }
+            button class="text-gray-500 hover:text-gray-700" type="button"
+                hx-post=(routes::TodoEditCancel::url(todo.id)) hx-target="#todo-title" hx-swap="outerHTML" { "Cancel" }
+            // keeps the lock while the editor is open
+            div hx-post=(routes::TodoEdit::url(todo.id)) hx-trigger={ "every " (HEARTBEAT_SECS) "s" } hx-swap="none" {}
+        }
+    }
+}
+
+pub async fn load_title(
+    state: &AppState,
+    tenant: &Tenant,
+    todo: &Todo,
+) -> Result<Markup, AppError> {
+    let db = state.read().await.for_tenant(tenant.id())?;
+    Ok(title_html(todo, lock(&db, todo.id)?.as_ref(), now(&db)))
+}
+
+// === Routes ===
+// open the editor, or say who else has it open
+pub async fn edit(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    user: CurrentUser,
+    Extension(Visitor(visitor)): Extension<Visitor>,
+    Path(id): Path<u64>,
+) -> Result<Markup, AppError> {
+    let events = state.events.clone();
+    let guard = state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    let todo = db.get::<Todo, _>(todo_key(id))?.ok_or(AppError::NotFound)?;
+    match acquire(&db, id, &visitor, &display_name(&user))? {
+        Ok(lock) => {
+            publish(&events, &tenant, &db, id, Some(&lock));
+            Ok(editor_html(&todo))
+        }
+        Err(lock) => Ok(html! {
+            (title_html(&todo, Some(&lock), now(&db)))
+            (views::notice_toast_oob(&format!("{} is editing this todo right now.", lock.name)))
+        }),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SetTitle {
+    title: String,
+}
+pub async fn save(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    Extension(Visitor(visitor)): Extension<Visitor>,
+    Path(id): Path<u64>,
+    FormOrJson(SetTitle { title }): FormOrJson<SetTitle>,
+) -> Result<Markup, AppError> {
+    let title = title.trim();
+    if title.is_empty() {
+        return Err(AppError::Invalid("a todo needs a title".to_string()));
+    }
+    let events = state.events.clone();
+    let writes = state.writes.clone();
+    let guard = state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    let mut todo = db.get::<Todo, _>(todo_key(id))?.ok_or(AppError::NotFound)?;
+    // someone whose lock ran out while another took over has to start again
+    if let Some(lock) = lock(&db, id)? {
+        if lock.holder != visitor {
+            return Err(AppError::Invalid(format!(
+                "{} is editing this todo, your change was not saved.",
+                lock.name
+            )));
+        }
+    }
+    let mut ops = history::record_ops(&db, &todo)?;
+    todo.title = title.to_string();
+    todo.touch(&**db.clock());
+    ops.extend(Repository::new(&db).put_ops(&todo)?);
+    ops.push(WriteOp::Remove { key: lock_key(id) });
+    writes.submit(&db, ops).await?;
+    publish(&events, &tenant, &db, id, None);
+    Ok(title_html(&todo, None, now(&db)))
+}
+
+// close the editor without saving
+pub async fn cancel(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    Extension(Visitor(visitor)): Extension<Visitor>,
+    Path(id): Path<u64>,
+) -> Result<Markup, AppError> {
+    let events = state.events.clone();
+    let guard = state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    let todo = db.get::<Todo, _>(todo_key(id))?.ok_or(AppError::NotFound)?;
+    let lock = match lock(&db, id)? {
+        Some(lock) if lock.holder == visitor => {
+            db.remove(lock_key(id))?;
+            publish(&events, &tenant, &db, id, None);
+            None
+        }
+        other => other,
+    };
+    Ok(title_html(&todo, lock.as_ref(), now(&db)))
+}
+
+// the badge as it is now
+pub async fn badge(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    Path(id): Path<u64>,
+) -> Result<Markup, AppError> {
+    let db = state.read().await.for_tenant(tenant.id())?;
+    Ok(badge_html(id, lock(&db, id)?.as_ref(), now(&db)))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::TestDb;
+
+    #[test]
+    fn test_acquire() -> anyhow::Result<()> {
+        let db = TestDb::new("editing")?;
+
+        assert!(lock(&db, 1)?.is_none());
+        assert!(acquire(&db, 1, "a", "Ada")?.is_ok());
+        // the holder renews, anyone else sees who holds it
+        assert!(acquire(&db, 1, "a", "Ada")?.is_ok());
+        assert_eq!(acquire(&db, 1, "b", "Bob")?.unwrap_err().name, "Ada");
+        assert!(acquire(&db, 2, "b", "Bob")?.is_ok());
+
+        // an expired lock the sweeper did not get to yet is gone
+        db.insert(
+            lock_key(1),
+            &EditLock {
+                holder: "a".into(),
+                name: "Ada".into(),
+                expires_at: 1,
+            },
+        )?;
+        assert!(acquire(&db, 1, "b", "Bob")?.is_ok());
+        Ok(())
+    }
+}
@@ -0,0 +1,195 @@
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use maud::{html, Markup, DOCTYPE};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    db::driver::Db,
+    error::AppError,
+    method_override,
+    models::{Progress, Todo},
+    repository::query::TodoQuery,
+    routes,
+    state::AppState,
+    tenant::Tenant,
+    views,
+};
+
+// embeddable progress widgets, `embed:{token}` in the root tree like kiosk links
+const EMBED_PREFIX: &str = "embed:";
+
+// A link that shows a workspace's progress in someone else's page, e.g. Notion or a blog.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Embed {
+    pub token: String,
+    pub tenant: Option<String>,
+    pub created_at: u64,
+}
+
+fn embed_key(token: &str) -> String {
+    format!("{}{}", EMBED_PREFIX, token)
+}
+
+pub fn create(db: &Db, tenant: Option<&str>) -> anyhow::Result<Embed> {
+    let mut token = [0; 24];
+    rand::thread_rng().fill_bytes(&mut token);
+    let embed = Embed {
+        token: hex::encode(token),
+        tenant: tenant.map(str::to_string),
+        created_at: db.clock().now_millis() / 1000,
+    };
+    db.insert(embed_key(&embed.token), &embed)?;
+    Ok(embed)
+}
+
+// the workspace's embed links
+pub fn list(db: &Db, tenant: Option<&str>) -> anyhow::Result<Vec<Embed>> {
+    let mut embeds = Vec::new();
+    for item in db.iter_prefix::<Embed>(EMBED_PREFIX)? {
+        let (_, embed) = item?;
+        if embed.tenant.as_deref() == tenant {
+            embeds.push(embed);
+        }
+    }
+    Ok(embeds)
+}
+
+// done over the todos that have not been archived
+pub fn progress(todos: &[Todo]) -> Progress {
+    Progress {
+        done: todos.iter().filter(|todo| todo.completed).count(),
+        total: todos.len(),
+    }
+}
+
+// === Components ===
+// A standalone document for an iframe: no scripts, no navigation, just the progress.
+fn widget_html(progress: Progress) -> Markup {
+    html! {
+        (DOCTYPE)
+        html {
+            head {
+                meta charset="utf-8";
+                title { "Todo progress" }
+                style {
+                    "body{font-family:sans-serif;margin:0;padding:12px;color:#374151}"
+                    ".bar{background:#e5e7eb;border-radius:4px;height:8px}"
+                    ".fill{background:#10b981;border-radius:4px;height:8px}"
+                }
+            }
+            body {
+                p { (progress.done) " of " (progress.total) " todos done (" (progress.percent()) "%)" }
+                div class="bar" role="progressbar" aria-valuemin="0" aria-valuemax="100" aria-valuenow=(progress.percent()) {
+                    div class="fill" style={ "width:" (progress.percent()) "%" } {}
+                }
+            }
+        }
+    }
+}
+
+pub fn settings_html(public_url: &str, embeds: &[Embed]) -> Markup {
+    html! {
+        section id="embeds" class="bg-white rounded-lg shadow-lg p-6 space-y-4" {
+            h2 class="text-2xl text-gray-700" { "Embeds" }
+            p class="text-gray-600" { "Show your progress on another site. Anyone with the link sees the counts, not the todos." }
+            ul class="space-y-2" {
+                @for embed in embeds {
+                    @let url = format!("{}{}", public_url.trim_end_matches('/'), routes::Embed::url(&embed.token));
+                    li class="flex items-center space-x-2" {
+                        input class="flex-grow rounded border p-2 font-mono text-sm" type="text" readonly aria-label="Embed code"
+                            value={ "<iframe src=\"" (url) "\" width=\"320\" height=\"80\" frameborder=\"0\"></iframe>" };
+                        form method="post" action=(routes::EmbedRevoke::url(&embed.token)) {
+                            input type="hidden" name=(method_override::METHOD_FIELD) value="DELETE";
+                            button class="text-red-500 hover:text-red-700" type="submit"
+                                hx-delete=(routes::EmbedRevoke::url(&embed.token)) hx-target="#embeds" hx-swap="outerHTML" { "Revoke" }
+                        }
+                    }
+                }
+            }
+            form method="post" action=(routes::Embeds::url()) hx-post=(routes::Embeds::url()) hx-target="#embeds" hx-swap="outerHTML" {
+                button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" { "Create embed" }
+            }
+        }
+    }
+}
+
+// === Routes ===
+pub async fn show(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+) -> Result<Response, AppError> {
+    let todos = {
+        let db = state.read().await;
+        let embed = db
+            .get::<Embed, _>(embed_key(&token))?
+            .ok_or(AppError::NotFound)?;
+        TodoQuery::new().list(&db.for_tenant(embed.tenant.as_deref())?)?
+    };
+    // the widget is the one page that may be framed, by the sources configured
+    let csp = format!("frame-ancestors {}", state.config.embed_frame_ancestors);
+    Ok((
+        [(
+            header::CONTENT_SECURITY_POLICY,
+            HeaderValue::from_str(&csp).map_err(anyhow::Error::from)?,
+        )],
+        widget_html(progress(&todos)),
+    )
+        .into_response())
+}
+
+pub async fn create_link(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let public_url = state.config.public_url.clone();
+    let db = state.write().await;
+    create(&db, tenant.id())?;
+    Ok(views::fragment_or_redirect(
+        &headers,
+        settings_html(&public_url, &list(&db, tenant.id())?),
+        &routes::Settings::url(),
+    ))
+}
+
+pub async fn revoke(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Path(token): Path<String>,
+) -> Result<Response, AppError> {
+    let public_url = state.config.public_url.clone();
+    let db = state.write().await;
+    // only the workspace a link belongs to can revoke it
+    if db
+        .get::<Embed, _>(embed_key(&token))?
+        .is_some_and(|embed| embed.tenant.as_deref() == tenant.id())
+    {
+        db.remove(embed_key(&token))?;
+    }
+    Ok(views::fragment_or_redirect(
+        &headers,
+        settings_html(&public_url, &list(&db, tenant.id())?),
+        &routes::Settings::url(),
+    ))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress_counts_completed() {
+        let mut done = Todo::new(1, "done".into());
+        done.set_completed(true);
+        let todos = vec![done, Todo::new(2, "open".into())];
+        let progress = progress(&todos);
+        assert_eq!((progress.done, progress.total), (1, 2));
+        assert_eq!(progress.percent(), 50);
+    }
+}
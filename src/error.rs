@@ -1,19 +1,99 @@
+use std::any::Any;
+
 use axum::{
-    http::StatusCode,
+    extract::Request,
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
     response::{IntoResponse, Response},
 };
 
-// Make our own error that wraps `anyhow::Error`.
-pub struct AppError(anyhow::Error);
+use crate::{telemetry::REQUEST_ID_HEADER, views};
+
+// what internal errors and panics show, the details only go to the log along with the request id
+const UNEXPECTED: &str = "The server hit an unexpected error while handling your request.";
+
+// Our app-wide error, every handler returns `Result<_, AppError>`.
+#[derive(Debug)]
+pub enum AppError {
+    // The requested page or record does not exist.
+    NotFound,
+    // The input breaks a rule, the message tells the user which.
+    Invalid(String),
+    // Fields of a form break rules, each with its message, see `FieldErrors`.
+    InvalidFields(FieldErrors),
+    // The record changed since the client last saw it, see `If-Match`.
+    PreconditionFailed,
+    // The handler did not finish within its configured timeout.
+    Timeout,
+    // The server is saturated, the client should retry after the given seconds.
+    Unavailable { retry_after: u64 },
+    // The client is being throttled, it may retry after the given seconds.
+    TooManyRequests { retry_after: u64 },
+    // Anything unexpected, wraps `anyhow::Error`.
+    Internal(anyhow::Error),
+}
 
 // Tell axum how to convert `AppError` into a response.
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Something went wrong: {}", self.0),
-        )
-            .into_response()
+        match self {
+            AppError::NotFound => ErrorReport::new(
+                StatusCode::NOT_FOUND,
+                "We couldn't find what you were looking for.",
+            )
+            .into_response(),
+            AppError::Invalid(message) => {
+                ErrorReport::new(StatusCode::UNPROCESSABLE_ENTITY, message).into_response()
+            }
+            AppError::InvalidFields(errors) => {
+                let mut response =
+                    ErrorReport::new(StatusCode::UNPROCESSABLE_ENTITY, errors.summary())
+                        .into_response();
+                response.extensions_mut().insert(errors);
+                response
+            }
+            AppError::PreconditionFailed => ErrorReport::new(
+                StatusCode::PRECONDITION_FAILED,
+                "This changed since you last loaded it, reload and try again.",
+            )
+            .into_response(),
+            AppError::Timeout => ErrorReport::new(
+                StatusCode::REQUEST_TIMEOUT,
+                "That took too long, please try again.",
+            )
+            .into_response(),
+            AppError::Unavailable { retry_after } => {
+                let mut response = ErrorReport::new(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "The server is busy right now, please try again in a moment.",
+                )
+                .into_response();
+                response
+                    .headers_mut()
+                    .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+                response
+            }
+            AppError::TooManyRequests { retry_after } => {
+                let mut response = ErrorReport::new(
+                    StatusCode::TOO_MANY_REQUESTS,
+                    format!(
+                        "Too many attempts, please wait {} seconds and try again.",
+                        retry_after
+                    ),
+                )
+                .into_response();
+                response
+                    .headers_mut()
+                    .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+                response
+            }
+            AppError::Internal(err) => {
+                tracing::error!(error = ?err, "internal error");
+                #[cfg(feature = "sentry")]
+                sentry::integrations::anyhow::capture_anyhow(&err);
+                ErrorReport::new(StatusCode::INTERNAL_SERVER_ERROR, UNEXPECTED).into_response()
+            }
+        }
     }
 }
 
@@ -24,6 +104,176 @@ where
     E: Into<anyhow::Error>,
 {
     fn from(err: E) -> Self {
-        Self(err.into())
+        match err.into().downcast::<Refused>() {
+            Ok(Refused(message)) => Self::Invalid(message),
+            Err(err) => Self::Internal(err),
+        }
+    }
+}
+
+// A write the repository layer turns down, e.g. to a todo under an archived tag. It comes up
+// as an `anyhow::Error` like any failed write and reaches the visitor as `Invalid`.
+#[derive(Debug)]
+pub struct Refused(pub String);
+impl std::fmt::Display for Refused {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+impl std::error::Error for Refused {}
+
+// === Form errors ===
+// Input breaking rules in several fields of a form at once, by field name, so they are reported
+// together instead of one per submit. On htmx requests the form shows them in a summary above
+// it and under each field, see `render_errors`, elsewhere they read like `Invalid`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FieldErrors(pub Vec<(String, String)>);
+impl FieldErrors {
+    pub fn add(&mut self, field: &str, message: impl Into<String>) {
+        self.0.push((field.to_string(), message.into()));
+    }
+    // `value` when nothing was added, every error at once otherwise
+    pub fn check<T>(self, value: T) -> Result<T, AppError> {
+        if self.0.is_empty() {
+            Ok(value)
+        } else {
+            Err(AppError::InvalidFields(self))
+        }
+    }
+    pub fn summary(&self) -> String {
+        let messages: Vec<&str> = self.0.iter().map(|(_, message)| message.as_str()).collect();
+        messages.join("; ")
+    }
+}
+
+// === Error pages ===
+// Attached to error responses, `render_errors` turns it into a page or a toast.
+#[derive(Debug, Clone)]
+pub struct ErrorReport {
+    pub status: StatusCode,
+    pub message: String,
+}
+impl ErrorReport {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+}
+impl IntoResponse for ErrorReport {
+    fn into_response(self) -> Response {
+        let mut response = (self.status, self.message.clone()).into_response();
+        response.extensions_mut().insert(self);
+        response
+    }
+}
+
+// `CatchPanicLayer` handler, the panic is rendered like any other error
+pub fn handle_panic(err: Box<dyn Any + Send + 'static>) -> Response {
+    let detail = if let Some(message) = err.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = err.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    };
+    tracing::error!(panic = %detail, "handler panicked");
+    ErrorReport::new(StatusCode::INTERNAL_SERVER_ERROR, UNEXPECTED).into_response()
+}
+
+// Router fallback for unmatched paths
+pub async fn not_found() -> AppError {
+    AppError::NotFound
+}
+
+// axum answers a wrong method with a bare 405, describe the allowed ones instead
+fn method_not_allowed(response: &Response) -> ErrorReport {
+    let allowed = response
+        .headers()
+        .get(header::ALLOW)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    ErrorReport::new(
+        StatusCode::METHOD_NOT_ALLOWED,
+        format!(
+            "This address only accepts the following methods: {}.",
+            allowed
+        ),
+    )
+}
+
+// Render error reports as a full page, or as a toast fragment for htmx requests. Field errors of
+// a form with an id, which htmx sends as `HX-Trigger`, go into the form instead.
+pub async fn render_errors(request: Request, next: Next) -> Response {
+    let htmx = request.headers().contains_key("hx-request");
+    let form = request
+        .headers()
+        .get("hx-trigger")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned);
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned);
+    let response = next.run(request).await;
+    let report = match response.extensions().get::<ErrorReport>() {
+        Some(report) => report.clone(),
+        None if response.status() == StatusCode::METHOD_NOT_ALLOWED => {
+            method_not_allowed(&response)
+        }
+        None => return response,
+    };
+
+    let request_id = request_id.as_deref();
+    let fields = response.extensions().get::<FieldErrors>();
+    let mut rendered = if let (true, Some(form), Some(errors)) = (htmx, &form, fields) {
+        form_errors(form, errors)
+    } else if htmx {
+        let mut rendered = (
+            report.status,
+            views::error::error_toast(&report.message, request_id),
+        )
+            .into_response();
+        let headers = rendered.headers_mut();
+        headers.insert("hx-retarget", HeaderValue::from_static("#toasts"));
+        headers.insert("hx-reswap", HeaderValue::from_static("beforeend"));
+        rendered
+    } else {
+        (
+            report.status,
+            views::error::error_page(report.status, &report.message, request_id),
+        )
+            .into_response()
+    };
+    // keep headers like `Allow` from the original response
+    for (name, value) in response.headers() {
+        if name != header::CONTENT_TYPE && name != header::CONTENT_LENGTH {
+            rendered.headers_mut().insert(name.clone(), value.clone());
+        }
+    }
+    rendered
+}
+
+// The summary and the messages under the fields swapped into `form` out of band, the rest of the
+// page left as it is. Focus moves to the summary once it is in, see `FORM_ERRORS_SCRIPT`.
+fn form_errors(form: &str, errors: &FieldErrors) -> Response {
+    let trigger = serde_json::json!({
+        views::error::FORM_ERRORS_EVENT: {
+            "form": form,
+            "fields": errors.0.iter().map(|(field, _)| field).collect::<Vec<_>>(),
+        }
+    });
+    let mut rendered = (
+        StatusCode::UNPROCESSABLE_ENTITY,
+        views::error::form_errors_oob(form, errors),
+    )
+        .into_response();
+    let headers = rendered.headers_mut();
+    headers.insert("hx-reswap", HeaderValue::from_static("none"));
+    if let Ok(trigger) = HeaderValue::from_str(&trigger.to_string()) {
+        headers.insert("hx-trigger-after-settle", trigger);
     }
+    rendered
 }
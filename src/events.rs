@@ -0,0 +1,290 @@
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    extract::State,
+    response::sse::{self, KeepAlive, Sse},
+};
+use axum_extra::extract::cookie::CookieJar;
+use tokio::sync::broadcast;
+use tokio_stream::{
+    wrappers::{errors::BroadcastStreamRecvError, BroadcastStream},
+    Stream, StreamExt,
+};
+
+use crate::{
+    auth::{
+        user::CurrentUser,
+        visitor::{self, Visitor},
+    },
+    error::AppError,
+    state::AppState,
+    tenant::Tenant,
+    toolbar,
+};
+
+// events a slow subscriber of a workspace may fall behind by before it misses some
+const CAPACITY: usize = 64;
+// Sent to a stream that fell behind, pages refresh what they show on it. A stream falling
+// behind more often than `MAX_LAGS` is closed, the browser connects again.
+pub const RESYNC_EVENT: &str = "resync";
+const MAX_LAGS: usize = 3;
+// what a refused stream is told to wait before connecting again
+const RETRY_AFTER_SECS: u64 = 30;
+
+// A fragment pushed to every open page of a workspace. htmx swaps `data` into the elements
+// listening with `sse-swap="{name}"`.
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub name: String,
+    pub data: String,
+}
+
+// === Events ===
+// One channel per workspace, so a page only wakes up for events of the workspace it shows.
+// Channels are opened by the first subscriber and closed once nobody listens anymore.
+#[derive(Debug, Clone)]
+pub struct Events {
+    topics: Arc<Mutex<HashMap<Option<String>, broadcast::Sender<Event>>>>,
+}
+impl Events {
+    pub fn new() -> Self {
+        Self {
+            topics: Arc::default(),
+        }
+    }
+
+    // send an event to the workspace's subscribers, nobody listening is fine
+    pub fn publish(&self, tenant: Option<&str>, name: impl Into<String>, data: impl Into<String>) {
+        let tenant = tenant.map(str::to_string);
+        let name = name.into();
+        toolbar::emitted(&name);
+        let mut topics = self.topics.lock().expect("events lock poisoned");
+        let Some(sender) = topics.get(&tenant) else {
+            return;
+        };
+        let event = Event {
+            name,
+            data: data.into(),
+        };
+        if sender.send(event).is_err() {
+            // the last page of the workspace went away
+            topics.remove(&tenant);
+        }
+    }
+
+    // send an event to the subscribers of every workspace
+    pub fn publish_all(&self, name: impl Into<String>, data: impl Into<String>) {
+        let name = name.into();
+        toolbar::emitted(&name);
+        let event = Event {
+            name,
+            data: data.into(),
+        };
+        let mut topics = self.topics.lock().expect("events lock poisoned");
+        topics.retain(|_, sender| sender.send(event.clone()).is_ok());
+    }
+
+    pub fn subscribe(&self, tenant: Option<&str>) -> broadcast::Receiver<Event> {
+        let mut topics = self.topics.lock().expect("events lock poisoned");
+        topics
+            .entry(tenant.map(str::to_string))
+            .or_insert_with(|| broadcast::channel(CAPACITY).0)
+            .subscribe()
+    }
+}
+impl Default for Events {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// === Connections ===
+// Open event streams, in total and per person, so many tabs or a misbehaving client cannot hold
+// an unbounded number of them. A person is the account when signed in, the visitor cookie
+// otherwise.
+#[derive(Debug, Clone, Default)]
+pub struct Connections {
+    open: Arc<Mutex<Open>>,
+    max_total: usize,
+    max_per_user: usize,
+}
+
+#[derive(Debug, Default)]
+struct Open {
+    total: usize,
+    per_user: HashMap<String, usize>,
+}
+
+impl Connections {
+    pub fn new(max_total: usize, max_per_user: usize) -> Self {
+        Self {
+            open: Arc::default(),
+            max_total,
+            max_per_user,
+        }
+    }
+
+    // Count a stream of `user` until the guard is dropped, refused over either limit.
+    pub fn open(&self, user: &str) -> Result<Connection, AppError> {
+        let mut open = self.open.lock().expect("connections lock poisoned");
+        if open.total >= self.max_total {
+            return Err(AppError::Unavailable {
+                retry_after: RETRY_AFTER_SECS,
+            });
+        }
+        let count = open.per_user.entry(user.to_string()).or_default();
+        if *count >= self.max_per_user {
+            return Err(AppError::TooManyRequests {
+                retry_after: RETRY_AFTER_SECS,
+            });
+        }
+        *count += 1;
+        open.total += 1;
+        Ok(Connection {
+            connections: self.clone(),
+            user: user.to_string(),
+        })
+    }
+
+    pub fn total(&self) -> usize {
+        self.open.lock().expect("connections lock poisoned").total
+    }
+}
+
+// an open stream, closed when dropped
+#[derive(Debug)]
+pub struct Connection {
+    connections: Connections,
+    user: String,
+}
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let mut open = self
+            .connections
+            .open
+            .lock()
+            .expect("connections lock poisoned");
+        open.total -= 1;
+        if let Some(count) = open.per_user.get_mut(&self.user) {
+            *count -= 1;
+            if *count == 0 {
+                open.per_user.remove(&self.user);
+            }
+        }
+    }
+}
+
+// What a stream sends for what it received: the event, a resync after falling behind, or
+// nothing to close it once it fell behind too often.
+fn forward(
+    received: Result<Event, BroadcastStreamRecvError>,
+    lags: &mut usize,
+) -> Option<sse::Event> {
+    match received {
+        Ok(event) => Some(sse::Event::default().event(event.name).data(event.data)),
+        Err(BroadcastStreamRecvError::Lagged(missed)) => {
+            *lags += 1;
+            tracing::debug!(missed, lags = *lags, "event stream fell behind");
+            (*lags <= MAX_LAGS).then(|| sse::Event::default().event(RESYNC_EVENT).data(""))
+        }
+    }
+}
+
+// === Routes ===
+// The server-sent event stream every page connects to, scoped to the request's workspace. The
+// visitor counts as viewing the workspace while it is open.
+pub async fn stream(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    user: CurrentUser,
+    jar: CookieJar,
+) -> Result<Sse<impl Stream<Item = Result<sse::Event, Infallible>>>, AppError> {
+    let tenant = tenant.0;
+    // without a cookie every stream is someone else
+    let Visitor(visitor) = visitor::from_jar(&state.secret_key, &jar)
+        .unwrap_or_else(|| Visitor(uuid::Uuid::new_v4().to_string()));
+    let connection = match state.connections.open(user.account().unwrap_or(&visitor)) {
+        Ok(connection) => connection,
+        Err(err) => {
+            tracing::warn!(open = state.connections.total(), "refused an event stream");
+            return Err(err);
+        }
+    };
+    let receiver = state.events.subscribe(tenant.as_deref());
+    // after subscribing, so the page hears about itself joining
+    let viewing = state.presence.join(tenant, visitor);
+    let mut lags = 0;
+    let events = BroadcastStream::new(receiver)
+        .map(move |received| {
+            // dropped with the stream when the page goes away
+            let _ = (&viewing, &connection);
+            forward(received, &mut lags)
+        })
+        .take_while(Option::is_some)
+        .filter_map(|event| event.map(Ok));
+    // the heartbeat keeps proxies from closing idle streams and notices pages gone away
+    let heartbeat = state.config.sse_heartbeat;
+    Ok(Sse::new(events).keep_alive(KeepAlive::new().interval(heartbeat)))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_topics() {
+        let events = Events::new();
+        let mut default = events.subscribe(None);
+        let mut acme = events.subscribe(Some("acme"));
+        events.publish(Some("acme"), "todo", "<li></li>");
+        assert_eq!(acme.try_recv().unwrap().name, "todo");
+        assert!(default.try_recv().is_err());
+
+        // nobody listens to `other`, nothing is opened for it
+        events.publish(Some("other"), "todo", "");
+        drop(acme);
+        events.publish(Some("acme"), "todo", "");
+        assert_eq!(events.topics.lock().unwrap().len(), 1);
+
+        events.publish_all("maintenance", "");
+        assert_eq!(default.try_recv().unwrap().name, "maintenance");
+    }
+
+    #[test]
+    fn test_connection_limits() {
+        let connections = Connections::new(3, 2);
+        let first = connections.open("alice").unwrap();
+        let _second = connections.open("alice").unwrap();
+        assert!(matches!(
+            connections.open("alice"),
+            Err(AppError::TooManyRequests { .. })
+        ));
+        let _bob = connections.open("bob").unwrap();
+        assert!(matches!(
+            connections.open("carol"),
+            Err(AppError::Unavailable { .. })
+        ));
+        drop(first);
+        assert_eq!(connections.total(), 2);
+        assert!(connections.open("alice").is_ok());
+    }
+
+    #[test]
+    fn test_lagging_streams_resync_then_close() {
+        let mut lags = 0;
+        let event = Event {
+            name: "todo".into(),
+            data: String::new(),
+        };
+        assert!(forward(Ok(event), &mut lags).is_some());
+        for _ in 0..MAX_LAGS {
+            assert!(forward(Err(BroadcastStreamRecvError::Lagged(10)), &mut lags).is_some());
+        }
+        assert!(forward(Err(BroadcastStreamRecvError::Lagged(10)), &mut lags).is_none());
+    }
+}
@@ -0,0 +1,501 @@
+use std::iter;
+
+use anyhow::Result;
+use axum::{
+    body::{Body, Bytes},
+    extract::{Query, State},
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+    Json,
+};
+use maud::{html, Markup};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    colors,
+    db::driver::Db,
+    error::AppError,
+    import, lists,
+    models::{self, Todo},
+    repository::{
+        entity::{Entity, Repository},
+        todo::todo_key,
+    },
+    routes,
+    state::AppState,
+    tags,
+    tenant::Tenant,
+    views,
+};
+
+const RESULT_ID: &str = "backup-result";
+// a backup of many thousand todos still fits
+pub const MAX_IMPORT_BYTES: usize = 16 * 1024 * 1024;
+// the columns of a csv export, an import takes them in any order and ignores others
+const CSV_COLUMNS: [&str; 13] = [
+    "id",
+    "title",
+    "completed",
+    "status",
+    "priority",
+    "due",
+    "scheduled_for",
+    "estimate_minutes",
+    "tags",
+    "list",
+    "archived",
+    "color",
+    "updated_at",
+];
+
+// === Formats ===
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    // every field, the same as the todos.json of a settings export
+    #[default]
+    Json,
+    // one row per todo, for spreadsheets, without locations and checklists
+    Csv,
+}
+impl Format {
+    fn content_type(self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            Format::Csv => "text/csv; charset=utf-8",
+        }
+    }
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::Csv => "csv",
+        }
+    }
+    // what an upload without `?format=` is, by its content type
+    fn of(headers: &HeaderMap) -> Self {
+        let content_type = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        if content_type.starts_with("text/csv") {
+            Format::Csv
+        } else {
+            Format::Json
+        }
+    }
+}
+
+fn todos_prefix() -> String {
+    format!("{}:", Todo::PREFIX)
+}
+
+// === Csv ===
+// quoted when it holds a separator, a quote or a line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn csv_row(fields: &[String]) -> String {
+    let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+    format!("{}\r\n", fields.join(","))
+}
+
+fn optional(value: Option<impl ToString>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+fn todo_row(todo: &Todo) -> String {
+    csv_row(&[
+        todo.id.to_string(),
+        todo.title.clone(),
+        todo.completed.to_string(),
+        todo.status.to_string(),
+        todo.priority.to_string(),
+        optional(todo.due),
+        optional(todo.scheduled_for),
+        optional(todo.estimate_minutes),
+        todo.tags.join(" "),
+        optional(todo.list.as_ref()),
+        todo.archived.to_string(),
+        optional(todo.color.as_ref()),
+        todo.updated_at.to_string(),
+    ])
+}
+
+// The rows of `text`, quoted fields may hold separators, quotes and line breaks.
+pub fn parse_csv(text: &str) -> Result<Vec<Vec<String>>, AppError> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => row.push(std::mem::take(&mut field)),
+            (false, '\r') if chars.peek() == Some(&'\n') => {}
+            (false, '\n') => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            (false, c) => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(AppError::Invalid(
+            "A quoted csv field is never closed.".into(),
+        ));
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    // blank lines carry nothing
+    rows.retain(|row| row.iter().any(|field| !field.is_empty()));
+    Ok(rows)
+}
+
+// set one csv column of `todo`, columns this doesn't know are left alone
+fn apply_column(todo: &mut Todo, column: &str, value: &str) -> Result<(), String> {
+    let value = value.trim();
+    let flag = |value: &str| match value {
+        "true" => Ok(true),
+        "false" | "" => Ok(false),
+        other => Err(format!("`{}` is neither true nor false", other)),
+    };
+    match column {
+        "title" => todo.title = value.to_string(),
+        "completed" => {
+            todo.completed = flag(value)?;
+            todo.status = match (todo.completed, todo.status) {
+                (true, _) => models::Status::Done,
+                (false, models::Status::Done) => models::Status::Backlog,
+                (false, status) => status,
+            };
+            todo.settle();
+        }
+        "status" if !value.is_empty() => {
+            todo.status = value.parse().map_err(|err| format!("{}", err))?;
+            todo.completed = todo.status == models::Status::Done;
+            todo.settle();
+        }
+        "priority" if !value.is_empty() => {
+            todo.priority = value.parse().map_err(|err| format!("{}", err))?
+        }
+        "due" => todo.due = models::parse_due(value).map_err(|err| format!("{:#}", err))?,
+        "scheduled_for" => {
+            todo.scheduled_for = models::parse_due(value).map_err(|err| format!("{:#}", err))?
+        }
+        "estimate_minutes" => {
+            todo.estimate_minutes = match value {
+                "" => None,
+                minutes => Some(
+                    minutes
+                        .parse()
+                        .map_err(|_| format!("`{}` is not a number of minutes", minutes))?,
+                ),
+            }
+        }
+        "tags" => todo.tags = tags::parse_field(value).map_err(|err| err.to_string())?,
+        "list" => todo.list = lists::parse(value).map_err(|err| err.to_string())?,
+        "archived" => todo.archived = flag(value)?,
+        "color" => todo.color = colors::parse_color(value).map_err(|err| err.to_string())?,
+        "updated_at" if !value.is_empty() => {
+            todo.updated_at = value
+                .parse()
+                .map_err(|_| format!("`{}` is not a unix time", value))?
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+// The todos of a csv with a header row. A row whose `id` is stored here changes that todo,
+// columns it leaves out keep their value.
+fn todos_from_csv(db: &Db, text: &str) -> Result<Vec<Todo>, AppError> {
+    let mut rows = parse_csv(text)?.into_iter();
+    let header: Vec<String> = rows
+        .next()
+        .ok_or_else(|| AppError::Invalid("The csv is empty.".into()))?
+        .iter()
+        .map(|column| column.trim().to_lowercase())
+        .collect();
+    if !header.iter().any(|column| column == "title") {
+        return Err(AppError::Invalid(
+            "The csv needs a header row with a `title` column.".into(),
+        ));
+    }
+    let todos = Repository::<Todo>::new(db);
+    let mut imported = Vec::new();
+    for (index, row) in rows.enumerate() {
+        let invalid = |err: String| AppError::Invalid(format!("Row {}: {}", index + 2, err));
+        let id = header
+            .iter()
+            .zip(&row)
+            .find(|(column, _)| *column == "id")
+            .map(|(_, id)| id.trim())
+            .filter(|id| !id.is_empty());
+        let stored = match id {
+            Some(id) => {
+                let id = id
+                    .parse()
+                    .map_err(|_| invalid(format!("`{}` is not an id", id)))?;
+                todos.get(id)?
+            }
+            None => None,
+        };
+        let mut todo = match stored {
+            Some(stored) => stored,
+            None => Todo::created(db.next_id()?, String::new(), &**db.clock()),
+        };
+        for (column, value) in header.iter().zip(&row) {
+            apply_column(&mut todo, column, value).map_err(invalid)?;
+        }
+        imported.push(todo);
+    }
+    Ok(imported)
+}
+
+// === Import ===
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Report {
+    pub created: usize,
+    pub updated: usize,
+}
+
+pub fn validate(todo: &Todo) -> Result<(), String> {
+    if todo.title.trim().is_empty() {
+        return Err("the title is empty".into());
+    }
+    if let Some(tag) = todo
+        .tags
+        .iter()
+        .find(|tag| tags::normalize(tag).as_deref() != Some(tag.as_str()))
+    {
+        return Err(format!("`{}` is not a valid tag", tag));
+    }
+    Ok(())
+}
+
+// Upsert `todos` in one batch: a todo whose id is stored here replaces it, any other gets a new
+// id, so records from another instance never land on ids this one hands out later. Nothing is
+// written unless every todo is valid.
+pub fn import_todos(db: &Db, todos: Vec<Todo>) -> Result<Report, AppError> {
+    let repository = Repository::<Todo>::new(db);
+    let mut report = Report::default();
+    let mut upserts = Vec::new();
+    for (index, mut todo) in todos.into_iter().enumerate() {
+        validate(&todo)
+            .map_err(|err| AppError::Invalid(format!("Todo {}: {}.", index + 1, err)))?;
+        match repository.get(todo.id)? {
+            Some(stored) => {
+                todo.version = stored.version.max(todo.version) + 1;
+                report.updated += 1;
+            }
+            None => {
+                todo.id = db.next_id()?;
+                report.created += 1;
+            }
+        }
+        upserts.push((todo_key(todo.id), todo));
+    }
+    db.import(&todos_prefix(), upserts)?;
+    // the records were written as they are, their index entries follow
+    repository.reindex()?;
+    Ok(report)
+}
+
+// === Export ===
+// the chunks of a json array, one todo per line
+fn json_chunks(
+    todos: impl Iterator<Item = Result<(String, Todo)>> + Send + 'static,
+) -> impl Iterator<Item = Result<String>> + Send {
+    let items = todos.enumerate().map(|(index, item)| {
+        let (_, todo) = item?;
+        let separator = if index == 0 { "\n  " } else { ",\n  " };
+        Ok(format!("{}{}", separator, serde_json::to_string(&todo)?))
+    });
+    iter::once(Ok("[".to_string()))
+        .chain(items)
+        .chain(iter::once(Ok("\n]\n".to_string())))
+}
+
+fn csv_chunks(
+    todos: impl Iterator<Item = Result<(String, Todo)>> + Send + 'static,
+) -> impl Iterator<Item = Result<String>> + Send {
+    let header: Vec<String> = CSV_COLUMNS
+        .iter()
+        .map(|column| column.to_string())
+        .collect();
+    iter::once(Ok(csv_row(&header))).chain(todos.map(|item| Ok(todo_row(&item?.1))))
+}
+
+// === Components ===
+pub fn settings_html() -> Markup {
+    html! {
+        section class="bg-white rounded-lg shadow-lg p-6 space-y-4" {
+            h2 class="text-2xl text-gray-700" { "Backup" }
+            p class="text-gray-600" {
+                "Download every todo, or bring todos back from a download. Todos already here are updated, the others are added."
+            }
+            div class="space-x-4" {
+                @for format in [Format::Json, Format::Csv] {
+                    a class="text-blue-500 hover:text-blue-700" download
+                        href={ (routes::Export::url()) "?format=" (format.extension()) } { "Download " (format.extension().to_uppercase()) }
+                }
+            }
+            label class="block text-gray-600" {
+                "Import a JSON or CSV download "
+                input type="file" accept=".json,.csv,application/json,text/csv" data-url=(routes::ImportTodos::url())
+                    onchange={ "const file = this.files[0]; if (!file) return;
+                        fetch(this.dataset.url, { method: 'POST', body: file, headers: { 'Content-Type': file.name.endsWith('.csv') ? 'text/csv' : 'application/json', 'HX-Request': 'true' } })
+                            .then((response) => response.text())
+                            .then((text) => { document.getElementById('" (RESULT_ID) "').innerHTML = text; });" };
+            }
+            p id=(RESULT_ID) class="text-gray-700" role="status" {}
+        }
+    }
+}
+
+fn report_html(report: &Report) -> Markup {
+    html! {
+        (report.created + report.updated) " todos imported, " (report.created) " new and " (report.updated) " updated."
+    }
+}
+
+// === Routes ===
+#[derive(Deserialize)]
+pub struct FormatQuery {
+    format: Option<Format>,
+}
+
+// Every todo of the workspace, archived ones too, streamed as it is read.
+pub async fn export(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    Query(FormatQuery { format }): Query<FormatQuery>,
+) -> Result<Response, AppError> {
+    let format = format.unwrap_or_default();
+    let todos = state
+        .read()
+        .await
+        .for_tenant(tenant.id())?
+        .export_prefix::<Todo>(&todos_prefix());
+    let chunks: Box<dyn Iterator<Item = Result<String>> + Send> = match format {
+        Format::Json => Box::new(json_chunks(todos)),
+        Format::Csv => Box::new(csv_chunks(todos)),
+    };
+    let filename = format!(
+        "{}-export.{}",
+        tenant.id().unwrap_or("todos"),
+        format.extension()
+    );
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", filename),
+            ),
+        ],
+        Body::from_stream(tokio_stream::iter(chunks)),
+    )
+        .into_response())
+}
+
+// The upload is the request body, its content type or `?format=` says which format it is in.
+pub async fn upload(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Query(FormatQuery { format }): Query<FormatQuery>,
+    body: Bytes,
+) -> Result<Response, AppError> {
+    let format = format.unwrap_or_else(|| Format::of(&headers));
+    let text = std::str::from_utf8(&body)
+        .map_err(|_| AppError::Invalid("The upload is not utf-8 text.".into()))?;
+    let guard = state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    let todos = match format {
+        Format::Json => import::parse_export(text)?,
+        Format::Csv => todos_from_csv(&db, text)?,
+    };
+    let report = import_todos(&db, todos)?;
+    tracing::info!(
+        created = report.created,
+        updated = report.updated,
+        "imported todos"
+    );
+    if views::wants_json(&headers) {
+        return Ok(Json(report).into_response());
+    }
+    Ok(views::fragment_or_redirect(
+        &headers,
+        report_html(&report),
+        &routes::Root::url(),
+    ))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::TestDb;
+
+    #[test]
+    fn test_csv_round_trips_awkward_fields() -> Result<(), AppError> {
+        let fields = vec!["a, b".to_string(), "say \"hi\"".into(), "two\nlines".into()];
+        let text = format!("{}{}", csv_row(&fields), csv_row(&["plain".into()]));
+        assert_eq!(parse_csv(&text)?, [fields, vec!["plain".to_string()]]);
+        assert!(parse_csv("\"open").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_upserts() -> anyhow::Result<()> {
+        let db = TestDb::new("export")?;
+        let mut stored = Todo::new(db.next_id()?, "Water plants".into());
+        stored.tags = vec!["home".into()];
+        Repository::<Todo>::new(&db).put(&stored)?;
+
+        let csv = format!(
+            "id,title,completed,tags\r\n{},Water the plants,true,garden\r\n,Buy milk,,\r\n",
+            stored.id
+        );
+        let todos = todos_from_csv(&db, &csv)?;
+        assert_eq!(
+            import_todos(&db, todos)?,
+            Report {
+                created: 1,
+                updated: 1
+            }
+        );
+        let repository = Repository::<Todo>::new(&db);
+        let updated = repository.get(stored.id)?.unwrap();
+        assert!(updated.completed && updated.title == "Water the plants");
+        assert_eq!(repository.ids_by("tag", "garden")?, [stored.id]);
+        assert!(repository.ids_by("tag", "home")?.is_empty());
+
+        // the export reads back what was imported
+        let exported: Vec<String> =
+            json_chunks(db.export_prefix(&todos_prefix())).collect::<Result<_>>()?;
+        let todos = import::parse_export(&exported.concat())?;
+        assert_eq!(todos.len(), 2);
+
+        // nothing is written when a record is invalid
+        let invalid = vec![Todo::new(0, "Fine".into()), Todo::new(0, " ".into())];
+        assert!(import_todos(&db, invalid).is_err());
+        assert_eq!(repository.list()?.len(), 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+use axum::{
+    async_trait,
+    extract::{FromRequest, Request},
+    http::header,
+    Form, Json,
+};
+use serde::de::DeserializeOwned;
+
+use crate::error::ErrorReport;
+
+// A request body sent either urlencoded (plain forms) or as json (the htmx json-enc
+// extension, API clients), picked by the content type.
+pub struct FormOrJson<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for FormOrJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ErrorReport;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let is_json = request
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|content_type| {
+                content_type.starts_with("application/json") || content_type.contains("+json")
+            });
+        if is_json {
+            Json::<T>::from_request(request, state)
+                .await
+                .map(|Json(value)| Self(value))
+                .map_err(|rejection| ErrorReport::new(rejection.status(), rejection.body_text()))
+        } else {
+            Form::<T>::from_request(request, state)
+                .await
+                .map(|Form(value)| Self(value))
+                .map_err(|rejection| ErrorReport::new(rejection.status(), rejection.body_text()))
+        }
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::StatusCode};
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Toggle {
+        id: u64,
+    }
+
+    fn request(content_type: &str, body: &'static str) -> Request {
+        Request::post("/toggle_todo")
+            .header(header::CONTENT_TYPE, content_type)
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_urlencoded() {
+        let request = request("application/x-www-form-urlencoded", "id=7");
+        let FormOrJson(toggle) = FormOrJson::<Toggle>::from_request(request, &())
+            .await
+            .ok()
+            .unwrap();
+        assert_eq!(toggle, Toggle { id: 7 });
+    }
+
+    #[tokio::test]
+    async fn test_json() {
+        let request = request("application/json", r#"{"id":7}"#);
+        let FormOrJson(toggle) = FormOrJson::<Toggle>::from_request(request, &())
+            .await
+            .ok()
+            .unwrap();
+        assert_eq!(toggle, Toggle { id: 7 });
+    }
+
+    #[tokio::test]
+    async fn test_rejection() {
+        let request = request("application/json", r#"{"id":"seven"}"#);
+        let Err(report) = FormOrJson::<Toggle>::from_request(request, &()).await else {
+            panic!("invalid json should be rejected");
+        };
+        assert_eq!(report.status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
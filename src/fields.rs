@@ -0,0 +1,466 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    response::Response,
+    Extension, Form,
+};
+use maud::{html, Markup};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::visitor::Visitor,
+    db::driver::Db,
+    error::AppError,
+    extract::FormOrJson,
+    filtering::ListFilter,
+    lists, method_override,
+    models::Todo,
+    repository, routes,
+    services::todo::TodoService,
+    state::AppState,
+    tags,
+    tenant::Tenant,
+    views::{self, dom},
+};
+
+// `list_schema:{list}`
+const SCHEMA_PREFIX: &str = "list_schema:";
+const SECTION_ID: &str = "custom-fields";
+const SETTINGS_ID: &str = "list-fields";
+const MAX_FIELDS: usize = 20;
+const MAX_OPTIONS: usize = 30;
+const MAX_VALUE_LEN: usize = 200;
+
+// === Schemas ===
+// The custom fields of a list, for workflows the todo itself has no place for: a size, an
+// invoice number, a room. Their values are kept on the todos by field name. The schema is soft,
+// a field taken out of it or a todo moved to another list keeps its values, they are only not
+// shown until a field of that name is back.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Schema {
+    pub fields: Vec<Field>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Field {
+    // normalized like a tag, the key of the values
+    pub name: String,
+    pub kind: Kind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Kind {
+    Text,
+    Number,
+    // one of these options
+    Select(Vec<String>),
+}
+impl Kind {
+    fn label(&self) -> &'static str {
+        match self {
+            Kind::Text => "text",
+            Kind::Number => "number",
+            Kind::Select(_) => "select",
+        }
+    }
+}
+
+impl Field {
+    // a value of a form field as stored, `None` when it is left empty
+    pub fn parse(&self, value: &str) -> Result<Option<String>, AppError> {
+        let value = value.trim();
+        if value.is_empty() {
+            return Ok(None);
+        }
+        match &self.kind {
+            Kind::Text if value.chars().count() > MAX_VALUE_LEN => Err(AppError::Invalid(format!(
+                "Keep {} under {} characters.",
+                self.name, MAX_VALUE_LEN
+            ))),
+            Kind::Text => Ok(Some(value.to_string())),
+            Kind::Number => match value.parse::<f64>() {
+                Ok(number) if number.is_finite() => Ok(Some(number.to_string())),
+                _ => Err(AppError::Invalid(format!(
+                    "{} is a number, `{}` is not one.",
+                    self.name, value
+                ))),
+            },
+            Kind::Select(options) => match options.iter().find(|option| *option == value) {
+                Some(option) => Ok(Some(option.clone())),
+                None => Err(AppError::Invalid(format!(
+                    "{} is one of {}.",
+                    self.name,
+                    options.join(", ")
+                ))),
+            },
+        }
+    }
+}
+
+fn schema_key(list: &str) -> String {
+    format!("{}{}", SCHEMA_PREFIX, list)
+}
+
+pub fn schema(db: &Db, list: &str) -> anyhow::Result<Schema> {
+    Ok(db.get(schema_key(list))?.unwrap_or_default())
+}
+pub fn set_schema(db: &Db, list: &str, schema: &Schema) -> anyhow::Result<()> {
+    if schema.fields.is_empty() {
+        return db.remove(schema_key(list));
+    }
+    db.insert(schema_key(list), schema)
+}
+
+// the fields of `todo`'s list, none outside a list
+pub fn of(db: &Db, todo: &Todo) -> anyhow::Result<Schema> {
+    match &todo.list {
+        Some(list) => schema(db, list),
+        None => Ok(Schema::default()),
+    }
+}
+
+// every list with its fields, by name
+pub fn schemas(db: &Db) -> anyhow::Result<Vec<(String, Schema)>> {
+    lists::counts(&repository::todo::all(db)?)
+        .into_keys()
+        .map(|list| {
+            let schema = schema(db, &list)?;
+            Ok((list, schema))
+        })
+        .collect()
+}
+
+// Set the fields of `schema` on `todo` from a form, the values of other fields stay.
+pub fn apply(
+    schema: &Schema,
+    todo: &mut Todo,
+    values: &HashMap<String, String>,
+) -> Result<(), AppError> {
+    for field in &schema.fields {
+        let Some(value) = values.get(&field.name) else {
+            continue;
+        };
+        match field.parse(value)? {
+            Some(value) => todo.fields.insert(field.name.clone(), value),
+            None => todo.fields.remove(&field.name),
+        };
+    }
+    Ok(())
+}
+
+// a field from the settings form, options are comma separated
+fn parse_field(name: &str, kind: &str, options: &str) -> Result<Field, AppError> {
+    let name = tags::normalize(name).ok_or_else(|| {
+        AppError::Invalid("Name fields with letters, digits, - and _ only.".into())
+    })?;
+    let kind = match kind {
+        "text" => Kind::Text,
+        "number" => Kind::Number,
+        "select" => {
+            let mut choices: Vec<String> = Vec::new();
+            for option in options.split(',').map(str::trim) {
+                if !option.is_empty() && !choices.iter().any(|choice| choice == option) {
+                    choices.push(option.to_string());
+                }
+            }
+            if choices.is_empty() || choices.len() > MAX_OPTIONS {
+                return Err(AppError::Invalid(format!(
+                    "A select field has between 1 and {} options.",
+                    MAX_OPTIONS
+                )));
+            }
+            Kind::Select(choices)
+        }
+        other => {
+            return Err(AppError::Invalid(format!(
+                "`{}` is not a kind of field, use text, number or select",
+                other
+            )))
+        }
+    };
+    Ok(Field { name, kind })
+}
+
+// === Components ===
+// The fields of the todo's list in its detail view, filled in and saved together. Each value
+// leads to the todos of the list with the same one.
+pub fn section_html(todo: &Todo, schema: &Schema) -> Markup {
+    let url = routes::TodoFields::url(todo.id);
+    html! {
+        @if !schema.fields.is_empty() {
+            section id=(SECTION_ID) class="space-y-2" {
+                h2 class="text-xl text-gray-700" { "Fields" }
+                form class="space-y-2" method="post" action=(url) hx-post=(url)
+                    hx-target=(dom::selector(SECTION_ID)) hx-swap="outerHTML" {
+                    @for field in &schema.fields {
+                        @let value = todo.fields.get(&field.name);
+                        div class="flex items-center space-x-2" {
+                            label class="w-32 text-gray-600" for=(dom::field(SECTION_ID, &field.name)) { (field.name) }
+                            (input_html(field, value))
+                            @if let (Some(value), Some(list)) = (value, &todo.list) {
+                                a class="text-xs text-blue-500 hover:underline" href=(filter_url(list, &field.name, value)) { "Same" }
+                            }
+                        }
+                    }
+                    button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded" type="submit" { "Save fields" }
+                }
+            }
+        }
+    }
+}
+
+// the todos of `list` whose field `name` is `value`
+fn filter_url(list: &str, name: &str, value: &str) -> String {
+    ListFilter {
+        list: Some(list.to_string()),
+        field: Some(format!("{}:{}", name, value)),
+        ..ListFilter::default()
+    }
+    .url()
+}
+
+fn input_html(field: &Field, value: Option<&String>) -> Markup {
+    let id = dom::field(SECTION_ID, &field.name);
+    html! {
+        @match &field.kind {
+            Kind::Text => {
+                input id=(id) class="flex-grow rounded p-2 border" type="text" name=(field.name) value=[value] maxlength=(MAX_VALUE_LEN);
+            }
+            Kind::Number => {
+                input id=(id) class="flex-grow rounded p-2 border" type="number" step="any" name=(field.name) value=[value];
+            }
+            Kind::Select(options) => {
+                select id=(id) class="flex-grow rounded p-2 border" name=(field.name) {
+                    option value="" selected[value.is_none()] { "—" }
+                    @for option in options {
+                        option value=(option) selected[value == Some(option)] { (option) }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// The fields of every list there is, in the settings.
+pub fn settings_html(lists: &[(String, Schema)]) -> Markup {
+    html! {
+        section id=(SETTINGS_ID) class="bg-white rounded-lg shadow-lg p-6 space-y-4" {
+            h2 class="text-2xl text-gray-700" { "Custom fields" }
+            p class="text-gray-600" {
+                "Give the todos of a list fields of their own, filled in on each todo. "
+                "Removing a field keeps what was filled in, adding it back shows it again."
+            }
+            @if lists.is_empty() {
+                p class="text-gray-500" { "No lists yet, move a todo to one to start it." }
+            }
+            @for (list, schema) in lists {
+                @let add = routes::ListFields::url(list);
+                div class="space-y-2" {
+                    h3 class="text-lg text-gray-700" { (list) }
+                    ul class="space-y-1" {
+                        @for field in &schema.fields {
+                            @let remove = routes::ListField::url(list, &field.name);
+                            li class="flex items-center justify-between" {
+                                span class="text-gray-700" {
+                                    (field.name) " "
+                                    span class="text-sm text-gray-500" {
+                                        (field.kind.label())
+                                        @if let Kind::Select(options) = &field.kind { ": " (options.join(", ")) }
+                                    }
+                                }
+                                form method="post" action=(remove) hx-delete=(remove)
+                                    hx-target=(dom::selector(SETTINGS_ID)) hx-swap="outerHTML" {
+                                    input type="hidden" name=(method_override::METHOD_FIELD) value="DELETE";
+                                    button class="text-red-500 hover:text-red-700" type="submit" { "Remove" }
+                                }
+                            }
+                        }
+                    }
+                    @if schema.fields.len() < MAX_FIELDS {
+                        form class="flex items-center space-x-2" method="post" action=(add) hx-post=(add)
+                            hx-target=(dom::selector(SETTINGS_ID)) hx-swap="outerHTML" {
+                            input class="w-32 rounded p-1 border" type="text" name="name" required placeholder="Name" aria-label="Field name";
+                            select class="rounded p-1 border" name="kind" aria-label="Kind of field" {
+                                option value="text" { "Text" }
+                                option value="number" { "Number" }
+                                option value="select" { "Select" }
+                            }
+                            input class="flex-grow rounded p-1 border" type="text" name="options" placeholder="Options for select, comma separated" aria-label="Options";
+                            button class="text-blue-500 hover:text-blue-700" type="submit" { "Add field" }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// === Routes ===
+// The values of a todo's fields, named after them.
+pub async fn save_values(
+    State(mut app_state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Extension(Visitor(visitor)): Extension<Visitor>,
+    Path(id): Path<u64>,
+    FormOrJson(values): FormOrJson<HashMap<String, String>>,
+) -> Result<Response, AppError> {
+    let state = app_state.clone();
+    let guard = app_state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    let todos = TodoService::new(&state, &db, tenant.id());
+    let schema = of(&db, &todos.get(id)?)?;
+    let todo = todos
+        .update(id, Some(visitor.as_str()), |todo| {
+            apply(&schema, todo, &values)
+        })
+        .await?;
+    let fragment = html! {
+        (section_html(&todo, &schema))
+        (views::notice_toast_oob("Fields saved."))
+    };
+    Ok(views::fragment_or_redirect(
+        &headers,
+        fragment,
+        &routes::TodoDetail::url(todo.id),
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct FieldForm {
+    name: String,
+    kind: String,
+    #[serde(default)]
+    options: String,
+}
+
+pub async fn add(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Path(list): Path<String>,
+    Form(FieldForm {
+        name,
+        kind,
+        options,
+    }): Form<FieldForm>,
+) -> Result<Response, AppError> {
+    let list = lists::normalize(&list).ok_or(AppError::NotFound)?;
+    let field = parse_field(&name, &kind, &options)?;
+    let db = state.write().await.for_tenant(tenant.id())?;
+    let mut schema = schema(&db, &list)?;
+    if schema.fields.iter().any(|other| other.name == field.name) {
+        return Err(AppError::Invalid(format!(
+            "{} already has a field {}.",
+            list, field.name
+        )));
+    }
+    if schema.fields.len() >= MAX_FIELDS {
+        return Err(AppError::Invalid(format!(
+            "A list has up to {} fields.",
+            MAX_FIELDS
+        )));
+    }
+    let notice = format!("Todos in {} have a field {}.", list, field.name);
+    schema.fields.push(field);
+    set_schema(&db, &list, &schema)?;
+    refreshed(&headers, &db, &notice)
+}
+
+pub async fn remove(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Path((list, name)): Path<(String, String)>,
+) -> Result<Response, AppError> {
+    let list = lists::normalize(&list).ok_or(AppError::NotFound)?;
+    let db = state.write().await.for_tenant(tenant.id())?;
+    let mut schema = schema(&db, &list)?;
+    let before = schema.fields.len();
+    schema.fields.retain(|field| field.name != name);
+    if schema.fields.len() == before {
+        return Err(AppError::NotFound);
+    }
+    set_schema(&db, &list, &schema)?;
+    refreshed(
+        &headers,
+        &db,
+        &format!("Removed {} from {}, the values are kept.", name, list),
+    )
+}
+
+fn refreshed(headers: &HeaderMap, db: &Db, notice: &str) -> Result<Response, AppError> {
+    let fragment = html! {
+        (settings_html(&schemas(db)?))
+        (views::notice_toast_oob(notice))
+    };
+    Ok(views::fragment_or_redirect(
+        headers,
+        fragment,
+        &routes::Settings::url(),
+    ))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::{TestDb, TodoFixture},
+        repository::{entity::Repository, query::TodoQuery},
+    };
+
+    #[test]
+    fn test_values_follow_the_schema() -> Result<(), AppError> {
+        let db = TestDb::new("fields")?;
+        let schema = Schema {
+            fields: vec![
+                parse_field("Size", "select", "small, large, small")?,
+                parse_field("weight", "number", "")?,
+            ],
+        };
+        assert_eq!(
+            schema.fields[0].kind,
+            Kind::Select(vec!["small".into(), "large".into()])
+        );
+        set_schema(&db, "parcels", &schema)?;
+        assert!(parse_field("two words", "text", "").is_err());
+
+        let mut todo = TodoFixture::new().titled("Ship the lamp").build(&db)?;
+        todo.list = Some("parcels".into());
+        todo.fields.insert("legacy".into(), "kept".into());
+        let form = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect()
+        };
+        assert!(apply(&schema, &mut todo, &form(&[("size", "huge")])).is_err());
+        assert!(apply(&schema, &mut todo, &form(&[("weight", "heavy")])).is_err());
+        apply(
+            &of(&db, &todo)?,
+            &mut todo,
+            &form(&[("size", "large"), ("weight", " 2.50 ")]),
+        )?;
+        assert_eq!(todo.fields.get("size").map(String::as_str), Some("large"));
+        assert_eq!(todo.fields.get("weight").map(String::as_str), Some("2.5"));
+        // not in the schema, left alone
+        assert_eq!(todo.fields.get("legacy").map(String::as_str), Some("kept"));
+        Repository::new(&db).put(&todo)?;
+        TodoFixture::new().titled("Ship the desk").persist(&db)?;
+
+        let filter = ListFilter {
+            field: Some("size:large".into()),
+            ..ListFilter::default()
+        };
+        let found = filter.query(TodoQuery::new())?.run(&db)?.items;
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].title, "Ship the lamp");
+        assert!(section_html(&todo, &schema)
+            .into_string()
+            .contains("field=size%3Alarge"));
+        Ok(())
+    }
+}
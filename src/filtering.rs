@@ -0,0 +1,564 @@
+use std::str::FromStr;
+
+use maud::{html, Markup};
+use serde::Deserialize;
+
+use crate::{
+    error::AppError,
+    models::Todo,
+    repository::query::{Sort, SortKey, TodoQuery},
+    routes,
+    views::{
+        dom,
+        hx::{Edge, Hx, Swap, Target},
+    },
+};
+
+// the status tabs, rendered with the list so they always show what it shows
+const FILTER_ID: &str = "todo-filter";
+// the search box above the list, left alone by swaps so typing isn't interrupted
+const SEARCH_ID: &str = "todo-search";
+pub const DEFAULT_PER_PAGE: usize = 50;
+pub const MAX_PER_PAGE: usize = 200;
+// lists matching more todos than this are shown a window at a time, see `Window`
+pub const WINDOW_THRESHOLD: usize = 500;
+
+// === Status ===
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatusFilter {
+    Active,
+    Completed,
+    #[default]
+    All,
+}
+impl StatusFilter {
+    pub const ALL: [StatusFilter; 3] = [
+        StatusFilter::All,
+        StatusFilter::Active,
+        StatusFilter::Completed,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            StatusFilter::Active => "active",
+            StatusFilter::Completed => "completed",
+            StatusFilter::All => "all",
+        }
+    }
+    pub fn label(self) -> &'static str {
+        match self {
+            StatusFilter::Active => "Active",
+            StatusFilter::Completed => "Completed",
+            StatusFilter::All => "All",
+        }
+    }
+}
+impl FromStr for StatusFilter {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, AppError> {
+        Self::ALL
+            .into_iter()
+            .find(|status| status.as_str() == s)
+            .ok_or_else(|| {
+                AppError::Invalid(format!(
+                    "`{}` is not a status, use `active`, `completed` or `all`",
+                    s
+                ))
+            })
+    }
+}
+
+// === Filter ===
+// The part of the list a visitor looks at, from the query of `GET /todos`:
+//
+//     /todos?status=active&q=milk&page=2&per_page=20
+//
+// Everything is optional, the defaults are left out of urls so the plain list stays `/todos`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct ListFilter {
+    // only the todos filed under this list, see `lists`
+    pub list: Option<String>,
+    pub status: StatusFilter,
+    pub q: String,
+    // from 1
+    pub page: usize,
+    pub per_page: usize,
+    // a `Page::next_cursor`, set by the load-more sentinel for the items after those shown
+    pub after: Option<String>,
+    // a cursor the items end right before, set by the sentinel above a window
+    pub before: Option<String>,
+    // `name:value`, only the todos whose custom field has that value, see `fields`
+    pub field: Option<String>,
+}
+impl Default for ListFilter {
+    fn default() -> Self {
+        Self {
+            list: None,
+            status: StatusFilter::All,
+            q: String::new(),
+            page: 1,
+            per_page: DEFAULT_PER_PAGE,
+            after: None,
+            before: None,
+            field: None,
+        }
+    }
+}
+impl ListFilter {
+    pub fn validate(self) -> Result<Self, AppError> {
+        if self.page == 0 {
+            return Err(AppError::Invalid("Pages are counted from 1.".into()));
+        }
+        if !(1..=MAX_PER_PAGE).contains(&self.per_page) {
+            return Err(AppError::Invalid(format!(
+                "A page holds between 1 and {} todos.",
+                MAX_PER_PAGE
+            )));
+        }
+        Ok(self)
+    }
+
+    pub fn is_filtered(&self) -> bool {
+        self.list.is_some()
+            || self.status != StatusFilter::All
+            || !self.q.trim().is_empty()
+            || self.field.is_some()
+    }
+
+    // `query` narrowed down to the todos of this filter, on every page
+    pub fn matching(&self, query: TodoQuery) -> TodoQuery {
+        let query = match &self.list {
+            Some(list) => query.in_list(list),
+            None => query,
+        };
+        let query = match self.status {
+            StatusFilter::Active => query.completed(false),
+            StatusFilter::Completed => query.completed(true),
+            StatusFilter::All => query,
+        };
+        let query = match self
+            .field
+            .as_deref()
+            .and_then(|field| field.split_once(':'))
+        {
+            Some((name, value)) => query.field(name, value),
+            None => query,
+        };
+        query.search(&self.q)
+    }
+    // `query`, sorted already, narrowed down to this filter's page
+    pub fn query(&self, query: TodoQuery) -> Result<TodoQuery, AppError> {
+        let query = self.matching(query).limit(self.per_page);
+        match (&self.after, &self.before) {
+            (Some(after), _) => query.after_cursor(after),
+            (None, Some(before)) => query.before_cursor(before),
+            (None, None) => Ok(query.offset((self.page - 1) * self.per_page)),
+        }
+    }
+
+    pub fn url(&self) -> String {
+        format!("{}{}", routes::Todos::url(), self.query_string())
+    }
+    // `?status=..`, empty for the plain list
+    pub fn query_string(&self) -> String {
+        let mut params = Vec::new();
+        if let Some(list) = &self.list {
+            params.push(format!("list={}", urlencoding::encode(list)));
+        }
+        if self.status != StatusFilter::All {
+            params.push(format!("status={}", self.status.as_str()));
+        }
+        if !self.q.trim().is_empty() {
+            params.push(format!("q={}", urlencoding::encode(self.q.trim())));
+        }
+        if let Some(field) = &self.field {
+            params.push(format!("field={}", urlencoding::encode(field)));
+        }
+        if self.page != 1 {
+            params.push(format!("page={}", self.page));
+        }
+        if self.per_page != DEFAULT_PER_PAGE {
+            params.push(format!("per_page={}", self.per_page));
+        }
+        if let Some(after) = &self.after {
+            params.push(format!("after={}", urlencoding::encode(after)));
+        }
+        if let Some(before) = &self.before {
+            params.push(format!("before={}", urlencoding::encode(before)));
+        }
+        if params.is_empty() {
+            String::new()
+        } else {
+            format!("?{}", params.join("&"))
+        }
+    }
+
+    // another status starts over on the first page
+    fn with_status(&self, status: StatusFilter) -> Self {
+        Self {
+            status,
+            page: 1,
+            after: None,
+            before: None,
+            ..self.clone()
+        }
+    }
+    fn with_page(&self, page: usize) -> Self {
+        Self {
+            page,
+            after: None,
+            before: None,
+            ..self.clone()
+        }
+    }
+    fn with_after(&self, cursor: &str) -> Self {
+        Self {
+            page: 1,
+            after: Some(cursor.to_string()),
+            before: None,
+            ..self.clone()
+        }
+    }
+    fn with_before(&self, cursor: &str) -> Self {
+        Self {
+            page: 1,
+            after: None,
+            before: Some(cursor.to_string()),
+            ..self.clone()
+        }
+    }
+}
+
+// === Windows ===
+// Past `WINDOW_THRESHOLD` the list doesn't grow as it is scrolled, it is one page of items
+// between two sentinels, and reaching either replaces the page with the one half a page further
+// along. The half both share is scrolled back to where it was, so the sentinel just reached is
+// out of view again and the dom stays a page long however far the list goes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Window {
+    pub before: Option<Sentinel>,
+    pub after: Option<Sentinel>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sentinel {
+    pub cursor: String,
+    // the open item shown at the edge after the swap, the one the visitor was looking at
+    pub anchor: Option<u64>,
+}
+
+impl Window {
+    // the window of `items`, a page in `sort` order with pages before and after it or not
+    pub fn of(items: &[Todo], sort: Sort, has_prev: bool, has_next: bool) -> Self {
+        let cursor = |todo: &Todo| SortKey::of(todo, sort).cursor(sort);
+        let open = |todo: &&Todo| !todo.completed;
+        let middle = items.len() / 2;
+        let before = items
+            .get(middle)
+            .filter(|_| has_prev)
+            .map(|first| Sentinel {
+                cursor: cursor(first),
+                anchor: items[..middle].iter().rev().find(open).map(|todo| todo.id),
+            });
+        let split = middle.max(1);
+        let after = items
+            .get(split - 1)
+            .filter(|_| has_next)
+            .map(|last| Sentinel {
+                cursor: cursor(last),
+                anchor: items[split..].iter().find(open).map(|todo| todo.id),
+            });
+        Self { before, after }
+    }
+}
+
+// === Components ===
+// what refreshes of the list include, so they keep showing the same part of it
+pub fn include_attr() -> String {
+    format!("#{}, #{}", FILTER_ID, SEARCH_ID)
+}
+
+// the list shown, for writes that may take a todo out of it
+pub fn shown_list_attr() -> String {
+    format!("#{} [name=list]", FILTER_ID)
+}
+
+// a link swapping the list for `filter`, and putting it in the address bar
+fn link_html(filter: &ListFilter, class: &str, current: bool, label: &str) -> Markup {
+    let url = filter.url();
+    let load = Hx::get(url.clone())
+        .target(Target::Element(dom::TODOS.into()))
+        .swap(Swap::MorphInner)
+        .push_url();
+    html! {
+        a class=(class) href=(url) aria-current=[current.then_some("page")]
+            hx-get=[load.get_path()] hx-target=[load.target_attr()] hx-swap=[load.swap_attr()] hx-push-url=[load.push_url_attr()] { (label) }
+    }
+}
+
+// The status tabs above the list. The hidden fields carry the filter into the refreshes of the
+// list, which include them.
+pub fn tabs_html(filter: &ListFilter) -> Markup {
+    html! {
+        nav id=(FILTER_ID) class="flex space-x-4 text-sm" aria-label="Filter todos" {
+            @if let Some(list) = &filter.list {
+                input type="hidden" name="list" value=(list);
+            }
+            input type="hidden" name="status" value=(filter.status.as_str());
+            input type="hidden" name="page" value=(filter.page);
+            input type="hidden" name="per_page" value=(filter.per_page);
+            @for status in StatusFilter::ALL {
+                @let current = status == filter.status;
+                (link_html(
+                    &filter.with_status(status),
+                    if current { "font-bold t-accent" } else { "t-muted hover:underline" },
+                    current,
+                    status.label(),
+                ))
+            }
+        }
+    }
+}
+
+// searching starts over on the first page, keeping the status
+pub fn search_html(filter: &ListFilter) -> Markup {
+    let search = Hx::get(routes::Todos::url())
+        .target(Target::Element(dom::TODOS.into()))
+        .swap(Swap::MorphInner)
+        .push_url();
+    html! {
+        form class="flex-grow" role="search" method="get" action=(routes::Todos::url()) {
+            input id=(SEARCH_ID) class="w-full rounded p-2 border" type="search" name="q" value=(filter.q)
+                placeholder="Search todos" aria-label="Search todos"
+                hx-get=[search.get_path()] hx-target=[search.target_attr()] hx-swap=[search.swap_attr()] hx-push-url=[search.push_url_attr()]
+                hx-trigger="input changed delay:300ms, search"
+                hx-include={ "#" (FILTER_ID) " [name=list], #" (FILTER_ID) " [name=status], #" (FILTER_ID) " [name=per_page]" };
+        }
+    }
+}
+
+// The last item of the open list while there are more, swapped for the items after it once
+// scrolled into view. They end in a sentinel of their own if there are more still.
+pub fn load_more_html(filter: &ListFilter, next_cursor: Option<&str>) -> Markup {
+    html! {
+        @if let Some(cursor) = next_cursor {
+            @let more = Hx::get(filter.with_after(cursor).url()).swap(Swap::OuterHtml);
+            li class="t-muted text-center text-sm py-2" hx-get=[more.get_path()] hx-swap=[more.swap_attr()] hx-trigger="revealed" {
+                "Loading more…"
+            }
+        }
+    }
+}
+
+// Above or below a window, swapped for the next window over once scrolled into view.
+pub fn sentinel_html(filter: &ListFilter, sentinel: Option<&Sentinel>, forward: bool) -> Markup {
+    html! {
+        @if let Some(sentinel) = sentinel {
+            @let hx = window_hx(filter, sentinel, forward);
+            li class="t-muted text-center text-sm py-2" hx-get=[hx.get_path()] hx-target=[hx.target_attr()] hx-swap=[hx.swap_attr()] hx-trigger="revealed" {
+                @if forward { "Loading more…" } @else { "Loading earlier…" }
+            }
+        }
+    }
+}
+
+fn window_hx(filter: &ListFilter, sentinel: &Sentinel, forward: bool) -> Hx {
+    let (filter, edge) = if forward {
+        (filter.with_after(&sentinel.cursor), Edge::Top)
+    } else {
+        (filter.with_before(&sentinel.cursor), Edge::Bottom)
+    };
+    let hx = Hx::get(filter.url())
+        .target(Target::Element(dom::list_items(dom::OPEN_LIST)))
+        .swap(Swap::InnerHtml);
+    match sentinel.anchor {
+        Some(anchor) => hx.show(dom::todo(anchor), edge),
+        None => hx,
+    }
+}
+
+// previous and next below the list, nothing when it fits on one page
+pub fn pager_html(filter: &ListFilter, has_prev: bool, has_next: bool) -> Markup {
+    html! {
+        @if has_prev || has_next {
+            nav class="flex justify-between items-center mt-4 text-sm" aria-label="Pages" {
+                @if has_prev {
+                    (link_html(&filter.with_page(filter.page - 1), "t-accent hover:underline", false, "← Previous"))
+                } @else {
+                    span {}
+                }
+                span class="t-muted" { "Page " (filter.page) }
+                @if has_next {
+                    (link_html(&filter.with_page(filter.page + 1), "t-accent hover:underline", false, "Next →"))
+                } @else {
+                    span {}
+                }
+            }
+        }
+    }
+}
+
+// shown instead of an empty list when nothing matches
+pub fn no_matches_html(filter: &ListFilter) -> Markup {
+    html! {
+        p class="t-muted text-center my-4" {
+            "No todos match"
+            @if !filter.q.trim().is_empty() { " \"" (filter.q.trim()) "\"" }
+            ". "
+            (link_html(&ListFilter::default(), "t-accent hover:underline", false, "Show all"))
+        }
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::query::Page;
+
+    #[test]
+    fn test_urls_leave_out_defaults() {
+        assert_eq!(ListFilter::default().url(), "/todos");
+        let filter = ListFilter {
+            list: None,
+            status: StatusFilter::Active,
+            q: " buy milk ".into(),
+            page: 2,
+            per_page: 20,
+            after: None,
+            before: None,
+            field: None,
+        };
+        assert_eq!(
+            filter.url(),
+            "/todos?status=active&q=buy%20milk&page=2&per_page=20"
+        );
+        assert_eq!(
+            filter.with_after("4_2").url(),
+            "/todos?status=active&q=buy%20milk&per_page=20&after=4_2"
+        );
+        assert_eq!(
+            filter.with_status(StatusFilter::All).url(),
+            "/todos?q=buy%20milk&per_page=20"
+        );
+        let groceries = ListFilter {
+            list: Some("groceries".into()),
+            ..ListFilter::default()
+        };
+        assert_eq!(groceries.url(), "/todos?list=groceries");
+        assert!(groceries.is_filtered());
+        assert!(ListFilter {
+            page: 0,
+            ..ListFilter::default()
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn test_query_pages_the_matches() -> Result<(), AppError> {
+        let todos: Vec<Todo> = (1..=5)
+            .map(|id| {
+                let mut todo = Todo::new(id, format!("Buy item {}", id));
+                todo.set_completed(id == 5);
+                todo
+            })
+            .collect();
+        let filter = ListFilter {
+            list: None,
+            status: StatusFilter::Active,
+            q: "BUY".into(),
+            page: 2,
+            per_page: 3,
+            after: None,
+            before: None,
+            field: None,
+        };
+        let page = filter.query(TodoQuery::new())?.apply(todos.clone());
+        let ids: Vec<u64> = page.items.iter().map(|todo| todo.id).collect();
+        assert_eq!(ids, [4]);
+        assert!(page.has_prev() && !page.has_next());
+
+        // the first page's cursor leads to the same todos
+        let first = ListFilter {
+            page: 1,
+            ..filter.clone()
+        };
+        let cursor = first
+            .query(TodoQuery::new())?
+            .apply(todos.clone())
+            .next_cursor;
+        assert_eq!(cursor.as_deref(), Some("3"));
+        let after = first
+            .with_after("3")
+            .query(TodoQuery::new())?
+            .apply(todos.clone());
+        assert_eq!(after.items, page.items);
+
+        let filter = ListFilter {
+            q: "item 2".into(),
+            ..ListFilter::default()
+        };
+        assert_eq!(
+            filter
+                .query(TodoQuery::new())?
+                .apply(todos.clone())
+                .items
+                .len(),
+            1
+        );
+
+        let mut filed = todos;
+        filed[1].list = Some("groceries".into());
+        let filter = ListFilter {
+            list: Some("groceries".into()),
+            ..ListFilter::default()
+        };
+        let page = filter.query(TodoQuery::new())?.apply(filed);
+        assert_eq!(
+            page.items.iter().map(|todo| todo.id).collect::<Vec<_>>(),
+            [2]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_windows_overlap() -> Result<(), AppError> {
+        let todos: Vec<Todo> = (1..=10)
+            .map(|id| Todo::new(id, format!("item {}", id)))
+            .collect();
+        let filter = ListFilter {
+            per_page: 4,
+            ..ListFilter::default()
+        };
+        let window = filter.query(TodoQuery::new())?.apply(todos.clone());
+        let of = |page: &Page<Todo>| {
+            Window::of(&page.items, Sort::Created, page.has_prev(), page.has_next())
+        };
+        // the first window has nothing before it
+        let first = of(&window);
+        assert_eq!(first.before, None);
+        let after = first.after.unwrap();
+        assert_eq!(after.anchor, Some(3));
+
+        // the next one starts half a page on, at the item scrolled to
+        let next = filter
+            .with_after(&after.cursor)
+            .query(TodoQuery::new())?
+            .apply(todos.clone());
+        let ids =
+            |page: &Page<Todo>| -> Vec<u64> { page.items.iter().map(|todo| todo.id).collect() };
+        assert_eq!(ids(&next), [3, 4, 5, 6]);
+        let before = of(&next).before.unwrap();
+        assert_eq!(before.anchor, Some(4));
+        let back = filter
+            .with_before(&before.cursor)
+            .query(TodoQuery::new())?
+            .apply(todos);
+        assert_eq!(ids(&back), [1, 2, 3, 4]);
+        Ok(())
+    }
+}
@@ -0,0 +1,182 @@
+use std::{ops::Deref, sync::Arc};
+
+use anyhow::Result;
+use time::{macros::datetime, Duration, OffsetDateTime};
+
+use crate::{
+    clock::MockClock,
+    db::driver::Db,
+    models::{Status, Todo},
+    repository::entity::Repository,
+    tags,
+};
+
+// what the fixture database's clock starts at, a wednesday
+pub const NOW: OffsetDateTime = datetime!(2024-03-06 12:00 UTC);
+
+// === Directories ===
+// A fresh directory to put files in, removed again with everything in it when dropped.
+pub struct TestDir {
+    path: String,
+}
+impl TestDir {
+    pub fn new(name: &str) -> Result<Self> {
+        let tick = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        Ok(Self {
+            path: format!("test_db_{}_{}", name, tick),
+        })
+    }
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+// === Databases ===
+// A database in a fresh directory with its clock stopped at `NOW`, removed again when dropped.
+pub struct TestDb {
+    // taken on drop, sled has to let go of its files before they are removed
+    db: Option<Db>,
+    dir: TestDir,
+    pub clock: MockClock,
+}
+impl TestDb {
+    pub fn new(name: &str) -> Result<Self> {
+        let dir = TestDir::new(name)?;
+        let clock = MockClock::new(NOW);
+        let db = Db::new_with_path(dir.path())?.with_clock(Arc::new(clock.clone()));
+        Ok(Self {
+            db: Some(db),
+            dir,
+            clock,
+        })
+    }
+    // the directory the database is in
+    pub fn path(&self) -> &str {
+        self.dir.path()
+    }
+}
+impl Deref for TestDb {
+    type Target = Db;
+
+    fn deref(&self) -> &Db {
+        self.db
+            .as_ref()
+            .expect("the database is only taken on drop")
+    }
+}
+impl Drop for TestDb {
+    fn drop(&mut self) {
+        drop(self.db.take());
+    }
+}
+
+// === Todos ===
+// A todo to store, dates relative to the database's clock so they come out the same every run.
+#[derive(Debug, Clone)]
+pub struct TodoFixture {
+    title: String,
+    status: Status,
+    due_in_days: Option<i64>,
+    scheduled_in_days: Option<i64>,
+    tags: Vec<String>,
+    estimate_minutes: Option<u32>,
+    archived: bool,
+}
+impl Default for TodoFixture {
+    fn default() -> Self {
+        Self {
+            title: "Water the plants".into(),
+            status: Status::Backlog,
+            due_in_days: None,
+            scheduled_in_days: None,
+            tags: Vec::new(),
+            estimate_minutes: None,
+            archived: false,
+        }
+    }
+}
+impl TodoFixture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn titled(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        self
+    }
+    pub fn with_status(mut self, status: Status) -> Self {
+        self.status = status;
+        self
+    }
+    pub fn completed(self) -> Self {
+        self.with_status(Status::Done)
+    }
+    // negative for overdue
+    pub fn due_in_days(mut self, days: i64) -> Self {
+        self.due_in_days = Some(days);
+        self
+    }
+    pub fn scheduled_in_days(mut self, days: i64) -> Self {
+        self.scheduled_in_days = Some(days);
+        self
+    }
+    pub fn with_tags<'a>(mut self, tags: impl IntoIterator<Item = &'a str>) -> Self {
+        self.tags = tags.into_iter().filter_map(tags::normalize).collect();
+        self
+    }
+    pub fn estimate(mut self, minutes: u32) -> Self {
+        self.estimate_minutes = Some(minutes);
+        self
+    }
+    pub fn archived(mut self) -> Self {
+        self.archived = true;
+        self
+    }
+
+    // the todo as it would be stored in `db`, with the next id
+    pub fn build(&self, db: &Db) -> Result<Todo> {
+        let today = db.clock().now().date();
+        let mut todo = Todo::new(db.next_id()?, self.title.clone());
+        todo.set_status(self.status);
+        todo.due = self.due_in_days.map(|days| today + Duration::days(days));
+        todo.scheduled_for = self
+            .scheduled_in_days
+            .map(|days| today + Duration::days(days));
+        todo.tags = self.tags.clone();
+        todo.estimate_minutes = self.estimate_minutes;
+        todo.archived = self.archived;
+        todo.updated_at = db.clock().now_millis() / 1000;
+        Ok(todo)
+    }
+    pub fn persist(&self, db: &Db) -> Result<Todo> {
+        let todo = self.build(db)?;
+        Repository::new(db).put(&todo)?;
+        Ok(todo)
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_todo_fixture() -> Result<()> {
+        let db = TestDb::new("fixtures")?;
+        let todo = TodoFixture::new()
+            .with_tags(["Home", "errands"])
+            .due_in_days(2)
+            .persist(&db)?;
+        assert_eq!(todo.due, Some(time::macros::date!(2024 - 03 - 08)));
+        assert_eq!(todo.tags, ["home", "errands"]);
+        let stored = Repository::<Todo>::new(&db).get(todo.id)?;
+        assert_eq!(stored, Some(todo));
+        Ok(())
+    }
+}
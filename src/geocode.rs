@@ -0,0 +1,118 @@
+use std::{fmt, future::Future, pin::Pin, time::Duration};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::{db::driver::Db, models::Coords};
+
+// lookups, `geocode:{query}`, so each place is only asked for once a month
+const CACHE_PREFIX: &str = "geocode:";
+const CACHE_LIFETIME: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+const TIMEOUT: Duration = Duration::from_secs(3);
+
+pub type GeocodeFuture<'a> = Pin<Box<dyn Future<Output = Result<Option<Coords>>> + Send + 'a>>;
+
+// Turns a free text place into coordinates, `None` when nothing matches.
+pub trait Geocoder: fmt::Debug + Send + Sync {
+    fn geocode<'a>(&'a self, query: &'a str) -> GeocodeFuture<'a>;
+}
+
+// === Nominatim ===
+// the OpenStreetMap search api, or a self-hosted instance of it
+#[derive(Debug)]
+pub struct Nominatim {
+    client: reqwest::Client,
+    base_url: String,
+}
+impl Nominatim {
+    pub fn new(base_url: &str) -> Result<Self> {
+        // the usage policy asks for an identifying user agent
+        let client = reqwest::Client::builder()
+            .user_agent(concat!(
+                env!("CARGO_PKG_NAME"),
+                "/",
+                env!("CARGO_PKG_VERSION")
+            ))
+            .timeout(TIMEOUT)
+            .build()?;
+        Ok(Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct Place {
+    lat: String,
+    lon: String,
+}
+
+impl Geocoder for Nominatim {
+    fn geocode<'a>(&'a self, query: &'a str) -> GeocodeFuture<'a> {
+        Box::pin(async move {
+            let places: Vec<Place> = self
+                .client
+                .get(format!("{}/search", self.base_url))
+                .query(&[("q", query), ("format", "jsonv2"), ("limit", "1")])
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            let Some(place) = places.first() else {
+                return Ok(None);
+            };
+            Ok(Some(Coords {
+                lat: place.lat.parse().context("invalid latitude")?,
+                lng: place.lon.parse().context("invalid longitude")?,
+            }))
+        })
+    }
+}
+
+// === Cache ===
+// Geocode `query`, answering from the cache when it was looked up before. Misses are cached too.
+pub async fn lookup(db: &Db, geocoder: &dyn Geocoder, query: &str) -> Result<Option<Coords>> {
+    let key = format!("{}{}", CACHE_PREFIX, query.trim().to_lowercase());
+    if let Some(cached) = db.get::<Option<Coords>, _>(&key)? {
+        return Ok(cached);
+    }
+    let coords = geocoder.geocode(query).await?;
+    db.insert_with_ttl(&key, &coords, CACHE_LIFETIME)?;
+    Ok(coords)
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::fixtures::TestDb;
+
+    #[derive(Debug, Default)]
+    struct Counting(AtomicUsize);
+    impl Geocoder for Counting {
+        fn geocode<'a>(&'a self, _query: &'a str) -> GeocodeFuture<'a> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async {
+                Ok(Some(Coords {
+                    lat: 52.5,
+                    lng: 13.4,
+                }))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lookup_is_cached() -> Result<()> {
+        let db = TestDb::new("geocode")?;
+        let geocoder = Counting::default();
+        let first = lookup(&db, &geocoder, "Berlin").await?;
+        let second = lookup(&db, &geocoder, " berlin ").await?;
+        assert_eq!(first, second);
+        assert_eq!(geocoder.0.load(Ordering::SeqCst), 1);
+        Ok(())
+    }
+}
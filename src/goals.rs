@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    response::Response,
+};
+use maud::{html, Markup};
+use serde::Deserialize;
+
+use crate::{
+    error::AppError,
+    extract::FormOrJson,
+    method_override,
+    models::{Goal, Progress},
+    repository::goal,
+    routes,
+    state::AppState,
+    tenant::Tenant,
+    views::{
+        self, dom,
+        nav::{self, Nav},
+    },
+};
+
+// === Components ===
+fn progress_html(progress: Progress) -> Markup {
+    html! {
+        div class="flex items-center space-x-2" {
+            div class="w-full bg-gray-200 rounded h-2" role="progressbar" aria-valuemin="0" aria-valuemax="100" aria-valuenow=(progress.percent()) {
+                div class="bg-green-500 h-2 rounded" style={ "width: " (progress.percent()) "%" } {}
+            }
+            span class="text-sm text-gray-500 whitespace-nowrap" { (progress.done) " / " (progress.total) }
+        }
+    }
+}
+
+fn goal_html(goal: &Goal, progress: Progress) -> Markup {
+    let url = routes::Goal::url(goal.id);
+    html! {
+        li id=(dom::goal(goal.id)) class="bg-white rounded-lg shadow-lg my-2 p-4 space-y-2" {
+            div class="flex items-center justify-between" {
+                form class="flex-grow mr-4" method="post" action=(url) hx-post=(url) hx-trigger="change" hx-target=(dom::selector(&dom::goal(goal.id))) hx-swap="outerHTML" {
+                    input class="w-full rounded p-1 text-gray-700" type="text" name="title" value=(goal.title) aria-label="Goal" required;
+                }
+                form method="post" action=(url) {
+                    input type="hidden" name=(method_override::METHOD_FIELD) value="DELETE";
+                    button class="bg-red-500 hover:bg-red-700 text-white font-bold py-1 px-2 rounded" type="submit"
+                        hx-delete=(url) hx-target=(dom::selector(&dom::goal(goal.id))) hx-swap="outerHTML" hx-confirm="Remove this goal? Its todos are kept." { "Remove" }
+                }
+            }
+            (progress_html(progress))
+        }
+    }
+}
+
+fn new_goal_html() -> Markup {
+    html! {
+        form class="flex justify-between items-center" method="post" action=(routes::CreateGoal::url())
+            hx-put=(routes::CreateGoal::url()) hx-target="#goals ul" hx-swap="beforeend" "hx-on::after-request"="this.reset()" {
+            input type="hidden" name=(method_override::METHOD_FIELD) value="PUT";
+            input class="w-full rounded p-2 mr-4" type="text" name="title" placeholder="New Goal" required;
+            button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" { "Add" }
+        }
+    }
+}
+
+// Which goal a todo counts towards, for its detail view.
+pub fn picker_html(todo: u64, goals: &[Goal], current: Option<u64>) -> Markup {
+    let url = routes::TodoGoal::url(todo);
+    html! {
+        form id="goal-picker" class="flex items-center space-x-2" method="post" action=(url)
+            hx-post=(url) hx-trigger="change" hx-target="this" hx-swap="outerHTML" {
+            label class="text-gray-600" for="goal-select" { "Goal" }
+            select id="goal-select" class="rounded border p-1" name="goal" {
+                option value="" selected[current.is_none()] { "None" }
+                @for goal in goals {
+                    option value=(goal.id) selected[current == Some(goal.id)] { (goal.title) }
+                }
+            }
+            noscript {
+                button class="text-blue-500 hover:text-blue-700" type="submit" { "Save" }
+            }
+        }
+    }
+}
+
+pub async fn load_picker(state: &AppState, tenant: &Tenant, todo: u64) -> Result<Markup, AppError> {
+    let db = state.read().await.for_tenant(tenant.id())?;
+    Ok(picker_html(
+        todo,
+        &goal::all(&db)?,
+        goal::goal_of(&db, todo)?,
+    ))
+}
+
+// === Routes ===
+pub async fn index(State(state): State<AppState>, tenant: Tenant) -> Result<Markup, AppError> {
+    let db = state.read().await.for_tenant(tenant.id())?;
+    let goals = goal::all(&db)?;
+    let progress: HashMap<u64, Progress> = goal::progress(&db)?;
+    Ok(views::page(
+        "Goals",
+        html! {
+            (nav::navigation(&Nav::goals()))
+            h1 class="text-4xl text-center text-gray-700 mb-6" { "Goals" }
+            section id="goals" class="bg-gray-200 rounded-lg p-4" {
+                (new_goal_html())
+                ul {
+                    @for goal in &goals {
+                        (goal_html(goal, progress.get(&goal.id).copied().unwrap_or_default()))
+                    }
+                }
+            }
+        },
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct GoalForm {
+    title: String,
+}
+pub async fn create(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    FormOrJson(GoalForm { title }): FormOrJson<GoalForm>,
+) -> Result<Response, AppError> {
+    let guard = state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    let goal = Goal {
+        id: db.next_id()?,
+        title,
+    };
+    db.insert(goal::goal_key(goal.id), &goal)?;
+    Ok(views::fragment_or_redirect(
+        &headers,
+        goal_html(&goal, Progress::default()),
+        &routes::Goals::url(),
+    ))
+}
+
+pub async fn update(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Path(id): Path<u64>,
+    FormOrJson(GoalForm { title }): FormOrJson<GoalForm>,
+) -> Result<Response, AppError> {
+    let guard = state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    let mut goal = db
+        .get::<Goal, _>(goal::goal_key(id))?
+        .ok_or(AppError::NotFound)?;
+    goal.title = title;
+    db.insert(goal::goal_key(id), &goal)?;
+    let progress = goal::progress(&db)?.get(&id).copied().unwrap_or_default();
+    Ok(views::fragment_or_redirect(
+        &headers,
+        goal_html(&goal, progress),
+        &routes::Goals::url(),
+    ))
+}
+
+pub async fn remove(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Path(id): Path<u64>,
+) -> Result<Response, AppError> {
+    let guard = state.write().await;
+    goal::remove(&guard.for_tenant(tenant.id())?, id)?;
+    Ok(views::deleted(&headers, &routes::Goals::url()))
+}
+
+#[derive(Deserialize)]
+pub struct PickGoal {
+    // a goal id, empty to unlink
+    goal: String,
+}
+pub async fn pick(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Path(todo): Path<u64>,
+    FormOrJson(PickGoal { goal: picked }): FormOrJson<PickGoal>,
+) -> Result<Response, AppError> {
+    let picked = match picked.trim() {
+        "" => None,
+        id => Some(
+            id.parse()
+                .map_err(|_| AppError::Invalid(format!("`{}` is not a goal", id)))?,
+        ),
+    };
+    let guard = state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    goal::set_goal(&db, todo, picked)?;
+    let fragment = picker_html(todo, &goal::all(&db)?, picked);
+    Ok(views::fragment_or_redirect(
+        &headers,
+        fragment,
+        &routes::TodoDetail::url(todo),
+    ))
+}
@@ -0,0 +1,80 @@
+use anyhow::Result;
+use maud::{html, Markup};
+
+use crate::{auth::visitor::Visitor, db::driver::Db, routes};
+
+// guest workspaces are tenant trees named after the visitor, `guest-{visitor}`
+const GUEST_PREFIX: &str = "guest-";
+
+// === Guests ===
+// With `GUEST_MODE` the base domain is no shared workspace, every browser gets its own under its
+// visitor id. Signing up claims it, moving its todos into the new workspace.
+pub fn tenant_id(visitor: &Visitor) -> String {
+    format!("{}{}", GUEST_PREFIX, visitor.0)
+}
+
+// signups cannot take these
+pub fn is_guest(tenant: &str) -> bool {
+    tenant.starts_with(GUEST_PREFIX)
+}
+
+// whether the guest workspace of `visitor` has anything worth claiming
+pub fn has_data(db: &Db, visitor: &Visitor) -> Result<bool> {
+    let guest = tenant_id(visitor);
+    if !db.tenant_ids()?.contains(&guest) {
+        return Ok(false);
+    }
+    Ok(!db.for_tenant(Some(&guest))?.is_empty())
+}
+
+// Move everything of the guest workspace into `tenant` in one transaction and remove it.
+// Returns how many keys moved.
+pub fn claim(db: &Db, visitor: &Visitor, tenant: &str) -> Result<usize> {
+    let guest = tenant_id(visitor);
+    let moved = db
+        .for_tenant(Some(&guest))?
+        .move_into(&db.for_tenant(Some(tenant))?)?;
+    db.drop_tenant(&guest)?;
+    Ok(moved)
+}
+
+// === Components ===
+pub fn banner_html() -> Markup {
+    html! {
+        div class="bg-blue-100 text-blue-900 rounded-lg p-4 mb-6 flex justify-between items-center" role="status" {
+            p { "You are using a guest workspace, only this browser can see it." }
+            a class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded whitespace-nowrap ml-4" href=(routes::Signup::url()) {
+                "Sign up to keep it"
+            }
+        }
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixtures::TestDb, models::Todo, repository};
+
+    #[test]
+    fn test_claim() -> Result<()> {
+        let db = TestDb::new("guest")?;
+        let visitor = Visitor("4d9c1f3e".into());
+        assert!(is_guest(&tenant_id(&visitor)));
+        assert!(!has_data(&db, &visitor)?);
+
+        let guest = db.for_tenant(Some(&tenant_id(&visitor)))?;
+        guest.insert("todo:1", &Todo::new(1, "Try it out".into()))?;
+        guest.insert("todo:2", &Todo::new(2, "Sign up".into()))?;
+        assert!(has_data(&db, &visitor)?);
+
+        assert_eq!(claim(&db, &visitor, "acme")?, 2);
+        assert_eq!(
+            repository::todo::all(&db.for_tenant(Some("acme"))?)?.len(),
+            2
+        );
+        assert!(!has_data(&db, &visitor)?);
+        assert!(!db.tenant_ids()?.contains(&tenant_id(&visitor)));
+        Ok(())
+    }
+}
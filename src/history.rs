@@ -0,0 +1,396 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, HeaderValue},
+    response::{IntoResponse, Redirect, Response},
+};
+use maud::{html, Markup};
+
+use crate::{
+    activity,
+    db::{driver::Db, queue::WriteOp},
+    error::AppError,
+    locale::Formatter,
+    models::Todo,
+    repository::{self, entity::Repository},
+    retention::Volume,
+    routes,
+    state::AppState,
+    tenant::Tenant,
+    views,
+};
+
+// earlier versions of a todo, `history:{todo}:{version}` with the version zero-padded so they
+// list in order
+const HISTORY_PREFIX: &str = "history:";
+// versions kept per todo, the oldest go first
+const MAX_VERSIONS: usize = 50;
+const HISTORY_ID: &str = "history";
+
+fn prefix(todo: u64) -> String {
+    format!("{}{}:", HISTORY_PREFIX, todo)
+}
+fn version_key(todo: u64, version: u64) -> String {
+    format!("{}{:020}", prefix(todo), version)
+}
+
+// === Versions ===
+// Keep `before`, the todo as it was until the change being written. Goes into the same batch as
+// the change.
+pub fn record_ops(db: &Db, before: &Todo) -> Result<Vec<WriteOp>> {
+    let keys = db
+        .iter_keys(&prefix(before.id))
+        .collect::<Result<Vec<_>>>()?;
+    let mut ops: Vec<WriteOp> = keys
+        .iter()
+        .rev()
+        .skip(MAX_VERSIONS - 1)
+        .map(|key| WriteOp::Remove { key: key.clone() })
+        .collect();
+    ops.push(WriteOp::Insert {
+        key: version_key(before.id, before.version),
+        value: db.encode(before)?,
+    });
+    Ok(ops)
+}
+
+// the kept versions of `todo`, oldest first
+pub fn versions(db: &Db, todo: u64) -> Result<Vec<Todo>> {
+    db.iter_prefix::<Todo>(&prefix(todo))?
+        .map(|item| item.map(|(_, todo)| todo))
+        .collect()
+}
+
+// === Retention ===
+// the kept versions of every todo, by todo
+fn by_todo(db: &Db) -> Result<BTreeMap<u64, Vec<(String, Todo)>>> {
+    let mut by_todo: BTreeMap<u64, Vec<_>> = BTreeMap::new();
+    for item in db.iter_prefix::<Todo>(HISTORY_PREFIX)? {
+        let (key, version) = item?;
+        by_todo.entry(version.id).or_default().push((key, version));
+    }
+    Ok(by_todo)
+}
+
+fn exists(db: &Db, todo: u64) -> Result<bool> {
+    Ok(db.get_payload(repository::todo::todo_key(todo))?.is_some())
+}
+
+// Drop the versions of todos still around that were changed before `cutoff`, in unix seconds.
+// Returns how many went.
+pub fn purge_before(db: &Db, cutoff: u64) -> Result<usize> {
+    let mut ops = Vec::new();
+    for (todo, versions) in by_todo(db)? {
+        if !exists(db, todo)? {
+            continue;
+        }
+        ops.extend(
+            versions
+                .into_iter()
+                .filter(|(_, version)| version.updated_at < cutoff)
+                .map(|(key, _)| WriteOp::Remove { key }),
+        );
+    }
+    let purged = ops.len();
+    db.apply_batch(ops)?;
+    Ok(purged)
+}
+
+// Removing a todo leaves its history, drop all of it once even its last version is from before
+// `cutoff`. Returns how many versions went.
+pub fn purge_removed_before(db: &Db, cutoff: u64) -> Result<usize> {
+    let mut ops = Vec::new();
+    for (todo, versions) in by_todo(db)? {
+        if exists(db, todo)?
+            || versions
+                .iter()
+                .any(|(_, version)| version.updated_at >= cutoff)
+        {
+            continue;
+        }
+        ops.extend(versions.into_iter().map(|(key, _)| WriteOp::Remove { key }));
+    }
+    let purged = ops.len();
+    db.apply_batch(ops)?;
+    Ok(purged)
+}
+
+// what the history of todos still around and of removed ones takes up
+pub fn volumes(db: &Db) -> Result<(Volume, Volume)> {
+    let (mut kept, mut removed) = (Volume::default(), Volume::default());
+    for key in db.iter_keys(HISTORY_PREFIX) {
+        let key = key?;
+        let todo = key
+            .strip_prefix(HISTORY_PREFIX)
+            .and_then(|rest| rest.split_once(':'))
+            .and_then(|(todo, _)| todo.parse().ok());
+        let bytes = db.get_payload(&key)?.map_or(0, |payload| payload.len());
+        match todo {
+            Some(todo) if !exists(db, todo)? => removed.add(bytes),
+            _ => kept.add(bytes),
+        }
+    }
+    Ok((kept, removed))
+}
+
+// === Diffs ===
+fn day(day: Option<time::Date>, dates: &Formatter) -> String {
+    day.map_or_else(|| "none".to_string(), |day| dates.date(day))
+}
+
+// What changed from `before` to `after`, one line per field.
+pub fn changes(before: &Todo, after: &Todo, dates: &Formatter) -> Vec<String> {
+    let mut changes = Vec::new();
+    if before.title != after.title {
+        changes.push(format!(
+            "Title changed from \"{}\" to \"{}\"",
+            before.title, after.title
+        ));
+    }
+    if before.status != after.status {
+        changes.push(format!(
+            "Moved from {} to {}",
+            before.status.label(),
+            after.status.label()
+        ));
+    }
+    match (before.due, after.due) {
+        (None, Some(due)) => changes.push(format!("Due date set to {}", dates.date(due))),
+        (Some(_), None) => changes.push("Due date removed".into()),
+        (Some(from), Some(to)) if from != to => changes.push(format!(
+            "Due date moved from {} to {}",
+            dates.date(from),
+            dates.date(to)
+        )),
+        _ => {}
+    }
+    if before.scheduled_for != after.scheduled_for {
+        changes.push(format!(
+            "Start date changed from {} to {}",
+            day(before.scheduled_for, dates),
+            day(after.scheduled_for, dates)
+        ));
+    }
+    if before.estimate_minutes != after.estimate_minutes {
+        changes.push("Estimate changed".into());
+    }
+    for tag in after.tags.iter().filter(|tag| !before.tags.contains(tag)) {
+        changes.push(format!("Tagged #{}", tag));
+    }
+    for tag in before.tags.iter().filter(|tag| !after.tags.contains(tag)) {
+        changes.push(format!("Untagged #{}", tag));
+    }
+    if before.archived != after.archived {
+        changes.push(
+            if after.archived {
+                "Archived"
+            } else {
+                "Unarchived"
+            }
+            .into(),
+        );
+    }
+    if before.color != after.color {
+        changes.push("Color changed".into());
+    }
+    changes
+}
+
+// === Components ===
+// The versions of a todo with what changed between them, and who made which change from the
+// activity log.
+pub fn history_html(
+    current: &Todo,
+    versions: &[Todo],
+    activity: &[activity::Entry],
+    dates: &Formatter,
+) -> Markup {
+    html! {
+        section id=(HISTORY_ID) class="space-y-2" {
+            h2 class="text-xl text-gray-700" { "History" }
+            @if versions.is_empty() {
+                p class="text-gray-500" { "No changes yet." }
+            }
+            ol class="space-y-2" {
+                // newest first, each version against the one that replaced it
+                @for (index, version) in versions.iter().enumerate().rev() {
+                    @let after = versions.get(index + 1).unwrap_or(current);
+                    li class="text-sm" {
+                        span class="t-muted" { (dates.ago(after.updated_at)) }
+                        ul class="ml-4 list-disc" {
+                            @for change in changes(version, after, dates) {
+                                li { (change) }
+                            }
+                        }
+                        @if version.title != current.title {
+                            form method="post" action=(routes::TodoHistoryRestore::url(current.id, version.version)) {
+                                button class="text-blue-500 hover:text-blue-700" type="submit"
+                                    hx-post=(routes::TodoHistoryRestore::url(current.id, version.version)) {
+                                    "Restore title \"" (version.title) "\""
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            @if !activity.is_empty() {
+                h3 class="text-lg text-gray-700" { "Who changed it" }
+                (activity::entries_html(activity, dates))
+            }
+        }
+    }
+}
+
+// the link on the detail view, the history loads in its place
+pub fn tab_html(todo: u64) -> Markup {
+    html! {
+        div id=(HISTORY_ID) {
+            a class="text-blue-500 hover:text-blue-700" href=(routes::TodoHistory::url(todo))
+                hx-get=(routes::TodoHistory::url(todo)) hx-target="this" hx-swap="outerHTML" { "History" }
+        }
+    }
+}
+
+// === Routes ===
+pub async fn index(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Path(id): Path<u64>,
+) -> Result<Response, AppError> {
+    let db = state.read().await.for_tenant(tenant.id())?;
+    let todo = db
+        .get::<Todo, _>(repository::todo::todo_key(id))?
+        .ok_or(AppError::NotFound)?;
+    let history = history_html(
+        &todo,
+        &versions(&db, id)?,
+        &activity::for_todo(&db, id)?,
+        &Formatter::load(&db)?,
+    );
+    if views::wants_fragment(&headers) {
+        return Ok(history.into_response());
+    }
+    Ok(views::page(&todo.title, history).into_response())
+}
+
+// Put the title of an earlier version back, as a change of its own.
+pub async fn restore(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Path((id, version)): Path<(u64, u64)>,
+) -> Result<Response, AppError> {
+    let writes = state.writes.clone();
+    let guard = state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    let todos = Repository::new(&db);
+    let before: Todo = todos.get(id)?.ok_or(AppError::NotFound)?;
+    let earlier = db
+        .get::<Todo, _>(version_key(id, version))?
+        .ok_or(AppError::NotFound)?;
+    let mut todo = before.clone();
+    todo.title = earlier.title;
+    todo.touch(&**db.clock());
+    let mut ops = record_ops(&db, &before)?;
+    ops.extend(todos.put_ops(&todo)?);
+    writes.submit(&db, ops).await?;
+    let detail = routes::TodoDetail::url(id);
+    if headers.contains_key("hx-request") {
+        let mut response = html! {}.into_response();
+        if let Ok(location) = HeaderValue::from_str(&detail) {
+            response.headers_mut().insert("hx-redirect", location);
+        }
+        return Ok(response);
+    }
+    Ok(Redirect::to(&detail).into_response())
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{TestDb, TodoFixture};
+
+    #[test]
+    fn test_versions_and_changes() -> Result<()> {
+        let db = TestDb::new("history")?;
+        let mut todo = TodoFixture::new()
+            .titled("Draft")
+            .due_in_days(1)
+            .persist(&db)?;
+        for title in ["Draft 2", "Final"] {
+            let before = todo.clone();
+            todo.title = title.into();
+            todo.touch(&db.clock);
+            db.apply_batch(record_ops(&db, &before)?)?;
+        }
+        let versions = versions(&db, todo.id)?;
+        assert_eq!(
+            versions
+                .iter()
+                .map(|v| v.title.as_str())
+                .collect::<Vec<_>>(),
+            ["Draft", "Draft 2"]
+        );
+
+        let dates = Formatter::load(&db)?;
+        let mut moved = todo.clone();
+        moved.due = None;
+        moved.tags = vec!["home".into()];
+        assert_eq!(
+            changes(&versions[0], &moved, &dates),
+            [
+                "Title changed from \"Draft\" to \"Final\"",
+                "Due date removed",
+                "Tagged #home"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_oldest_versions_dropped() -> Result<()> {
+        let db = TestDb::new("history_cap")?;
+        let mut todo = TodoFixture::new().build(&db)?;
+        for _ in 0..MAX_VERSIONS + 5 {
+            db.apply_batch(record_ops(&db, &todo)?)?;
+            todo.touch(&db.clock);
+        }
+        let versions = versions(&db, todo.id)?;
+        assert_eq!(versions.len(), MAX_VERSIONS);
+        assert_eq!(versions[0].version, 6);
+        Ok(())
+    }
+
+    #[test]
+    fn test_purge() -> Result<()> {
+        let db = TestDb::new("history_purge")?;
+        let kept = TodoFixture::new().titled("Kept").persist(&db)?;
+        let removed = TodoFixture::new().titled("Removed").persist(&db)?;
+        for (mut todo, days) in [(kept.clone(), [40, 10]), (removed.clone(), [50, 45])] {
+            for (version, days) in days.into_iter().enumerate() {
+                todo.version = version as u64;
+                todo.updated_at = 100 * 86_400 - days * 86_400;
+                db.apply_batch(record_ops(&db, &todo)?)?;
+            }
+        }
+        db.remove(repository::todo::todo_key(removed.id))?;
+        let (history, gone) = volumes(&db)?;
+        assert_eq!((history.keys, gone.keys), (2, 2));
+
+        let cutoff = 100 * 86_400 - 30 * 86_400;
+        // only the history of todos still around
+        assert_eq!(purge_before(&db, cutoff)?, 1);
+        assert_eq!(versions(&db, kept.id)?.len(), 1);
+        assert_eq!(versions(&db, removed.id)?.len(), 2);
+        // a removed todo goes as a whole once its last version is past the cutoff
+        assert_eq!(purge_removed_before(&db, 100 * 86_400 - 47 * 86_400)?, 0);
+        assert_eq!(purge_removed_before(&db, cutoff)?, 2);
+        assert!(versions(&db, removed.id)?.is_empty());
+        assert_eq!(versions(&db, kept.id)?.len(), 1);
+        Ok(())
+    }
+}
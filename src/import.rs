@@ -0,0 +1,376 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::Duration,
+};
+
+use anyhow::Result;
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Redirect, Response},
+    Form,
+};
+use maud::{html, Markup};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::Clock,
+    db::{driver::Db, queue::WriteOp},
+    error::AppError,
+    models::Todo,
+    repository::{entity::Repository, query::TodoQuery},
+    routes,
+    state::AppState,
+    tenant::Tenant,
+    views::{
+        self,
+        nav::{self, Nav},
+    },
+};
+
+// imports waiting for their duplicates to be reviewed, `import:{token}`
+pub const PENDING_PREFIX: &str = "import:";
+// how long a review may stay open before the import has to be started over
+const PENDING_LIFETIME: Duration = Duration::from_secs(60 * 60);
+// titles at least this long may differ by a typo or two and still count as the same
+const FUZZY_MIN_LEN: usize = 8;
+const FUZZY_MAX_EDITS: usize = 2;
+
+// Parsed todos from an export, kept until the user has decided what to do with duplicates.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingImport {
+    pub todos: Vec<Todo>,
+}
+
+fn pending_key(token: &str) -> String {
+    format!("{}{}", PENDING_PREFIX, token)
+}
+
+// what to do with an imported todo that matches an existing one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Choice {
+    // fill in what the existing todo is missing
+    Merge,
+    #[default]
+    Skip,
+    // import it anyway, as a todo of its own
+    Create,
+}
+impl Choice {
+    const ALL: [Choice; 3] = [Choice::Merge, Choice::Skip, Choice::Create];
+
+    fn value(self) -> &'static str {
+        match self {
+            Choice::Merge => "merge",
+            Choice::Skip => "skip",
+            Choice::Create => "create",
+        }
+    }
+    fn label(self) -> &'static str {
+        match self {
+            Choice::Merge => "Merge",
+            Choice::Skip => "Skip",
+            Choice::Create => "Create",
+        }
+    }
+    fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|choice| choice.value() == value)
+    }
+}
+
+// the todos of a settings export's todos.json
+pub fn parse_export(json: &str) -> Result<Vec<Todo>, AppError> {
+    serde_json::from_str(json)
+        .map_err(|err| AppError::Invalid(format!("not a todos.json export: {}", err)))
+}
+
+// === Duplicates ===
+// lowercase words without punctuation, so "Buy milk!" and "buy  milk" compare equal
+pub fn normalize_title(title: &str) -> String {
+    title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+fn similar_titles(a: &str, b: &str) -> bool {
+    let (a, b) = (normalize_title(a), normalize_title(b));
+    a == b || (a.len().min(b.len()) >= FUZZY_MIN_LEN && edit_distance(&a, &b) <= FUZZY_MAX_EDITS)
+}
+
+// the existing todo `todo` duplicates, same due date and (nearly) the same title
+pub fn find_duplicate<'a>(existing: &'a [Todo], todo: &Todo) -> Option<&'a Todo> {
+    existing.iter().find(|candidate| {
+        candidate.due == todo.due && similar_titles(&candidate.title, &todo.title)
+    })
+}
+
+fn merge(existing: &mut Todo, incoming: &Todo, clock: &dyn Clock) {
+    if incoming.completed && !existing.completed {
+        existing.set_status(incoming.status);
+    }
+    existing.estimate_minutes = existing.estimate_minutes.or(incoming.estimate_minutes);
+    existing.location = existing.location.take().or(incoming.location.clone());
+    existing.color = existing.color.take().or(incoming.color.clone());
+    for tag in &incoming.tags {
+        if !existing.tags.contains(tag) {
+            existing.tags.push(tag.clone());
+        }
+    }
+    existing.touch(clock);
+}
+
+// The writes an import comes down to, `choices` by the index of the imported todo. Todos
+// without a duplicate are always created.
+fn plan(
+    db: &Db,
+    existing: &[Todo],
+    pending: &PendingImport,
+    choices: &HashMap<usize, Choice>,
+) -> Result<Vec<WriteOp>> {
+    let mut merged: BTreeMap<u64, Todo> = BTreeMap::new();
+    let mut ops = Vec::new();
+    for (index, incoming) in pending.todos.iter().enumerate() {
+        let choice = match find_duplicate(existing, incoming) {
+            Some(duplicate) => {
+                let choice = choices.get(&index).copied().unwrap_or_default();
+                if choice == Choice::Merge {
+                    let target = merged
+                        .entry(duplicate.id)
+                        .or_insert_with(|| duplicate.clone());
+                    merge(target, incoming, &**db.clock());
+                }
+                choice
+            }
+            None => Choice::Create,
+        };
+        if choice == Choice::Create {
+            let mut todo = incoming.clone();
+            todo.id = db.next_id()?;
+            ops.extend(Repository::new(db).put_ops(&todo)?);
+        }
+    }
+    for todo in merged.values() {
+        ops.extend(Repository::new(db).put_ops(todo)?);
+    }
+    Ok(ops)
+}
+
+// === Components ===
+pub fn settings_html() -> Markup {
+    html! {
+        section class="bg-white rounded-lg shadow-lg p-6 space-y-4" {
+            h2 class="text-2xl text-gray-700" { "Import" }
+            form class="space-y-2" method="post" action=(routes::Import::url()) {
+                label class="block text-gray-600" for="import-json" { "Paste the todos.json of an export" }
+                textarea id="import-json" class="w-full rounded border p-2 font-mono text-sm" name="json" rows="4" required {}
+                button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" { "Review import" }
+            }
+        }
+    }
+}
+
+fn review_html(token: &str, existing: &[Todo], pending: &PendingImport) -> Markup {
+    let conflicts: Vec<(usize, &Todo, &Todo)> = pending
+        .todos
+        .iter()
+        .enumerate()
+        .filter_map(|(index, todo)| Some((index, todo, find_duplicate(existing, todo)?)))
+        .collect();
+    let new = pending.todos.len() - conflicts.len();
+    html! {
+        form class="bg-white rounded-lg shadow-lg p-6 space-y-4" method="post" action=(routes::ImportReview::url(token)) {
+            p class="text-gray-700" {
+                (new) " new todos will be created. "
+                @if !conflicts.is_empty() { (conflicts.len()) " look like todos you already have, pick what to do with each." }
+            }
+            @if !conflicts.is_empty() {
+                table class="w-full text-left" {
+                    thead {
+                        tr class="text-gray-500" { th { "Importing" } th { "Already have" } th { "Do" } }
+                    }
+                    tbody {
+                        @for (index, incoming, duplicate) in &conflicts {
+                            tr class="border-t" {
+                                td class="py-2" {
+                                    (incoming.title)
+                                    @if let Some(due) = incoming.due { span class="text-gray-500" { " due " (due) } }
+                                }
+                                td class="py-2" { (duplicate.title) }
+                                td class="py-2 space-x-2" {
+                                    @for choice in Choice::ALL {
+                                        label {
+                                            input type="radio" name={ "choice-" (index) } value=(choice.value())
+                                                checked[choice == Choice::default()];
+                                            " " (choice.label())
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            div class="space-x-4" {
+                button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" { "Import" }
+                a class="text-blue-500 hover:text-blue-700" href=(routes::Settings::url()) { "Cancel" }
+            }
+        }
+    }
+}
+
+// === Routes ===
+#[derive(Deserialize)]
+pub struct StartImport {
+    json: String,
+}
+pub async fn start(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    Form(StartImport { json }): Form<StartImport>,
+) -> Result<Response, AppError> {
+    let todos = parse_export(&json)?;
+    let mut token = [0; 16];
+    rand::thread_rng().fill_bytes(&mut token);
+    let token = hex::encode(token);
+    let guard = state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    db.insert_with_ttl(
+        pending_key(&token),
+        &PendingImport { todos },
+        PENDING_LIFETIME,
+    )?;
+    Ok(Redirect::to(&routes::ImportReview::url(&token)).into_response())
+}
+
+pub async fn review(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    Path(token): Path<String>,
+) -> Result<Markup, AppError> {
+    let (existing, pending) = {
+        let db = state.read().await.for_tenant(tenant.id())?;
+        let pending = db
+            .get::<PendingImport, _>(pending_key(&token))?
+            .ok_or(AppError::NotFound)?;
+        (
+            TodoQuery::new()
+                .including_archived()
+                .including_someday()
+                .list(&db)?,
+            pending,
+        )
+    };
+    Ok(views::page(
+        "Import",
+        html! {
+            (nav::navigation(&Nav::settings()))
+            h1 class="text-4xl text-center text-gray-700 mb-6" { "Import" }
+            (review_html(&token, &existing, &pending))
+        },
+    ))
+}
+
+// Apply the reviewed import in a single batch, so it lands completely or not at all.
+pub async fn commit(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    Path(token): Path<String>,
+    Form(form): Form<HashMap<String, String>>,
+) -> Result<Response, AppError> {
+    let choices: HashMap<usize, Choice> = form
+        .iter()
+        .filter_map(|(name, value)| {
+            let index = name.strip_prefix("choice-")?.parse().ok()?;
+            Some((index, Choice::parse(value)?))
+        })
+        .collect();
+    let writes = state.writes.clone();
+    let guard = state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    let pending = db
+        .get::<PendingImport, _>(pending_key(&token))?
+        .ok_or(AppError::NotFound)?;
+    let existing = TodoQuery::new()
+        .including_archived()
+        .including_someday()
+        .list(&db)?;
+    let mut ops = plan(&db, &existing, &pending, &choices)?;
+    ops.push(WriteOp::Remove {
+        key: pending_key(&token),
+    });
+    writes.submit(&db, ops).await?;
+    Ok(Redirect::to(&routes::Root::url()).into_response())
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use time::macros::date;
+
+    use super::*;
+    use crate::fixtures::TestDb;
+
+    #[test]
+    fn test_find_duplicate() {
+        let mut milk = Todo::new(1, "Buy milk for the week".into());
+        milk.due = Some(date!(2024 - 05 - 01));
+        let existing = vec![milk];
+
+        let mut typo = Todo::new(9, "buy  mlk for the week!".into());
+        typo.due = Some(date!(2024 - 05 - 01));
+        assert_eq!(
+            find_duplicate(&existing, &typo).map(|todo| todo.id),
+            Some(1)
+        );
+
+        // a different day is a different todo
+        typo.due = None;
+        assert!(find_duplicate(&existing, &typo).is_none());
+        // short titles have to match exactly
+        assert!(!similar_titles("cat", "car"));
+    }
+
+    #[test]
+    fn test_plan_follows_choices() -> Result<()> {
+        let db = TestDb::new("import")?;
+        let existing = vec![Todo::new(db.next_id()?, "Call mom".into())];
+        let mut done = Todo::new(1, "call mom".into());
+        done.set_completed(true);
+        let pending = PendingImport {
+            todos: vec![done, Todo::new(2, "Water plants".into())],
+        };
+
+        let skipped = plan(&db, &existing, &pending, &HashMap::new())?;
+        assert_eq!(skipped.len(), 1);
+
+        db.apply_batch(plan(
+            &db,
+            &existing,
+            &pending,
+            &HashMap::from([(0, Choice::Merge)]),
+        )?)?;
+        let merged = db
+            .get::<Todo, _>(format!("todo:{}", existing[0].id))?
+            .unwrap();
+        assert!(merged.completed);
+        assert_eq!(TodoQuery::new().list(&db)?.len(), 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,288 @@
+use std::{fmt, time::Duration};
+
+use anyhow::Result;
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{header, HeaderMap, HeaderName, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use serde_json::json;
+use sha2::Sha256;
+
+use crate::{
+    api::v1::TodoResource,
+    auth,
+    db::driver::Db,
+    error::{AppError, ErrorReport},
+    routes,
+    services::todo::{NewTodo, TodoService},
+    state::AppState,
+    tenant::Tenant,
+};
+
+// A sender signs `{timestamp}.{nonce}.{body}` with `INBOUND_WEBHOOK_SECRET` and sends the hex
+// hmac-sha256 along with the unix timestamp and a nonce of its choosing.
+pub const TIMESTAMP_HEADER: HeaderName = HeaderName::from_static("x-webhook-timestamp");
+pub const NONCE_HEADER: HeaderName = HeaderName::from_static("x-webhook-nonce");
+pub const SIGNATURE_HEADER: HeaderName = HeaderName::from_static("x-webhook-signature");
+
+// nonces seen, `webhook_nonce:{nonce}` in the default tree
+const NONCE_PREFIX: &str = "webhook_nonce:";
+// how far the timestamp may be from the server's clock either way
+const MAX_SKEW: Duration = Duration::from_secs(5 * 60);
+// a nonce is remembered for as long as its timestamp is accepted, the skew check refuses it after
+const NONCE_TTL: Duration = Duration::from_secs(2 * MAX_SKEW.as_secs());
+const MAX_NONCE_LEN: usize = 128;
+
+fn nonce_key(nonce: &str) -> String {
+    format!("{}{}", NONCE_PREFIX, nonce)
+}
+
+// === Signatures ===
+pub fn signed_payload(timestamp: u64, nonce: &str, body: &[u8]) -> Vec<u8> {
+    let mut payload = format!("{}.{}.", timestamp, nonce).into_bytes();
+    payload.extend_from_slice(body);
+    payload
+}
+
+pub fn signature(secret: &str, timestamp: u64, nonce: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac accepts keys of any length");
+    mac.update(&signed_payload(timestamp, nonce, body));
+    hex::encode(mac.finalize().into_bytes())
+}
+
+// Why a request was turned away, told to the sender so it can fix its side.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rejection {
+    Missing(HeaderName),
+    Invalid(HeaderName),
+    // seconds the timestamp is off by
+    Stale(i64),
+    BadSignature,
+    Replayed,
+}
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::Missing(name) => write!(f, "the {} header is missing", name),
+            Rejection::Invalid(name) => write!(f, "the {} header is not valid", name),
+            Rejection::Stale(skew) => write!(
+                f,
+                "the timestamp is {}s off the server's clock, at most {}s are accepted",
+                skew,
+                MAX_SKEW.as_secs()
+            ),
+            Rejection::BadSignature => write!(f, "the signature does not match the body"),
+            Rejection::Replayed => write!(f, "this nonce was used already"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Signed {
+    timestamp: u64,
+    nonce: String,
+    signature: String,
+}
+
+fn header(headers: &HeaderMap, name: &HeaderName) -> Result<String, Rejection> {
+    let value = headers
+        .get(name)
+        .ok_or_else(|| Rejection::Missing(name.clone()))?;
+    let value = value
+        .to_str()
+        .map_err(|_| Rejection::Invalid(name.clone()))?;
+    Ok(value.trim().to_string())
+}
+
+fn signed(headers: &HeaderMap) -> Result<Signed, Rejection> {
+    let timestamp = header(headers, &TIMESTAMP_HEADER)?
+        .parse()
+        .map_err(|_| Rejection::Invalid(TIMESTAMP_HEADER))?;
+    let nonce = header(headers, &NONCE_HEADER)?;
+    if nonce.is_empty() || nonce.len() > MAX_NONCE_LEN {
+        return Err(Rejection::Invalid(NONCE_HEADER));
+    }
+    Ok(Signed {
+        timestamp,
+        nonce,
+        signature: header(headers, &SIGNATURE_HEADER)?.to_ascii_lowercase(),
+    })
+}
+
+// Check a request without remembering its nonce, for `verify` and the debugging endpoint.
+fn check(
+    db: &Db,
+    secret: &str,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<Result<Signed, Rejection>> {
+    let signed = match signed(headers) {
+        Ok(signed) => signed,
+        Err(rejection) => return Ok(Err(rejection)),
+    };
+    let now = (db.clock().now_millis() / 1000) as i64;
+    let skew = signed.timestamp as i64 - now;
+    if skew.unsigned_abs() > MAX_SKEW.as_secs() {
+        return Ok(Err(Rejection::Stale(skew)));
+    }
+    let expected = signature(secret, signed.timestamp, &signed.nonce, body);
+    if !auth::constant_time_eq(expected.as_bytes(), signed.signature.as_bytes()) {
+        return Ok(Err(Rejection::BadSignature));
+    }
+    if db.get_payload(nonce_key(&signed.nonce))?.is_some() {
+        return Ok(Err(Rejection::Replayed));
+    }
+    Ok(Ok(signed))
+}
+
+// Accept a signed request once, its nonce is kept until the timestamp would be refused anyway.
+pub fn verify(
+    db: &Db,
+    secret: &str,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<Result<(), Rejection>> {
+    let signed = match check(db, secret, headers, body)? {
+        Ok(signed) => signed,
+        Err(rejection) => return Ok(Err(rejection)),
+    };
+    db.insert_with_ttl(nonce_key(&signed.nonce), &signed.timestamp, NONCE_TTL)?;
+    Ok(Ok(()))
+}
+
+fn secret(state: &AppState) -> Result<&str, AppError> {
+    state
+        .config
+        .inbound_webhook_secret
+        .as_deref()
+        .ok_or(AppError::NotFound)
+}
+
+// === Routes ===
+#[derive(Debug, Deserialize)]
+struct QuickCreate {
+    // quick-add text, `#tags` included
+    title: String,
+    due: Option<String>,
+}
+
+// Create a todo from another service, in the workspace of the host it was sent to.
+pub async fn create_todo(
+    State(mut app_state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, AppError> {
+    let state = app_state.clone();
+    let secret = secret(&state)?;
+    let guard = app_state.write().await;
+    if let Err(rejection) = verify(&guard, secret, &headers, &body)? {
+        tracing::warn!(%rejection, "refused an inbound webhook");
+        return Ok(
+            ErrorReport::new(StatusCode::UNAUTHORIZED, rejection.to_string()).into_response(),
+        );
+    }
+    let QuickCreate { title, due } =
+        serde_json::from_slice(&body).map_err(|err| AppError::Invalid(err.to_string()))?;
+    let new = NewTodo::parse(&title, due.as_deref().unwrap_or_default(), "", "")?;
+    let db = guard.for_tenant(tenant.id())?;
+    let todo = TodoService::new(&state, &db, tenant.id())
+        .create(new)
+        .await?;
+    Ok((
+        StatusCode::CREATED,
+        [(header::LOCATION, routes::ApiTodo::url(todo.id))],
+        Json(TodoResource::from(&todo)),
+    )
+        .into_response())
+}
+
+// With `DEV_MODE`, what the server makes of a request signed for `/hooks/todos`: the payload it
+// signs and the signature it expects. Nothing is created and the nonce stays unused.
+pub async fn debug_signature(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<serde_json::Value>, AppError> {
+    if !state.config.dev_mode {
+        return Err(AppError::NotFound);
+    }
+    let secret = secret(&state)?;
+    let db = state.read().await;
+    let given = signed(&headers);
+    let (timestamp, nonce) = match &given {
+        Ok(signed) => (signed.timestamp, signed.nonce.as_str()),
+        Err(_) => (0, ""),
+    };
+    let verdict = match check(&db, secret, &headers, &body)? {
+        Ok(_) => "accepted".to_string(),
+        Err(rejection) => rejection.to_string(),
+    };
+    Ok(Json(json!({
+        "verdict": verdict,
+        "server_time": db.clock().now_millis() / 1000,
+        "signed_payload": String::from_utf8_lossy(&signed_payload(timestamp, nonce, &body)),
+        "expected_signature": signature(secret, timestamp, nonce, &body),
+        "given_signature": given.ok().map(|signed| signed.signature),
+    })))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+    use crate::fixtures::TestDb;
+
+    fn signed_headers(secret: &str, timestamp: u64, nonce: &str, body: &[u8]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(TIMESTAMP_HEADER, HeaderValue::from(timestamp));
+        headers.insert(NONCE_HEADER, HeaderValue::from_str(nonce).unwrap());
+        headers.insert(
+            SIGNATURE_HEADER,
+            HeaderValue::from_str(&signature(secret, timestamp, nonce, body)).unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn test_verify_refuses_replays() -> Result<()> {
+        let db = TestDb::new("inbound")?;
+        let now = db.clock().now_millis() / 1000;
+        let body = br#"{"title":"Call back"}"#;
+        let headers = signed_headers("secret", now, "n1", body);
+        assert_eq!(verify(&db, "secret", &headers, body)?, Ok(()));
+        // the captured request sent again
+        assert_eq!(
+            verify(&db, "secret", &headers, body)?,
+            Err(Rejection::Replayed)
+        );
+
+        let headers = signed_headers("secret", now, "n2", body);
+        assert_eq!(
+            verify(&db, "secret", &headers, br#"{"title":"Changed"}"#)?,
+            Err(Rejection::BadSignature)
+        );
+        assert_eq!(
+            verify(&db, "other", &headers, body)?,
+            Err(Rejection::BadSignature)
+        );
+        let old = signed_headers("secret", now - 600, "n3", body);
+        assert_eq!(
+            verify(&db, "secret", &old, body)?,
+            Err(Rejection::Stale(-600))
+        );
+        assert_eq!(
+            verify(&db, "secret", &HeaderMap::new(), body)?,
+            Err(Rejection::Missing(TIMESTAMP_HEADER))
+        );
+        Ok(())
+    }
+}
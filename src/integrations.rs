@@ -0,0 +1,611 @@
+use anyhow::Result;
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+};
+use maud::{html, Markup};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use time::{Date, OffsetDateTime};
+
+use crate::{
+    db::{driver::Db, queue::WriteOp},
+    domain::events::DomainEvent,
+    error::AppError,
+    extract::FormOrJson,
+    locale::Formatter,
+    method_override,
+    models::Todo,
+    repository::query::TodoQuery,
+    routes,
+    state::AppState,
+    tasks::{self, TaskKind},
+    tenant::Tenant,
+    views::{self, meta},
+    webhooks,
+};
+
+// the settings of a list, `list_settings:{list}` in the tree of its workspace
+const SETTINGS_PREFIX: &str = "list_settings:";
+// how the last delivery went, `list_delivery:{list}:{integration}`
+const DELIVERY_PREFIX: &str = "list_delivery:";
+// calendar feeds, `calendar_feed:{token}` in the root tree since the token picks the workspace
+const FEED_PREFIX: &str = "calendar_feed:";
+// A workspace holds one list so far, every setting is stored for it. The keys already carry the
+// list, so more lists per workspace only need to pass theirs.
+pub const MAIN_LIST: &str = "main";
+// carries delivery badges to the settings pages of the workspace
+pub const STATUS_EVENT: &str = "integration-status";
+const SECTION_ID: &str = "integrations";
+
+// === Settings ===
+// Outbound deliveries of a list's changes, run by the task queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Integration {
+    // the json of `webhooks::payload`
+    Webhook,
+    // a line of text to a Slack incoming webhook, which posts to the channel it was made for
+    Slack,
+}
+impl Integration {
+    pub const ALL: [Integration; 2] = [Integration::Webhook, Integration::Slack];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Integration::Webhook => "webhook",
+            Integration::Slack => "slack",
+        }
+    }
+    pub fn label(self) -> &'static str {
+        match self {
+            Integration::Webhook => "Webhook",
+            Integration::Slack => "Slack",
+        }
+    }
+}
+impl std::fmt::Display for Integration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListSettings {
+    // lists without one deliver to `WEBHOOK_URL`, when set
+    pub webhook_url: Option<String>,
+    pub slack_webhook_url: Option<String>,
+    // the secret in the url of the calendar feed, `None` while the feed is off
+    pub calendar_token: Option<String>,
+}
+impl ListSettings {
+    pub fn url(&self, integration: Integration) -> Option<&str> {
+        match integration {
+            Integration::Webhook => self.webhook_url.as_deref(),
+            Integration::Slack => self.slack_webhook_url.as_deref(),
+        }
+    }
+}
+
+fn settings_key(list: &str) -> String {
+    format!("{}{}", SETTINGS_PREFIX, list)
+}
+pub fn get(db: &Db, list: &str) -> Result<ListSettings> {
+    Ok(db.get(settings_key(list))?.unwrap_or_default())
+}
+pub fn set(db: &Db, list: &str, settings: &ListSettings) -> Result<()> {
+    db.insert(settings_key(list), settings)
+}
+
+// `https://…` as typed, blank for none
+fn parse_url(value: &str) -> Result<Option<String>, AppError> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(None);
+    }
+    match reqwest::Url::parse(value) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => Ok(Some(value.to_string())),
+        _ => Err(AppError::Invalid(format!(
+            "`{}` is not an http(s) url",
+            value
+        ))),
+    }
+}
+
+// === Deliveries ===
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Delivery {
+    // unix seconds
+    pub at: u64,
+    // why it failed, `None` when it got through
+    pub error: Option<String>,
+}
+impl Delivery {
+    pub fn of(result: &Result<()>, at: u64) -> Self {
+        Self {
+            at,
+            error: result.as_ref().err().map(|err| err.to_string()),
+        }
+    }
+}
+
+fn delivery_key(list: &str, integration: Integration) -> String {
+    format!("{}{}:{}", DELIVERY_PREFIX, list, integration)
+}
+pub fn delivery(db: &Db, list: &str, integration: Integration) -> Result<Option<Delivery>> {
+    db.get(delivery_key(list, integration))
+}
+// the write recording `delivery`, in the batch finishing the attempt
+pub fn delivery_op(
+    db: &Db,
+    list: &str,
+    integration: Integration,
+    delivery: &Delivery,
+) -> Result<WriteOp> {
+    Ok(WriteOp::Insert {
+        key: delivery_key(list, integration),
+        value: db.encode(delivery)?,
+    })
+}
+
+// What `integration` is sent for `event`, nothing for events that are not about a todo.
+pub fn body(integration: Integration, event: &DomainEvent, tenant: Option<&str>) -> Option<String> {
+    match integration {
+        Integration::Webhook => webhooks::payload(event, tenant),
+        Integration::Slack => {
+            let todo = event.todo()?;
+            let text = match event {
+                DomainEvent::TodoCreated(_) => format!("New todo: {}", todo.title),
+                DomainEvent::TodoCompleted(_) => format!("Done: {}", todo.title),
+                DomainEvent::TodoCancelled(_) => {
+                    match todo
+                        .resolution
+                        .as_ref()
+                        .and_then(|resolution| resolution.reason())
+                    {
+                        Some(reason) => format!("Cancelled: {} ({})", todo.title, reason),
+                        None => format!("Cancelled: {}", todo.title),
+                    }
+                }
+                DomainEvent::TodoReopened(_) => format!("Reopened: {}", todo.title),
+                DomainEvent::TodoRemoved(_) => format!("Removed: {}", todo.title),
+                DomainEvent::TodoMerged { merged, .. } => {
+                    format!("Merged: {} into {}", merged.title, todo.title)
+                }
+                _ => format!("Changed: {}", todo.title),
+            };
+            Some(json!({ "text": text }).to_string())
+        }
+    }
+}
+
+fn test_body(integration: Integration, tenant: Option<&str>) -> String {
+    match integration {
+        Integration::Webhook => json!({ "event": "test", "tenant": tenant }).to_string(),
+        Integration::Slack => json!({ "text": "Test from your todo list." }).to_string(),
+    }
+}
+
+// The deliveries of `event` to the integrations of the list, to go into the batch of the change.
+// The url is looked up when the task runs, so a changed setting applies to what is still queued.
+pub fn ops(
+    state: &AppState,
+    db: &Db,
+    tenant: Option<&str>,
+    event: &DomainEvent,
+) -> Result<Vec<WriteOp>> {
+    let settings = get(db, MAIN_LIST)?;
+    let mut ops = Vec::new();
+    if settings.webhook_url.is_none() {
+        ops.extend(webhooks::ops(state, db, tenant, event)?);
+    }
+    for integration in Integration::ALL {
+        let Some(body) = settings
+            .url(integration)
+            .and_then(|_| body(integration, event, tenant))
+        else {
+            continue;
+        };
+        ops.push(tasks::enqueue_op(
+            db,
+            TaskKind::Integration {
+                tenant: tenant.map(str::to_string),
+                list: MAIN_LIST.to_string(),
+                integration,
+                body,
+            },
+        )?);
+    }
+    Ok(ops)
+}
+
+// === Calendar feed ===
+// Open todos with a due date as all-day events, for calendar apps subscribing to the feed url.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Feed {
+    tenant: Option<String>,
+}
+
+fn feed_key(token: &str) -> String {
+    format!("{}{}", FEED_PREFIX, token)
+}
+
+fn ics_date(date: Date) -> String {
+    format!(
+        "{:04}{:02}{:02}",
+        date.year(),
+        date.month() as u8,
+        date.day()
+    )
+}
+
+fn ics_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+pub fn calendar_ics(todos: &[Todo], now: OffsetDateTime) -> String {
+    let now = now.to_offset(time::UtcOffset::UTC);
+    let stamp = format!(
+        "{}T{:02}{:02}{:02}Z",
+        ics_date(now.date()),
+        now.hour(),
+        now.minute(),
+        now.second()
+    );
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//rust-htmx//todos//EN".to_string(),
+    ];
+    for todo in todos.iter().filter(|todo| !todo.completed) {
+        let Some(due) = todo.due else {
+            continue;
+        };
+        lines.extend([
+            "BEGIN:VEVENT".to_string(),
+            format!("UID:todo-{}@rust-htmx", todo.id),
+            format!("DTSTAMP:{}", stamp),
+            format!("DTSTART;VALUE=DATE:{}", ics_date(due)),
+            format!("SUMMARY:{}", ics_text(&todo.title)),
+            "END:VEVENT".to_string(),
+        ]);
+    }
+    lines.push("END:VCALENDAR".to_string());
+    lines.join("\r\n") + "\r\n"
+}
+
+// === Components ===
+fn badge_id(integration: Integration) -> String {
+    format!("integration-{}-status", integration)
+}
+
+// how the last delivery went, replaced out of band when the next one is done
+pub fn badge_html(
+    integration: Integration,
+    delivery: Option<&Delivery>,
+    dates: &Formatter,
+    oob: bool,
+) -> Markup {
+    html! {
+        span id=(badge_id(integration)) hx-swap-oob=[oob.then_some("true")] {
+            @match delivery {
+                None => span class="text-xs rounded-full bg-gray-200 text-gray-600 py-1 px-2" { "Nothing sent yet" },
+                Some(Delivery { at, error: None }) => span class="text-xs rounded-full bg-green-100 text-green-700 py-1 px-2" {
+                    "Delivered " (dates.ago(*at))
+                },
+                Some(Delivery { at, error: Some(error) }) => span class="text-xs rounded-full bg-red-100 text-red-700 py-1 px-2" title=(error) {
+                    "Failed " (dates.ago(*at))
+                },
+            }
+        }
+    }
+}
+
+fn queued_badge_html(integration: Integration) -> Markup {
+    html! {
+        span id=(badge_id(integration)) hx-swap-oob="true" {
+            span class="text-xs rounded-full bg-gray-200 text-gray-600 py-1 px-2" { "Test queued…" }
+        }
+    }
+}
+
+// what the settings section shows
+pub struct Overview {
+    pub settings: ListSettings,
+    pub deliveries: Vec<(Integration, Option<Delivery>)>,
+    pub dates: Formatter,
+}
+impl Overview {
+    pub fn load(db: &Db) -> Result<Self> {
+        let deliveries = Integration::ALL
+            .into_iter()
+            .map(|integration| Ok((integration, delivery(db, MAIN_LIST, integration)?)))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            settings: get(db, MAIN_LIST)?,
+            deliveries,
+            dates: Formatter::load(db)?,
+        })
+    }
+}
+
+pub fn settings_html(public_url: &str, overview: &Overview) -> Markup {
+    let settings = &overview.settings;
+    html! {
+        section id=(SECTION_ID) class="bg-white rounded-lg shadow-lg p-6 space-y-4" {
+            h2 class="text-2xl text-gray-700" { "Integrations" }
+            p class="text-gray-600" { "Where changes to this list are sent." }
+            // delivery badges as the task queue finishes them
+            div class="hidden" sse-swap=(STATUS_EVENT) hx-swap="none" {}
+            form class="space-y-2" method="post" action=(routes::Integrations::url())
+                hx-post=(routes::Integrations::url()) hx-target={ "#" (SECTION_ID) } hx-swap="outerHTML" {
+                label class="block" {
+                    span class="text-gray-700" { "Webhook url" }
+                    input class="w-full rounded p-2 border" type="url" name="webhook_url"
+                        value=(settings.webhook_url.as_deref().unwrap_or_default()) placeholder="https://example.com/hook";
+                }
+                label class="block" {
+                    span class="text-gray-700" { "Slack incoming webhook" }
+                    input class="w-full rounded p-2 border" type="url" name="slack_webhook_url"
+                        value=(settings.slack_webhook_url.as_deref().unwrap_or_default()) placeholder="https://hooks.slack.com/services/…";
+                }
+                button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" { "Save" }
+            }
+            ul class="space-y-2" {
+                @for (integration, delivery) in &overview.deliveries {
+                    li class="flex items-center space-x-2" {
+                        span class="flex-grow text-gray-700" { (integration.label()) }
+                        (badge_html(*integration, delivery.as_ref(), &overview.dates, false))
+                        @if settings.url(*integration).is_some() {
+                            form method="post" action=(routes::IntegrationTest::url(integration)) {
+                                button class="text-sm text-blue-500 hover:text-blue-700" type="submit"
+                                    hx-post=(routes::IntegrationTest::url(integration)) hx-swap="none" { "Send test" }
+                            }
+                        }
+                    }
+                }
+            }
+            h3 class="text-xl text-gray-700" { "Calendar feed" }
+            @if let Some(token) = &settings.calendar_token {
+                @let url = meta::absolute(public_url, &routes::CalendarFeed::url(token));
+                p class="text-gray-600" { "Subscribe to this url in your calendar app to see due todos:" }
+                input class="w-full rounded p-2 border font-mono text-sm" type="text" readonly value=(url) onclick="this.select()";
+                div class="flex space-x-2" {
+                    form method="post" action=(routes::CalendarFeedSetting::url())
+                        hx-post=(routes::CalendarFeedSetting::url()) hx-target={ "#" (SECTION_ID) } hx-swap="outerHTML" {
+                        button class="text-sm text-blue-500 hover:text-blue-700" type="submit" { "New link" }
+                    }
+                    form method="post" action=(routes::CalendarFeedSetting::url())
+                        hx-delete=(routes::CalendarFeedSetting::url()) hx-target={ "#" (SECTION_ID) } hx-swap="outerHTML" {
+                        input type="hidden" name=(method_override::METHOD_FIELD) value="DELETE";
+                        button class="text-sm text-red-500 hover:text-red-700" type="submit" { "Turn off" }
+                    }
+                }
+            } @else {
+                form method="post" action=(routes::CalendarFeedSetting::url())
+                    hx-post=(routes::CalendarFeedSetting::url()) hx-target={ "#" (SECTION_ID) } hx-swap="outerHTML" {
+                    button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" { "Create feed link" }
+                }
+            }
+        }
+    }
+}
+
+// === Routes ===
+fn section_response(
+    state: &AppState,
+    db: &Db,
+    headers: &HeaderMap,
+    notice: Option<&str>,
+) -> Result<Response, AppError> {
+    let fragment = html! {
+        (settings_html(&state.config.public_url, &Overview::load(db)?))
+        @if let Some(notice) = notice {
+            (views::notice_toast_oob(notice))
+        }
+    };
+    Ok(views::fragment_or_redirect(
+        headers,
+        fragment,
+        &routes::Settings::url(),
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct SaveIntegrations {
+    #[serde(default)]
+    webhook_url: String,
+    #[serde(default)]
+    slack_webhook_url: String,
+}
+pub async fn save(
+    State(mut app_state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    FormOrJson(form): FormOrJson<SaveIntegrations>,
+) -> Result<Response, AppError> {
+    let webhook_url = parse_url(&form.webhook_url)?;
+    let slack_webhook_url = parse_url(&form.slack_webhook_url)?;
+    let state = app_state.clone();
+    let guard = app_state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    let settings = ListSettings {
+        webhook_url,
+        slack_webhook_url,
+        ..get(&db, MAIN_LIST)?
+    };
+    set(&db, MAIN_LIST, &settings)?;
+    section_response(&state, &db, &headers, Some("Integrations saved."))
+}
+
+// Queue a test delivery to `integration`, its badge tells how it went.
+pub async fn test_fire(
+    State(mut app_state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Path(integration): Path<Integration>,
+) -> Result<Response, AppError> {
+    let state = app_state.clone();
+    let guard = app_state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    if get(&db, MAIN_LIST)?.url(integration).is_none() {
+        return Err(AppError::Invalid(format!(
+            "Set up the {} first.",
+            integration.label()
+        )));
+    }
+    state.tasks.enqueue(
+        &db,
+        TaskKind::Integration {
+            tenant: tenant.id().map(str::to_string),
+            list: MAIN_LIST.to_string(),
+            integration,
+            body: test_body(integration, tenant.id()),
+        },
+    )?;
+    let fragment = html! {
+        (queued_badge_html(integration))
+        (views::notice_toast_oob(&format!("A test is on its way to the {}.", integration.label())))
+    };
+    Ok(views::fragment_or_redirect(
+        &headers,
+        fragment,
+        &routes::Settings::url(),
+    ))
+}
+
+// a new feed link, the one before stops working
+pub async fn enable_calendar(
+    State(mut app_state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let state = app_state.clone();
+    let root = app_state.write().await;
+    let db = root.for_tenant(tenant.id())?;
+    let mut settings = get(&db, MAIN_LIST)?;
+    if let Some(token) = &settings.calendar_token {
+        root.remove(feed_key(token))?;
+    }
+    let mut token = [0; 24];
+    rand::thread_rng().fill_bytes(&mut token);
+    let token = hex::encode(token);
+    root.insert(
+        feed_key(&token),
+        &Feed {
+            tenant: tenant.id().map(str::to_string),
+        },
+    )?;
+    settings.calendar_token = Some(token);
+    set(&db, MAIN_LIST, &settings)?;
+    section_response(&state, &db, &headers, None)
+}
+
+pub async fn disable_calendar(
+    State(mut app_state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let state = app_state.clone();
+    let root = app_state.write().await;
+    let db = root.for_tenant(tenant.id())?;
+    let mut settings = get(&db, MAIN_LIST)?;
+    if let Some(token) = settings.calendar_token.take() {
+        root.remove(feed_key(&token))?;
+    }
+    set(&db, MAIN_LIST, &settings)?;
+    section_response(&state, &db, &headers, None)
+}
+
+// the feed itself, the token in the url is all it takes
+pub async fn calendar_feed(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+) -> Result<Response, AppError> {
+    let root = state.read().await;
+    let feed = root
+        .get::<Feed, _>(feed_key(&token))?
+        .ok_or(AppError::NotFound)?;
+    let db = root.for_tenant(feed.tenant.as_deref())?;
+    let todos = TodoQuery::new().completed(false).list(&db)?;
+    Ok((
+        [(header::CONTENT_TYPE, "text/calendar; charset=utf-8")],
+        calendar_ics(&todos, db.clock().now()),
+    )
+        .into_response())
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use time::macros::{date, datetime};
+
+    use super::*;
+    use crate::fixtures::TestDb;
+
+    #[test]
+    fn test_settings_and_deliveries() -> Result<()> {
+        let db = TestDb::new("integrations")?;
+        assert_eq!(get(&db, MAIN_LIST)?, ListSettings::default());
+        let settings = ListSettings {
+            slack_webhook_url: Some("https://hooks.slack.com/services/T/B/x".into()),
+            ..ListSettings::default()
+        };
+        set(&db, MAIN_LIST, &settings)?;
+        assert_eq!(
+            get(&db, MAIN_LIST)?.url(Integration::Slack),
+            settings.slack_webhook_url.as_deref()
+        );
+        assert_eq!(get(&db, MAIN_LIST)?.url(Integration::Webhook), None);
+
+        let failed = Delivery::of(&Err(anyhow::anyhow!("webhook answered 500")), 1_700_000_000);
+        db.apply_batch([delivery_op(&db, MAIN_LIST, Integration::Slack, &failed)?])?;
+        assert_eq!(delivery(&db, MAIN_LIST, Integration::Slack)?, Some(failed));
+        assert_eq!(delivery(&db, MAIN_LIST, Integration::Webhook)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_url() {
+        assert_eq!(parse_url(" ").unwrap(), None);
+        assert_eq!(
+            parse_url("https://example.com/hook").unwrap().as_deref(),
+            Some("https://example.com/hook")
+        );
+        assert!(parse_url("ftp://example.com").is_err());
+        assert!(parse_url("example.com").is_err());
+    }
+
+    #[test]
+    fn test_slack_body() -> Result<()> {
+        let mut todo = Todo::new(7, "Water the plants".into());
+        todo.set_completed(true);
+        let event = DomainEvent::toggled(todo);
+        let sent: serde_json::Value =
+            serde_json::from_str(&body(Integration::Slack, &event, None).unwrap())?;
+        assert_eq!(sent["text"], "Done: Water the plants");
+        let shared = DomainEvent::ListShared {
+            token: "abc".into(),
+        };
+        assert_eq!(body(Integration::Slack, &shared, None), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_calendar_ics() {
+        let mut due = Todo::new(1, "Call Ada, then Bob".into());
+        due.due = Some(date!(2024 - 03 - 06));
+        let undated = Todo::new(2, "Someday".into());
+        let ics = calendar_ics(&[due, undated], datetime!(2024-03-01 12:30:00 UTC));
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ics.contains("DTSTART;VALUE=DATE:20240306\r\n"));
+        assert!(ics.contains("DTSTAMP:20240301T123000Z\r\n"));
+        assert!(ics.contains("SUMMARY:Call Ada\\, then Bob\r\n"));
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 1);
+    }
+}
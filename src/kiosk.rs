@@ -0,0 +1,454 @@
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    response::Response,
+    Form,
+};
+use maud::{html, Markup};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use time::Date;
+
+use crate::{
+    auth::policy::{self, Action, Capability, Role},
+    db::driver::Db,
+    domain::{self, events::DomainEvent},
+    error::AppError,
+    method_override,
+    models::{Status, Todo},
+    repository::query::TodoQuery,
+    routes, seo,
+    services::todo::{NewTodo, TodoService},
+    state::AppState,
+    tenant::Tenant,
+    views,
+};
+
+// kiosk links, `kiosk:{token}` in the root tree since the token picks the workspace
+const KIOSK_PREFIX: &str = "kiosk:";
+// what a link allows besides looking, `kiosk_capability:{token}`, absent for view-only links
+const CAPABILITY_PREFIX: &str = "kiosk_capability:";
+const ADD_FORM_ID: &str = "kiosk-add";
+// how long a new link works, in days, 0 until it is revoked
+const LIFETIMES: [(u64, &str); 4] = [
+    (0, "Until revoked"),
+    (1, "For a day"),
+    (7, "For a week"),
+    (30, "For a month"),
+];
+
+// A link that shows a workspace's day on a wall display without signing in.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Kiosk {
+    pub token: String,
+    pub tenant: Option<String>,
+    pub created_at: u64,
+}
+
+fn kiosk_key(token: &str) -> String {
+    format!("{}{}", KIOSK_PREFIX, token)
+}
+
+fn capability_key(token: &str) -> String {
+    format!("{}{}", CAPABILITY_PREFIX, token)
+}
+
+// A link for `tenant`, swept with its capability once `lifetime` has passed.
+pub fn create(
+    db: &Db,
+    tenant: Option<&str>,
+    capability: Capability,
+    lifetime: Option<Duration>,
+) -> anyhow::Result<Kiosk> {
+    let mut token = [0; 24];
+    rand::thread_rng().fill_bytes(&mut token);
+    let kiosk = Kiosk {
+        token: hex::encode(token),
+        tenant: tenant.map(str::to_string),
+        created_at: db.clock().now_millis() / 1000,
+    };
+    store(db, &kiosk_key(&kiosk.token), &kiosk, lifetime)?;
+    if capability != Capability::View {
+        store(db, &capability_key(&kiosk.token), &capability, lifetime)?;
+    }
+    Ok(kiosk)
+}
+
+fn store<T: Serialize>(
+    db: &Db,
+    key: &str,
+    value: &T,
+    lifetime: Option<Duration>,
+) -> anyhow::Result<()> {
+    match lifetime {
+        Some(lifetime) => db.insert_with_ttl(key, value, lifetime),
+        None => db.insert(key, value),
+    }
+}
+
+// what the link `token` allows, links from before capabilities only show
+pub fn capability(db: &Db, token: &str) -> anyhow::Result<Capability> {
+    Ok(db
+        .get::<Capability, _>(capability_key(token))?
+        .unwrap_or_default())
+}
+
+// the token of a kiosk path, for the policy
+pub fn token_of(path: &str) -> Option<&str> {
+    let rest = path.strip_prefix("/kiosk/")?;
+    rest.split('/').next().filter(|token| !token.is_empty())
+}
+
+// the workspace's kiosk links
+pub fn list(db: &Db, tenant: Option<&str>) -> anyhow::Result<Vec<Kiosk>> {
+    let mut kiosks = Vec::new();
+    for item in db.iter_prefix::<Kiosk>(KIOSK_PREFIX)? {
+        let (_, kiosk) = item?;
+        if kiosk.tenant.as_deref() == tenant {
+            kiosks.push(kiosk);
+        }
+    }
+    Ok(kiosks)
+}
+
+// === Panels ===
+// what the display rotates through
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Panel {
+    Today,
+    Overdue,
+    InProgress,
+}
+impl Panel {
+    const ALL: [Panel; 3] = [Panel::Today, Panel::Overdue, Panel::InProgress];
+
+    fn title(self) -> &'static str {
+        match self {
+            Panel::Today => "Today",
+            Panel::Overdue => "Overdue",
+            Panel::InProgress => "In Progress",
+        }
+    }
+
+    fn shows(self, todo: &Todo, today: Date) -> bool {
+        match self {
+            Panel::Today => todo.due == Some(today),
+            Panel::Overdue => !todo.completed && todo.due.is_some_and(|due| due < today),
+            Panel::InProgress => todo.status == Status::InProgress,
+        }
+    }
+}
+
+// === Components ===
+// With a link that may tick items off, every item is a button doing that.
+fn item_html(token: &str, todo: &Todo, capability: Capability) -> Markup {
+    let class = if todo.completed {
+        "line-through text-gray-400"
+    } else {
+        "text-gray-700"
+    };
+    html! {
+        li id={ "kiosk-todo-" (todo.id) } class={ "text-5xl " (class) } {
+            @if capability == Capability::Complete {
+                button type="button" class="text-left" aria-pressed=(todo.completed)
+                    hx-post=(routes::KioskToggle::url(token, todo.id))
+                    hx-target={ "#kiosk-todo-" (todo.id) } hx-swap="outerHTML" {
+                    (todo.title)
+                }
+            } @else {
+                (todo.title)
+            }
+        }
+    }
+}
+
+// One panel, polling for the next one. Every poll is also the refresh.
+fn panel_html(
+    token: &str,
+    index: usize,
+    todos: &[Todo],
+    rotate_secs: u64,
+    capability: Capability,
+    today: Date,
+) -> Markup {
+    let panel = Panel::ALL[index % Panel::ALL.len()];
+    let next = routes::KioskPanel::url(token, (index + 1) % Panel::ALL.len());
+    html! {
+        div id="kiosk" class="min-h-screen p-12 space-y-8" hx-get=(next) hx-trigger={ "every " (rotate_secs) "s" } hx-swap="outerHTML" {
+            h1 class="text-7xl font-bold text-gray-800" { (panel.title()) }
+            ul class="space-y-4" {
+                @for todo in todos.iter().filter(|todo| panel.shows(todo, today)) {
+                    (item_html(token, todo, capability))
+                }
+            }
+        }
+    }
+}
+
+// Outside the panel, so the rotation does not swap away what is being typed.
+fn add_form_html(token: &str) -> Markup {
+    let url = routes::KioskAdd::url(token);
+    html! {
+        form id=(ADD_FORM_ID) class="fixed bottom-0 inset-x-0 p-6 bg-white shadow-lg flex space-x-4" method="post" action=(url)
+            hx-post=(url) hx-swap="none" "hx-on::after-request"="if (event.detail.successful) this.reset()" {
+            input class="flex-grow rounded border p-4 text-2xl" type="text" name="title" required
+                placeholder="Suggest a todo" aria-label="Suggest a todo";
+            button class="bg-blue-500 hover:bg-blue-700 text-white text-2xl font-bold py-2 px-6 rounded" type="submit" { "Add" }
+        }
+    }
+}
+
+pub fn settings_html(kiosks: &[(Kiosk, Capability)]) -> Markup {
+    html! {
+        section id="kiosks" class="bg-white rounded-lg shadow-lg p-6 space-y-4" {
+            h2 class="text-2xl text-gray-700" { "Kiosk displays" }
+            p class="text-gray-600" {
+                "Anyone with a kiosk link can see today's todos, without signing in. "
+                "A link can also let them tick todos off, or add new ones and nothing else."
+            }
+            ul class="space-y-2" {
+                @for (kiosk, capability) in kiosks {
+                    li class="flex items-center space-x-2" {
+                        a class="flex-grow text-blue-500 hover:text-blue-700 truncate" href=(routes::Kiosk::url(&kiosk.token)) target="_blank" {
+                            (routes::Kiosk::url(&kiosk.token))
+                        }
+                        span class="text-sm text-gray-500 whitespace-nowrap" { (capability.label()) }
+                        form method="post" action=(routes::KioskRevoke::url(&kiosk.token)) {
+                            input type="hidden" name=(method_override::METHOD_FIELD) value="DELETE";
+                            button class="text-red-500 hover:text-red-700" type="submit"
+                                hx-delete=(routes::KioskRevoke::url(&kiosk.token)) hx-target="#kiosks" hx-swap="outerHTML" { "Revoke" }
+                        }
+                    }
+                }
+            }
+            form class="flex items-center space-x-2" method="post" action=(routes::Kiosks::url())
+                hx-post=(routes::Kiosks::url()) hx-target="#kiosks" hx-swap="outerHTML" {
+                select class="rounded border p-2" name="capability" aria-label="What the link allows" {
+                    @for capability in Capability::ALL {
+                        option value=(capability.as_str()) { (capability.label()) }
+                    }
+                }
+                select class="rounded border p-2" name="days" aria-label="How long the link works" {
+                    @for (days, label) in LIFETIMES {
+                        option value=(days) { (label) }
+                    }
+                }
+                button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" { "Create kiosk link" }
+            }
+        }
+    }
+}
+
+// the workspace's links with what each allows, for the settings page
+pub fn with_capabilities(
+    db: &Db,
+    tenant: Option<&str>,
+) -> anyhow::Result<Vec<(Kiosk, Capability)>> {
+    list(db, tenant)?
+        .into_iter()
+        .map(|kiosk| {
+            let capability = capability(db, &kiosk.token)?;
+            Ok((kiosk, capability))
+        })
+        .collect()
+}
+
+async fn load(state: &AppState, token: &str) -> Result<(Kiosk, Vec<Todo>), AppError> {
+    let db = state.read().await;
+    let kiosk = db
+        .get::<Kiosk, _>(kiosk_key(token))?
+        .ok_or(AppError::NotFound)?;
+    let todos = TodoQuery::new().list(&db.for_tenant(kiosk.tenant.as_deref())?)?;
+    Ok((kiosk, todos))
+}
+
+// the link `token` if it allows `action`, `policy::enforce` turned others away already
+fn allowed(db: &Db, token: &str, action: Action) -> Result<Kiosk, AppError> {
+    let kiosk = db
+        .get::<Kiosk, _>(kiosk_key(token))?
+        .ok_or(AppError::NotFound)?;
+    if !policy::can(&Role::Viewer(capability(db, token)?), action) {
+        return Err(AppError::NotFound);
+    }
+    Ok(kiosk)
+}
+
+// === Routes ===
+pub async fn show(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+) -> Result<Markup, AppError> {
+    let (kiosk, todos) = load(&state, &token).await?;
+    let capability = capability(&*state.read().await, &token)?;
+    // a shared kiosk link unfurls with the workspace's name and progress
+    let name = seo::site_name(&*state.read().await, kiosk.tenant.as_deref())?;
+    let meta = seo::share_meta(
+        &state.config.public_url,
+        &routes::Kiosk::url(&token),
+        &name,
+        &todos,
+    );
+    let content = html! {
+        (panel_html(&token, 0, &todos, state.config.kiosk_rotate_secs, capability, state.clock.now().date()))
+        @if capability == Capability::Add {
+            (add_form_html(&token))
+        }
+    };
+    Ok(views::page_with_meta(&name, &meta, content))
+}
+
+pub async fn panel(
+    State(state): State<AppState>,
+    Path((token, index)): Path<(String, usize)>,
+) -> Result<Markup, AppError> {
+    let (_, todos) = load(&state, &token).await?;
+    let capability = capability(&*state.read().await, &token)?;
+    Ok(panel_html(
+        &token,
+        index,
+        &todos,
+        state.config.kiosk_rotate_secs,
+        capability,
+        state.clock.now().date(),
+    ))
+}
+
+pub async fn toggle(
+    State(mut app_state): State<AppState>,
+    Path((token, id)): Path<(String, u64)>,
+) -> Result<Markup, AppError> {
+    let state = app_state.clone();
+    let guard = app_state.write().await;
+    let kiosk = allowed(&guard, &token, Action::Complete)?;
+    let db = guard.for_tenant(kiosk.tenant.as_deref())?;
+    let todo = TodoService::new(&state, &db, kiosk.tenant.as_deref()).toggle(id)?;
+    Ok(item_html(&token, &todo, Capability::Complete))
+}
+
+#[derive(Deserialize)]
+pub struct AddForm {
+    title: String,
+}
+
+pub async fn add(
+    State(mut app_state): State<AppState>,
+    headers: HeaderMap,
+    Path(token): Path<String>,
+    Form(form): Form<AddForm>,
+) -> Result<Response, AppError> {
+    let state = app_state.clone();
+    let guard = app_state.write().await;
+    let kiosk = allowed(&guard, &token, Action::Add)?;
+    let db = guard.for_tenant(kiosk.tenant.as_deref())?;
+    // the title as typed, tags and places are for the people in the workspace
+    let new = NewTodo {
+        title: form.title.trim().to_string(),
+        ..NewTodo::default()
+    };
+    if new.title.is_empty() {
+        return Err(AppError::Invalid("A todo needs a title.".into()));
+    }
+    let todo = TodoService::new(&state, &db, kiosk.tenant.as_deref())
+        .create(new)
+        .await?;
+    Ok(views::fragment_or_redirect(
+        &headers,
+        views::notice_toast_oob(&format!("\"{}\" was added.", todo.title)),
+        &routes::Kiosk::url(&token),
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct LinkForm {
+    #[serde(default)]
+    capability: Capability,
+    #[serde(default)]
+    days: u64,
+}
+
+pub async fn create_link(
+    State(mut app_state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Form(form): Form<LinkForm>,
+) -> Result<Response, AppError> {
+    let state = app_state.clone();
+    let db = app_state.write().await;
+    if !LIFETIMES.iter().any(|(days, _)| *days == form.days) {
+        return Err(AppError::Invalid("Unknown link lifetime".into()));
+    }
+    let lifetime = (form.days > 0).then(|| Duration::from_secs(form.days * 24 * 60 * 60));
+    let kiosk = create(&db, tenant.id(), form.capability, lifetime)?;
+    domain::events::committed(
+        &state,
+        tenant.id(),
+        &DomainEvent::ListShared { token: kiosk.token },
+    );
+    Ok(views::fragment_or_redirect(
+        &headers,
+        settings_html(&with_capabilities(&db, tenant.id())?),
+        &routes::Settings::url(),
+    ))
+}
+
+pub async fn revoke(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Path(token): Path<String>,
+) -> Result<Response, AppError> {
+    let db = state.write().await;
+    // only the workspace a link belongs to can revoke it
+    if db
+        .get::<Kiosk, _>(kiosk_key(&token))?
+        .is_some_and(|kiosk| kiosk.tenant.as_deref() == tenant.id())
+    {
+        db.remove(kiosk_key(&token))?;
+        db.remove(capability_key(&token))?;
+    }
+    Ok(views::fragment_or_redirect(
+        &headers,
+        settings_html(&with_capabilities(&db, tenant.id())?),
+        &routes::Settings::url(),
+    ))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::TestDb;
+
+    #[test]
+    fn test_links_are_scoped_to_their_workspace() -> anyhow::Result<()> {
+        let db = TestDb::new("kiosk")?;
+        let acme = create(&db, Some("acme"), Capability::Complete, None)?;
+        let other = create(&db, None, Capability::View, None)?;
+        let listed = list(&db, Some("acme"))?;
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].token, acme.token);
+        assert_eq!(capability(&db, &acme.token)?, Capability::Complete);
+        assert_eq!(capability(&db, &other.token)?, Capability::View);
+        assert_eq!(
+            token_of(&routes::KioskAdd::url(&acme.token)),
+            Some(acme.token.as_str())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_links_expire_with_their_capability() -> anyhow::Result<()> {
+        let db = TestDb::new("kiosk_expiry")?;
+        let day = Duration::from_secs(24 * 60 * 60);
+        let kiosk = create(&db, None, Capability::Add, Some(day))?;
+        let kept = create(&db, None, Capability::Add, None)?;
+        db.clock.advance(day + Duration::from_secs(1));
+        db.sweep_expired(db.clock().now_millis())?;
+        assert!(db.get::<Kiosk, _>(kiosk_key(&kiosk.token))?.is_none());
+        assert_eq!(capability(&db, &kiosk.token)?, Capability::View);
+        assert_eq!(list(&db, None)?.len(), 1);
+        assert_eq!(capability(&db, &kept.token)?, Capability::Add);
+        Ok(())
+    }
+}
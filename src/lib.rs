@@ -0,0 +1,98 @@
+pub mod accounts;
+pub mod activity;
+pub mod admin;
+pub mod analytics;
+pub mod api;
+pub mod assets;
+pub mod assistant;
+pub mod attachments;
+pub mod audit;
+pub mod auth;
+pub mod board;
+pub mod branding;
+pub mod bundle;
+pub mod caching;
+pub mod calendar;
+pub mod cancel;
+pub mod checklist;
+pub mod cli;
+pub mod clock;
+pub mod colors;
+pub mod completed;
+pub mod config;
+pub mod daily_goal;
+pub mod db;
+pub mod diff;
+pub mod doctor;
+pub mod domain;
+pub mod editing;
+pub mod embed;
+pub mod error;
+pub mod events;
+pub mod export;
+pub mod extract;
+pub mod fields;
+pub mod filtering;
+#[cfg(any(test, feature = "fixtures"))]
+pub mod fixtures;
+pub mod geocode;
+pub mod goals;
+pub mod guest;
+pub mod history;
+pub mod import;
+pub mod inbound;
+pub mod integrations;
+pub mod kiosk;
+pub mod limits;
+pub mod lists;
+pub mod locale;
+pub mod maintenance;
+pub mod merge;
+pub mod method_override;
+pub mod migrate;
+pub mod models;
+pub mod palette;
+pub mod plan;
+pub mod presence;
+pub mod previews;
+pub mod privacy;
+pub mod pwa;
+pub mod reactions;
+pub mod recorder;
+pub mod registration;
+pub mod reload;
+pub mod repository;
+pub mod restore;
+pub mod retention;
+pub mod review;
+pub mod rollup;
+pub mod routes;
+pub mod scheduled;
+pub mod scheduler;
+pub mod search;
+pub mod seo;
+pub mod server;
+pub mod services;
+pub mod settings;
+pub mod setup;
+pub mod snapshots;
+pub mod someday;
+pub mod sorting;
+pub mod state;
+pub mod stats;
+pub mod subscriptions;
+pub mod suggest;
+pub mod sync;
+pub mod tags;
+pub mod tasks;
+pub mod telemetry;
+pub mod tenant;
+pub mod theme;
+pub mod today;
+pub mod toolbar;
+#[cfg(feature = "tray")]
+pub mod tray;
+pub mod unread;
+pub mod views;
+pub mod voice;
+pub mod webhooks;
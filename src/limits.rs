@@ -0,0 +1,23 @@
+use anyhow::anyhow;
+use axum::BoxError;
+use tower::{load_shed::error::Overloaded, timeout::error::Elapsed};
+
+use crate::error::AppError;
+
+// seconds a shed client is asked to wait before retrying
+pub const RETRY_AFTER_SECS: u64 = 1;
+
+// turn errors from the timeout and load-shed layers into regular error responses
+pub async fn handle_error(err: BoxError) -> AppError {
+    if err.is::<Overloaded>() {
+        tracing::warn!("concurrency limit reached, shedding request");
+        AppError::Unavailable {
+            retry_after: RETRY_AFTER_SECS,
+        }
+    } else if err.is::<Elapsed>() {
+        tracing::warn!("request timed out");
+        AppError::Timeout
+    } else {
+        AppError::Internal(anyhow!(err))
+    }
+}
@@ -0,0 +1,440 @@
+use std::collections::BTreeMap;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    response::Response,
+    Form,
+};
+use maud::{html, Markup};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    activity,
+    db::driver::Db,
+    domain::events::DomainEvent,
+    error::{AppError, Refused},
+    events::Events,
+    filtering::{self, ListFilter},
+    history,
+    models::Todo,
+    repository::{self, entity::Repository},
+    routes,
+    scheduler::{Job, Schedule},
+    state::AppState,
+    tags,
+    tenant::Tenant,
+    views::{self, combobox, dom},
+};
+
+// `list_policy:{list}`
+const POLICY_PREFIX: &str = "list_policy:";
+const POLICIES_ID: &str = "list-policies";
+// the longest a completed todo may wait to be archived
+const MAX_ARCHIVE_DAYS: u32 = 3650;
+// sent to the open pages of a workspace when the job archived some of its todos
+pub const AUTO_ARCHIVED_EVENT: &str = "todos-archived";
+// who auto-archived todos, in the activity log
+const ARCHIVE_ACTOR: &str = "auto-archive";
+
+// === Lists ===
+// A list is a name todos are filed under, at most one per todo, and there is one as long as a
+// todo is filed under it. Names follow the rules of tags, see `tags::normalize`.
+pub fn normalize(name: &str) -> Option<String> {
+    tags::normalize(name)
+}
+
+// the list of a form field, empty for none
+pub fn parse(value: &str) -> Result<Option<String>, AppError> {
+    if value.trim().is_empty() {
+        return Ok(None);
+    }
+    normalize(value).map(Some).ok_or_else(|| {
+        AppError::Invalid(format!(
+            "`{}` is not a list name, use a-z, 0-9, - and _",
+            value.trim()
+        ))
+    })
+}
+
+// how many todos are filed under each list
+pub fn counts(todos: &[Todo]) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for list in todos.iter().filter_map(|todo| todo.list.as_ref()) {
+        *counts.entry(list.clone()).or_default() += 1;
+    }
+    counts
+}
+
+// the todo list narrowed down to `list`
+pub fn url(list: Option<&str>) -> String {
+    ListFilter {
+        list: list.map(str::to_string),
+        ..ListFilter::default()
+    }
+    .url()
+}
+
+// === Policies ===
+// What happens to the todos of a list on its own, off until set on the settings page.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Policy {
+    // completed todos left alone this many days are archived
+    pub archive_after_days: Option<u32>,
+}
+
+fn policy_key(list: &str) -> String {
+    format!("{}{}", POLICY_PREFIX, list)
+}
+pub fn policy(db: &Db, list: &str) -> anyhow::Result<Policy> {
+    Ok(db.get(policy_key(list))?.unwrap_or_default())
+}
+pub fn set_policy(db: &Db, list: &str, policy: &Policy) -> anyhow::Result<()> {
+    if policy == &Policy::default() {
+        return db.remove(policy_key(list));
+    }
+    db.insert(policy_key(list), policy)
+}
+
+// the days of a form field, empty for off
+fn parse_days(value: &str) -> Result<Option<u32>, AppError> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(None);
+    }
+    match value.parse() {
+        Ok(days) if (1..=MAX_ARCHIVE_DAYS).contains(&days) => Ok(Some(days)),
+        _ => Err(AppError::Invalid(format!(
+            "Archive after 1 to {} days, or leave it empty.",
+            MAX_ARCHIVE_DAYS
+        ))),
+    }
+}
+
+// === Auto-archive ===
+// Archive the completed todos of every list with a policy once they were left alone long
+// enough, the time of their last change standing in for when they were completed. Each one gets
+// an entry in the activity log. Returns how many went, by list.
+pub fn archive_completed(db: &Db) -> anyhow::Result<BTreeMap<String, usize>> {
+    let now = db.clock().now_millis() / 1000;
+    let policies: BTreeMap<String, u32> = db
+        .iter_prefix::<Policy>(POLICY_PREFIX)?
+        .filter_map(|item| match item {
+            Ok((key, policy)) => Some(Ok((
+                key.trim_start_matches(POLICY_PREFIX).to_string(),
+                policy.archive_after_days?,
+            ))),
+            Err(err) => Some(Err(err)),
+        })
+        .collect::<anyhow::Result<_>>()?;
+    let todos = Repository::<Todo>::new(db);
+    let mut ops = Vec::new();
+    let mut archived = BTreeMap::new();
+    for before in repository::todo::all(db)? {
+        let Some(days) = before.list.as_ref().and_then(|list| policies.get(list)) else {
+            continue;
+        };
+        let cutoff = now.saturating_sub(u64::from(*days) * 86_400);
+        if !before.completed || before.archived || before.updated_at > cutoff {
+            continue;
+        }
+        let mut after = before.clone();
+        after.archived = true;
+        after.touch(&**db.clock());
+        // a todo under an archived tag stays as it is
+        let put = match todos.put_ops(&after) {
+            Ok(put) => put,
+            Err(err) if err.is::<Refused>() => continue,
+            Err(err) => return Err(err),
+        };
+        ops.extend(history::record_ops(db, &before)?);
+        ops.extend(put);
+        ops.extend(activity::record_ops_by(
+            db,
+            &DomainEvent::TodoArchived(after.clone()),
+            Some(ARCHIVE_ACTOR),
+        )?);
+        *archived.entry(after.list.unwrap_or_default()).or_default() += 1;
+    }
+    db.apply_batch(ops)?;
+    Ok(archived)
+}
+
+// Once a night, run `archive_completed` in every workspace and let whoever is looking know.
+pub fn archive_job(events: Events) -> Job {
+    Job::new(
+        "list_archive",
+        Schedule::Daily {
+            hour: 3,
+            minute: 40,
+        },
+        move |db| {
+            let events = events.clone();
+            Box::pin(async move {
+                let db = db.write().await;
+                archive_in(&db.for_tenant(None)?, &events, None)?;
+                for id in db.tenant_ids()? {
+                    archive_in(&db.for_tenant(Some(&id))?, &events, Some(&id))?;
+                }
+                Ok(())
+            })
+        },
+    )
+}
+
+fn archive_in(db: &Db, events: &Events, tenant: Option<&str>) -> anyhow::Result<()> {
+    let archived = archive_completed(db)?;
+    if archived.is_empty() {
+        return Ok(());
+    }
+    tracing::info!(tenant = ?tenant, lists = ?archived, "archived completed todos");
+    let total: usize = archived.values().sum();
+    let message = match archived.keys().collect::<Vec<_>>()[..] {
+        [list] => format!("{} completed todos in {} were archived.", total, list),
+        _ => format!("{} completed todos were archived.", total),
+    };
+    events.publish(
+        tenant,
+        AUTO_ARCHIVED_EVENT,
+        views::notice_toast_oob(&message).into_string(),
+    );
+    Ok(())
+}
+
+// === Components ===
+// the list of an item, leading to the todos filed with it
+pub fn chip_html(todo: &Todo) -> Markup {
+    html! {
+        @if let Some(list) = &todo.list {
+            a class="ml-2 text-xs t-muted hover:underline" href=(url(Some(list))) { "in " (list) }
+        }
+    }
+}
+
+// Move a todo to another list, loaded into the slot next to its item. The list shown goes along,
+// so a todo leaving it can be taken out of the view.
+pub fn picker_html(todo: &Todo) -> Markup {
+    let url = routes::TodoMove::url(todo.id);
+    let current = todo.list.as_deref().unwrap_or_default();
+    html! {
+        form class="flex items-center space-x-1" method="post" action=(url) hx-post=(url)
+            hx-target=(dom::selector(&dom::todo(todo.id))) hx-swap="outerHTML" hx-include=(filtering::shown_list_attr()) {
+            (combobox::combobox_html(&dom::list_picker(todo.id), "to", current, "Move to…", &routes::ListSuggestions::url(), false))
+            button class="text-xs text-blue-500 hover:text-blue-700" type="submit" { "Move" }
+        }
+    }
+}
+
+// the options of a list field for what was typed, a name nobody uses yet starts a new list
+fn suggestions_html(counts: &BTreeMap<String, usize>, typed: &str) -> Markup {
+    let typed = typed.trim();
+    let list = normalize(typed);
+    if !typed.is_empty() && list.is_none() {
+        return html! {};
+    }
+    let prefix = list.as_deref().unwrap_or_default();
+    html! {
+        @if typed.is_empty() {
+            (combobox::option_html("", html! { "No list" }))
+        }
+        @for (list, count) in tags::suggestions(counts, prefix) {
+            (combobox::option_html(list, html! { (list) span class="ml-2 text-sm text-gray-500" { (count) } }))
+        }
+        @if let Some(list) = list.filter(|list| !counts.contains_key(list)) {
+            (combobox::option_html(&list, html! { "New list " (list) }))
+        }
+    }
+}
+
+// shows what the auto-archive policies put away as a toast on the list
+pub fn archived_slot() -> Markup {
+    html! {
+        div class="hidden" sse-swap=(AUTO_ARCHIVED_EVENT) hx-swap="none" {}
+    }
+}
+
+// The policies of every list there is, in the settings.
+pub fn settings_html(lists: &[(String, Policy)]) -> Markup {
+    html! {
+        section id=(POLICIES_ID) class="bg-white rounded-lg shadow-lg p-6 space-y-4" {
+            h2 class="text-2xl text-gray-700" { "Lists" }
+            p class="text-gray-600" {
+                "Completed todos of a list can be archived on their own, a number of days after they were last changed. "
+                "Leave it empty to keep them."
+            }
+            @if lists.is_empty() {
+                p class="text-gray-500" { "No lists yet, move a todo to one to start it." }
+            }
+            ul class="space-y-2" {
+                @for (list, policy) in lists {
+                    @let url = routes::ListPolicy::url(list);
+                    li {
+                        form class="flex items-center space-x-2" method="post" action=(url) hx-post=(url)
+                            hx-target=(dom::selector(POLICIES_ID)) hx-swap="outerHTML" {
+                            span class="flex-grow text-gray-700" { (list) }
+                            label class="text-sm text-gray-600" {
+                                "Archive completed after "
+                                input class="w-20 rounded p-1 border" type="number" name="archive_after_days" min="1" max=(MAX_ARCHIVE_DAYS)
+                                    value=[policy.archive_after_days];
+                                " days"
+                            }
+                            button class="text-blue-500 hover:text-blue-700" type="submit" { "Save" }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// every list with its policy, by name
+pub fn policies(db: &Db) -> anyhow::Result<Vec<(String, Policy)>> {
+    counts(&repository::todo::all(db)?)
+        .into_keys()
+        .map(|list| {
+            let policy = policy(db, &list)?;
+            Ok((list, policy))
+        })
+        .collect()
+}
+
+// === Routes ===
+#[derive(Deserialize)]
+pub struct PolicyForm {
+    #[serde(default)]
+    archive_after_days: String,
+}
+
+pub async fn save_policy(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Path(list): Path<String>,
+    Form(PolicyForm { archive_after_days }): Form<PolicyForm>,
+) -> Result<Response, AppError> {
+    let list = normalize(&list).ok_or(AppError::NotFound)?;
+    let policy = Policy {
+        archive_after_days: parse_days(&archive_after_days)?,
+    };
+    let db = state.write().await.for_tenant(tenant.id())?;
+    set_policy(&db, &list, &policy)?;
+    let notice = match policy.archive_after_days {
+        Some(days) => format!(
+            "Completed todos in {} are archived after {} days.",
+            list, days
+        ),
+        None => format!("Completed todos in {} are kept.", list),
+    };
+    let fragment = html! {
+        (settings_html(&policies(&db)?))
+        (views::notice_toast_oob(&notice))
+    };
+    Ok(views::fragment_or_redirect(
+        &headers,
+        fragment,
+        &routes::Settings::url(),
+    ))
+}
+
+pub async fn picker(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    Path(id): Path<u64>,
+) -> Result<Markup, AppError> {
+    let db = state.read().await.for_tenant(tenant.id())?;
+    let todo = db
+        .get::<Todo, _>(repository::todo::todo_key(id))?
+        .ok_or(AppError::NotFound)?;
+    Ok(picker_html(&todo))
+}
+
+#[derive(Deserialize)]
+pub struct Suggest {
+    #[serde(default)]
+    q: String,
+}
+// the options of a list field as it is typed in, see `combobox`
+pub async fn suggest(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    Query(Suggest { q }): Query<Suggest>,
+) -> Result<Markup, AppError> {
+    let db = state.read().await.for_tenant(tenant.id())?;
+    Ok(suggestions_html(&counts(&repository::todo::all(&db)?), &q))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{TestDb, TodoFixture};
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse(" Groceries ").unwrap(), Some("groceries".to_string()));
+        assert_eq!(parse("").unwrap(), None);
+        assert!(parse("two words").is_err());
+    }
+
+    #[test]
+    fn test_archive_completed() -> anyhow::Result<()> {
+        let db = TestDb::new("list-archive")?;
+        let filed = |title: &str, list: &str, done: bool| -> anyhow::Result<Todo> {
+            let mut fixture = TodoFixture::new().titled(title);
+            if done {
+                fixture = fixture.completed();
+            }
+            let mut todo = fixture.build(&db)?;
+            todo.list = Some(list.into());
+            Repository::new(&db).put(&todo)?;
+            Ok(todo)
+        };
+        let milk = filed("Milk", "groceries", true)?;
+        filed("Eggs", "groceries", false)?;
+        let taxes = filed("Taxes", "admin", true)?;
+        set_policy(
+            &db,
+            "groceries",
+            &Policy {
+                archive_after_days: Some(7),
+            },
+        )?;
+        assert!(archive_completed(&db)?.is_empty());
+
+        db.clock.advance(std::time::Duration::from_secs(8 * 86_400));
+        let archived = archive_completed(&db)?;
+        assert_eq!(archived.get("groceries"), Some(&1));
+        let get = |id| Repository::<Todo>::new(&db).get(id);
+        assert!(get(milk.id)?.unwrap().archived);
+        // no policy, kept
+        assert!(!get(taxes.id)?.unwrap().archived);
+        // told in the activity log
+        let entries = activity::for_todo(&db, milk.id)?;
+        assert_eq!(entries[0].verb(), "archived");
+        assert_eq!(entries[0].actor.as_deref(), Some(ARCHIVE_ACTOR));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_days() {
+        assert_eq!(parse_days(" 30 ").unwrap(), Some(30));
+        assert_eq!(parse_days("").unwrap(), None);
+        assert!(parse_days("0").is_err() && parse_days("soon").is_err());
+    }
+
+    #[test]
+    fn test_suggestions_html() {
+        let mut todos = vec![Todo::new(1, "Milk".into()), Todo::new(2, "Eggs".into())];
+        todos[0].list = Some("groceries".into());
+        let counts = counts(&todos);
+        assert_eq!(counts.get("groceries"), Some(&1));
+
+        let html = suggestions_html(&counts, "gro").into_string();
+        assert!(html.contains(r#"data-value="groceries""#) && html.contains("New list gro"));
+        // moving back out of every list is offered
+        assert!(suggestions_html(&counts, "")
+            .into_string()
+            .contains(r#"data-value="">No list"#));
+        assert!(suggestions_html(&counts, "a b").into_string().is_empty());
+    }
+}
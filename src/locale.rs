@@ -0,0 +1,365 @@
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use axum::{extract::State, http::HeaderMap, response::Response};
+use maud::{html, Markup};
+use serde::{Deserialize, Serialize};
+use time::{Date, Month, OffsetDateTime, UtcOffset};
+
+use crate::{
+    db::driver::Db, error::AppError, extract::FormOrJson, routes, state::AppState, tenant::Tenant,
+    views,
+};
+
+// per workspace, like the theme
+const PREFERENCES_KEY: &str = "preferences";
+// relative dates further away than this are written out
+const RELATIVE_DAYS: i64 = 7;
+
+// === Preferences ===
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Locale {
+    #[default]
+    EnUs,
+    EnGb,
+    De,
+    Fr,
+}
+impl Locale {
+    pub const ALL: [Locale; 4] = [Locale::EnUs, Locale::EnGb, Locale::De, Locale::Fr];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Locale::EnUs => "en-US",
+            Locale::EnGb => "en-GB",
+            Locale::De => "de",
+            Locale::Fr => "fr",
+        }
+    }
+    pub fn label(&self) -> &'static str {
+        match self {
+            Locale::EnUs => "English (US)",
+            Locale::EnGb => "English (UK)",
+            Locale::De => "Deutsch",
+            Locale::Fr => "Français",
+        }
+    }
+}
+impl FromStr for Locale {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|locale| locale.as_str() == s)
+            .ok_or_else(|| anyhow!("unknown locale `{}`", s))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Preferences {
+    pub locale: Locale,
+    // what "today" is, minutes east of UTC
+    pub utc_offset_minutes: i16,
+}
+
+pub fn get(db: &Db) -> anyhow::Result<Preferences> {
+    Ok(db.get(PREFERENCES_KEY)?.unwrap_or_default())
+}
+pub fn set(db: &Db, preferences: Preferences) -> anyhow::Result<()> {
+    db.insert(PREFERENCES_KEY, &preferences)
+}
+
+// === Formatting ===
+// Dates and times the way the workspace reads them, relative to now in its timezone.
+#[derive(Debug, Clone, Copy)]
+pub struct Formatter {
+    locale: Locale,
+    now: OffsetDateTime,
+}
+impl Formatter {
+    pub fn new(preferences: Preferences, now: OffsetDateTime) -> Self {
+        let offset = UtcOffset::from_whole_seconds(i32::from(preferences.utc_offset_minutes) * 60)
+            .unwrap_or(UtcOffset::UTC);
+        Self {
+            locale: preferences.locale,
+            now: now.to_offset(offset),
+        }
+    }
+    pub fn load(db: &Db) -> anyhow::Result<Self> {
+        Ok(Self::new(get(db)?, db.clock().now()))
+    }
+
+    pub fn today(&self) -> Date {
+        self.now.date()
+    }
+    // the day `unix` falls on in the workspace's offset
+    pub fn day_of(&self, unix: u64) -> Option<Date> {
+        let at = OffsetDateTime::from_unix_timestamp(unix as i64).ok()?;
+        Some(at.to_offset(self.now.offset()).date())
+    }
+
+    // `Mar 5, 2024`, `5 Mar 2024`, `5. März 2024`, `5 mars 2024`
+    pub fn date(&self, date: Date) -> String {
+        let (day, year) = (date.day(), date.year());
+        let month = month_name(self.locale, date.month());
+        match self.locale {
+            Locale::EnUs => format!("{} {}, {}", month, day, year),
+            Locale::EnGb | Locale::Fr => format!("{} {} {}", day, month, year),
+            Locale::De => format!("{}. {} {}", day, month, year),
+        }
+    }
+
+    // `today`, `in 2 days`, `3 days ago`, or the date after a week
+    pub fn day(&self, date: Date) -> String {
+        let days = (date - self.today()).whole_days();
+        let n = days.abs();
+        match (self.locale, days) {
+            (_, days) if days.abs() > RELATIVE_DAYS => self.date(date),
+            (Locale::De, 0) => "heute".into(),
+            (Locale::De, 1) => "morgen".into(),
+            (Locale::De, -1) => "gestern".into(),
+            (Locale::De, 2..) => format!("in {} Tagen", n),
+            (Locale::De, _) => format!("vor {} Tagen", n),
+            (Locale::Fr, 0) => "aujourd'hui".into(),
+            (Locale::Fr, 1) => "demain".into(),
+            (Locale::Fr, -1) => "hier".into(),
+            (Locale::Fr, 2..) => format!("dans {} jours", n),
+            (Locale::Fr, _) => format!("il y a {} jours", n),
+            (_, 0) => "today".into(),
+            (_, 1) => "tomorrow".into(),
+            (_, -1) => "yesterday".into(),
+            (_, 2..) => format!("in {} days", n),
+            (_, _) => format!("{} days ago", n),
+        }
+    }
+
+    // `due tomorrow`, or how long it is overdue
+    pub fn due(&self, due: Date) -> String {
+        let late = (self.today() - due).whole_days();
+        if late <= 0 || late > RELATIVE_DAYS {
+            return match self.locale {
+                Locale::De => format!("fällig {}", self.day(due)),
+                Locale::Fr => format!("échéance {}", self.day(due)),
+                _ => format!("due {}", self.day(due)),
+            };
+        }
+        match (self.locale, late) {
+            (Locale::De, 1) => "seit gestern überfällig".into(),
+            (Locale::De, _) => format!("seit {} Tagen überfällig", late),
+            (Locale::Fr, 1) => "en retard d'un jour".into(),
+            (Locale::Fr, _) => format!("en retard de {} jours", late),
+            (_, 1) => "overdue by a day".into(),
+            (_, _) => format!("overdue by {} days", late),
+        }
+    }
+
+    // `starts tomorrow`, for a todo scheduled later
+    pub fn starts(&self, day: Date) -> String {
+        match self.locale {
+            Locale::De => format!("beginnt {}", self.day(day)),
+            Locale::Fr => format!("commence {}", self.day(day)),
+            _ => format!("starts {}", self.day(day)),
+        }
+    }
+
+    // `just now`, `5 minutes ago`, `3 hours ago`, then days as `day` writes them
+    pub fn ago(&self, unix: u64) -> String {
+        let Ok(at) = OffsetDateTime::from_unix_timestamp(unix as i64) else {
+            return String::new();
+        };
+        let at = at.to_offset(self.now.offset());
+        let minutes = (self.now - at).whole_minutes().max(0);
+        let hours = minutes / 60;
+        match (self.locale, minutes, hours) {
+            (_, _, 24..) => self.day(at.date()),
+            // an hour ago may have been yesterday already
+            (_, _, _) if at.date() != self.today() => self.day(at.date()),
+            (Locale::De, 0, _) => "gerade eben".into(),
+            (Locale::De, 1, _) => "vor 1 Minute".into(),
+            (Locale::De, 2..=59, _) => format!("vor {} Minuten", minutes),
+            (Locale::De, _, 1) => "vor 1 Stunde".into(),
+            (Locale::De, _, _) => format!("vor {} Stunden", hours),
+            (Locale::Fr, 0, _) => "à l'instant".into(),
+            (Locale::Fr, 1, _) => "il y a 1 minute".into(),
+            (Locale::Fr, 2..=59, _) => format!("il y a {} minutes", minutes),
+            (Locale::Fr, _, 1) => "il y a 1 heure".into(),
+            (Locale::Fr, _, _) => format!("il y a {} heures", hours),
+            (_, 0, _) => "just now".into(),
+            (_, 1, _) => "a minute ago".into(),
+            (_, 2..=59, _) => format!("{} minutes ago", minutes),
+            (_, _, 1) => "an hour ago".into(),
+            (_, _, _) => format!("{} hours ago", hours),
+        }
+    }
+
+    // `completed yesterday`
+    pub fn completed(&self, unix: u64) -> String {
+        match self.locale {
+            Locale::De => format!("erledigt {}", self.ago(unix)),
+            Locale::Fr => format!("terminé {}", self.ago(unix)),
+            _ => format!("completed {}", self.ago(unix)),
+        }
+    }
+}
+
+fn month_name(locale: Locale, month: Month) -> &'static str {
+    const EN: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    const DE: [&str; 12] = [
+        "Jan.", "Feb.", "März", "Apr.", "Mai", "Juni", "Juli", "Aug.", "Sept.", "Okt.", "Nov.",
+        "Dez.",
+    ];
+    const FR: [&str; 12] = [
+        "janv.", "févr.", "mars", "avr.", "mai", "juin", "juil.", "août", "sept.", "oct.", "nov.",
+        "déc.",
+    ];
+    let index = u8::from(month) as usize - 1;
+    match locale {
+        Locale::EnUs | Locale::EnGb => EN[index],
+        Locale::De => DE[index],
+        Locale::Fr => FR[index],
+    }
+}
+
+// `+02:00` for 120
+fn offset_label(minutes: i16) -> String {
+    let sign = if minutes < 0 { '-' } else { '+' };
+    let minutes = minutes.unsigned_abs();
+    format!("UTC{}{:02}:{:02}", sign, minutes / 60, minutes % 60)
+}
+
+// === Components ===
+pub fn settings_html(current: Preferences) -> Markup {
+    html! {
+        section id="locale" class="bg-white rounded-lg shadow-lg p-6 space-y-4" {
+            h2 class="text-2xl text-gray-700" { "Language and time" }
+            form class="space-y-2" method="post" action=(routes::LocaleSetting::url())
+                hx-post=(routes::LocaleSetting::url()) hx-trigger="change" hx-target="#locale" hx-swap="outerHTML" {
+                label class="flex items-center space-x-2" {
+                    span class="w-24" { "Dates" }
+                    select class="rounded p-2 border" name="locale" {
+                        @for locale in Locale::ALL {
+                            option value=(locale.as_str()) selected[locale == current.locale] { (locale.label()) }
+                        }
+                    }
+                }
+                label class="flex items-center space-x-2" {
+                    span class="w-24" { "Timezone" }
+                    select class="rounded p-2 border" name="utc_offset_minutes" {
+                        // every half hour from UTC-12:00 to UTC+14:00
+                        @for minutes in (-24..=28).map(|half_hours: i16| half_hours * 30) {
+                            option value=(minutes) selected[minutes == current.utc_offset_minutes] { (offset_label(minutes)) }
+                        }
+                    }
+                }
+                noscript {
+                    button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" { "Save" }
+                }
+            }
+        }
+    }
+}
+
+// === Routes ===
+#[derive(Deserialize)]
+pub struct SetPreferences {
+    locale: String,
+    utc_offset_minutes: i16,
+}
+pub async fn set_preferences(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    FormOrJson(SetPreferences {
+        locale,
+        utc_offset_minutes,
+    }): FormOrJson<SetPreferences>,
+) -> Result<Response, AppError> {
+    let locale: Locale = locale
+        .parse()
+        .map_err(|err: anyhow::Error| AppError::Invalid(err.to_string()))?;
+    if !(-12 * 60..=14 * 60).contains(&utc_offset_minutes) {
+        return Err(AppError::Invalid("That timezone does not exist.".into()));
+    }
+    let preferences = Preferences {
+        locale,
+        utc_offset_minutes,
+    };
+    let guard = state.write().await;
+    set(&guard.for_tenant(tenant.id())?, preferences)?;
+    Ok(views::fragment_or_redirect(
+        &headers,
+        settings_html(preferences),
+        &routes::Settings::url(),
+    ))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use time::macros::{date, datetime};
+
+    use super::*;
+
+    fn formatter(locale: Locale) -> Formatter {
+        let preferences = Preferences {
+            locale,
+            utc_offset_minutes: 120,
+        };
+        Formatter::new(preferences, datetime!(2024-03-05 23:30 UTC))
+    }
+
+    #[test]
+    fn test_dates() {
+        // it is already the 6th two hours east of UTC
+        assert_eq!(formatter(Locale::EnUs).today(), date!(2024 - 03 - 06));
+        assert_eq!(
+            formatter(Locale::EnUs).date(date!(2024 - 03 - 05)),
+            "Mar 5, 2024"
+        );
+        assert_eq!(
+            formatter(Locale::EnGb).date(date!(2024 - 03 - 05)),
+            "5 Mar 2024"
+        );
+        assert_eq!(
+            formatter(Locale::De).date(date!(2024 - 03 - 05)),
+            "5. März 2024"
+        );
+        assert_eq!(
+            formatter(Locale::Fr).date(date!(2024 - 12 - 25)),
+            "25 déc. 2024"
+        );
+        for locale in Locale::ALL {
+            assert_eq!(locale.as_str().parse::<Locale>().unwrap(), locale);
+        }
+        assert_eq!(offset_label(-210), "UTC-03:30");
+    }
+
+    #[test]
+    fn test_relative() {
+        let en = formatter(Locale::EnUs);
+        assert_eq!(en.due(date!(2024 - 03 - 06)), "due today");
+        assert_eq!(en.due(date!(2024 - 03 - 08)), "due in 2 days");
+        assert_eq!(en.due(date!(2024 - 03 - 05)), "overdue by a day");
+        assert_eq!(en.due(date!(2024 - 03 - 03)), "overdue by 3 days");
+        assert_eq!(en.due(date!(2024 - 04 - 01)), "due Apr 1, 2024");
+        assert_eq!(en.due(date!(2024 - 01 - 01)), "due Jan 1, 2024");
+        let now = datetime!(2024-03-05 23:30 UTC).unix_timestamp() as u64;
+        assert_eq!(en.ago(now - 20), "just now");
+        assert_eq!(en.ago(now - 5 * 60), "5 minutes ago");
+        // 22:00 UTC is midnight at UTC+2
+        assert_eq!(en.ago(now - 90 * 60), "an hour ago");
+        assert_eq!(en.completed(now - 2 * 60 * 60), "completed yesterday");
+        assert_eq!(en.ago(now - 4 * 24 * 60 * 60), "4 days ago");
+
+        let de = formatter(Locale::De);
+        assert_eq!(de.due(date!(2024 - 03 - 07)), "fällig morgen");
+        assert_eq!(de.due(date!(2024 - 03 - 04)), "seit 2 Tagen überfällig");
+        assert_eq!(de.completed(now - 5 * 60), "erledigt vor 5 Minuten");
+        let fr = formatter(Locale::Fr);
+        assert_eq!(fr.day(date!(2024 - 03 - 09)), "dans 3 jours");
+        assert_eq!(fr.ago(now - 3 * 60 * 60), "hier");
+    }
+}
@@ -1,196 +1,1641 @@
-pub mod db;
-pub mod error;
-pub mod models;
-pub mod repository;
-
-use std::sync::Arc;
+use std::{
+    collections::{HashMap, HashSet},
+    net::Ipv4Addr,
+};
 
 use anyhow::Result;
 use axum::{
-    extract::{Query, State},
-    routing::{delete, get, post, put},
-    Form, Json, Router,
+    body::Bytes,
+    error_handling::HandleErrorLayer,
+    extract::{DefaultBodyLimit, Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+    routing::{delete, get, patch, post, put},
+    Extension, Json, Router,
 };
-use db::driver::Db;
-use error::AppError;
-use maud::{html, Markup, DOCTYPE};
-use models::Todo;
-use serde::Deserialize;
-use tokio::{
-    net::TcpListener,
-    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+use maud::{html, Markup};
+use rust_htmx::{
+    accounts, activity, admin, analytics,
+    api::{self, v1::TodoResource},
+    assistant, attachments,
+    auth::{
+        csrf, policy, user,
+        visitor::{self, Visitor},
+    },
+    board,
+    branding::{self, Branding},
+    bundle, caching, calendar, cancel, checklist, cli, colors,
+    completed::{self, COMPLETED_ID},
+    config::Config,
+    daily_goal,
+    db::{driver::Db, loader},
+    diff::{self, ListDigest, Patch},
+    editing, embed,
+    error::{self, AppError},
+    events, export,
+    extract::FormOrJson,
+    fields,
+    filtering::{self, ListFilter, Window},
+    geocode, goals, guest, history, inbound, integrations, kiosk, limits, lists,
+    locale::Formatter,
+    maintenance, merge, method_override, migrate,
+    models::{self, Location, Priority, Todo},
+    palette, plan, presence, previews, pwa,
+    reactions::{self, Reactions},
+    recorder, registration, reload,
+    repository::{self, entity::Repository},
+    review, routes, scheduled, search, seo, server,
+    services::{
+        list::ListService,
+        todo::{NewTodo, TodoService},
+    },
+    settings, setup, someday,
+    sorting::{self, Order, View},
+    state::AppState,
+    stats, subscriptions, suggest, sync, tags, telemetry,
+    tenant::Tenant,
+    theme, today, toolbar, unread,
+    views::{
+        self, combobox, dom,
+        hx::{Hx, Swap, Target},
+        meta::{self, Meta},
+        mobile,
+        nav::{self, Nav},
+        overrides::{self, Part},
+        panel::{self, PANEL_ID},
+    },
+    voice,
+};
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpListener;
+use tower::{limit::GlobalConcurrencyLimitLayer, ServiceBuilder};
+use tower_http::{
+    catch_panic::CatchPanicLayer,
+    normalize_path::NormalizePathLayer,
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    trace::TraceLayer,
 };
 
-// === App State ===
-#[derive(Debug, Clone)]
-struct AppState {
-    state: Arc<RwLock<Db>>,
-}
-impl AppState {
-    fn new() -> Result<Self> {
-        Ok(Self {
-            state: Arc::new(RwLock::new(Db::new()?)),
-        })
-    }
+#[tokio::main]
+async fn main() -> Result<()> {
+    // initialize tracing
+    let mut config = Config::from_env()?;
+    telemetry::init(&config);
+    #[cfg(feature = "sentry")]
+    let _sentry = telemetry::init_sentry(&config);
 
-    // borrow immutable state
-    async fn read(&self) -> RwLockReadGuard<'_, Db> {
-        self.state.read().await
+    // admin commands run against the database and exit instead of serving, see `cli`
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(dir) = cli::take_option(&mut args, "--data-dir")? {
+        config.data_dir = dir.into();
     }
-    // borrow mutable state
-    async fn write(&mut self) -> RwLockWriteGuard<'_, Db> {
-        self.state.write().await
+    // `--tray` serves on localhost only and sits in the system tray, see `tray`
+    let tray = args.iter().any(|arg| arg == "--tray");
+    args.retain(|arg| arg != "--tray");
+    // `--read-only` looks at a copy, for when the server holds the database
+    let read_only = args.iter().any(|arg| arg == "--read-only");
+    args.retain(|arg| arg != "--read-only");
+    if let Some(command) = cli::Command::parse(&args)? {
+        if read_only {
+            return cli::run_read_only(command, &config);
+        }
+        return cli::run(command, &config);
+    }
+    anyhow::ensure!(
+        !read_only,
+        "--read-only is for commands, the server needs the database itself"
+    );
+    #[cfg(not(feature = "tray"))]
+    anyhow::ensure!(!tray, "--tray needs a build with the `tray` feature");
+    if tray {
+        config.listen_addr.set_ip(Ipv4Addr::LOCALHOST.into());
     }
-}
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    // initialize tracing
-    tracing_subscriber::fmt::init();
+    // components replaced by html files, see `views::overrides`
+    if let Some(dir) = &config.template_dir {
+        let templates = overrides::load(dir)?;
+        tracing::info!(dir = %dir.display(), templates = templates.len(), "overriding components");
+        overrides::install(templates);
+    }
 
     // build our application with a route
-    let state = AppState::new()?;
-    let app = Router::new()
+    let state = AppState::new(&config)?;
+    if state
+        .setup_pending
+        .load(std::sync::atomic::Ordering::Relaxed)
+    {
+        println!(
+            "Finish setting up this instance at {}{}",
+            config.public_url,
+            routes::Setup::url()
+        );
+    }
+    // reloadable settings follow `CONFIG_FILE` without a restart
+    if let Some(path) = &config.config_file {
+        reload::spawn_watcher(state.clone(), path.clone(), reload::WATCH_INTERVAL);
+    }
+    let reads = Router::new()
         // `GET /` goes to `root`
-        .route("/", get(root))
-        .route("/todos", get(todos))
-        .route("/create_todo", put(create_todo))
-        .route("/toggle_todo", post(toggle_todo))
-        .route("/remove_todo", delete(remove_todo))
-        .with_state(state);
-
-    // run our app with hyper, listening globally on port 3000
-    let listener = TcpListener::bind("0.0.0.0:3000").await?;
-    println!("Listening on http://localhost:3000");
-    axum::serve(listener, app).await?;
+        .route(routes::Root::PATH, get(root))
+        .route(routes::QuickAdd::PATH, get(quick_add))
+        .route(routes::Suggestions::PATH, get(suggest::suggestions))
+        .route(routes::Setup::PATH, get(setup::show))
+        .route(routes::ThemeCss::PATH, get(theme::stylesheet))
+        .route(routes::Manifest::PATH, get(pwa::show_manifest))
+        .route(routes::AppIcon::PATH, get(pwa::app_icon))
+        .route(routes::MaskableIcon::PATH, get(pwa::maskable_icon))
+        .route(routes::Signup::PATH, get(registration::show))
+        .route(routes::AccountSignup::PATH, get(accounts::show_signup))
+        .route(routes::AccountLogin::PATH, get(accounts::show_login))
+        .route(routes::Todos::PATH, get(todos))
+        .route(routes::TodoDetail::PATH, get(todo_detail))
+        .route(routes::Today::PATH, get(today::index))
+        .route(routes::Board::PATH, get(board::board))
+        .route(routes::BoardColumn::PATH, get(board::column))
+        .route(routes::Plan::PATH, get(plan::index))
+        .route(routes::PlanColumn::PATH, get(plan::column))
+        .route(routes::Calendar::PATH, get(calendar::calendar))
+        .route(routes::CalendarMonth::PATH, get(calendar::month))
+        .route(routes::CalendarDay::PATH, get(calendar::day))
+        .route(routes::Goals::PATH, get(goals::index))
+        .route(routes::TodoPalette::PATH, get(todo_palette))
+        .route(routes::TodoMove::PATH, get(lists::picker))
+        .route(routes::TodoEditLock::PATH, get(editing::badge))
+        .route(routes::Stats::PATH, get(stats::index))
+        .route(routes::Palette::PATH, get(palette::preview))
+        .route(routes::StatsSidebar::PATH, get(stats::sidebar))
+        .route(routes::Activity::PATH, get(activity::index))
+        .route(routes::ActivityPreview::PATH, get(activity::preview))
+        .route(routes::ListBadges::PATH, get(unread::badges))
+        .route(routes::Tags::PATH, get(tags::index))
+        .route(routes::TagCloud::PATH, get(tags::cloud))
+        .route(routes::TagSuggestions::PATH, get(tags::suggest))
+        .route(routes::ListSuggestions::PATH, get(lists::suggest))
+        .route(routes::TagBundle::PATH, get(bundle::download))
+        .route(routes::ArchivedTags::PATH, get(tags::archived))
+        .route(routes::Review::PATH, get(review::index))
+        .route(routes::ReviewNudge::PATH, get(review::nudge))
+        .route(routes::Someday::PATH, get(someday::index))
+        .route(routes::SomedayNudge::PATH, get(someday::nudge))
+        .route(routes::DailyGoalRing::PATH, get(daily_goal::ring))
+        .route(routes::Export::PATH, get(export::export))
+        .route(routes::PanelClose::PATH, get(panel::close))
+        .route(routes::Kiosk::PATH, get(kiosk::show))
+        .route(routes::KioskPanel::PATH, get(kiosk::panel))
+        .route(routes::Embed::PATH, get(embed::show))
+        .route(routes::CalendarFeed::PATH, get(integrations::calendar_feed))
+        .route(routes::MaintenanceBanner::PATH, get(maintenance::banner))
+        .route(routes::BrandHeader::PATH, get(branding::header))
+        .route(routes::BrandLogo::PATH, get(branding::show_logo))
+        .route(routes::Sitemap::PATH, get(seo::sitemap))
+        .route(routes::Robots::PATH, get(seo::robots))
+        .route(
+            routes::DevRequests::PATH,
+            get(recorder::index).delete(recorder::clear),
+        )
+        .route(routes::DevRequest::PATH, get(recorder::show))
+        .route(routes::DevSignature::PATH, post(inbound::debug_signature))
+        .route(routes::TodoAttachment::PATH, get(attachments::download))
+        .route(routes::TodoHistory::PATH, get(history::index))
+        .route(routes::EditTodo::PATH, get(edit_todo))
+        .route(routes::TodoItem::PATH, get(todo_item))
+        .route(routes::Search::PATH, get(search::index))
+        .route(routes::TodoAttachmentView::PATH, get(attachments::view))
+        .merge(api::reads(&config))
+        .route_layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(limits::handle_error))
+                .timeout(config.read_timeout),
+        );
+    let writes = Router::new()
+        .route(routes::CreateTodo::PATH, put(create_todo))
+        .route(routes::QuickAddAudio::PATH, post(quick_add_audio))
+        .route(routes::Setup::PATH, post(setup::create_admin))
+        .route(routes::SetupInstance::PATH, post(setup::set_instance))
+        .route(routes::Signup::PATH, post(registration::signup))
+        .route(routes::AccountSignup::PATH, post(accounts::signup))
+        .route(routes::AccountLogin::PATH, post(accounts::login))
+        .route(routes::AccountLogout::PATH, post(accounts::logout))
+        .route(routes::ToggleTodo::PATH, post(toggle_todo))
+        .route(routes::ListSeen::PATH, post(unread::mark))
+        .route(routes::KioskToggle::PATH, post(kiosk::toggle))
+        .route(routes::KioskAdd::PATH, post(kiosk::add))
+        .route(routes::UpdateTodo::PATH, patch(update_todo))
+        .route(routes::RemoveTodo::PATH, delete(remove_todo))
+        .route(
+            routes::ImportTodos::PATH,
+            post(export::upload).layer(DefaultBodyLimit::max(export::MAX_IMPORT_BYTES)),
+        )
+        .route(
+            routes::ImportBundle::PATH,
+            post(bundle::upload).layer(DefaultBodyLimit::max(export::MAX_IMPORT_BYTES)),
+        )
+        .route(
+            routes::ImportMigration::PATH,
+            post(migrate::upload).layer(DefaultBodyLimit::max(export::MAX_IMPORT_BYTES)),
+        )
+        .route(routes::TodoBlockers::PATH, post(add_blocker))
+        .route(routes::TodoBlocker::PATH, delete(remove_blocker))
+        .route(routes::TodoStatus::PATH, post(board::set_status))
+        .route(routes::TodoDue::PATH, patch(today::reschedule))
+        .route(routes::TodoSchedule::PATH, patch(plan::schedule))
+        .route(routes::InboundTodos::PATH, post(inbound::create_todo))
+        .route(routes::TodoChecklist::PATH, post(checklist::add))
+        .route(routes::TodoFields::PATH, post(fields::save_values))
+        .route(routes::TodoHistoryRestore::PATH, post(history::restore))
+        .route(
+            routes::SearchSubscriptions::PATH,
+            post(subscriptions::create),
+        )
+        .route(
+            routes::SearchSubscription::PATH,
+            delete(subscriptions::remove),
+        )
+        .route(routes::DigestEntry::PATH, delete(subscriptions::dismiss))
+        .route(
+            routes::TodoChecklistItem::PATH,
+            post(checklist::toggle).delete(checklist::remove),
+        )
+        .route(
+            routes::TodoAttachments::PATH,
+            post(attachments::upload).layer(DefaultBodyLimit::max(config.attachment_max_bytes)),
+        )
+        .route(routes::CreateGoal::PATH, put(goals::create))
+        .route(
+            routes::Goal::PATH,
+            post(goals::update).delete(goals::remove),
+        )
+        .route(routes::TodoGoal::PATH, post(goals::pick))
+        .route(routes::TodoMove::PATH, post(move_todo))
+        .route(routes::TodoMerge::PATH, post(merge::merge))
+        .route(routes::TodoCancel::PATH, post(cancel::cancel))
+        .route(routes::PaletteRun::PATH, post(palette::run))
+        .route(routes::Tag::PATH, post(tags::rename).delete(tags::remove))
+        .route(routes::TagArchive::PATH, post(tags::archive))
+        .route(routes::TagRestore::PATH, post(tags::restore))
+        .route(routes::TodoColor::PATH, post(set_color))
+        .route(routes::TodoReactions::PATH, post(reactions::react))
+        .route(routes::TodoEdit::PATH, post(editing::edit))
+        .route(routes::TodoEditCancel::PATH, post(editing::cancel))
+        .route(routes::TodoTitle::PATH, post(editing::save))
+        .route(routes::TodoBreakdown::PATH, post(assistant::suggest))
+        .route(routes::TodoSteps::PATH, post(assistant::accept))
+        .route(routes::ReviewStart::PATH, post(review::start))
+        .route(routes::ViewSort::PATH, post(sorting::set_order))
+        .route(routes::CompletedTodos::PATH, post(show_completed))
+        .route(routes::ScheduledTodos::PATH, post(scheduled::show))
+        .route(routes::ReviewTodo::PATH, post(review::act))
+        .route(routes::Someday::PATH, post(someday::act))
+        .route(routes::TodoSomeday::PATH, post(someday::move_to_someday))
+        .merge(api::writes(&config))
+        .route_layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(limits::handle_error))
+                .timeout(config.write_timeout),
+        );
+    let app = Router::new()
+        .merge(reads)
+        .merge(writes)
+        .nest("/admin", admin::router(state.clone()))
+        .nest("/settings", settings::router())
+        .fallback(error::not_found)
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            setup::redirect_until_done,
+        ));
+    #[cfg(feature = "embed-assets")]
+    let app = app.route(routes::Asset::PATH, get(rust_htmx::assets::serve));
+    // Long-lived, so outside the request timeouts and the concurrency limit, which would
+    // otherwise count every open page against it. Every other layer applies to it as well.
+    let events = Router::new().route(routes::Events::PATH, get(events::stream));
+    let app = app
+        .layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(limits::handle_error))
+                .load_shed()
+                .layer(GlobalConcurrencyLimitLayer::new(config.concurrency_limit)),
+        )
+        .merge(events);
+    #[cfg(feature = "sentry")]
+    let app = {
+        use sentry::integrations::tower::{NewSentryLayer, SentryHttpLayer};
+        app.layer(
+            ServiceBuilder::new()
+                .layer(NewSentryLayer::<axum::extract::Request>::new_from_top())
+                .layer(SentryHttpLayer::with_transaction())
+                .layer(axum::middleware::from_fn(telemetry::sentry_context)),
+        )
+    };
+    let app = app
+        .layer(
+            ServiceBuilder::new()
+                .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+                .layer(PropagateRequestIdLayer::x_request_id())
+                // `Cache-Control` and `Vary`, private unless the route is shared or static
+                .layer(axum::middleware::from_fn(caching::headers))
+                .layer(
+                    TraceLayer::new_for_http()
+                        .make_span_with(telemetry::make_span)
+                        .on_response(telemetry::on_response),
+                )
+                // inside the request span, which it records its read counts on
+                .layer(axum::middleware::from_fn(loader::layer))
+                // with `DEV_MODE`, the counts of the loader above and the time taken, on every page
+                .layer(axum::middleware::from_fn_with_state(
+                    state.clone(),
+                    toolbar::layer,
+                ))
+                // on the bytes the handler rendered, the dev toolbar left out
+                .layer(axum::middleware::from_fn(caching::etag))
+                // around the error pages so they carry the headers too, and ahead of sign-in,
+                // which would turn preflights away
+                .layer(axum::middleware::from_fn_with_state(
+                    state.clone(),
+                    api::cors::layer,
+                ))
+                .layer(axum::middleware::from_fn(error::render_errors))
+                .layer(axum::middleware::from_fn_with_state(
+                    state.clone(),
+                    visitor::ensure,
+                ))
+                // after the visitor cookie, which it counts sessions by
+                .layer(axum::middleware::from_fn_with_state(
+                    state.clone(),
+                    analytics::count,
+                ))
+                .layer(axum::middleware::from_fn_with_state(
+                    state.clone(),
+                    csrf::protect,
+                ))
+                .layer(axum::middleware::from_fn_with_state(
+                    state.clone(),
+                    maintenance::read_only,
+                ))
+                .layer(axum::middleware::from_fn_with_state(
+                    state.clone(),
+                    user::require,
+                ))
+                // needs to know whose request it is
+                .layer(axum::middleware::from_fn_with_state(
+                    state.clone(),
+                    api::rate_limit::layer,
+                ))
+                // so is the activity log, for who changed what
+                .layer(axum::middleware::from_fn(activity::layer))
+                .layer(axum::middleware::from_fn_with_state(
+                    state.clone(),
+                    policy::enforce,
+                ))
+                .layer(CatchPanicLayer::custom(error::handle_panic)),
+        )
+        .with_state(state.clone());
+    // outermost, to see exactly what went over the wire
+    let app = match &state.recorder {
+        Some(recording) => app.layer(axum::middleware::from_fn_with_state(
+            recording.clone(),
+            recorder::record,
+        )),
+        None => app,
+    };
+    // method overrides and trailing slashes have to be resolved before routing
+    let app = ServiceBuilder::new()
+        .layer(NormalizePathLayer::trim_trailing_slash())
+        .layer(axum::middleware::from_fn(method_override::method_override))
+        .service(app);
+
+    // run our app with hyper, on port 3000 of every interface unless `LISTEN_ADDR` says otherwise
+    let listener = TcpListener::bind(config.listen_addr).await?;
+    let url = format!("http://{}", listener.local_addr()?);
+    println!("Listening on {}", url);
+    // on ctrl-c or SIGTERM stop accepting connections, finish what is in flight, then flush
+    let (stopping, mut stopped) = tokio::sync::watch::channel(false);
+    let server = server::serve(
+        listener,
+        app,
+        server::Tuning::from_config(&config),
+        async move {
+            shutdown_signal().await;
+            let _ = stopping.send(true);
+        },
+    );
+    // the tray needs the main thread for its event loop, the server moves to a task
+    #[cfg(feature = "tray")]
+    if tray {
+        tokio::spawn(server);
+        let runtime = tokio::runtime::Handle::current();
+        return tokio::task::block_in_place(|| rust_htmx::tray::run(state, url, runtime));
+    }
+    // event streams never end on their own, they get the grace period and are cut off after it
+    let grace = config.shutdown_grace;
+    tokio::select! {
+        served = server => served?,
+        _ = async {
+            let _ = stopped.wait_for(|stopping| *stopping).await;
+            tokio::time::sleep(grace).await;
+        } => tracing::warn!("connections still open after the grace period, closing them"),
+    }
+    state.writes.flush().await?;
+    if let Some(counter) = &state.analytics {
+        analytics::flush(&*state.read().await, counter.take())?;
+    }
+    state.read().await.flush()?;
+    tracing::info!("shut down");
     Ok(())
 }
 
-// basic handler that responds with a static string
-async fn root(state: State<AppState>) -> Result<Markup, AppError> {
-    Ok(html! {
-        (DOCTYPE)
-        html {
-            head {
-                meta charset="utf-8";
-                title { "Magical Axum + Maud + Htmx To-Do" }
-                script src="https://unpkg.com/htmx.org@1.9.10" {}
-                script src="https://unpkg.com/htmx.org/dist/ext/json-enc.js" {}
-                script src="https://cdn.tailwindcss.com" {}
-            }
-            body class="bg-gray-100 font-sans leading-normal tracking-normal" {
-                div class="container mx-auto p-8" {
-                    h1 class="text-4xl text-center text-gray-700 mb-6" { "Magical Axum + Maud + Htmx To-Do" }
-                    (new_todo_html())
-                    div id="todos" class="mt-6" {
-                        (todos(state).await?)
-                    }
-                }
+// ctrl-c, or SIGTERM from a service manager or container runtime
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            tracing::error!(%err, "failed to listen for ctrl-c");
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(err) => {
+                tracing::error!(%err, "failed to listen for SIGTERM");
+                std::future::pending::<()>().await;
             }
         }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+    tracing::info!("shutting down");
+}
+
+// basic handler that responds with a static string
+async fn root(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    Extension(Visitor(visitor)): Extension<Visitor>,
+) -> Result<Markup, AppError> {
+    let filter = ListFilter::default();
+    let (todos, list) = load_todos(&state, &tenant, Some(&visitor), Some(&filter)).await?;
+    let viewers = state.presence.count(tenant.id());
+    Ok(list_page(
+        &landing(&state).await?,
+        &todos,
+        &list,
+        viewers,
+        state.transcriber.is_some(),
+        tenant.is_guest(),
+    ))
+}
+
+// what the list page is titled and introduced with, and how links to it unfurl
+struct Landing {
+    name: String,
+    branding: Branding,
+    meta: Meta,
+}
+
+async fn landing(state: &AppState) -> Result<Landing, AppError> {
+    let db = state.read().await;
+    let name = branding::name(&db)?;
+    let branding = branding::get(&db)?;
+    let meta = seo::landing_meta(&state.config.public_url, &name, &branding);
+    Ok(Landing {
+        name,
+        branding,
+        meta,
     })
 }
 
 // === Components ===
+fn list_page(
+    landing: &Landing,
+    todos: &[Todo],
+    list: &ListState,
+    viewers: usize,
+    voice: bool,
+    guest: bool,
+) -> Markup {
+    views::page_with_meta(
+        &landing.name,
+        &landing.meta,
+        html! {
+            h1 class="text-4xl text-center text-gray-700 mb-6" { (landing.name) }
+            (branding::welcome_html(&landing.branding))
+            @if guest { (guest::banner_html()) }
+            (presence::slot_html(viewers))
+            (sync::sink_html())
+            (nav::navigation(&Nav::todos()))
+            div class="hidden md:block" { (new_todo_html(voice)) }
+            div class="mt-4 flex items-center space-x-4" {
+                (filtering::search_html(&list.filter))
+                (sorting::dropdown_html(View::List, list.order))
+                (scheduled::toggle_html(list.scheduled_shown))
+                (palette::palette_html())
+            }
+            (scheduled::nudge_slot())
+            (subscriptions::toast_slot())
+            (tags::archived_slot())
+            (lists::archived_slot())
+            (mobile::create_sheet(new_todo_html(voice)))
+            div class="flex flex-col md:flex-row md:space-x-6" {
+                // catches up with changes made elsewhere when the tab comes back into view, or when
+                // its event stream fell behind and missed some
+                div id=(dom::TODOS) class="mt-6 flex-grow" hx-get=(routes::Todos::url()) hx-include=(filtering::include_attr())
+                    hx-trigger={ "visibilitychange[document.visibilityState === 'visible'] from:document, " (sorting::SORTED_EVENT) " from:body, "
+                        (scheduled::SHOWN_EVENT) " from:body, " (palette::APPLIED_EVENT) " from:body, sse:" (scheduled::SURFACED_EVENT) ", sse:" (events::RESYNC_EVENT) }
+                    hx-headers=(diff::digest_headers()) hx-swap=(Swap::MorphInner) {
+                    (todos_html(todos, list))
+                }
+                // each scans the whole workspace, so they come after the list
+                aside class="mt-6 md:w-64 space-y-4" {
+                    (views::lazy(&routes::StatsSidebar::url(), "stats", Some(&routes::Stats::url())))
+                    (views::lazy(&routes::ActivityPreview::url(), "recent changes", None))
+                    (views::lazy(&routes::TagCloud::url(), "tags", Some(&routes::Tags::url())))
+                }
+            }
+        },
+    )
+}
+
+// Just the form for a new todo, small enough for a popup window (the tray opens it). Added todos
+// are listed below it.
+async fn quick_add(State(state): State<AppState>) -> Markup {
+    views::page(
+        "Quick add",
+        html! {
+            (new_todo_html(state.transcriber.is_some()))
+            div id=(dom::TODOS) class="mt-6" {
+                ul id=(dom::list_items(dom::OPEN_LIST)) class="list-none p-0" {}
+            }
+        },
+    )
+}
+
 // a single line item in the todo list
-fn todo_html(todo: &Todo) -> Markup {
+fn todo_html(todo: &Todo, blocked: bool, reactions: &Reactions, dates: &Formatter) -> Markup {
+    todo_item_html(todo, blocked, reactions, dates, false)
+}
+// the line item replacing its current version out of band
+fn todo_oob_html(todo: &Todo, blocked: bool, reactions: &Reactions, dates: &Formatter) -> Markup {
+    todo_item_html(todo, blocked, reactions, dates, true)
+}
+
+fn todo_item_html(
+    todo: &Todo,
+    blocked: bool,
+    reactions: &Reactions,
+    dates: &Formatter,
+    oob: bool,
+) -> Markup {
+    if let Some(template) = overrides::get(Part::TodoItem) {
+        return todo_item_override(template, todo, dates, oob);
+    }
+    let toggle = Hx::post(routes::ToggleTodo::url())
+        .target(Target::Element(dom::todo(todo.id)))
+        .swap(Swap::Morph)
+        .transition()
+        .vals(&ToggleTodo { id: todo.id });
+    let remove = Hx::delete(routes::RemoveTodo::url())
+        .target(Target::Element(dom::todo(todo.id)))
+        .swap(Swap::Delete)
+        .swap_delay(views::DELETE_SWAP_MS)
+        .vals(&RemoveTodo { id: todo.id });
+    let detail = Hx::get(routes::TodoDetail::url(todo.id))
+        .target(Target::Id(PANEL_ID))
+        .push_url();
+    let edit = Hx::get(routes::EditTodo::url(todo.id))
+        .target(Target::Element(dom::todo(todo.id)))
+        .swap(Swap::OuterHtml);
+    let put_aside = Hx::post(routes::TodoSomeday::url(todo.id))
+        .target(Target::Element(dom::todo(todo.id)))
+        .swap(Swap::Delete)
+        .swap_delay(views::DELETE_SWAP_MS);
     html! {
-        li class="flex items-center bg-white rounded-lg shadow-lg my-2 py-2 px-4" {
-            label class="flex-grow" {
-                @if todo.completed {
-                    input type="checkbox" checked class="mr-2" hx-post="/toggle_todo" hx-target="closest li" hx-vals=(serde_json::json!({ "id": todo.id }))
-                        hx-swap="outerHTML";
-                } @else {
-                    input type="checkbox" class="mr-2" hx-post="/toggle_todo" hx-target="closest li" hx-vals=(serde_json::json!({ "id": todo.id }))
-                        hx-swap="outerHTML";
+        li id=(dom::todo(todo.id)) class="todo-item flex items-center bg-white rounded-lg shadow-lg my-2 py-2 px-4" data-swipe hx-swap-oob=[oob.then_some("morph")]
+            style=[todo.color.as_ref().map(|color| format!("border-left: 6px solid {}", color))] {
+            // the forms are the fallback without javascript, htmx takes over the inputs otherwise
+            form class="flex-grow" method="post" action=(routes::ToggleTodo::url()) {
+                input type="hidden" name="id" value=(todo.id);
+                label {
+                    input type="checkbox" checked[todo.completed] class="mr-2" data-swipe-complete hx-post=[toggle.post_path()] hx-target=[toggle.target_attr()]
+                        hx-vals=[toggle.vals_attr()] hx-swap=[toggle.swap_attr()];
+                    a class={"hover:underline " @if todo.is_cancelled() { "line-through t-muted italic" } @else if todo.completed { "line-through" }} href=(routes::TodoDetail::url(todo.id))
+                        hx-get=[detail.get_path()] hx-target=[detail.target_attr()] hx-push-url=[detail.push_url_attr()] { (todo.title) }
+                    (cancel::badge_html(todo))
+                    (priority_html(todo.priority))
+                    @if let Some(due) = todo.due {
+                        span class="ml-2 text-xs t-muted" title=(dates.date(due)) { (dates.due(due)) }
+                    }
+                    @if let Some(day) = todo.scheduled_for {
+                        @if day > dates.today() {
+                            span class="ml-2 text-xs t-muted" title=(dates.date(day)) { (dates.starts(day)) }
+                        }
+                    }
+                    @if let Some(minutes) = todo.estimate_minutes {
+                        span class="ml-2 text-xs t-muted" { (stats::format_minutes(minutes)) }
+                    }
+                    (checklist::progress_html(todo))
+                    @if blocked {
+                        // marked by more than its color
+                        span class="ml-2 text-xs font-bold t-warning rounded px-2 py-1" { "⚠ Blocked" }
+                    }
+                    (tags::chips_html(&todo.tags))
+                    (lists::chip_html(todo))
                 }
-                span class={@if todo.completed { "line-through" } @else { "" }} { (todo.title) }
+                noscript {
+                    button class="ml-2 text-blue-500 hover:text-blue-700" type="submit" {
+                        @if todo.completed { "Mark as not done" } @else { "Mark as done" }
+                    }
+                }
+            }
+            (editing::badge_slot(todo.id, html! {}))
+            (reactions::reactions_html(todo.id, reactions))
+            button class="mr-2 text-blue-500 hover:text-blue-700" type="button"
+                hx-get=[edit.get_path()] hx-target=[edit.target_attr()] hx-swap=[edit.swap_attr()] { "Edit" }
+            form method="post" action=(routes::TodoSomeday::url(todo.id)) {
+                button class="mr-2 text-blue-500 hover:text-blue-700" type="submit" title="Put aside for some day"
+                    hx-post=[put_aside.post_path()] hx-target=[put_aside.target_attr()] hx-swap=[put_aside.swap_attr()] { "Someday" }
+            }
+            // the list picker and the palette load into the slots next to their buttons
+            button class="mr-2 text-blue-500 hover:text-blue-700" type="button" title="Move to another list"
+                hx-get=(routes::TodoMove::url(todo.id)) hx-target=(dom::selector(&dom::mover(todo.id))) { "Move" }
+            span id=(dom::mover(todo.id)) class="mr-2" {}
+            span id=(dom::palette(todo.id)) class="mr-2" {}
+            // on small screens swipes stand in for the color and remove buttons
+            button class="hidden md:inline-block mr-2 w-5 h-5 rounded-full border" style=[todo.color.as_ref().map(|color| format!("background-color: {}", color))]
+                type="button" title="Color" aria-label="Color" hx-get=(routes::TodoPalette::url(todo.id)) hx-target=(dom::selector(&dom::palette(todo.id))) {}
+            form class="hidden md:block" method="post" action=(routes::RemoveTodo::url()) {
+                input type="hidden" name=(method_override::METHOD_FIELD) value="DELETE";
+                input type="hidden" name="id" value=(todo.id);
+                button class="bg-red-500 hover:bg-red-700 text-white font-bold py-1 px-2 rounded" type="submit" hx-delete=[remove.delete_path()] hx-target=[remove.target_attr()]
+                    hx-swap=[remove.swap_attr()] hx-vals=[remove.vals_attr()] data-swipe-delete { "Remove" }
+            }
+        }
+    }
+}
+
+// The item from `TEMPLATE_DIR`. Its root element gets the id htmx swaps it by.
+fn todo_item_override(template: &str, todo: &Todo, dates: &Formatter, oob: bool) -> Markup {
+    let attributes = format!(
+        "id=\"{}\"{}",
+        dom::todo(todo.id),
+        if oob { " hx-swap-oob=\"morph\"" } else { "" }
+    );
+    let id = todo.id.to_string();
+    let due = todo.due.map(|due| dates.due(due)).unwrap_or_default();
+    let tags = todo.tags.join(", ");
+    let detail_url = routes::TodoDetail::url(todo.id);
+    let toggle_url = routes::ToggleTodo::url();
+    overrides::render(
+        template,
+        &[
+            ("id", id.as_str()),
+            ("title", todo.title.as_str()),
+            ("due", due.as_str()),
+            ("completed", if todo.completed { "completed" } else { "" }),
+            (
+                "cancelled",
+                if todo.is_cancelled() { "cancelled" } else { "" },
+            ),
+            ("tags", tags.as_str()),
+            ("detail_url", detail_url.as_str()),
+            ("toggle_url", toggle_url.as_str()),
+            ("list", todo.list.as_deref().unwrap_or_default()),
+        ],
+        &[("attributes", &attributes)],
+    )
+}
+
+// normal priority goes without saying
+fn priority_html(priority: Priority) -> Markup {
+    html! {
+        @match priority {
+            Priority::High => {
+                span class="ml-2 text-xs font-bold t-warning rounded px-2 py-1" { "↑ High" }
             }
-            button class="bg-red-500 hover:bg-red-700 text-white font-bold py-1 px-2 rounded" hx-delete="/remove_todo" hx-target="closest li" hx-swap="outerHTML" hx-vals=(serde_json::json!({ "id": todo.id })) { "Remove" }
+            Priority::Low => {
+                span class="ml-2 text-xs t-muted" { "↓ Low" }
+            }
+            Priority::Normal => {}
         }
     }
 }
 
-// an input box to create a new todo
-fn new_todo_html() -> Markup {
+// An input box to create a new todo, with a mic button when todos can be added by voice. While
+// the title is typed, suggestions for its due date and estimate show up below.
+fn new_todo_html(voice: bool) -> Markup {
+    let create = Hx::put(routes::CreateTodo::url())
+        .target(Target::Element(dom::list_items(dom::OPEN_LIST)))
+        .swap(Swap::BeforeEnd);
+    let form = dom::NEW_TODO;
+    let field = |name: &str| dom::field(form, name);
+    let error = |name: &str| dom::field_error(form, name);
     html! {
-        form class="flex justify-between items-center" hx-put="/create_todo" hx-target="#todos ul" hx-swap="beforeend" "hx-on::after-request"="this.reset()" {
-            input class="w-full rounded p-2 mr-4" type="text" name="title" placeholder="New Todo" required;
+        form id=(form) class="flex flex-wrap gap-y-2 justify-between items-center" method="post" action=(routes::CreateTodo::url())
+            hx-put=[create.put_path()] hx-target=[create.target_attr()] hx-swap=[create.swap_attr()]
+            // the suggestions' requests bubble up here as well
+            "hx-on::after-request"="if (event.detail.elt === this && event.detail.successful) { this.reset(); this.querySelector('.suggestions').replaceChildren(); }" {
+            (views::error::summary_slot(form))
+            input type="hidden" name=(method_override::METHOD_FIELD) value="PUT";
+            input id=(field("title")) class="flex-grow rounded p-2 mr-4" type="text" name="title" placeholder="New Todo" required aria-describedby=(error("title"))
+                hx-get=(routes::Suggestions::url()) hx-trigger="keyup changed delay:500ms" hx-target="next .suggestions" hx-swap="innerHTML";
+            input id=(field("due")) class="rounded p-2 mr-4" type="date" name="due" aria-label="Due date" aria-describedby=(error("due"));
+            input id=(field("scheduled_for")) class="rounded p-2 mr-4" type="date" name="scheduled_for" aria-label="Start date" aria-describedby=(error("scheduled_for"));
+            input id=(field("estimate")) class="w-24 rounded p-2 mr-4" type="number" name="estimate" min="0" placeholder="Min" aria-label="Estimate in minutes" aria-describedby=(error("estimate"));
+            @if voice {
+                (voice::mic_button_html())
+            }
             button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" { "Add" }
+            @for name in ["title", "due", "scheduled_for", "estimate"] {
+                (views::error::field_error_slot(form, name))
+            }
+            div class="suggestions w-full" {}
+        }
+    }
+}
+
+// everything about one todo, as a page body or a fragment
+fn todo_detail_html(
+    todo: &Todo,
+    dates: &Formatter,
+    title: Markup,
+    previews: Markup,
+    goal: Markup,
+    blockers: Markup,
+) -> Markup {
+    html! {
+        article id="todo-detail" class="bg-white rounded-lg shadow-lg p-6 space-y-4" {
+            (title)
+            p class="text-gray-600" {
+                @if todo.is_cancelled() {
+                    "Cancelled, " (dates.completed(todo.updated_at)) "."
+                } @else if todo.completed {
+                    "Done, " (dates.completed(todo.updated_at)) "."
+                } @else {
+                    "Still to do"
+                    @if let Some(due) = todo.due {
+                        ", " span title=(dates.date(due)) { (dates.due(due)) }
+                    }
+                    "."
+                }
+                @if let Some(minutes) = todo.estimate_minutes { " Estimated " (stats::format_minutes(minutes)) "." }
+            }
+            @if let Some(location) = &todo.location {
+                a class="inline-block text-sm bg-blue-100 text-blue-800 rounded px-2 py-1 hover:bg-blue-200" href=(location.map_url())
+                    target="_blank" rel="noopener noreferrer" { "📍 " (location.text) }
+            }
+            (previews)
+            (goal)
+            (blockers)
+            a class="text-blue-500 hover:text-blue-700" href=(routes::Root::url()) hx-boost="true" { "Back to your todos" }
+        }
+    }
+}
+
+// the todos `todo` waits for, and a picker to add more
+fn blockers_html(todo: &Todo, blockers: &[Todo], candidates: &[Todo]) -> Markup {
+    let add = Hx::post(routes::TodoBlockers::url(todo.id))
+        .target(Target::Id("blockers"))
+        .swap(Swap::Morph);
+    html! {
+        section id="blockers" class="space-y-2" {
+            h2 class="text-xl text-gray-700" { "Blocked by" }
+            @if blockers.is_empty() {
+                p class="text-gray-500" { "Nothing, this todo can start right away." }
+            }
+            ul class="space-y-1" {
+                @for blocker in blockers {
+                    @let remove = Hx::delete(routes::TodoBlocker::url(todo.id, blocker.id))
+                        .target(Target::Id("blockers"))
+                        .swap(Swap::Morph);
+                    li class="flex items-center justify-between" {
+                        span class={ @if blocker.completed { "line-through text-gray-400" } @else { "text-gray-700" } } { (blocker.title) }
+                        form method="post" action=(routes::TodoBlocker::url(todo.id, blocker.id)) {
+                            input type="hidden" name=(method_override::METHOD_FIELD) value="DELETE";
+                            button class="text-red-500 hover:text-red-700" type="submit" hx-delete=[remove.delete_path()]
+                                hx-target=[remove.target_attr()] hx-swap=[remove.swap_attr()] { "Remove" }
+                        }
+                    }
+                }
+            }
+            @if !candidates.is_empty() {
+                form class="flex items-center" method="post" action=(routes::TodoBlockers::url(todo.id))
+                    hx-post=[add.post_path()] hx-target=[add.target_attr()] hx-swap=[add.swap_attr()] {
+                    select class="flex-grow rounded p-2 mr-2 border" name="blocker" {
+                        @for candidate in candidates {
+                            option value=(candidate.id) { (candidate.title) }
+                        }
+                    }
+                    button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded" type="submit" { "Add blocker" }
+                }
+            }
+        }
+    }
+}
+
+fn todos_html(todos: &[Todo], list: &ListState) -> Markup {
+    let (open, completed): (Vec<&Todo>, Vec<&Todo>) =
+        todos.iter().partition(|todo| !todo.completed);
+    let mut items: Vec<Markup> = open.iter().map(|todo| list_item_html(todo, list)).collect();
+    if let Some(first) = open.iter().position(|todo| list.unseen.contains(&todo.id)) {
+        items.insert(first, unread::divider_html());
+    }
+    html! {
+        (list_html(todos.is_empty(), &items, &completed, list))
+        @if let Some(shown) = &list.filter.list {
+            (unread::seen_trigger(shown))
+        }
+    }
+}
+
+// the tabs, the open items, the completed section, and the pager for browsers without scripts
+fn list_html(empty: bool, items: &[Markup], completed: &[&Todo], list: &ListState) -> Markup {
+    html! {
+        (filtering::tabs_html(&list.filter))
+        ul id=(dom::list_items(dom::OPEN_LIST)) class="list-none p-0" {
+            (open_items_html(items, list))
+        }
+        @if empty && list.filter.is_filtered() {
+            (filtering::no_matches_html(&list.filter))
         }
+        (completed_html(completed, list, false))
+        noscript {
+            (filtering::pager_html(&list.filter, list.has_prev, list.next_cursor.is_some()))
+        }
+    }
+}
+
+// the open items and the load-more sentinel, or in a long list the sentinels of their window, see
+// `filtering::Window`
+fn open_items_html(items: &[Markup], list: &ListState) -> Markup {
+    html! {
+        @if let Some(window) = &list.window {
+            (filtering::sentinel_html(&list.filter, window.before.as_ref(), false))
+            @for item in items { (item) }
+            (filtering::sentinel_html(&list.filter, window.after.as_ref(), true))
+        } @else {
+            @for item in items { (item) }
+            (filtering::load_more_html(&list.filter, list.next_cursor.as_deref()))
+        }
+    }
+}
+
+// What a window sentinel is swapped for: the open items of the next window over and their
+// sentinels, the completed ones among them replace the completed section.
+fn window_html(todos: &[Todo], list: &ListState) -> Markup {
+    let (open, completed): (Vec<&Todo>, Vec<&Todo>) =
+        todos.iter().partition(|todo| !todo.completed);
+    let items: Vec<Markup> = open.iter().map(|todo| list_item_html(todo, list)).collect();
+    html! {
+        (open_items_html(&items, list))
+        (completed_html(&completed, list, true))
     }
 }
 
-fn todos_html(todos: &[Todo]) -> Markup {
+// What a load-more sentinel is swapped for: the open items after it and the next sentinel. The
+// completed ones go to the end of the completed section, when it is open.
+fn more_items_html(todos: &[Todo], list: &ListState) -> Markup {
+    let (open, completed): (Vec<&Todo>, Vec<&Todo>) =
+        todos.iter().partition(|todo| !todo.completed);
     html! {
-        ul class="list-none p-0" {
-            @for todo in todos {
-                (todo_html(&todo))
+        @for todo in open { (list_item_html(todo, list)) }
+        (filtering::load_more_html(&list.filter, list.next_cursor.as_deref()))
+        @if list.completed_open && !completed.is_empty() {
+            ul hx-swap-oob={ "beforeend:#" (dom::list_items(dom::COMPLETED_LIST)) } {
+                @for todo in completed { (list_item_html(todo, list)) }
+            }
+        }
+    }
+}
+
+fn list_item_html(todo: &Todo, list: &ListState) -> Markup {
+    let no_reactions = Reactions::default();
+    let reactions = list.reactions.get(&todo.id).unwrap_or(&no_reactions);
+    todo_html(
+        todo,
+        list.blocked.contains(&todo.id),
+        reactions,
+        &list.dates,
+    )
+}
+
+// The completed todos below the list, collapsed to their count until opened. Their items are
+// only rendered once the section is open.
+fn completed_html(completed: &[&Todo], list: &ListState, oob: bool) -> Markup {
+    let open = list.completed_open;
+    // the section is loaded again for the same part of the list
+    let url = format!(
+        "{}{}",
+        routes::CompletedTodos::url(),
+        list.filter.query_string()
+    );
+    let toggle = Hx::post(url.clone())
+        .target(Target::Id(COMPLETED_ID))
+        .swap(Swap::OuterHtml)
+        .vals(&ShowCompleted { open: !open });
+    html! {
+        section id=(COMPLETED_ID) class="mt-6" hx-swap-oob=[oob.then_some("true")] {
+            @if !completed.is_empty() {
+                form method="post" action=(url) {
+                    input type="hidden" name="open" value=(!open);
+                    button class="t-muted hover:underline" type="submit" aria-expanded=(open)
+                        hx-post=[toggle.post_path()] hx-target=[toggle.target_attr()] hx-swap=[toggle.swap_attr()] hx-vals=[toggle.vals_attr()] {
+                        @if open { "▾ " } @else { "▸ " }
+                        "Completed (" (completed.len()) ")"
+                    }
+                }
+                @if open {
+                    ul id=(dom::list_items(dom::COMPLETED_LIST)) class="list-none p-0" {
+                        @for todo in completed {
+                            (list_item_html(todo, list))
+                        }
+                    }
+                }
             }
         }
     }
 }
 
 // === Routes ===
-async fn todos(State(state): State<AppState>) -> Result<Markup, AppError> {
-    let state = state.read().await;
-    let mut todos = state.iter_prefix::<Todo>("todo")?;
-    let mut todos_vec = Vec::new();
-    for todo_result in &mut todos {
-        if let Ok((_, todo)) = todo_result {
-            todos_vec.push(todo);
-        } else {
-            return Err(anyhow::anyhow!("Error getting todos").into());
+// The list fragment, or the whole page when visited directly or restored from history. The
+// query picks the status, search and page, see `filtering::ListFilter`.
+async fn todos(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    Extension(Visitor(visitor)): Extension<Visitor>,
+    Query(filter): Query<ListFilter>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let filter = filter.validate()?;
+    let (todos, list) = load_todos(&state, &tenant, Some(&visitor), Some(&filter)).await?;
+    if views::wants_json(&headers) {
+        let todos: Vec<TodoResource> = todos.iter().map(TodoResource::from).collect();
+        return Ok(Json(todos).into_response());
+    }
+    if !views::wants_fragment(&headers) {
+        let viewers = state.presence.count(tenant.id());
+        let landing = landing(&state).await?;
+        let voice = state.transcriber.is_some();
+        let guest = tenant.is_guest();
+        return Ok(list_page(&landing, &todos, &list, viewers, voice, guest).into_response());
+    }
+    if list.window.is_some() && (filter.after.is_some() || filter.before.is_some()) {
+        return Ok(window_html(&todos, &list).into_response());
+    }
+    if filter.after.is_some() {
+        return Ok(more_items_html(&todos, &list).into_response());
+    }
+    if !state.live.load().list_diffing {
+        return Ok(todos_html(&todos, &list).into_response());
+    }
+    // only the items that changed since this session's last render go out, the completed
+    // section is small enough to always send
+    let (todos, completed): (Vec<&Todo>, Vec<&Todo>) =
+        todos.iter().partition(|todo| !todo.completed);
+    let no_reactions = Reactions::default();
+    let items: Vec<Markup> = todos
+        .iter()
+        .map(|todo| list_item_html(todo, &list))
+        .collect();
+    let digest = ListDigest::new(
+        todos
+            .iter()
+            .zip(&items)
+            .map(|(todo, item)| (todo.id, item.0.as_str())),
+    );
+    // each part of the list is diffed against its own last render
+    let session = format!(
+        "{}:{}:{}",
+        visitor,
+        tenant.id().unwrap_or_default(),
+        filter.query_string()
+    );
+    let previous = state.renders.swap(session, digest.clone());
+    match diff::diff(previous.as_ref(), diff::client_digest(&headers), &digest) {
+        Patch::Full => Ok(html! {
+            (list_html(todos.is_empty() && completed.is_empty(), &items, &completed, &list))
+            (diff::digest_html(&digest, false))
         }
+        .into_response()),
+        Patch::Items(changed) => {
+            let fragment = html! {
+                @for todo in todos.iter().filter(|todo| changed.contains(&todo.id)) {
+                    (todo_oob_html(todo, list.blocked.contains(&todo.id), list.reactions.get(&todo.id).unwrap_or(&no_reactions), &list.dates))
+                }
+                (completed_html(&completed, &list, true))
+                (diff::digest_html(&digest, true))
+            };
+            // the list stays, the items are swapped out of band
+            Ok(([("hx-reswap", "none")], fragment).into_response())
+        }
+    }
+}
+
+// what list items show besides the todo itself
+struct ListState {
+    // todos waiting for an open todo
+    blocked: HashSet<u64>,
+    reactions: HashMap<u64, Reactions>,
+    // due and done dates in the workspace's language and timezone
+    dates: Formatter,
+    order: Order,
+    // the visitor opened the completed section
+    completed_open: bool,
+    // the visitor sees todos scheduled for later
+    scheduled_shown: bool,
+    // the part of the list shown, and where the pages around it start
+    filter: ListFilter,
+    has_prev: bool,
+    next_cursor: Option<String>,
+    // long lists are shown a window at a time, see `filtering::WINDOW_THRESHOLD`
+    window: Option<Window>,
+    // todos of the shown list others changed since the visitor's last look, see `unread`
+    unseen: HashSet<u64>,
+}
+
+// the list as `ListService::visible` has it, see there
+async fn load_todos(
+    state: &AppState,
+    tenant: &Tenant,
+    visitor: Option<&str>,
+    filter: Option<&ListFilter>,
+) -> Result<(Vec<Todo>, ListState), AppError> {
+    let db = state.read().await.for_tenant(tenant.id())?;
+    let listing = ListService::new(&db).visible(visitor, filter)?;
+    let unseen = match (&listing.filter.list, activity::actor()) {
+        (Some(shown), Some(member)) => unread::unseen_todos(&db, &member, shown)?,
+        _ => HashSet::new(),
+    };
+    let window = listing
+        .page
+        .total_hint
+        .is_some_and(|total| total > filtering::WINDOW_THRESHOLD)
+        .then(|| {
+            Window::of(
+                &listing.page.items,
+                sorting::sort(listing.order),
+                listing.page.has_prev(),
+                listing.page.has_next(),
+            )
+        });
+    let list = ListState {
+        blocked: listing.blocked,
+        reactions: listing.reactions,
+        dates: listing.dates,
+        order: listing.order,
+        completed_open: listing.completed_open,
+        scheduled_shown: listing.scheduled_shown,
+        filter: listing.filter,
+        has_prev: listing.page.has_prev(),
+        next_cursor: listing.page.next_cursor,
+        window,
+        unseen,
+    };
+    Ok((listing.page.items, list))
+}
+
+// the blockers section of `todo`'s detail view
+async fn load_blockers(state: &AppState, tenant: &Tenant, todo: &Todo) -> Result<Markup, AppError> {
+    let (todos, _) = load_todos(state, tenant, None, None).await?;
+    let ids = repository::todo::blockers(&state.read().await.for_tenant(tenant.id())?, todo.id)?;
+    let (blockers, candidates): (Vec<Todo>, Vec<Todo>) = todos
+        .into_iter()
+        .filter(|candidate| candidate.id != todo.id)
+        .partition(|candidate| ids.contains(&candidate.id));
+    Ok(blockers_html(todo, &blockers, &candidates))
+}
+
+// A todo under its own url. Boosted links and direct visits get the full page, the list opens
+// it in the slide-over panel, other htmx requests get only the fragment.
+async fn todo_detail(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    Path(id): Path<u64>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let db = state.read().await.for_tenant(tenant.id())?;
+    let Some(todo) = db.get::<Todo, _>(format!("todo:{}", id))? else {
+        // the links of a todo merged away lead to the one it was merged into
+        return match merge::merged_into(&db, id)? {
+            Some(survivor) => Ok(Redirect::to(&routes::TodoDetail::url(survivor)).into_response()),
+            None => Err(AppError::NotFound),
+        };
+    };
+    let nav = Nav::todos().with_todo(todo.id, &todo.title);
+    let dates = Formatter::load(&state.read().await.for_tenant(tenant.id())?)?;
+    let detail = todo_detail_html(
+        &todo,
+        &dates,
+        editing::load_title(&state, &tenant, &todo).await?,
+        previews::load(&state, &tenant, &todo).await?,
+        goals::load_picker(&state, &tenant, todo.id).await?,
+        html! {
+            (checklist::checklist_html(&todo))
+            (fields::section_html(&todo, &fields::of(&db, &todo)?))
+            (load_blockers(&state, &tenant, &todo).await?)
+            (attachments::load(&state, &tenant, &todo).await?)
+            (cancel::section_html(&todo))
+            (merge::load(&state, &tenant, &todo).await?)
+            (history::tab_html(todo.id))
+            @if state.assistant.is_some() && !todo.completed {
+                (assistant::breakdown_html(todo.id))
+            }
+        },
+    );
+    if panel::is_panel_request(&headers) {
+        return Ok(panel::panel(&todo.title, &nav, detail).into_response());
+    }
+    if views::wants_fragment(&headers) {
+        return Ok(html! {
+            (nav::navigation_oob(&nav))
+            (detail)
+        }
+        .into_response());
     }
-    Ok(todos_html(&todos_vec))
+    let canonical = meta::absolute(&state.config.public_url, &routes::TodoDetail::url(todo.id));
+    let content = html! {
+        (nav::navigation(&nav))
+        (detail)
+    };
+    Ok(views::page_with_meta(&todo.title, &Meta::canonical(canonical), content).into_response())
+}
+
+// a `near:` place, with coordinates when a geocoder is configured and finds it
+async fn locate(state: &AppState, text: String) -> Location {
+    let Some(geocoder) = &state.geocoder else {
+        return Location { text, coords: None };
+    };
+    let db = state.read().await.clone();
+    let coords = geocode::lookup(&db, geocoder.as_ref(), &text)
+        .await
+        .unwrap_or_else(|err| {
+            tracing::warn!(error = %err, "geocoding failed");
+            None
+        });
+    Location { text, coords }
 }
 
 #[derive(Deserialize)]
 struct CreateTodo {
     title: String,
+    // yyyy-mm-dd, empty or missing for none
+    due: Option<String>,
+    // yyyy-mm-dd, the list hides the todo until then
+    scheduled_for: Option<String>,
+    // minutes, empty or missing for none
+    estimate: Option<String>,
 }
 async fn create_todo(
     State(mut app_state): State<AppState>,
-    Form(CreateTodo { title }): Form<CreateTodo>,
-) -> Result<Markup, AppError> {
+    tenant: Tenant,
+    headers: HeaderMap,
+    FormOrJson(CreateTodo {
+        title,
+        due,
+        scheduled_for,
+        estimate,
+    }): FormOrJson<CreateTodo>,
+) -> Result<Response, AppError> {
+    let mut new = NewTodo::parse(
+        &title,
+        due.as_deref().unwrap_or_default(),
+        scheduled_for.as_deref().unwrap_or_default(),
+        estimate.as_deref().unwrap_or_default(),
+    )?;
+    // geocode before taking the lock, the lookup may go out to the network
+    if let Some(place) = new.near.take() {
+        new.location = Some(locate(&app_state, place).await);
+    }
+    let state = app_state.clone();
     let app_state = app_state.write().await;
-    let id = app_state.next_id()?;
-    let todo = Todo::new(id, title);
-    let key = format!("todo:{}", id);
-    app_state.insert(&key, &todo)?;
-    Ok(todo_html(&todo))
+    let db = app_state.for_tenant(tenant.id())?;
+    let todo = TodoService::new(&state, &db, tenant.id())
+        .create(new)
+        .await?;
+    let dates = Formatter::load(&db)?;
+    let item = todo_html(&todo, false, &Reactions::default(), &dates);
+    sync::created(&state.events, tenant.id(), todo.id, item.clone());
+    if views::wants_json(&headers) {
+        let location = routes::ApiTodo::url(todo.id);
+        let created = (
+            StatusCode::CREATED,
+            [(header::LOCATION, location)],
+            Json(TodoResource::from(&todo)),
+        );
+        return Ok(created.into_response());
+    }
+    let fragment = if calendar::is_calendar_request(&headers) {
+        calendar::entry_oob_html(&todo)
+    } else {
+        item
+    };
+    let fragment = html! {
+        (fragment)
+        @if let Some(warning) = stats::capacity_warning(&state, &db, &todo)? {
+            (warning)
+        }
+    };
+    Ok(views::fragment_or_redirect(
+        &headers,
+        fragment,
+        &routes::Root::url(),
+    ))
 }
 
-#[derive(Deserialize)]
+// A recording sent by the mic button, transcribed and then added like typed quick-add text.
+async fn quick_add_audio(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, AppError> {
+    let Some(transcriber) = state.transcriber.clone() else {
+        return Err(AppError::NotFound);
+    };
+    if body.is_empty() {
+        return Err(AppError::Invalid("The recording is empty.".into()));
+    }
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("application/octet-stream");
+    let title = transcriber.transcribe(&body, content_type).await?;
+    if title.is_empty() {
+        return Err(AppError::Invalid(
+            "Nothing was heard in the recording.".into(),
+        ));
+    }
+    tracing::info!(bytes = body.len(), "transcribed a voice quick-add");
+    create_todo(
+        State(state),
+        tenant,
+        headers,
+        FormOrJson(CreateTodo {
+            title,
+            due: None,
+            scheduled_for: None,
+            estimate: None,
+        }),
+    )
+    .await
+}
+
+#[derive(Serialize, Deserialize)]
 struct ToggleTodo {
     id: u64,
 }
 async fn toggle_todo(
     State(mut app_state): State<AppState>,
-    Form(ToggleTodo { id }): Form<ToggleTodo>,
-) -> Result<Markup, AppError> {
+    tenant: Tenant,
+    headers: HeaderMap,
+    Extension(Visitor(visitor)): Extension<Visitor>,
+    FormOrJson(ToggleTodo { id }): FormOrJson<ToggleTodo>,
+) -> Result<Response, AppError> {
+    let state = app_state.clone();
     let app_state = app_state.write().await;
-    let key = format!("todo:{}", id);
-    let mut todo = app_state.get::<Todo, _>(&key)?;
-    if let Some(ref mut todo) = todo {
-        todo.completed = !todo.completed;
-        app_state.insert(&key, &todo)?;
+    let db = app_state.for_tenant(tenant.id())?;
+    let todos = TodoService::new(&state, &db, tenant.id());
+    let todo = todos.toggle(id)?;
+    let blocked = repository::todo::is_blocked(&db, id)?;
+    // finishing a blocker frees the todos that were only waiting for it
+    let unblocked = if todo.completed {
+        todos.unblocked_by(id)?
+    } else {
+        Vec::new()
+    };
+    let dates = Formatter::load(&db)?;
+    sync::changed(
+        &state.events,
+        tenant.id(),
+        html! {
+            (todo_oob_html(&todo, blocked, &reactions::get(&db, id)?, &dates))
+            @for todo in &unblocked {
+                (todo_oob_html(todo, false, &reactions::get(&db, todo.id)?, &dates))
+            }
+        },
+    );
+    if views::wants_json(&headers) {
+        return Ok(Json(TodoResource::from(&todo)).into_response());
     }
-    let todo = todo.unwrap();
-    Ok(todo_html(&todo))
+    let fragment = html! {
+        (todo_html(&todo, blocked, &reactions::get(&db, id)?, &dates))
+        @for todo in &unblocked {
+            (todo_oob_html(todo, false, &reactions::get(&db, todo.id)?, &dates))
+            (views::notice_toast_oob(&format!("\"{}\" is no longer blocked.", todo.title)))
+        }
+        (daily_goal::progress_oob(&db, &visitor, todo.completed)?)
+    };
+    Ok(views::fragment_or_redirect(
+        &headers,
+        fragment,
+        &routes::Root::url(),
+    ))
 }
 
-#[derive(Deserialize)]
+// === Inline editing ===
+// the line item as a form for its title, due date, priority and tags
+fn todo_form_html(todo: &Todo) -> Markup {
+    let update = Hx::patch(routes::UpdateTodo::url())
+        .target(Target::Element(dom::todo(todo.id)))
+        .swap(Swap::OuterHtml);
+    let cancel = Hx::get(routes::TodoItem::url(todo.id))
+        .target(Target::Element(dom::todo(todo.id)))
+        .swap(Swap::OuterHtml);
+    html! {
+        li id=(dom::todo(todo.id)) class="todo-item bg-white rounded-lg shadow-lg my-2 py-2 px-4" {
+            form class="flex flex-wrap gap-2 items-center" method="post" action=(routes::UpdateTodo::url())
+                hx-patch=[update.patch_path()] hx-target=[update.target_attr()] hx-swap=[update.swap_attr()] {
+                input type="hidden" name=(method_override::METHOD_FIELD) value="PATCH";
+                input type="hidden" name="id" value=(todo.id);
+                input class="flex-grow rounded p-2 border" type="text" name="title" value=(todo.title) aria-label="Title" autofocus required;
+                input class="rounded p-2 border" type="date" name="due" value=[todo.due.map(|due| due.to_string())] aria-label="Due date";
+                select class="rounded p-2 border" name="priority" aria-label="Priority" {
+                    @for priority in Priority::ALL {
+                        option value=(priority) selected[priority == todo.priority] { (priority.label()) }
+                    }
+                }
+                (combobox::combobox_html(&dom::tag_picker(todo.id), "tags", &todo.tags.join(" "), "Tags", &routes::TagSuggestions::url(), true))
+                button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" { "Save" }
+                a class="text-gray-500 hover:text-gray-700" href=(routes::Root::url())
+                    hx-get=[cancel.get_path()] hx-target=[cancel.target_attr()] hx-swap=[cancel.swap_attr()] { "Cancel" }
+            }
+        }
+    }
+}
+
+// the line item of `todo` as the list renders it
+fn load_todo_html(db: &Db, todo: &Todo) -> Result<Markup, AppError> {
+    Ok(todo_html(
+        todo,
+        repository::todo::is_blocked(db, todo.id)?,
+        &reactions::get(db, todo.id)?,
+        &Formatter::load(db)?,
+    ))
+}
+
+async fn edit_todo(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    Path(id): Path<u64>,
+) -> Result<Markup, AppError> {
+    let db = state.read().await.for_tenant(tenant.id())?;
+    let todo = Repository::<Todo>::new(&db)
+        .get(id)?
+        .ok_or(AppError::NotFound)?;
+    Ok(todo_form_html(&todo))
+}
+
+// the line item again, closing its form
+async fn todo_item(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    Path(id): Path<u64>,
+) -> Result<Markup, AppError> {
+    let db = state.read().await.for_tenant(tenant.id())?;
+    let todo = Repository::<Todo>::new(&db)
+        .get(id)?
+        .ok_or(AppError::NotFound)?;
+    load_todo_html(&db, &todo)
+}
+
+#[derive(Serialize, Deserialize)]
+struct UpdateTodo {
+    id: u64,
+    title: String,
+    // yyyy-mm-dd, empty or missing for none
+    due: Option<String>,
+    #[serde(default)]
+    priority: Priority,
+    // see `tags::parse_field`
+    tags: Option<String>,
+}
+async fn update_todo(
+    State(mut app_state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Extension(Visitor(visitor)): Extension<Visitor>,
+    FormOrJson(UpdateTodo {
+        id,
+        title,
+        due,
+        priority,
+        tags,
+    }): FormOrJson<UpdateTodo>,
+) -> Result<Response, AppError> {
+    let due = models::parse_due(due.as_deref().unwrap_or_default())
+        .map_err(|err| AppError::Invalid(err.to_string()))?;
+    let tags = tags::parse_field(tags.as_deref().unwrap_or_default())?;
+    let state = app_state.clone();
+    let guard = app_state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    let todo = TodoService::new(&state, &db, tenant.id())
+        .update(id, Some(visitor.as_str()), |todo| {
+            todo.title = title.trim().to_string();
+            todo.due = due;
+            todo.priority = priority;
+            todo.tags = tags;
+            Ok(())
+        })
+        .await?;
+    if views::wants_json(&headers) {
+        return Ok(Json(TodoResource::from(&todo)).into_response());
+    }
+    Ok(views::fragment_or_redirect(
+        &headers,
+        load_todo_html(&db, &todo)?,
+        &routes::Root::url(),
+    ))
+}
+
+#[derive(Serialize, Deserialize)]
+struct ShowCompleted {
+    open: bool,
+}
+// Open or collapse the completed section, remembered for the next visit.
+async fn show_completed(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    Extension(Visitor(visitor)): Extension<Visitor>,
+    Query(filter): Query<ListFilter>,
+    headers: HeaderMap,
+    FormOrJson(ShowCompleted { open }): FormOrJson<ShowCompleted>,
+) -> Result<Response, AppError> {
+    let filter = filter.validate()?;
+    completed::set_open(
+        &state.write().await.for_tenant(tenant.id())?,
+        View::List,
+        &visitor,
+        open,
+    )?;
+    let (todos, list) = load_todos(&state, &tenant, Some(&visitor), Some(&filter)).await?;
+    let done: Vec<&Todo> = todos.iter().filter(|todo| todo.completed).collect();
+    Ok(views::fragment_or_redirect(
+        &headers,
+        completed_html(&done, &list, false),
+        &routes::Root::url(),
+    ))
+}
+
+#[derive(Serialize, Deserialize)]
 struct RemoveTodo {
     id: u64,
 }
 async fn remove_todo(
     State(mut app_state): State<AppState>,
-    Form(RemoveTodo { id }): Form<RemoveTodo>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    FormOrJson(RemoveTodo { id }): FormOrJson<RemoveTodo>,
+) -> Result<Response, AppError> {
+    let state = app_state.clone();
+    let app_state = app_state.write().await;
+    let db = app_state.for_tenant(tenant.id())?;
+    TodoService::new(&state, &db, tenant.id())
+        .remove(id)
+        .await?;
+    sync::removed(&state.events, tenant.id(), id);
+    Ok(views::deleted(&headers, &routes::Root::url()))
+}
+
+async fn todo_palette(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    Path(id): Path<u64>,
 ) -> Result<Markup, AppError> {
+    let db = state.read().await.for_tenant(tenant.id())?;
+    let todo = db
+        .get::<Todo, _>(repository::todo::todo_key(id))?
+        .ok_or(AppError::NotFound)?;
+    Ok(colors::palette_html(
+        id,
+        &colors::palette(&db)?,
+        todo.color.as_deref(),
+    ))
+}
+
+#[derive(Deserialize)]
+struct MoveTodo {
+    // the list to file the todo under, empty for none
+    #[serde(default)]
+    to: String,
+    // the list shown where it was moved, if the page shows one
+    list: Option<String>,
+}
+// File a todo under another list. In the view of a list it left, the item is taken out,
+// otherwise it is swapped for the item showing its new list.
+async fn move_todo(
+    State(mut app_state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Extension(Visitor(visitor)): Extension<Visitor>,
+    Path(id): Path<u64>,
+    FormOrJson(MoveTodo { to, list: shown }): FormOrJson<MoveTodo>,
+) -> Result<Response, AppError> {
+    let to = lists::parse(&to)?;
+    let state = app_state.clone();
+    let guard = app_state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    let todo = TodoService::new(&state, &db, tenant.id())
+        .update(id, Some(visitor.as_str()), |todo| {
+            todo.list = to;
+            Ok(())
+        })
+        .await?;
+    tracing::info!(id, list = ?todo.list, "moved todo");
+    if views::wants_json(&headers) {
+        return Ok(Json(TodoResource::from(&todo)).into_response());
+    }
+    let location = lists::url(todo.list.as_deref());
+    if shown.is_some() && shown != todo.list {
+        return Ok(views::deleted(&headers, &location));
+    }
+    Ok(views::fragment_or_redirect(
+        &headers,
+        load_todo_html(&db, &todo)?,
+        &location,
+    ))
+}
+
+#[derive(Deserialize)]
+struct SetColor {
+    // `#rrggbb`, empty for none
+    color: String,
+}
+async fn set_color(
+    State(mut app_state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Path(id): Path<u64>,
+    FormOrJson(SetColor { color }): FormOrJson<SetColor>,
+) -> Result<Response, AppError> {
+    let color = colors::parse_color(&color)?;
     let app_state = app_state.write().await;
-    let key = format!("todo:{}", id);
-    app_state.remove(&key)?;
-    Ok(html! {})
+    let db = app_state.for_tenant(tenant.id())?;
+    let todo = Repository::<Todo>::new(&db)
+        .update(id, |todo| {
+            todo.color = color.clone();
+            todo.touch(&**db.clock());
+        })?
+        .ok_or(AppError::NotFound)?;
+    let blocked = repository::todo::is_blocked(&db, id)?;
+    Ok(views::fragment_or_redirect(
+        &headers,
+        todo_html(
+            &todo,
+            blocked,
+            &reactions::get(&db, id)?,
+            &Formatter::load(&db)?,
+        ),
+        &routes::Root::url(),
+    ))
+}
+
+#[derive(Deserialize)]
+struct AddBlocker {
+    blocker: u64,
+}
+async fn add_blocker(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Path(id): Path<u64>,
+    FormOrJson(AddBlocker { blocker }): FormOrJson<AddBlocker>,
+) -> Result<Response, AppError> {
+    let todo = {
+        let guard = state.write().await;
+        let db = guard.for_tenant(tenant.id())?;
+        repository::todo::add_blocker(&db, id, blocker)?;
+        db.get::<Todo, _>(format!("todo:{}", id))?
+            .ok_or(AppError::NotFound)?
+    };
+    let blockers = load_blockers(&state, &tenant, &todo).await?;
+    Ok(views::fragment_or_redirect(
+        &headers,
+        blockers,
+        &routes::TodoDetail::url(id),
+    ))
+}
+
+async fn remove_blocker(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Path((id, blocker)): Path<(u64, u64)>,
+) -> Result<Response, AppError> {
+    let todo = {
+        let guard = state.write().await;
+        let db = guard.for_tenant(tenant.id())?;
+        repository::todo::remove_blocker(&db, id, blocker)?;
+        db.get::<Todo, _>(format!("todo:{}", id))?
+            .ok_or(AppError::NotFound)?
+    };
+    let blockers = load_blockers(&state, &tenant, &todo).await?;
+    Ok(views::fragment_or_redirect(
+        &headers,
+        blockers,
+        &routes::TodoDetail::url(id),
+    ))
 }
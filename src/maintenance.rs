@@ -0,0 +1,117 @@
+use std::sync::atomic::Ordering;
+
+use axum::{
+    extract::{Request, State},
+    http::{Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use maud::{html, Markup};
+
+use crate::{config::Config, db::driver::Db, error::ErrorReport, routes, state::AppState};
+
+// set from the admin page, `MAINTENANCE_MODE` wins over it
+const KEY: &str = "maintenance";
+// the element the banner replaces, on every page
+pub const BANNER_ID: &str = "maintenance-banner";
+// the event carrying banner changes to open pages
+pub const BANNER_EVENT: &str = "maintenance";
+
+// === Maintenance ===
+// While on, the app is read-only, e.g. for a backup or a migration. Everything that changes data
+// is refused except the admin pages, so it can be switched off again.
+pub fn stored(db: &Db) -> anyhow::Result<bool> {
+    Ok(db.get(KEY)?.unwrap_or(false))
+}
+pub fn store(db: &Db, on: bool) -> anyhow::Result<()> {
+    db.insert(KEY, &on)
+}
+
+pub fn is_forced(config: &Config) -> bool {
+    config.maintenance_mode
+}
+
+pub fn is_on(state: &AppState) -> bool {
+    is_forced(&state.live.load()) || state.maintenance.load(Ordering::Relaxed)
+}
+
+// Switch it and tell every open page.
+pub fn switch(state: &AppState, db: &Db, on: bool) -> anyhow::Result<()> {
+    store(db, on)?;
+    state.maintenance.store(on, Ordering::Relaxed);
+    announce(state);
+    Ok(())
+}
+
+// show every open page whether it is on, after a switch or a reloaded `MAINTENANCE_MODE`
+pub fn announce(state: &AppState) {
+    state
+        .events
+        .publish_all(BANNER_EVENT, banner_html(is_on(state)).into_string());
+}
+
+fn is_write(method: &Method) -> bool {
+    !matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+// Refuse writes while in maintenance, htmx requests get the message as a toast.
+pub async fn read_only(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let path = request.uri().path();
+    let exempt = path.starts_with("/admin")
+        // a download, nothing is written
+        || path == routes::ExportMyData::url()
+        || path == routes::MigrateExport::url();
+    if is_on(&state) && is_write(request.method()) && !exempt {
+        return ErrorReport::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Down for maintenance, nothing can be changed right now. Try again in a few minutes.",
+        )
+        .into_response();
+    }
+    next.run(request).await
+}
+
+// === Components ===
+// Swapped in out of band, by the lazy load on each page and by the event when switched.
+pub fn banner_html(on: bool) -> Markup {
+    html! {
+        div id=(BANNER_ID) hx-swap-oob="true" {
+            @if on {
+                div class="t-warning bg-yellow-100 text-center py-2 px-4" role="status" {
+                    "Down for maintenance, the app is read-only for now."
+                }
+            }
+        }
+    }
+}
+
+// on every page, fills itself in after load and listens for changes
+pub fn banner_slot() -> Markup {
+    html! {
+        div id=(BANNER_ID) hx-get=(routes::MaintenanceBanner::url()) hx-trigger="load" hx-swap="none" {}
+        div class="hidden" sse-swap=(BANNER_EVENT) hx-swap="none" {}
+    }
+}
+
+// === Routes ===
+pub async fn banner(State(state): State<AppState>) -> Markup {
+    banner_html(is_on(&state))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::TestDb;
+
+    #[test]
+    fn test_stored() -> anyhow::Result<()> {
+        let db = TestDb::new("maintenance")?;
+        assert!(!stored(&db)?);
+        store(&db, true)?;
+        assert!(stored(&db)?);
+        assert!(is_write(&Method::DELETE) && !is_write(&Method::GET));
+        assert!(banner_html(true).into_string().contains("read-only"));
+        Ok(())
+    }
+}
@@ -0,0 +1,276 @@
+use anyhow::Result;
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, HeaderValue},
+    response::{IntoResponse, Redirect, Response},
+    Extension, Json,
+};
+use maud::{html, Markup};
+use serde::Deserialize;
+
+use crate::{
+    api::v1::TodoResource,
+    attachments,
+    auth::visitor::Visitor,
+    db::{driver::Db, queue::WriteOp},
+    error::AppError,
+    extract::FormOrJson,
+    history,
+    models::{Priority, Todo},
+    reactions,
+    repository::{self, entity::Repository, goal, query::TodoQuery},
+    routes,
+    services::todo::TodoService,
+    state::AppState,
+    tenant::Tenant,
+    views,
+};
+
+// `merged_into:{id}`, the todo a merged away todo lives on in
+const TOMBSTONE_PREFIX: &str = "merged_into:";
+// tombstones followed one after the other at most, for todos merged more than once
+const MAX_HOPS: usize = 16;
+const MERGE_ID: &str = "merge";
+
+pub fn tombstone_key(id: u64) -> String {
+    format!("{}{}", TOMBSTONE_PREFIX, id)
+}
+
+// Where the links of todo `id` lead now, `None` for a todo that was never merged away.
+pub fn merged_into(db: &Db, id: u64) -> Result<Option<u64>> {
+    let mut found = None;
+    let mut current = id;
+    for _ in 0..MAX_HOPS {
+        match db.get::<u64, _>(tombstone_key(current))? {
+            Some(next) => {
+                found = Some(next);
+                current = next;
+            }
+            None => break,
+        }
+    }
+    Ok(found)
+}
+
+// === Merging ===
+fn rank(priority: Priority) -> usize {
+    Priority::ALL
+        .iter()
+        .position(|known| *known == priority)
+        .unwrap_or_default()
+}
+
+// `survivor` with what `absorbed` brings: its tags and checklist steps, and whatever the
+// survivor leaves unset. The higher priority wins, the survivor's title and state stay.
+pub fn combine(survivor: &Todo, absorbed: &Todo) -> Todo {
+    let mut todo = survivor.clone();
+    for tag in &absorbed.tags {
+        if !todo.tags.contains(tag) {
+            todo.tags.push(tag.clone());
+        }
+    }
+    for item in &absorbed.checklist {
+        if !todo.checklist.iter().any(|own| own.text == item.text) {
+            todo.checklist.push(item.clone());
+        }
+    }
+    todo.due = todo.due.or(absorbed.due);
+    todo.scheduled_for = todo.scheduled_for.or(absorbed.scheduled_for);
+    todo.estimate_minutes = todo.estimate_minutes.or(absorbed.estimate_minutes);
+    todo.location = todo.location.or_else(|| absorbed.location.clone());
+    todo.color = todo.color.or_else(|| absorbed.color.clone());
+    todo.list = todo.list.or_else(|| absorbed.list.clone());
+    if rank(absorbed.priority) > rank(todo.priority) {
+        todo.priority = absorbed.priority;
+    }
+    todo
+}
+
+// The writes merging `absorbed` into the survivor, `before` as stored and `after` combined. The
+// absorbed todo's attachments, reactions, blockers and goal move over, then it is removed and
+// leaves a tombstone pointing at the survivor.
+pub fn ops(db: &Db, before: &Todo, after: &Todo, absorbed: &Todo) -> Result<Vec<WriteOp>> {
+    let mut ops = history::record_ops(db, before)?;
+    ops.extend(Repository::new(db).put_ops(after)?);
+    ops.extend(attachments::move_ops(db, absorbed.id, after.id)?);
+    ops.extend(reactions::absorb_op(db, absorbed.id, after.id)?);
+    ops.extend(repository::todo::merge_blockers_ops(
+        db,
+        absorbed.id,
+        after.id,
+    )?);
+    if goal::goal_of(db, after.id)?.is_none() {
+        if let Some(goal) = goal::goal_of(db, absorbed.id)? {
+            ops.push(WriteOp::Insert {
+                key: goal::goal_of_key(after.id),
+                value: db.encode(&goal)?,
+            });
+        }
+    }
+    ops.extend(repository::todo::remove_ops(db, absorbed.id)?);
+    ops.push(WriteOp::Insert {
+        key: tombstone_key(absorbed.id),
+        value: db.encode(&after.id)?,
+    });
+    Ok(ops)
+}
+
+// === Components ===
+// Fold a duplicate into a todo, or the todo into the duplicate, from its detail view.
+pub fn section_html(todo: &Todo, candidates: &[Todo]) -> Markup {
+    let url = routes::TodoMerge::url(todo.id);
+    html! {
+        section id=(MERGE_ID) class="space-y-2" {
+            h2 class="text-xl text-gray-700" { "Merge" }
+            @if candidates.is_empty() {
+                p class="text-gray-500" { "There is no other todo to merge with." }
+            } @else {
+                form class="space-y-2" method="post" action=(url) hx-post=(url)
+                    hx-confirm="Merge the two todos? The one not kept is removed." {
+                    select class="w-full rounded p-2 border" name="duplicate" aria-label="Duplicate" {
+                        @for candidate in candidates {
+                            option value=(candidate.id) { (candidate.title) }
+                        }
+                    }
+                    fieldset class="flex items-center space-x-4 text-sm text-gray-700" {
+                        legend class="sr-only" { "Which todo to keep" }
+                        label { input type="radio" name="keep" value="this" checked; " Keep this one" }
+                        label { input type="radio" name="keep" value="duplicate"; " Keep the duplicate" }
+                    }
+                    p class="text-sm text-gray-500" {
+                        "Tags, checklist steps, attachments and reactions end up on the todo kept."
+                    }
+                    button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded" type="submit" { "Merge" }
+                }
+            }
+        }
+    }
+}
+
+pub async fn load(state: &AppState, tenant: &Tenant, todo: &Todo) -> Result<Markup, AppError> {
+    let db = state.read().await.for_tenant(tenant.id())?;
+    let candidates: Vec<Todo> = TodoQuery::new()
+        .list(&db)?
+        .into_iter()
+        .filter(|candidate| candidate.id != todo.id)
+        .collect();
+    Ok(section_html(todo, &candidates))
+}
+
+// === Routes ===
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Keep {
+    #[default]
+    This,
+    Duplicate,
+}
+
+#[derive(Deserialize)]
+pub struct MergeForm {
+    duplicate: u64,
+    #[serde(default)]
+    keep: Keep,
+}
+
+// Merge todo `id` and a duplicate of it, then show the one kept.
+pub async fn merge(
+    State(mut app_state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Extension(Visitor(visitor)): Extension<Visitor>,
+    Path(id): Path<u64>,
+    FormOrJson(MergeForm { duplicate, keep }): FormOrJson<MergeForm>,
+) -> Result<Response, AppError> {
+    let (survivor, absorbed) = match keep {
+        Keep::This => (id, duplicate),
+        Keep::Duplicate => (duplicate, id),
+    };
+    let state = app_state.clone();
+    let guard = app_state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    let todo = TodoService::new(&state, &db, tenant.id())
+        .merge(survivor, absorbed, Some(visitor.as_str()))
+        .await?;
+    tracing::info!(survivor, absorbed, "merged todos");
+    if views::wants_json(&headers) {
+        return Ok(Json(TodoResource::from(&todo)).into_response());
+    }
+    let detail = routes::TodoDetail::url(todo.id);
+    if headers.contains_key("hx-request") {
+        let mut response = html! {}.into_response();
+        if let Ok(location) = HeaderValue::from_str(&detail) {
+            response.headers_mut().insert("hx-redirect", location);
+        }
+        return Ok(response);
+    }
+    Ok(Redirect::to(&detail).into_response())
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        attachments::Attachment,
+        fixtures::{TestDb, TodoFixture},
+        models::ChecklistItem,
+    };
+
+    #[test]
+    fn test_combine() {
+        let mut survivor = Todo::new(1, "Call the plumber".into());
+        survivor.tags = vec!["home".into()];
+        let mut absorbed = Todo::new(2, "Plumber".into());
+        absorbed.tags = vec!["home".into(), "urgent".into()];
+        absorbed.priority = Priority::High;
+        absorbed.checklist = vec![ChecklistItem {
+            text: "Find the number".into(),
+            done: true,
+        }];
+        let combined = combine(&survivor, &absorbed);
+        assert_eq!(combined.title, "Call the plumber");
+        assert_eq!(combined.tags, ["home", "urgent"]);
+        assert_eq!(combined.priority, Priority::High);
+        assert_eq!(combined.checklist.len(), 1);
+    }
+
+    #[test]
+    fn test_ops() -> Result<()> {
+        let db = TestDb::new("merge")?;
+        let survivor = TodoFixture::new().titled("Call the plumber").persist(&db)?;
+        let absorbed = TodoFixture::new()
+            .titled("Plumber")
+            .with_tags(["urgent"])
+            .persist(&db)?;
+        let waiting = TodoFixture::new().titled("Fix the sink").persist(&db)?;
+        repository::todo::add_blocker(&db, waiting.id, absorbed.id)?;
+        let attachment = Attachment {
+            id: db.next_id()?,
+            todo: absorbed.id,
+            name: "quote.pdf".into(),
+            content_type: "application/pdf".into(),
+            size: 3,
+            created_at: 0,
+        };
+        db.apply_batch(attachments::add_ops(&db, &attachment, b"pdf")?)?;
+
+        let after = combine(&survivor, &absorbed);
+        db.apply_batch(ops(&db, &survivor, &after, &absorbed)?)?;
+        let todos = Repository::<Todo>::new(&db);
+        assert!(todos.get(absorbed.id)?.is_none());
+        assert_eq!(todos.ids_by("tag", "urgent")?, [survivor.id]);
+        assert_eq!(
+            attachments::for_todo(&db, survivor.id)?[0].name,
+            "quote.pdf"
+        );
+        assert!(attachments::for_todo(&db, absorbed.id)?.is_empty());
+        assert_eq!(repository::todo::blockers(&db, waiting.id)?, [survivor.id]);
+        // old links lead to the survivor, through later merges too
+        assert_eq!(merged_into(&db, absorbed.id)?, Some(survivor.id));
+        db.insert(tombstone_key(survivor.id), &waiting.id)?;
+        assert_eq!(merged_into(&db, absorbed.id)?, Some(waiting.id));
+        assert_eq!(merged_into(&db, waiting.id)?, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+use axum::{
+    body::{self, Body},
+    extract::Request,
+    http::{header, HeaderName, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::error::ErrorReport;
+
+pub const METHOD_OVERRIDE_HEADER: HeaderName = HeaderName::from_static("x-http-method-override");
+// the hidden form field plain html forms can use instead of the header
+pub const METHOD_FIELD: &str = "_method";
+// forms carrying an override are small, do not buffer anything larger
+const MAX_FORM_BYTES: usize = 64 * 1024;
+
+// Let a `POST` stand in for `PUT`, `PATCH` or `DELETE` where proxies block those, via the
+// `X-HTTP-Method-Override` header or a `_method` form field. Has to wrap the whole router,
+// routing happens on the rewritten method.
+pub async fn method_override(request: Request, next: Next) -> Response {
+    match apply(request).await {
+        Ok(request) => next.run(request).await,
+        Err(report) => report.into_response(),
+    }
+}
+
+async fn apply(mut request: Request) -> Result<Request, ErrorReport> {
+    if request.method() != Method::POST {
+        return Ok(request);
+    }
+    let from_header = request
+        .headers()
+        .get(&METHOD_OVERRIDE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let method = match from_header {
+        Some(method) => Some(method),
+        None if is_urlencoded(&request) => {
+            let (parts, body) = request.into_parts();
+            let bytes = body::to_bytes(body, MAX_FORM_BYTES).await.map_err(|_| {
+                ErrorReport::new(StatusCode::PAYLOAD_TOO_LARGE, "That form is too large.")
+            })?;
+            let method = form_field(&bytes, METHOD_FIELD);
+            request = Request::from_parts(parts, Body::from(bytes));
+            method
+        }
+        None => None,
+    };
+    if let Some(method) = method.and_then(|method| allowed(&method)) {
+        *request.method_mut() = method;
+    }
+    Ok(request)
+}
+
+fn is_urlencoded(request: &Request) -> bool {
+    request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("application/x-www-form-urlencoded"))
+}
+
+// only methods a `POST` may be upgraded to, never `GET` or `CONNECT`
+fn allowed(method: &str) -> Option<Method> {
+    match method.trim().to_ascii_uppercase().as_str() {
+        "PUT" => Some(Method::PUT),
+        "PATCH" => Some(Method::PATCH),
+        "DELETE" => Some(Method::DELETE),
+        _ => None,
+    }
+}
+
+fn form_field(body: &[u8], name: &str) -> Option<String> {
+    let body = std::str::from_utf8(body).ok()?;
+    body.split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .and_then(|(_, value)| urlencoding::decode(&value.replace('+', " ")).ok())
+        .map(|value| value.into_owned())
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn method_after(request: Request) -> Method {
+        apply(request).await.ok().unwrap().method().clone()
+    }
+
+    #[tokio::test]
+    async fn test_header_override() {
+        let request = Request::post("/remove_todo")
+            .header(&METHOD_OVERRIDE_HEADER, "delete")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(method_after(request).await, Method::DELETE);
+    }
+
+    #[tokio::test]
+    async fn test_form_field_override_keeps_body() {
+        let request = Request::post("/create_todo")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from("title=Milk&_method=PUT"))
+            .unwrap();
+        let request = apply(request).await.ok().unwrap();
+        assert_eq!(request.method(), Method::PUT);
+        let body = body::to_bytes(request.into_body(), MAX_FORM_BYTES)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"title=Milk&_method=PUT");
+    }
+
+    #[tokio::test]
+    async fn test_only_post_and_safe_targets() {
+        let request = Request::get("/todos")
+            .header(&METHOD_OVERRIDE_HEADER, "DELETE")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(method_after(request).await, Method::GET);
+        let request = Request::post("/todos")
+            .header(&METHOD_OVERRIDE_HEADER, "GET")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(method_after(request).await, Method::POST);
+    }
+}
@@ -0,0 +1,303 @@
+use anyhow::{anyhow, Result};
+use argon2::Argon2;
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{header, HeaderMap, HeaderName},
+    response::{IntoResponse, Response},
+    Form, Json,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    XChaCha20Poly1305, XNonce,
+};
+use maud::{html, Markup};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bundle::{self, Report},
+    db::driver::Db,
+    error::AppError,
+    lists::{self, Policy},
+    locale::{self, Preferences},
+    repository::query::TodoQuery,
+    routes,
+    state::AppState,
+    tenant::Tenant,
+    theme::{self, Theme},
+    views,
+};
+
+// bumped when the archive changes shape, older instances refuse newer archives
+const VERSION: u32 = 1;
+// the passphrase of an upload, the body is the archive itself
+pub const PASSPHRASE_HEADER: HeaderName = HeaderName::from_static("x-migration-passphrase");
+const MIN_PASSPHRASE: usize = 12;
+const SALT_LEN: usize = 16;
+const RESULT_ID: &str = "migrate-result";
+
+// === Archive ===
+// A whole workspace, to move it to another instance: every todo with its dependencies and
+// attachment listings, the way a bundle has them, the list policies and the preferences.
+// Recreated there under new ids, like a bundle.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Contents {
+    pub version: u32,
+    pub todos: bundle::Contents,
+    pub policies: Vec<(String, Policy)>,
+    pub preferences: Preferences,
+    pub theme: Theme,
+}
+
+// The contents sealed with a passphrase, which never travels with it:
+//
+//     {"version": 1, "salt": "..", "nonce": "..", "ciphertext": ".."}
+//
+// The key is argon2 of the passphrase and salt, the cipher xchacha20-poly1305, so a wrong
+// passphrase and a changed archive both fail to open.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Archive {
+    pub version: u32,
+    pub salt: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+pub fn contents(db: &Db) -> Result<Contents> {
+    let todos = TodoQuery::new()
+        .including_archived()
+        .including_someday()
+        .list(db)?;
+    Ok(Contents {
+        version: VERSION,
+        todos: bundle::contents_of(db, "", todos)?,
+        policies: lists::policies(db)?
+            .into_iter()
+            .filter(|(_, policy)| policy != &Policy::default())
+            .collect(),
+        preferences: locale::get(db)?,
+        theme: theme::get(db)?,
+    })
+}
+
+fn check_passphrase(passphrase: &str) -> Result<(), AppError> {
+    if passphrase.chars().count() < MIN_PASSPHRASE {
+        return Err(AppError::Invalid(format!(
+            "Use a passphrase of at least {} characters.",
+            MIN_PASSPHRASE
+        )));
+    }
+    Ok(())
+}
+
+fn cipher(passphrase: &str, salt: &[u8]) -> Result<XChaCha20Poly1305> {
+    let mut key = [0; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|err| anyhow!("deriving the archive key failed: {}", err))?;
+    XChaCha20Poly1305::new_from_slice(&key).map_err(|_| anyhow!("archive keys are 32 bytes"))
+}
+
+pub fn seal(contents: &Contents, passphrase: &str) -> Result<Archive> {
+    let mut salt = [0; SALT_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let plaintext = serde_json::to_vec(contents)?;
+    let ciphertext = cipher(passphrase, &salt)?
+        .encrypt(&nonce, plaintext.as_slice())
+        .map_err(|_| anyhow!("failed to encrypt the archive"))?;
+    Ok(Archive {
+        version: VERSION,
+        salt: hex::encode(salt),
+        nonce: hex::encode(nonce),
+        ciphertext: STANDARD.encode(ciphertext),
+    })
+}
+
+pub fn open(json: &str, passphrase: &str) -> Result<Contents, AppError> {
+    let not_an_archive = |err: &dyn std::fmt::Display| {
+        AppError::Invalid(format!("not a migration archive: {}", err))
+    };
+    let archive: Archive = serde_json::from_str(json).map_err(|err| not_an_archive(&err))?;
+    if archive.version > VERSION {
+        return Err(AppError::Invalid(format!(
+            "The archive is version {}, this instance reads up to {}.",
+            archive.version, VERSION
+        )));
+    }
+    let salt = hex::decode(&archive.salt).map_err(|err| not_an_archive(&err))?;
+    let nonce = hex::decode(&archive.nonce).map_err(|err| not_an_archive(&err))?;
+    if nonce.len() != 24 {
+        return Err(not_an_archive(&"the nonce is not 24 bytes"));
+    }
+    let ciphertext = STANDARD
+        .decode(&archive.ciphertext)
+        .map_err(|err| not_an_archive(&err))?;
+    let plaintext = cipher(passphrase, &salt)?
+        .decrypt(XNonce::from_slice(&nonce), ciphertext.as_slice())
+        .map_err(|_| {
+            AppError::Invalid(
+                "The passphrase does not open this archive, or it was changed.".into(),
+            )
+        })?;
+    serde_json::from_slice(&plaintext).map_err(|err| not_an_archive(&err))
+}
+
+// Recreate `contents` in `db`: the todos as new ones, the policies and preferences over the ones
+// here. Nothing is written unless every todo is valid.
+pub async fn import(state: &AppState, db: &Db, contents: Contents) -> Result<Report, AppError> {
+    let (ops, report) = bundle::restore_ops(db, contents.todos, false)?;
+    state.writes.submit(db, ops).await?;
+    for (list, policy) in &contents.policies {
+        lists::set_policy(db, list, policy)?;
+    }
+    locale::set(db, contents.preferences)?;
+    theme::set(db, contents.theme)?;
+    Ok(report)
+}
+
+// === Components ===
+pub fn settings_html() -> Markup {
+    html! {
+        section class="bg-white rounded-lg shadow-lg p-6 space-y-4" {
+            h2 class="text-2xl text-gray-700" { "Move to another instance" }
+            p class="text-gray-600" {
+                "Download everything here as one archive, locked with a passphrase, and import it on the other instance. "
+                "Todos are added there as new ones, lists, list settings and preferences come along."
+            }
+            // a plain download, posted so the passphrase stays out of urls, history and logs
+            form class="flex items-center space-x-2" method="post" action=(routes::MigrateExport::url()) {
+                input class="flex-grow rounded border p-2" type="password" name="passphrase" required
+                    minlength=(MIN_PASSPHRASE) autocomplete="new-password" aria-label="Passphrase for the archive" placeholder="Passphrase";
+                button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" { "Download archive" }
+            }
+            div class="flex items-center space-x-2" {
+                input id="migrate-passphrase" class="flex-grow rounded border p-2" type="password" autocomplete="off"
+                    aria-label="Passphrase of the archive to import" placeholder="Passphrase of the archive";
+                label class="text-gray-600" {
+                    "Import an archive "
+                    input type="file" accept=".json,application/json" data-url=(routes::ImportMigration::url())
+                        onchange={ "const file = this.files[0]; if (!file) return;
+                            const passphrase = document.getElementById('migrate-passphrase').value;
+                            fetch(this.dataset.url, { method: 'POST', body: file, headers: { 'Content-Type': 'application/json', 'HX-Request': 'true', '" (PASSPHRASE_HEADER) "': passphrase } })
+                                .then((response) => response.text())
+                                .then((text) => { document.getElementById('" (RESULT_ID) "').innerHTML = text; });" };
+                }
+            }
+            p id=(RESULT_ID) class="text-gray-700" role="status" {}
+        }
+    }
+}
+
+fn report_html(report: &Report) -> Markup {
+    html! {
+        (report.todos) " todos moved in, with your lists and preferences."
+        @if report.attachments_left > 0 {
+            " " (report.attachments_left) " attachments stayed behind, their files are on the old instance."
+        }
+    }
+}
+
+// === Routes ===
+#[derive(Deserialize)]
+pub struct ExportForm {
+    passphrase: String,
+}
+
+pub async fn export(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    Form(ExportForm { passphrase }): Form<ExportForm>,
+) -> Result<Response, AppError> {
+    check_passphrase(&passphrase)?;
+    let contents = contents(&state.read().await.for_tenant(tenant.id())?)?;
+    // argon2 takes a moment, off the async workers
+    let archive = tokio::task::spawn_blocking(move || seal(&contents, &passphrase))
+        .await
+        .map_err(anyhow::Error::from)??;
+    tracing::info!(tenant = ?tenant.id(), "exported migration archive");
+    let filename = format!("{}-migration.json", tenant.id().unwrap_or("todos"));
+    Ok((
+        [(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", filename),
+        )],
+        Json(archive),
+    )
+        .into_response())
+}
+
+// The archive is the request body, like a bundle upload.
+pub async fn upload(
+    State(mut app_state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, AppError> {
+    let state = app_state.clone();
+    let passphrase = headers
+        .get(&PASSPHRASE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let text = String::from_utf8(body.to_vec())
+        .map_err(|_| AppError::Invalid("The upload is not utf-8 text.".into()))?;
+    let contents = tokio::task::spawn_blocking(move || open(&text, &passphrase))
+        .await
+        .map_err(anyhow::Error::from)??;
+    let guard = app_state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    let report = import(&state, &db, contents).await?;
+    tracing::info!(tenant = ?tenant.id(), todos = report.todos, "imported migration archive");
+    if views::wants_json(&headers) {
+        return Ok(Json(report).into_response());
+    }
+    Ok(views::fragment_or_redirect(
+        &headers,
+        report_html(&report),
+        &routes::Root::url(),
+    ))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixtures::TestDb, locale::Locale, models::Todo, repository::entity::Repository};
+
+    #[tokio::test]
+    async fn test_round_trip() -> Result<(), AppError> {
+        let from = TestDb::new("migrate-from")?;
+        let mut milk = Todo::new(from.next_id()?, "Milk".into());
+        milk.list = Some("groceries".into());
+        from.apply_batch(Repository::new(&from).put_ops(&milk)?)?;
+        let policy = Policy {
+            archive_after_days: Some(7),
+        };
+        lists::set_policy(&from, "groceries", &policy)?;
+        locale::set(
+            &from,
+            Preferences {
+                locale: Locale::De,
+                utc_offset_minutes: 60,
+            },
+        )?;
+
+        let archive = serde_json::to_string(&seal(&contents(&from)?, "correct horse battery")?)?;
+        assert!(!archive.contains("Milk"));
+        assert!(open(&archive, "wrong horse battery").is_err());
+        let contents = open(&archive, "correct horse battery")?;
+
+        let to = TestDb::new("migrate-to")?;
+        let (ops, report) = bundle::restore_ops(&to, contents.todos.clone(), false)?;
+        to.apply_batch(ops)?;
+        assert_eq!(report.todos, 1);
+        let moved = TodoQuery::new().list(&to)?;
+        assert_eq!(moved[0].list.as_deref(), Some("groceries"));
+        assert_eq!(contents.policies, [("groceries".to_string(), policy)]);
+        assert_eq!(contents.preferences.locale, Locale::De);
+        Ok(())
+    }
+}
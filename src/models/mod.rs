@@ -1,17 +1,462 @@
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use anyhow::{anyhow, Context};
 use serde::{Deserialize, Serialize};
+use time::{macros::format_description, Date};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use crate::clock::{Clock, SystemClock};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Todo {
     pub id: u64,
     pub title: String,
     pub completed: bool,
+    // the board column, `completed` follows it so list and board agree
+    pub status: Status,
+    pub due: Option<Date>,
+    // unix seconds of the last change, the weekly review picks up todos left alone too long
+    pub updated_at: u64,
+    // out of the list, board and calendar but kept
+    pub archived: bool,
+    // how long the todo is expected to take
+    pub estimate_minutes: Option<u32>,
+    pub location: Option<Location>,
+    // `#rrggbb`, the accent the todo is drawn with
+    pub color: Option<String>,
+    // bumped on every change, the api hands it out as the todo's etag
+    pub version: u64,
+    // normalized, see `tags::normalize`
+    pub tags: Vec<String>,
+    // the day work can start, the list leaves it out until then
+    pub scheduled_for: Option<Date>,
+    // small steps ticked off inside the todo, not todos of their own
+    pub checklist: Vec<ChecklistItem>,
+    pub priority: Priority,
+    // the list it is filed under, normalized like a tag, `None` for none
+    pub list: Option<String>,
+    // how it came to an end, `None` while it is open
+    pub resolution: Option<Resolution>,
+    // values of the custom fields of its list by field name, see `fields`. Bundles from before
+    // them have none.
+    #[serde(default)]
+    pub fields: BTreeMap<String, String>,
 }
 impl Todo {
+    // stamped with the system clock, handlers use `created` with theirs
     pub fn new(id: u64, title: String) -> Self {
+        Self::created(id, title, &SystemClock)
+    }
+    pub fn created(id: u64, title: String, clock: &dyn Clock) -> Self {
         Self {
             id,
             title,
             completed: false,
+            status: Status::Backlog,
+            due: None,
+            updated_at: clock.now_millis() / 1000,
+            archived: false,
+            estimate_minutes: None,
+            location: None,
+            color: None,
+            version: 1,
+            tags: Vec::new(),
+            scheduled_for: None,
+            checklist: Vec::new(),
+            priority: Priority::Normal,
+            list: None,
+            resolution: None,
+            fields: BTreeMap::new(),
+        }
+    }
+
+    // scheduled to start after `today`
+    pub fn is_scheduled_after(&self, today: Date) -> bool {
+        self.scheduled_for.is_some_and(|day| day > today)
+    }
+
+    // `(done, total)` of the checklist, `None` without one
+    pub fn checklist_progress(&self) -> Option<(usize, usize)> {
+        if self.checklist.is_empty() {
+            return None;
+        }
+        let done = self.checklist.iter().filter(|item| item.done).count();
+        Some((done, self.checklist.len()))
+    }
+
+    // A new version as of now, once whatever changed the todo is done with it.
+    pub fn touch(&mut self, clock: &dyn Clock) {
+        self.updated_at = clock.now_millis() / 1000;
+        self.version += 1;
+    }
+
+    pub fn set_status(&mut self, status: Status) {
+        self.status = status;
+        self.completed = status == Status::Done;
+        self.settle();
+    }
+    // A done todo is resolved, as done unless it was cancelled, an open one is not.
+    pub fn settle(&mut self) {
+        self.resolution = if self.completed {
+            Some(self.resolution.take().unwrap_or(Resolution::Done))
+        } else {
+            None
+        };
+    }
+    // finished without being done, the reason is for whoever looks back at it
+    pub fn cancel(&mut self, reason: Option<String>) {
+        self.set_status(Status::Done);
+        self.resolution = Some(Resolution::Cancelled { reason });
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.completed && matches!(self.resolution, Some(Resolution::Cancelled { .. }))
+    }
+    // checking a todo off moves it to done, unchecking it back to the backlog
+    pub fn set_completed(&mut self, completed: bool) {
+        self.set_status(if completed {
+            Status::Done
+        } else {
+            Status::Backlog
+        });
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChecklistItem {
+    pub text: String,
+    pub done: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    #[default]
+    Backlog,
+    InProgress,
+    Done,
+    // put aside for some day, left out of the list, the board and the review
+    Someday,
+}
+impl Status {
+    pub const ALL: [Status; 4] = [
+        Status::Backlog,
+        Status::InProgress,
+        Status::Done,
+        Status::Someday,
+    ];
+    // the columns of the board, someday todos stay off it
+    pub const COLUMNS: [Status; 3] = [Status::Backlog, Status::InProgress, Status::Done];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Status::Backlog => "Backlog",
+            Status::InProgress => "In Progress",
+            Status::Done => "Done",
+            Status::Someday => "Someday",
+        }
+    }
+}
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Status::Backlog => "backlog",
+            Status::InProgress => "in_progress",
+            Status::Done => "done",
+            Status::Someday => "someday",
+        })
+    }
+}
+impl FromStr for Status {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Status::ALL
+            .into_iter()
+            .find(|status| status.to_string() == s)
+            .ok_or_else(|| anyhow!("unknown status `{}`", s))
+    }
+}
+
+// The end of a todo: done, or cancelled as won't do.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Resolution {
+    Done,
+    Cancelled { reason: Option<String> },
+}
+impl Resolution {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Resolution::Done => "Done",
+            Resolution::Cancelled { .. } => "Cancelled",
+        }
+    }
+    pub fn reason(&self) -> Option<&str> {
+        match self {
+            Resolution::Done => None,
+            Resolution::Cancelled { reason } => reason.as_deref(),
+        }
+    }
+}
+impl fmt::Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Resolution::Done => "done",
+            Resolution::Cancelled { .. } => "cancelled",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+impl Priority {
+    pub const ALL: [Priority; 3] = [Priority::Low, Priority::Normal, Priority::High];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Priority::Low => "Low",
+            Priority::Normal => "Normal",
+            Priority::High => "High",
+        }
+    }
+}
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "high",
+        })
+    }
+}
+impl FromStr for Priority {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Priority::ALL
+            .into_iter()
+            .find(|priority| priority.to_string() == s)
+            .ok_or_else(|| anyhow!("unknown priority `{}`", s))
+    }
+}
+
+// where a todo happens, coordinates are filled in by geocoding when it is configured
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Location {
+    pub text: String,
+    pub coords: Option<Coords>,
+}
+impl Location {
+    // an OpenStreetMap link, to the pin when there are coordinates or a search otherwise
+    pub fn map_url(&self) -> String {
+        match self.coords {
+            Some(Coords { lat, lng }) => format!(
+                "https://www.openstreetmap.org/?mlat={0}&mlon={1}#map=16/{0}/{1}",
+                lat, lng
+            ),
+            None => format!(
+                "https://www.openstreetmap.org/search?query={}",
+                urlencoding::encode(&self.text)
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Coords {
+    pub lat: f64,
+    pub lng: f64,
+}
+
+// An outcome todos contribute to, linked through `goal_of:{todo}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Goal {
+    pub id: u64,
+    pub title: String,
+}
+
+// how many of a goal's linked todos are done
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Progress {
+    pub done: usize,
+    pub total: usize,
+}
+impl Progress {
+    pub fn percent(self) -> usize {
+        (self.done * 100).checked_div(self.total).unwrap_or(0)
+    }
+}
+
+// Parse a due date as sent by `<input type="date">`, an empty field means no due date.
+pub fn parse_due(value: &str) -> anyhow::Result<Option<Date>> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(None);
+    }
+    Date::parse(value, format_description!("[year]-[month]-[day]"))
+        .map(Some)
+        .with_context(|| format!("`{}` is not a date", value))
+}
+
+// Take a `near:place` quick-add token out of a title, `near:"two words"` for places with
+// spaces. Returns the remaining title and the place.
+pub fn parse_near(title: &str) -> (String, Option<String>) {
+    let Some(start) = title.find("near:") else {
+        return (title.to_string(), None);
+    };
+    if start > 0 && !title[..start].ends_with(char::is_whitespace) {
+        return (title.to_string(), None);
+    }
+    let rest = &title[start + "near:".len()..];
+    let (place, after) = match rest.strip_prefix('"') {
+        Some(quoted) => match quoted.split_once('"') {
+            Some((place, after)) => (place, after),
+            None => (quoted, ""),
+        },
+        None => rest.split_once(char::is_whitespace).unwrap_or((rest, "")),
+    };
+    let remaining = format!("{} {}", title[..start].trim_end(), after.trim_start());
+    let place = place.trim();
+    (
+        remaining.trim().to_string(),
+        (!place.is_empty()).then(|| place.to_string()),
+    )
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use proptest::{collection, option, prelude::*, sample};
+
+    use super::*;
+    use crate::db::codec::{Codec, Keyring};
+
+    fn arb_date() -> impl Strategy<Value = Date> {
+        (1900i32..2200, 1u16..=365)
+            .prop_map(|(year, day)| Date::from_ordinal_date(year, day).unwrap())
+    }
+
+    prop_compose! {
+        fn arb_location()(
+            text in ".{0,40}",
+            coords in option::of((-90.0..90.0f64, -180.0..180.0f64)),
+        ) -> Location {
+            Location {
+                text,
+                coords: coords.map(|(lat, lng)| Coords { lat, lng }),
+            }
         }
     }
+
+    prop_compose! {
+        fn arb_todo()(
+            id in any::<u64>(),
+            title in ".{0,200}",
+            status in sample::select(Status::ALL.to_vec()),
+            due in option::of(arb_date()),
+            updated_at in any::<u64>(),
+            archived in any::<bool>(),
+            estimate_minutes in option::of(any::<u32>()),
+            location in option::of(arb_location()),
+            color in option::of("#[0-9a-f]{6}"),
+            version in any::<u64>(),
+            tags in collection::vec("[a-z0-9_-]{1,32}", 0..4),
+            scheduled_for in option::of(arb_date()),
+            checklist in collection::vec((".{0,40}", any::<bool>()), 0..4),
+            priority in sample::select(Priority::ALL.to_vec()),
+            list in option::of("[a-z0-9_-]{1,32}"),
+            reason in option::of(option::of(".{0,40}")),
+            fields in collection::vec(("[a-z0-9_-]{1,32}", ".{0,40}"), 0..3),
+        ) -> Todo {
+            Todo {
+                id,
+                title,
+                completed: status == Status::Done,
+                status,
+                due,
+                updated_at,
+                archived,
+                estimate_minutes,
+                location,
+                color,
+                version,
+                tags,
+                scheduled_for,
+                checklist: checklist
+                    .into_iter()
+                    .map(|(text, done)| ChecklistItem { text, done })
+                    .collect(),
+                priority,
+                list,
+                resolution: (status == Status::Done).then(|| match reason {
+                    Some(reason) => Resolution::Cancelled { reason },
+                    None => Resolution::Done,
+                }),
+                fields: fields.into_iter().collect(),
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_todo_round_trips_through_codec(todo in arb_todo()) {
+            let keyring = Keyring::from_keys(vec![vec![7; 32]]).unwrap();
+            let codecs = [
+                Codec::new(),
+                Codec::new().with_compression(64),
+                Codec::new().with_compression(64).with_keyring(keyring),
+            ];
+            for codec in codecs {
+                let bytes = codec.encode(&todo, b"todo:1").unwrap();
+                prop_assert_eq!(codec.decode::<Todo>(&bytes, b"todo:1").unwrap(), todo.clone());
+            }
+        }
+
+        #[test]
+        fn test_quick_add_never_panics(title in any::<String>()) {
+            let (rest, _) = parse_near(&title);
+            prop_assert!(rest.len() <= title.len());
+            let _ = parse_due(&title);
+        }
+    }
+
+    #[test]
+    fn test_cancel() {
+        let mut todo = Todo::new(1, "Renew the gym".into());
+        todo.cancel(Some("moved away".into()));
+        assert!(todo.completed && todo.is_cancelled());
+        assert_eq!(
+            todo.resolution.as_ref().and_then(Resolution::reason),
+            Some("moved away")
+        );
+        // done again it stays cancelled, reopened it is open like any other
+        todo.set_status(Status::Done);
+        assert!(todo.is_cancelled());
+        todo.set_completed(false);
+        assert_eq!(todo.resolution, None);
+        todo.set_completed(true);
+        assert_eq!(todo.resolution, Some(Resolution::Done));
+    }
+
+    #[test]
+    fn test_parse_near() {
+        assert_eq!(
+            parse_near("buy milk near:Tesco today"),
+            ("buy milk today".to_string(), Some("Tesco".to_string()))
+        );
+        assert_eq!(
+            parse_near(r#"picnic near:"Central Park""#),
+            ("picnic".to_string(), Some("Central Park".to_string()))
+        );
+        assert_eq!(
+            parse_near("email linear:ticket"),
+            ("email linear:ticket".to_string(), None)
+        );
+        assert_eq!(parse_near("near:"), (String::new(), None));
+    }
 }
@@ -0,0 +1,282 @@
+use std::collections::HashSet;
+
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    response::{IntoResponse, Redirect, Response},
+    Extension, Form,
+};
+use maud::{html, Markup, PreEscaped};
+use serde::Deserialize;
+
+use crate::{
+    auth::visitor::Visitor,
+    error::AppError,
+    filtering::{self, ListFilter, StatusFilter},
+    models::Todo,
+    routes,
+    services::bulk::{BulkService, Command, Preview},
+    snapshots,
+    state::AppState,
+    tenant::Tenant,
+    views::{
+        self,
+        nav::{self, Nav},
+    },
+};
+
+pub const PALETTE_ID: &str = "command-palette";
+const INPUT_ID: &str = "palette-command";
+const RESULT_ID: &str = "palette-result";
+// sent once a command ran, the list refreshes on it
+pub const APPLIED_EVENT: &str = "bulk-applied";
+// changes listed in a preview, the rest are counted
+const MAX_SHOWN: usize = 10;
+
+// Ctrl+K, or ⌘K on a mac, opens the palette from anywhere on the list.
+const PALETTE_SCRIPT: &str = r#"
+document.addEventListener("keydown", function (evt) {
+    const palette = document.getElementById("command-palette");
+    if (!palette || evt.key.toLowerCase() !== "k" || !(evt.ctrlKey || evt.metaKey)) return;
+    evt.preventDefault();
+    if (!palette.open) palette.showModal();
+    document.getElementById("palette-command").select();
+});
+"#;
+
+// === Components ===
+// Commands run on every todo the list shows, a preview first and then once confirmed. The
+// filter of the list goes along with the command, see `filtering::include_attr`.
+pub fn palette_html() -> Markup {
+    let open = format!(
+        "document.getElementById('{}').showModal(); document.getElementById('{}').select()",
+        PALETTE_ID, INPUT_ID
+    );
+    let url = routes::Palette::url();
+    html! {
+        button class="text-sm t-muted hover:underline whitespace-nowrap" type="button" title="Commands (Ctrl+K)" onclick=(open) { "Commands" }
+        dialog id=(PALETTE_ID) class="w-full max-w-xl rounded-lg p-4 shadow-xl space-y-2" aria-label="Commands" {
+            form class="flex items-center" method="get" action=(url) hx-get=(url) hx-target={ "#" (RESULT_ID) } hx-include=(filtering::include_attr()) {
+                input id=(INPUT_ID) class="flex-grow rounded p-2 mr-2 border" type="text" name="command" required autocomplete="off"
+                    placeholder="tag all overdue as #urgent" aria-label="Command" aria-describedby={ (INPUT_ID) "-hint" };
+                button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded" type="submit" { "Preview" }
+            }
+            p id={ (INPUT_ID) "-hint" } class="text-xs t-muted" {
+                "Runs on the todos shown: tag, untag, push due dates by 1 week, move to a list, complete. "
+                "Add overdue or open to narrow it down."
+            }
+            div id=(RESULT_ID) aria-live="polite" {}
+        }
+        script { (PreEscaped(PALETTE_SCRIPT)) }
+    }
+}
+
+// what a command changes on one todo
+fn change_html(before: &Todo, after: &Todo) -> Markup {
+    let added: Vec<&String> = after
+        .tags
+        .iter()
+        .filter(|tag| !before.tags.contains(tag))
+        .collect();
+    let removed: Vec<&String> = before
+        .tags
+        .iter()
+        .filter(|tag| !after.tags.contains(tag))
+        .collect();
+    html! {
+        span class="ml-2 t-muted" {
+            @for tag in added { "+#" (tag) " " }
+            @for tag in removed { "−#" (tag) " " }
+            @if let (Some(from), Some(to)) = (before.due, after.due) {
+                @if from != to { "due " (from) " → " (to) }
+            }
+            @if before.list != after.list { "→ " (after.list.as_deref().unwrap_or_default()) }
+            @if !before.completed && after.completed { "done" }
+        }
+    }
+}
+
+// How many todos a command changes and how, with the button running it on exactly those.
+fn preview_html(preview: &Preview, query: &CommandQuery) -> Markup {
+    let url = routes::PaletteRun::url();
+    let ids: Vec<String> = preview.ids().iter().map(u64::to_string).collect();
+    let changing = preview.changes.len();
+    html! {
+        div class="space-y-2 text-gray-700" {
+            p {
+                (preview.command.describe()) ": "
+                strong { (changing) } " of " (preview.covered) " todos change."
+            }
+            @if preview.locked > 0 {
+                p class="text-sm t-warning" { (preview.locked) " are being edited by someone else and are left alone." }
+            }
+            @if changing > 0 {
+                ul class="text-sm max-h-48 overflow-y-auto" {
+                    @for (before, after) in preview.changes.iter().take(MAX_SHOWN) {
+                        li { (before.title) (change_html(before, after)) }
+                    }
+                }
+                @if changing > MAX_SHOWN {
+                    p class="text-sm t-muted" { "and " (changing - MAX_SHOWN) " more" }
+                }
+                form method="post" action=(url) hx-post=(url) hx-target={ "#" (RESULT_ID) }
+                    "hx-on::after-request"="if (event.detail.successful) this.closest('dialog')?.close()" {
+                    input type="hidden" name="command" value=(query.command);
+                    @if let Some(list) = &query.list {
+                        input type="hidden" name="list" value=(list);
+                    }
+                    input type="hidden" name="status" value=(query.status.as_str());
+                    input type="hidden" name="q" value=(query.q);
+                    input type="hidden" name="ids" value=(ids.join(","));
+                    label class="block text-sm mb-2" {
+                        input class="mr-2" type="checkbox" name="snapshot" value="on" checked;
+                        "Take a snapshot first, to put a list back from in the settings"
+                    }
+                    button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded" type="submit" {
+                        "Change " (changing) " todos"
+                    }
+                }
+            }
+        }
+    }
+}
+
+// === Routes ===
+// a command and the filter of the list it runs on
+#[derive(Debug, Deserialize)]
+pub struct CommandQuery {
+    command: String,
+    list: Option<String>,
+    #[serde(default)]
+    status: StatusFilter,
+    #[serde(default)]
+    q: String,
+}
+impl CommandQuery {
+    fn filter(&self) -> ListFilter {
+        ListFilter {
+            list: self.list.clone().filter(|list| !list.is_empty()),
+            status: self.status,
+            q: self.q.clone(),
+            ..ListFilter::default()
+        }
+    }
+}
+
+// what a command would change, into the palette or as a page of its own without javascript
+pub async fn preview(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Extension(Visitor(visitor)): Extension<Visitor>,
+    Query(query): Query<CommandQuery>,
+) -> Result<Markup, AppError> {
+    let command = Command::parse(&query.command)?;
+    let db = state.read().await.for_tenant(tenant.id())?;
+    let preview = BulkService::new(&state, &db, tenant.id()).preview(
+        &query.filter(),
+        Some(visitor.as_str()),
+        command,
+    )?;
+    let fragment = preview_html(&preview, &query);
+    if views::wants_fragment(&headers) {
+        return Ok(fragment);
+    }
+    Ok(views::page(
+        "Commands",
+        html! {
+            (nav::navigation(&Nav::todos()))
+            section class="bg-white rounded-lg shadow-lg p-6" { (fragment) }
+        },
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RunForm {
+    #[serde(flatten)]
+    query: CommandQuery,
+    // the todos the preview showed, comma separated
+    #[serde(default)]
+    ids: String,
+    // checked, a snapshot is taken before anything changes
+    #[serde(default)]
+    snapshot: Option<String>,
+}
+
+// Run a command on the todos its preview showed, then refresh the list.
+pub async fn run(
+    State(mut app_state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Extension(Visitor(visitor)): Extension<Visitor>,
+    Form(RunForm {
+        query,
+        ids,
+        snapshot,
+    }): Form<RunForm>,
+) -> Result<Response, AppError> {
+    let command = Command::parse(&query.command)?;
+    let confirmed: HashSet<u64> = ids
+        .split(',')
+        .filter_map(|id| id.trim().parse().ok())
+        .collect();
+    let filter = query.filter();
+    let state = app_state.clone();
+    let guard = app_state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    if snapshot.is_some() {
+        let (_, ops) = snapshots::take_ops(&db, &format!("Before `{}`", query.command.trim()))?;
+        state.writes.submit(&db, ops).await?;
+    }
+    let changed = BulkService::new(&state, &db, tenant.id())
+        .run(&filter, Some(visitor.as_str()), command, &confirmed)
+        .await?;
+    tracing::info!(changed = changed.len(), command = %query.command, "ran a bulk command");
+    if !headers.contains_key("hx-request") {
+        return Ok(Redirect::to(&filter.url()).into_response());
+    }
+    let notice = format!("Changed {} todos.", changed.len());
+    Ok((
+        [("hx-trigger", APPLIED_EVENT)],
+        html! {
+            p class="text-gray-700" { (notice) }
+            (views::notice_toast_oob(&notice))
+        },
+    )
+        .into_response())
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::bulk::Action;
+
+    #[test]
+    fn test_preview_html() {
+        let before = Todo::new(7, "File taxes".into());
+        let mut after = before.clone();
+        after.tags.push("urgent".into());
+        let command = Command::parse("tag all #urgent").unwrap();
+        assert_eq!(command.action, Action::Tag("urgent".into()));
+        let preview = Preview {
+            command,
+            covered: 2,
+            changes: vec![(before, after)],
+            locked: 0,
+        };
+        let query = CommandQuery {
+            command: "tag all #urgent".into(),
+            list: None,
+            status: StatusFilter::Active,
+            q: String::new(),
+        };
+        let html = preview_html(&preview, &query).into_string();
+        assert!(html.contains("<strong>1</strong> of 2 todos change."));
+        assert!(html.contains("+#urgent"));
+        // the run covers what was previewed, on the same filter
+        assert!(html.contains(r#"name="ids" value="7""#));
+        assert!(html.contains(r#"name="status" value="active""#));
+        assert!(html.contains(r#"name="snapshot" value="on" checked"#));
+    }
+}
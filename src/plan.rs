@@ -0,0 +1,366 @@
+use std::fmt;
+
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    response::Response,
+    Extension,
+};
+use maud::{html, Markup, PreEscaped};
+use serde::Deserialize;
+use time::{Date, Duration};
+
+use crate::{
+    auth::visitor::Visitor,
+    db::driver::Db,
+    domain::events::CHANGED_EVENT,
+    error::AppError,
+    extract::FormOrJson,
+    locale::Formatter,
+    method_override,
+    models::{self, Todo},
+    repository::query::{Sort, TodoQuery},
+    routes,
+    services::todo::TodoService,
+    state::AppState,
+    stats::format_minutes,
+    tenant::Tenant,
+    views::{
+        self, dom,
+        nav::{self, Nav},
+    },
+};
+
+// days shown, today first
+const DAYS: i64 = 7;
+
+// dragging a card onto a column patches its day, the response redraws both columns
+const PLAN_SCRIPT: &str = r##"
+document.addEventListener("dragstart", function (evt) {
+    const card = evt.target.closest && evt.target.closest("[data-schedule-url]");
+    if (card) evt.dataTransfer.setData("text/plain", card.dataset.scheduleUrl);
+});
+function dropOnDay(evt, day) {
+    evt.preventDefault();
+    const url = evt.dataTransfer.getData("text/plain");
+    if (url) htmx.ajax("PATCH", url, { swap: "none", values: { day: day } });
+}
+"##;
+
+// === Slots ===
+// Where a todo sits on the plan: a day of the week or the tray of todos without one. Todos
+// scheduled before today are still to do, they sit on today.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Slot {
+    Unscheduled,
+    Day(Date),
+}
+impl Slot {
+    pub fn of(todo: &Todo, today: Date) -> Self {
+        match todo.scheduled_for {
+            Some(day) => Self::Day(day.max(today)),
+            None => Self::Unscheduled,
+        }
+    }
+
+    // `unscheduled` or `2024-03-06`, as in urls and the form
+    pub fn parse(value: &str) -> Result<Self, AppError> {
+        if value == "unscheduled" {
+            return Ok(Self::Unscheduled);
+        }
+        match models::parse_due(value) {
+            Ok(Some(day)) => Ok(Self::Day(day)),
+            _ => Err(AppError::Invalid(format!("`{}` is not a day", value))),
+        }
+    }
+
+    pub fn scheduled_for(self) -> Option<Date> {
+        match self {
+            Self::Unscheduled => None,
+            Self::Day(day) => Some(day),
+        }
+    }
+
+    fn column_id(self) -> String {
+        format!("plan-{}", self)
+    }
+}
+impl fmt::Display for Slot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unscheduled => f.write_str("unscheduled"),
+            Self::Day(day) => write!(f, "{}", day),
+        }
+    }
+}
+
+// the tray, then today and the six days after it
+pub fn slots(today: Date) -> Vec<Slot> {
+    let mut slots = vec![Slot::Unscheduled];
+    slots.extend((0..DAYS).map(|days| Slot::Day(today + Duration::days(days))));
+    slots
+}
+
+// open todos in `slot`, soonest due first
+pub fn todos_in(db: &Db, slot: Slot, today: Date) -> anyhow::Result<Vec<Todo>> {
+    Ok(TodoQuery::new()
+        .completed(false)
+        .sort(Sort::Due)
+        .list(db)?
+        .into_iter()
+        .filter(|todo| Slot::of(todo, today) == slot)
+        .collect())
+}
+
+// === Components ===
+fn card_html(todo: &Todo, slots: &[Slot], today: Date, dates: &Formatter) -> Markup {
+    let url = routes::TodoSchedule::url(todo.id);
+    let current = Slot::of(todo, today);
+    html! {
+        div id=(dom::card(todo.id)) class="bg-white rounded-lg shadow p-2 cursor-move space-y-1" draggable="true"
+            data-schedule-url=(url) {
+            a class="text-gray-700 hover:underline" href=(routes::TodoDetail::url(todo.id)) { (todo.title) }
+            @if let Some(minutes) = todo.estimate_minutes {
+                span class="ml-1 text-xs text-gray-500" { (format_minutes(minutes)) }
+            }
+            // moving without dragging, for keyboards and without javascript
+            form method="post" action=(url) hx-patch=(url) hx-trigger="change" hx-swap="none" {
+                input type="hidden" name=(method_override::METHOD_FIELD) value="PATCH";
+                select class="text-xs rounded border p-1" name="day" aria-label="Day" {
+                    @for slot in slots {
+                        option value=(slot) selected[*slot == current] { (slot_label(*slot, dates)) }
+                    }
+                }
+                noscript {
+                    button class="ml-2 text-xs text-blue-500 hover:text-blue-700" type="submit" { "Move" }
+                }
+            }
+        }
+    }
+}
+
+fn slot_label(slot: Slot, dates: &Formatter) -> String {
+    match slot {
+        Slot::Unscheduled => "Unscheduled".to_string(),
+        Slot::Day(day) => format!("{} {}", day.weekday(), dates.date(day)),
+    }
+}
+
+// How much of `capacity` the estimates of a day take, as a bar. Without a capacity only the
+// total is shown.
+pub fn capacity_html(estimated: u32, capacity: Option<u32>) -> Markup {
+    html! {
+        div class="space-y-1" {
+            @if let Some(capacity) = capacity.filter(|capacity| *capacity > 0) {
+                @let percent = (u64::from(estimated) * 100 / u64::from(capacity)).min(100);
+                @let over = estimated > capacity;
+                div class="h-2 rounded bg-gray-300" role="meter" aria-label="Planned work"
+                    aria-valuemin="0" aria-valuemax=(capacity) aria-valuenow=(estimated) {
+                    div class={ "h-2 rounded " @if over { "bg-red-500" } @else { "bg-green-500" } }
+                        style={ "width: " (percent) "%" } {}
+                }
+                p class={ "text-xs " @if over { "text-red-600 font-bold" } @else { "text-gray-500" } } {
+                    (format_minutes(estimated)) " / " (format_minutes(capacity))
+                }
+            } @else {
+                p class="text-xs text-gray-500" { (format_minutes(estimated)) " planned" }
+            }
+        }
+    }
+}
+
+// what a column holds, loaded after the page and redrawn when a card moves
+fn column_body_html(
+    todos: &[Todo],
+    slot: Slot,
+    today: Date,
+    dates: &Formatter,
+    capacity: Option<u32>,
+) -> Markup {
+    let estimated = todos.iter().filter_map(|todo| todo.estimate_minutes).sum();
+    let slots = slots(today);
+    html! {
+        @if slot != Slot::Unscheduled {
+            (capacity_html(estimated, capacity))
+        }
+        @for todo in todos {
+            (card_html(todo, &slots, today, dates))
+        }
+    }
+}
+
+fn column_html(slot: Slot, dates: &Formatter) -> Markup {
+    let heading = match slot {
+        Slot::Unscheduled => "Unscheduled".to_string(),
+        Slot::Day(day) if day == dates.today() => "Today".to_string(),
+        Slot::Day(day) => day.weekday().to_string(),
+    };
+    html! {
+        section class="bg-gray-200 rounded-lg p-3 flex-1 min-w-[10rem]" {
+            h2 class="text-lg text-gray-700" { (heading) }
+            @if let Slot::Day(day) = slot {
+                p class="text-xs text-gray-500 mb-2" { (dates.date(day)) }
+            }
+            div id=(slot.column_id()) class="space-y-2 min-h-[4rem]"
+                hx-get=(routes::PlanColumn::url(slot)) hx-trigger={ "load, sse:" (CHANGED_EVENT) }
+                ondragover="event.preventDefault()" ondrop={ "dropOnDay(event, '" (slot) "')" } {
+                p class="text-gray-500" { "Loading…" }
+            }
+        }
+    }
+}
+
+// the contents of the column of `slot`, swapped in out of band
+fn column_oob_html(
+    db: &Db,
+    slot: Slot,
+    dates: &Formatter,
+    capacity: Option<u32>,
+) -> Result<Markup, AppError> {
+    let todos = todos_in(db, slot, dates.today())?;
+    Ok(html! {
+        div hx-swap-oob={ "innerHTML:" (dom::selector(&slot.column_id())) } {
+            (column_body_html(&todos, slot, dates.today(), dates, capacity))
+        }
+    })
+}
+
+// === Routes ===
+pub async fn index(State(state): State<AppState>, tenant: Tenant) -> Result<Markup, AppError> {
+    let dates = Formatter::load(&state.read().await.for_tenant(tenant.id())?)?;
+    Ok(views::page(
+        "Plan",
+        html! {
+            (nav::navigation(&Nav::plan()))
+            h1 class="text-4xl text-center text-gray-700 mb-6" { "This week" }
+            div class="flex space-x-3 overflow-x-auto" {
+                @for slot in slots(dates.today()) {
+                    (column_html(slot, &dates))
+                }
+            }
+            script { (PreEscaped(PLAN_SCRIPT)) }
+        },
+    ))
+}
+
+// the cards of one column, loaded once the column is on the page
+pub async fn column(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    Path(slot): Path<String>,
+) -> Result<Markup, AppError> {
+    let slot = Slot::parse(&slot)?;
+    let db = state.read().await.for_tenant(tenant.id())?;
+    let dates = Formatter::load(&db)?;
+    let todos = todos_in(&db, slot, dates.today())?;
+    Ok(column_body_html(
+        &todos,
+        slot,
+        dates.today(),
+        &dates,
+        state.config.daily_capacity_minutes,
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct Schedule {
+    day: String,
+}
+// Move a todo to another day or back to the tray. The column it left and the one it joined are
+// redrawn out of band, with their capacity.
+pub async fn schedule(
+    State(mut app_state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Extension(Visitor(visitor)): Extension<Visitor>,
+    Path(id): Path<u64>,
+    FormOrJson(Schedule { day }): FormOrJson<Schedule>,
+) -> Result<Response, AppError> {
+    let slot = Slot::parse(&day)?;
+    let state = app_state.clone();
+    let guard = app_state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    let dates = Formatter::load(&db)?;
+    let todos = TodoService::new(&state, &db, tenant.id());
+    let before = Slot::of(&todos.get(id)?, dates.today());
+    todos
+        .update(id, Some(&visitor), |todo| {
+            todo.scheduled_for = slot.scheduled_for();
+            Ok(())
+        })
+        .await?;
+    let capacity = state.config.daily_capacity_minutes;
+    let fragment = html! {
+        (column_oob_html(&db, slot, &dates, capacity)?)
+        @if before != slot {
+            (column_oob_html(&db, before, &dates, capacity)?)
+        }
+    };
+    Ok(views::fragment_or_redirect(
+        &headers,
+        fragment,
+        &routes::Plan::url(),
+    ))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use time::macros::date;
+
+    use super::*;
+    use crate::fixtures::{TestDb, TodoFixture};
+
+    #[test]
+    fn test_slots() -> Result<(), AppError> {
+        let today = date!(2024 - 03 - 06);
+        let slots = slots(today);
+        assert_eq!(slots.len(), 8);
+        assert_eq!(slots[0], Slot::Unscheduled);
+        assert_eq!(slots[1], Slot::Day(today));
+        assert_eq!(slots[7], Slot::Day(date!(2024 - 03 - 12)));
+
+        for slot in slots {
+            assert_eq!(Slot::parse(&slot.to_string())?, slot);
+        }
+        assert!(Slot::parse("someday").is_err());
+
+        // what slipped is still on today
+        let mut todo = Todo::new(1, "Milk".into());
+        assert_eq!(Slot::of(&todo, today), Slot::Unscheduled);
+        todo.scheduled_for = Some(date!(2024 - 03 - 01));
+        assert_eq!(Slot::of(&todo, today), Slot::Day(today));
+        Ok(())
+    }
+
+    #[test]
+    fn test_todos_in() -> anyhow::Result<()> {
+        let db = TestDb::new("plan")?;
+        let today = db.clock().now().date();
+        let tomorrow = TodoFixture::new().scheduled_in_days(1).persist(&db)?;
+        let tray = TodoFixture::new().persist(&db)?;
+        TodoFixture::new()
+            .scheduled_in_days(1)
+            .completed()
+            .persist(&db)?;
+        let ids = |todos: Vec<Todo>| todos.iter().map(|todo| todo.id).collect::<Vec<_>>();
+        assert_eq!(
+            ids(todos_in(&db, Slot::Day(today + Duration::days(1)), today)?),
+            [tomorrow.id]
+        );
+        assert_eq!(ids(todos_in(&db, Slot::Unscheduled, today)?), [tray.id]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_capacity_html() {
+        let half = capacity_html(120, Some(240)).into_string();
+        assert!(half.contains("width: 50%"));
+        assert!(half.contains("2h / 4h"));
+        let over = capacity_html(300, Some(240)).into_string();
+        assert!(over.contains("width: 100%") && over.contains("bg-red-500"));
+        assert!(capacity_html(45, None)
+            .into_string()
+            .contains("45m planned"));
+    }
+}
@@ -0,0 +1,151 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use maud::{html, Markup};
+
+use crate::events::Events;
+
+// the event and element the chip is swapped into
+pub const PRESENCE_EVENT: &str = "presence";
+
+// === Presence ===
+// Who has a page of a workspace open, counted by the event streams connected to it. A person with
+// several tabs open counts once.
+#[derive(Debug, Clone, Default)]
+pub struct Presence {
+    // open streams per visitor, per workspace
+    viewers: Arc<Mutex<HashMap<Option<String>, HashMap<String, usize>>>>,
+    events: Events,
+}
+impl Presence {
+    pub fn new(events: Events) -> Self {
+        Self {
+            viewers: Arc::default(),
+            events,
+        }
+    }
+
+    // Count a stream of `visitor` until the returned guard is dropped, telling every open page
+    // of the workspace how many people are there now.
+    pub fn join(&self, tenant: Option<String>, visitor: String) -> Viewing {
+        let count = {
+            let mut viewers = self.viewers.lock().expect("presence lock poisoned");
+            let workspace = viewers.entry(tenant.clone()).or_default();
+            *workspace.entry(visitor.clone()).or_default() += 1;
+            workspace.len()
+        };
+        self.broadcast(tenant.as_deref(), count);
+        Viewing {
+            presence: self.clone(),
+            tenant,
+            visitor,
+        }
+    }
+
+    pub fn count(&self, tenant: Option<&str>) -> usize {
+        let viewers = self.viewers.lock().expect("presence lock poisoned");
+        viewers
+            .get(&tenant.map(str::to_string))
+            .map_or(0, HashMap::len)
+    }
+
+    fn leave(&self, tenant: &Option<String>, visitor: &str) {
+        let count = {
+            let mut viewers = self.viewers.lock().expect("presence lock poisoned");
+            let Some(workspace) = viewers.get_mut(tenant) else {
+                return;
+            };
+            if let Some(streams) = workspace.get_mut(visitor) {
+                *streams -= 1;
+                if *streams == 0 {
+                    workspace.remove(visitor);
+                }
+            }
+            let count = workspace.len();
+            if count == 0 {
+                viewers.remove(tenant);
+            }
+            count
+        };
+        self.broadcast(tenant.as_deref(), count);
+    }
+
+    fn broadcast(&self, tenant: Option<&str>, count: usize) {
+        self.events
+            .publish(tenant, PRESENCE_EVENT, chip_html(count).into_string());
+    }
+}
+
+// an open event stream, leaves the workspace when dropped
+#[derive(Debug)]
+pub struct Viewing {
+    presence: Presence,
+    tenant: Option<String>,
+    visitor: String,
+}
+impl Drop for Viewing {
+    fn drop(&mut self) {
+        self.presence.leave(&self.tenant, &self.visitor);
+    }
+}
+
+// === Components ===
+// only shown once someone else is around
+pub fn chip_html(count: usize) -> Markup {
+    html! {
+        @if count > 1 {
+            span class="inline-block bg-green-100 text-green-800 text-sm rounded-full px-3 py-1" {
+                (count) " people viewing"
+            }
+        }
+    }
+}
+
+// where the chip goes, filled in live
+pub fn slot_html(count: usize) -> Markup {
+    html! {
+        div id=(PRESENCE_EVENT) class="text-center mb-4" sse-swap=(PRESENCE_EVENT) {
+            (chip_html(count))
+        }
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_join_and_leave() {
+        let events = Events::new();
+        let mut received = events.subscribe(None);
+        let presence = Presence::new(events);
+        let first = presence.join(None, "a".into());
+        let second_tab = presence.join(None, "a".into());
+        let other = presence.join(Some("acme".into()), "b".into());
+        assert_eq!(presence.count(None), 1);
+        assert_eq!(presence.count(Some("acme")), 1);
+
+        let second = presence.join(None, "b".into());
+        assert_eq!(presence.count(None), 2);
+        drop(first);
+        assert_eq!(presence.count(None), 2);
+        drop(second_tab);
+        drop(second);
+        drop(other);
+        assert_eq!(presence.count(None), 0);
+        assert_eq!(presence.count(Some("acme")), 0);
+
+        let joined = received.try_recv().unwrap();
+        assert_eq!(joined.name, PRESENCE_EVENT);
+        assert_eq!(joined.data, "");
+    }
+
+    #[test]
+    fn test_chip_html() {
+        assert_eq!(chip_html(1).into_string(), "");
+        assert!(chip_html(2).into_string().contains("2 people viewing"));
+    }
+}
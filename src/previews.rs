@@ -0,0 +1,429 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use maud::{html, Markup};
+use reqwest::{header, redirect, Url};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    db::{driver::Db, queue::WriteOp},
+    error::AppError,
+    events::Events,
+    models::Todo,
+    state::AppState,
+    tasks::{self, TaskKind, TaskQueue},
+    tenant::Tenant,
+};
+
+// fetched metadata, `preview:{url}` in the root tree since pages are the same for everyone
+const CACHE_PREFIX: &str = "preview:";
+const CACHE_LIFETIME: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+// failed fetches are retried sooner
+const FAILURE_LIFETIME: Duration = Duration::from_secs(60 * 60);
+const TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_REDIRECTS: usize = 3;
+// the metadata sits in the head, there is no need to read whole pages
+const MAX_BODY: usize = 512 * 1024;
+const MAX_LINKS: usize = 3;
+const MAX_DESCRIPTION: usize = 200;
+
+// What a page says about itself in its OpenGraph tags, falling back to `<title>`. Images are
+// left out on purpose, showing them would tell third parties who opened the todo.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Preview {
+    pub url: String,
+    pub site_name: Option<String>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+}
+
+fn cache_key(url: &str) -> String {
+    format!("{}{}", CACHE_PREFIX, url)
+}
+
+// `Some(None)` when the url was fetched but had nothing to show
+pub fn cached(db: &Db, url: &str) -> Result<Option<Option<Preview>>> {
+    db.get(cache_key(url))
+}
+
+// the event a preview is pushed to open detail views with
+fn event_name(url: &str) -> String {
+    format!("preview-{}", &hex::encode(Sha256::digest(url))[..16])
+}
+
+// http(s) links in `text`, in order and without duplicates
+pub fn find_urls(text: &str) -> Vec<String> {
+    let mut urls = Vec::new();
+    for word in text.split_whitespace() {
+        let word = word.trim_end_matches(|c| ".,;:!?)]'\"".contains(c));
+        if !(word.starts_with("http://") || word.starts_with("https://")) {
+            continue;
+        }
+        let Ok(url) = Url::parse(word) else {
+            continue;
+        };
+        if url.host_str().is_some() && !urls.contains(&url.to_string()) {
+            urls.push(url.to_string());
+        }
+    }
+    urls.truncate(MAX_LINKS);
+    urls
+}
+
+// === SSRF ===
+// Addresses a fetch may connect to. Anything that reaches the instance's own network is refused,
+// so a todo title cannot be used to probe it.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || a == 0
+                // shared address space, 100.64.0.0/10
+                || (a == 100 && b & 0xc0 == 64))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public(IpAddr::V4(ip));
+            }
+            let first = ip.segments()[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // unique local, fc00::/7
+                || first & 0xfe00 == 0xfc00
+                // link local, fe80::/10
+                || first & 0xffc0 == 0xfe80)
+        }
+    }
+}
+
+// resolve the url's host, refusing it when any of its addresses is not public
+async fn resolve_public(url: &Url) -> Result<SocketAddr> {
+    if !matches!(url.scheme(), "http" | "https") {
+        bail!("refusing to fetch a {} url", url.scheme());
+    }
+    let host = url.host_str().ok_or_else(|| anyhow!("url has no host"))?;
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port)).await?.collect();
+    if addrs.is_empty() || addrs.iter().any(|addr| !is_public(addr.ip())) {
+        bail!(
+            "refusing to fetch {}, it resolves to a private address",
+            host
+        );
+    }
+    Ok(addrs[0])
+}
+
+// === Fetching ===
+async fn fetch(url: &str) -> Result<Option<Preview>> {
+    let mut url = Url::parse(url)?;
+    for _ in 0..=MAX_REDIRECTS {
+        // connect to the address that was checked, a second lookup could answer differently
+        let addr = resolve_public(&url).await?;
+        let client = reqwest::Client::builder()
+            .user_agent(concat!(
+                env!("CARGO_PKG_NAME"),
+                "/",
+                env!("CARGO_PKG_VERSION")
+            ))
+            .timeout(TIMEOUT)
+            .redirect(redirect::Policy::none())
+            .resolve(url.host_str().unwrap_or_default(), addr)
+            .build()?;
+        let mut response = client.get(url.clone()).send().await?;
+        if response.status().is_redirection() {
+            let location = response
+                .headers()
+                .get(header::LOCATION)
+                .context("redirect without a location")?
+                .to_str()?;
+            url = url.join(location)?;
+            continue;
+        }
+        let response_url = response.url().to_string();
+        let is_html = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.contains("text/html"));
+        if !response.status().is_success() || !is_html {
+            return Ok(None);
+        }
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            body.extend_from_slice(&chunk);
+            if body.len() >= MAX_BODY {
+                break;
+            }
+        }
+        let preview = parse(&response_url, &String::from_utf8_lossy(&body));
+        return Ok((preview.title.is_some() || preview.description.is_some()).then_some(preview));
+    }
+    Err(anyhow!("too many redirects"))
+}
+
+// === Parsing ===
+pub fn parse(url: &str, html: &str) -> Preview {
+    // ascii lowercasing keeps byte offsets, so positions found in `lower` index into `html`
+    let lower = html.to_ascii_lowercase();
+    let mut preview = Preview {
+        url: url.to_string(),
+        ..Preview::default()
+    };
+    let mut description = None;
+    let mut rest = 0;
+    while let Some(start) = lower[rest..].find("<meta") {
+        let start = rest + start;
+        let Some(end) = lower[start..].find('>') else {
+            break;
+        };
+        let attributes = attributes(&html[start + "<meta".len()..start + end]);
+        rest = start + end;
+        let property = attributes
+            .iter()
+            .find(|(name, _)| name == "property" || name == "name")
+            .map(|(_, value)| value.to_ascii_lowercase());
+        let Some((_, content)) = attributes.iter().find(|(name, _)| name == "content") else {
+            continue;
+        };
+        let content = decode_entities(content.trim());
+        match property.as_deref() {
+            Some("og:title") => preview.title = Some(content),
+            Some("og:description") => preview.description = Some(content),
+            Some("og:site_name") => preview.site_name = Some(content),
+            Some("description") => description = Some(content),
+            _ => {}
+        }
+    }
+    if preview.title.is_none() {
+        preview.title = lower.find("<title").and_then(|start| {
+            let open = start + lower[start..].find('>')? + 1;
+            let close = open + lower[open..].find("</title")?;
+            Some(decode_entities(html[open..close].trim()))
+        });
+    }
+    preview.description =
+        preview.description.or(description).map(|description| {
+            match description.char_indices().nth(MAX_DESCRIPTION) {
+                Some((end, _)) => format!("{}…", &description[..end]),
+                None => description,
+            }
+        });
+    preview.title = preview.title.filter(|title| !title.is_empty());
+    preview
+}
+
+// `name="value"` pairs of a tag, names lowercased
+fn attributes(tag: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    let mut rest = tag.trim_start();
+    while let Some(eq) = rest.find('=') {
+        let name = rest[..eq].trim().to_ascii_lowercase();
+        let value = rest[eq + 1..].trim_start();
+        let (value, after) = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => match value[1..].find(quote) {
+                Some(end) => (&value[1..end + 1], &value[end + 2..]),
+                None => break,
+            },
+            _ => {
+                let end = value.find(char::is_whitespace).unwrap_or(value.len());
+                (&value[..end], &value[end..])
+            }
+        };
+        // a bare attribute before this one ends up in front of the name
+        let name = name.rsplit(char::is_whitespace).next().unwrap_or_default();
+        attributes.push((name.to_string(), value.to_string()));
+        rest = after.trim_start();
+    }
+    attributes
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+// === Fetcher ===
+// Fetch `url` for the task queue and push the card to open detail views. A failure clears the
+// placeholder and is cached for a while, the queue retries it in the background.
+pub async fn fetch_and_publish(
+    db: &Db,
+    events: &Events,
+    tenant: Option<&str>,
+    url: &str,
+) -> Result<()> {
+    // asked for twice before the first fetch finished
+    if let Some(Some(preview)) = cached(db, url)? {
+        events.publish(tenant, event_name(url), card_html(&preview).into_string());
+        return Ok(());
+    }
+    let result = fetch(url).await;
+    let (preview, lifetime) = match &result {
+        Ok(preview) => (preview.clone(), CACHE_LIFETIME),
+        Err(err) => {
+            tracing::warn!(url, error = %err, "link preview failed");
+            (None, FAILURE_LIFETIME)
+        }
+    };
+    db.insert_with_ttl(cache_key(url), &preview, lifetime)?;
+    // an empty event clears the placeholder when there is nothing to show
+    let card = preview
+        .as_ref()
+        .map(|preview| card_html(preview).into_string())
+        .unwrap_or_default();
+    events.publish(tenant, event_name(url), card);
+    result.map(|_| ())
+}
+
+// Views ask for previews they do not have yet and get them pushed over server-sent events once
+// fetched, so a slow site never holds up a page.
+#[derive(Debug, Clone)]
+pub struct Previews {
+    tasks: TaskQueue,
+}
+impl Previews {
+    pub fn new(tasks: TaskQueue) -> Self {
+        Self { tasks }
+    }
+
+    // queue a fetch of `url` on its own
+    pub fn request(&self, db: &Db, tenant: Option<&str>, url: &str) -> Result<()> {
+        self.tasks.enqueue(db, fetch_task(tenant, url))
+    }
+
+    // fetches of the links in a new todo, to go into the batch creating it
+    pub fn ops(&self, db: &Db, tenant: Option<&str>, todo: &Todo) -> Result<Vec<WriteOp>> {
+        find_urls(&todo.title)
+            .iter()
+            .map(|url| tasks::enqueue_op(db, fetch_task(tenant, url)))
+            .collect()
+    }
+}
+
+fn fetch_task(tenant: Option<&str>, url: &str) -> TaskKind {
+    TaskKind::LinkPreview {
+        tenant: tenant.map(str::to_string),
+        url: url.to_string(),
+    }
+}
+
+// === Components ===
+fn card_html(preview: &Preview) -> Markup {
+    let host = Url::parse(&preview.url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string));
+    html! {
+        a class="block border rounded p-3 hover:bg-gray-50" href=(preview.url) target="_blank" rel="noopener noreferrer" {
+            @if let Some(site) = preview.site_name.as_ref().or(host.as_ref()) {
+                p class="text-xs text-gray-500" { (site) }
+            }
+            @if let Some(title) = &preview.title {
+                p class="font-bold text-gray-700" { (title) }
+            }
+            @if let Some(description) = &preview.description {
+                p class="text-sm text-gray-600" { (description) }
+            }
+        }
+    }
+}
+
+// Cards for the links in a todo. Links not fetched yet get a placeholder the fetcher fills in.
+pub async fn load(state: &AppState, tenant: &Tenant, todo: &Todo) -> Result<Markup, AppError> {
+    let Some(previews) = &state.previews else {
+        return Ok(html! {});
+    };
+    let urls = find_urls(&todo.title);
+    if urls.is_empty() {
+        return Ok(html! {});
+    }
+    let db = state.read().await;
+    let mut cards = Vec::new();
+    for url in urls {
+        let card = match cached(&db, &url)? {
+            Some(preview) => preview.as_ref().map(card_html),
+            None => {
+                previews.request(&db, tenant.id(), &url)?;
+                Some(html! {
+                    div sse-swap=(event_name(&url)) {
+                        p class="text-sm text-gray-400" { "Loading a preview of " (url) "…" }
+                    }
+                })
+            }
+        };
+        cards.extend(card);
+    }
+    Ok(html! {
+        @if !cards.is_empty() {
+            section class="space-y-2" {
+                @for card in cards { (card) }
+            }
+        }
+    })
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_urls() {
+        assert_eq!(
+            find_urls("read https://example.com/a, then (https://example.com/b) and https://example.com/a"),
+            vec!["https://example.com/a", "https://example.com/b"]
+        );
+        assert!(find_urls("ftp://example.com http:// nothing").is_empty());
+    }
+
+    #[test]
+    fn test_private_addresses_are_refused() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:10.0.0.1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{} is private", ip);
+        }
+        assert!(is_public("93.184.216.34".parse().unwrap()));
+        assert!(is_public("2606:2800:220:1::".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_parse_open_graph() {
+        let html = r#"<html><head><title>Fallback</title>
+            <meta property="og:title" content="Rust &amp; htmx">
+            <meta name='description' content='A page about things'>
+            <meta property="og:site_name" content="Example" />
+            </head></html>"#;
+        let preview = parse("https://example.com", html);
+        assert_eq!(preview.title.as_deref(), Some("Rust & htmx"));
+        assert_eq!(preview.description.as_deref(), Some("A page about things"));
+        assert_eq!(preview.site_name.as_deref(), Some("Example"));
+
+        let preview = parse("https://example.com", "<TITLE> Plain </TITLE>");
+        assert_eq!(preview.title.as_deref(), Some("Plain"));
+    }
+}
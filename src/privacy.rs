@@ -0,0 +1,48 @@
+use std::io::{Cursor, Write};
+
+use anyhow::Result;
+use zip::{write::FileOptions, ZipWriter};
+
+use crate::{
+    db::driver::Db,
+    import,
+    repository::{entity, query::TodoQuery},
+};
+
+// every keyspace holding data that belongs to the workspace owner
+pub const OWNED_KEYSPACES: &[&str] = &[
+    "todo:",
+    "goal:",
+    "goal_of:",
+    "swatch:",
+    "reactions:",
+    import::PENDING_PREFIX,
+    // the tags of todos are in the keys
+    entity::INDEX_PREFIX,
+];
+
+// A zip archive with one json file per keyspace the owner has data in.
+pub fn export_archive(db: &Db) -> Result<Vec<u8>> {
+    let todos = TodoQuery::new()
+        .including_archived()
+        .including_someday()
+        .list(db)?;
+
+    let mut archive = ZipWriter::new(Cursor::new(Vec::new()));
+    archive.start_file("todos.json", FileOptions::default())?;
+    archive.write_all(&serde_json::to_vec_pretty(&todos)?)?;
+    Ok(archive.finish()?.into_inner())
+}
+
+// Remove every key the owner has data in, in a single batch. Returns how many keys were erased.
+pub fn erase_owned(db: &Db) -> Result<usize> {
+    let mut keys = Vec::new();
+    for keyspace in OWNED_KEYSPACES {
+        for item in db.iter_keys(keyspace) {
+            keys.push(item?);
+        }
+    }
+    let erased = keys.len();
+    db.remove_all(keys)?;
+    Ok(erased)
+}
@@ -0,0 +1,117 @@
+use axum::{
+    extract::State,
+    http::header,
+    response::{IntoResponse, Response},
+};
+use serde_json::{json, Value};
+
+use crate::{
+    error::AppError,
+    routes, setup,
+    state::AppState,
+    tenant::{self, Tenant},
+    theme::{self, Theme},
+};
+
+// === Manifest ===
+// What a browser needs to install the app to a home screen. The name is the workspace's, or the
+// instance's without tenancy, the colors follow the theme picked in the settings.
+pub fn manifest(name: &str, theme: Theme) -> Value {
+    json!({
+        "name": name,
+        "short_name": name.chars().take(12).collect::<String>(),
+        "start_url": routes::Root::url(),
+        "scope": routes::Root::url(),
+        "display": "standalone",
+        "background_color": "#f3f4f6",
+        "theme_color": theme.accent(),
+        "icons": [
+            { "src": routes::AppIcon::url(), "sizes": "any", "type": "image/svg+xml", "purpose": "any" },
+            { "src": routes::MaskableIcon::url(), "sizes": "any", "type": "image/svg+xml", "purpose": "maskable" },
+        ],
+    })
+}
+
+// A check mark on the accent color. The maskable one fills the whole square, launchers crop it
+// to their own shape and only the middle 80% is sure to stay visible.
+pub fn icon(theme: Theme, maskable: bool) -> String {
+    let background = if maskable {
+        format!(
+            r#"<rect width="512" height="512" fill="{}"/>"#,
+            theme.accent()
+        )
+    } else {
+        format!(
+            r#"<circle cx="256" cy="256" r="240" fill="{}"/>"#,
+            theme.accent()
+        )
+    };
+    format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 512 512">{}<path d="M160 264l64 64 128-144" fill="none" stroke="#fff" stroke-width="40" stroke-linecap="round" stroke-linejoin="round"/></svg>"#,
+        background
+    )
+}
+
+async fn name_and_theme(state: &AppState, tenant: &Tenant) -> Result<(String, Theme), AppError> {
+    let db = state.read().await;
+    let theme = theme::get(&db.for_tenant(tenant.id())?)?;
+    let name = match tenant.id() {
+        Some(id) => tenant::get(&db, id)?.map(|record| record.name),
+        None => setup::instance(&db)?.map(|instance| instance.name),
+    };
+    Ok((name.unwrap_or_else(|| "Todos".to_string()), theme))
+}
+
+// === Routes ===
+pub async fn show_manifest(
+    State(state): State<AppState>,
+    tenant: Tenant,
+) -> Result<Response, AppError> {
+    let (name, theme) = name_and_theme(&state, &tenant).await?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/manifest+json"),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        manifest(&name, theme).to_string(),
+    )
+        .into_response())
+}
+
+pub async fn app_icon(State(state): State<AppState>, tenant: Tenant) -> Result<Response, AppError> {
+    svg(&state, &tenant, false).await
+}
+pub async fn maskable_icon(
+    State(state): State<AppState>,
+    tenant: Tenant,
+) -> Result<Response, AppError> {
+    svg(&state, &tenant, true).await
+}
+
+async fn svg(state: &AppState, tenant: &Tenant, maskable: bool) -> Result<Response, AppError> {
+    let (_, theme) = name_and_theme(state, tenant).await?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, "image/svg+xml"),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        icon(theme, maskable),
+    )
+        .into_response())
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest() {
+        let manifest = manifest("Household chores", Theme::ColorBlind);
+        assert_eq!(manifest["short_name"], "Household ch");
+        assert_eq!(manifest["theme_color"], Theme::ColorBlind.accent());
+        assert_eq!(manifest["display"], "standalone");
+        assert_eq!(manifest["icons"][1]["purpose"], "maskable");
+        assert!(icon(Theme::Default, true).starts_with("<svg"));
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use anyhow::Result;
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    response::Response,
+    Extension,
+};
+use maud::{html, Markup};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::visitor::Visitor,
+    db::{driver::Db, queue::WriteOp},
+    error::AppError,
+    extract::FormOrJson,
+    models::Todo,
+    repository, routes,
+    state::AppState,
+    tenant::Tenant,
+    views::{self, dom},
+};
+
+pub const EMOJIS: [&str; 3] = ["👍", "✅", "🔥"];
+// who reacted with what to a todo, `reactions:{todo}`
+const REACTIONS_PREFIX: &str = "reactions:";
+
+// emoji to the visitors who reacted with it
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Reactions(BTreeMap<String, BTreeSet<String>>);
+impl Reactions {
+    pub fn count(&self, emoji: &str) -> usize {
+        self.0.get(emoji).map_or(0, BTreeSet::len)
+    }
+}
+
+pub fn reactions_key(todo: u64) -> String {
+    format!("{}{}", REACTIONS_PREFIX, todo)
+}
+
+pub fn get(db: &Db, todo: u64) -> Result<Reactions> {
+    Ok(db.get(reactions_key(todo))?.unwrap_or_default())
+}
+
+// the reactions of every todo in `todos` that has any
+pub fn for_todos(db: &Db, todos: &[Todo]) -> Result<HashMap<u64, Reactions>> {
+    let mut reactions = HashMap::new();
+    for todo in todos {
+        if let Some(found) = db.get::<Reactions, _>(reactions_key(todo.id))? {
+            reactions.insert(todo.id, found);
+        }
+    }
+    Ok(reactions)
+}
+
+// The reactions of `from` added to those of `into`, for merging the todos. `None` when `from`
+// has none.
+pub fn absorb_op(db: &Db, from: u64, into: u64) -> Result<Option<WriteOp>> {
+    let absorbed = get(db, from)?;
+    if absorbed.0.is_empty() {
+        return Ok(None);
+    }
+    let mut reactions = get(db, into)?;
+    for (emoji, visitors) in absorbed.0 {
+        reactions.0.entry(emoji).or_default().extend(visitors);
+    }
+    Ok(Some(WriteOp::Insert {
+        key: reactions_key(into),
+        value: db.encode(&reactions)?,
+    }))
+}
+
+// React to `todo`, or take the reaction back when `visitor` already reacted with `emoji`.
+pub fn toggle(db: &Db, todo: u64, emoji: &str, visitor: &str) -> Result<Reactions> {
+    let mut reactions = get(db, todo)?;
+    let visitors = reactions.0.entry(emoji.to_string()).or_default();
+    if !visitors.remove(visitor) {
+        visitors.insert(visitor.to_string());
+    }
+    reactions.0.retain(|_, visitors| !visitors.is_empty());
+    db.insert(reactions_key(todo), &reactions)?;
+    Ok(reactions)
+}
+
+// === Components ===
+fn buttons_html(todo: u64, reactions: &Reactions) -> Markup {
+    let url = routes::TodoReactions::url(todo);
+    html! {
+        @for emoji in EMOJIS {
+            form class="inline" method="post" action=(url) hx-post=(url) hx-target=(dom::selector(&dom::reactions(todo))) {
+                button class="text-sm rounded-full border px-2 mr-1 hover:bg-gray-100" type="submit" name="emoji" value=(emoji) {
+                    (emoji)
+                    @let count = reactions.count(emoji);
+                    @if count > 0 { " " (count) }
+                }
+            }
+        }
+    }
+}
+
+// The reaction buttons of a todo. Other open pages get the new counts over server-sent events.
+pub fn reactions_html(todo: u64, reactions: &Reactions) -> Markup {
+    html! {
+        span id=(dom::reactions(todo)) class="reactions mr-2" sse-swap=(event_name(todo)) {
+            (buttons_html(todo, reactions))
+        }
+    }
+}
+
+fn event_name(todo: u64) -> String {
+    dom::reactions(todo)
+}
+
+// === Routes ===
+#[derive(Deserialize)]
+pub struct React {
+    emoji: String,
+}
+pub async fn react(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Extension(Visitor(visitor)): Extension<Visitor>,
+    Path(todo): Path<u64>,
+    FormOrJson(React { emoji }): FormOrJson<React>,
+) -> Result<Response, AppError> {
+    if !EMOJIS.contains(&emoji.as_str()) {
+        return Err(AppError::Invalid(format!("`{}` is not a reaction", emoji)));
+    }
+    let events = state.events.clone();
+    let guard = state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    if db
+        .get::<Todo, _>(repository::todo::todo_key(todo))?
+        .is_none()
+    {
+        return Err(AppError::NotFound);
+    }
+    let reactions = toggle(&db, todo, &emoji, &visitor)?;
+    let buttons = buttons_html(todo, &reactions);
+    events.publish(tenant.id(), event_name(todo), buttons.clone().into_string());
+    Ok(views::fragment_or_redirect(
+        &headers,
+        buttons,
+        &routes::Root::url(),
+    ))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::TestDb;
+
+    #[test]
+    fn test_toggle_per_visitor() -> Result<()> {
+        let db = TestDb::new("reactions")?;
+        toggle(&db, 1, "👍", "ann")?;
+        let reactions = toggle(&db, 1, "👍", "bob")?;
+        assert_eq!(reactions.count("👍"), 2);
+        let reactions = toggle(&db, 1, "👍", "ann")?;
+        assert_eq!(reactions.count("👍"), 1);
+        assert_eq!(reactions.count("🔥"), 0);
+        Ok(())
+    }
+}
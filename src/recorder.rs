@@ -0,0 +1,390 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Path, Request, State},
+    http::{header, HeaderMap},
+    middleware::Next,
+    response::Response,
+};
+use maud::{html, Markup};
+
+use crate::{
+    clock::Clock,
+    error::AppError,
+    routes,
+    state::AppState,
+    views::{self, lint},
+};
+
+// exchanges kept, the oldest are dropped first
+const CAPACITY: usize = 200;
+// bodies are cut off after this many bytes
+const BODY_LIMIT: usize = 64 * 1024;
+
+// === Recorder ===
+// With `DEV_MODE`, every request and its response are kept in memory for `/dev/requests`, to see
+// what htmx sent and got back without the browser's devtools.
+#[derive(Debug, Clone)]
+pub struct Exchange {
+    pub id: u64,
+    // unix seconds the request came in
+    pub at: u64,
+    pub millis: u128,
+    pub method: String,
+    pub uri: String,
+    pub request_headers: Vec<(String, String)>,
+    pub request_body: String,
+    pub status: u16,
+    pub response_headers: Vec<(String, String)>,
+    pub response_body: String,
+    // what `views::lint` found in the html that went out
+    pub problems: Vec<String>,
+}
+impl Exchange {
+    // the `HX-*` headers of the request, what most htmx questions come down to
+    pub fn htmx_headers(&self) -> impl Iterator<Item = &(String, String)> {
+        self.request_headers
+            .iter()
+            .filter(|(name, _)| name.starts_with("hx-"))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    exchanges: Arc<Mutex<VecDeque<Exchange>>>,
+    next_id: Arc<AtomicU64>,
+    // the database's, which the retention purge compares `Exchange::at` against
+    clock: Arc<dyn Clock>,
+}
+impl Recorder {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            exchanges: Arc::default(),
+            next_id: Arc::default(),
+            clock,
+        }
+    }
+
+    pub fn push(&self, mut exchange: Exchange) {
+        exchange.id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut exchanges = self.exchanges.lock().expect("recorder lock poisoned");
+        if exchanges.len() == CAPACITY {
+            exchanges.pop_front();
+        }
+        exchanges.push_back(exchange);
+    }
+    // newest first
+    pub fn list(&self) -> Vec<Exchange> {
+        let exchanges = self.exchanges.lock().expect("recorder lock poisoned");
+        exchanges.iter().rev().cloned().collect()
+    }
+    pub fn get(&self, id: u64) -> Option<Exchange> {
+        let exchanges = self.exchanges.lock().expect("recorder lock poisoned");
+        exchanges.iter().find(|exchange| exchange.id == id).cloned()
+    }
+    // drop the exchanges recorded before `cutoff` in unix seconds, returns how many went
+    pub fn purge_before(&self, cutoff: u64) -> usize {
+        let mut exchanges = self.exchanges.lock().expect("recorder lock poisoned");
+        let before = exchanges.len();
+        exchanges.retain(|exchange| exchange.at >= cutoff);
+        before - exchanges.len()
+    }
+    // how many exchanges are kept and the bytes of their bodies
+    pub fn volume(&self) -> (usize, usize) {
+        let exchanges = self.exchanges.lock().expect("recorder lock poisoned");
+        let bytes = exchanges
+            .iter()
+            .map(|exchange| exchange.request_body.len() + exchange.response_body.len())
+            .sum();
+        (exchanges.len(), bytes)
+    }
+    pub fn clear(&self) {
+        self.exchanges
+            .lock()
+            .expect("recorder lock poisoned")
+            .clear();
+    }
+}
+
+fn headers(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+            (name.to_string(), value)
+        })
+        .collect()
+}
+
+fn text(bytes: &[u8]) -> String {
+    let cut = &bytes[..bytes.len().min(BODY_LIMIT)];
+    let mut text = String::from_utf8_lossy(cut).into_owned();
+    if bytes.len() > BODY_LIMIT {
+        text.push_str(&format!("\n… {} more bytes", bytes.len() - BODY_LIMIT));
+    }
+    text
+}
+
+fn is_html(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/html"))
+}
+
+// Lint what htmx will swap in, a whole page is checked for its own ids.
+fn lint(body: &str) -> Vec<String> {
+    let problems = if body.trim_start().starts_with("<!DOCTYPE") {
+        lint::lint_page(body)
+    } else {
+        lint::lint_fragment(body)
+    };
+    problems.iter().map(ToString::to_string).collect()
+}
+
+// event streams never end, they pass through unbuffered
+fn is_stream(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/event-stream"))
+}
+
+// Buffer both bodies to keep a copy, the recorder's own pages are left out.
+pub async fn record(
+    State(recorder): State<Recorder>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    if request
+        .uri()
+        .path()
+        .starts_with(&routes::DevRequests::url())
+    {
+        return Ok(next.run(request).await);
+    }
+    let started = Instant::now();
+    let (parts, body) = request.into_parts();
+    let request_body = to_bytes(body, usize::MAX)
+        .await
+        .map_err(|err| AppError::Invalid(err.to_string()))?;
+    let mut exchange = Exchange {
+        id: 0,
+        at: recorder.clock.now_millis() / 1000,
+        millis: 0,
+        method: parts.method.to_string(),
+        uri: parts.uri.to_string(),
+        request_headers: headers(&parts.headers),
+        request_body: text(&request_body),
+        status: 0,
+        response_headers: Vec::new(),
+        response_body: String::new(),
+        problems: Vec::new(),
+    };
+    let response = next
+        .run(Request::from_parts(parts, Body::from(request_body)))
+        .await;
+    exchange.status = response.status().as_u16();
+    exchange.response_headers = headers(response.headers());
+    if is_stream(response.headers()) {
+        exchange.millis = started.elapsed().as_millis();
+        recorder.push(exchange);
+        return Ok(response);
+    }
+    let (parts, body) = response.into_parts();
+    let response_body = to_bytes(body, usize::MAX)
+        .await
+        .map_err(|err| AppError::Invalid(err.to_string()))?;
+    exchange.response_body = text(&response_body);
+    if is_html(&parts.headers) {
+        exchange.problems = lint(&String::from_utf8_lossy(&response_body));
+        for problem in &exchange.problems {
+            tracing::warn!(uri = %exchange.uri, %problem, "htmx attribute problem");
+        }
+    }
+    exchange.millis = started.elapsed().as_millis();
+    recorder.push(exchange);
+    Ok(Response::from_parts(parts, Body::from(response_body)))
+}
+
+// === Components ===
+fn headers_html(headers: &[(String, String)]) -> Markup {
+    html! {
+        dl class="grid grid-cols-[max-content_1fr] gap-x-4 font-mono text-sm" {
+            @for (name, value) in headers {
+                dt class="text-gray-500" { (name) }
+                dd class="break-all" { (value) }
+            }
+        }
+    }
+}
+
+fn status_class(status: u16) -> &'static str {
+    match status {
+        200..=399 => "text-green-600",
+        400..=499 => "text-yellow-600",
+        _ => "text-red-600",
+    }
+}
+
+// === Routes ===
+fn recorder(state: &AppState) -> Result<&Recorder, AppError> {
+    state.recorder.as_ref().ok_or(AppError::NotFound)
+}
+
+pub async fn index(State(state): State<AppState>) -> Result<Markup, AppError> {
+    let exchanges = recorder(&state)?.list();
+    Ok(views::page(
+        "Requests",
+        html! {
+            div class="flex justify-between items-center mb-6" {
+                h1 class="text-4xl text-gray-700" { "Requests" }
+                button class="bg-red-500 hover:bg-red-700 text-white font-bold py-2 px-4 rounded"
+                    hx-delete=(routes::DevRequests::url()) hx-target="#exchanges" { "Clear" }
+            }
+            table class="w-full bg-white rounded-lg shadow-lg text-sm" {
+                thead {
+                    tr {
+                        th class="py-2 px-4 text-left" { "Method" }
+                        th class="py-2 px-4 text-left" { "Url" }
+                        th class="py-2 px-4 text-left" { "Status" }
+                        th class="py-2 px-4 text-left" { "htmx" }
+                        th class="py-2 px-4 text-left" { "Problems" }
+                        th class="py-2 px-4 text-right" { "Time" }
+                    }
+                }
+                tbody id="exchanges" {
+                    @for exchange in &exchanges {
+                        tr class="border-t" {
+                            td class="py-2 px-4 font-mono" { (exchange.method) }
+                            td class="py-2 px-4 font-mono break-all" {
+                                a class="text-blue-500 hover:underline" href=(routes::DevRequest::url(exchange.id)) { (exchange.uri) }
+                            }
+                            td class={ "py-2 px-4 " (status_class(exchange.status)) } { (exchange.status) }
+                            td class="py-2 px-4 font-mono" {
+                                @for (name, value) in exchange.htmx_headers() {
+                                    div { (name) ": " (value) }
+                                }
+                            }
+                            td class="py-2 px-4 text-red-600" {
+                                @if !exchange.problems.is_empty() { (exchange.problems.len()) }
+                            }
+                            td class="py-2 px-4 text-right" { (exchange.millis) "ms" }
+                        }
+                    }
+                }
+            }
+        },
+    ))
+}
+
+pub async fn show(State(state): State<AppState>, Path(id): Path<u64>) -> Result<Markup, AppError> {
+    let exchange = recorder(&state)?.get(id).ok_or(AppError::NotFound)?;
+    Ok(views::page(
+        "Request",
+        html! {
+            a class="text-blue-500 hover:underline" href=(routes::DevRequests::url()) { "All requests" }
+            h1 class="text-2xl text-gray-700 font-mono my-4 break-all" {
+                (exchange.method) " " (exchange.uri) " "
+                span class=(status_class(exchange.status)) { (exchange.status) }
+            }
+            @if !exchange.problems.is_empty() {
+                section class="bg-red-50 text-red-700 rounded-lg shadow-lg p-4 mb-6" {
+                    h2 class="text-xl mb-2" { "Problems" }
+                    ul class="list-disc pl-6 font-mono text-sm" {
+                        @for problem in &exchange.problems { li { (problem) } }
+                    }
+                }
+            }
+            section class="bg-white rounded-lg shadow-lg p-4 mb-6" {
+                h2 class="text-xl text-gray-700 mb-2" { "Request" }
+                (headers_html(&exchange.request_headers))
+                @if !exchange.request_body.is_empty() {
+                    pre class="bg-gray-100 rounded p-2 mt-2 overflow-x-auto text-sm" { (exchange.request_body) }
+                }
+            }
+            section class="bg-white rounded-lg shadow-lg p-4" {
+                h2 class="text-xl text-gray-700 mb-2" { "Response, " (exchange.millis) "ms" }
+                (headers_html(&exchange.response_headers))
+                pre class="bg-gray-100 rounded p-2 mt-2 overflow-x-auto text-sm" { (exchange.response_body) }
+            }
+        },
+    ))
+}
+
+pub async fn clear(State(state): State<AppState>) -> Result<Markup, AppError> {
+    recorder(&state)?.clear();
+    Ok(html! {})
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::SystemClock;
+
+    fn exchange(uri: &str) -> Exchange {
+        Exchange {
+            id: 0,
+            at: 0,
+            millis: 0,
+            method: "GET".into(),
+            uri: uri.into(),
+            request_headers: vec![
+                ("hx-request".into(), "true".into()),
+                ("accept".into(), "*/*".into()),
+            ],
+            request_body: String::new(),
+            status: 200,
+            response_headers: Vec::new(),
+            response_body: String::new(),
+            problems: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_ring_buffer() {
+        let recorder = Recorder::new(Arc::new(SystemClock));
+        for n in 0..CAPACITY + 5 {
+            recorder.push(exchange(&format!("/todos/{}", n)));
+        }
+        let exchanges = recorder.list();
+        assert_eq!(exchanges.len(), CAPACITY);
+        assert_eq!(exchanges[0].uri, format!("/todos/{}", CAPACITY + 4));
+        assert_eq!(exchanges[CAPACITY - 1].uri, "/todos/5");
+        assert!(recorder.get(4).is_none());
+        assert_eq!(recorder.get(5).unwrap().htmx_headers().count(), 1);
+        recorder.clear();
+        assert!(recorder.list().is_empty());
+    }
+
+    #[test]
+    fn test_purge_before() {
+        let recorder = Recorder::new(Arc::new(SystemClock));
+        for at in [100, 200, 300] {
+            recorder.push(Exchange {
+                at,
+                response_body: "<li></li>".into(),
+                ..exchange(&format!("/todos/{}", at))
+            });
+        }
+        assert_eq!(recorder.purge_before(250), 2);
+        assert_eq!(recorder.list()[0].uri, "/todos/300");
+        assert_eq!(recorder.volume(), (1, 9));
+    }
+
+    #[test]
+    fn test_text() {
+        assert_eq!(text(b"<li>one</li>"), "<li>one</li>");
+        let long = vec![b'a'; BODY_LIMIT + 3];
+        assert!(text(&long).ends_with("… 3 more bytes"));
+    }
+}
@@ -0,0 +1,296 @@
+use std::time::Duration;
+
+use axum::{
+    extract::State,
+    response::{IntoResponse, Response},
+    Extension, Form,
+};
+use maud::{html, Markup};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::visitor::Visitor,
+    config::{Config, Tenancy},
+    db::driver::Db,
+    error::AppError,
+    guest, routes,
+    state::AppState,
+    tenant::{self, TenantRecord},
+    views,
+};
+
+// set by the setup wizard and the admin page, `REGISTRATION_OPEN` wins over it
+const OPEN_KEY: &str = "registration_open";
+const INVITE_PREFIX: &str = "invite:";
+
+// === Registration ===
+// Whether visitors may create a workspace of their own at `/signup`. Closed unless opened, an
+// invite code gets past a closed registration.
+pub fn is_open(config: &Config, db: &Db) -> anyhow::Result<bool> {
+    match config.registration_open {
+        Some(open) => Ok(open),
+        None => Ok(db.get(OPEN_KEY)?.unwrap_or(false)),
+    }
+}
+pub fn set_open(db: &Db, open: bool) -> anyhow::Result<()> {
+    db.insert(OPEN_KEY, &open)
+}
+
+// === Invites ===
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Invite {
+    pub code: String,
+    // unlimited when unset
+    pub max_uses: Option<u32>,
+    pub uses: u32,
+    // unix seconds, the sweeper removes the invite after it
+    pub expires_at: Option<u64>,
+    pub created_at: u64,
+}
+impl Invite {
+    pub fn is_usable(&self, now: u64) -> bool {
+        self.expires_at.map_or(true, |at| now < at)
+            && self.max_uses.map_or(true, |max| self.uses < max)
+    }
+}
+
+fn invite_key(code: &str) -> String {
+    format!("{}{}", INVITE_PREFIX, code)
+}
+
+pub fn create_invite(
+    db: &Db,
+    max_uses: Option<u32>,
+    valid_for: Option<Duration>,
+) -> anyhow::Result<Invite> {
+    let mut code = [0; 8];
+    rand::thread_rng().fill_bytes(&mut code);
+    let now = db.clock().now_millis() / 1000;
+    let invite = Invite {
+        code: hex::encode(code),
+        max_uses,
+        uses: 0,
+        expires_at: valid_for.map(|valid_for| now + valid_for.as_secs()),
+        created_at: now,
+    };
+    store(db, &invite, now)?;
+    Ok(invite)
+}
+
+// newest first
+pub fn list_invites(db: &Db) -> anyhow::Result<Vec<Invite>> {
+    let mut invites = db
+        .iter_prefix::<Invite>(INVITE_PREFIX)?
+        .map(|item| item.map(|(_, invite)| invite))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    invites.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    Ok(invites)
+}
+
+pub fn revoke_invite(db: &Db, code: &str) -> anyhow::Result<()> {
+    db.remove(invite_key(code))
+}
+
+// Count a use of the invite, false when it is unknown, expired or used up.
+pub fn redeem(db: &Db, code: &str, now: u64) -> anyhow::Result<bool> {
+    let Some(mut invite) = db.get::<Invite, _>(invite_key(code))? else {
+        return Ok(false);
+    };
+    if !invite.is_usable(now) {
+        return Ok(false);
+    }
+    invite.uses += 1;
+    store(db, &invite, now)?;
+    Ok(true)
+}
+
+// a plain insert would drop the expiry, so expiring invites keep what is left of it
+fn store(db: &Db, invite: &Invite, now: u64) -> anyhow::Result<()> {
+    match invite.expires_at {
+        Some(at) => db.insert_with_ttl(
+            invite_key(&invite.code),
+            invite,
+            Duration::from_secs(at.saturating_sub(now)),
+        ),
+        None => db.insert(invite_key(&invite.code), invite),
+    }
+}
+
+// === Signup ===
+// `https://acme.example.com` for `acme` when the instance is at `https://example.com`
+fn workspace_url(public_url: &str, base_domain: &str, id: &str) -> String {
+    let (scheme, rest) = public_url.split_once("://").unwrap_or(("http", public_url));
+    let authority = rest.split('/').next().unwrap_or_default();
+    match authority.rsplit_once(':') {
+        Some((_, port)) => format!("{}://{}.{}:{}", scheme, id, base_domain, port),
+        None => format!("{}://{}.{}", scheme, id, base_domain),
+    }
+}
+
+// signing up creates a workspace, so there is nothing to sign up for without subdomains
+fn base_domain(config: &Config) -> Result<&str, AppError> {
+    match &config.tenancy {
+        Tenancy::Subdomain { base_domain } => Ok(base_domain),
+        Tenancy::Single => Err(AppError::NotFound),
+    }
+}
+
+// === Components ===
+fn signup_page(open: bool, guest: bool, error: Option<&str>) -> Markup {
+    views::page(
+        "Sign up",
+        html! {
+            div class="bg-white rounded-lg shadow-lg p-8 max-w-md mx-auto" {
+                h1 class="text-2xl text-gray-700 mb-4" { "Create a workspace" }
+                form class="space-y-4" method="post" action=(routes::Signup::url()) {
+                    input class="w-full rounded p-2 border" type="text" name="id" placeholder="subdomain" pattern="[a-z0-9-]+" autofocus required;
+                    input class="w-full rounded p-2 border" type="text" name="name" placeholder="Workspace name" required;
+                    @if open {
+                        input class="w-full rounded p-2 border" type="text" name="invite" placeholder="Invite code (optional)";
+                    } @else {
+                        input class="w-full rounded p-2 border" type="text" name="invite" placeholder="Invite code" required;
+                    }
+                    @if guest {
+                        label class="flex items-center" {
+                            input class="mr-2" type="checkbox" name="claim" value="true" checked;
+                            "Bring along the todos of my guest workspace"
+                        }
+                    }
+                    @if let Some(error) = error {
+                        p class="text-red-500" { (error) }
+                    }
+                    button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" { "Create" }
+                }
+            }
+        },
+    )
+}
+
+// === Routes ===
+pub async fn show(
+    State(state): State<AppState>,
+    Extension(visitor): Extension<Visitor>,
+) -> Result<Markup, AppError> {
+    base_domain(&state.config)?;
+    let db = state.read().await;
+    let open = is_open(&state.live.load(), &db)?;
+    let guest = state.config.guest_mode && guest::has_data(&db, &visitor)?;
+    Ok(signup_page(open, guest, None))
+}
+
+#[derive(Deserialize)]
+pub struct Signup {
+    id: String,
+    name: String,
+    #[serde(default)]
+    invite: String,
+    // move the guest workspace over
+    #[serde(default)]
+    claim: bool,
+}
+pub async fn signup(
+    State(mut state): State<AppState>,
+    Extension(visitor): Extension<Visitor>,
+    Form(Signup {
+        id,
+        name,
+        invite,
+        claim,
+    }): Form<Signup>,
+) -> Result<Response, AppError> {
+    let config = state.live.load();
+    let base_domain = base_domain(&config)?;
+    let id = id.trim().to_string();
+    let name = name.trim().to_string();
+    let invite = invite.trim();
+    let db = state.write().await;
+    let open = is_open(&config, &db)?;
+    let guest = config.guest_mode && guest::has_data(&db, &visitor)?;
+    // checked under the write lock, two signups can neither take the same id nor one last use
+    let error = if !tenant::is_valid_id(&id) {
+        Some("The subdomain may only contain a-z, 0-9 and -.")
+    } else if guest::is_guest(&id) {
+        Some("That subdomain is taken.")
+    } else if name.is_empty() {
+        Some("Give the workspace a name.")
+    } else if tenant::get(&db, &id)?.is_some() {
+        Some("That subdomain is taken.")
+    } else if !invite.is_empty() && !redeem(&db, invite, db.clock().now_millis() / 1000)? {
+        Some("That invite code is not valid anymore.")
+    } else if invite.is_empty() && !open {
+        Some("Signing up needs an invite code.")
+    } else {
+        None
+    };
+    if let Some(error) = error {
+        return Ok(signup_page(open, guest, Some(error)).into_response());
+    }
+    let record = TenantRecord { id, name };
+    tenant::register(&db, &record)?;
+    // the guest workspace stays as it was if the move fails
+    let claimed = if guest && claim {
+        guest::claim(&db, &visitor, &record.id)?
+    } else {
+        0
+    };
+    tracing::info!(tenant = %record.id, invited = !invite.is_empty(), claimed, "signed up");
+    let url = workspace_url(&config.public_url, base_domain, &record.id);
+    Ok(views::page(
+        "Sign up",
+        html! {
+            div class="bg-white rounded-lg shadow-lg p-8 max-w-md mx-auto" {
+                h1 class="text-2xl text-gray-700 mb-4" { (record.name) " is ready" }
+                a class="text-blue-500 hover:text-blue-700" href=(url) { (url) }
+            }
+        },
+    )
+    .into_response())
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::TestDb;
+
+    #[test]
+    fn test_workspace_url() {
+        assert_eq!(
+            workspace_url("https://example.com", "example.com", "acme"),
+            "https://acme.example.com"
+        );
+        assert_eq!(
+            workspace_url("http://localhost:3000/", "localhost", "acme"),
+            "http://acme.localhost:3000"
+        );
+    }
+
+    #[test]
+    fn test_invites() -> anyhow::Result<()> {
+        let db = TestDb::new("registration")?;
+        let now = db.clock().now_millis() / 1000;
+
+        let once = create_invite(&db, Some(1), Some(Duration::from_secs(3600)))?;
+        assert!(redeem(&db, &once.code, now)?);
+        assert!(!redeem(&db, &once.code, now)?);
+
+        let expiring = create_invite(&db, None, Some(Duration::from_secs(60)))?;
+        assert!(redeem(&db, &expiring.code, now)?);
+        assert!(!redeem(&db, &expiring.code, now + 60)?);
+
+        let revoked = create_invite(&db, None, None)?;
+        revoke_invite(&db, &revoked.code)?;
+        assert!(!redeem(&db, &revoked.code, now)?);
+        assert!(!redeem(&db, "unknown", now)?);
+        assert_eq!(list_invites(&db)?.len(), 2);
+
+        let mut config = Config::default();
+        assert!(!is_open(&config, &db)?);
+        set_open(&db, true)?;
+        assert!(is_open(&config, &db)?);
+        config.registration_open = Some(false);
+        assert!(!is_open(&config, &db)?);
+        Ok(())
+    }
+}
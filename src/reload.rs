@@ -0,0 +1,271 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{bail, Result};
+
+use crate::{
+    config::{parse_list, parse_setting, Config},
+    maintenance,
+    state::AppState,
+    telemetry,
+};
+
+// how often the config file is checked for changes
+pub const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+// === Reloadable settings ===
+// What the config file may set, by the variable the environment sets it with. Everything else is
+// read once at startup.
+pub const RELOADABLE: &[&str] = &[
+    "LOG_LEVEL",
+    "LIST_DIFFING",
+    "REGISTRATION_OPEN",
+    "LOGIN_LOCKOUT_AFTER",
+    "MAINTENANCE_MODE",
+    "CORS_ALLOWED_ORIGINS",
+    "CORS_ALLOWED_METHODS",
+    "CORS_ALLOWED_HEADERS",
+];
+
+// Override the reloadable settings of `config` with the `KEY=VALUE` lines of a config file.
+// Blank lines and `#` comments are skipped, an empty value unsets a setting.
+pub fn apply_file(config: &mut Config, text: &str) -> Result<()> {
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            bail!("line {} of the config file is not `KEY=VALUE`", number + 1);
+        };
+        let (key, value) = (key.trim(), value.trim().trim_matches('"'));
+        match key {
+            "LOG_LEVEL" => config.log_level = parse_setting(key, value)?,
+            "LIST_DIFFING" => config.list_diffing = parse_setting(key, value)?.unwrap_or(false),
+            "REGISTRATION_OPEN" => config.registration_open = parse_setting(key, value)?,
+            "LOGIN_LOCKOUT_AFTER" => config.login_lockout_after = parse_setting(key, value)?,
+            "MAINTENANCE_MODE" => {
+                config.maintenance_mode = parse_setting(key, value)?.unwrap_or(false)
+            }
+            "CORS_ALLOWED_ORIGINS" => config.cors_allowed_origins = parse_list(value),
+            "CORS_ALLOWED_METHODS" => config.cors_allowed_methods = parse_list(value),
+            "CORS_ALLOWED_HEADERS" => config.cors_allowed_headers = parse_list(value),
+            other => bail!(
+                "{} can't be set in the config file, only {}",
+                other,
+                RELOADABLE.join(", ")
+            ),
+        }
+    }
+    Ok(())
+}
+
+// `NAME: old -> new` for every reloadable setting that differs
+pub fn changes(old: &Config, new: &Config) -> Vec<String> {
+    let settings = [
+        (
+            "LOG_LEVEL",
+            format!("{:?}", old.log_level),
+            format!("{:?}", new.log_level),
+        ),
+        (
+            "LIST_DIFFING",
+            old.list_diffing.to_string(),
+            new.list_diffing.to_string(),
+        ),
+        (
+            "REGISTRATION_OPEN",
+            format!("{:?}", old.registration_open),
+            format!("{:?}", new.registration_open),
+        ),
+        (
+            "LOGIN_LOCKOUT_AFTER",
+            format!("{:?}", old.login_lockout_after),
+            format!("{:?}", new.login_lockout_after),
+        ),
+        (
+            "MAINTENANCE_MODE",
+            old.maintenance_mode.to_string(),
+            new.maintenance_mode.to_string(),
+        ),
+        (
+            "CORS_ALLOWED_ORIGINS",
+            old.cors_allowed_origins.join(","),
+            new.cors_allowed_origins.join(","),
+        ),
+        (
+            "CORS_ALLOWED_METHODS",
+            old.cors_allowed_methods.join(","),
+            new.cors_allowed_methods.join(","),
+        ),
+        (
+            "CORS_ALLOWED_HEADERS",
+            old.cors_allowed_headers.join(","),
+            new.cors_allowed_headers.join(","),
+        ),
+    ];
+    settings
+        .into_iter()
+        .filter(|(_, old, new)| old != new)
+        .map(|(name, old, new)| format!("{}: {} -> {}", name, old, new))
+        .collect()
+}
+
+// `current` with the reloadable settings of `next`, the rest stays as it was started with
+pub fn reloaded(current: &Config, next: &Config) -> Config {
+    Config {
+        log_level: next.log_level.clone(),
+        list_diffing: next.list_diffing,
+        registration_open: next.registration_open,
+        login_lockout_after: next.login_lockout_after,
+        maintenance_mode: next.maintenance_mode,
+        cors_allowed_origins: next.cors_allowed_origins.clone(),
+        cors_allowed_methods: next.cors_allowed_methods.clone(),
+        cors_allowed_headers: next.cors_allowed_headers.clone(),
+        ..current.clone()
+    }
+}
+
+// === Live config ===
+// The configuration as it is now. A reload swaps it whole, so a handler holding one sees a
+// consistent version for the rest of the request.
+#[derive(Debug, Clone)]
+pub struct LiveConfig(Arc<RwLock<Arc<Config>>>);
+impl LiveConfig {
+    pub fn new(config: Config) -> Self {
+        Self(Arc::new(RwLock::new(Arc::new(config))))
+    }
+    pub fn load(&self) -> Arc<Config> {
+        self.0.read().expect("config lock poisoned").clone()
+    }
+    // put `config` in place, returns the one it replaced
+    pub fn store(&self, config: Config) -> Arc<Config> {
+        std::mem::replace(
+            &mut *self.0.write().expect("config lock poisoned"),
+            Arc::new(config),
+        )
+    }
+}
+
+// === Reloading ===
+// Read the environment and the config file again and apply what changed, returns the changes.
+// Nothing is applied when the file doesn't parse.
+pub fn reload(state: &AppState) -> Result<Vec<String>> {
+    let next = Config::from_env()?;
+    let current = state.live.load();
+    let changes = changes(&current, &next);
+    if changes.is_empty() {
+        return Ok(changes);
+    }
+    let next = reloaded(&current, &next);
+    if next.log_level != current.log_level {
+        telemetry::set_filter(next.log_level.as_deref())?;
+    }
+    let maintenance_changed = next.maintenance_mode != current.maintenance_mode;
+    state.live.store(next);
+    if maintenance_changed {
+        maintenance::announce(state);
+    }
+    Ok(changes)
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+// Reload whenever the modification time of the config file moves.
+pub fn spawn_watcher(state: AppState, path: PathBuf, interval: Duration) {
+    tokio::spawn(async move {
+        let mut seen = modified(&path);
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let modified = modified(&path);
+            if modified == seen {
+                continue;
+            }
+            seen = modified;
+            match reload(&state) {
+                Ok(changes) if changes.is_empty() => {}
+                Ok(changes) => tracing::info!(
+                    changes = %changes.join(", "),
+                    "reloaded {}",
+                    path.display()
+                ),
+                Err(err) => tracing::error!(
+                    error = %err,
+                    "failed to reload {}, keeping the current settings",
+                    path.display()
+                ),
+            }
+        }
+    });
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_file() -> Result<()> {
+        let mut config = Config {
+            registration_open: Some(true),
+            ..Config::default()
+        };
+        apply_file(
+            &mut config,
+            "# tweaked at runtime\n\nLOG_LEVEL = debug,sled=warn\nMAINTENANCE_MODE=true\n\
+             LOGIN_LOCKOUT_AFTER=\"5\"\nREGISTRATION_OPEN=\n\
+             CORS_ALLOWED_ORIGINS=https://app.example.com, http://localhost:5173\n",
+        )?;
+        assert_eq!(config.log_level.as_deref(), Some("debug,sled=warn"));
+        assert!(config.maintenance_mode);
+        assert_eq!(config.login_lockout_after, Some(5));
+        assert_eq!(config.registration_open, None);
+        assert_eq!(
+            config.cors_allowed_origins,
+            ["https://app.example.com", "http://localhost:5173"]
+        );
+
+        assert!(apply_file(&mut config, "LISTEN_ADDR=0.0.0.0:80").is_err());
+        assert!(apply_file(&mut config, "LIST_DIFFING").is_err());
+        assert!(apply_file(&mut config, "LOGIN_LOCKOUT_AFTER=many").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_reloaded_keeps_startup_settings() {
+        let current = Config {
+            concurrency_limit: 8,
+            ..Config::default()
+        };
+        let next = Config {
+            list_diffing: true,
+            concurrency_limit: 128,
+            ..Config::default()
+        };
+        assert_eq!(changes(&current, &next), ["LIST_DIFFING: false -> true"]);
+        let reloaded = reloaded(&current, &next);
+        assert!(reloaded.list_diffing);
+        assert_eq!(reloaded.concurrency_limit, 8);
+        assert!(changes(&reloaded, &next).is_empty());
+    }
+
+    #[test]
+    fn test_live_config_swaps_whole() {
+        let live = LiveConfig::new(Config::default());
+        let held = live.load();
+        let old = live.store(Config {
+            maintenance_mode: true,
+            ..Config::default()
+        });
+        assert!(!old.maintenance_mode && !held.maintenance_mode);
+        assert!(live.load().maintenance_mode);
+    }
+}
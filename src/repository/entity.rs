@@ -0,0 +1,244 @@
+use std::{collections::BTreeSet, marker::PhantomData};
+
+use anyhow::Result;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::db::{
+    driver::{Db, Tx},
+    queue::WriteOp,
+};
+
+// secondary index entries, `index:{entity}:{index}:{value}:{id}` with an empty value, so
+// listing by a value only walks the keys under it
+pub const INDEX_PREFIX: &str = "index:";
+
+// === Entities ===
+// A value stored under `{PREFIX}:{id}`, and the secondary indexes it is found by.
+pub trait Entity: Serialize + DeserializeOwned {
+    const PREFIX: &'static str;
+
+    fn id(&self) -> u64;
+    // (index, value) pairs, an entity is listed once under each
+    fn index_entries(&self) -> Vec<(&'static str, String)> {
+        Vec::new()
+    }
+    // refuse writing `self` or over it, e.g. while it is read only, see `error::Refused`
+    fn check_write(&self, _db: &Db) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub fn key<T: Entity>(id: u64) -> String {
+    format!("{}:{}", T::PREFIX, id)
+}
+
+fn index_prefix<T: Entity>(index: &str, value: &str) -> String {
+    format!("{}{}:{}:{}:", INDEX_PREFIX, T::PREFIX, index, value)
+}
+
+fn index_key<T: Entity>(index: &str, value: &str, id: u64) -> String {
+    format!("{}{}", index_prefix::<T>(index, value), id)
+}
+
+// === Repository ===
+// Typed access to the entities of one tree, owning their keys and index entries. Writes come as
+// `WriteOp`s so they go into the same batch as the rest of a change, the index entries with
+// the value they point at.
+pub struct Repository<'a, T> {
+    db: &'a Db,
+    entity: PhantomData<T>,
+}
+impl<'a, T: Entity> Repository<'a, T> {
+    pub fn new(db: &'a Db) -> Self {
+        Self {
+            db,
+            entity: PhantomData,
+        }
+    }
+
+    pub fn get(&self, id: u64) -> Result<Option<T>> {
+        self.db.get(key::<T>(id))
+    }
+    // the same, read inside a transaction
+    pub fn get_in(&self, tx: &Tx, id: u64) -> Result<Option<T>> {
+        tx.get(key::<T>(id))
+    }
+
+    // every entity, in key order
+    pub fn list(&self) -> Result<Vec<T>> {
+        self.db
+            .iter_prefix::<T>(&format!("{}:", T::PREFIX))?
+            .map(|item| item.map(|(_, entity)| entity))
+            .collect()
+    }
+
+    // the ids listed under `value` of `index`, without reading the entities
+    pub fn ids_by(&self, index: &str, value: &str) -> Result<Vec<u64>> {
+        let prefix = index_prefix::<T>(index, value);
+        self.db
+            .iter_keys(&prefix)
+            .filter_map(|key| match key {
+                Ok(key) => key[prefix.len()..].parse().ok().map(Ok),
+                Err(err) => Some(Err(err)),
+            })
+            .collect()
+    }
+
+    pub fn list_by(&self, index: &str, value: &str) -> Result<Vec<T>> {
+        let mut entities = Vec::new();
+        for id in self.ids_by(index, value)? {
+            entities.extend(self.get(id)?);
+        }
+        Ok(entities)
+    }
+
+    // Store `entity`, new or changed. Index entries of the stored version it no longer has go.
+    pub fn put_ops(&self, entity: &T) -> Result<Vec<WriteOp>> {
+        self.ops_replacing(self.get(entity.id())?, entity)
+    }
+    // `entity` in place of `stored`
+    fn ops_replacing(&self, stored: Option<T>, entity: &T) -> Result<Vec<WriteOp>> {
+        if let Some(stored) = &stored {
+            stored.check_write(self.db)?;
+        }
+        entity.check_write(self.db)?;
+        let id = entity.id();
+        let entries: BTreeSet<_> = entity.index_entries().into_iter().collect();
+        let stale: BTreeSet<_> = match stored {
+            Some(stored) => stored.index_entries().into_iter().collect(),
+            None => BTreeSet::new(),
+        };
+        let mut ops: Vec<WriteOp> = stale
+            .difference(&entries)
+            .map(|(index, value)| WriteOp::Remove {
+                key: index_key::<T>(index, value, id),
+            })
+            .collect();
+        for (index, value) in &entries {
+            ops.push(WriteOp::Insert {
+                key: index_key::<T>(index, value, id),
+                value: self.db.encode(&())?,
+            });
+        }
+        ops.push(WriteOp::Insert {
+            key: key::<T>(id),
+            value: self.db.encode(entity)?,
+        });
+        Ok(ops)
+    }
+
+    pub fn delete_ops(&self, id: u64) -> Result<Vec<WriteOp>> {
+        let mut ops: Vec<WriteOp> = match self.get(id)? {
+            Some(stored) => {
+                stored.check_write(self.db)?;
+                stored
+                    .index_entries()
+                    .into_iter()
+                    .map(|(index, value)| WriteOp::Remove {
+                        key: index_key::<T>(index, &value, id),
+                    })
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+        ops.push(WriteOp::Remove { key: key::<T>(id) });
+        Ok(ops)
+    }
+
+    // the same, written right away
+    pub fn put(&self, entity: &T) -> Result<()> {
+        self.db.apply_batch(self.put_ops(entity)?)
+    }
+    pub fn delete(&self, id: u64) -> Result<()> {
+        self.db.apply_batch(self.delete_ops(id)?)
+    }
+    // or as part of a transaction, the index entries replaced are the ones `tx` reads
+    pub fn put_in(&self, tx: &Tx, entity: &T) -> Result<()> {
+        tx.apply_batch(self.ops_replacing(self.get_in(tx, entity.id())?, entity)?)
+    }
+
+    // Change the stored entity with `change`, `None` when there is none. Read and write are one
+    // transaction, a concurrent change to the entity isn't lost, `change` may run more than once.
+    pub fn update(&self, id: u64, change: impl Fn(&mut T)) -> Result<Option<T>> {
+        self.db.transaction(|tx| {
+            let Some(mut entity) = self.get_in(tx, id)? else {
+                return Ok(None);
+            };
+            change(&mut entity);
+            self.put_in(tx, &entity)?;
+            Ok(Some(entity))
+        })
+    }
+
+    // Rebuild every index entry, for entities stored before an index existed. Returns how many
+    // entities were indexed.
+    pub fn reindex(&self) -> Result<usize> {
+        let prefix = format!("{}{}:", INDEX_PREFIX, T::PREFIX);
+        let mut ops: Vec<WriteOp> = self
+            .db
+            .iter_keys(&prefix)
+            .map(|key| key.map(|key| WriteOp::Remove { key }))
+            .collect::<Result<_>>()?;
+        let entities = self.list()?;
+        for entity in &entities {
+            for (index, value) in entity.index_entries() {
+                ops.push(WriteOp::Insert {
+                    key: index_key::<T>(index, &value, entity.id()),
+                    value: self.db.encode(&())?,
+                });
+            }
+        }
+        self.db.apply_batch(ops)?;
+        Ok(entities.len())
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::{TestDb, TodoFixture},
+        models::Todo,
+    };
+
+    #[test]
+    fn test_indexes_follow_changes() -> Result<()> {
+        let db = TestDb::new("entity")?;
+        let todos = Repository::<Todo>::new(&db);
+        let mut todo = Todo::new(db.next_id()?, "Water the plants".into());
+        todo.tags = vec!["home".into()];
+        todos.put(&todo)?;
+        assert_eq!(todos.ids_by("tag", "home")?, [todo.id]);
+        assert_eq!(todos.ids_by("completed", "false")?, [todo.id]);
+
+        let done = todos
+            .update(todo.id, |todo| {
+                todo.set_completed(true);
+                todo.tags = vec!["garden".into()];
+            })?
+            .unwrap();
+        assert!(todos.ids_by("tag", "home")?.is_empty());
+        assert!(todos.ids_by("completed", "false")?.is_empty());
+        assert_eq!(todos.list_by("tag", "garden")?, [done]);
+
+        todos.delete(todo.id)?;
+        assert!(todos.get(todo.id)?.is_none());
+        assert!(todos.ids_by("completed", "true")?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_reindex() -> Result<()> {
+        let db = TestDb::new("entity_reindex")?;
+        // written around the repository, as before the index existed
+        let todo = TodoFixture::new().with_tags(["work"]).build(&db)?;
+        db.insert(key::<Todo>(todo.id), &todo)?;
+        db.insert(index_key::<Todo>("tag", "gone", todo.id), &())?;
+        let todos = Repository::<Todo>::new(&db);
+        assert_eq!(todos.reindex()?, 1);
+        assert_eq!(todos.ids_by("tag", "work")?, [todo.id]);
+        assert!(todos.ids_by("tag", "gone")?.is_empty());
+        Ok(())
+    }
+}
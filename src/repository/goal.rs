@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+use super::todo;
+use crate::{
+    db::driver::Db,
+    error::AppError,
+    models::{Goal, Progress, Todo},
+};
+
+// goals, `goal:{id}`
+const GOAL_PREFIX: &str = "goal:";
+// the goal a todo counts towards, `goal_of:{todo}`
+const GOAL_OF_PREFIX: &str = "goal_of:";
+
+pub fn goal_key(id: u64) -> String {
+    format!("{}{}", GOAL_PREFIX, id)
+}
+pub fn goal_of_key(todo: u64) -> String {
+    format!("{}{}", GOAL_OF_PREFIX, todo)
+}
+
+pub fn all(db: &Db) -> Result<Vec<Goal>> {
+    db.iter_prefix::<Goal>(GOAL_PREFIX)?
+        .map(|item| item.map(|(_, goal)| goal))
+        .collect()
+}
+
+pub fn goal_of(db: &Db, todo: u64) -> Result<Option<u64>> {
+    db.get(goal_of_key(todo))
+}
+
+// Link `todo` to `goal`, or unlink it with `None`.
+pub fn set_goal(db: &Db, todo: u64, goal: Option<u64>) -> Result<(), AppError> {
+    match goal {
+        Some(goal) => {
+            if db.get::<Goal, _>(goal_key(goal))?.is_none() {
+                return Err(AppError::NotFound);
+            }
+            db.insert(goal_of_key(todo), &goal)?;
+        }
+        None => db.remove(goal_of_key(todo))?,
+    }
+    Ok(())
+}
+
+// A goal goes away on its own, its todos stay and are unlinked.
+pub fn remove(db: &Db, goal: u64) -> Result<()> {
+    let mut keys = vec![goal_key(goal)];
+    for item in db.iter_prefix::<u64>(GOAL_OF_PREFIX)? {
+        let (key, linked) = item?;
+        if linked == goal {
+            keys.push(key);
+        }
+    }
+    db.remove_all(keys)
+}
+
+// progress per goal, from the completion of the todos linked to it
+pub fn progress(db: &Db) -> Result<HashMap<u64, Progress>> {
+    let mut progress: HashMap<u64, Progress> = HashMap::new();
+    for item in db.iter_prefix::<u64>(GOAL_OF_PREFIX)? {
+        let (key, goal) = item?;
+        let Some(id) = key
+            .strip_prefix(GOAL_OF_PREFIX)
+            .and_then(|id| id.parse().ok())
+        else {
+            continue;
+        };
+        let Some(todo) = db.get::<Todo, _>(todo::todo_key(id))? else {
+            continue;
+        };
+        if todo.archived {
+            continue;
+        }
+        let entry = progress.entry(goal).or_default();
+        entry.total += 1;
+        if todo.completed {
+            entry.done += 1;
+        }
+    }
+    Ok(progress)
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::TestDb;
+
+    #[test]
+    fn test_progress_follows_linked_todos() -> Result<()> {
+        let db = TestDb::new("goal")?;
+        db.insert(
+            goal_key(1),
+            &Goal {
+                id: 1,
+                title: "ship".into(),
+            },
+        )?;
+        let mut done = Todo::new(2, "done".into());
+        done.set_completed(true);
+        db.insert(todo::todo_key(2), &done)?;
+        db.insert(todo::todo_key(3), &Todo::new(3, "open".into()))?;
+        for id in [2, 3] {
+            set_goal(&db, id, Some(1)).map_err(|_| anyhow::anyhow!("set goal"))?;
+        }
+        assert!(matches!(set_goal(&db, 3, Some(9)), Err(AppError::NotFound)));
+
+        let progress = progress(&db)?[&1];
+        assert_eq!(progress, Progress { done: 1, total: 2 });
+        assert_eq!(progress.percent(), 50);
+
+        remove(&db, 1)?;
+        assert_eq!(goal_of(&db, 2)?, None);
+        Ok(())
+    }
+}
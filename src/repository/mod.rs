@@ -1 +1,7 @@
+pub mod entity;
+pub mod goal;
+pub mod query;
+pub mod review;
+pub mod tag_archive;
 pub mod todo;
+pub mod trash;
@@ -0,0 +1,535 @@
+use std::{collections::BTreeSet, str::FromStr};
+
+use anyhow::Result;
+use time::Date;
+
+use super::{tag_archive, todo};
+use crate::{
+    db::driver::Db,
+    error::AppError,
+    models::{self, Status, Todo},
+};
+
+// === Sort ===
+// the orders todos can be listed in, creation order follows the ids
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Sort {
+    #[default]
+    Created,
+    // soonest first, todos without a due date last
+    Due,
+    // least recently touched first
+    Updated,
+    // a to z, ignoring case
+    Title,
+}
+impl FromStr for Sort {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, AppError> {
+        match s {
+            "created" => Ok(Self::Created),
+            "due" => Ok(Self::Due),
+            "updated" => Ok(Self::Updated),
+            "title" => Ok(Self::Title),
+            other => Err(AppError::Invalid(format!(
+                "cannot sort by `{}`, use `created`, `due`, `updated` or `title`",
+                other
+            ))),
+        }
+    }
+}
+impl Sort {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Sort::Created => "created",
+            Sort::Due => "due",
+            Sort::Updated => "updated",
+            Sort::Title => "title",
+        }
+    }
+}
+
+// A todo's place in a sort order. Cursors are these keys, so a page still continues in the
+// right place when the todo it ended on is gone.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SortKey {
+    undated: bool,
+    due: Option<Date>,
+    updated_at: u64,
+    // lowercased
+    title: String,
+    id: u64,
+}
+impl SortKey {
+    pub fn of(todo: &Todo, sort: Sort) -> Self {
+        let mut key = Self {
+            undated: false,
+            due: None,
+            updated_at: 0,
+            title: String::new(),
+            id: todo.id,
+        };
+        match sort {
+            Sort::Created => {}
+            Sort::Due => {
+                key.undated = todo.due.is_none();
+                key.due = todo.due;
+            }
+            Sort::Updated => key.updated_at = todo.updated_at,
+            Sort::Title => key.title = todo.title.to_lowercase(),
+        }
+        key
+    }
+
+    // `{id}` by creation, `{yyyy-mm-dd|none}_{id}` by due date, `{updated_at}_{id}` by update,
+    // `{title}_{id}` by title
+    pub fn cursor(&self, sort: Sort) -> String {
+        match sort {
+            Sort::Created => self.id.to_string(),
+            Sort::Due => match self.due {
+                Some(due) => format!("{}_{}", due, self.id),
+                None => format!("none_{}", self.id),
+            },
+            Sort::Updated => format!("{}_{}", self.updated_at, self.id),
+            Sort::Title => format!("{}_{}", self.title, self.id),
+        }
+    }
+    pub fn parse(cursor: &str, sort: Sort) -> Result<Self, AppError> {
+        let invalid = || AppError::Invalid(format!("`{}` is not a cursor", cursor));
+        let mut key = Self {
+            undated: false,
+            due: None,
+            updated_at: 0,
+            title: String::new(),
+            id: 0,
+        };
+        let id = match sort {
+            Sort::Created => cursor,
+            Sort::Due => {
+                let (due, id) = cursor.split_once('_').ok_or_else(invalid)?;
+                key.due = match due {
+                    "none" => None,
+                    due => models::parse_due(due).map_err(|_| invalid())?,
+                };
+                key.undated = key.due.is_none();
+                id
+            }
+            Sort::Updated => {
+                let (updated_at, id) = cursor.split_once('_').ok_or_else(invalid)?;
+                key.updated_at = updated_at.parse().map_err(|_| invalid())?;
+                id
+            }
+            // titles may contain `_` themselves, ids don't
+            Sort::Title => {
+                let (title, id) = cursor.rsplit_once('_').ok_or_else(invalid)?;
+                key.title = title.to_string();
+                id
+            }
+        };
+        key.id = id.parse().map_err(|_| invalid())?;
+        Ok(key)
+    }
+}
+
+// === Query ===
+// Which todos to list and how. Everything that lists todos builds one of these instead of
+// filtering the whole keyspace itself. Archived and someday todos are left out unless asked
+// for, or someday todos are what the query is after. Reading from the database, todos under an
+// archived tag count as archived.
+#[derive(Debug, Clone, Default)]
+pub struct TodoQuery {
+    archived: bool,
+    someday: bool,
+    completed: Option<bool>,
+    status: Option<Status>,
+    // inclusive
+    due_between: Option<(Date, Date)>,
+    updated_before: Option<u64>,
+    available_on: Option<Date>,
+    list: Option<String>,
+    // lowercased, found in the title or a tag
+    text: Option<String>,
+    // custom field names and the values they must have, see `fields`
+    fields: Vec<(String, String)>,
+    sort: Sort,
+    after: Option<SortKey>,
+    before: Option<SortKey>,
+    // matches skipped before the page starts, for numbered pages
+    offset: usize,
+    limit: Option<usize>,
+}
+
+// === Pages ===
+// One page of a listing and where the pages around it start, for views and the api alike. The
+// cursors are those of the first and last item, `None` at either end of the listing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    // pass as `before` for the page before this one
+    pub prev_cursor: Option<String>,
+    // pass as `after` for the page after this one
+    pub next_cursor: Option<String>,
+    // everything that matched, over all pages
+    pub total_hint: Option<usize>,
+}
+impl<T> Page<T> {
+    pub fn has_prev(&self) -> bool {
+        self.prev_cursor.is_some()
+    }
+    pub fn has_next(&self) -> bool {
+        self.next_cursor.is_some()
+    }
+    // the same page with every item turned into something else, e.g. an api resource
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            prev_cursor: self.prev_cursor,
+            next_cursor: self.next_cursor,
+            total_hint: self.total_hint,
+        }
+    }
+}
+
+impl TodoQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn including_archived(mut self) -> Self {
+        self.archived = true;
+        self
+    }
+    pub fn including_someday(mut self) -> Self {
+        self.someday = true;
+        self
+    }
+    pub fn completed(mut self, completed: bool) -> Self {
+        self.completed = Some(completed);
+        self
+    }
+    pub fn status(mut self, status: Status) -> Self {
+        self.status = Some(status);
+        self
+    }
+    pub fn due_between(mut self, from: Date, to: Date) -> Self {
+        self.due_between = Some((from, to));
+        self
+    }
+    // last touched before `updated_at`, in unix seconds
+    pub fn updated_before(mut self, updated_at: u64) -> Self {
+        self.updated_before = Some(updated_at);
+        self
+    }
+    // leaves out todos scheduled to start after `day`
+    pub fn available_on(mut self, day: Date) -> Self {
+        self.available_on = Some(day);
+        self
+    }
+    // the todos filed under `list`
+    pub fn in_list(mut self, list: &str) -> Self {
+        self.list = Some(list.to_string());
+        self
+    }
+    // todos whose title or a tag contains `text`, ignoring case, blank matches everything
+    pub fn search(mut self, text: &str) -> Self {
+        let text = text.trim().to_lowercase();
+        self.text = (!text.is_empty()).then_some(text);
+        self
+    }
+    // todos whose custom field `name` is `value`, as stored
+    pub fn field(mut self, name: &str, value: &str) -> Self {
+        self.fields.push((name.to_string(), value.to_string()));
+        self
+    }
+    pub fn sort(mut self, sort: Sort) -> Self {
+        self.sort = sort;
+        self
+    }
+    pub fn after(mut self, cursor: SortKey) -> Self {
+        self.after = Some(cursor);
+        self
+    }
+    // continue after a `Page::next_cursor` of the same sort
+    pub fn after_cursor(self, cursor: &str) -> Result<Self, AppError> {
+        let key = SortKey::parse(cursor, self.sort)?;
+        Ok(self.after(key))
+    }
+    // paging backwards, the page ends right before `cursor`
+    pub fn before(mut self, cursor: SortKey) -> Self {
+        self.before = Some(cursor);
+        self
+    }
+    // end right before a `Page::prev_cursor` of the same sort
+    pub fn before_cursor(self, cursor: &str) -> Result<Self, AppError> {
+        let key = SortKey::parse(cursor, self.sort)?;
+        Ok(self.before(key))
+    }
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn matches(&self, todo: &Todo) -> bool {
+        (self.archived || !todo.archived)
+            && (self.someday
+                || self.status == Some(Status::Someday)
+                || todo.status != Status::Someday)
+            && self
+                .completed
+                .map_or(true, |completed| todo.completed == completed)
+            && self.status.map_or(true, |status| todo.status == status)
+            && self.due_between.map_or(true, |(from, to)| {
+                todo.due.is_some_and(|due| from <= due && due <= to)
+            })
+            && self
+                .updated_before
+                .map_or(true, |cutoff| todo.updated_at < cutoff)
+            && self
+                .available_on
+                .map_or(true, |day| !todo.is_scheduled_after(day))
+            && self
+                .list
+                .as_ref()
+                .map_or(true, |list| todo.list.as_ref() == Some(list))
+            && self.text.as_ref().map_or(true, |text| {
+                todo.title.to_lowercase().contains(text)
+                    || todo.tags.iter().any(|tag| tag.contains(text))
+            })
+            && self
+                .fields
+                .iter()
+                .all(|(name, value)| todo.fields.get(name) == Some(value))
+    }
+
+    // the matching todos of `todos`, sorted and cut down to the page
+    pub fn apply(&self, todos: impl IntoIterator<Item = Todo>) -> Page<Todo> {
+        self.page(
+            todos
+                .into_iter()
+                .filter(|todo| self.matches(todo))
+                .collect(),
+        )
+    }
+
+    // `todos` all match, sort them and cut out the page
+    fn page(&self, mut todos: Vec<Todo>) -> Page<Todo> {
+        todos.sort_by_cached_key(|todo| SortKey::of(todo, self.sort));
+        let key = |todo: &Todo| SortKey::of(todo, self.sort);
+        let start = match &self.after {
+            Some(after) => todos.partition_point(|todo| key(todo) <= *after),
+            None => 0,
+        };
+        let end = match &self.before {
+            Some(before) => todos.partition_point(|todo| key(todo) < *before),
+            None => todos.len(),
+        }
+        .max(start);
+        let start = (start + self.offset).min(end);
+        // paging backwards takes the end of the range, forwards its start
+        let backwards = self.before.is_some() && self.after.is_none();
+        let (start, end) = match self.limit {
+            None => (start, end),
+            Some(limit) if backwards => (end.saturating_sub(limit).max(start), end),
+            Some(limit) => (start, end.min(start + limit)),
+        };
+        let total = todos.len();
+        todos.truncate(end);
+        todos.drain(..start);
+        let cursor =
+            |todo: Option<&Todo>| todo.map(|todo| SortKey::of(todo, self.sort).cursor(self.sort));
+        Page {
+            prev_cursor: cursor(todos.first()).filter(|_| start > 0),
+            next_cursor: cursor(todos.last()).filter(|_| end < total),
+            total_hint: Some(total),
+            items: todos,
+        }
+    }
+
+    // Filtered while reading, only the matching todos are kept to be sorted and paged.
+    pub fn run(&self, db: &Db) -> Result<Page<Todo>> {
+        let archived_tags = if self.archived {
+            BTreeSet::new()
+        } else {
+            tag_archive::names(db)?
+        };
+        let mut matching = Vec::new();
+        for todo in todo::iter(db)? {
+            let todo = todo?;
+            if self.matches(&todo) && !todo.tags.iter().any(|tag| archived_tags.contains(tag)) {
+                matching.push(todo);
+            }
+        }
+        Ok(self.page(matching))
+    }
+    // the matching todos without the paging details
+    pub fn list(&self, db: &Db) -> Result<Vec<Todo>> {
+        Ok(self.run(db)?.items)
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use proptest::{option, prelude::*, sample};
+    use time::macros::date;
+
+    use super::*;
+
+    prop_compose! {
+        // todos with distinct ids
+        fn arb_todos()(
+            todos in proptest::collection::btree_map(
+                any::<u64>(),
+                (option::of(1u16..=365), 0u64..1_000),
+                0..40,
+            ),
+        ) -> Vec<Todo> {
+            todos
+                .into_iter()
+                .map(|(id, (due, updated_at))| {
+                    let mut todo = Todo::new(id, id.to_string());
+                    todo.due = due.map(|day| Date::from_ordinal_date(2024, day).unwrap());
+                    todo.updated_at = updated_at;
+                    todo
+                })
+                .collect()
+        }
+    }
+
+    fn arb_sort() -> impl Strategy<Value = Sort> {
+        sample::select(vec![Sort::Created, Sort::Due, Sort::Updated, Sort::Title])
+    }
+
+    proptest! {
+        #[test]
+        fn test_cursors_round_trip(todos in arb_todos(), sort in arb_sort()) {
+            for todo in &todos {
+                let key = SortKey::of(todo, sort);
+                prop_assert_eq!(SortKey::parse(&key.cursor(sort), sort).unwrap(), key);
+            }
+        }
+
+        // walking the pages forward visits every todo once, in sort order
+        #[test]
+        fn test_pages_cover_every_todo(todos in arb_todos(), sort in arb_sort(), limit in 1usize..10) {
+            let mut seen = Vec::new();
+            let mut query = TodoQuery::new().sort(sort).limit(limit);
+            loop {
+                let page = query.apply(todos.clone());
+                seen.extend(page.items.iter().map(|todo| SortKey::of(todo, sort)));
+                match &page.next_cursor {
+                    Some(cursor) => query = query.after_cursor(cursor).unwrap(),
+                    None => break,
+                }
+            }
+            let mut expected: Vec<SortKey> = todos.iter().map(|todo| SortKey::of(todo, sort)).collect();
+            expected.sort();
+            prop_assert_eq!(seen, expected);
+        }
+    }
+
+    fn ids(page: &Page<Todo>) -> Vec<u64> {
+        page.items.iter().map(|todo| todo.id).collect()
+    }
+
+    #[test]
+    fn test_pages_follow_cursors() {
+        let todos: Vec<Todo> = (1..=5)
+            .rev()
+            .map(|id| Todo::new(id, format!("todo {}", id)))
+            .collect();
+        let first = TodoQuery::new().limit(2).apply(todos.clone());
+        assert_eq!(ids(&first), [1, 2]);
+        assert!(!first.has_prev() && first.has_next());
+        assert_eq!(first.next_cursor.as_deref(), Some("2"));
+        assert_eq!(first.total_hint, Some(5));
+
+        let cursor = SortKey::of(&first.items[1], Sort::Created);
+        let second = TodoQuery::new().limit(2).after(cursor).apply(todos.clone());
+        assert_eq!(ids(&second), [3, 4]);
+        assert!(second.has_prev() && second.has_next());
+
+        let cursor = SortKey::of(&second.items[0], Sort::Created);
+        let back = TodoQuery::new().limit(2).before(cursor).apply(todos);
+        assert_eq!(ids(&back), [1, 2]);
+    }
+
+    #[test]
+    fn test_filters() {
+        let mut done = Todo::new(1, "done".into());
+        done.set_completed(true);
+        let mut archived = Todo::new(2, "archived".into());
+        archived.archived = true;
+        let mut may = Todo::new(3, "may".into());
+        may.due = Some(date!(2024 - 05 - 10));
+        let todos = vec![done, archived, may];
+
+        assert_eq!(ids(&TodoQuery::new().apply(todos.clone())), [1, 3]);
+        assert_eq!(
+            ids(&TodoQuery::new().including_archived().apply(todos.clone())),
+            [1, 2, 3]
+        );
+        assert_eq!(
+            ids(&TodoQuery::new().completed(false).apply(todos.clone())),
+            [3]
+        );
+        let may = TodoQuery::new().due_between(date!(2024 - 05 - 01), date!(2024 - 05 - 31));
+        assert_eq!(ids(&may.apply(todos)), [3]);
+    }
+
+    #[test]
+    fn test_someday_left_out() {
+        let mut someday = Todo::new(1, "someday".into());
+        someday.set_status(Status::Someday);
+        let todos = vec![someday, Todo::new(2, "now".into())];
+        assert_eq!(ids(&TodoQuery::new().apply(todos.clone())), [2]);
+        assert_eq!(
+            ids(&TodoQuery::new().including_someday().apply(todos.clone())),
+            [1, 2]
+        );
+        assert_eq!(
+            ids(&TodoQuery::new().status(Status::Someday).apply(todos)),
+            [1]
+        );
+    }
+
+    #[test]
+    fn test_available_on() {
+        let mut later = Todo::new(1, "later".into());
+        later.scheduled_for = Some(date!(2024 - 05 - 10));
+        let todos = vec![later, Todo::new(2, "now".into())];
+        let before = TodoQuery::new().available_on(date!(2024 - 05 - 09));
+        assert_eq!(ids(&before.apply(todos.clone())), [2]);
+        let on = TodoQuery::new().available_on(date!(2024 - 05 - 10));
+        assert_eq!(ids(&on.apply(todos)), [1, 2]);
+    }
+
+    #[test]
+    fn test_due_cursor_round_trips() {
+        let mut todo = Todo::new(4, "Milk".into());
+        todo.due = Some(date!(2024 - 05 - 01));
+        let key = SortKey::of(&todo, Sort::Due);
+        assert_eq!(key.cursor(Sort::Due), "2024-05-01_4");
+        assert_eq!(
+            SortKey::parse(&key.cursor(Sort::Due), Sort::Due).unwrap(),
+            key
+        );
+        // undated todos come after every dated one
+        assert!(key < SortKey::of(&Todo::new(1, "Eggs".into()), Sort::Due));
+    }
+
+    #[test]
+    fn test_title_cursor_round_trips() {
+        let key = SortKey::of(&Todo::new(12, "Fix the_bug".into()), Sort::Title);
+        assert_eq!(key.cursor(Sort::Title), "fix the_bug_12");
+        assert_eq!(
+            SortKey::parse(&key.cursor(Sort::Title), Sort::Title).unwrap(),
+            key
+        );
+    }
+}
@@ -0,0 +1,137 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use super::query::{Sort, TodoQuery};
+use crate::{db::driver::Db, models::Todo};
+
+// review sessions, `review:{id}`, zero padded so they iterate oldest first
+const REVIEW_PREFIX: &str = "review:";
+// how long after a finished review the next one is due
+pub const REVIEW_INTERVAL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Keep,
+    Reschedule,
+    Archive,
+    Delete,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Session {
+    pub id: u64,
+    pub started_at: u64,
+    pub finished_at: Option<u64>,
+    pub kept: u32,
+    pub rescheduled: u32,
+    pub archived: u32,
+    pub deleted: u32,
+}
+
+fn key(id: u64) -> String {
+    format!("{}{:020}", REVIEW_PREFIX, id)
+}
+
+fn now(db: &Db) -> u64 {
+    db.clock().now_millis() / 1000
+}
+
+fn sessions(db: &Db) -> Result<Vec<Session>> {
+    db.iter_prefix::<Session>(REVIEW_PREFIX)?
+        .map(|item| item.map(|(_, session)| session))
+        .collect()
+}
+
+// Open todos nobody touched in `stale_after`, oldest first. Every review action touches,
+// archives or deletes the todo, so this shrinks as the review goes on.
+pub fn stale(db: &Db, stale_after: Duration) -> Result<Vec<Todo>> {
+    let cutoff = now(db).saturating_sub(stale_after.as_secs());
+    TodoQuery::new()
+        .completed(false)
+        .updated_before(cutoff)
+        .sort(Sort::Updated)
+        .list(db)
+}
+
+// === Sessions ===
+// the session in progress, if a review was started and not finished
+pub fn current(db: &Db) -> Result<Option<Session>> {
+    Ok(sessions(db)?
+        .pop()
+        .filter(|session| session.finished_at.is_none()))
+}
+
+pub fn start(db: &Db) -> Result<Session> {
+    if let Some(session) = current(db)? {
+        return Ok(session);
+    }
+    let session = Session {
+        id: db.next_id()?,
+        started_at: now(db),
+        ..Default::default()
+    };
+    db.insert(key(session.id), &session)?;
+    Ok(session)
+}
+
+pub fn record(db: &Db, session: &mut Session, action: Action) -> Result<()> {
+    match action {
+        Action::Keep => session.kept += 1,
+        Action::Reschedule => session.rescheduled += 1,
+        Action::Archive => session.archived += 1,
+        Action::Delete => session.deleted += 1,
+    }
+    db.insert(key(session.id), session)
+}
+
+pub fn finish(db: &Db, session: &mut Session) -> Result<()> {
+    session.finished_at = Some(now(db));
+    db.insert(key(session.id), session)
+}
+
+// A review is due when there is something stale and the last one finished over a week ago.
+pub fn is_due(db: &Db, stale_after: Duration) -> Result<bool> {
+    let last_finished = sessions(db)?
+        .iter()
+        .filter_map(|session| session.finished_at)
+        .max();
+    if last_finished.is_some_and(|at| at + REVIEW_INTERVAL.as_secs() > now(db)) {
+        return Ok(false);
+    }
+    Ok(!stale(db, stale_after)?.is_empty())
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixtures::TestDb, repository::todo};
+
+    #[test]
+    fn test_review_due_until_finished() -> Result<()> {
+        let db = TestDb::new("review")?;
+        let mut old = Todo::created(1, "old".into(), &db.clock);
+        old.updated_at -= 30 * 24 * 60 * 60;
+        db.insert(todo::todo_key(1), &old)?;
+        db.insert(
+            todo::todo_key(2),
+            &Todo::created(2, "fresh".into(), &db.clock),
+        )?;
+
+        let stale_after = Duration::from_secs(14 * 24 * 60 * 60);
+        let stale_ids: Vec<u64> = stale(&db, stale_after)?.iter().map(|t| t.id).collect();
+        assert_eq!(stale_ids, vec![1]);
+        assert!(is_due(&db, stale_after)?);
+
+        let mut session = start(&db)?;
+        assert_eq!(start(&db)?.id, session.id);
+        record(&db, &mut session, Action::Keep)?;
+        finish(&db, &mut session)?;
+        assert!(current(&db)?.is_none());
+        assert!(!is_due(&db, stale_after)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::Duration,
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use super::todo;
+use crate::{db::driver::Db, error::Refused, models::Todo};
+
+// `tag_archive:{tag}`
+const ARCHIVE_PREFIX: &str = "tag_archive:";
+
+// === Archived tags ===
+// A tag put away with everything under it. Its todos are left out of listings like archived
+// todos and are read only until the tag is restored, the repository refuses writes to them,
+// see `Entity::check_write`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchivedTag {
+    pub tag: String,
+    // unix seconds
+    pub archived_at: u64,
+    // by the auto-archive policy rather than by someone
+    pub automatic: bool,
+}
+
+fn key(tag: &str) -> String {
+    format!("{}{}", ARCHIVE_PREFIX, tag)
+}
+
+fn now(db: &Db) -> u64 {
+    db.clock().now_millis() / 1000
+}
+
+// archived last first
+pub fn list(db: &Db) -> Result<Vec<ArchivedTag>> {
+    let mut archived = db
+        .iter_prefix::<ArchivedTag>(ARCHIVE_PREFIX)?
+        .map(|item| item.map(|(_, archived)| archived))
+        .collect::<Result<Vec<_>>>()?;
+    archived.sort_by(|a, b| b.archived_at.cmp(&a.archived_at));
+    Ok(archived)
+}
+
+pub fn names(db: &Db) -> Result<BTreeSet<String>> {
+    db.iter_keys(ARCHIVE_PREFIX)
+        .map(|key| key.map(|key| key[ARCHIVE_PREFIX.len()..].to_string()))
+        .collect()
+}
+
+pub fn is_archived(db: &Db, tag: &str) -> Result<bool> {
+    Ok(db.get::<ArchivedTag, _>(key(tag))?.is_some())
+}
+
+pub fn archive(db: &Db, tag: &str, automatic: bool) -> Result<ArchivedTag> {
+    let archived = ArchivedTag {
+        tag: tag.to_string(),
+        archived_at: now(db),
+        automatic,
+    };
+    db.insert(key(tag), &archived)?;
+    Ok(archived)
+}
+
+// false when the tag was not archived
+pub fn restore(db: &Db, tag: &str) -> Result<bool> {
+    if !is_archived(db, tag)? {
+        return Ok(false);
+    }
+    db.remove(key(tag))?;
+    Ok(true)
+}
+
+// refuses `todo` while one of its tags is archived
+pub fn check_writable(db: &Db, todo: &Todo) -> Result<()> {
+    for tag in &todo.tags {
+        if is_archived(db, tag)? {
+            return Err(Refused(format!(
+                "#{} is archived, restore it to change its todos.",
+                tag
+            ))
+            .into());
+        }
+    }
+    Ok(())
+}
+
+// === Policy ===
+// Tags none of whose todos changed in `idle_for`, the ones the policy archives.
+pub fn idle(db: &Db, idle_for: Duration) -> Result<Vec<String>> {
+    let cutoff = now(db).saturating_sub(idle_for.as_secs());
+    let archived = names(db)?;
+    let mut last_activity: BTreeMap<String, u64> = BTreeMap::new();
+    for todo in todo::iter(db)? {
+        let todo = todo?;
+        for tag in &todo.tags {
+            let last = last_activity.entry(tag.clone()).or_default();
+            *last = (*last).max(todo.updated_at);
+        }
+    }
+    Ok(last_activity
+        .into_iter()
+        .filter(|(tag, last)| *last < cutoff && !archived.contains(tag))
+        .map(|(tag, _)| tag)
+        .collect())
+}
+
+// archive the idle tags, returns them
+pub fn archive_idle(db: &Db, idle_for: Duration) -> Result<Vec<String>> {
+    let idle = idle(db, idle_for)?;
+    for tag in &idle {
+        archive(db, tag, true)?;
+    }
+    Ok(idle)
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        error::AppError,
+        fixtures::{TestDb, TodoFixture},
+        repository::{entity::Repository, query::TodoQuery},
+    };
+
+    #[test]
+    fn test_archived_tags_are_read_only() -> Result<()> {
+        let db = TestDb::new("tag_archive")?;
+        let mut trip = TodoFixture::new().with_tags(["trip"]).build(&db)?;
+        let todos = Repository::<Todo>::new(&db);
+        archive(&db, "trip", false)?;
+        assert_eq!(names(&db)?, BTreeSet::from(["trip".to_string()]));
+
+        trip.title = "Changed".into();
+        let refused = AppError::from(todos.put_ops(&trip).unwrap_err());
+        assert!(matches!(refused, AppError::Invalid(_)));
+        assert!(todos.delete_ops(trip.id).is_err());
+        // left out of listings like archived todos
+        assert!(TodoQuery::new().list(&db)?.is_empty());
+        assert_eq!(TodoQuery::new().including_archived().list(&db)?.len(), 1);
+
+        assert!(restore(&db, "trip")?);
+        assert!(todos.put_ops(&trip).is_ok());
+        assert!(!restore(&db, "trip")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_idle_tags() -> Result<()> {
+        let db = TestDb::new("tag_archive_idle")?;
+        let now = now(&db);
+        let mut old = TodoFixture::new().with_tags(["old", "mixed"]).build(&db)?;
+        old.updated_at = now - 40 * 24 * 60 * 60;
+        Repository::new(&db).put(&old)?;
+        TodoFixture::new().with_tags(["mixed"]).build(&db)?;
+
+        let month = Duration::from_secs(30 * 24 * 60 * 60);
+        assert_eq!(archive_idle(&db, month)?, ["old"]);
+        assert!(list(&db)?[0].automatic);
+        // archived already
+        assert!(idle(&db, month)?.is_empty());
+        Ok(())
+    }
+}
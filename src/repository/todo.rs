@@ -1 +1,285 @@
+use std::collections::{HashMap, HashSet};
 
+use anyhow::Result;
+
+use super::{
+    entity::{self, Entity, Repository},
+    goal, tag_archive, trash,
+};
+use crate::{
+    db::{driver::Db, queue::WriteOp},
+    error::AppError,
+    models::Todo,
+    reactions,
+};
+
+// the todos a todo waits for, `blocked_by:{id}`
+const BLOCKED_BY_PREFIX: &str = "blocked_by:";
+
+impl Entity for Todo {
+    const PREFIX: &'static str = "todo";
+
+    fn id(&self) -> u64 {
+        self.id
+    }
+    // open and done todos, and todos by tag or list, are listed without reading the others
+    fn index_entries(&self) -> Vec<(&'static str, String)> {
+        let mut entries = vec![("completed", self.completed.to_string())];
+        entries.extend(self.tags.iter().map(|tag| ("tag", tag.clone())));
+        entries.extend(self.list.iter().map(|list| ("list", list.clone())));
+        entries
+    }
+    fn check_write(&self, db: &Db) -> Result<()> {
+        tag_archive::check_writable(db, self)
+    }
+}
+
+pub fn todo_key(id: u64) -> String {
+    entity::key::<Todo>(id)
+}
+pub fn blocked_by_key(id: u64) -> String {
+    format!("{}{}", BLOCKED_BY_PREFIX, id)
+}
+
+// removing a todo, along with its index entries and the keys that hang off it, into the trash
+pub fn remove_ops(db: &Db, id: u64) -> Result<Vec<WriteOp>> {
+    let mut ops = match db.get::<Todo, _>(todo_key(id))? {
+        Some(todo) => trash::keep_ops(db, &todo)?,
+        None => Vec::new(),
+    };
+    ops.extend(Repository::<Todo>::new(db).delete_ops(id)?);
+    ops.extend([
+        WriteOp::Remove {
+            key: blocked_by_key(id),
+        },
+        WriteOp::Remove {
+            key: goal::goal_of_key(id),
+        },
+        WriteOp::Remove {
+            key: reactions::reactions_key(id),
+        },
+    ]);
+    Ok(ops)
+}
+
+// the todos tagged `tag`, from the tag index
+pub fn tagged(db: &Db, tag: &str) -> Result<Vec<Todo>> {
+    Repository::<Todo>::new(db).list_by("tag", tag)
+}
+
+// every todo, in key order, listings go through `query::TodoQuery`
+pub fn all(db: &Db) -> Result<Vec<Todo>> {
+    db.snapshot("todo")?
+        .iter::<Todo>()
+        .map(|item| item.map(|(_, todo)| todo))
+        .collect()
+}
+// the same one at a time, for going through them without holding on to all
+pub fn iter(db: &Db) -> Result<impl Iterator<Item = Result<Todo>> + '_> {
+    Ok(db
+        .iter_prefix::<Todo>(&format!("{}:", Todo::PREFIX))?
+        .map(|item| item.map(|(_, todo)| todo)))
+}
+
+// === Dependencies ===
+pub fn blockers(db: &Db, id: u64) -> Result<Vec<u64>> {
+    Ok(db.get(blocked_by_key(id))?.unwrap_or_default())
+}
+
+// Make `id` wait for `blocker`, refusing self references, unknown todos and cycles.
+pub fn add_blocker(db: &Db, id: u64, blocker: u64) -> Result<(), AppError> {
+    if id == blocker {
+        return Err(AppError::Invalid("A todo cannot block itself.".into()));
+    }
+    if db.get::<Todo, _>(todo_key(id))?.is_none() || db.get::<Todo, _>(todo_key(blocker))?.is_none()
+    {
+        return Err(AppError::NotFound);
+    }
+    if depends_on(db, blocker, id)? {
+        return Err(AppError::Invalid(
+            "That todo already waits for this one, blocking it would go in circles.".into(),
+        ));
+    }
+    let mut blockers = blockers(db, id)?;
+    if !blockers.contains(&blocker) {
+        blockers.push(blocker);
+        db.insert(blocked_by_key(id), &blockers)?;
+    }
+    Ok(())
+}
+pub fn remove_blocker(db: &Db, id: u64, blocker: u64) -> Result<()> {
+    let mut blockers = blockers(db, id)?;
+    blockers.retain(|known| *known != blocker);
+    if blockers.is_empty() {
+        db.remove(blocked_by_key(id))
+    } else {
+        db.insert(blocked_by_key(id), &blockers)
+    }
+}
+
+// Merging `from` into `to`: `to` waits for what either waited for, and whatever waited for
+// `from` waits for `to` instead. A todo never ends up waiting for itself.
+pub fn merge_blockers_ops(db: &Db, from: u64, to: u64) -> Result<Vec<WriteOp>> {
+    let mut ops = Vec::new();
+    let mut merged = blockers(db, to)?;
+    merged.extend(blockers(db, from)?);
+    let mut waiting: Vec<(u64, Vec<u64>)> = vec![(to, merged)];
+    for item in db.iter_prefix::<Vec<u64>>(BLOCKED_BY_PREFIX)? {
+        let (key, blockers) = item?;
+        let Some(id) = key[BLOCKED_BY_PREFIX.len()..].parse::<u64>().ok() else {
+            continue;
+        };
+        if id != from && id != to && blockers.contains(&from) {
+            waiting.push((id, blockers));
+        }
+    }
+    for (id, blockers) in waiting {
+        let mut replaced: Vec<u64> = Vec::new();
+        for blocker in blockers {
+            let blocker = if blocker == from { to } else { blocker };
+            if blocker != id && blocker != from && !replaced.contains(&blocker) {
+                replaced.push(blocker);
+            }
+        }
+        ops.push(if replaced.is_empty() {
+            WriteOp::Remove {
+                key: blocked_by_key(id),
+            }
+        } else {
+            WriteOp::Insert {
+                key: blocked_by_key(id),
+                value: db.encode(&replaced)?,
+            }
+        });
+    }
+    Ok(ops)
+}
+
+// whether `id` waits for `target`, directly or through other todos
+fn depends_on(db: &Db, id: u64, target: u64) -> Result<bool> {
+    let mut seen = HashSet::new();
+    let mut stack = vec![id];
+    while let Some(current) = stack.pop() {
+        if current == target {
+            return Ok(true);
+        }
+        if seen.insert(current) {
+            stack.extend(blockers(db, current)?);
+        }
+    }
+    Ok(false)
+}
+
+// whether any todo `id` waits for is still open, removed blockers no longer count
+pub fn is_blocked(db: &Db, id: u64) -> Result<bool> {
+    for blocker in blockers(db, id)? {
+        if db
+            .get::<Todo, _>(todo_key(blocker))?
+            .is_some_and(|todo| !todo.completed)
+        {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+// the ids among `todos` that wait for an open todo
+pub fn blocked_ids(db: &Db, todos: &[Todo]) -> Result<HashSet<u64>> {
+    let open = todos
+        .iter()
+        .map(|todo| (todo.id, !todo.completed))
+        .collect::<HashMap<_, _>>();
+    let mut blocked = HashSet::new();
+    for item in db.iter_prefix::<Vec<u64>>(BLOCKED_BY_PREFIX)? {
+        let (key, blockers) = item?;
+        let Some(id) = key[BLOCKED_BY_PREFIX.len()..].parse::<u64>().ok() else {
+            continue;
+        };
+        if blockers
+            .iter()
+            .any(|blocker| open.get(blocker) == Some(&true))
+        {
+            blocked.insert(id);
+        }
+    }
+    Ok(blocked)
+}
+
+// The todos that were only waiting for `completed`, which was just marked as done.
+pub fn unblocked_by(db: &Db, completed: u64) -> Result<Vec<Todo>> {
+    let mut unblocked = Vec::new();
+    for item in db.iter_prefix::<Vec<u64>>(BLOCKED_BY_PREFIX)? {
+        let (key, blockers) = item?;
+        if !blockers.contains(&completed) {
+            continue;
+        }
+        let Some(id) = key[BLOCKED_BY_PREFIX.len()..].parse::<u64>().ok() else {
+            continue;
+        };
+        let mut still_blocked = false;
+        for blocker in blockers.iter().filter(|blocker| **blocker != completed) {
+            if db
+                .get::<Todo, _>(todo_key(*blocker))?
+                .is_some_and(|todo| !todo.completed)
+            {
+                still_blocked = true;
+                break;
+            }
+        }
+        if still_blocked {
+            continue;
+        }
+        if let Some(todo) = db.get::<Todo, _>(todo_key(id))? {
+            unblocked.push(todo);
+        }
+    }
+    Ok(unblocked)
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::TestDb;
+
+    fn setup(name: &str) -> Result<TestDb> {
+        let db = TestDb::new(name)?;
+        for id in 1..=3 {
+            db.insert(todo_key(id), &Todo::new(id, format!("todo {}", id)))?;
+        }
+        Ok(db)
+    }
+
+    #[test]
+    fn test_blockers_reject_cycles() -> Result<()> {
+        let db = setup("todo_cycles")?;
+        assert!(add_blocker(&db, 1, 2).is_ok());
+        assert!(add_blocker(&db, 2, 3).is_ok());
+        assert!(matches!(add_blocker(&db, 3, 1), Err(AppError::Invalid(_))));
+        assert!(matches!(add_blocker(&db, 1, 1), Err(AppError::Invalid(_))));
+        assert!(matches!(add_blocker(&db, 1, 9), Err(AppError::NotFound)));
+        assert_eq!(blockers(&db, 1)?, vec![2]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_unblocked_when_blockers_complete() -> Result<()> {
+        let db = setup("todo_unblocked")?;
+        add_blocker(&db, 1, 2).map_err(|_| anyhow::anyhow!("add blocker"))?;
+        add_blocker(&db, 1, 3).map_err(|_| anyhow::anyhow!("add blocker"))?;
+        assert!(is_blocked(&db, 1)?);
+
+        let mut done = Todo::new(2, "todo 2".into());
+        done.set_completed(true);
+        db.insert(todo_key(2), &done)?;
+        assert!(unblocked_by(&db, 2)?.is_empty());
+
+        let mut done = Todo::new(3, "todo 3".into());
+        done.set_completed(true);
+        db.insert(todo_key(3), &done)?;
+        let unblocked = unblocked_by(&db, 3)?;
+        assert_eq!(unblocked.len(), 1);
+        assert_eq!(unblocked[0].id, 1);
+        assert!(!is_blocked(&db, 1)?);
+        Ok(())
+    }
+}
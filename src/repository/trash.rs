@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+use anyhow::Result;
+
+use super::entity::Repository;
+use crate::{
+    db::{driver::Db, queue::WriteOp},
+    models::Todo,
+};
+
+// removed todos, `trash:{id}`
+const TRASH_PREFIX: &str = "trash:";
+// how long a removed todo can be brought back
+pub const TRASH_LIFETIME: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+// === Trash ===
+// Removing a todo keeps it here for `TRASH_LIFETIME`, it can be restored until the ttl sweeper
+// purges it for good. It goes in along with the removal, see `todo::remove_ops`.
+fn key(id: u64) -> String {
+    format!("{}{}", TRASH_PREFIX, id)
+}
+
+pub fn keep_ops(db: &Db, todo: &Todo) -> Result<Vec<WriteOp>> {
+    db.ttl_ops(&key(todo.id), todo, TRASH_LIFETIME)
+}
+
+// removed todos, newest first
+pub fn list(db: &Db) -> Result<Vec<Todo>> {
+    let mut todos = db
+        .iter_prefix::<Todo>(TRASH_PREFIX)?
+        .map(|item| item.map(|(_, todo)| todo))
+        .collect::<Result<Vec<_>>>()?;
+    todos.sort_by(|a, b| b.id.cmp(&a.id));
+    Ok(todos)
+}
+
+// Put a removed todo back. `None` when it is not in the trash, or was purged already.
+pub fn restore(db: &Db, id: u64) -> Result<Option<Todo>> {
+    let Some(todo) = db.get::<Todo, _>(key(id))? else {
+        return Ok(None);
+    };
+    let mut ops = Repository::<Todo>::new(db).put_ops(&todo)?;
+    ops.push(WriteOp::Remove { key: key(id) });
+    db.apply_batch(ops)?;
+    Ok(Some(todo))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::{TestDb, TodoFixture},
+        repository::todo,
+    };
+
+    #[test]
+    fn test_trash_restores_until_purged() -> Result<()> {
+        let db = TestDb::new("trash")?;
+        let milk = TodoFixture::new().titled("Milk").persist(&db)?;
+        db.apply_batch(todo::remove_ops(&db, milk.id)?)?;
+        assert_eq!(list(&db)?.len(), 1);
+        assert_eq!(
+            restore(&db, milk.id)?.map(|todo| todo.title),
+            Some("Milk".into())
+        );
+        assert!(db.get::<Todo, _>(todo::todo_key(milk.id))?.is_some());
+        assert!(list(&db)?.is_empty());
+
+        db.apply_batch(todo::remove_ops(&db, milk.id)?)?;
+        db.clock.advance(TRASH_LIFETIME);
+        db.sweep_expired(db.clock().now_millis())?;
+        assert!(list(&db)?.is_empty());
+        assert!(restore(&db, milk.id)?.is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,186 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{
+    clock::Clock,
+    config::Config,
+    db::{driver::Db, migrations},
+    repository, state,
+};
+
+// the directory that was live before the last restore, kept to roll back to
+const PREVIOUS_FILE: &str = "db.previous";
+
+// === Backups ===
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backup {
+    pub name: String,
+    // unix seconds
+    pub created_at: u64,
+}
+
+// the backups in `backups/`, newest first
+pub fn list(config: &Config) -> Result<Vec<Backup>> {
+    let dir = config.backups_dir();
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let created_at = entry
+            .metadata()?
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|at| at.as_secs())
+            .unwrap_or_default();
+        backups.push(Backup {
+            name: entry.file_name().to_string_lossy().into_owned(),
+            created_at,
+        });
+    }
+    backups.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    Ok(backups)
+}
+
+fn backup_path(config: &Config, name: &str) -> Result<PathBuf> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        bail!("`{}` is not a backup name", name);
+    }
+    let path = config.backups_dir().join(name);
+    if !path.is_dir() {
+        bail!("there is no backup `{}`", name);
+    }
+    Ok(path)
+}
+
+// === Restore ===
+// A backup copied next to the live database, migrated and checked, ready to be swapped in.
+#[derive(Debug)]
+pub struct Staged {
+    pub path: PathBuf,
+    pub db: Db,
+    pub report: Report,
+}
+
+// what the check of a staged database found, it fails on anything unreadable
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Report {
+    pub trees: usize,
+    pub keys: usize,
+    pub todos: usize,
+}
+
+// Copy backup `name` into a new directory in the data dir, bring it to the current schema and
+// check it. The live database is not touched.
+pub fn stage(config: &Config, clock: &dyn Clock, name: &str) -> Result<Staged> {
+    let source = state::open_db_at(config, &backup_path(config, name)?)?;
+    let path = config
+        .data_dir
+        .join(format!("db-restore-{}", clock.now_millis()));
+    source.backup(&path)?;
+    drop(source);
+    let db = state::open_db_at(config, &path)?;
+    migrations::run(&db)?;
+    let report = verify(&db).with_context(|| format!("backup `{}` is damaged", name))?;
+    Ok(Staged { path, db, report })
+}
+
+// Read back every value of every tree, and every todo as a todo.
+pub fn verify(db: &Db) -> Result<Report> {
+    let mut report = Report::default();
+    for tree in db.all_trees()? {
+        report.trees += 1;
+        for key in tree.iter_keys("") {
+            let key = key?;
+            tree.get_payload(&key)?
+                .with_context(|| format!("`{}` went missing", key))?;
+            report.keys += 1;
+        }
+        report.todos += repository::todo::all(&tree)?.len();
+    }
+    Ok(report)
+}
+
+// Make `path` the live database for the next start, remembering the current one to roll back
+// to. Each pointer is replaced by a rename, so a crash leaves either the old or the new one.
+pub fn activate(config: &Config, path: &Path) -> Result<()> {
+    let current = config.db_path();
+    write_pointer(&config.data_dir.join(PREVIOUS_FILE), &current)?;
+    write_pointer(&config.active_db_file(), path)
+}
+
+fn write_pointer(file: &Path, target: &Path) -> Result<()> {
+    let name = target
+        .file_name()
+        .context("database path has no directory name")?
+        .to_string_lossy();
+    let temporary = file.with_extension("tmp");
+    fs::write(&temporary, name.as_bytes())?;
+    fs::rename(temporary, file)?;
+    Ok(())
+}
+
+// the directory that was live before the last restore, if it is still there
+pub fn previous(config: &Config) -> Option<PathBuf> {
+    let name = fs::read_to_string(config.data_dir.join(PREVIOUS_FILE)).ok()?;
+    let path = config.data_dir.join(name.trim());
+    (path.is_dir() && path != config.db_path()).then_some(path)
+}
+
+// Open the database that was live before the last restore and make it live again.
+pub fn stage_rollback(config: &Config) -> Result<(PathBuf, Db)> {
+    let Some(path) = previous(config) else {
+        bail!("there is nothing to roll back to");
+    };
+    let db = state::open_db_at(config, &path)?;
+    migrations::run(&db)?;
+    Ok((path, db))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clock::SystemClock, fixtures::TestDir, models::Todo};
+
+    #[test]
+    fn test_stage_and_activate() -> Result<()> {
+        let dir = TestDir::new("restore")?;
+        let data_dir = PathBuf::from(dir.path());
+        let config = Config {
+            data_dir: data_dir.clone(),
+            ..Config::default()
+        };
+        let live = state::open_db(&config)?;
+        live.insert("todo:1", &Todo::new(1, "Backed up".into()))?;
+        live.for_tenant(Some("acme"))?
+            .insert("todo:2", &Todo::new(2, "Acme's".into()))?;
+        live.backup(&config.backups_dir().join("nightly"))?;
+        live.insert("todo:3", &Todo::new(3, "After the backup".into()))?;
+
+        assert_eq!(list(&config)?[0].name, "nightly");
+        assert!(stage(&config, &SystemClock, "../db").is_err());
+        let staged = stage(&config, &SystemClock, "nightly")?;
+        assert_eq!(staged.report.trees, 2);
+        assert_eq!(staged.report.todos, 2);
+
+        activate(&config, &staged.path)?;
+        assert_eq!(config.db_path(), staged.path);
+        assert_eq!(previous(&config), Some(data_dir.join("db")));
+        // sled locks a directory while it is open
+        drop(live);
+        let (rollback, db) = stage_rollback(&config)?;
+        assert_eq!(rollback, data_dir.join("db"));
+        assert!(repository::todo::all(&db)?.iter().any(|todo| todo.id == 3));
+        Ok(())
+    }
+}
@@ -0,0 +1,238 @@
+use std::time::Duration;
+
+use anyhow::Result;
+
+use crate::{
+    activity,
+    config::Config,
+    db::driver::Db,
+    history,
+    recorder::Recorder,
+    scheduler::{Job, Schedule},
+    subscriptions,
+};
+
+// === Keyspaces ===
+// What piles up without anyone removing it, each with its own policy and purge job.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Keyspace {
+    Activity,
+    Notifications,
+    DeletedTodos,
+    Recordings,
+}
+impl Keyspace {
+    pub const ALL: [Keyspace; 4] = [
+        Keyspace::Activity,
+        Keyspace::Notifications,
+        Keyspace::DeletedTodos,
+        Keyspace::Recordings,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Keyspace::Activity => "Activity log",
+            Keyspace::Notifications => "Notifications",
+            Keyspace::DeletedTodos => "Deleted todos",
+            Keyspace::Recordings => "Request recordings",
+        }
+    }
+    pub fn description(self) -> &'static str {
+        match self {
+            Keyspace::Activity => {
+                "earlier versions of todos and who changed them, `history:` and `activity:`"
+            }
+            Keyspace::Notifications => "subscription digest entries, `digest:`",
+            Keyspace::DeletedTodos => "the history removed todos leave behind, `history:`",
+            Keyspace::Recordings => "dev mode requests and responses, in memory",
+        }
+    }
+    pub fn job_name(self) -> &'static str {
+        match self {
+            Keyspace::Activity => "purge_activity",
+            Keyspace::Notifications => "purge_notifications",
+            Keyspace::DeletedTodos => "purge_deleted_todos",
+            Keyspace::Recordings => "purge_recordings",
+        }
+    }
+    // how long it is kept, for good when unset
+    pub fn policy(self, config: &Config) -> Option<Duration> {
+        match self {
+            Keyspace::Activity => config.retention_activity,
+            Keyspace::Notifications => config.retention_notifications,
+            Keyspace::DeletedTodos => config.retention_deleted_todos,
+            Keyspace::Recordings => config.retention_recordings,
+        }
+    }
+    // the stored keyspaces are purged at night one after another, recordings are counted in
+    // hours
+    fn schedule(self) -> Schedule {
+        match self {
+            Keyspace::Activity => Schedule::Daily { hour: 4, minute: 0 },
+            Keyspace::Notifications => Schedule::Daily {
+                hour: 4,
+                minute: 10,
+            },
+            Keyspace::DeletedTodos => Schedule::Daily {
+                hour: 4,
+                minute: 20,
+            },
+            Keyspace::Recordings => Schedule::Every(Duration::from_secs(60 * 60)),
+        }
+    }
+}
+
+// === Volumes ===
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Volume {
+    pub keys: usize,
+    // stored bytes, after compression and encryption
+    pub bytes: usize,
+}
+impl Volume {
+    pub fn add(&mut self, bytes: usize) {
+        self.keys += 1;
+        self.bytes += bytes;
+    }
+    pub fn of_prefix(db: &Db, prefix: &str) -> Result<Self> {
+        let mut volume = Self::default();
+        for key in db.iter_keys(prefix) {
+            let bytes = db.get_payload(key?)?.map_or(0, |payload| payload.len());
+            volume.add(bytes);
+        }
+        Ok(volume)
+    }
+}
+impl std::ops::AddAssign for Volume {
+    fn add_assign(&mut self, other: Self) {
+        self.keys += other.keys;
+        self.bytes += other.bytes;
+    }
+}
+
+// What `keyspace` holds across every workspace. Recordings are only kept with `DEV_MODE`.
+pub fn volume(db: &Db, keyspace: Keyspace, recorder: Option<&Recorder>) -> Result<Volume> {
+    if keyspace == Keyspace::Recordings {
+        let (keys, bytes) = recorder.map(Recorder::volume).unwrap_or_default();
+        return Ok(Volume { keys, bytes });
+    }
+    let mut volume = Volume::default();
+    for tree in db.all_trees()? {
+        volume += match keyspace {
+            Keyspace::Activity => {
+                let mut volume = history::volumes(&tree)?.0;
+                volume += activity::volume(&tree)?;
+                volume
+            }
+            Keyspace::DeletedTodos => history::volumes(&tree)?.1,
+            Keyspace::Notifications => subscriptions::digest_volume(&tree)?,
+            Keyspace::Recordings => Volume::default(),
+        };
+    }
+    Ok(volume)
+}
+
+// === Purging ===
+fn cutoff(db: &Db, keep: Duration) -> u64 {
+    (db.clock().now_millis() / 1000).saturating_sub(keep.as_secs())
+}
+
+// Remove what a stored keyspace holds from before `keep` ago in every workspace. Returns how many
+// keys went.
+pub fn purge(db: &Db, keyspace: Keyspace, keep: Duration) -> Result<usize> {
+    let cutoff = cutoff(db, keep);
+    let mut purged = 0;
+    for tree in db.all_trees()? {
+        purged += match keyspace {
+            Keyspace::Activity => {
+                history::purge_before(&tree, cutoff)? + activity::purge_before(&tree, cutoff)?
+            }
+            Keyspace::Notifications => subscriptions::purge_digest_before(&tree, cutoff)?,
+            Keyspace::DeletedTodos => history::purge_removed_before(&tree, cutoff)?,
+            Keyspace::Recordings => 0,
+        };
+    }
+    Ok(purged)
+}
+
+// === Jobs ===
+// A purge job for every keyspace with a policy, recordings only when there is a recorder.
+pub fn jobs(config: &Config, recorder: Option<&Recorder>) -> Vec<Job> {
+    Keyspace::ALL
+        .into_iter()
+        .filter_map(|keyspace| {
+            let keep = keyspace.policy(config)?;
+            let recorder = match keyspace {
+                Keyspace::Recordings => Some(recorder?.clone()),
+                _ => None,
+            };
+            Some(Job::new(
+                keyspace.job_name(),
+                keyspace.schedule(),
+                move |db| {
+                    let recorder = recorder.clone();
+                    Box::pin(async move {
+                        let purged = match recorder {
+                            Some(recorder) => {
+                                recorder.purge_before(cutoff(&*db.read().await, keep))
+                            }
+                            None => purge(&*db.write().await, keyspace, keep)?,
+                        };
+                        if purged > 0 {
+                            tracing::info!(job = keyspace.job_name(), purged, "purged");
+                        }
+                        Ok(())
+                    })
+                },
+            ))
+        })
+        .collect()
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        clock::SystemClock,
+        fixtures::{TestDb, TodoFixture},
+    };
+
+    #[test]
+    fn test_jobs_follow_policies() {
+        let mut config = Config::default();
+        assert!(jobs(&config, None).is_empty());
+        config.retention_notifications = Some(Duration::from_secs(86_400));
+        config.retention_recordings = Some(Duration::from_secs(3_600));
+        // recordings are only purged with a recorder
+        let names = |list: Vec<Job>| list.iter().map(|job| job.name).collect::<Vec<_>>();
+        assert_eq!(names(jobs(&config, None)), ["purge_notifications"]);
+        assert_eq!(
+            names(jobs(&config, Some(&Recorder::new(Arc::new(SystemClock))))),
+            ["purge_notifications", "purge_recordings"]
+        );
+    }
+
+    #[test]
+    fn test_purge_every_workspace() -> Result<()> {
+        let db = TestDb::new("retention")?;
+        let other = db.for_tenant(Some("acme"))?;
+        for tree in [&*db, &other] {
+            subscriptions::subscribe(tree, "tag:bug")?;
+            TodoFixture::new().with_tags(["bug"]).persist(tree)?;
+            subscriptions::evaluate(tree)?;
+        }
+        assert_eq!(volume(&db, Keyspace::Notifications, None)?.keys, 2);
+
+        db.clock.advance(Duration::from_secs(2 * 86_400));
+        let keep = Duration::from_secs(86_400);
+        assert_eq!(purge(&db, Keyspace::Notifications, keep)?, 2);
+        assert_eq!(
+            volume(&db, Keyspace::Notifications, None)?,
+            Volume::default()
+        );
+        Ok(())
+    }
+}
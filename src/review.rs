@@ -0,0 +1,202 @@
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+};
+use maud::{html, Markup};
+use serde::Deserialize;
+
+use crate::{
+    db::driver::Db,
+    error::AppError,
+    extract::FormOrJson,
+    models::{self, Todo},
+    repository::{
+        self,
+        entity::Repository,
+        review::{self, Action, Session},
+    },
+    routes,
+    state::AppState,
+    tenant::Tenant,
+    views::{
+        self,
+        nav::{self, Nav},
+    },
+};
+
+const REVIEW_ID: &str = "review";
+
+// unix seconds as `YYYY-MM-DD`
+pub fn format_day(secs: u64) -> String {
+    time::OffsetDateTime::from_unix_timestamp(secs as i64)
+        .map(|time| time.date().to_string())
+        .unwrap_or_default()
+}
+
+// === Components ===
+fn intro_html(stale: usize) -> Markup {
+    html! {
+        div id=(REVIEW_ID) class="bg-white rounded-lg shadow-lg p-6 space-y-4" {
+            @if stale == 0 {
+                p class="text-gray-700" { "Nothing has gone stale, there is nothing to review." }
+            } @else {
+                p class="text-gray-700" { (stale) " todos have not been touched in a while. Go through them one at a time and decide what to do with each." }
+                form method="post" action=(routes::ReviewStart::url()) hx-post=(routes::ReviewStart::url()) hx-target={ "#" (REVIEW_ID) } hx-swap="outerHTML" {
+                    button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" { "Start review" }
+                }
+            }
+        }
+    }
+}
+
+fn step_html(todo: &Todo, remaining: usize) -> Markup {
+    let url = routes::ReviewTodo::url(todo.id);
+    html! {
+        div id=(REVIEW_ID) class="bg-white rounded-lg shadow-lg p-6 space-y-4" {
+            p class="text-sm text-gray-500" { (remaining) " left to review" }
+            h2 class="text-2xl text-gray-700" { (todo.title) }
+            p class="text-gray-600" {
+                "Last touched " (format_day(todo.updated_at)) "."
+                @if let Some(due) = todo.due { " Due " (due) "." }
+            }
+            form class="flex items-center space-x-2" method="post" action=(url) hx-post=(url) hx-target={ "#" (REVIEW_ID) } hx-swap="outerHTML" {
+                button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" name="action" value="keep" { "Keep" }
+                input class="rounded border p-2" type="date" name="due" value=[todo.due] aria-label="New due date";
+                button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" name="action" value="reschedule" { "Reschedule" }
+                button class="bg-gray-500 hover:bg-gray-700 text-white font-bold py-2 px-4 rounded" type="submit" name="action" value="archive" { "Archive" }
+                button class="bg-red-500 hover:bg-red-700 text-white font-bold py-2 px-4 rounded" type="submit" name="action" value="delete" { "Delete" }
+            }
+        }
+    }
+}
+
+fn summary_html(session: &Session) -> Markup {
+    html! {
+        div id=(REVIEW_ID) class="bg-white rounded-lg shadow-lg p-6 space-y-4" {
+            h2 class="text-2xl text-gray-700" { "Review done" }
+            p class="text-gray-700" {
+                "Kept " (session.kept) ", rescheduled " (session.rescheduled)
+                ", archived " (session.archived) ", deleted " (session.deleted) "."
+            }
+            a class="text-blue-500 hover:text-blue-700" href=(routes::Root::url()) hx-boost="true" { "Back to your todos" }
+        }
+    }
+}
+
+// The next step of the session, finishing it once nothing stale is left. `skip` is the todo
+// just handled, which a queued write may not have reached yet.
+fn next_html(
+    state: &AppState,
+    db: &Db,
+    session: &mut Session,
+    skip: Option<u64>,
+) -> Result<Markup, AppError> {
+    let stale: Vec<Todo> = review::stale(db, state.config.review_stale_after)?
+        .into_iter()
+        .filter(|todo| Some(todo.id) != skip)
+        .collect();
+    match stale.first() {
+        Some(todo) => Ok(step_html(todo, stale.len())),
+        None => {
+            review::finish(db, session)?;
+            Ok(summary_html(session))
+        }
+    }
+}
+
+// === Routes ===
+pub async fn index(State(state): State<AppState>, tenant: Tenant) -> Result<Markup, AppError> {
+    let db = state.read().await.for_tenant(tenant.id())?;
+    let content = match review::current(&db)? {
+        Some(mut session) => next_html(&state, &db, &mut session, None)?,
+        None => intro_html(review::stale(&db, state.config.review_stale_after)?.len()),
+    };
+    Ok(views::page(
+        "Review",
+        html! {
+            (nav::navigation(&Nav::review()))
+            h1 class="text-4xl text-center text-gray-700 mb-6" { "Weekly review" }
+            (content)
+        },
+    ))
+}
+
+pub async fn start(
+    State(mut app_state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let state = app_state.clone();
+    let guard = app_state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    let mut session = review::start(&db)?;
+    let fragment = next_html(&state, &db, &mut session, None)?;
+    Ok(views::fragment_or_redirect(
+        &headers,
+        fragment,
+        &routes::Review::url(),
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct Act {
+    action: Action,
+    // yyyy-mm-dd, needed to reschedule
+    due: Option<String>,
+}
+pub async fn act(
+    State(mut app_state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Path(id): Path<u64>,
+    FormOrJson(Act { action, due }): FormOrJson<Act>,
+) -> Result<Response, AppError> {
+    let state = app_state.clone();
+    let guard = app_state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    let Some(mut session) = review::current(&db)? else {
+        return Err(AppError::Invalid("Start a review first.".into()));
+    };
+    let todos = Repository::new(&db);
+    let mut todo: Todo = todos.get(id)?.ok_or(AppError::NotFound)?;
+    let ops = match action {
+        Action::Delete => repository::todo::remove_ops(&db, id)?,
+        _ => {
+            match action {
+                Action::Reschedule => {
+                    let due = models::parse_due(due.as_deref().unwrap_or_default())
+                        .map_err(|err| AppError::Invalid(err.to_string()))?;
+                    todo.due = Some(due.ok_or_else(|| {
+                        AppError::Invalid("Pick a date to reschedule to.".into())
+                    })?);
+                }
+                Action::Archive => todo.archived = true,
+                _ => {}
+            }
+            todo.touch(&**db.clock());
+            todos.put_ops(&todo)?
+        }
+    };
+    state.writes.submit(&db, ops).await?;
+    review::record(&db, &mut session, action)?;
+    let fragment = next_html(&state, &db, &mut session, Some(id))?;
+    Ok(views::fragment_or_redirect(
+        &headers,
+        fragment,
+        &routes::Review::url(),
+    ))
+}
+
+// the header badge, lazily loaded by the navigation
+pub async fn nudge(State(state): State<AppState>, tenant: Tenant) -> Result<Markup, AppError> {
+    let db = state.read().await.for_tenant(tenant.id())?;
+    if !review::is_due(&db, state.config.review_stale_after)? {
+        return Ok(html! {});
+    }
+    Ok(html! {
+        li {
+            a class="text-xs font-bold t-warning rounded px-2 py-1" href=(routes::Review::url()) { "Review due" }
+        }
+    })
+}
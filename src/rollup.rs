@@ -0,0 +1,137 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use time::{Date, OffsetDateTime};
+
+use crate::{
+    db::driver::Db,
+    models::Todo,
+    repository,
+    scheduler::{Job, Schedule},
+};
+
+// `rollup:{yyyy-mm-dd}`, iso dates so the keys sort by day
+const ROLLUP_PREFIX: &str = "rollup:";
+
+// === Rollups ===
+// The state of a workspace at the end of a day, kept so stats can look back without the
+// history of every todo.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rollup {
+    pub day: Date,
+    // open at the end of the day
+    pub open: usize,
+    // finished during the day
+    pub completed: usize,
+    // estimated minutes of the open todos
+    pub estimated: u32,
+}
+
+fn rollup_key(day: Date) -> String {
+    format!("{}{}", ROLLUP_PREFIX, day)
+}
+
+// the day a todo was last changed, for a done todo that is when it was finished
+pub fn changed_on(todo: &Todo) -> Option<Date> {
+    OffsetDateTime::from_unix_timestamp(todo.updated_at as i64)
+        .ok()
+        .map(|at| at.date())
+}
+
+// `todos` as they are now, summed up for `day`
+pub fn compute(todos: &[Todo], day: Date) -> Rollup {
+    let todos = todos.iter().filter(|todo| !todo.archived);
+    let mut rollup = Rollup {
+        day,
+        open: 0,
+        completed: 0,
+        estimated: 0,
+    };
+    for todo in todos {
+        if !todo.completed {
+            rollup.open += 1;
+            rollup.estimated += todo.estimate_minutes.unwrap_or_default();
+        } else if changed_on(todo) == Some(day) {
+            rollup.completed += 1;
+        }
+    }
+    rollup
+}
+
+// Store the rollup of `day` for one workspace, replacing one taken earlier.
+pub fn record(db: &Db, day: Date) -> Result<Rollup> {
+    let rollup = compute(&repository::todo::all(db)?, day);
+    db.insert(rollup_key(day), &rollup)?;
+    Ok(rollup)
+}
+
+// the stored rollups from `from` on, oldest first
+pub fn since(db: &Db, from: Date) -> Result<Vec<Rollup>> {
+    let start = rollup_key(from);
+    db.iter_prefix::<Rollup>(ROLLUP_PREFIX)?
+        .filter(|item| item.as_ref().map_or(true, |(key, _)| *key >= start))
+        .map(|item| item.map(|(_, rollup)| rollup))
+        .collect()
+}
+
+// === Nightly ===
+// Record yesterday for every workspace right after midnight, a missed night is made up for at
+// the next startup.
+pub fn job() -> Job {
+    Job::new("rollups", Schedule::Daily { hour: 0, minute: 1 }, |db| {
+        Box::pin(async move {
+            let db = db.write().await;
+            match db.clock().now().date().previous_day() {
+                Some(yesterday) => record_all(&db, yesterday),
+                None => Ok(()),
+            }
+        })
+    })
+    .jitter(Duration::from_secs(60))
+}
+
+fn record_all(db: &Db, day: Date) -> Result<()> {
+    for tree in db.all_trees()? {
+        if tree.get::<Rollup, _>(rollup_key(day))?.is_none() {
+            record(&tree, day)?;
+        }
+    }
+    tracing::debug!(%day, "recorded rollups");
+    Ok(())
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use time::macros::date;
+
+    use super::*;
+    use crate::fixtures::TestDb;
+
+    #[test]
+    fn test_record_and_since() -> Result<()> {
+        let db = TestDb::new("rollup")?;
+        let mut open = Todo::new(1, "open".into());
+        open.estimate_minutes = Some(30);
+        db.insert("todo:1", &open)?;
+        let mut done = Todo::new(2, "done".into());
+        done.set_completed(true);
+        db.insert("todo:2", &done)?;
+
+        let today = changed_on(&done).unwrap();
+        assert_eq!(
+            record(&db, today)?,
+            Rollup {
+                day: today,
+                open: 1,
+                completed: 1,
+                estimated: 30,
+            }
+        );
+        record(&db, date!(2020 - 01 - 01))?;
+        assert_eq!(since(&db, date!(2020 - 01 - 02))?.len(), 1);
+        assert_eq!(since(&db, date!(2019 - 12 - 31))?[0].completed, 0);
+        Ok(())
+    }
+}
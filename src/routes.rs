@@ -0,0 +1,275 @@
+use std::fmt::Display;
+
+// Every path is written once. `PATH` is what the router registers (relative to where the
+// router is nested), `url(..)` is the full link views point at, with typed path params.
+//
+//     TenantErase(tenant) = "/tenants/:tenant/erase" in "/admin";
+//
+// gives `TenantErase::PATH == "/tenants/:tenant/erase"` and
+// `TenantErase::url("acme") == "/admin/tenants/acme/erase"`.
+macro_rules! routes {
+    ($(
+        $name:ident $(($($param:ident),*))? = $path:literal $(in $prefix:literal)?;
+    )*) => {
+        $(
+            pub struct $name;
+            impl $name {
+                pub const PATH: &'static str = $path;
+                pub fn url($($($param: impl Display),*)?) -> String {
+                    fill(
+                        concat!($($prefix,)? $path),
+                        &[$($((stringify!($param), $param.to_string())),*)?],
+                    )
+                }
+            }
+        )*
+
+        // the full path of every route, for checking the links views render
+        pub const ALL: &[&str] = &[$(concat!($($prefix,)? $path)),*];
+    };
+}
+
+routes! {
+    // todos
+    Root = "/";
+    Todos = "/todos";
+    TodoDetail(id) = "/todos/:id";
+    CompletedTodos = "/todos/completed";
+    ScheduledTodos = "/todos/scheduled";
+    TodoBlockers(id) = "/todos/:id/blockers";
+    TodoBlocker(id, blocker) = "/todos/:id/blockers/:blocker";
+    TodoStatus(id) = "/todos/:id/status";
+    TodoDue(id) = "/todos/:id/due";
+    TodoHistory(id) = "/todos/:id/history";
+    TodoHistoryRestore(id, version) = "/todos/:id/history/:version/restore";
+    TodoChecklist(id) = "/todos/:id/checklist";
+    TodoChecklistItem(id, index) = "/todos/:id/checklist/:index";
+    TodoFields(id) = "/todos/:id/fields";
+    TodoAttachments(id) = "/todos/:id/attachments";
+    TodoAttachment(id, attachment) = "/todos/:id/attachments/:attachment";
+    TodoAttachmentView(id, attachment) = "/todos/:id/attachments/:attachment/view";
+    Today = "/today";
+    Search = "/search";
+    Palette = "/palette";
+    PaletteRun = "/palette/run";
+    SearchSubscriptions = "/search/subscriptions";
+    SearchSubscription(id) = "/search/subscriptions/:id";
+    DigestEntry(id) = "/search/digest/:id";
+    Board = "/board";
+    BoardColumn(status) = "/board/:status";
+    Plan = "/plan";
+    PlanColumn(slot) = "/plan/:slot";
+    TodoSchedule(id) = "/todos/:id/schedule";
+    Calendar = "/calendar";
+    CalendarMonth(year, month) = "/calendar/:year/:month";
+    CalendarDay(year, month, day) = "/calendar/:year/:month/:day";
+    TodoGoal(id) = "/todos/:id/goal";
+    TodoPalette(id) = "/todos/:id/palette";
+    TodoMove(id) = "/todos/:id/move";
+    TodoMerge(id) = "/todos/:id/merge";
+    TodoCancel(id) = "/todos/:id/cancel";
+    TodoColor(id) = "/todos/:id/color";
+    TodoReactions(id) = "/todos/:id/reactions";
+    TodoEdit(id) = "/todos/:id/edit";
+    TodoEditCancel(id) = "/todos/:id/edit/cancel";
+    TodoEditLock(id) = "/todos/:id/lock";
+    TodoTitle(id) = "/todos/:id/title";
+    TodoBreakdown(id) = "/todos/:id/breakdown";
+    TodoSteps(id) = "/todos/:id/steps";
+    Events = "/events";
+    Kiosk(token) = "/kiosk/:token";
+    KioskPanel(token, panel) = "/kiosk/:token/:panel";
+    KioskAdd(token) = "/kiosk/:token/todos";
+    KioskToggle(token, id) = "/kiosk/:token/todos/:id/toggle";
+    Embed(token) = "/embed/:token";
+    CalendarFeed(token) = "/feeds/:token/calendar.ics";
+    Goals = "/goals";
+    Goal(id) = "/goals/:id";
+    CreateGoal = "/create_goal";
+    Stats = "/stats";
+    StatsSidebar = "/stats/sidebar";
+    Activity = "/activity";
+    ActivityPreview = "/activity/preview";
+    ListBadges = "/lists/badges";
+    ListSeen(list) = "/lists/:list/seen";
+    Tags = "/tags";
+    Tag(tag) = "/tags/:tag";
+    TagBundle(tag) = "/tags/:tag/bundle";
+    TagArchive(tag) = "/tags/:tag/archive";
+    TagRestore(tag) = "/tags/:tag/restore";
+    ArchivedTags = "/archived_tags";
+    TagCloud = "/tag_cloud";
+    TagSuggestions = "/tag_suggestions";
+    ListSuggestions = "/list_suggestions";
+    Review = "/review";
+    ReviewStart = "/review/start";
+    ReviewTodo(id) = "/review/todos/:id";
+    ReviewNudge = "/review/nudge";
+    Someday = "/someday";
+    SomedayNudge = "/someday/nudge";
+    TodoSomeday(id) = "/todos/:id/someday";
+    DailyGoalRing = "/daily_goal";
+    ViewSort(view) = "/sort/:view";
+    CreateTodo = "/create_todo";
+    ToggleTodo = "/toggle_todo";
+    EditTodo(id) = "/edit_todo/:id";
+    UpdateTodo = "/update_todo";
+    TodoItem(id) = "/todos/:id/item";
+    RemoveTodo = "/remove_todo";
+    PanelClose = "/panel/close";
+    QuickAdd = "/quick_add";
+    QuickAddAudio = "/quickadd/audio";
+    Suggestions = "/suggestions";
+    Export = "/export";
+    ImportTodos = "/import";
+    ImportBundle = "/import/bundle";
+    ImportMigration = "/import/migration";
+    Asset(name) = "/assets/:name";
+    Setup = "/setup";
+    ThemeCss = "/theme.css";
+    Manifest = "/manifest.webmanifest";
+    AppIcon = "/icon.svg";
+    MaskableIcon = "/icon-maskable.svg";
+    SetupInstance = "/setup/instance";
+    Signup = "/signup";
+    AccountSignup = "/account/signup";
+    AccountLogin = "/account/login";
+    AccountLogout = "/account/logout";
+    MaintenanceBanner = "/maintenance_banner";
+    BrandHeader = "/brand";
+    BrandLogo = "/brand/logo";
+    Sitemap = "/sitemap.xml";
+    Robots = "/robots.txt";
+    DevRequests = "/dev/requests";
+    DevRequest(id) = "/dev/requests/:id";
+    DevSignature = "/dev/signature";
+    InboundTodos = "/hooks/todos";
+
+    // json api, v1
+    ApiBatch = "/batch" in "/api/v1";
+    ApiTodos = "/todos" in "/api/v1";
+    ApiTodo(id) = "/todos/:id" in "/api/v1";
+
+    // settings
+    Settings = "/" in "/settings";
+    ExportMyData = "/export_my_data" in "/settings";
+    MigrateExport = "/migrate/export" in "/settings";
+    DeleteAccount = "/delete_account" in "/settings";
+    Swatches = "/swatches" in "/settings";
+    Swatch(color) = "/swatches/:color" in "/settings";
+    Kiosks = "/kiosks" in "/settings";
+    KioskRevoke(token) = "/kiosks/:token" in "/settings";
+    Embeds = "/embeds" in "/settings";
+    EmbedRevoke(token) = "/embeds/:token" in "/settings";
+    Import = "/import" in "/settings";
+    ThemeSetting = "/theme" in "/settings";
+    LocaleSetting = "/locale" in "/settings";
+    DailyGoalSetting = "/daily_goal" in "/settings";
+    ImportReview(token) = "/import/:token" in "/settings";
+    Integrations = "/integrations" in "/settings";
+    IntegrationTest(integration) = "/integrations/:integration/test" in "/settings";
+    CalendarFeedSetting = "/integrations/calendar" in "/settings";
+    ListPolicy(list) = "/lists/:list/policy" in "/settings";
+    ListFields(list) = "/lists/:list/fields" in "/settings";
+    ListField(list, name) = "/lists/:list/fields/:name" in "/settings";
+    Snapshots = "/snapshots" in "/settings";
+    SnapshotRestore(id) = "/snapshots/:id/restore" in "/settings";
+    SnapshotRemove(id) = "/snapshots/:id" in "/settings";
+
+    // admin
+    Mfa = "/mfa" in "/admin";
+    MfaEnroll = "/mfa/enroll" in "/admin";
+    MfaConfirm = "/mfa/confirm" in "/admin";
+    MfaDisable = "/mfa/disable" in "/admin";
+    MfaVerify = "/mfa/verify" in "/admin";
+    Tenants = "/tenants" in "/admin";
+    TenantExport(tenant) = "/tenants/:tenant/export" in "/admin";
+    TenantErase(tenant) = "/tenants/:tenant/erase" in "/admin";
+    TenantMembers(tenant) = "/tenants/:tenant/members" in "/admin";
+    TenantMemberRemove(tenant, account) = "/tenants/:tenant/members/:account/remove" in "/admin";
+    Migrations = "/migrations" in "/admin";
+    Jobs = "/jobs" in "/admin";
+    Analytics = "/analytics" in "/admin";
+    JobRun(job) = "/jobs/:job/run" in "/admin";
+    Retention = "/retention" in "/admin";
+    Queue = "/queue" in "/admin";
+    Maintenance = "/maintenance" in "/admin";
+    Doctor = "/doctor" in "/admin";
+    QueueMetrics = "/queue/metrics" in "/admin";
+    Backups = "/backups" in "/admin";
+    BackupRestore(name) = "/backups/:name/restore" in "/admin";
+    BackupRollback = "/backups/rollback" in "/admin";
+    DeadTask(id) = "/queue/dead/:id" in "/admin";
+    DeadTaskRetry(id) = "/queue/dead/:id/retry" in "/admin";
+    Registration = "/registration" in "/admin";
+    Branding = "/branding" in "/admin";
+    BrandingLogo = "/branding/logo" in "/admin";
+    Invites = "/invites" in "/admin";
+    InviteRevoke(code) = "/invites/:code/revoke" in "/admin";
+    DeviceRevoke(device) = "/devices/:device/revoke" in "/admin";
+    PasskeyRegisterStart = "/passkeys/register/start" in "/admin";
+    PasskeyRegisterFinish = "/passkeys/register/finish" in "/admin";
+    Login = "/login" in "/admin";
+    LoginStart = "/login/start" in "/admin";
+    LoginFinish = "/login/finish" in "/admin";
+}
+
+// Whether some route serves `url`, a `:param` segment matches any value.
+pub fn is_route(url: &str) -> bool {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let segments: Vec<&str> = path.trim_end_matches('/').split('/').collect();
+    ALL.iter().any(|route| {
+        let route: Vec<&str> = route.trim_end_matches('/').split('/').collect();
+        route.len() == segments.len()
+            && route.iter().zip(&segments).all(|(route, segment)| {
+                (route.starts_with(':') && !segment.is_empty()) || route == segment
+            })
+    })
+}
+
+// substitute `:param` segments, a nested `/` route is served without the trailing slash
+fn fill(path: &str, params: &[(&str, String)]) -> String {
+    let url = path
+        .split('/')
+        .map(|segment| match segment.strip_prefix(':') {
+            Some(name) => params
+                .iter()
+                .find(|(param, _)| *param == name)
+                .map(|(_, value)| urlencoding::encode(value).into_owned())
+                .unwrap_or_default(),
+            None => segment.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("/");
+    match url.strip_suffix('/') {
+        Some(trimmed) if !trimmed.is_empty() => trimmed.to_string(),
+        _ => url,
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_urls() {
+        assert_eq!(Root::url(), "/");
+        assert_eq!(ToggleTodo::url(), "/toggle_todo");
+        assert_eq!(Settings::url(), "/settings");
+        assert_eq!(TodoDetail::url(42), "/todos/42");
+        assert_eq!(TenantErase::PATH, "/tenants/:tenant/erase");
+        assert_eq!(TenantErase::url("acme"), "/admin/tenants/acme/erase");
+        assert_eq!(DeviceRevoke::url("a b"), "/admin/devices/a%20b/revoke");
+    }
+
+    #[test]
+    fn test_is_route() {
+        assert!(is_route("/"));
+        assert!(is_route("/settings"));
+        assert!(is_route("/todos/42?tab=notes"));
+        assert!(is_route(&TenantErase::url("acme")));
+        assert!(!is_route("/todos/42/nothing"));
+        assert!(!is_route("/todo/42"));
+    }
+}
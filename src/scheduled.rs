@@ -0,0 +1,184 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use axum::{
+    extract::State,
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+    Extension,
+};
+use maud::{html, Markup};
+use serde::{Deserialize, Serialize};
+use time::Date;
+
+use crate::{
+    auth::visitor::Visitor,
+    db::driver::Db,
+    error::AppError,
+    events::Events,
+    extract::FormOrJson,
+    locale::Formatter,
+    models::Todo,
+    repository::query::TodoQuery,
+    routes,
+    scheduler::{Job, Schedule},
+    state::AppState,
+    tenant::Tenant,
+    views::{
+        self,
+        hx::{Hx, Swap, Target},
+    },
+};
+
+// the toggle next to the sort order
+const TOGGLE_ID: &str = "scheduled-toggle";
+// the list refreshes on it after the toggle was switched
+pub const SHOWN_EVENT: &str = "scheduled-shown";
+// pushed to open pages when todos reach their start date
+pub const SURFACED_EVENT: &str = "scheduled";
+// per workspace, the last day whose todos were surfaced
+const SURFACED_KEY: &str = "scheduled_surfaced";
+
+// === Showing ===
+// Todos scheduled for later are left out of the list unless shown, remembered per person like
+// the completed section, `scheduled_shown:{visitor}`.
+fn key(visitor: &str) -> String {
+    format!("scheduled_shown:{}", visitor)
+}
+
+pub fn is_shown(db: &Db, visitor: &str) -> Result<bool> {
+    Ok(db.get(key(visitor))?.unwrap_or(false))
+}
+pub fn set_shown(db: &Db, visitor: &str, shown: bool) -> Result<()> {
+    db.insert(key(visitor), &shown)
+}
+
+// === Surfacing ===
+// open todos whose start date is `day`
+pub fn starting_on(db: &Db, day: Date) -> Result<Vec<Todo>> {
+    Ok(TodoQuery::new()
+        .completed(false)
+        .list(db)?
+        .into_iter()
+        .filter(|todo| todo.scheduled_for == Some(day))
+        .collect())
+}
+
+// Tell the open pages of one workspace about the todos starting today in its timezone, once a
+// day. Returns how many started.
+pub fn surface(db: &Db, events: &Events, tenant: Option<&str>) -> Result<usize> {
+    let today = Formatter::load(db)?.today();
+    if db.get::<Date, _>(SURFACED_KEY)? >= Some(today) {
+        return Ok(0);
+    }
+    let starting = starting_on(db, today)?;
+    db.insert(SURFACED_KEY, &today)?;
+    if !starting.is_empty() {
+        events.publish(tenant, SURFACED_EVENT, nudge_html(&starting).into_string());
+    }
+    Ok(starting.len())
+}
+
+// Workspaces start their day at different times, so every quarter hour each gets a look.
+pub fn job(events: Events) -> Job {
+    Job::new(
+        "scheduled",
+        Schedule::Every(Duration::from_secs(15 * 60)),
+        move |db| {
+            let events = events.clone();
+            Box::pin(async move { surface_all(&*db.write().await, &events) })
+        },
+    )
+}
+
+fn surface_all(db: &Db, events: &Events) -> Result<()> {
+    surface(&db.for_tenant(None)?, events, None)?;
+    for id in db.tenant_ids()? {
+        surface(&db.for_tenant(Some(&id))?, events, Some(&id))?;
+    }
+    Ok(())
+}
+
+// === Components ===
+fn nudge_html(starting: &[Todo]) -> Markup {
+    let message = match starting {
+        [todo] => format!("\"{}\" starts today.", todo.title),
+        todos => format!("{} scheduled todos start today.", todos.len()),
+    };
+    views::notice_toast_oob(&message)
+}
+
+// on the list, shows the nudge as a toast
+pub fn nudge_slot() -> Markup {
+    html! {
+        div class="hidden" sse-swap=(SURFACED_EVENT) hx-swap="none" {}
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ShowScheduled {
+    shown: bool,
+}
+
+pub fn toggle_html(shown: bool) -> Markup {
+    let toggle = Hx::post(routes::ScheduledTodos::url())
+        .target(Target::Id(TOGGLE_ID))
+        .swap(Swap::OuterHtml)
+        .vals(&ShowScheduled { shown: !shown });
+    html! {
+        form id=(TOGGLE_ID) class="inline" method="post" action=(routes::ScheduledTodos::url()) {
+            input type="hidden" name="shown" value=(!shown);
+            button class="t-muted hover:underline" type="submit" aria-pressed=(shown)
+                hx-post=[toggle.post_path()] hx-target=[toggle.target_attr()] hx-swap=[toggle.swap_attr()] hx-vals=[toggle.vals_attr()] {
+                @if shown { "Hide scheduled" } @else { "Show scheduled" }
+            }
+        }
+    }
+}
+
+// === Routes ===
+// Show or hide todos scheduled for later, the list refreshes itself on the event.
+pub async fn show(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    Extension(Visitor(visitor)): Extension<Visitor>,
+    headers: HeaderMap,
+    FormOrJson(ShowScheduled { shown }): FormOrJson<ShowScheduled>,
+) -> Result<Response, AppError> {
+    set_shown(
+        &state.write().await.for_tenant(tenant.id())?,
+        &visitor,
+        shown,
+    )?;
+    Ok((
+        [("hx-trigger", SHOWN_EVENT)],
+        views::fragment_or_redirect(&headers, toggle_html(shown), &routes::Root::url()),
+    )
+        .into_response())
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{TestDb, TodoFixture};
+
+    #[test]
+    fn test_surface_once_a_day() -> Result<()> {
+        let db = TestDb::new("scheduled")?;
+        TodoFixture::new().scheduled_in_days(0).persist(&db)?;
+        TodoFixture::new().scheduled_in_days(1).persist(&db)?;
+
+        let events = Events::new();
+        assert_eq!(surface(&db, &events, None)?, 1);
+        assert_eq!(surface(&db, &events, None)?, 0);
+        // the next day the other one starts
+        db.clock
+            .advance(std::time::Duration::from_secs(24 * 60 * 60));
+        assert_eq!(surface(&db, &events, None)?, 1);
+        assert!(!is_shown(&db, "a")?);
+        set_shown(&db, "a", true)?;
+        assert!(is_shown(&db, "a")? && !is_shown(&db, "b")?);
+        Ok(())
+    }
+}
@@ -0,0 +1,302 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Result};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use time::{OffsetDateTime, Time};
+use tokio::sync::RwLock;
+
+use crate::db::driver::Db;
+
+// `job:{name}`, in the default tree
+const JOB_PREFIX: &str = "job:";
+
+pub type JobFuture = Pin<Box<dyn Future<Output = Result<()>> + Send>>;
+type JobFn = Box<dyn Fn(Arc<RwLock<Db>>) -> JobFuture + Send + Sync>;
+
+// === Schedules ===
+// When a job is due, in UTC. Parses the two cron shapes in use, `M H * * *` for once a day and
+// `*/N * * * *` for every N minutes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schedule {
+    Every(Duration),
+    Daily { hour: u8, minute: u8 },
+}
+impl Schedule {
+    // the first time the job is due after it last ran at `last`
+    pub fn next_after(&self, last: OffsetDateTime) -> OffsetDateTime {
+        match *self {
+            Schedule::Every(interval) => last + interval,
+            Schedule::Daily { hour, minute } => {
+                let time = Time::from_hms(hour, minute, 0).unwrap_or(Time::MIDNIGHT);
+                let today = last.replace_time(time);
+                if today > last {
+                    today
+                } else {
+                    today + time::Duration::DAY
+                }
+            }
+        }
+    }
+}
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Schedule::Every(interval) => write!(f, "every {}m", interval.as_secs() / 60),
+            Schedule::Daily { hour, minute } => {
+                write!(f, "daily at {:02}:{:02} UTC", hour, minute)
+            }
+        }
+    }
+}
+impl FromStr for Schedule {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let [minute, hour, "*", "*", "*"] = fields[..] else {
+            bail!("unsupported schedule \"{}\"", s);
+        };
+        if let ("*", Some(every)) = (hour, minute.strip_prefix("*/")) {
+            let every: u64 = every.parse()?;
+            if every == 0 {
+                bail!("unsupported schedule \"{}\"", s);
+            }
+            return Ok(Schedule::Every(Duration::from_secs(every * 60)));
+        }
+        let (hour, minute): (u8, u8) = (hour.parse()?, minute.parse()?);
+        if hour > 23 || minute > 59 {
+            return Err(anyhow!("no such time of day in \"{}\"", s));
+        }
+        Ok(Schedule::Daily { hour, minute })
+    }
+}
+
+// === Jobs ===
+pub struct Job {
+    pub name: &'static str,
+    pub schedule: Schedule,
+    // a random delay up to this much, so instances sharing a schedule do not start together
+    pub jitter: Duration,
+    run: JobFn,
+    // a run still going makes the next one skip
+    running: AtomicBool,
+}
+impl Job {
+    pub fn new(
+        name: &'static str,
+        schedule: Schedule,
+        run: impl Fn(Arc<RwLock<Db>>) -> JobFuture + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            name,
+            schedule,
+            jitter: Duration::ZERO,
+            run: Box::new(run),
+            running: AtomicBool::new(false),
+        }
+    }
+    pub fn jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+    // false when a run is already going
+    fn claim(&self) -> bool {
+        !self.running.swap(true, Ordering::SeqCst)
+    }
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+}
+impl fmt::Debug for Job {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Job")
+            .field("name", &self.name)
+            .field("schedule", &self.schedule)
+            .finish()
+    }
+}
+
+// the outcome of a job's last run, kept across restarts
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LastRun {
+    // unix seconds
+    pub started_at: u64,
+    pub finished_at: u64,
+    pub error: Option<String>,
+}
+
+fn job_key(name: &str) -> String {
+    format!("{}{}", JOB_PREFIX, name)
+}
+
+pub fn last_run(db: &Db, name: &str) -> Result<Option<LastRun>> {
+    db.get(job_key(name))
+}
+
+// Run `job` unless a run of it is still going, returns whether it ran.
+async fn run(db: &Arc<RwLock<Db>>, job: &Job) -> Result<bool> {
+    if !job.claim() {
+        tracing::debug!(job = job.name, "skipped, the last run is still going");
+        return Ok(false);
+    }
+    finish(db, job).await?;
+    Ok(true)
+}
+
+// run a claimed job and record how it went
+async fn finish(db: &Arc<RwLock<Db>>, job: &Job) -> Result<()> {
+    let clock = db.read().await.clock().clone();
+    let started_at = clock.now_millis() / 1000;
+    let result = (job.run)(db.clone()).await;
+    job.running.store(false, Ordering::SeqCst);
+    if let Err(err) = &result {
+        tracing::error!(job = job.name, error = %err, "job failed");
+    }
+    let last = LastRun {
+        started_at,
+        finished_at: clock.now_millis() / 1000,
+        error: result.err().map(|err| err.to_string()),
+    };
+    db.write().await.insert(job_key(job.name), &last)?;
+    Ok(())
+}
+
+// === Scheduler ===
+// Runs registered jobs on their schedules, each in its own task. A job whose time passed while
+// the app was down runs right at startup.
+#[derive(Debug, Clone)]
+pub struct Scheduler {
+    db: Arc<RwLock<Db>>,
+    jobs: Arc<Vec<Arc<Job>>>,
+}
+impl Scheduler {
+    pub fn spawn(db: Arc<RwLock<Db>>, jobs: Vec<Job>) -> Self {
+        let jobs: Vec<Arc<Job>> = jobs.into_iter().map(Arc::new).collect();
+        for job in &jobs {
+            let (db, job) = (db.clone(), job.clone());
+            tokio::spawn(async move {
+                loop {
+                    if let Err(err) = tick(&db, &job).await {
+                        tracing::error!(job = job.name, error = %err, "failed to schedule job");
+                        tokio::time::sleep(Duration::from_secs(60)).await;
+                    }
+                }
+            });
+        }
+        Self {
+            db,
+            jobs: Arc::new(jobs),
+        }
+    }
+
+    pub fn jobs(&self) -> &[Arc<Job>] {
+        &self.jobs
+    }
+
+    // Start `name` now in the background, `None` when there is no such job and `Some(false)`
+    // when it is already running.
+    pub fn run_now(&self, name: &str) -> Option<bool> {
+        let job = self.jobs.iter().find(|job| job.name == name)?.clone();
+        if !job.claim() {
+            return Some(false);
+        }
+        let db = self.db.clone();
+        tokio::spawn(async move {
+            if let Err(err) = finish(&db, &job).await {
+                tracing::error!(job = job.name, error = %err, "failed to record job run");
+            }
+        });
+        Some(true)
+    }
+}
+
+// run `job` when it is due, otherwise wait until it is
+async fn tick(db: &Arc<RwLock<Db>>, job: &Job) -> Result<()> {
+    let (last, now) = {
+        let db = db.read().await;
+        (last_run(&db, job.name)?, db.clock().now())
+    };
+    let due = match last {
+        Some(last) => job
+            .schedule
+            .next_after(OffsetDateTime::from_unix_timestamp(last.started_at as i64)?),
+        None => now,
+    };
+    let wait = Duration::try_from(due - now).unwrap_or_default();
+    if !wait.is_zero() {
+        let jitter = if job.jitter.is_zero() {
+            Duration::ZERO
+        } else {
+            rand::thread_rng().gen_range(Duration::ZERO..job.jitter)
+        };
+        // checked again afterwards, a manual run in between moves the next one
+        tokio::time::sleep(wait + jitter).await;
+        return Ok(());
+    }
+    run(db, job).await?;
+    Ok(())
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+    use crate::fixtures::TestDb;
+
+    #[test]
+    fn test_schedule() -> Result<()> {
+        let daily: Schedule = "30 2 * * *".parse()?;
+        assert_eq!(
+            daily,
+            Schedule::Daily {
+                hour: 2,
+                minute: 30
+            }
+        );
+        assert_eq!(
+            daily.next_after(datetime!(2024-01-05 01:00 UTC)),
+            datetime!(2024-01-05 02:30 UTC)
+        );
+        assert_eq!(
+            daily.next_after(datetime!(2024-01-05 02:30 UTC)),
+            datetime!(2024-01-06 02:30 UTC)
+        );
+        let every: Schedule = "*/15 * * * *".parse()?;
+        assert_eq!(
+            every.next_after(datetime!(2024-01-05 01:00 UTC)),
+            datetime!(2024-01-05 01:15 UTC)
+        );
+        assert!("0 0 1 * *".parse::<Schedule>().is_err());
+        assert!("0 24 * * *".parse::<Schedule>().is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_records_and_skips_overlaps() -> Result<()> {
+        let test_db = TestDb::new("scheduler")?;
+        let db = Arc::new(RwLock::new(test_db.for_tenant(None)?));
+        let job = Job::new("failing", Schedule::Every(Duration::from_secs(60)), |_| {
+            Box::pin(async { Err(anyhow!("out of disk")) })
+        });
+
+        assert!(run(&db, &job).await?);
+        let last = last_run(&*db.read().await, "failing")?.unwrap();
+        assert_eq!(last.error.as_deref(), Some("out of disk"));
+
+        job.running.store(true, Ordering::SeqCst);
+        assert!(!run(&db, &job).await?);
+        Ok(())
+    }
+}
@@ -0,0 +1,307 @@
+use anyhow::Result;
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+};
+use maud::{html, Markup};
+use serde::Deserialize;
+
+use crate::{
+    attachments::{self, Attachment},
+    db::driver::Db,
+    error::AppError,
+    models::{Goal, Status, Todo},
+    repository::{self, query::TodoQuery},
+    routes,
+    state::AppState,
+    subscriptions, tags,
+    tenant::Tenant,
+    views::{
+        self,
+        nav::{self, Nav},
+    },
+};
+
+const RESULTS_ID: &str = "search-results";
+// per group, the rest is left for a narrower query
+const MAX_PER_GROUP: usize = 20;
+const MIN_QUERY: usize = 2;
+
+// === Search ===
+// Everything in the workspace whose text contains the query, grouped by what it is. A search
+// only reads the tree of the visitor's workspace, so it finds nothing they could not open.
+#[derive(Debug, Default)]
+pub struct Results {
+    pub todos: Vec<Todo>,
+    // the todo and the text of the matching item
+    pub checklist_items: Vec<(Todo, String)>,
+    pub attachments: Vec<(Todo, Attachment)>,
+    pub goals: Vec<Goal>,
+    pub tags: Vec<String>,
+}
+impl Results {
+    pub fn is_empty(&self) -> bool {
+        self.todos.is_empty()
+            && self.checklist_items.is_empty()
+            && self.attachments.is_empty()
+            && self.goals.is_empty()
+            && self.tags.is_empty()
+    }
+}
+
+fn contains(text: &str, needle: &str) -> bool {
+    text.to_lowercase().contains(needle)
+}
+
+// === Filters ===
+// A query split into qualifiers and the words left over, `tag:bug is:open fix login`. Unknown
+// qualifiers are searched for as words.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Filters {
+    // lowercased, joined by spaces
+    pub text: String,
+    pub tags: Vec<String>,
+    pub status: Option<Status>,
+    pub completed: Option<bool>,
+    pub archived: Option<bool>,
+}
+impl Filters {
+    pub fn parse(query: &str) -> Self {
+        let mut filters = Self::default();
+        let mut words = Vec::new();
+        for word in query.split_whitespace() {
+            match word.split_once(':') {
+                Some(("tag", tag)) if tags::normalize(tag).is_some() => {
+                    filters.tags.extend(tags::normalize(tag))
+                }
+                Some(("status", status)) if status.parse::<Status>().is_ok() => {
+                    filters.status = status.parse().ok()
+                }
+                Some(("is", "open")) => filters.completed = Some(false),
+                Some(("is", "done")) => filters.completed = Some(true),
+                Some(("is", "archived")) => filters.archived = Some(true),
+                _ => words.push(word.to_lowercase()),
+            }
+        }
+        filters.text = words.join(" ");
+        filters
+    }
+
+    pub fn has_qualifiers(&self) -> bool {
+        !self.tags.is_empty()
+            || self.status.is_some()
+            || self.completed.is_some()
+            || self.archived.is_some()
+    }
+
+    // archived todos only match `is:archived`
+    pub fn matches(&self, todo: &Todo) -> bool {
+        contains(&todo.title, &self.text)
+            && self.tags.iter().all(|tag| todo.tags.contains(tag))
+            && self.status.map_or(true, |status| todo.status == status)
+            && self
+                .completed
+                .map_or(true, |completed| todo.completed == completed)
+            && todo.archived == self.archived.unwrap_or(false)
+    }
+}
+
+pub fn search(db: &Db, query: &str) -> Result<Results> {
+    let mut results = Results::default();
+    if query.trim().chars().count() < MIN_QUERY {
+        return Ok(results);
+    }
+    let filters = Filters::parse(query);
+    let todos = TodoQuery::new()
+        .including_archived()
+        .including_someday()
+        .list(db)?;
+    // a tag narrows down to the todos under it in the tag index
+    let candidates = match filters.tags.first() {
+        Some(tag) => {
+            let tagged = repository::todo::tagged(db, tag)?;
+            TodoQuery::new()
+                .including_archived()
+                .including_someday()
+                .apply(tagged)
+                .items
+        }
+        None => todos.clone(),
+    };
+    results.todos = candidates
+        .into_iter()
+        .filter(|todo| filters.matches(todo))
+        .collect();
+    // the rest has no qualifiers to match, only words
+    let needle = filters.text;
+    if needle.chars().count() < MIN_QUERY {
+        results.todos.truncate(MAX_PER_GROUP);
+        return Ok(results);
+    }
+    for todo in &todos {
+        for item in &todo.checklist {
+            if contains(&item.text, &needle) {
+                results
+                    .checklist_items
+                    .push((todo.clone(), item.text.clone()));
+            }
+        }
+        for attachment in attachments::for_todo(db, todo.id)? {
+            if contains(&attachment.name, &needle) {
+                results.attachments.push((todo.clone(), attachment));
+            }
+        }
+    }
+    let needle_tag = needle.trim_start_matches('#');
+    results.tags = tags::counts(&todos)
+        .into_keys()
+        .filter(|tag| tag.contains(needle_tag))
+        .collect();
+    results.goals = repository::goal::all(db)?
+        .into_iter()
+        .filter(|goal| contains(&goal.title, &needle))
+        .collect();
+
+    results.todos.truncate(MAX_PER_GROUP);
+    results.checklist_items.truncate(MAX_PER_GROUP);
+    results.attachments.truncate(MAX_PER_GROUP);
+    results.goals.truncate(MAX_PER_GROUP);
+    results.tags.truncate(MAX_PER_GROUP);
+    Ok(results)
+}
+
+// === Components ===
+fn todo_link(todo: &Todo) -> Markup {
+    html! {
+        a class={ "hover:underline " @if todo.completed { "line-through" } } href=(routes::TodoDetail::url(todo.id)) { (todo.title) }
+        @if todo.archived {
+            span class="ml-2 text-xs t-muted" { "archived" }
+        }
+    }
+}
+
+fn group_html(title: &str, count: usize, items: Markup) -> Markup {
+    html! {
+        @if count > 0 {
+            section class="space-y-1" {
+                h2 class="text-xl text-gray-700" { (title) span class="ml-2 text-sm t-muted" { (count) } }
+                ul class="space-y-1" { (items) }
+            }
+        }
+    }
+}
+
+pub fn results_html(query: &str, results: &Results) -> Markup {
+    html! {
+        div id=(RESULTS_ID) class="space-y-4" {
+            @if query.trim().chars().count() < MIN_QUERY {
+                p class="text-gray-500" { "Type at least " (MIN_QUERY) " characters." }
+            } @else if results.is_empty() {
+                p class="text-gray-500" { "Nothing matches \"" (query.trim()) "\"." }
+            }
+            (group_html("Todos", results.todos.len(), html! {
+                @for todo in &results.todos { li { (todo_link(todo)) } }
+            }))
+            (group_html("Checklist items", results.checklist_items.len(), html! {
+                @for (todo, item) in &results.checklist_items {
+                    li { (item) span class="t-muted" { " in " } (todo_link(todo)) }
+                }
+            }))
+            (group_html("Attachments", results.attachments.len(), html! {
+                @for (todo, attachment) in &results.attachments {
+                    li { (attachment.name) span class="t-muted" { " on " } (todo_link(todo)) }
+                }
+            }))
+            (group_html("Goals", results.goals.len(), html! {
+                @for goal in &results.goals {
+                    li { a class="hover:underline" href=(routes::Goals::url()) { (goal.title) } }
+                }
+            }))
+            (group_html("Tags", results.tags.len(), html! {
+                @for tag in &results.tags {
+                    li { a class="hover:underline" href=(routes::Tag::url(tag)) { "#" (tag) } }
+                }
+            }))
+        }
+    }
+}
+
+// the search box, results update while typing
+pub fn form_html(query: &str) -> Markup {
+    html! {
+        form class="flex mb-6" action=(routes::Search::url()) method="get" role="search" {
+            input class="flex-grow rounded p-2 mr-2" type="search" name="q" value=(query)
+                placeholder="Search todos, checklists, files, goals and tags" aria-label="Search" autofocus
+                hx-get=(routes::Search::url()) hx-trigger="input changed delay:300ms, search"
+                hx-target={ "#" (RESULTS_ID) } hx-swap="outerHTML" hx-push-url="true";
+            button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" { "Search" }
+        }
+    }
+}
+
+// === Routes ===
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    #[serde(default)]
+    q: String,
+}
+
+pub async fn index(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Query(SearchQuery { q }): Query<SearchQuery>,
+) -> Result<Markup, AppError> {
+    let db = state.read().await.for_tenant(tenant.id())?;
+    let results = results_html(&q, &search(&db, &q)?);
+    if views::wants_fragment(&headers) {
+        return Ok(results);
+    }
+    Ok(views::page(
+        "Search",
+        html! {
+            (nav::navigation(&Nav::search()))
+            (form_html(&q))
+            (subscriptions::load(&db, &q)?)
+            (subscriptions::toast_slot())
+            (results)
+        },
+    ))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::{TestDb, TodoFixture},
+        models::ChecklistItem,
+    };
+
+    #[test]
+    fn test_grouped_results() -> Result<()> {
+        let db = TestDb::new("search")?;
+        TodoFixture::new()
+            .titled("Book the flights")
+            .with_tags(["travel"])
+            .persist(&db)?;
+        let mut packing = TodoFixture::new().titled("Pack").archived().build(&db)?;
+        packing.checklist.push(ChecklistItem {
+            text: "Flight tickets".into(),
+            done: false,
+        });
+        db.insert(repository::todo::todo_key(packing.id), &packing)?;
+        TodoFixture::new().titled("Water the plants").persist(&db)?;
+
+        let results = search(&db, "FLIGHT")?;
+        // archived todos only show for `is:archived`, their checklists always
+        assert_eq!(results.todos.len(), 1);
+        assert_eq!(search(&db, "flight is:archived")?.todos[0].title, "Pack");
+        assert_eq!(results.checklist_items[0].1, "Flight tickets");
+        assert!(results.tags.is_empty() && results.goals.is_empty());
+        assert_eq!(search(&db, "#trav")?.tags, ["travel"]);
+        // one letter finds nothing rather than everything
+        assert!(search(&db, "a")?.is_empty());
+        Ok(())
+    }
+}
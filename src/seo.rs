@@ -0,0 +1,149 @@
+use axum::{
+    extract::State,
+    http::header,
+    response::{IntoResponse, Response},
+};
+use maud::{html, Markup, PreEscaped};
+
+use crate::{
+    branding::{self, Branding},
+    config::Config,
+    db::driver::Db,
+    embed,
+    error::AppError,
+    models::Todo,
+    registration, routes,
+    state::AppState,
+    tenant,
+    views::meta::{self, Meta},
+};
+
+// === Sitemap ===
+// The pages anyone may find: the landing page, and signing up while registration is open. Kiosk
+// and embed links are secrets, they unfurl when shared but are never listed.
+pub fn public_paths(config: &Config, db: &Db) -> anyhow::Result<Vec<String>> {
+    let mut paths = vec![routes::Root::url()];
+    if registration::is_open(config, db)? {
+        paths.push(routes::Signup::url());
+    }
+    Ok(paths)
+}
+
+pub fn sitemap_xml(public_url: &str, paths: &[String]) -> Markup {
+    html! {
+        (PreEscaped(r#"<?xml version="1.0" encoding="UTF-8"?>"#))
+        urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9" {
+            @for path in paths {
+                url { loc { (meta::absolute(public_url, path)) } }
+            }
+        }
+    }
+}
+
+pub fn robots_txt(public_url: &str) -> String {
+    format!(
+        "User-agent: *\nDisallow: /admin/\nDisallow: /kiosk/\nDisallow: /embed/\nSitemap: {}\n",
+        meta::absolute(public_url, &routes::Sitemap::url())
+    )
+}
+
+// === Meta ===
+// "3 of 4 todos done (75%)"
+pub fn progress_summary(todos: &[Todo]) -> String {
+    let progress = embed::progress(todos);
+    format!(
+        "{} of {} todos done ({}%)",
+        progress.done,
+        progress.total,
+        progress.percent()
+    )
+}
+
+// the landing page, described by the first paragraph of the welcome text
+pub fn landing_meta(public_url: &str, name: &str, branding: &Branding) -> Meta {
+    let meta = Meta::canonical(meta::absolute(public_url, &routes::Root::url()))
+        .site_name(name)
+        .image(meta::absolute(public_url, &routes::BrandLogo::url()));
+    match branding.paragraphs().first() {
+        Some(description) => meta.description(*description),
+        None => meta,
+    }
+}
+
+// a shared list, described by how far along it is
+pub fn share_meta(public_url: &str, path: &str, site_name: &str, todos: &[Todo]) -> Meta {
+    Meta::canonical(meta::absolute(public_url, path))
+        .site_name(site_name)
+        .description(progress_summary(todos))
+        .image(meta::absolute(public_url, &routes::BrandLogo::url()))
+        .noindex()
+}
+
+// the name a workspace's shared pages go by
+pub fn site_name(db: &Db, tenant_id: Option<&str>) -> anyhow::Result<String> {
+    match tenant_id
+        .map(|id| tenant::get(db, id))
+        .transpose()?
+        .flatten()
+    {
+        Some(record) => Ok(record.name),
+        None => branding::name(db),
+    }
+}
+
+// === Routes ===
+pub async fn sitemap(State(state): State<AppState>) -> Result<Response, AppError> {
+    let paths = public_paths(&state.live.load(), &*state.read().await)?;
+    Ok((
+        [(header::CONTENT_TYPE, "application/xml; charset=utf-8")],
+        sitemap_xml(&state.config.public_url, &paths),
+    )
+        .into_response())
+}
+
+pub async fn robots(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+        robots_txt(&state.config.public_url),
+    )
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sitemap_lists_absolute_urls() {
+        let paths = vec![routes::Root::url(), routes::Signup::url()];
+        let xml = sitemap_xml("https://todo.example/", &paths).into_string();
+        assert!(xml.starts_with(r#"<?xml version="1.0" encoding="UTF-8"?><urlset"#));
+        assert!(xml.contains("<loc>https://todo.example/</loc>"));
+        assert!(xml.contains("<loc>https://todo.example/signup</loc>"));
+
+        let robots = robots_txt("https://todo.example");
+        assert!(robots.contains("Disallow: /kiosk/\n"));
+        assert!(robots.contains("Sitemap: https://todo.example/sitemap.xml"));
+    }
+
+    #[test]
+    fn test_share_meta() {
+        let mut done = Todo::new(1, "Pack".into());
+        done.set_completed(true);
+        let todos = [done, Todo::new(2, "Book flights".into())];
+        let meta = share_meta("https://todo.example", "/kiosk/abc", "Trip", &todos);
+        assert_eq!(meta.description.as_deref(), Some("1 of 2 todos done (50%)"));
+        assert_eq!(
+            meta.canonical.as_deref(),
+            Some("https://todo.example/kiosk/abc")
+        );
+        assert!(meta.noindex);
+
+        let branding = Branding {
+            welcome: Some("Our chores.\n\nBe nice.".into()),
+            ..Branding::default()
+        };
+        let meta = landing_meta("https://todo.example", "Home", &branding);
+        assert_eq!(meta.description.as_deref(), Some("Our chores."));
+    }
+}
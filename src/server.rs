@@ -0,0 +1,177 @@
+use std::{convert::Infallible, future::Future, net::SocketAddr, time::Duration};
+
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Request},
+    response::Response,
+};
+use hyper::{body::Incoming, server::conn::http1, service::service_fn};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo, TokioTimer},
+    server::conn::auto,
+};
+use tokio::{net::TcpListener, sync::watch};
+use tower::{Service, ServiceExt};
+
+use crate::config::Config;
+
+// === Tuning ===
+// How connections are served, from `Config`. The defaults suit a few hundred open pages, each
+// holding an event stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tuning {
+    // speak HTTP/2 to clients that ask for it (h2c), HTTP/1.1 only otherwise
+    pub http2: bool,
+    // requests in flight on one HTTP/2 connection, every open event stream is one
+    pub max_concurrent_streams: u32,
+    // reuse HTTP/1.1 connections for the next request
+    pub keep_alive: bool,
+    // ping idle HTTP/2 connections this often, so dead ones holding streams are noticed
+    pub keep_alive_interval: Option<Duration>,
+    // how long a ping may go unanswered before the connection is closed
+    pub keep_alive_timeout: Duration,
+    // send small writes, like event stream messages, right away instead of batching them
+    pub tcp_nodelay: bool,
+}
+impl Default for Tuning {
+    fn default() -> Self {
+        Self {
+            http2: true,
+            max_concurrent_streams: 200,
+            keep_alive: true,
+            keep_alive_interval: Some(Duration::from_secs(30)),
+            keep_alive_timeout: Duration::from_secs(20),
+            tcp_nodelay: true,
+        }
+    }
+}
+impl Tuning {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            http2: config.http2,
+            max_concurrent_streams: config.http2_max_concurrent_streams,
+            keep_alive: config.keep_alive,
+            keep_alive_interval: config.http2_keep_alive_interval,
+            keep_alive_timeout: config.http2_keep_alive_timeout,
+            tcp_nodelay: config.tcp_nodelay,
+        }
+    }
+}
+
+// === Serving ===
+// Like `axum::serve` with the tuning applied: accept until `shutdown` completes, then let every
+// open connection finish what it is doing. Handlers see the peer as `ConnectInfo<SocketAddr>`.
+pub async fn serve<S>(
+    listener: TcpListener,
+    app: S,
+    tuning: Tuning,
+    shutdown: impl Future<Output = ()>,
+) -> std::io::Result<()>
+where
+    S: Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send,
+{
+    // every connection holds a receiver and finishes up when the sender sends
+    let (closing, closed) = watch::channel(());
+    tokio::pin!(shutdown);
+    loop {
+        let (stream, addr) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(err) => {
+                    // e.g. out of file descriptors, give the ones in use a moment
+                    tracing::warn!(%err, "could not accept a connection");
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            },
+            _ = &mut shutdown => break,
+        };
+        if let Err(err) = stream.set_nodelay(tuning.tcp_nodelay) {
+            tracing::debug!(%err, "could not set TCP_NODELAY");
+        }
+        let app = app.clone();
+        let service = service_fn(move |request: hyper::Request<Incoming>| {
+            let mut request = request.map(Body::new);
+            request.extensions_mut().insert(ConnectInfo(addr));
+            app.clone().oneshot(request)
+        });
+        let io = TokioIo::new(stream);
+        let mut closed = closed.clone();
+        if tuning.http2 {
+            tokio::spawn(async move {
+                let builder = auto_builder(tuning);
+                let connection = builder.serve_connection_with_upgrades(io, service);
+                tokio::pin!(connection);
+                tokio::select! {
+                    result = connection.as_mut() => log_result(result),
+                    _ = closed.changed() => {
+                        connection.as_mut().graceful_shutdown();
+                        log_result(connection.await);
+                    }
+                }
+            });
+        } else {
+            tokio::spawn(async move {
+                let connection = http1::Builder::new()
+                    .keep_alive(tuning.keep_alive)
+                    .serve_connection(io, service)
+                    .with_upgrades();
+                tokio::pin!(connection);
+                tokio::select! {
+                    result = connection.as_mut() => log_result(result),
+                    _ = closed.changed() => {
+                        connection.as_mut().graceful_shutdown();
+                        log_result(connection.await);
+                    }
+                }
+            });
+        }
+    }
+    drop(listener);
+    drop(closed);
+    // tell the open connections, then wait for the last one to drop its receiver
+    let _ = closing.send(());
+    closing.closed().await;
+    Ok(())
+}
+
+// HTTP/1.1 or HTTP/2, whichever the client speaks
+fn auto_builder(tuning: Tuning) -> auto::Builder<TokioExecutor> {
+    let mut builder = auto::Builder::new(TokioExecutor::new());
+    builder.http1().keep_alive(tuning.keep_alive);
+    builder
+        .http2()
+        .timer(TokioTimer::new())
+        .max_concurrent_streams(tuning.max_concurrent_streams)
+        .keep_alive_interval(tuning.keep_alive_interval)
+        .keep_alive_timeout(tuning.keep_alive_timeout);
+    builder
+}
+
+// a client going away mid-request is nothing to warn about
+fn log_result<E: std::fmt::Display>(result: Result<(), E>) {
+    if let Err(err) = result {
+        tracing::debug!(%err, "connection ended with an error");
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tuning_follows_the_config() {
+        let config = Config {
+            http2: false,
+            http2_keep_alive_interval: None,
+            tcp_nodelay: false,
+            ..Config::default()
+        };
+        let tuning = Tuning::from_config(&config);
+        assert!(!tuning.http2 && !tuning.tcp_nodelay);
+        assert_eq!(tuning.keep_alive_interval, None);
+        assert_eq!(Tuning::from_config(&Config::default()), Tuning::default());
+    }
+}
@@ -0,0 +1,109 @@
+use crate::{
+    accounts::{self, User},
+    auth::throttle::LoginThrottle,
+    config::{AuthMode, Config},
+    db::driver::Db,
+    error::AppError,
+    tenant,
+};
+
+pub const MIN_PASSWORD_LEN: usize = 8;
+
+// names are compared lowercased, "Ada" signs in as "ada"
+pub fn normalize_name(name: &str) -> String {
+    name.trim().to_ascii_lowercase()
+}
+
+// why `name` and `password` cannot make an account, shown on the form
+pub fn signup_refusal(db: &Db, name: &str, password: &str) -> anyhow::Result<Option<&'static str>> {
+    Ok(if !tenant::is_valid_id(name) {
+        Some("The name may only contain a-z, 0-9 and -.")
+    } else if password.chars().count() < MIN_PASSWORD_LEN {
+        Some("The password needs at least 8 characters.")
+    } else if accounts::get(db, name)?.is_some() {
+        Some("That name is taken.")
+    } else {
+        None
+    })
+}
+
+// === Service ===
+// The rules for accounts: who may sign up, and how often a name or address may guess a password
+// before it has to wait. Callers hold the write lock for `db`, so two signups cannot take the
+// same name.
+pub struct AuthService<'a> {
+    config: &'a Config,
+    db: &'a Db,
+}
+impl<'a> AuthService<'a> {
+    pub fn new(config: &'a Config, db: &'a Db) -> Self {
+        Self { config, db }
+    }
+
+    // there is nothing to sign up for unless accounts bring their own workspace
+    pub fn enabled(config: &Config) -> Result<(), AppError> {
+        match config.auth_mode {
+            AuthMode::Users => Ok(()),
+            _ => Err(AppError::NotFound),
+        }
+    }
+
+    // the new account, or why there is none
+    pub fn signup(
+        &self,
+        name: &str,
+        password: &str,
+    ) -> Result<Result<User, &'static str>, AppError> {
+        Self::enabled(self.config)?;
+        let name = normalize_name(name);
+        if let Some(refusal) = signup_refusal(self.db, &name, password)? {
+            return Ok(Err(refusal));
+        }
+        let user = accounts::create(self.db, &name, password)?;
+        tracing::info!(account = %name, "signed up");
+        Ok(Ok(user))
+    }
+
+    // The account when the password is right. `subjects` are what failures are counted against,
+    // the name and the address it came from.
+    pub fn login(
+        &self,
+        throttle: &LoginThrottle,
+        name: &str,
+        password: &str,
+        subjects: &[String],
+    ) -> Result<Option<User>, AppError> {
+        Self::enabled(self.config)?;
+        let has_failures = throttle.check(self.db, subjects)?;
+        let Some(user) = accounts::authenticate(self.db, &normalize_name(name), password)? else {
+            throttle.record_failure(self.db, subjects)?;
+            return Ok(None);
+        };
+        if has_failures {
+            throttle.reset(self.db, subjects)?;
+        }
+        tracing::info!(account = %user.name, "signed in");
+        Ok(Some(user))
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::TestDb;
+
+    #[test]
+    fn test_signup_refusal() -> anyhow::Result<()> {
+        let db = TestDb::new("auth_service")?;
+        assert_eq!(signup_refusal(&db, "ada", "correct horse")?, None);
+        assert!(signup_refusal(&db, "ada lovelace", "correct horse")?.is_some());
+        assert!(signup_refusal(&db, "ada", "short")?.is_some());
+        accounts::create(&db, "ada", "correct horse")?;
+        assert_eq!(
+            signup_refusal(&db, &normalize_name(" Ada "), "correct horse")?,
+            Some("That name is taken.")
+        );
+        Ok(())
+    }
+}
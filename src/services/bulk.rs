@@ -0,0 +1,337 @@
+use std::collections::HashSet;
+
+use time::{Date, Duration};
+
+use crate::{
+    db::driver::Db,
+    domain::events,
+    editing,
+    error::AppError,
+    filtering::ListFilter,
+    history, lists,
+    locale::Formatter,
+    models::Todo,
+    repository::{entity::Repository, query::TodoQuery},
+    scheduled,
+    services::todo::changed_event,
+    state::AppState,
+    tags,
+};
+
+// todos a command may change at once, more need a narrower filter
+pub const MAX_BULK: usize = 500;
+// how far due dates are pushed at most, either way
+const MAX_PUSH_DAYS: i64 = 3650;
+
+// === Commands ===
+// What a bulk command does to each todo it covers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Tag(String),
+    Untag(String),
+    // days due dates move by, earlier when negative
+    PushDue(i64),
+    Move(String),
+    Complete,
+}
+
+// which todos of the filter a command covers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Scope {
+    #[default]
+    All,
+    Open,
+    // open and due before today
+    Overdue,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Command {
+    pub action: Action,
+    pub scope: Scope,
+}
+
+fn not_a_command(text: &str) -> AppError {
+    AppError::Invalid(format!(
+        "`{}` is not a command, try `tag all overdue as #urgent`, `untag all #urgent`, \
+         `push due dates by 1 week`, `move all open to groceries` or `complete all overdue`.",
+        text.trim()
+    ))
+}
+
+impl Command {
+    // A command as typed in the palette, the verb first: `tag all overdue as #urgent`. Filler
+    // words are skipped, `overdue` and `open` narrow it down.
+    pub fn parse(text: &str) -> Result<Self, AppError> {
+        let words: Vec<String> = text.split_whitespace().map(str::to_lowercase).collect();
+        let (verb, rest) = words.split_first().ok_or_else(|| not_a_command(text))?;
+        let mut scope = Scope::All;
+        let mut args = Vec::new();
+        for word in rest {
+            match word.as_str() {
+                "overdue" => scope = Scope::Overdue,
+                "open" => scope = Scope::Open,
+                "all" | "the" | "todos" | "due" | "dates" | "as" | "by" | "to" => {}
+                _ => args.push(word.as_str()),
+            }
+        }
+        let tag = |args: &[&str]| match args {
+            [tag] => tags::normalize(tag.trim_start_matches('#')).ok_or_else(|| {
+                AppError::Invalid(format!("`{}` is not a tag, use a-z, 0-9, - and _", tag))
+            }),
+            _ => Err(not_a_command(text)),
+        };
+        let action = match verb.as_str() {
+            "tag" => Action::Tag(tag(&args)?),
+            "untag" => Action::Untag(tag(&args)?),
+            "push" | "postpone" => {
+                let (count, unit) = match args.as_slice() {
+                    [count] => (*count, "days"),
+                    [count, unit] => (*count, *unit),
+                    _ => return Err(not_a_command(text)),
+                };
+                let count: i64 = count.parse().map_err(|_| not_a_command(text))?;
+                let days = match unit {
+                    "day" | "days" => count,
+                    "week" | "weeks" => count.saturating_mul(7),
+                    _ => return Err(not_a_command(text)),
+                };
+                if days == 0 || days.abs() > MAX_PUSH_DAYS {
+                    return Err(AppError::Invalid(format!(
+                        "Push due dates by up to {} days.",
+                        MAX_PUSH_DAYS
+                    )));
+                }
+                Action::PushDue(days)
+            }
+            "move" => match args.as_slice() {
+                [list] => Action::Move(lists::parse(list)?.ok_or_else(|| not_a_command(text))?),
+                _ => return Err(not_a_command(text)),
+            },
+            "complete" | "finish" if args.is_empty() => Action::Complete,
+            _ => return Err(not_a_command(text)),
+        };
+        Ok(Self { action, scope })
+    }
+
+    // what it does, for the preview
+    pub fn describe(&self) -> String {
+        let action = match &self.action {
+            Action::Tag(tag) => format!("Tag as #{}", tag),
+            Action::Untag(tag) => format!("Remove #{} from", tag),
+            Action::PushDue(days) => format!("Move due dates by {:+} days on", days),
+            Action::Move(list) => format!("Move to {}", list),
+            Action::Complete => "Complete".to_string(),
+        };
+        let scope = match self.scope {
+            Scope::All => "",
+            Scope::Open => " open",
+            Scope::Overdue => " overdue",
+        };
+        format!("{} the{} todos shown", action, scope)
+    }
+
+    pub fn covers(&self, todo: &Todo, today: Date) -> bool {
+        match self.scope {
+            Scope::All => true,
+            Scope::Open => !todo.completed,
+            Scope::Overdue => !todo.completed && todo.due.is_some_and(|due| due < today),
+        }
+    }
+
+    // `todo` as the command leaves it, `None` when it has nothing to change
+    pub fn apply(&self, todo: &Todo) -> Option<Todo> {
+        let mut after = todo.clone();
+        match &self.action {
+            Action::Tag(tag) if !todo.tags.contains(tag) => after.tags.push(tag.clone()),
+            Action::Untag(tag) if todo.tags.contains(tag) => after.tags.retain(|own| own != tag),
+            Action::PushDue(days) => {
+                after.due = Some(todo.due?.checked_add(Duration::days(*days))?)
+            }
+            Action::Move(list) if todo.list.as_ref() != Some(list) => {
+                after.list = Some(list.clone())
+            }
+            Action::Complete if !todo.completed => after.set_completed(true),
+            _ => return None,
+        }
+        Some(after)
+    }
+}
+
+// === Preview ===
+// What a command would do to the todos of a filter, shown before it is run.
+#[derive(Debug, Clone)]
+pub struct Preview {
+    pub command: Command,
+    // the todos of the filter it covers
+    pub covered: usize,
+    // the todos it changes, as they are and as they would be
+    pub changes: Vec<(Todo, Todo)>,
+    // changes left out, the todo is open in the editor of someone else
+    pub locked: usize,
+}
+impl Preview {
+    pub fn ids(&self) -> Vec<u64> {
+        self.changes.iter().map(|(before, _)| before.id).collect()
+    }
+}
+
+// === Service ===
+// Commands run on every todo of a filter at once, all of it in one batch. The same rules as for
+// a single change apply, see `TodoService`.
+pub struct BulkService<'a> {
+    state: &'a AppState,
+    db: &'a Db,
+    tenant: Option<&'a str>,
+}
+impl<'a> BulkService<'a> {
+    pub fn new(state: &'a AppState, db: &'a Db, tenant: Option<&'a str>) -> Self {
+        Self { state, db, tenant }
+    }
+
+    // The todos `filter` lists for `visitor`, on every page. Todos scheduled for later count
+    // only for visitors who see them.
+    fn targets(&self, filter: &ListFilter, visitor: Option<&str>) -> Result<Vec<Todo>, AppError> {
+        let mut query = TodoQuery::new();
+        if let Some(visitor) = visitor {
+            if !scheduled::is_shown(self.db, visitor)? {
+                query = query.available_on(Formatter::load(self.db)?.today());
+            }
+        }
+        Ok(filter.matching(query).list(self.db)?)
+    }
+
+    pub fn preview(
+        &self,
+        filter: &ListFilter,
+        visitor: Option<&str>,
+        command: Command,
+    ) -> Result<Preview, AppError> {
+        let today = Formatter::load(self.db)?.today();
+        let covered: Vec<Todo> = self
+            .targets(filter, visitor)?
+            .into_iter()
+            .filter(|todo| command.covers(todo, today))
+            .collect();
+        if covered.len() > MAX_BULK {
+            return Err(AppError::Invalid(format!(
+                "That covers {} todos, narrow the list down to {} or fewer.",
+                covered.len(),
+                MAX_BULK
+            )));
+        }
+        let mut preview = Preview {
+            command,
+            covered: covered.len(),
+            changes: Vec::new(),
+            locked: 0,
+        };
+        for todo in covered {
+            let Some(after) = preview.command.apply(&todo) else {
+                continue;
+            };
+            let locked = editing::lock(self.db, todo.id)?
+                .is_some_and(|lock| Some(lock.holder.as_str()) != visitor);
+            if locked {
+                preview.locked += 1;
+            } else {
+                preview.changes.push((todo, after));
+            }
+        }
+        Ok(preview)
+    }
+
+    // Run a command previewed before on the todos that were `confirmed`, in one batch. A todo
+    // that came into the filter since is left alone, one that left it too.
+    pub async fn run(
+        &self,
+        filter: &ListFilter,
+        visitor: Option<&str>,
+        command: Command,
+        confirmed: &HashSet<u64>,
+    ) -> Result<Vec<Todo>, AppError> {
+        let preview = self.preview(filter, visitor, command)?;
+        let todos = Repository::<Todo>::new(self.db);
+        let mut ops = Vec::new();
+        let mut told = Vec::new();
+        let mut changed = Vec::new();
+        for (before, mut after) in preview.changes {
+            if !confirmed.contains(&before.id) {
+                continue;
+            }
+            after.version = before.version;
+            after.touch(&**self.db.clock());
+            ops.extend(history::record_ops(self.db, &before)?);
+            ops.extend(todos.put_ops(&after)?);
+            let event = changed_event(&before, after.clone());
+            ops.extend(events::ops(self.state, self.db, self.tenant, &event)?);
+            told.push(event);
+            changed.push(after);
+        }
+        self.state.writes.submit(self.db, ops).await?;
+        for event in &told {
+            events::committed(self.state, self.tenant, event);
+        }
+        Ok(changed)
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use time::macros::date;
+
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Command::parse("Tag all overdue as #Urgent").unwrap(),
+            Command {
+                action: Action::Tag("urgent".into()),
+                scope: Scope::Overdue
+            }
+        );
+        assert_eq!(
+            Command::parse("push due dates by 1 week").unwrap().action,
+            Action::PushDue(7)
+        );
+        assert_eq!(
+            Command::parse("push -2 days").unwrap().action,
+            Action::PushDue(-2)
+        );
+        assert_eq!(
+            Command::parse("move all open to groceries").unwrap(),
+            Command {
+                action: Action::Move("groceries".into()),
+                scope: Scope::Open
+            }
+        );
+        assert!(Command::parse("").is_err());
+        assert!(Command::parse("tag all").is_err());
+        assert!(Command::parse("push by a week").is_err());
+        assert!(Command::parse("delete everything").is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        let today = date!(2024 - 03 - 10);
+        let mut overdue = Todo::new(1, "File taxes".into());
+        overdue.due = Some(date!(2024 - 03 - 01));
+        let undated = Todo::new(2, "Read a book".into());
+
+        let tag = Command::parse("tag overdue #urgent").unwrap();
+        assert!(tag.covers(&overdue, today) && !tag.covers(&undated, today));
+        let tagged = tag.apply(&overdue).unwrap();
+        assert_eq!(tagged.tags, ["urgent"]);
+        // already there, nothing to change
+        assert_eq!(tag.apply(&tagged), None);
+
+        let push = Command::parse("push by 1 week").unwrap();
+        assert_eq!(
+            push.apply(&overdue).unwrap().due,
+            Some(date!(2024 - 03 - 08))
+        );
+        assert_eq!(push.apply(&undated), None);
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    completed,
+    db::driver::Db,
+    error::AppError,
+    filtering::{ListFilter, StatusFilter},
+    locale::Formatter,
+    models::Todo,
+    reactions::{self, Reactions},
+    repository::{
+        self,
+        query::{Page, TodoQuery},
+    },
+    scheduled,
+    sorting::{self, Order, View},
+};
+
+// === Listing ===
+// The todos of the list and what their items need to know about them, copied out so rendering
+// does not hold the lock or see half-applied writes.
+#[derive(Debug, Clone)]
+pub struct Listing {
+    pub page: Page<Todo>,
+    // todos waiting for an open todo
+    pub blocked: HashSet<u64>,
+    pub reactions: HashMap<u64, Reactions>,
+    // due and done dates in the workspace's language and timezone
+    pub dates: Formatter,
+    pub order: Order,
+    // the visitor opened the completed section
+    pub completed_open: bool,
+    // the visitor sees todos scheduled for later
+    pub scheduled_shown: bool,
+    // the part of the list shown
+    pub filter: ListFilter,
+}
+
+// === Service ===
+// Which todos the list of a workspace shows, the same for the page, its fragments and the
+// panels built from it.
+pub struct ListService<'a> {
+    db: &'a Db,
+}
+impl<'a> ListService<'a> {
+    pub fn new(db: &'a Db) -> Self {
+        Self { db }
+    }
+
+    // The todos of `filter`, every one without it. With a visitor the list is the one they see,
+    // todos scheduled for later are left out unless they chose to see them.
+    pub fn visible(
+        &self,
+        visitor: Option<&str>,
+        filter: Option<&ListFilter>,
+    ) -> Result<Listing, AppError> {
+        let db = self.db;
+        let dates = Formatter::load(db)?;
+        let (completed_open, scheduled_shown) = match visitor {
+            Some(visitor) => (
+                completed::is_open(db, View::List, visitor)?,
+                scheduled::is_shown(db, visitor)?,
+            ),
+            None => (false, true),
+        };
+        let order = sorting::get(db, View::List)?;
+        let mut query = TodoQuery::new().sort(sorting::sort(order));
+        if !scheduled_shown {
+            query = query.available_on(dates.today());
+        }
+        if let Some(filter) = filter {
+            query = filter.query(query)?;
+        }
+        let page = query.run(db)?;
+        let filter = filter.cloned().unwrap_or_default();
+        Ok(Listing {
+            blocked: repository::todo::blocked_ids(db, &page.items)?,
+            reactions: reactions::for_todos(db, &page.items)?,
+            dates,
+            order,
+            // looking only at completed todos, hiding them would leave nothing
+            completed_open: completed_open || filter.status == StatusFilter::Completed,
+            scheduled_shown,
+            filter,
+            page,
+        })
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{TestDb, TodoFixture};
+
+    #[test]
+    fn test_visible_hides_scheduled_todos() -> anyhow::Result<()> {
+        let db = TestDb::new("list_service")?;
+        TodoFixture::new().titled("Now").persist(&db)?;
+        TodoFixture::new()
+            .titled("Later")
+            .scheduled_in_days(3)
+            .persist(&db)?;
+
+        let titles = |listing: Listing| -> Vec<String> {
+            listing
+                .page
+                .items
+                .into_iter()
+                .map(|todo| todo.title)
+                .collect()
+        };
+        let lists = ListService::new(&db);
+        // visitors see todos scheduled for later only once they ask to
+        assert_eq!(titles(lists.visible(Some("alice"), None)?), ["Now"]);
+        // without a visitor nothing is hidden
+        assert_eq!(titles(lists.visible(None, None)?).len(), 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,4 @@
+pub mod auth;
+pub mod bulk;
+pub mod list;
+pub mod todo;
@@ -0,0 +1,291 @@
+use time::Date;
+
+use crate::{
+    clock::Clock,
+    db::driver::Db,
+    domain::events::{self, DomainEvent},
+    editing,
+    error::{AppError, FieldErrors},
+    history, merge,
+    models::{self, Location, Todo},
+    repository::{
+        self,
+        entity::Repository,
+        todo::{remove_ops, todo_key},
+    },
+    state::AppState,
+    tags,
+};
+
+// === New todos ===
+// A todo as typed into quick-add or sent by a client, checked but not stored yet.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NewTodo {
+    pub title: String,
+    pub due: Option<Date>,
+    pub scheduled_for: Option<Date>,
+    pub estimate_minutes: Option<u32>,
+    pub tags: Vec<String>,
+    // a `near:` place, looked up by the caller before the todo is created
+    pub near: Option<String>,
+    pub location: Option<Location>,
+}
+impl NewTodo {
+    // Quick-add text with its `near:` place and `#tags` taken out, and the optional fields of the
+    // form, empty ones meaning none. Every field is checked, the errors come back together.
+    pub fn parse(
+        title: &str,
+        due: &str,
+        scheduled_for: &str,
+        estimate: &str,
+    ) -> Result<Self, AppError> {
+        let mut errors = FieldErrors::default();
+        let mut date = |field: &str, value: &str| {
+            models::parse_due(value).unwrap_or_else(|err| {
+                errors.add(field, err.to_string());
+                None
+            })
+        };
+        let due = date("due", due);
+        let scheduled_for = date("scheduled_for", scheduled_for);
+        let estimate_minutes = match estimate.trim() {
+            "" => None,
+            minutes => minutes.parse().map(Some).unwrap_or_else(|_| {
+                errors.add(
+                    "estimate",
+                    format!("`{}` is not a number of minutes", minutes),
+                );
+                None
+            }),
+        };
+        let (title, near) = models::parse_near(title);
+        let (title, tags) = tags::parse_tags(&title);
+        let title = title.trim().to_string();
+        if title.is_empty() {
+            errors.add("title", "a todo needs a title");
+        }
+        errors.check(Self {
+            title,
+            due,
+            scheduled_for,
+            estimate_minutes,
+            tags,
+            near,
+            location: None,
+        })
+    }
+
+    fn into_todo(self, id: u64, clock: &dyn Clock) -> Todo {
+        let mut todo = Todo::created(id, self.title, clock);
+        todo.due = self.due;
+        todo.scheduled_for = self.scheduled_for;
+        todo.estimate_minutes = self.estimate_minutes;
+        todo.tags = self.tags;
+        todo.location = self.location;
+        todo
+    }
+}
+
+// what a change to `before` amounts to, finishing, cancelling or reopening it counts as that
+pub fn changed_event(before: &Todo, after: Todo) -> DomainEvent {
+    if before.completed != after.completed || before.is_cancelled() != after.is_cancelled() {
+        DomainEvent::toggled(after)
+    } else {
+        DomainEvent::TodoUpdated(after)
+    }
+}
+
+// === Service ===
+// The rules for changing todos of a workspace, the same for the list, the api and anything else
+// changing them: input is checked, edit locks are honoured, history is kept and every change is
+// told as a domain event. Callers hold the write lock for `db`.
+pub struct TodoService<'a> {
+    state: &'a AppState,
+    db: &'a Db,
+    tenant: Option<&'a str>,
+}
+impl<'a> TodoService<'a> {
+    pub fn new(state: &'a AppState, db: &'a Db, tenant: Option<&'a str>) -> Self {
+        Self { state, db, tenant }
+    }
+
+    pub fn get(&self, id: u64) -> Result<Todo, AppError> {
+        self.db
+            .get::<Todo, _>(todo_key(id))?
+            .ok_or(AppError::NotFound)
+    }
+
+    pub async fn create(&self, new: NewTodo) -> Result<Todo, AppError> {
+        let todo = new.into_todo(self.db.next_id()?, &**self.db.clock());
+        let mut ops = Repository::new(self.db).put_ops(&todo)?;
+        let event = DomainEvent::TodoCreated(todo.clone());
+        ops.extend(events::ops(self.state, self.db, self.tenant, &event)?);
+        self.state.writes.submit(self.db, ops).await?;
+        events::committed(self.state, self.tenant, &event);
+        Ok(todo)
+    }
+
+    // Complete an open todo or reopen a completed one. It is read and flipped in one
+    // transaction, two toggles never both see it open.
+    pub fn toggle(&self, id: u64) -> Result<Todo, AppError> {
+        let todos = Repository::<Todo>::new(self.db);
+        let toggled = self.db.transaction(|tx| {
+            let Some(mut todo) = todos.get_in(tx, id)? else {
+                return Ok(None);
+            };
+            let mut ops = history::record_ops(self.db, &todo)?;
+            todo.set_completed(!todo.completed);
+            todo.touch(&**self.db.clock());
+            let event = DomainEvent::toggled(todo.clone());
+            ops.extend(events::ops(self.state, self.db, self.tenant, &event)?);
+            tx.apply_batch(ops)?;
+            todos.put_in(tx, &todo)?;
+            Ok(Some((todo, event)))
+        })?;
+        let (todo, event) = toggled.ok_or(AppError::NotFound)?;
+        events::committed(self.state, self.tenant, &event);
+        Ok(todo)
+    }
+
+    // Change todo `id` as `editor`, a visitor, or as a client without one. A todo open in the
+    // title editor of someone else is left alone.
+    pub async fn update(
+        &self,
+        id: u64,
+        editor: Option<&str>,
+        change: impl FnOnce(&mut Todo) -> Result<(), AppError>,
+    ) -> Result<Todo, AppError> {
+        let before = self.get(id)?;
+        self.check_lock(id, editor)?;
+        let mut todo = before.clone();
+        change(&mut todo)?;
+        if todo.title.trim().is_empty() {
+            return Err(AppError::Invalid("a todo needs a title".to_string()));
+        }
+        // one change is one version, whatever `change` did to it
+        todo.version = before.version;
+        todo.touch(&**self.db.clock());
+        let mut ops = history::record_ops(self.db, &before)?;
+        ops.extend(Repository::new(self.db).put_ops(&todo)?);
+        let event = changed_event(&before, todo.clone());
+        ops.extend(events::ops(self.state, self.db, self.tenant, &event)?);
+        self.state.writes.submit(self.db, ops).await?;
+        events::committed(self.state, self.tenant, &event);
+        Ok(todo)
+    }
+
+    // Merge todo `absorbed` into `survivor` as `editor`, see `merge::combine`. It is written in
+    // one batch, and links to the absorbed todo lead to the survivor from then on.
+    pub async fn merge(
+        &self,
+        survivor: u64,
+        absorbed: u64,
+        editor: Option<&str>,
+    ) -> Result<Todo, AppError> {
+        if survivor == absorbed {
+            return Err(AppError::Invalid(
+                "A todo cannot be merged into itself.".into(),
+            ));
+        }
+        let before = self.get(survivor)?;
+        let absorbed = self.get(absorbed)?;
+        self.check_lock(before.id, editor)?;
+        self.check_lock(absorbed.id, editor)?;
+        let mut todo = merge::combine(&before, &absorbed);
+        todo.version = before.version;
+        todo.touch(&**self.db.clock());
+        let mut ops = merge::ops(self.db, &before, &todo, &absorbed)?;
+        let event = DomainEvent::TodoMerged {
+            into: todo.clone(),
+            merged: absorbed,
+        };
+        ops.extend(events::ops(self.state, self.db, self.tenant, &event)?);
+        self.state.writes.submit(self.db, ops).await?;
+        events::committed(self.state, self.tenant, &event);
+        Ok(todo)
+    }
+
+    // the removed todo, `None` when there was none
+    pub async fn remove(&self, id: u64) -> Result<Option<Todo>, AppError> {
+        let mut ops = remove_ops(self.db, id)?;
+        let event = self
+            .db
+            .get::<Todo, _>(todo_key(id))?
+            .map(DomainEvent::TodoRemoved);
+        if let Some(event) = &event {
+            ops.extend(events::ops(self.state, self.db, self.tenant, event)?);
+        }
+        self.state.writes.submit(self.db, ops).await?;
+        let Some(event) = event else {
+            return Ok(None);
+        };
+        events::committed(self.state, self.tenant, &event);
+        Ok(event.todo().cloned())
+    }
+
+    // a todo open in the title editor of someone else is not to be changed
+    fn check_lock(&self, id: u64, editor: Option<&str>) -> Result<(), AppError> {
+        if let Some(lock) = editing::lock(self.db, id)? {
+            if Some(lock.holder.as_str()) != editor {
+                return Err(AppError::Invalid(format!(
+                    "{} is editing this todo, your change was not saved.",
+                    lock.name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    // todos that were only waiting for `id`, now that it is done
+    pub fn unblocked_by(&self, id: u64) -> Result<Vec<Todo>, AppError> {
+        Ok(repository::todo::unblocked_by(self.db, id)?)
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::date;
+
+    #[test]
+    fn test_parse_new_todo() -> Result<(), AppError> {
+        let new = NewTodo::parse("Buy milk #errands near:Lidl", "2024-05-01", "", " 15 ")?;
+        assert_eq!(new.title, "Buy milk");
+        assert_eq!(new.tags, ["errands"]);
+        assert_eq!(new.near.as_deref(), Some("Lidl"));
+        assert_eq!(new.due, Some(date!(2024 - 05 - 01)));
+        assert_eq!(new.estimate_minutes, Some(15));
+
+        assert!(NewTodo::parse("  #errands ", "", "", "").is_err());
+        assert!(NewTodo::parse("Buy milk", "tomorrow-ish", "", "").is_err());
+        assert!(NewTodo::parse("Buy milk", "", "", "an hour").is_err());
+        // every field with an error is told at once
+        let Err(AppError::InvalidFields(errors)) = NewTodo::parse(" ", "soon", "", "an hour")
+        else {
+            panic!("expected field errors");
+        };
+        let fields: Vec<&str> = errors.0.iter().map(|(field, _)| field.as_str()).collect();
+        assert_eq!(fields, ["due", "estimate", "title"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_changed_event() {
+        let before = Todo::new(1, "Buy milk".into());
+        let mut renamed = before.clone();
+        renamed.title = "Buy oat milk".into();
+        assert_eq!(changed_event(&before, renamed).name(), "todo.updated");
+
+        let mut done = before.clone();
+        done.set_completed(true);
+        assert_eq!(
+            changed_event(&before, done.clone()).name(),
+            "todo.completed"
+        );
+        // a done todo found not worth doing after all
+        let mut cancelled = done.clone();
+        cancelled.cancel(None);
+        assert_eq!(changed_event(&done, cancelled).name(), "todo.cancelled");
+    }
+}
@@ -0,0 +1,267 @@
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, HeaderValue},
+    response::{IntoResponse, Redirect, Response},
+    routing::{delete, get, post},
+    Extension, Form, Router,
+};
+use maud::{html, Markup};
+use serde::Deserialize;
+
+use crate::{
+    accounts,
+    api::rate_limit::{self, Quota},
+    auth::{policy::Capability, user::CurrentUser, visitor::Visitor},
+    bundle, colors,
+    config::AuthMode,
+    daily_goal::{self, DailyGoal},
+    embed::{self, Embed},
+    error::AppError,
+    export,
+    fields::{self, Schema},
+    import,
+    integrations::{self, Overview},
+    kiosk::{self, Kiosk},
+    lists::{self, Policy},
+    locale::{self, Preferences},
+    migrate, privacy, routes,
+    snapshots::{self, Snapshot},
+    state::AppState,
+    tenant,
+    tenant::Tenant,
+    theme::{self, Theme},
+    views::{
+        self,
+        nav::{self, Nav},
+        panel,
+    },
+};
+
+// what has to be typed to confirm erasing everything
+const DELETE_CONFIRMATION: &str = "delete my data";
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route(routes::Settings::PATH, get(index))
+        .route(routes::ExportMyData::PATH, post(export_my_data))
+        .route(routes::MigrateExport::PATH, post(migrate::export))
+        .route(
+            routes::DeleteAccount::PATH,
+            get(confirm_delete_account).post(delete_account),
+        )
+        .route(routes::Swatches::PATH, post(colors::add_swatch))
+        .route(routes::Swatch::PATH, delete(colors::remove_swatch))
+        .route(routes::Kiosks::PATH, post(kiosk::create_link))
+        .route(routes::KioskRevoke::PATH, delete(kiosk::revoke))
+        .route(routes::Embeds::PATH, post(embed::create_link))
+        .route(routes::EmbedRevoke::PATH, delete(embed::revoke))
+        .route(routes::ThemeSetting::PATH, post(theme::set_theme))
+        .route(routes::LocaleSetting::PATH, post(locale::set_preferences))
+        .route(routes::DailyGoalSetting::PATH, post(daily_goal::set_goal))
+        .route(routes::Integrations::PATH, post(integrations::save))
+        .route(routes::IntegrationTest::PATH, post(integrations::test_fire))
+        .route(
+            routes::CalendarFeedSetting::PATH,
+            post(integrations::enable_calendar).delete(integrations::disable_calendar),
+        )
+        .route(routes::Import::PATH, post(import::start))
+        .route(
+            routes::ImportReview::PATH,
+            get(import::review).post(import::commit),
+        )
+        .route(routes::ListPolicy::PATH, post(lists::save_policy))
+        .route(routes::ListFields::PATH, post(fields::add))
+        .route(routes::ListField::PATH, delete(fields::remove))
+        .route(routes::Snapshots::PATH, post(snapshots::take))
+        .route(routes::SnapshotRestore::PATH, post(snapshots::restore))
+        .route(routes::SnapshotRemove::PATH, delete(snapshots::remove))
+}
+
+// === Components ===
+fn delete_account_html() -> Markup {
+    html! {
+        div id="delete-account" {
+            button class="bg-red-500 hover:bg-red-700 text-white font-bold py-2 px-4 rounded"
+                hx-get=(routes::DeleteAccount::url()) hx-target="#delete-account" hx-swap="outerHTML" { "Delete my data" }
+        }
+    }
+}
+
+fn confirm_delete_account_html() -> Markup {
+    html! {
+        form id="delete-account" class="space-y-2" method="post" action=(routes::DeleteAccount::url()) {
+            p class="text-gray-700" {
+                "This erases every todo in this workspace and cannot be undone. Type "
+                strong { (DELETE_CONFIRMATION) } " to confirm."
+            }
+            input class="w-full rounded p-2" type="text" name="confirm" autocomplete="off" required;
+            button class="bg-red-500 hover:bg-red-700 text-white font-bold py-2 px-4 rounded" type="submit" { "Erase everything" }
+        }
+    }
+}
+
+// everything the settings sections show
+struct Sections {
+    swatches: Vec<colors::Swatch>,
+    kiosks: Vec<(Kiosk, Capability)>,
+    embeds: Vec<Embed>,
+    theme: Theme,
+    preferences: Preferences,
+    daily_goal: DailyGoal,
+    integrations: Overview,
+    snapshots: Vec<Snapshot>,
+    lists: Vec<(String, Policy)>,
+    schemas: Vec<(String, Schema)>,
+    // with `API_RATE_LIMIT`, how much of it the signed-in user used
+    api_quota: Option<Quota>,
+    // the signed-in account with `AUTH_MODE=users`, it can sign out here
+    account: Option<String>,
+}
+
+fn sections_html(public_url: &str, now: u64, sections: &Sections) -> Markup {
+    html! {
+        div class="space-y-6" {
+            (colors::settings_html(&sections.swatches))
+            (kiosk::settings_html(&sections.kiosks))
+            (embed::settings_html(public_url, &sections.embeds))
+            (integrations::settings_html(public_url, &sections.integrations))
+            @if let Some(quota) = &sections.api_quota {
+                (rate_limit::settings_html(quota, now))
+            }
+            (import::settings_html())
+            (export::settings_html())
+            (bundle::settings_html())
+            (migrate::settings_html())
+            (snapshots::settings_html(&sections.snapshots))
+            (lists::settings_html(&sections.lists))
+            (fields::settings_html(&sections.schemas))
+            (theme::settings_html(sections.theme))
+            (locale::settings_html(sections.preferences))
+            (daily_goal::settings_html(sections.daily_goal))
+            (views::motion_settings_html())
+            @if let Some(account) = &sections.account {
+                (accounts::settings_html(account))
+            }
+            section class="bg-white rounded-lg shadow-lg p-6 space-y-4" {
+                h2 class="text-2xl text-gray-700" { "Your data" }
+                form method="post" action=(routes::ExportMyData::url()) {
+                    button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" { "Export my data" }
+                }
+                (delete_account_html())
+            }
+        }
+    }
+}
+
+// === Routes ===
+async fn index(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    user: CurrentUser,
+    Extension(Visitor(visitor)): Extension<Visitor>,
+    headers: HeaderMap,
+) -> Result<Markup, AppError> {
+    let account = match state.config.auth_mode {
+        AuthMode::Users => user.account().map(String::from),
+        _ => None,
+    };
+    let sections = {
+        let db = state.read().await;
+        Sections {
+            swatches: colors::custom(&db.for_tenant(tenant.id())?)?,
+            kiosks: kiosk::with_capabilities(&db, tenant.id())?,
+            embeds: embed::list(&db, tenant.id())?,
+            theme: theme::get(&db.for_tenant(tenant.id())?)?,
+            preferences: locale::get(&db.for_tenant(tenant.id())?)?,
+            daily_goal: daily_goal::get(&db.for_tenant(tenant.id())?, &visitor)?,
+            integrations: Overview::load(&db.for_tenant(tenant.id())?)?,
+            snapshots: snapshots::all(&db.for_tenant(tenant.id())?)?,
+            lists: lists::policies(&db.for_tenant(tenant.id())?)?,
+            schemas: fields::schemas(&db.for_tenant(tenant.id())?)?,
+            api_quota: match state.config.api_rate_limit {
+                Some(limit) => Some(rate_limit::peek(
+                    &db,
+                    &user,
+                    limit,
+                    state.config.api_rate_window,
+                )?),
+                None => None,
+            },
+            account,
+        }
+    };
+    let public_url = &state.config.public_url;
+    let now = state.clock.now_millis() / 1000;
+    if panel::is_panel_request(&headers) {
+        return Ok(panel::panel(
+            "Settings",
+            &Nav::settings(),
+            sections_html(public_url, now, &sections),
+        ));
+    }
+    Ok(views::page(
+        "Settings",
+        html! {
+            (nav::navigation(&Nav::settings()))
+            h1 class="text-4xl text-center text-gray-700 mb-6" { "Settings" }
+            (sections_html(public_url, now, &sections))
+        },
+    ))
+}
+
+async fn export_my_data(
+    State(state): State<AppState>,
+    tenant: Tenant,
+) -> Result<impl IntoResponse, AppError> {
+    let archive = {
+        let db = state.read().await;
+        privacy::export_archive(&db.for_tenant(tenant.id())?)?
+    };
+    let filename = format!("{}-export.zip", tenant.id().unwrap_or("todos"));
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", filename),
+            ),
+        ],
+        archive,
+    ))
+}
+
+async fn confirm_delete_account() -> Markup {
+    confirm_delete_account_html()
+}
+
+#[derive(Deserialize)]
+struct DeleteAccount {
+    confirm: String,
+}
+async fn delete_account(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Form(DeleteAccount { confirm }): Form<DeleteAccount>,
+) -> Result<Response, AppError> {
+    if confirm.trim() != DELETE_CONFIRMATION {
+        return Ok(confirm_delete_account_html().into_response());
+    }
+    let db = state.write().await;
+    match tenant.id() {
+        // a tenant is the account, it goes away as a whole
+        Some(id) => tenant::erase(&db, id)?,
+        None => {
+            let erased = privacy::erase_owned(&db)?;
+            tracing::info!(erased, "erased workspace data on request");
+        }
+    }
+    if headers.contains_key("hx-request") {
+        let mut response = html! {}.into_response();
+        response
+            .headers_mut()
+            .insert("hx-redirect", HeaderValue::from_static("/"));
+        return Ok(response);
+    }
+    Ok(Redirect::to(&routes::Root::url()).into_response())
+}
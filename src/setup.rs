@@ -0,0 +1,271 @@
+use std::sync::atomic::Ordering;
+
+use axum::{
+    extract::{Request, State},
+    http::Method,
+    middleware::Next,
+    response::{IntoResponse, Redirect, Response},
+    Form,
+};
+use maud::{html, Markup};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::password::{self, AdminCredentials},
+    config::Config,
+    db::driver::Db,
+    error::AppError,
+    registration, routes,
+    state::AppState,
+    views,
+};
+
+const MIN_PASSWORD_LEN: usize = 12;
+// written when the wizard is finished, `/setup` is gone from then on
+const INSTANCE_KEY: &str = "instance";
+
+// === Instance ===
+// what the setup wizard asked for
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Instance {
+    pub name: String,
+    // IANA name, e.g. `Europe/Berlin`
+    pub timezone: String,
+}
+
+pub fn instance(db: &Db) -> anyhow::Result<Option<Instance>> {
+    db.get(INSTANCE_KEY)
+}
+
+// === Wizard ===
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Account,
+    Instance,
+    Done,
+}
+
+// Where the wizard stands. It only runs for a fresh database: an `ADMIN_PASSWORD` or todos and
+// tenants from before the wizard existed mean the instance was set up by hand.
+pub fn step(config: &Config, db: &Db) -> anyhow::Result<Step> {
+    if instance(db)?.is_some() || config.admin_password.is_some() {
+        return Ok(Step::Done);
+    }
+    if password::admin(db)?.is_some() {
+        return Ok(Step::Instance);
+    }
+    let has_data = db.iter_keys("todo:").next().is_some() || !db.tenant_ids()?.is_empty();
+    Ok(if has_data { Step::Done } else { Step::Account })
+}
+
+// Send page visits to the wizard while it is unfinished, anything else is left alone.
+pub async fn redirect_until_done(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let pending = state.setup_pending.load(Ordering::Relaxed);
+    let path = request.uri().path();
+    let is_page = request.method() == Method::GET
+        && !path.starts_with(&routes::Setup::url())
+        && !path.starts_with("/api/")
+        && !path.starts_with("/assets/")
+        && path != routes::ThemeCss::url()
+        && path != routes::Manifest::url()
+        && path != routes::AppIcon::url()
+        && path != routes::Events::url();
+    if pending && is_page {
+        return Redirect::to(&routes::Setup::url()).into_response();
+    }
+    next.run(request).await
+}
+
+// === Components ===
+fn setup_page(step: Step, error: Option<&str>) -> Markup {
+    views::page(
+        "Setup",
+        html! {
+            div class="bg-white rounded-lg shadow-lg p-8 max-w-md mx-auto" {
+                @match step {
+                    Step::Account => {
+                        p class="text-gray-400 text-sm" { "Step 1 of 2" }
+                        h1 class="text-2xl text-gray-700 mb-4" { "Create the admin account" }
+                        form class="space-y-4" method="post" action=(routes::Setup::url()) {
+                            input class="w-full rounded p-2 border" type="text" name="username" placeholder="Username" autocomplete="username" autofocus required;
+                            input class="w-full rounded p-2 border" type="password" name="password" placeholder="Password" autocomplete="new-password" minlength=(MIN_PASSWORD_LEN) required;
+                            input class="w-full rounded p-2 border" type="password" name="confirm" placeholder="Repeat password" autocomplete="new-password" required;
+                            (error_html(error))
+                            button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" { "Next" }
+                        }
+                    }
+                    Step::Instance | Step::Done => {
+                        p class="text-gray-400 text-sm" { "Step 2 of 2" }
+                        h1 class="text-2xl text-gray-700 mb-4" { "About this instance" }
+                        form class="space-y-4" method="post" action=(routes::SetupInstance::url()) {
+                            input class="w-full rounded p-2 border" type="text" name="name" placeholder="Instance name" autofocus required;
+                            input id="timezone" class="w-full rounded p-2 border" type="text" name="timezone" placeholder="Europe/Berlin" required;
+                            // the browser knows the timezone better than a guess on the server
+                            script { "document.getElementById('timezone').value = Intl.DateTimeFormat().resolvedOptions().timeZone;" }
+                            label class="flex items-center space-x-2" {
+                                input type="checkbox" name="registration_open" value="true";
+                                span { "Let visitors sign up on their own" }
+                            }
+                            (error_html(error))
+                            button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" { "Finish" }
+                        }
+                    }
+                }
+            }
+        },
+    )
+}
+
+fn error_html(error: Option<&str>) -> Markup {
+    html! {
+        @if let Some(error) = error {
+            p class="text-red-500" { (error) }
+        }
+    }
+}
+
+// === Routes ===
+pub async fn show(State(state): State<AppState>) -> Result<Markup, AppError> {
+    match step(&state.config, &*state.read().await)? {
+        Step::Done => Err(AppError::NotFound),
+        step => Ok(setup_page(step, None)),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CreateAdmin {
+    username: String,
+    password: String,
+    confirm: String,
+}
+pub async fn create_admin(
+    State(mut state): State<AppState>,
+    Form(CreateAdmin {
+        username,
+        password,
+        confirm,
+    }): Form<CreateAdmin>,
+) -> Result<Response, AppError> {
+    let username = username.trim();
+    let error = if username.is_empty() || username.contains(':') {
+        Some("Pick a username without colons.".to_string())
+    } else if password.chars().count() < MIN_PASSWORD_LEN {
+        Some(format!(
+            "The password needs at least {} characters.",
+            MIN_PASSWORD_LEN
+        ))
+    } else if password != confirm {
+        Some("The passwords do not match.".to_string())
+    } else {
+        None
+    };
+    if let Some(error) = error {
+        return Ok(setup_page(Step::Account, Some(&error)).into_response());
+    }
+    let credentials = AdminCredentials::new(username, &password)?;
+    let config = state.config.clone();
+    let db = state.write().await;
+    // checked under the write lock, only the first of two racing submissions wins
+    if step(&config, &db)? != Step::Account {
+        return Err(AppError::NotFound);
+    }
+    password::set_admin(&db, &credentials)?;
+    tracing::info!(account = %username, "created the admin account");
+    Ok(Redirect::to(&routes::Setup::url()).into_response())
+}
+
+#[derive(Deserialize)]
+pub struct SetInstance {
+    name: String,
+    timezone: String,
+    #[serde(default)]
+    registration_open: bool,
+}
+pub async fn set_instance(
+    State(mut state): State<AppState>,
+    Form(SetInstance {
+        name,
+        timezone,
+        registration_open,
+    }): Form<SetInstance>,
+) -> Result<Response, AppError> {
+    let timezone = timezone.trim();
+    let error = if name.trim().is_empty() {
+        Some("Give the instance a name.")
+    } else if !is_timezone_name(timezone) {
+        Some("Use a timezone name like Europe/Berlin or UTC.")
+    } else {
+        None
+    };
+    if let Some(error) = error {
+        return Ok(setup_page(Step::Instance, Some(error)).into_response());
+    }
+    let instance = Instance {
+        name: name.trim().to_string(),
+        timezone: timezone.to_string(),
+    };
+    let config = state.config.clone();
+    let pending = state.setup_pending.clone();
+    let db = state.write().await;
+    if step(&config, &db)? != Step::Instance {
+        return Err(AppError::NotFound);
+    }
+    registration::set_open(&db, registration_open)?;
+    db.insert(INSTANCE_KEY, &instance)?;
+    pending.store(false, Ordering::Relaxed);
+    tracing::info!(instance = %instance.name, "finished setup");
+    Ok(Redirect::to(&routes::Root::url()).into_response())
+}
+
+// `Area/Location` as in the tz database, or `UTC`. Without a copy of the database this only
+// checks the shape.
+fn is_timezone_name(name: &str) -> bool {
+    name == "UTC"
+        || name.split_once('/').is_some_and(|(area, location)| {
+            !area.is_empty()
+                && !location.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '/' | '_' | '-' | '+'))
+        })
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::TestDb;
+
+    #[test]
+    fn test_is_timezone_name() {
+        assert!(is_timezone_name("UTC"));
+        assert!(is_timezone_name("Europe/Berlin"));
+        assert!(is_timezone_name("America/Argentina/Buenos_Aires"));
+        assert!(!is_timezone_name("Berlin"));
+        assert!(!is_timezone_name("Europe/"));
+        assert!(!is_timezone_name("<script>/x"));
+    }
+
+    #[test]
+    fn test_steps() -> anyhow::Result<()> {
+        let db = TestDb::new("setup")?;
+        let config = Config::default();
+
+        assert_eq!(step(&config, &db)?, Step::Account);
+        password::set_admin(&db, &AdminCredentials::new("admin", "a long password")?)?;
+        assert_eq!(step(&config, &db)?, Step::Instance);
+        db.insert(
+            INSTANCE_KEY,
+            &Instance {
+                name: "Home".into(),
+                timezone: "UTC".into(),
+            },
+        )?;
+        assert_eq!(step(&config, &db)?, Step::Done);
+        Ok(())
+    }
+}
@@ -0,0 +1,353 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    response::Response,
+    Form,
+};
+use maud::{html, Markup};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::{
+    bundle::{self, Contents, Report},
+    db::{driver::Db, queue::WriteOp},
+    error::AppError,
+    lists, method_override, repository, routes,
+    state::AppState,
+    tenant::Tenant,
+    views,
+};
+
+// `snapshot:{id}`
+const SNAPSHOT_PREFIX: &str = "snapshot:";
+// the newest kept, taking another one lets go of the oldest
+pub const MAX_SNAPSHOTS: usize = 20;
+const MAX_NAME_LEN: usize = 80;
+const SNAPSHOTS_ID: &str = "snapshots";
+
+fn snapshot_key(id: u64) -> String {
+    format!("{}{}", SNAPSHOT_PREFIX, id)
+}
+
+// === Snapshots ===
+// A named copy of every todo of the workspace, taken before something risky like a big import
+// or a bulk command. It is a bundle without a tag, see `bundle::Contents`, and one list at a time
+// is put back from it under new ids while the rest of the workspace stays as it is.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub id: u64,
+    pub name: String,
+    // unix seconds
+    pub taken_at: u64,
+    pub todos: usize,
+    // the lists it holds todos of, with how many
+    pub lists: BTreeMap<String, usize>,
+    // kept as json like a downloaded bundle, so it still reads after todos change shape
+    contents: String,
+}
+impl Snapshot {
+    pub fn contents(&self) -> Result<Contents> {
+        Ok(serde_json::from_str(&self.contents)?)
+    }
+}
+
+pub fn parse_name(value: &str) -> Result<String, AppError> {
+    let name = value.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+        return Err(AppError::Invalid(format!(
+            "Name the snapshot in 1 to {} characters.",
+            MAX_NAME_LEN
+        )));
+    }
+    Ok(name.to_string())
+}
+
+// every snapshot, the newest first
+pub fn all(db: &Db) -> Result<Vec<Snapshot>> {
+    let mut snapshots = db
+        .iter_prefix::<Snapshot>(SNAPSHOT_PREFIX)?
+        .map(|item| item.map(|(_, snapshot)| snapshot))
+        .collect::<Result<Vec<_>>>()?;
+    snapshots.sort_by_key(|snapshot| std::cmp::Reverse(snapshot.id));
+    Ok(snapshots)
+}
+
+pub fn get(db: &Db, id: u64) -> Result<Option<Snapshot>> {
+    db.get(snapshot_key(id))
+}
+
+// The writes taking a snapshot named `name` of the workspace as it is, and letting go of the
+// oldest ones beyond `MAX_SNAPSHOTS`.
+pub fn take_ops(db: &Db, name: &str) -> Result<(Snapshot, Vec<WriteOp>)> {
+    let todos = repository::todo::all(db)?;
+    let lists = lists::counts(&todos);
+    let contents = bundle::contents_of(db, "", todos)?;
+    let snapshot = Snapshot {
+        id: db.next_id()?,
+        name: name.to_string(),
+        taken_at: contents.exported_at,
+        todos: contents.todos.len(),
+        lists,
+        contents: serde_json::to_string(&contents)?,
+    };
+    let mut ops = vec![WriteOp::Insert {
+        key: snapshot_key(snapshot.id),
+        value: db.encode(&snapshot)?,
+    }];
+    for old in all(db)?.iter().skip(MAX_SNAPSHOTS - 1) {
+        ops.push(WriteOp::Remove {
+            key: snapshot_key(old.id),
+        });
+    }
+    Ok((snapshot, ops))
+}
+
+// The writes putting `list` back as it was in `snapshot`: its todos now are removed and those of
+// the snapshot recreated under new ids, attachments whose files are still here included.
+pub fn restore_list_ops(
+    db: &Db,
+    snapshot: &Snapshot,
+    list: &str,
+) -> Result<(Vec<WriteOp>, Report), AppError> {
+    let mut ops = Vec::new();
+    for todo in repository::todo::all(db)? {
+        if todo.list.as_deref() == Some(list) {
+            ops.extend(repository::todo::remove_ops(db, todo.id)?);
+        }
+    }
+    let mut contents = snapshot.contents()?;
+    contents
+        .todos
+        .retain(|bundled| bundled.todo.list.as_deref() == Some(list));
+    let (restore, report) = bundle::restore_ops(db, contents, true)?;
+    ops.extend(restore);
+    Ok((ops, report))
+}
+
+// === Components ===
+fn taken_on(snapshot: &Snapshot) -> String {
+    OffsetDateTime::from_unix_timestamp(snapshot.taken_at as i64)
+        .map(|at| at.date().to_string())
+        .unwrap_or_default()
+}
+
+fn snapshot_html(snapshot: &Snapshot) -> Markup {
+    let lists = &snapshot.lists;
+    let restore = routes::SnapshotRestore::url(snapshot.id);
+    let remove = routes::SnapshotRemove::url(snapshot.id);
+    html! {
+        li class="space-y-1" {
+            div class="flex items-center space-x-2" {
+                span class="flex-grow text-gray-700" {
+                    (snapshot.name)
+                    span class="ml-2 text-sm t-muted" { (taken_on(snapshot)) ", " (snapshot.todos) " todos" }
+                }
+                form method="post" action=(remove) {
+                    input type="hidden" name=(method_override::METHOD_FIELD) value="DELETE";
+                    button class="text-red-500 hover:text-red-700" type="submit"
+                        hx-delete=(remove) hx-target={ "#" (SNAPSHOTS_ID) } hx-swap="outerHTML" { "Delete" }
+                }
+            }
+            @if !lists.is_empty() {
+                form class="flex items-center space-x-2 text-sm" method="post" action=(restore) hx-post=(restore)
+                    hx-target={ "#" (SNAPSHOTS_ID) } hx-swap="outerHTML"
+                    hx-confirm="Replace the todos of this list with the ones in the snapshot?" {
+                    select class="rounded p-1 border" name="list" aria-label="List to restore" {
+                        @for (list, count) in lists {
+                            option value=(list) { (list) " (" (count) ")" }
+                        }
+                    }
+                    button class="text-blue-500 hover:text-blue-700" type="submit" { "Restore list" }
+                }
+            }
+        }
+    }
+}
+
+pub fn settings_html(snapshots: &[Snapshot]) -> Markup {
+    let url = routes::Snapshots::url();
+    html! {
+        section id=(SNAPSHOTS_ID) class="bg-white rounded-lg shadow-lg p-6 space-y-4" {
+            h2 class="text-2xl text-gray-700" { "Snapshots" }
+            p class="text-gray-600" {
+                "Take a snapshot before a big change, any list can be put back from it later. "
+                "The newest " (MAX_SNAPSHOTS) " are kept."
+            }
+            ul class="space-y-2" {
+                @for snapshot in snapshots { (snapshot_html(snapshot)) }
+            }
+            form class="flex items-center" method="post" action=(url) hx-post=(url) hx-target={ "#" (SNAPSHOTS_ID) } hx-swap="outerHTML" {
+                input class="flex-grow rounded p-2 mr-2 border" type="text" name="name" required maxlength=(MAX_NAME_LEN)
+                    placeholder="Before the spring clean" aria-label="Snapshot name";
+                button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" { "Take snapshot" }
+            }
+        }
+    }
+}
+
+fn refreshed(headers: &HeaderMap, db: &Db, notice: &str) -> Result<Response, AppError> {
+    let fragment = html! {
+        (settings_html(&all(db)?))
+        (views::notice_toast_oob(notice))
+    };
+    Ok(views::fragment_or_redirect(
+        headers,
+        fragment,
+        &routes::Settings::url(),
+    ))
+}
+
+// === Routes ===
+#[derive(Deserialize)]
+pub struct TakeForm {
+    name: String,
+}
+
+pub async fn take(
+    State(mut app_state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Form(TakeForm { name }): Form<TakeForm>,
+) -> Result<Response, AppError> {
+    let name = parse_name(&name)?;
+    let state = app_state.clone();
+    let guard = app_state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    let (snapshot, ops) = take_ops(&db, &name)?;
+    state.writes.submit(&db, ops).await?;
+    tracing::info!(id = snapshot.id, todos = snapshot.todos, "took a snapshot");
+    refreshed(&headers, &db, &format!("Took the snapshot \"{}\".", name))
+}
+
+#[derive(Deserialize)]
+pub struct RestoreForm {
+    list: String,
+}
+
+// Put one list back as it was in a snapshot, the others stay as they are.
+pub async fn restore(
+    State(mut app_state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Path(id): Path<u64>,
+    Form(RestoreForm { list }): Form<RestoreForm>,
+) -> Result<Response, AppError> {
+    let list = lists::parse(&list)?.ok_or_else(|| AppError::Invalid("Pick a list.".into()))?;
+    let state = app_state.clone();
+    let guard = app_state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    let snapshot = get(&db, id)?.ok_or(AppError::NotFound)?;
+    let (ops, report) = restore_list_ops(&db, &snapshot, &list)?;
+    state.writes.submit(&db, ops).await?;
+    tracing::info!(id, %list, todos = report.todos, "restored a list from a snapshot");
+    refreshed(
+        &headers,
+        &db,
+        &format!(
+            "Put back {} todos of {} from \"{}\".",
+            report.todos, list, snapshot.name
+        ),
+    )
+}
+
+pub async fn remove(
+    State(mut app_state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Path(id): Path<u64>,
+) -> Result<Response, AppError> {
+    let state = app_state.clone();
+    let guard = app_state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    let ops = vec![WriteOp::Remove {
+        key: snapshot_key(id),
+    }];
+    state.writes.submit(&db, ops).await?;
+    refreshed(&headers, &db, "Deleted the snapshot.")
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        attachments::{self, Attachment},
+        fixtures::{TestDb, TodoFixture},
+        models::Todo,
+    };
+
+    fn filed(db: &Db, title: &str, list: &str) -> Result<Todo> {
+        let mut todo = TodoFixture::new().titled(title).build(db)?;
+        todo.list = Some(list.into());
+        repository::entity::Repository::new(db).put(&todo)?;
+        Ok(todo)
+    }
+
+    #[test]
+    fn test_restore_list() -> Result<(), AppError> {
+        let db = TestDb::new("snapshots")?;
+        let milk = filed(&db, "Milk", "groceries")?;
+        filed(&db, "Eggs", "groceries")?;
+        filed(&db, "Taxes", "admin")?;
+        let attachment = Attachment {
+            id: db.next_id()?,
+            todo: milk.id,
+            name: "receipt.png".into(),
+            content_type: "image/png".into(),
+            size: 3,
+            created_at: 0,
+        };
+        db.apply_batch(attachments::add_ops(&db, &attachment, b"png")?)?;
+        let (snapshot, ops) = take_ops(&db, "Before the clean up")?;
+        db.apply_batch(ops)?;
+        assert_eq!(snapshot.lists.get("groceries"), Some(&2));
+
+        // the groceries are cleared out and the admin list changes too
+        for todo in repository::todo::all(&db)? {
+            db.apply_batch(repository::todo::remove_ops(&db, todo.id)?)?;
+        }
+        filed(&db, "Bread", "groceries")?;
+        filed(&db, "Pension", "admin")?;
+
+        let snapshot = get(&db, snapshot.id)?.unwrap();
+        let (ops, report) = restore_list_ops(&db, &snapshot, "groceries")?;
+        db.apply_batch(ops)?;
+        assert_eq!(report.todos, 2);
+        assert_eq!(report.attachments_left, 0);
+        let mut titles: Vec<(String, Option<String>)> = repository::todo::all(&db)?
+            .into_iter()
+            .map(|todo| (todo.title, todo.list))
+            .collect();
+        titles.sort();
+        assert_eq!(
+            titles,
+            [
+                ("Eggs".to_string(), Some("groceries".to_string())),
+                ("Milk".to_string(), Some("groceries".to_string())),
+                ("Pension".to_string(), Some("admin".to_string())),
+            ]
+        );
+        // under its new id, with the file it had
+        let milk = repository::todo::all(&db)?
+            .into_iter()
+            .find(|todo| todo.title == "Milk")
+            .unwrap();
+        assert_eq!(attachments::for_todo(&db, milk.id)?[0].name, "receipt.png");
+        Ok(())
+    }
+
+    #[test]
+    fn test_oldest_are_let_go() -> Result<(), AppError> {
+        let db = TestDb::new("snapshots-pruned")?;
+        for index in 0..=MAX_SNAPSHOTS {
+            db.apply_batch(take_ops(&db, &format!("snapshot {}", index))?.1)?;
+        }
+        let snapshots = all(&db)?;
+        assert_eq!(snapshots.len(), MAX_SNAPSHOTS);
+        assert_eq!(snapshots[0].name, format!("snapshot {}", MAX_SNAPSHOTS));
+        Ok(())
+    }
+}
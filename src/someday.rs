@@ -0,0 +1,349 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use axum::{
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    response::Response,
+    Form,
+};
+use maud::{html, Markup};
+use serde::Deserialize;
+
+use crate::{
+    db::driver::Db,
+    error::AppError,
+    history,
+    models::{Status, Todo},
+    repository::{
+        self,
+        entity::Repository,
+        query::{Sort, TodoQuery},
+    },
+    routes,
+    state::AppState,
+    tenant::Tenant,
+    views::{
+        self,
+        nav::{self, Nav},
+    },
+};
+
+const REPORT_ID: &str = "someday-report";
+// when the aging report was last gone through, unix seconds
+const REVIEWED_KEY: &str = "someday:reviewed";
+// a month, as far as aging goes
+const MONTH_SECS: u64 = 30 * 24 * 60 * 60;
+const MAX_MONTHS: u32 = 120;
+
+fn now(db: &Db) -> u64 {
+    db.clock().now_millis() / 1000
+}
+
+// === Someday ===
+// Todos put aside for some day, out of the list, the board and the weekly review until revived.
+// Moving a todo here touches it, so its age counts from then. Once a month the ones put aside
+// too long ago come up again, to be revived or let go of.
+
+// every someday todo, put aside longest ago first
+pub fn all(db: &Db) -> Result<Vec<Todo>> {
+    TodoQuery::new()
+        .status(Status::Someday)
+        .sort(Sort::Updated)
+        .list(db)
+}
+
+// someday todos put aside over `months` months ago, oldest first
+pub fn aged(db: &Db, months: u32) -> Result<Vec<Todo>> {
+    let cutoff = now(db).saturating_sub(months as u64 * MONTH_SECS);
+    TodoQuery::new()
+        .status(Status::Someday)
+        .updated_before(cutoff)
+        .sort(Sort::Updated)
+        .list(db)
+}
+
+// whole months between `then` and `now`
+fn months_ago(now: u64, then: u64) -> u64 {
+    now.saturating_sub(then) / MONTH_SECS
+}
+
+pub fn mark_reviewed(db: &Db) -> Result<()> {
+    db.insert(REVIEWED_KEY, &now(db))
+}
+
+// The report is due when something has aged and it was last gone through over a month ago.
+pub fn is_report_due(db: &Db, months: u32) -> Result<bool> {
+    let reviewed: Option<u64> = db.get(REVIEWED_KEY)?;
+    if reviewed.is_some_and(|at| at + MONTH_SECS > now(db)) {
+        return Ok(false);
+    }
+    Ok(!aged(db, months)?.is_empty())
+}
+
+// the ids of the checked `todo-{id}` boxes of the report
+fn selected(form: &HashMap<String, String>) -> HashSet<u64> {
+    form.keys()
+        .filter_map(|key| key.strip_prefix("todo-")?.parse().ok())
+        .collect()
+}
+
+// === Components ===
+fn item_html(todo: &Todo, now: u64, checked: bool) -> Markup {
+    let name = format!("todo-{}", todo.id);
+    html! {
+        li class="flex items-center py-1" {
+            label class="flex-grow" {
+                input class="mr-2" type="checkbox" name=(name) value="on" checked[checked];
+                a class="hover:underline" href=(routes::TodoDetail::url(todo.id)) { (todo.title) }
+            }
+            span class="text-xs t-muted" {
+                @match months_ago(now, todo.updated_at) {
+                    0 => { "put aside this month" }
+                    1 => { "put aside a month ago" }
+                    months => { "put aside " (months) " months ago" }
+                }
+            }
+        }
+    }
+}
+
+// The aged todos, checked so the report handles them at once, then the rest unchecked.
+// `skip` are todos just handled, which a queued write may not have reached yet.
+fn report_html(db: &Db, months: u32, skip: &HashSet<u64>) -> Result<Markup, AppError> {
+    let now = now(db);
+    let cutoff = now.saturating_sub(months as u64 * MONTH_SECS);
+    let (aged, recent): (Vec<Todo>, Vec<Todo>) = all(db)?
+        .into_iter()
+        .filter(|todo| !skip.contains(&todo.id))
+        .partition(|todo| todo.updated_at < cutoff);
+    let url = routes::Someday::url();
+    Ok(html! {
+        section id=(REPORT_ID) class="bg-white rounded-lg shadow-lg p-6 space-y-4" {
+            @if aged.is_empty() && recent.is_empty() {
+                p class="text-gray-700" { "Nothing is put aside for some day." }
+            } @else {
+                form class="space-y-4" method="post" action=(url) hx-post=(url) hx-target={ "#" (REPORT_ID) } hx-swap="outerHTML" {
+                    input type="hidden" name="months" value=(months);
+                    @if aged.is_empty() {
+                        p class="text-gray-700" { "Nothing has been put aside for over " (months) " months." }
+                    } @else {
+                        h2 class="text-2xl text-gray-700" { "Put aside over " (months) " months ago" }
+                        p class="text-gray-600" { "Bring back what still matters, let go of the rest." }
+                        ul { @for todo in &aged { (item_html(todo, now, true)) } }
+                    }
+                    @if !recent.is_empty() {
+                        h2 class="text-xl text-gray-700" { "More recently" }
+                        ul { @for todo in &recent { (item_html(todo, now, false)) } }
+                    }
+                    div class="flex space-x-2" {
+                        button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" name="action" value="revive" { "Revive" }
+                        button class="bg-red-500 hover:bg-red-700 text-white font-bold py-2 px-4 rounded" type="submit" name="action" value="delete"
+                            hx-confirm="Delete the checked todos for good?" { "Delete" }
+                        button class="bg-gray-500 hover:bg-gray-700 text-white font-bold py-2 px-4 rounded" type="submit" name="action" value="keep" { "Keep them all" }
+                    }
+                }
+            }
+        }
+    })
+}
+
+// the months a report looks back, from `?months=`
+fn months_html(months: u32) -> Markup {
+    html! {
+        form class="flex items-center space-x-2 mb-4" method="get" action=(routes::Someday::url()) {
+            label for="someday-months" class="text-gray-700" { "Older than" }
+            input id="someday-months" class="w-20 rounded p-2 border" type="number" name="months" min="1" max=(MAX_MONTHS) value=(months);
+            span class="text-gray-700" { "months" }
+            button class="text-blue-500 hover:text-blue-700" type="submit" { "Show" }
+        }
+    }
+}
+
+// === Routes ===
+#[derive(Deserialize)]
+pub struct ReportQuery {
+    months: Option<u32>,
+}
+fn validate_months(months: Option<u32>, default: u32) -> Result<u32, AppError> {
+    let months = months.unwrap_or(default);
+    if !(1..=MAX_MONTHS).contains(&months) {
+        return Err(AppError::Invalid(format!(
+            "Look back between 1 and {} months.",
+            MAX_MONTHS
+        )));
+    }
+    Ok(months)
+}
+
+pub async fn index(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    Query(ReportQuery { months }): Query<ReportQuery>,
+) -> Result<Markup, AppError> {
+    let months = validate_months(months, state.config.someday_stale_months)?;
+    let db = state.read().await.for_tenant(tenant.id())?;
+    Ok(views::page(
+        "Someday",
+        html! {
+            (nav::navigation(&Nav::someday()))
+            h1 class="text-4xl text-center text-gray-700 mb-6" { "Someday" }
+            (months_html(months))
+            (report_html(&db, months, &HashSet::new())?)
+        },
+    ))
+}
+
+// Put a todo aside, it leaves the list it was on.
+pub async fn move_to_someday(
+    State(mut app_state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Path(id): Path<u64>,
+) -> Result<Response, AppError> {
+    let state = app_state.clone();
+    let guard = app_state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    let todos = Repository::<Todo>::new(&db);
+    let mut todo = todos.get(id)?.ok_or(AppError::NotFound)?;
+    let mut ops = history::record_ops(&db, &todo)?;
+    todo.set_status(Status::Someday);
+    todo.touch(&**db.clock());
+    ops.extend(todos.put_ops(&todo)?);
+    state.writes.submit(&db, ops).await?;
+    Ok(views::fragment_or_redirect(
+        &headers,
+        views::notice_toast_oob(&format!("\"{}\" is put aside for some day.", todo.title)),
+        &routes::Root::url(),
+    ))
+}
+
+// Revive or delete the checked todos of the report, or keep them all. Any of them goes
+// through the report for the month.
+pub async fn act(
+    State(mut app_state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Form(form): Form<HashMap<String, String>>,
+) -> Result<Response, AppError> {
+    let state = app_state.clone();
+    let months = validate_months(
+        form.get("months").and_then(|months| months.parse().ok()),
+        state.config.someday_stale_months,
+    )?;
+    let guard = app_state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    let todos = Repository::<Todo>::new(&db);
+    let action = form.get("action").map(String::as_str).unwrap_or("keep");
+    if !["revive", "delete", "keep"].contains(&action) {
+        return Err(AppError::Invalid(format!(
+            "`{}` is not an action, use `revive`, `delete` or `keep`",
+            action
+        )));
+    }
+    let mut handled = HashSet::new();
+    let mut ops = Vec::new();
+    if action != "keep" {
+        for id in selected(&form) {
+            // only what is still put aside, a todo revived meanwhile stays where it is
+            let Some(mut todo) = todos.get(id)? else {
+                continue;
+            };
+            if todo.status != Status::Someday {
+                continue;
+            }
+            if action == "revive" {
+                ops.extend(history::record_ops(&db, &todo)?);
+                todo.set_status(Status::Backlog);
+                todo.touch(&**db.clock());
+                ops.extend(todos.put_ops(&todo)?);
+            } else {
+                ops.extend(repository::todo::remove_ops(&db, id)?);
+            }
+            handled.insert(id);
+        }
+    }
+    state.writes.submit(&db, ops).await?;
+    mark_reviewed(&db)?;
+    let notice = match action {
+        "revive" => format!("Revived {} todos.", handled.len()),
+        "delete" => format!("Deleted {} todos.", handled.len()),
+        _ => "Kept everything for some day.".to_string(),
+    };
+    let fragment = html! {
+        (report_html(&db, months, &handled)?)
+        (views::notice_toast_oob(&notice))
+    };
+    Ok(views::fragment_or_redirect(
+        &headers,
+        fragment,
+        &format!("{}?months={}", routes::Someday::url(), months),
+    ))
+}
+
+// the header badge once a month, lazily loaded by the navigation
+pub async fn nudge(State(state): State<AppState>, tenant: Tenant) -> Result<Markup, AppError> {
+    let db = state.read().await.for_tenant(tenant.id())?;
+    if !is_report_due(&db, state.config.someday_stale_months)? {
+        return Ok(html! {});
+    }
+    Ok(html! {
+        li {
+            a class="text-xs font-bold t-warning rounded px-2 py-1" href=(routes::Someday::url()) { "Someday report" }
+        }
+    })
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::TestDb;
+
+    fn put_aside(db: &Db, id: u64, updated_at: u64) -> Result<()> {
+        let mut todo = Todo::new(id, format!("todo {}", id));
+        todo.status = Status::Someday;
+        todo.updated_at = updated_at;
+        db.insert(repository::todo::todo_key(id), &todo)
+    }
+
+    #[test]
+    fn test_aged_and_due() -> Result<()> {
+        let db = TestDb::new("someday")?;
+        let now = now(&db);
+        put_aside(&db, 1, now - 4 * MONTH_SECS)?;
+        put_aside(&db, 2, now - MONTH_SECS)?;
+        db.insert(repository::todo::todo_key(3), &Todo::new(3, "open".into()))?;
+
+        let ids = |todos: Vec<Todo>| todos.iter().map(|todo| todo.id).collect::<Vec<_>>();
+        assert_eq!(ids(all(&db)?), [1, 2]);
+        assert_eq!(ids(aged(&db, 3)?), [1]);
+        assert!(aged(&db, 6)?.is_empty());
+        // someday todos are left out of everything else
+        assert_eq!(ids(TodoQuery::new().list(&db)?), [3]);
+
+        assert!(is_report_due(&db, 3)?);
+        assert!(!is_report_due(&db, 6)?);
+        mark_reviewed(&db)?;
+        assert!(!is_report_due(&db, 3)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_selected_and_ages() {
+        let form: HashMap<String, String> = [
+            ("todo-4", "on"),
+            ("todo-x", "on"),
+            ("action", "revive"),
+            ("months", "3"),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+        assert_eq!(selected(&form), HashSet::from([4]));
+        assert_eq!(months_ago(MONTH_SECS * 5 + 10, 10), 5);
+        assert_eq!(months_ago(10, 20), 0);
+        assert!(validate_months(None, 3).is_ok());
+        assert!(validate_months(Some(0), 3).is_err());
+    }
+}
@@ -0,0 +1,206 @@
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Response},
+};
+use maud::{html, Markup};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    db::driver::Db,
+    error::AppError,
+    extract::FormOrJson,
+    models::Todo,
+    repository::query::{Sort, SortKey},
+    routes,
+    state::AppState,
+    tenant::Tenant,
+};
+
+// the lists listen for it and fetch themselves again
+pub const SORTED_EVENT: &str = "sorted";
+
+// === Views ===
+// the pages listing todos, each remembers its own order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum View {
+    List,
+    Board,
+}
+impl View {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            View::List => "list",
+            View::Board => "board",
+        }
+    }
+}
+impl FromStr for View {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, AppError> {
+        match s {
+            "list" => Ok(View::List),
+            "board" => Ok(View::Board),
+            _ => Err(AppError::NotFound),
+        }
+    }
+}
+
+// === Orders ===
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Order {
+    #[default]
+    Created,
+    Due,
+    // least recently touched first
+    Updated,
+    Alphabetical,
+}
+impl Order {
+    pub const ALL: [Order; 4] = [
+        Order::Created,
+        Order::Due,
+        Order::Updated,
+        Order::Alphabetical,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Order::Created => "created",
+            Order::Due => "due",
+            Order::Updated => "updated",
+            Order::Alphabetical => "alphabetical",
+        }
+    }
+    pub fn label(&self) -> &'static str {
+        match self {
+            Order::Created => "Oldest first",
+            Order::Due => "Due date",
+            Order::Updated => "Least recently changed",
+            Order::Alphabetical => "A to Z",
+        }
+    }
+}
+impl FromStr for Order {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|order| order.as_str() == s)
+            .ok_or_else(|| anyhow!("cannot sort by `{}`", s))
+    }
+}
+
+// per workspace, `sort:{view}`
+fn key(view: View) -> String {
+    format!("sort:{}", view.as_str())
+}
+pub fn get(db: &Db, view: View) -> anyhow::Result<Order> {
+    Ok(db.get(key(view))?.unwrap_or_default())
+}
+pub fn set(db: &Db, view: View, order: Order) -> anyhow::Result<()> {
+    db.insert(key(view), &order)
+}
+
+// how a `TodoQuery` lists todos in `order`
+pub fn sort(order: Order) -> Sort {
+    match order {
+        Order::Created => Sort::Created,
+        Order::Due => Sort::Due,
+        Order::Updated => Sort::Updated,
+        Order::Alphabetical => Sort::Title,
+    }
+}
+
+// Put `todos` in `order`, ties keep creation order.
+pub fn apply(todos: &mut [Todo], order: Order) {
+    let sort = sort(order);
+    todos.sort_by_cached_key(|todo| SortKey::of(todo, sort));
+}
+
+// === Components ===
+// Picking an order saves it and has the lists of the page fetch themselves again.
+pub fn dropdown_html(view: View, current: Order) -> Markup {
+    html! {
+        form class="flex justify-end items-center text-sm text-gray-600" method="post" action=(routes::ViewSort::url(view.as_str()))
+            hx-post=(routes::ViewSort::url(view.as_str())) hx-trigger="change" hx-swap="outerHTML" {
+            label {
+                "Sort by "
+                select class="rounded p-1 border ml-1" name="order" {
+                    @for order in Order::ALL {
+                        option value=(order.as_str()) selected[order == current] { (order.label()) }
+                    }
+                }
+            }
+            noscript {
+                button class="ml-2 text-blue-500 hover:text-blue-700" type="submit" { "Sort" }
+            }
+        }
+    }
+}
+
+// === Routes ===
+#[derive(Deserialize)]
+pub struct SetOrder {
+    order: String,
+}
+pub async fn set_order(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    Path(view): Path<String>,
+    FormOrJson(SetOrder { order }): FormOrJson<SetOrder>,
+) -> Result<Response, AppError> {
+    let view: View = view.parse()?;
+    let order: Order = order
+        .parse()
+        .map_err(|err: anyhow::Error| AppError::Invalid(err.to_string()))?;
+    set(&state.write().await.for_tenant(tenant.id())?, view, order)?;
+    Ok(([("hx-trigger", SORTED_EVENT)], dropdown_html(view, order)).into_response())
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use time::macros::date;
+
+    use super::*;
+    use crate::fixtures::TestDb;
+
+    #[test]
+    fn test_apply() {
+        let mut todos: Vec<Todo> = ["water plants", "Buy milk", "call mum"]
+            .into_iter()
+            .enumerate()
+            .map(|(id, title)| Todo::new(id as u64, title.into()))
+            .collect();
+        todos[2].due = Some(date!(2024 - 03 - 01));
+        todos[0].due = Some(date!(2024 - 03 - 05));
+        let ids = |todos: &[Todo]| todos.iter().map(|todo| todo.id).collect::<Vec<_>>();
+
+        apply(&mut todos, Order::Alphabetical);
+        assert_eq!(ids(&todos), [1, 2, 0]);
+        apply(&mut todos, Order::Due);
+        assert_eq!(ids(&todos), [2, 0, 1]);
+        apply(&mut todos, Order::Created);
+        assert_eq!(ids(&todos), [0, 1, 2]);
+        for order in Order::ALL {
+            assert_eq!(order.as_str().parse::<Order>().unwrap(), order);
+        }
+        assert!("priority".parse::<Order>().is_err());
+    }
+
+    #[test]
+    fn test_stored_per_view() -> anyhow::Result<()> {
+        let db = TestDb::new("sorting")?;
+        assert_eq!(get(&db, View::List)?, Order::Created);
+        set(&db, View::List, Order::Alphabetical)?;
+        set(&db, View::Board, Order::Due)?;
+        assert_eq!(get(&db, View::List)?, Order::Alphabetical);
+        assert_eq!(get(&db, View::Board)?, Order::Due);
+        Ok(())
+    }
+}
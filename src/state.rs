@@ -0,0 +1,210 @@
+use std::{
+    path::Path,
+    sync::{atomic::AtomicBool, Arc},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use rand::RngCore;
+use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::{
+    analytics::{self, Counter},
+    assistant::{LanguageModel, OpenAiCompatible},
+    audit::AuditSink,
+    clock::{Clock, SystemClock},
+    config::Config,
+    db::{
+        codec::{Codec, Keyring},
+        driver::Db,
+        lock, migrations,
+        queue::WriteQueue,
+        ttl,
+    },
+    diff::RenderCache,
+    events::{Connections, Events},
+    geocode::{Geocoder, Nominatim},
+    lists, maintenance,
+    presence::Presence,
+    previews::Previews,
+    recorder::Recorder,
+    reload::LiveConfig,
+    retention, rollup, scheduled,
+    scheduler::Scheduler,
+    setup::{self, Step},
+    subscriptions, tags,
+    tasks::TaskQueue,
+    voice::{Transcriber, WhisperHttp},
+};
+
+// how often keys inserted with a ttl are checked for expiry
+const SWEEP_INTERVAL: Duration = Duration::from_secs(30);
+
+// === App State ===
+#[derive(Debug, Clone)]
+pub struct AppState {
+    state: Arc<RwLock<Db>>,
+    // as read at startup, the reloadable settings are read from `live`
+    pub config: Arc<Config>,
+    // the configuration with the latest reloadable settings, see `reload`
+    pub live: LiveConfig,
+    // signs cookies, see `auth::sign`
+    pub secret_key: Arc<Vec<u8>>,
+    pub writes: WriteQueue,
+    // resolves todo locations to coordinates, see `NOMINATIM_URL`
+    pub geocoder: Option<Arc<dyn Geocoder>>,
+    // turns voice quick-adds into text, see `TRANSCRIPTION_URL`
+    pub transcriber: Option<Arc<dyn Transcriber>>,
+    // suggests steps for todos, see `ASSISTANT_URL`
+    pub assistant: Option<Arc<dyn LanguageModel>>,
+    // fragments pushed to open pages, see `events::stream`
+    pub events: Events,
+    // the event streams open, see `SSE_MAX_CONNECTIONS`
+    pub connections: Connections,
+    // who has a page of each workspace open, see `presence`
+    pub presence: Presence,
+    // the lists sessions last saw, see `LIST_DIFFING`
+    pub renders: RenderCache,
+    // outbound work retried in the background, see `tasks`
+    pub tasks: TaskQueue,
+    // fetches link previews in the background, see `LINK_PREVIEWS`
+    pub previews: Option<Previews>,
+    // nightly and periodic jobs, see `/admin/jobs`
+    pub scheduler: Scheduler,
+    // read-only for maintenance, switched on the admin page, see `maintenance`
+    pub maintenance: Arc<AtomicBool>,
+    // the first-run wizard is unfinished, see `setup`
+    pub setup_pending: Arc<AtomicBool>,
+    // recent requests for debugging, see `DEV_MODE`
+    pub recorder: Option<Recorder>,
+    // page views waiting to be added to the day, see `ANALYTICS`
+    pub analytics: Option<Counter>,
+    // domain events appended to a file, see `AUDIT_LOG`
+    pub audit: Option<AuditSink>,
+    // the time handlers go by, the database carries the same one, see `clock`
+    pub clock: Arc<dyn Clock>,
+}
+impl AppState {
+    pub fn new(config: &Config) -> Result<Self> {
+        Self::new_with_clock(config, Arc::new(SystemClock))
+    }
+    pub fn new_with_clock(config: &Config, clock: Arc<dyn Clock>) -> Result<Self> {
+        let db = open_db(config)?.with_clock(clock.clone());
+        let setup_pending = setup::step(config, &db)? != Step::Done;
+        let maintenance = maintenance::stored(&db)?;
+        let state = Arc::new(RwLock::new(db));
+        let writes = WriteQueue::spawn(state.clone(), config.write_mode);
+        ttl::spawn_sweeper(state.clone(), SWEEP_INTERVAL);
+        let events = Events::new();
+        let mut jobs = vec![
+            rollup::job(),
+            scheduled::job(events.clone()),
+            subscriptions::job(events.clone()),
+            lists::archive_job(events.clone()),
+        ];
+        if let Some(idle_for) = config.tag_archive_after {
+            jobs.push(tags::archive_job(events.clone(), idle_for));
+        }
+        let analytics = config.analytics.then(Counter::default);
+        if let Some(counter) = &analytics {
+            jobs.push(analytics::flush_job(counter.clone()));
+        }
+        let recorder = config.dev_mode.then(|| Recorder::new(clock.clone()));
+        jobs.extend(retention::jobs(config, recorder.as_ref()));
+        let scheduler = Scheduler::spawn(state.clone(), jobs);
+        let audit = match &config.audit_log {
+            Some(path) => Some(AuditSink::open(path, config.audit_log_max_bytes)?),
+            None => None,
+        };
+        let geocoder = match &config.nominatim_url {
+            Some(url) => Some(Arc::new(Nominatim::new(url)?) as Arc<dyn Geocoder>),
+            None => None,
+        };
+        let transcriber = match &config.transcription_url {
+            Some(url) => Some(Arc::new(WhisperHttp::new(url)?) as Arc<dyn Transcriber>),
+            None => None,
+        };
+        let assistant = match &config.assistant_url {
+            Some(url) => Some(Arc::new(OpenAiCompatible::new(
+                url,
+                &config.assistant_model,
+                config.assistant_api_key.clone(),
+            )?) as Arc<dyn LanguageModel>),
+            None => None,
+        };
+        let presence = Presence::new(events.clone());
+        let tasks = TaskQueue::spawn(state.clone(), events.clone(), config.queue_workers)?;
+        let previews = config.link_previews.then(|| Previews::new(tasks.clone()));
+        Ok(Self {
+            state,
+            config: Arc::new(config.clone()),
+            live: LiveConfig::new(config.clone()),
+            secret_key: Arc::new(secret_key(config)?),
+            writes,
+            geocoder,
+            transcriber,
+            assistant,
+            events,
+            connections: Connections::new(config.sse_max_connections, config.sse_max_per_user),
+            presence,
+            renders: RenderCache::default(),
+            tasks,
+            previews,
+            scheduler,
+            maintenance: Arc::new(AtomicBool::new(maintenance)),
+            setup_pending: Arc::new(AtomicBool::new(setup_pending)),
+            recorder,
+            analytics,
+            audit,
+            clock,
+        })
+    }
+
+    // borrow immutable state
+    pub async fn read(&self) -> RwLockReadGuard<'_, Db> {
+        self.state.read().await
+    }
+    // borrow mutable state
+    pub async fn write(&mut self) -> RwLockWriteGuard<'_, Db> {
+        self.state.write().await
+    }
+    // Put `db` in place of the live database for every handler and background task, returns
+    // the one it replaced.
+    pub async fn swap_db(&self, db: Db) -> Db {
+        let db = db.with_clock(self.clock.clone());
+        std::mem::replace(&mut *self.state.write().await, db)
+    }
+}
+
+// open the database with the codec described by `config`, migrated to the latest version
+pub fn open_db(config: &Config) -> Result<Db> {
+    let db = open_db_unmigrated(config)?;
+    migrations::run(&db)?;
+    Ok(db)
+}
+// the database as it is on disk, for looking at it before migrations run
+pub fn open_db_unmigrated(config: &Config) -> Result<Db> {
+    open_db_at(config, &config.db_path())
+}
+// Another database with the same codec, e.g. a backup. One held by another process is waited
+// for as long as `DB_LOCK_WAIT_SECS` says.
+pub fn open_db_at(config: &Config, path: &Path) -> Result<Db> {
+    let mut codec = Codec::new();
+    if let Some(path) = &config.encryption_keyfile {
+        codec = codec.with_keyring(Keyring::load(path)?);
+    }
+    if let Some(threshold) = config.compression_threshold {
+        codec = codec.with_compression(threshold);
+    }
+    lock::retry(config.db_lock_wait, || Db::open(path, codec.clone()))
+}
+
+fn secret_key(config: &Config) -> Result<Vec<u8>> {
+    if let Some(key) = &config.secret_key {
+        return hex::decode(key).context("SECRET_KEY must be hex encoded");
+    }
+    tracing::warn!("SECRET_KEY is not set, signed cookies will not survive a restart");
+    let mut key = vec![0; 32];
+    rand::thread_rng().fill_bytes(&mut key);
+    Ok(key)
+}
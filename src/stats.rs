@@ -0,0 +1,413 @@
+use std::collections::{BTreeMap, HashSet};
+
+use axum::extract::State;
+use maud::{html, Markup};
+use time::{Date, Duration};
+
+use crate::{
+    db::driver::Db,
+    error::AppError,
+    models::Todo,
+    repository::query::TodoQuery,
+    rollup::{self, Rollup},
+    routes,
+    state::AppState,
+    tenant::Tenant,
+    views::{
+        self,
+        nav::{self, Nav},
+    },
+};
+
+// days shown in the burndown
+const BURNDOWN_DAYS: i64 = 30;
+const CHART_WIDTH: usize = 300;
+const CHART_HEIGHT: usize = 80;
+
+// estimated work due on one day
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DayLoad {
+    pub todos: usize,
+    pub estimated: u32,
+}
+
+// `95` as `1h 35m`
+pub fn format_minutes(minutes: u32) -> String {
+    match (minutes / 60, minutes % 60) {
+        (0, minutes) => format!("{}m", minutes),
+        (hours, 0) => format!("{}h", hours),
+        (hours, minutes) => format!("{}h {}m", hours, minutes),
+    }
+}
+
+// open todos with a due date, summed per day
+pub fn per_day(todos: &[Todo]) -> BTreeMap<Date, DayLoad> {
+    let mut days: BTreeMap<Date, DayLoad> = BTreeMap::new();
+    for todo in todos.iter().filter(|todo| !todo.completed) {
+        if let Some(due) = todo.due {
+            let day = days.entry(due).or_default();
+            day.todos += 1;
+            day.estimated += todo.estimate_minutes.unwrap_or_default();
+        }
+    }
+    days
+}
+
+// A warning toast when `todo` is due today and puts today over the configured capacity. The
+// todo is counted as given, its write may still be queued.
+pub fn capacity_warning(
+    state: &AppState,
+    db: &Db,
+    todo: &Todo,
+) -> Result<Option<Markup>, AppError> {
+    let (Some(capacity), Some(due)) = (state.config.daily_capacity_minutes, todo.due) else {
+        return Ok(None);
+    };
+    if due != state.clock.now().date() {
+        return Ok(None);
+    }
+    let mut todos: Vec<Todo> = TodoQuery::new()
+        .due_between(due, due)
+        .list(db)?
+        .into_iter()
+        .filter(|other| other.id != todo.id)
+        .collect();
+    todos.push(todo.clone());
+    let today = per_day(&todos).get(&due).copied().unwrap_or_default();
+    if today.estimated <= capacity {
+        return Ok(None);
+    }
+    Ok(Some(views::warning_toast_oob(&format!(
+        "Today holds {} of work, over your {} capacity.",
+        format_minutes(today.estimated),
+        format_minutes(capacity)
+    ))))
+}
+
+// Days in a row with at least one todo done, up to `today`. A streak still counts while today
+// has nothing done yet, it only breaks once a whole day passes.
+pub fn streak(rollups: &[Rollup], today: &Rollup) -> usize {
+    let active: HashSet<Date> = rollups
+        .iter()
+        .chain([today])
+        .filter(|rollup| rollup.completed > 0)
+        .map(|rollup| rollup.day)
+        .collect();
+    let mut day = if active.contains(&today.day) {
+        Some(today.day)
+    } else {
+        today.day.previous_day()
+    };
+    let mut days = 0;
+    while let Some(current) = day.filter(|day| active.contains(day)) {
+        days += 1;
+        day = current.previous_day();
+    }
+    days
+}
+
+// the finished todos of a tag or list, told apart by how they ended
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Finished {
+    pub done: usize,
+    pub cancelled: usize,
+}
+impl Finished {
+    // the share of them cancelled, in percent
+    pub fn cancel_rate(self) -> usize {
+        (self.cancelled * 100)
+            .checked_div(self.done + self.cancelled)
+            .unwrap_or(0)
+    }
+}
+
+fn finished_per<'a>(
+    todos: &'a [Todo],
+    groups: impl Fn(&'a Todo) -> Vec<&'a str>,
+) -> BTreeMap<String, Finished> {
+    let mut finished: BTreeMap<String, Finished> = BTreeMap::new();
+    for todo in todos.iter().filter(|todo| todo.completed) {
+        for group in groups(todo) {
+            let entry = finished.entry(group.to_string()).or_default();
+            if todo.is_cancelled() {
+                entry.cancelled += 1;
+            } else {
+                entry.done += 1;
+            }
+        }
+    }
+    finished
+}
+pub fn finished_per_tag(todos: &[Todo]) -> BTreeMap<String, Finished> {
+    finished_per(todos, |todo| todo.tags.iter().map(String::as_str).collect())
+}
+pub fn finished_per_list(todos: &[Todo]) -> BTreeMap<String, Finished> {
+    finished_per(todos, |todo| todo.list.as_deref().into_iter().collect())
+}
+
+// the rollups of the last `BURNDOWN_DAYS` and one for today as it stands
+fn recent(db: &Db) -> Result<(Vec<Rollup>, Rollup), AppError> {
+    let today = db.clock().now().date();
+    let rollups = rollup::since(db, today - Duration::days(BURNDOWN_DAYS))?;
+    let now = rollup::compute(&TodoQuery::new().list(db)?, today);
+    Ok((rollups, now))
+}
+
+// === Components ===
+// Open todos per day as a line, scaled to the busiest day.
+pub fn burndown_svg(series: &[Rollup]) -> Markup {
+    let max = series
+        .iter()
+        .map(|rollup| rollup.open)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let step = CHART_WIDTH / series.len().saturating_sub(1).max(1);
+    let points: Vec<String> = series
+        .iter()
+        .enumerate()
+        .map(|(index, rollup)| {
+            format!(
+                "{},{}",
+                index * step,
+                CHART_HEIGHT - rollup.open * CHART_HEIGHT / max
+            )
+        })
+        .collect();
+    html! {
+        svg class="w-full h-24" viewBox={ "0 0 " (CHART_WIDTH) " " (CHART_HEIGHT) } preserveAspectRatio="none"
+            role="img" aria-label="Open todos per day" {
+            polyline points=(points.join(" ")) fill="none" stroke="currentColor" stroke-width="2" {}
+        }
+    }
+}
+
+// The tags or lists whose todos get cancelled most, the most cancelled first.
+fn cancellations_html(heading: &str, finished: &BTreeMap<String, Finished>) -> Markup {
+    let mut rows: Vec<(&String, &Finished)> = finished.iter().collect();
+    rows.sort_by_key(|(_, finished)| std::cmp::Reverse(finished.cancel_rate()));
+    html! {
+        h2 class="text-2xl text-gray-700" { "Cancelled per " (heading) }
+        @if rows.is_empty() {
+            p class="text-gray-500" { "Nothing with a " (heading) " is finished yet." }
+        } @else {
+            table class="w-full text-left text-gray-700" {
+                thead {
+                    tr { th { "Name" } th { "Done" } th { "Cancelled" } th { "Rate" } }
+                }
+                tbody {
+                    @for (name, finished) in rows {
+                        tr {
+                            td { (name) }
+                            td { (finished.done) }
+                            td { (finished.cancelled) }
+                            td { (finished.cancel_rate()) "%" }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// === Routes ===
+// the summary next to the list, loaded after the page
+pub async fn sidebar(State(state): State<AppState>, tenant: Tenant) -> Result<Markup, AppError> {
+    let db = state.read().await.for_tenant(tenant.id())?;
+    let todos = TodoQuery::new().list(&db)?;
+    let (rollups, today) = recent(&db)?;
+    let open = todos.iter().filter(|todo| !todo.completed).count();
+    let cancelled = todos.iter().filter(|todo| todo.is_cancelled()).count();
+    let estimated: u32 = todos
+        .iter()
+        .filter(|todo| !todo.completed)
+        .filter_map(|todo| todo.estimate_minutes)
+        .sum();
+    Ok(html! {
+        section id="stats-sidebar" class="bg-white rounded-lg shadow-lg p-4 space-y-2" {
+            h2 class="text-xl text-gray-700" { "At a glance" }
+            dl class="grid grid-cols-2 gap-1 text-sm text-gray-700" {
+                dt { "Open" } dd { (open) }
+                dt { "Done" } dd { (todos.len() - open - cancelled) }
+                dt { "Cancelled" } dd { (cancelled) }
+                dt { "Still to do" } dd { (format_minutes(estimated)) }
+                dt { "Streak" } dd { (streak(&rollups, &today)) " days" }
+            }
+            a class="text-blue-500 hover:text-blue-700 text-sm" href=(routes::Stats::url()) { "More stats" }
+        }
+    })
+}
+
+pub async fn index(State(state): State<AppState>, tenant: Tenant) -> Result<Markup, AppError> {
+    let db = state.read().await.for_tenant(tenant.id())?;
+    let todos = TodoQuery::new().list(&db)?;
+    let (mut rollups, today) = recent(&db)?;
+    let streak = streak(&rollups, &today);
+    rollups.retain(|rollup| rollup.day != today.day);
+    rollups.push(today);
+    let estimated = |done: bool| -> u32 {
+        todos
+            .iter()
+            .filter(|todo| todo.completed == done)
+            .filter_map(|todo| todo.estimate_minutes)
+            .sum()
+    };
+    let capacity = state.config.daily_capacity_minutes;
+    Ok(views::page(
+        "Stats",
+        html! {
+            (nav::navigation(&Nav::stats()))
+            h1 class="text-4xl text-center text-gray-700 mb-6" { "Stats" }
+            section class="bg-white rounded-lg shadow-lg p-6 space-y-4" {
+                h2 class="text-2xl text-gray-700" { "Estimated time" }
+                dl class="grid grid-cols-2 gap-2 text-gray-700" {
+                    dt { "Still to do" } dd { (format_minutes(estimated(false))) }
+                    dt { "Done" } dd { (format_minutes(estimated(true))) }
+                }
+                h2 class="text-2xl text-gray-700" { "Burndown" }
+                p class="text-gray-700" {
+                    @match streak {
+                        0 => { "No streak yet, finish a todo to start one." }
+                        1 => { "Something done 1 day in a row." }
+                        days => { "Something done " (days) " days in a row." }
+                    }
+                }
+                div class="t-accent" { (burndown_svg(&rollups)) }
+                h2 class="text-2xl text-gray-700" { "Per day" }
+                table class="w-full text-left text-gray-700" {
+                    thead {
+                        tr { th { "Day" } th { "Todos" } th { "Estimated" } }
+                    }
+                    tbody {
+                        @for (day, load) in per_day(&todos) {
+                            @let over = capacity.is_some_and(|capacity| load.estimated > capacity);
+                            tr class=[over.then_some("text-red-600 font-bold")] {
+                                td { (day) }
+                                td { (load.todos) }
+                                td {
+                                    (format_minutes(load.estimated))
+                                    @if let Some(capacity) = capacity { " / " (format_minutes(capacity)) }
+                                }
+                            }
+                        }
+                    }
+                }
+                (cancellations_html("tag", &finished_per_tag(&todos)))
+                (cancellations_html("list", &finished_per_list(&todos)))
+            }
+        },
+    ))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use time::macros::date;
+
+    use super::*;
+
+    #[test]
+    fn test_format_minutes() {
+        assert_eq!(format_minutes(45), "45m");
+        assert_eq!(format_minutes(120), "2h");
+        assert_eq!(format_minutes(95), "1h 35m");
+    }
+
+    fn rollup(day: Date, completed: usize) -> Rollup {
+        Rollup {
+            day,
+            open: 3,
+            completed,
+            estimated: 0,
+        }
+    }
+
+    #[test]
+    fn test_streak() {
+        let rollups = [
+            rollup(date!(2024 - 01 - 01), 2),
+            rollup(date!(2024 - 01 - 02), 0),
+            rollup(date!(2024 - 01 - 03), 1),
+            rollup(date!(2024 - 01 - 04), 4),
+        ];
+        assert_eq!(streak(&rollups, &rollup(date!(2024 - 01 - 05), 1)), 3);
+        // today may still come
+        assert_eq!(streak(&rollups, &rollup(date!(2024 - 01 - 05), 0)), 2);
+        assert_eq!(streak(&rollups, &rollup(date!(2024 - 01 - 06), 0)), 0);
+    }
+
+    #[test]
+    fn test_burndown_svg() {
+        let series = [
+            rollup(date!(2024 - 01 - 01), 0),
+            rollup(date!(2024 - 01 - 02), 0),
+        ];
+        let svg = burndown_svg(&series).into_string();
+        assert!(svg.contains("points=\"0,0 300,0\""));
+        assert!(burndown_svg(&[]).into_string().contains("points=\"\""));
+    }
+
+    #[test]
+    fn test_finished_per_tag_and_list() {
+        let mut todos = Vec::new();
+        for (id, tags, list, cancelled) in [
+            (1, vec!["home"], Some("chores"), false),
+            (2, vec!["home", "garden"], Some("chores"), true),
+            (3, vec!["garden"], None, true),
+        ] {
+            let mut todo = Todo::new(id, format!("todo {}", id));
+            todo.tags = tags.into_iter().map(str::to_string).collect();
+            todo.list = list.map(str::to_string);
+            if cancelled {
+                todo.cancel(None);
+            } else {
+                todo.set_completed(true);
+            }
+            todos.push(todo);
+        }
+        // open todos are not finished either way
+        todos.push(Todo::new(4, "todo 4".into()));
+        todos[3].tags = vec!["home".into()];
+
+        let tags = finished_per_tag(&todos);
+        assert_eq!(
+            tags["home"],
+            Finished {
+                done: 1,
+                cancelled: 1
+            }
+        );
+        assert_eq!(tags["home"].cancel_rate(), 50);
+        assert_eq!(tags["garden"].cancel_rate(), 100);
+        let lists = finished_per_list(&todos);
+        assert_eq!(lists.len(), 1);
+        assert_eq!(lists["chores"].cancelled, 1);
+        assert_eq!(Finished::default().cancel_rate(), 0);
+    }
+
+    #[test]
+    fn test_per_day_skips_done_and_undated() {
+        let mut todos = Vec::new();
+        for (id, due, estimate, done) in [
+            (1, Some(date!(2024 - 01 - 05)), Some(30), false),
+            (2, Some(date!(2024 - 01 - 05)), None, false),
+            (3, Some(date!(2024 - 01 - 05)), Some(60), true),
+            (4, None, Some(15), false),
+        ] {
+            let mut todo = Todo::new(id, format!("todo {}", id));
+            todo.due = due;
+            todo.estimate_minutes = estimate;
+            todo.set_completed(done);
+            todos.push(todo);
+        }
+        let days = per_day(&todos);
+        assert_eq!(days.len(), 1);
+        assert_eq!(
+            days[&date!(2024 - 01 - 05)],
+            DayLoad {
+                todos: 2,
+                estimated: 30
+            }
+        );
+    }
+}
@@ -0,0 +1,416 @@
+use std::{collections::BTreeSet, time::Duration};
+
+use anyhow::Result;
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    response::Response,
+};
+use maud::{html, Markup};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    db::{driver::Db, queue::WriteOp},
+    error::AppError,
+    events::Events,
+    extract::FormOrJson,
+    locale::Formatter,
+    repository::query::TodoQuery,
+    retention::Volume,
+    routes,
+    scheduler::{Job, Schedule},
+    search::Filters,
+    state::AppState,
+    tenant::Tenant,
+    views,
+};
+
+// saved searches of a workspace, `subscription:{id}`
+const SUBSCRIPTION_PREFIX: &str = "subscription:";
+// what they turned up, `digest:{id}`
+const DIGEST_PREFIX: &str = "digest:";
+const SUBSCRIPTIONS_ID: &str = "subscriptions";
+// pushed to open pages when a saved search has new matches
+pub const MATCHED_EVENT: &str = "subscription-matched";
+// entries kept, the oldest go first
+const MAX_DIGEST: usize = 100;
+
+// === Subscriptions ===
+// A saved search the workspace is told about when todos start matching it. `seen` holds what
+// matched at the last look, so only todos that were not matching before are reported.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Subscription {
+    pub id: u64,
+    pub query: String,
+    pub seen: BTreeSet<u64>,
+}
+
+// new matches of a subscription, the todos with their titles at the time
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DigestEntry {
+    pub id: u64,
+    pub subscription: u64,
+    pub query: String,
+    pub todos: Vec<(u64, String)>,
+    // unix seconds
+    pub created_at: u64,
+}
+
+fn subscription_key(id: u64) -> String {
+    format!("{}{}", SUBSCRIPTION_PREFIX, id)
+}
+fn digest_key(id: u64) -> String {
+    format!("{}{}", DIGEST_PREFIX, id)
+}
+
+pub fn all(db: &Db) -> Result<Vec<Subscription>> {
+    db.iter_prefix::<Subscription>(SUBSCRIPTION_PREFIX)?
+        .map(|item| item.map(|(_, subscription)| subscription))
+        .collect()
+}
+
+// newest first
+pub fn digest(db: &Db) -> Result<Vec<DigestEntry>> {
+    let mut entries = db
+        .iter_prefix::<DigestEntry>(DIGEST_PREFIX)?
+        .map(|item| item.map(|(_, entry)| entry))
+        .collect::<Result<Vec<_>>>()?;
+    entries.sort_by(|a, b| b.id.cmp(&a.id));
+    Ok(entries)
+}
+
+// the todos matching `filters`, archived ones only for `is:archived`
+fn matching(db: &Db, filters: &Filters) -> Result<BTreeSet<u64>> {
+    Ok(TodoQuery::new()
+        .including_archived()
+        .including_someday()
+        .list(db)?
+        .iter()
+        .filter(|todo| filters.matches(todo))
+        .map(|todo| todo.id)
+        .collect())
+}
+
+// What already matches counts as seen, only later matches are reported.
+pub fn subscribe(db: &Db, query: &str) -> Result<Subscription, AppError> {
+    let query = query.split_whitespace().collect::<Vec<_>>().join(" ");
+    let filters = Filters::parse(&query);
+    if filters.text.is_empty() && !filters.has_qualifiers() {
+        return Err(AppError::Invalid("Search for something first.".into()));
+    }
+    if let Some(existing) = all(db)?
+        .into_iter()
+        .find(|subscription| subscription.query == query)
+    {
+        return Ok(existing);
+    }
+    let subscription = Subscription {
+        id: db.next_id()?,
+        seen: matching(db, &filters)?,
+        query,
+    };
+    db.insert(subscription_key(subscription.id), &subscription)?;
+    Ok(subscription)
+}
+
+// === Evaluation ===
+// Compare every subscription with what matches now, storing a digest entry for those with new
+// matches. Returns the new entries.
+pub fn evaluate(db: &Db) -> Result<Vec<DigestEntry>> {
+    let subscriptions = all(db)?;
+    if subscriptions.is_empty() {
+        return Ok(Vec::new());
+    }
+    let todos = TodoQuery::new()
+        .including_archived()
+        .including_someday()
+        .list(db)?;
+    let now = db.clock().now_millis() / 1000;
+    let mut ops = Vec::new();
+    let mut entries = Vec::new();
+    for mut subscription in subscriptions {
+        let filters = Filters::parse(&subscription.query);
+        let matches: Vec<_> = todos.iter().filter(|todo| filters.matches(todo)).collect();
+        let new: Vec<(u64, String)> = matches
+            .iter()
+            .filter(|todo| !subscription.seen.contains(&todo.id))
+            .map(|todo| (todo.id, todo.title.clone()))
+            .collect();
+        let seen: BTreeSet<u64> = matches.iter().map(|todo| todo.id).collect();
+        if seen == subscription.seen {
+            continue;
+        }
+        subscription.seen = seen;
+        ops.push(WriteOp::Insert {
+            key: subscription_key(subscription.id),
+            value: db.encode(&subscription)?,
+        });
+        if !new.is_empty() {
+            let entry = DigestEntry {
+                id: db.next_id()?,
+                subscription: subscription.id,
+                query: subscription.query.clone(),
+                todos: new,
+                created_at: now,
+            };
+            ops.push(WriteOp::Insert {
+                key: digest_key(entry.id),
+                value: db.encode(&entry)?,
+            });
+            entries.push(entry);
+        }
+    }
+    let stored = digest(db)?;
+    let excess = (stored.len() + entries.len()).saturating_sub(MAX_DIGEST);
+    for old in stored.iter().rev().take(excess) {
+        ops.push(WriteOp::Remove {
+            key: digest_key(old.id),
+        });
+    }
+    db.apply_batch(ops)?;
+    Ok(entries)
+}
+
+// Look for new matches in one workspace and tell its open pages.
+pub fn notify(db: &Db, events: &Events, tenant: Option<&str>) -> Result<usize> {
+    let entries = evaluate(db)?;
+    for entry in &entries {
+        events.publish(tenant, MATCHED_EVENT, toast_html(entry).into_string());
+    }
+    Ok(entries.len())
+}
+
+pub fn job(events: Events) -> Job {
+    Job::new(
+        "subscriptions",
+        Schedule::Every(Duration::from_secs(5 * 60)),
+        move |db| {
+            let events = events.clone();
+            Box::pin(async move { notify_all(&*db.write().await, &events) })
+        },
+    )
+}
+
+fn notify_all(db: &Db, events: &Events) -> Result<()> {
+    notify(&db.for_tenant(None)?, events, None)?;
+    for id in db.tenant_ids()? {
+        notify(&db.for_tenant(Some(&id))?, events, Some(&id))?;
+    }
+    Ok(())
+}
+
+// === Retention ===
+// Drop the digest entries from before `cutoff`, in unix seconds. Returns how many went.
+pub fn purge_digest_before(db: &Db, cutoff: u64) -> Result<usize> {
+    let ops: Vec<WriteOp> = digest(db)?
+        .into_iter()
+        .filter(|entry| entry.created_at < cutoff)
+        .map(|entry| WriteOp::Remove {
+            key: digest_key(entry.id),
+        })
+        .collect();
+    let purged = ops.len();
+    db.apply_batch(ops)?;
+    Ok(purged)
+}
+
+pub fn digest_volume(db: &Db) -> Result<Volume> {
+    Volume::of_prefix(db, DIGEST_PREFIX)
+}
+
+// === Components ===
+fn toast_html(entry: &DigestEntry) -> Markup {
+    let message = match &entry.todos[..] {
+        [(_, title)] => format!("\"{}\" now matches \"{}\".", title, entry.query),
+        todos => format!("{} todos now match \"{}\".", todos.len(), entry.query),
+    };
+    views::notice_toast_oob(&message)
+}
+
+// shows new matches as a toast
+pub fn toast_slot() -> Markup {
+    html! {
+        div class="hidden" sse-swap=(MATCHED_EVENT) hx-swap="none" {}
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Subscribe {
+    query: String,
+}
+
+// the saved searches and their digest, on the search page
+pub fn section_html(
+    query: &str,
+    subscriptions: &[Subscription],
+    digest: &[DigestEntry],
+    dates: &Formatter,
+) -> Markup {
+    let query = query.trim();
+    let subscribed = subscriptions
+        .iter()
+        .any(|subscription| subscription.query == query);
+    let target = format!("#{}", SUBSCRIPTIONS_ID);
+    html! {
+        section id=(SUBSCRIPTIONS_ID) class="space-y-2 mb-6" {
+            @if !query.is_empty() && !subscribed {
+                form method="post" action=(routes::SearchSubscriptions::url())
+                    hx-post=(routes::SearchSubscriptions::url()) hx-target=(target) hx-swap="outerHTML" {
+                    input type="hidden" name="query" value=(query);
+                    button class="text-blue-500 hover:text-blue-700" type="submit" { "Notify me about new matches" }
+                }
+            }
+            @if !subscriptions.is_empty() {
+                h2 class="text-xl text-gray-700" { "Saved searches" }
+                ul class="space-y-1" {
+                    @for subscription in subscriptions {
+                        li class="flex items-center" {
+                            a class="hover:underline" href={ (routes::Search::url()) "?q=" (urlencoding::encode(&subscription.query)) } { (subscription.query) }
+                            button class="ml-2 text-red-500 hover:text-red-700" type="button" aria-label={ "Stop following " (subscription.query) }
+                                hx-delete=(routes::SearchSubscription::url(subscription.id)) hx-target=(target) hx-swap="outerHTML" { "×" }
+                        }
+                    }
+                }
+            }
+            @if !digest.is_empty() {
+                h2 class="text-xl text-gray-700" { "New matches" }
+                ul class="space-y-2" {
+                    @for entry in digest {
+                        li class="text-sm" {
+                            span class="t-muted" { (dates.ago(entry.created_at)) " · " (entry.query) }
+                            button class="ml-2 t-muted hover:underline" type="button"
+                                hx-delete=(routes::DigestEntry::url(entry.id)) hx-target=(target) hx-swap="outerHTML" { "Dismiss" }
+                            ul class="ml-4 list-disc" {
+                                @for (id, title) in &entry.todos {
+                                    li { a class="hover:underline" href=(routes::TodoDetail::url(id)) { (title) } }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn load(db: &Db, query: &str) -> Result<Markup> {
+    Ok(section_html(
+        query,
+        &all(db)?,
+        &digest(db)?,
+        &Formatter::load(db)?,
+    ))
+}
+
+// === Routes ===
+pub async fn create(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    FormOrJson(Subscribe { query }): FormOrJson<Subscribe>,
+) -> Result<Response, AppError> {
+    let guard = state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    let subscription = subscribe(&db, &query)?;
+    Ok(views::fragment_or_redirect(
+        &headers,
+        load(&db, &subscription.query)?,
+        &routes::Search::url(),
+    ))
+}
+
+pub async fn remove(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Path(id): Path<u64>,
+) -> Result<Response, AppError> {
+    let guard = state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    db.remove(subscription_key(id))?;
+    Ok(views::fragment_or_redirect(
+        &headers,
+        load(&db, "")?,
+        &routes::Search::url(),
+    ))
+}
+
+pub async fn dismiss(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Path(id): Path<u64>,
+) -> Result<Response, AppError> {
+    let guard = state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    db.remove(digest_key(id))?;
+    Ok(views::fragment_or_redirect(
+        &headers,
+        load(&db, "")?,
+        &routes::Search::url(),
+    ))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::{TestDb, TodoFixture},
+        repository,
+    };
+
+    #[test]
+    fn test_new_matches_reported_once() -> Result<()> {
+        let db = TestDb::new("subscriptions")?;
+        TodoFixture::new()
+            .titled("Old crash")
+            .with_tags(["bug"])
+            .persist(&db)?;
+        let subscription = subscribe(&db, "tag:bug  is:open")?;
+        assert_eq!(subscription.query, "tag:bug is:open");
+        // what matched before subscribing is not news
+        assert!(evaluate(&db)?.is_empty());
+
+        let mut todo = TodoFixture::new()
+            .titled("Login fails")
+            .with_tags(["bug"])
+            .persist(&db)?;
+        TodoFixture::new().titled("Unrelated").persist(&db)?;
+        let entries = evaluate(&db)?;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].todos, [(todo.id, "Login fails".to_string())]);
+        assert!(evaluate(&db)?.is_empty());
+
+        // closing and reopening makes it start matching again
+        todo.set_completed(true);
+        db.insert(repository::todo::todo_key(todo.id), &todo)?;
+        assert!(evaluate(&db)?.is_empty());
+        todo.set_completed(false);
+        db.insert(repository::todo::todo_key(todo.id), &todo)?;
+        assert_eq!(evaluate(&db)?.len(), 1);
+        assert_eq!(digest(&db)?.len(), 2);
+
+        assert!(subscribe(&db, "   ").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_purge_digest_before() -> Result<()> {
+        let db = TestDb::new("subscriptions_purge")?;
+        subscribe(&db, "tag:bug")?;
+        TodoFixture::new().with_tags(["bug"]).persist(&db)?;
+        evaluate(&db)?;
+        db.clock.advance(std::time::Duration::from_secs(60));
+        TodoFixture::new().with_tags(["bug"]).persist(&db)?;
+        evaluate(&db)?;
+        assert_eq!(digest_volume(&db)?.keys, 2);
+
+        let cutoff = db.clock().now_millis() / 1000 - 30;
+        assert_eq!(purge_digest_before(&db, cutoff)?, 1);
+        let kept = digest(&db)?;
+        assert_eq!(kept.len(), 1);
+        assert!(kept[0].created_at >= cutoff);
+        Ok(())
+    }
+}
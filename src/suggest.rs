@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+
+use axum::extract::{Query, State};
+use maud::{html, Markup};
+use serde::Deserialize;
+use time::{Date, Duration, Weekday};
+
+use crate::{
+    error::AppError, import::normalize_title, models::Todo, repository, rollup, state::AppState,
+    stats, tags, tenant::Tenant,
+};
+
+// shorter words ("buy", "the") say little about what a todo is
+const MIN_WORD_LEN: usize = 4;
+// finishing days only hint at a habit once there are a few of them
+const MIN_COMPLETIONS: usize = 5;
+
+// === Suggestions ===
+// What the create form offers for a title being typed, worked out from the workspace's own
+// history without going out to the network.
+#[derive(Debug, Default, PartialEq)]
+pub struct Suggestions {
+    // the date and why it was picked
+    pub due: Option<(Date, String)>,
+    pub estimate_minutes: Option<u32>,
+}
+
+fn words(title: &str) -> Vec<String> {
+    normalize_title(title)
+        .split(' ')
+        .filter(|word| word.len() >= MIN_WORD_LEN)
+        .map(str::to_string)
+        .collect()
+}
+
+// sharing a tag or a longer word
+fn is_similar(words: &[String], tags: &[String], todo: &Todo) -> bool {
+    todo.tags.iter().any(|tag| tags.contains(tag))
+        || normalize_title(&todo.title)
+            .split(' ')
+            .any(|word| words.iter().any(|known| known == word))
+}
+
+// the most frequent weekday, ties going to the earlier one in the week
+fn usual_weekday(days: impl Iterator<Item = Weekday>) -> Option<(Weekday, usize)> {
+    let mut counts: HashMap<Weekday, usize> = HashMap::new();
+    for day in days {
+        *counts.entry(day).or_default() += 1;
+    }
+    counts
+        .into_iter()
+        .max_by_key(|(day, count)| (*count, std::cmp::Reverse(day.number_from_monday())))
+}
+
+// the first `weekday` after `today`
+fn next(today: Date, weekday: Weekday) -> Date {
+    let ahead =
+        (weekday.number_days_from_monday() + 7 - today.weekday().number_days_from_monday()) % 7;
+    today + Duration::days(if ahead == 0 { 7 } else { ahead as i64 })
+}
+
+// Similar todos that had a due date suggest the weekday they were due on, otherwise the weekday
+// todos usually get done on. Estimates are the median of similar todos' estimates.
+pub fn suggest(title: &str, todos: &[Todo], today: Date) -> Suggestions {
+    let (title, tags) = tags::parse_tags(title);
+    let words = words(&title);
+    if words.is_empty() && tags.is_empty() {
+        return Suggestions::default();
+    }
+    let similar: Vec<&Todo> = todos
+        .iter()
+        .filter(|todo| is_similar(&words, &tags, todo))
+        .collect();
+
+    let due = match usual_weekday(
+        similar
+            .iter()
+            .filter_map(|todo| todo.due)
+            .map(|due| due.weekday()),
+    ) {
+        Some((weekday, _)) => {
+            let example = similar
+                .iter()
+                .find(|todo| todo.due.is_some_and(|due| due.weekday() == weekday));
+            let reason = match example {
+                Some(todo) => format!("like \"{}\"", todo.title),
+                None => "like similar todos".to_string(),
+            };
+            Some((next(today, weekday), reason))
+        }
+        None => usual_weekday(
+            todos
+                .iter()
+                .filter(|todo| todo.completed)
+                .filter_map(rollup::changed_on)
+                .map(|day| day.weekday()),
+        )
+        .filter(|(_, count)| *count >= MIN_COMPLETIONS)
+        .map(|(weekday, _)| {
+            (
+                next(today, weekday),
+                "when you usually get things done".to_string(),
+            )
+        }),
+    };
+
+    let mut estimates: Vec<u32> = similar
+        .iter()
+        .filter_map(|todo| todo.estimate_minutes)
+        .collect();
+    estimates.sort_unstable();
+    let estimate_minutes = estimates.get(estimates.len() / 2).copied();
+
+    Suggestions {
+        due,
+        estimate_minutes,
+    }
+}
+
+// === Components ===
+// Chips under the create form, clicking one fills in its field, the cross dismisses it.
+fn chips_html(suggestions: &Suggestions) -> Markup {
+    html! {
+        @if let Some((due, reason)) = &suggestions.due {
+            span class="inline-flex items-center mr-2 text-xs t-chip rounded px-2 py-1" {
+                button type="button" title=(reason)
+                    onclick={ "this.closest('form').elements.due.value = '" (due) "'" } { "Due " (due.weekday()) " " (due) }
+                button class="ml-1" type="button" aria-label="Dismiss" onclick="this.parentElement.remove()" { "×" }
+            }
+        }
+        @if let Some(minutes) = suggestions.estimate_minutes {
+            span class="inline-flex items-center mr-2 text-xs t-chip rounded px-2 py-1" {
+                button type="button" title="like similar todos"
+                    onclick={ "this.closest('form').elements.estimate.value = '" (minutes) "'" } { "~" (stats::format_minutes(minutes)) }
+                button class="ml-1" type="button" aria-label="Dismiss" onclick="this.parentElement.remove()" { "×" }
+            }
+        }
+    }
+}
+
+// === Routes ===
+#[derive(Deserialize)]
+pub struct SuggestQuery {
+    #[serde(default)]
+    title: String,
+}
+// asked for while the title is typed, see `new_todo_html`
+pub async fn suggestions(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    Query(SuggestQuery { title }): Query<SuggestQuery>,
+) -> Result<Markup, AppError> {
+    let todos = repository::todo::all(&state.read().await.for_tenant(tenant.id())?)?;
+    let today = state.clock.now().date();
+    Ok(chips_html(&suggest(&title, &todos, today)))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use time::macros::date;
+
+    use super::*;
+
+    fn todo(id: u64, title: &str, due: Option<Date>, estimate: Option<u32>) -> Todo {
+        let mut todo = Todo::new(id, title.to_string());
+        todo.due = due;
+        todo.estimate_minutes = estimate;
+        todo
+    }
+
+    #[test]
+    fn test_suggest_from_similar_todos() {
+        // both Saturdays
+        let todos = vec![
+            todo(
+                1,
+                "Groceries for the week",
+                Some(date!(2024 - 03 - 02)),
+                Some(45),
+            ),
+            todo(2, "groceries", Some(date!(2024 - 03 - 09)), Some(30)),
+            todo(3, "Call the bank", Some(date!(2024 - 03 - 04)), Some(10)),
+        ];
+        // a Wednesday
+        let suggestions = suggest("Groceries", &todos, date!(2024 - 03 - 13));
+        assert_eq!(suggestions.due.unwrap().0, date!(2024 - 03 - 16));
+        assert_eq!(suggestions.estimate_minutes, Some(45));
+
+        assert_eq!(
+            suggest("buy it", &todos, date!(2024 - 03 - 13)),
+            Suggestions::default()
+        );
+    }
+
+    #[test]
+    fn test_suggest_from_finishing_days() {
+        // a Monday, in unix seconds
+        let monday = 1_709_553_600;
+        let mut todos: Vec<Todo> = (1..=MIN_COMPLETIONS as u64)
+            .map(|id| {
+                let mut todo = todo(id, "Something", None, None);
+                todo.set_completed(true);
+                todo.updated_at = monday + (id - 1) * 7 * 24 * 60 * 60;
+                todo
+            })
+            .collect();
+        let suggestions = suggest("Write report", &todos, date!(2024 - 03 - 13));
+        assert_eq!(suggestions.due.unwrap().0, date!(2024 - 03 - 18));
+
+        todos.pop();
+        assert_eq!(
+            suggest("Write report", &todos, date!(2024 - 03 - 13)).due,
+            None
+        );
+    }
+
+    #[test]
+    fn test_next() {
+        // a Wednesday
+        let today = date!(2024 - 03 - 13);
+        assert_eq!(next(today, Weekday::Thursday), date!(2024 - 03 - 14));
+        assert_eq!(next(today, Weekday::Wednesday), date!(2024 - 03 - 20));
+    }
+}
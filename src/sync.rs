@@ -0,0 +1,89 @@
+use maud::{html, Markup};
+
+use crate::{events::Events, views::dom};
+
+// the event the fragments go out as, and what the sink listens for
+pub const SYNC_EVENT: &str = "todos-synced";
+
+// === Sync ===
+// Every open page of a workspace gets the items a create, toggle or remove rendered, as out of
+// band swaps, so other tabs show the change without a reload. The tab that made the change gets
+// them too, the swaps are written to leave a page that already shows the change as it is.
+
+// A new item goes at the end of the open list, unless the list has it already.
+pub fn created(events: &Events, tenant: Option<&str>, id: u64, item: Markup) {
+    let target = format!(
+        "{}:not(:has({}))",
+        dom::selector(&dom::list_items(dom::OPEN_LIST)),
+        dom::selector(&dom::todo(id))
+    );
+    publish(
+        events,
+        tenant,
+        html! {
+            ul hx-swap-oob={ "beforeend:" (target) } { (item) }
+        },
+    );
+}
+
+// Items morphed in place by their ids, see `todo_oob_html`.
+pub fn changed(events: &Events, tenant: Option<&str>, items: Markup) {
+    publish(events, tenant, items);
+}
+
+pub fn removed(events: &Events, tenant: Option<&str>, id: u64) {
+    publish(
+        events,
+        tenant,
+        html! {
+            li id=(dom::todo(id)) hx-swap-oob="delete" {}
+        },
+    );
+}
+
+fn publish(events: &Events, tenant: Option<&str>, fragment: Markup) {
+    events.publish(tenant, SYNC_EVENT, fragment.into_string());
+}
+
+// === Components ===
+// Takes the fragments in on the page, swapping nothing itself but their out of band parts.
+pub fn sink_html() -> Markup {
+    html! {
+        div hidden sse-swap=(SYNC_EVENT) hx-swap="none" {}
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_tab_gets_the_change() {
+        let events = Events::new();
+        let mut first = events.subscribe(Some("acme"));
+        let mut second = events.subscribe(Some("acme"));
+        let mut other = events.subscribe(Some("other"));
+        created(
+            &events,
+            Some("acme"),
+            7,
+            html! { li id=(dom::todo(7)) { "Milk" } },
+        );
+
+        let received = second.try_recv().unwrap();
+        assert_eq!(received.name, SYNC_EVENT);
+        assert_eq!(
+            received.data,
+            r##"<ul hx-swap-oob="beforeend:#list-open-items:not(:has(#todo-7))"><li id="todo-7">Milk</li></ul>"##
+        );
+        assert_eq!(first.try_recv().unwrap().data, received.data);
+        assert!(other.try_recv().is_err());
+
+        removed(&events, Some("acme"), 7);
+        assert_eq!(
+            second.try_recv().unwrap().data,
+            r#"<li id="todo-7" hx-swap-oob="delete"></li>"#
+        );
+    }
+}
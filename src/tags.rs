@@ -0,0 +1,582 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    response::Response,
+};
+use maud::{html, Markup};
+use serde::Deserialize;
+
+use crate::{
+    clock::Clock,
+    db::{driver::Db, queue::WriteOp},
+    error::AppError,
+    events::Events,
+    extract::FormOrJson,
+    method_override,
+    models::Todo,
+    repository::{
+        self,
+        entity::Repository,
+        tag_archive::{self, ArchivedTag},
+    },
+    review, routes,
+    scheduler::{Job, Schedule},
+    state::AppState,
+    tenant::Tenant,
+    views::{
+        self, combobox,
+        nav::{self, Nav},
+    },
+};
+
+const MAX_LEN: usize = 32;
+// tags shown in the sidebar cloud, the most used ones
+const CLOUD_LEN: usize = 20;
+// tags offered while one is typed
+const SUGGESTIONS_LEN: usize = 8;
+const ARCHIVED_ID: &str = "archived-tags";
+// tags the auto-archive policy just put away, shown as a toast
+pub const AUTO_ARCHIVED_EVENT: &str = "tags-archived";
+
+// === Tags ===
+// `#Errands` and `errands` are the same tag, anything but a-z, 0-9, - and _ is not a tag
+pub fn normalize(tag: &str) -> Option<String> {
+    let tag = tag.trim().trim_start_matches('#').to_lowercase();
+    let valid = !tag.is_empty()
+        && tag.len() <= MAX_LEN
+        && tag
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'));
+    valid.then_some(tag)
+}
+
+// Take the `#tag` words out of a quick-add title, "buy milk #errands" is tagged `errands`.
+pub fn parse_tags(title: &str) -> (String, Vec<String>) {
+    let mut words = Vec::new();
+    let mut tags: Vec<String> = Vec::new();
+    for word in title.split_whitespace() {
+        match word.strip_prefix('#').and_then(normalize) {
+            Some(tag) => {
+                if !tags.contains(&tag) {
+                    tags.push(tag);
+                }
+            }
+            None => words.push(word),
+        }
+    }
+    (words.join(" "), tags)
+}
+
+// The tags of an edit form's field, separated by spaces or commas, `#` optional.
+pub fn parse_field(value: &str) -> Result<Vec<String>, AppError> {
+    let mut tags: Vec<String> = Vec::new();
+    for word in value.split(|c: char| c.is_whitespace() || c == ',') {
+        if word.is_empty() {
+            continue;
+        }
+        let tag = normalize(word)
+            .ok_or_else(|| AppError::Invalid(format!("`{}` is not a valid tag", word)))?;
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    Ok(tags)
+}
+
+// how many todos carry each tag
+pub fn counts(todos: &[Todo]) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for tag in todos.iter().flat_map(|todo| &todo.tags) {
+        *counts.entry(tag.clone()).or_default() += 1;
+    }
+    counts
+}
+
+// the same without archived tags, which have a page of their own
+fn active_counts(db: &Db, todos: &[Todo]) -> anyhow::Result<BTreeMap<String, usize>> {
+    let archived = tag_archive::names(db)?;
+    let mut counts = counts(todos);
+    counts.retain(|tag, _| !archived.contains(tag));
+    Ok(counts)
+}
+
+// The names to offer for `typed`, a normalized prefix: those starting with it, the most used
+// first, then those that only contain it. Nothing typed offers the most used ones.
+pub fn suggestions<'a>(counts: &'a BTreeMap<String, usize>, typed: &str) -> Vec<(&'a str, usize)> {
+    let mut found: Vec<(bool, &str, usize)> = counts
+        .iter()
+        .filter(|(tag, _)| tag.contains(typed))
+        .map(|(tag, count)| (!tag.starts_with(typed), tag.as_str(), *count))
+        .collect();
+    found.sort_by(|a, b| a.0.cmp(&b.0).then(b.2.cmp(&a.2)).then(a.1.cmp(b.1)));
+    found.truncate(SUGGESTIONS_LEN);
+    found
+        .into_iter()
+        .map(|(_, tag, count)| (tag, count))
+        .collect()
+}
+
+// Replace `from` with `to` on one todo, or strip it when `to` is unset. False when the todo
+// does not carry `from`.
+fn retag(todo: &mut Todo, from: &str, to: Option<&str>, clock: &dyn Clock) -> bool {
+    let Some(at) = todo.tags.iter().position(|tag| tag == from) else {
+        return false;
+    };
+    match to {
+        Some(to) if to == from => return false,
+        // a todo that has both keeps one
+        Some(to) if !todo.tags.iter().any(|tag| tag == to) => todo.tags[at] = to.to_string(),
+        _ => {
+            todo.tags.remove(at);
+        }
+    }
+    todo.touch(clock);
+    true
+}
+
+// The writes renaming `from` to `to` across the workspace, submitted as one batch so no todo
+// is left with the old name. Renaming to a tag in use merges the two, `None` deletes the tag.
+pub fn rewrite(db: &Db, from: &str, to: Option<&str>) -> anyhow::Result<Vec<WriteOp>> {
+    let mut ops = Vec::new();
+    for mut todo in repository::todo::tagged(db, from)? {
+        if retag(&mut todo, from, to, &**db.clock()) {
+            ops.extend(Repository::new(db).put_ops(&todo)?);
+        }
+    }
+    Ok(ops)
+}
+
+// === Components ===
+// the tags of a list item
+pub fn chips_html(tags: &[String]) -> Markup {
+    html! {
+        @for tag in tags {
+            a class="ml-2 text-xs t-chip rounded px-2 py-1 hover:underline" href=(routes::Tags::url()) { "#" (tag) }
+        }
+    }
+}
+
+fn tag_html(tag: &str, count: usize) -> Markup {
+    let url = routes::Tag::url(tag);
+    html! {
+        li class="flex items-center justify-between bg-white rounded-lg shadow-lg my-2 p-4" {
+            span class="font-bold text-gray-700" { "#" (tag) span class="ml-2 text-sm font-normal text-gray-500" { (count) } }
+            div class="flex items-center space-x-2" {
+                form method="post" action=(routes::TagArchive::url(tag)) hx-post=(routes::TagArchive::url(tag)) hx-target="#tags" hx-swap="outerHTML" {
+                    button class="text-blue-500 hover:text-blue-700" type="submit" title="Hide the tag and keep its todos as they are" { "Archive" }
+                }
+                a class="text-blue-500 hover:text-blue-700" href=(routes::TagBundle::url(tag)) download title="Download these todos as a bundle to share" { "Bundle" }
+                form class="flex items-center space-x-2" method="post" action=(url) hx-post=(url) hx-target="#tags" hx-swap="outerHTML" {
+                    input class="rounded p-1 border text-gray-700" type="text" name="to" placeholder="Rename or merge into" aria-label={ "New name for #" (tag) } required;
+                    button class="text-blue-500 hover:text-blue-700" type="submit" { "Rename" }
+                }
+                form method="post" action=(url) {
+                    input type="hidden" name=(method_override::METHOD_FIELD) value="DELETE";
+                    button class="bg-red-500 hover:bg-red-700 text-white font-bold py-1 px-2 rounded" type="submit"
+                        hx-delete=(url) hx-target="#tags" hx-swap="outerHTML" hx-confirm={ "Remove #" (tag) " from every todo?" } { "Delete" }
+                }
+            }
+        }
+    }
+}
+
+fn tags_html(counts: &BTreeMap<String, usize>) -> Markup {
+    html! {
+        section id="tags" class="bg-gray-200 rounded-lg p-4" {
+            @if counts.is_empty() {
+                p class="text-gray-500" { "No tags yet, add one with #tag when creating a todo." }
+            }
+            ul {
+                @for (tag, count) in counts {
+                    (tag_html(tag, *count))
+                }
+            }
+        }
+    }
+}
+
+// the most used tags, sized by how often they are used
+fn cloud_html(counts: &BTreeMap<String, usize>) -> Markup {
+    let mut top: Vec<(&String, &usize)> = counts.iter().collect();
+    top.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    top.truncate(CLOUD_LEN);
+    top.sort_by(|a, b| a.0.cmp(b.0));
+    let max = top.iter().map(|(_, count)| **count).max().unwrap_or(1);
+    html! {
+        section id="tag-cloud" class="bg-white rounded-lg shadow-lg p-4 space-y-2" {
+            h2 class="text-xl text-gray-700" { a class="hover:underline" href=(routes::Tags::url()) { "Tags" } }
+            @if top.is_empty() {
+                p class="text-gray-500" { "No tags yet." }
+            }
+            p class="leading-loose" {
+                @for (tag, count) in top {
+                    @let size = match count * 3 / max { 0 => "text-xs", 1 => "text-sm", 2 => "text-base", _ => "text-lg" };
+                    span class={ "mr-2 text-blue-700 " (size) } title={ (count) " todos" } { "#" (tag) }
+                }
+            }
+        }
+    }
+}
+
+fn archived_html(archived: &[(ArchivedTag, usize)]) -> Markup {
+    html! {
+        section id=(ARCHIVED_ID) class="bg-gray-200 rounded-lg p-4" {
+            @if archived.is_empty() {
+                p class="text-gray-500" { "No archived tags." }
+            }
+            ul {
+                @for (archived, count) in archived {
+                    @let url = routes::TagRestore::url(&archived.tag);
+                    li class="flex items-center justify-between bg-white rounded-lg shadow-lg my-2 p-4" {
+                        span class="font-bold text-gray-700" {
+                            "#" (archived.tag)
+                            span class="ml-2 text-sm font-normal text-gray-500" {
+                                (count) " todos, archived "
+                                @if archived.automatic { "after a while without activity " }
+                                "on " (review::format_day(archived.archived_at))
+                            }
+                        }
+                        form method="post" action=(url) hx-post=(url) hx-target={ "#" (ARCHIVED_ID) } hx-swap="outerHTML" {
+                            button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded" type="submit" { "Restore" }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// the options of a tag field for what was typed, a tag nobody uses yet can be created
+fn suggestions_html(counts: &BTreeMap<String, usize>, typed: &str) -> Markup {
+    let typed = typed.trim().trim_start_matches('#');
+    let tag = normalize(typed);
+    if !typed.is_empty() && tag.is_none() {
+        return html! {};
+    }
+    let prefix = tag.as_deref().unwrap_or_default();
+    html! {
+        @for (tag, count) in suggestions(counts, prefix) {
+            (combobox::option_html(tag, html! { "#" (tag) span class="ml-2 text-sm text-gray-500" { (count) } }))
+        }
+        @if let Some(tag) = tag.filter(|tag| !counts.contains_key(tag)) {
+            (combobox::option_html(&tag, html! { "Create #" (tag) }))
+        }
+    }
+}
+
+// shows what the auto-archive policy put away as a toast on the list
+pub fn archived_slot() -> Markup {
+    html! {
+        div class="hidden" sse-swap=(AUTO_ARCHIVED_EVENT) hx-swap="none" {}
+    }
+}
+
+async fn load_counts(
+    state: &AppState,
+    tenant: &Tenant,
+) -> Result<BTreeMap<String, usize>, AppError> {
+    let db = state.read().await.for_tenant(tenant.id())?;
+    Ok(active_counts(&db, &repository::todo::all(&db)?)?)
+}
+
+fn load_archived(db: &Db) -> anyhow::Result<Vec<(ArchivedTag, usize)>> {
+    tag_archive::list(db)?
+        .into_iter()
+        .map(|archived| {
+            let count = Repository::<Todo>::new(db)
+                .ids_by("tag", &archived.tag)?
+                .len();
+            Ok((archived, count))
+        })
+        .collect()
+}
+
+// === Routes ===
+pub async fn index(State(state): State<AppState>, tenant: Tenant) -> Result<Markup, AppError> {
+    let counts = load_counts(&state, &tenant).await?;
+    Ok(views::page(
+        "Tags",
+        html! {
+            (nav::navigation(&Nav::tags()))
+            h1 class="text-4xl text-center text-gray-700 mb-6" { "Tags" }
+            (tags_html(&counts))
+            p class="text-center mt-4" {
+                a class="text-blue-500 hover:text-blue-700" href=(routes::ArchivedTags::url()) { "Archived tags" }
+            }
+        },
+    ))
+}
+
+pub async fn archived(State(state): State<AppState>, tenant: Tenant) -> Result<Markup, AppError> {
+    let db = state.read().await.for_tenant(tenant.id())?;
+    Ok(views::page(
+        "Archived tags",
+        html! {
+            (nav::navigation(&Nav::tags()))
+            h1 class="text-4xl text-center text-gray-700 mb-6" { "Archived tags" }
+            p class="text-center text-gray-600 mb-4" {
+                "The todos under an archived tag are out of your lists and can't be changed until it is restored."
+            }
+            (archived_html(&load_archived(&db)?))
+        },
+    ))
+}
+
+// Archive a tag by hand, its todos stay as they are.
+pub async fn archive(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Path(tag): Path<String>,
+) -> Result<Response, AppError> {
+    let guard = state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    let todos = repository::todo::all(&db)?;
+    if !todos.iter().any(|todo| todo.tags.contains(&tag)) {
+        return Err(AppError::NotFound);
+    }
+    tag_archive::archive(&db, &tag, false)?;
+    tracing::info!(%tag, "archived tag");
+    Ok(views::fragment_or_redirect(
+        &headers,
+        tags_html(&active_counts(&db, &todos)?),
+        &routes::Tags::url(),
+    ))
+}
+
+pub async fn restore(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Path(tag): Path<String>,
+) -> Result<Response, AppError> {
+    let guard = state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    if !tag_archive::restore(&db, &tag)? {
+        return Err(AppError::NotFound);
+    }
+    tracing::info!(%tag, "restored tag");
+    Ok(views::fragment_or_redirect(
+        &headers,
+        archived_html(&load_archived(&db)?),
+        &routes::ArchivedTags::url(),
+    ))
+}
+
+// lazily loaded into the sidebar of the list, it scans every todo
+pub async fn cloud(State(state): State<AppState>, tenant: Tenant) -> Result<Markup, AppError> {
+    Ok(cloud_html(&load_counts(&state, &tenant).await?))
+}
+
+#[derive(Deserialize)]
+pub struct Suggest {
+    #[serde(default)]
+    q: String,
+}
+// the options of a tag field as it is typed in, see `combobox`
+pub async fn suggest(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    Query(Suggest { q }): Query<Suggest>,
+) -> Result<Markup, AppError> {
+    Ok(suggestions_html(&load_counts(&state, &tenant).await?, &q))
+}
+
+#[derive(Deserialize)]
+pub struct RenameTag {
+    to: String,
+}
+pub async fn rename(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Path(tag): Path<String>,
+    FormOrJson(RenameTag { to }): FormOrJson<RenameTag>,
+) -> Result<Response, AppError> {
+    let to = normalize(&to).ok_or_else(|| {
+        AppError::Invalid(format!(
+            "`{}` is not a tag, use up to {} of a-z, 0-9, - and _",
+            to, MAX_LEN
+        ))
+    })?;
+    apply(state, &tenant, &headers, &tag, Some(&to)).await
+}
+
+pub async fn remove(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Path(tag): Path<String>,
+) -> Result<Response, AppError> {
+    apply(state, &tenant, &headers, &tag, None).await
+}
+
+async fn apply(
+    mut state: AppState,
+    tenant: &Tenant,
+    headers: &HeaderMap,
+    from: &str,
+    to: Option<&str>,
+) -> Result<Response, AppError> {
+    let writes = state.writes.clone();
+    let guard = state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    let ops = rewrite(&db, from, to)?;
+    if ops.is_empty() && to != Some(from) {
+        return Err(AppError::NotFound);
+    }
+    tracing::info!(tag = %from, to = ?to, writes = ops.len(), "rewrote tag");
+    writes.submit(&db, ops).await?;
+    // queued writes may not have landed yet, count from what was just written
+    let mut todos = repository::todo::all(&db)?;
+    for todo in &mut todos {
+        retag(todo, from, to, &**db.clock());
+    }
+    Ok(views::fragment_or_redirect(
+        headers,
+        tags_html(&active_counts(&db, &todos)?),
+        &routes::Tags::url(),
+    ))
+}
+
+// === Auto-archive ===
+// Once a night, archive the tags of every workspace none of whose todos changed in `idle_for`,
+// and let whoever is looking know.
+pub fn archive_job(events: Events, idle_for: Duration) -> Job {
+    Job::new(
+        "tag_archive",
+        Schedule::Daily {
+            hour: 3,
+            minute: 30,
+        },
+        move |db| {
+            let events = events.clone();
+            Box::pin(async move { archive_all(&*db.write().await, &events, idle_for) })
+        },
+    )
+}
+
+fn archive_all(db: &Db, events: &Events, idle_for: Duration) -> anyhow::Result<()> {
+    archive_idle(&db.for_tenant(None)?, events, None, idle_for)?;
+    for id in db.tenant_ids()? {
+        archive_idle(&db.for_tenant(Some(&id))?, events, Some(&id), idle_for)?;
+    }
+    Ok(())
+}
+
+fn archive_idle(
+    db: &Db,
+    events: &Events,
+    tenant: Option<&str>,
+    idle_for: Duration,
+) -> anyhow::Result<()> {
+    let archived = tag_archive::archive_idle(db, idle_for)?;
+    if archived.is_empty() {
+        return Ok(());
+    }
+    tracing::info!(tenant = ?tenant, tags = ?archived, "archived idle tags");
+    let message = match &archived[..] {
+        [tag] => format!(
+            "#{} was archived after {} days without changes.",
+            tag,
+            idle_for.as_secs() / 86_400
+        ),
+        tags => format!(
+            "{} tags were archived after {} days without changes.",
+            tags.len(),
+            idle_for.as_secs() / 86_400
+        ),
+    };
+    events.publish(
+        tenant,
+        AUTO_ARCHIVED_EVENT,
+        views::notice_toast_oob(&message).into_string(),
+    );
+    Ok(())
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::TestDb;
+
+    #[test]
+    fn test_parse_tags() {
+        assert_eq!(normalize("#Errands"), Some("errands".to_string()));
+        assert_eq!(normalize("two words"), None);
+        assert_eq!(normalize(&"x".repeat(MAX_LEN + 1)), None);
+        assert_eq!(
+            parse_tags("buy #Errands milk #errands #home"),
+            (
+                "buy milk".to_string(),
+                vec!["errands".to_string(), "home".to_string()]
+            )
+        );
+        // a lone `#` and punctuation stay in the title
+        assert_eq!(parse_tags("call # #1!").0, "call # #1!");
+    }
+
+    #[test]
+    fn test_parse_field() {
+        assert_eq!(
+            parse_field("#Home, errands home").unwrap(),
+            ["home".to_string(), "errands".to_string()]
+        );
+        assert!(parse_field(" ,").unwrap().is_empty());
+        assert!(parse_field("home #1!").is_err());
+    }
+
+    #[test]
+    fn test_suggestions() {
+        let counts: BTreeMap<String, usize> = [("home", 1), ("errands", 3), ("homework", 5)]
+            .into_iter()
+            .map(|(tag, count)| (tag.to_string(), count))
+            .collect();
+        assert_eq!(suggestions(&counts, "home"), [("homework", 5), ("home", 1)]);
+        // prefixes first
+        assert_eq!(suggestions(&counts, "e")[0].0, "errands");
+        assert_eq!(suggestions(&counts, "").len(), 3);
+
+        let html = suggestions_html(&counts, "#Hom").into_string();
+        assert!(html.contains(r#"data-value="homework""#) && html.contains("Create #hom"));
+        // an exact match is not offered twice
+        assert!(!suggestions_html(&counts, "home")
+            .into_string()
+            .contains("Create"));
+        assert!(suggestions_html(&counts, "1!").into_string().is_empty());
+    }
+
+    #[test]
+    fn test_rewrite() -> anyhow::Result<()> {
+        let db = TestDb::new("tags")?;
+        let tagged = |id, tags: &[&str]| {
+            let mut todo = Todo::new(id, format!("todo {}", id));
+            todo.tags = tags.iter().map(|tag| tag.to_string()).collect();
+            Repository::new(&db).put(&todo)
+        };
+        tagged(1, &["home", "errands"])?;
+        tagged(2, &["errands"])?;
+        tagged(3, &["work"])?;
+
+        // merging into a tag the todo already has leaves one of them
+        let ops = rewrite(&db, "errands", Some("home"))?;
+        let rewritten = ops
+            .iter()
+            .filter(|op| matches!(op, WriteOp::Insert { key, .. } if key.starts_with("todo:")))
+            .count();
+        assert_eq!(rewritten, 2);
+        db.apply_batch(ops)?;
+        let todos = repository::todo::all(&db)?;
+        assert_eq!(counts(&todos).get("home"), Some(&2));
+        assert_eq!(counts(&todos).get("errands"), None);
+        assert_eq!(todos[0].version, 2);
+
+        db.apply_batch(rewrite(&db, "work", None)?)?;
+        assert!(repository::todo::all(&db)?[2].tags.is_empty());
+        assert!(rewrite(&db, "unknown", None)?.is_empty());
+        Ok(())
+    }
+}
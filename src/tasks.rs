@@ -0,0 +1,454 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Notify, RwLock, Semaphore};
+
+use crate::{
+    db::{driver::Db, queue::WriteOp},
+    events::Events,
+    integrations::{self, Delivery, Integration},
+    locale::Formatter,
+    previews,
+};
+
+// pending tasks, `task:{run_at}:{id}` with the run time zero padded so they sort by it
+const TASK_PREFIX: &str = "task:";
+// tasks that ran out of attempts, `dead:{id}`
+const DEAD_PREFIX: &str = "dead:";
+// the first retry waits this long, every further one twice as long as the one before
+const BACKOFF_BASE: Duration = Duration::from_secs(30);
+const BACKOFF_MAX: Duration = Duration::from_secs(6 * 60 * 60);
+pub const MAX_ATTEMPTS: u32 = 8;
+// due tasks picked up per pass of the dispatcher
+const BATCH: usize = 64;
+// the dispatcher looks again at least this often, in case a wakeup was missed
+const IDLE_POLL: Duration = Duration::from_secs(60);
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+// === Tasks ===
+// Outbound work that may fail and is worth retrying. New kinds go at the end, the variant index
+// is what the codec stores.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TaskKind {
+    // POST `body` as json to `url`
+    Webhook {
+        url: String,
+        body: String,
+    },
+    LinkPreview {
+        tenant: Option<String>,
+        url: String,
+    },
+    // POST `body` to the url `integration` has in the settings of `list` when it runs
+    Integration {
+        tenant: Option<String>,
+        list: String,
+        integration: Integration,
+        body: String,
+    },
+}
+impl TaskKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            TaskKind::Webhook { .. } => "webhook",
+            TaskKind::LinkPreview { .. } => "link preview",
+            TaskKind::Integration { integration, .. } => integration.label(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Task {
+    pub id: u64,
+    pub kind: TaskKind,
+    // unix millis, not before
+    pub run_at: u64,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+}
+
+fn task_key(task: &Task) -> String {
+    format!("{}{:020}:{}", TASK_PREFIX, task.run_at, task.id)
+}
+fn dead_key(id: u64) -> String {
+    format!("{}{}", DEAD_PREFIX, id)
+}
+
+// how long to wait before the next attempt after `attempts` failed ones
+pub fn backoff(attempts: u32) -> Duration {
+    BACKOFF_BASE
+        .saturating_mul(1 << attempts.saturating_sub(1).min(20))
+        .min(BACKOFF_MAX)
+}
+
+// === Outbox ===
+// The write adding a task for `kind`, due right away. It goes into the tree of the change that
+// causes it and in the same batch, so the change and its side effects are stored together or
+// not at all. The dispatcher picks tasks up from every tree.
+pub fn enqueue_op(db: &Db, kind: TaskKind) -> Result<WriteOp> {
+    let task = Task {
+        id: db.next_id()?,
+        kind,
+        run_at: db.clock().now_millis(),
+        attempts: 0,
+        last_error: None,
+    };
+    Ok(WriteOp::Insert {
+        key: task_key(&task),
+        value: db.encode(&task)?,
+    })
+}
+
+fn in_every_tree(root: &Db, prefix: &str) -> Result<Vec<Task>> {
+    let mut tasks = Vec::new();
+    for tree in root.all_trees()? {
+        for item in tree.iter_prefix::<Task>(prefix)? {
+            tasks.push(item?.1);
+        }
+    }
+    Ok(tasks)
+}
+
+// the tasks waiting for their first or next attempt, soonest first within a tree
+pub fn pending(root: &Db) -> Result<Vec<Task>> {
+    in_every_tree(root, TASK_PREFIX)
+}
+
+pub fn dead(root: &Db) -> Result<Vec<Task>> {
+    in_every_tree(root, DEAD_PREFIX)
+}
+
+// the tree holding dead task `id`, with the task
+fn find_dead(root: &Db, id: u64) -> Result<Option<(Db, Task)>> {
+    for tree in root.all_trees()? {
+        if let Some(task) = tree.get::<Task, _>(dead_key(id))? {
+            return Ok(Some((tree, task)));
+        }
+    }
+    Ok(None)
+}
+
+// Queue a dead task again with fresh attempts, false when there is no such task.
+pub fn retry_dead(root: &Db, id: u64) -> Result<bool> {
+    let Some((db, mut task)) = find_dead(root, id)? else {
+        return Ok(false);
+    };
+    task.attempts = 0;
+    task.run_at = db.clock().now_millis();
+    db.apply_batch([
+        WriteOp::Remove { key: dead_key(id) },
+        WriteOp::Insert {
+            key: task_key(&task),
+            value: db.encode(&task)?,
+        },
+    ])?;
+    Ok(true)
+}
+
+pub fn discard_dead(root: &Db, id: u64) -> Result<bool> {
+    let Some((db, _)) = find_dead(root, id)? else {
+        return Ok(false);
+    };
+    db.remove(dead_key(id))?;
+    Ok(true)
+}
+
+// The writes recording how an attempt at `task` went: gone when it worked, otherwise due again
+// after the backoff or dead once it is out of attempts.
+fn outcome(db: &Db, mut task: Task, result: Result<()>, now: u64) -> Result<Vec<WriteOp>> {
+    let mut ops = vec![WriteOp::Remove {
+        key: task_key(&task),
+    }];
+    if let Err(err) = result {
+        task.attempts += 1;
+        task.last_error = Some(err.to_string());
+        let key = if task.attempts >= MAX_ATTEMPTS {
+            tracing::warn!(task = task.id, kind = task.kind.label(), error = %err, "task is dead");
+            dead_key(task.id)
+        } else {
+            task.run_at = now + backoff(task.attempts).as_millis() as u64;
+            task_key(&task)
+        };
+        ops.push(WriteOp::Insert {
+            key,
+            value: db.encode(&task)?,
+        });
+    }
+    Ok(ops)
+}
+
+// === Queue ===
+// Runs queued tasks in the background, at most `workers` at a time. Tasks stay stored until an
+// attempt finishes, so a crash means they run again after the restart rather than not at all.
+// A task may then run twice, webhooks carry its id as `Idempotency-Key` to tell.
+#[derive(Debug, Clone)]
+pub struct TaskQueue {
+    inner: Arc<Inner>,
+}
+#[derive(Debug)]
+struct Inner {
+    lock: Arc<RwLock<Db>>,
+    events: Events,
+    client: reqwest::Client,
+    workers: Arc<Semaphore>,
+    // ids of the tasks being attempted right now
+    running: Mutex<HashSet<u64>>,
+    wakeup: Notify,
+}
+impl TaskQueue {
+    pub fn spawn(lock: Arc<RwLock<Db>>, events: Events, workers: usize) -> Result<Self> {
+        let inner = Arc::new(Inner {
+            lock,
+            events,
+            client: reqwest::Client::builder()
+                .timeout(WEBHOOK_TIMEOUT)
+                .build()?,
+            workers: Arc::new(Semaphore::new(workers.max(1))),
+            running: Mutex::new(HashSet::new()),
+            wakeup: Notify::new(),
+        });
+        tokio::spawn(dispatch(inner.clone()));
+        Ok(Self { inner })
+    }
+
+    // queue `kind` on its own, when there is no change to go with
+    pub fn enqueue(&self, db: &Db, kind: TaskKind) -> Result<()> {
+        db.apply_batch([enqueue_op(db, kind)?])?;
+        self.notify();
+        Ok(())
+    }
+
+    // have the dispatcher look for due tasks, after queueing some with `enqueue_op`
+    pub fn notify(&self) {
+        self.inner.wakeup.notify_one();
+    }
+
+    pub fn running(&self) -> usize {
+        self.inner.running.lock().map(|ids| ids.len()).unwrap_or(0)
+    }
+}
+
+async fn dispatch(inner: Arc<Inner>) {
+    loop {
+        let wait = match start_due(&inner).await {
+            Ok(wait) => wait,
+            Err(err) => {
+                tracing::error!(error = %err, "failed to dispatch tasks");
+                IDLE_POLL
+            }
+        };
+        tokio::select! {
+            _ = inner.wakeup.notified() => {}
+            _ = tokio::time::sleep(wait) => {}
+        }
+    }
+}
+
+// start every due task that is not running yet, returns how long until the next one is due
+async fn start_due(inner: &Arc<Inner>) -> Result<Duration> {
+    let root = inner.lock.read().await.clone();
+    let now = root.clock().now_millis();
+    let mut due = Vec::new();
+    let mut wait = IDLE_POLL;
+    for tree in root.all_trees()? {
+        let mut taken = 0;
+        for item in tree.iter_prefix::<Task>(TASK_PREFIX)? {
+            let (_, task) = item?;
+            if task.run_at > now {
+                wait = wait.min(Duration::from_millis(task.run_at - now));
+                break;
+            }
+            if taken == BATCH {
+                wait = Duration::ZERO;
+                break;
+            }
+            taken += 1;
+            due.push((tree.clone(), task));
+        }
+    }
+    for (tree, task) in due {
+        let claimed = inner
+            .running
+            .lock()
+            .map(|mut ids| ids.insert(task.id))
+            .unwrap_or(false);
+        if !claimed {
+            continue;
+        }
+        let permit = inner.workers.clone().acquire_owned().await?;
+        let inner = inner.clone();
+        tokio::spawn(async move {
+            let id = task.id;
+            if let Err(err) = attempt(&inner, &tree, task).await {
+                tracing::error!(task = id, error = %err, "failed to record a task attempt");
+            }
+            if let Ok(mut ids) = inner.running.lock() {
+                ids.remove(&id);
+            }
+            drop(permit);
+            inner.wakeup.notify_one();
+        });
+    }
+    Ok(wait)
+}
+
+async fn attempt(inner: &Inner, tree: &Db, task: Task) -> Result<()> {
+    let result = run(inner, tree, &task).await;
+    let now = tree.clock().now_millis();
+    // integrations keep how their last delivery went, for the badge in the settings
+    let delivered = match &task.kind {
+        TaskKind::Integration {
+            tenant,
+            list,
+            integration,
+            ..
+        } => Some((
+            tenant.clone(),
+            list.clone(),
+            *integration,
+            Delivery::of(&result, now / 1000),
+        )),
+        _ => None,
+    };
+    let mut ops = outcome(tree, task, result, now)?;
+    if let Some((_, list, integration, delivery)) = &delivered {
+        ops.push(integrations::delivery_op(
+            tree,
+            list,
+            *integration,
+            delivery,
+        )?);
+    }
+    tree.apply_batch(ops)?;
+    if let Some((tenant, _, integration, delivery)) = delivered {
+        let badge =
+            integrations::badge_html(integration, Some(&delivery), &Formatter::load(tree)?, true);
+        inner.events.publish(
+            tenant.as_deref(),
+            integrations::STATUS_EVENT,
+            badge.into_string(),
+        );
+    }
+    Ok(())
+}
+
+async fn run(inner: &Inner, tree: &Db, task: &Task) -> Result<()> {
+    match &task.kind {
+        TaskKind::Webhook { url, body } => post_json(inner, task, url, body).await,
+        TaskKind::LinkPreview { tenant, url } => {
+            let db = inner.lock.read().await.clone();
+            previews::fetch_and_publish(&db, &inner.events, tenant.as_deref(), url).await
+        }
+        TaskKind::Integration {
+            list,
+            integration,
+            body,
+            ..
+        } => {
+            // switched off since it was queued
+            let Some(url) = integrations::get(tree, list)?
+                .url(*integration)
+                .map(str::to_string)
+            else {
+                return Ok(());
+            };
+            post_json(inner, task, &url, body).await
+        }
+    }
+}
+
+async fn post_json(inner: &Inner, task: &Task, url: &str, body: &str) -> Result<()> {
+    let response = inner
+        .client
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header("Idempotency-Key", task.id.to_string())
+        .body(body.to_string())
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        bail!("{} answered {}", task.kind.label(), response.status());
+    }
+    Ok(())
+}
+
+// === Metrics ===
+// queue depth in the Prometheus text format, for `/admin/queue/metrics`
+pub fn metrics(root: &Db, running: usize) -> Result<String> {
+    Ok(format!(
+        "# TYPE tasks_pending gauge\ntasks_pending {}\n\
+         # TYPE tasks_running gauge\ntasks_running {}\n\
+         # TYPE tasks_dead gauge\ntasks_dead {}\n",
+        pending(root)?.len(),
+        running,
+        dead(root)?.len()
+    ))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+
+    use super::*;
+    use crate::fixtures::TestDb;
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(1), Duration::from_secs(30));
+        assert_eq!(backoff(3), Duration::from_secs(120));
+        assert_eq!(backoff(30), BACKOFF_MAX);
+    }
+
+    #[test]
+    fn test_retries_until_dead() -> Result<()> {
+        let db = TestDb::new("tasks")?;
+        let kind = TaskKind::Webhook {
+            url: "http://example.com/hook".into(),
+            body: "{}".into(),
+        };
+        db.apply_batch([enqueue_op(&db, kind)?])?;
+
+        for attempt in 1..=MAX_ATTEMPTS {
+            let task = pending(&db)?.remove(0);
+            db.apply_batch(outcome(&db, task, Err(anyhow!("refused")), 0)?)?;
+            if attempt < MAX_ATTEMPTS {
+                assert_eq!(pending(&db)?[0].attempts, attempt);
+            }
+        }
+        assert!(pending(&db)?.is_empty());
+        let dead = dead(&db)?;
+        assert_eq!(dead[0].last_error.as_deref(), Some("refused"));
+
+        assert!(retry_dead(&db, dead[0].id)?);
+        let task = pending(&db)?.remove(0);
+        assert_eq!(task.attempts, 0);
+        db.apply_batch(outcome(&db, task, Ok(()), 0)?)?;
+        assert!(pending(&db)?.is_empty() && super::dead(&db)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_outbox_in_tenant_tree() -> Result<()> {
+        let root = TestDb::new("outbox")?;
+        let db = root.for_tenant(Some("acme"))?;
+        let kind = TaskKind::LinkPreview {
+            tenant: Some("acme".into()),
+            url: "https://example.com".into(),
+        };
+        db.apply_batch([
+            WriteOp::Insert {
+                key: "todo:1".into(),
+                value: db.encode(&"todo")?,
+            },
+            enqueue_op(&db, kind.clone())?,
+        ])?;
+        assert_eq!(pending(&root)?[0].kind, kind);
+        Ok(())
+    }
+}
@@ -0,0 +1,208 @@
+use std::{sync::OnceLock, time::Duration};
+
+use axum::{
+    body::Body,
+    extract::MatchedPath,
+    http::{Request, Response},
+};
+use tracing::Span;
+use tracing_subscriber::{
+    fmt, layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Registry,
+};
+
+use crate::{
+    auth::user::CurrentUser,
+    config::{Config, LogFormat},
+};
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+// the `user_id` of requests in single-user mode
+const OWNER_ID: &str = "owner";
+
+// swaps the filter of the installed subscriber, see `set_filter`
+static FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+// `LOG_LEVEL`, then `RUST_LOG`, then info
+fn filter(log_level: Option<&str>) -> anyhow::Result<EnvFilter> {
+    match log_level {
+        Some(directives) => Ok(EnvFilter::try_new(directives)?),
+        None => Ok(EnvFilter::try_from_default_env()
+            .unwrap_or_else(|_| EnvFilter::new("info,tower_http=info"))),
+    }
+}
+
+// install the global subscriber in the configured format
+pub fn init(config: &Config) {
+    let filter = filter(config.log_level.as_deref()).unwrap_or_else(|err| {
+        eprintln!("invalid LOG_LEVEL, logging at info: {}", err);
+        EnvFilter::new("info,tower_http=info")
+    });
+    let (filter, handle) = reload::Layer::new(filter);
+    let _ = FILTER.set(handle);
+    let registry = tracing_subscriber::registry().with(filter);
+    match config.log_format {
+        LogFormat::Pretty => registry.with(fmt::layer()).init(),
+        LogFormat::Json => registry
+            .with(
+                fmt::layer()
+                    .json()
+                    .with_current_span(true)
+                    .with_span_list(false),
+            )
+            .init(),
+    }
+}
+
+// Change what is logged while running, for a reloaded `LOG_LEVEL`.
+pub fn set_filter(log_level: Option<&str>) -> anyhow::Result<()> {
+    let filter = filter(log_level)?;
+    if let Some(handle) = FILTER.get() {
+        handle.reload(filter)?;
+    }
+    Ok(())
+}
+
+// the span every request is processed in, the empty fields are filled in later
+pub fn make_span(request: &Request<Body>) -> Span {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(MatchedPath::as_str)
+        .unwrap_or_else(|| request.uri().path());
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("-");
+    tracing::info_span!(
+        "request",
+        method = %request.method(),
+        route,
+        request_id,
+        user_id = tracing::field::Empty,
+        tenant = tracing::field::Empty,
+        status = tracing::field::Empty,
+        latency_ms = tracing::field::Empty,
+        db_reads = tracing::field::Empty,
+        db_cache_hits = tracing::field::Empty,
+    )
+}
+
+// Who the request is for, on the request span and in the sentry scope. Called by
+// `user::require` once the user is known.
+pub fn identify(user: &CurrentUser, tenant: Option<&str>) {
+    let user_id = user.account().unwrap_or(OWNER_ID);
+    let span = Span::current();
+    span.record("user_id", user_id);
+    if let Some(tenant) = tenant {
+        span.record("tenant", tenant);
+    }
+    #[cfg(feature = "sentry")]
+    sentry::configure_scope(|scope| {
+        scope.set_user(Some(sentry::User {
+            id: Some(user_id.to_string()),
+            ..Default::default()
+        }));
+        if let Some(tenant) = tenant {
+            scope.set_tag("tenant", tenant);
+        }
+    });
+}
+
+// record the outcome on the request span and emit a single summary line
+pub fn on_response(response: &Response<Body>, latency: Duration, span: &Span) {
+    span.record("status", response.status().as_u16());
+    span.record("latency_ms", latency.as_millis() as u64);
+    tracing::info!("finished processing request");
+}
+
+// === Sentry ===
+// keep the returned guard alive for the lifetime of the process, dropping it flushes pending events
+#[cfg(feature = "sentry")]
+pub fn init_sentry(config: &Config) -> Option<sentry::ClientInitGuard> {
+    let dsn = config.sentry_dsn.as_deref()?;
+    let guard = sentry::init((
+        dsn,
+        sentry::ClientOptions {
+            release: sentry::release_name!(),
+            ..Default::default()
+        },
+    ));
+    Some(guard)
+}
+
+// attach the request context to the per-request sentry hub
+#[cfg(feature = "sentry")]
+pub async fn sentry_context(
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_owned())
+        .unwrap_or_else(|| request.uri().path().to_owned());
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned);
+    sentry::configure_scope(|scope| {
+        scope.set_tag("route", route);
+        if let Some(request_id) = request_id {
+            scope.set_tag("request_id", request_id);
+        }
+    });
+    next.run(request).await
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+
+    use serde_json::Value;
+
+    use super::*;
+
+    // what the json layer wrote, shared with the test
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+    impl io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_user_id_is_logged() {
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::registry().with(
+            fmt::layer()
+                .json()
+                .with_current_span(true)
+                .with_span_list(false)
+                .with_writer(move || writer.clone()),
+        );
+        tracing::subscriber::with_default(subscriber, || {
+            let request = Request::get("/todos").body(Body::empty()).unwrap();
+            make_span(&request).in_scope(|| {
+                identify(&CurrentUser::Account("ada".into()), Some("user:ada"));
+                tracing::info!("finished processing request");
+            });
+        });
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        let line: Value = serde_json::from_str(output.lines().next().unwrap()).unwrap();
+        assert_eq!(line["span"]["user_id"], "ada");
+        assert_eq!(line["span"]["tenant"], "user:ada");
+        assert_eq!(line["span"]["route"], "/todos");
+    }
+}
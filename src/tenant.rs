@@ -0,0 +1,203 @@
+use anyhow::Result;
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{header, request::Parts},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accounts,
+    auth::{user::CurrentUser, visitor::Visitor},
+    config::{AuthMode, Tenancy},
+    db::driver::Db,
+    error::AppError,
+    guest,
+    state::AppState,
+};
+
+// registered tenants are stored in the default tree, `tenant:{id}`
+const REGISTRY_PREFIX: &str = "tenant:";
+// the accounts that may work in a tenant, `tenant_member:{id}:{account}`
+const MEMBER_PREFIX: &str = "tenant_member:";
+
+// === Tenant ===
+// The workspace a request operates on, `None` is the default workspace. In guest mode the base
+// domain is the visitor's guest workspace instead, with `AUTH_MODE=users` the signed-in
+// account's own. A subdomain only resolves for the accounts that are members of its tenant,
+// for everyone else it is as unknown as one that was never created.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tenant(pub Option<String>);
+impl Tenant {
+    pub fn id(&self) -> Option<&str> {
+        self.0.as_deref()
+    }
+    pub fn is_guest(&self) -> bool {
+        self.id().is_some_and(guest::is_guest)
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for Tenant {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let base_domain = match &state.config.tenancy {
+            Tenancy::Single => return Ok(Tenant(account_workspace(parts, state))),
+            Tenancy::Subdomain { base_domain } => base_domain,
+        };
+        let host = parts
+            .headers
+            .get(header::HOST)
+            .and_then(|host| host.to_str().ok())
+            .unwrap_or_default();
+        let id = match subdomain(host, base_domain) {
+            Some(id) => id,
+            None if host_name(host) == base_domain => {
+                if !state.config.guest_mode {
+                    return Ok(Tenant(None));
+                }
+                let visitor = parts
+                    .extensions
+                    .get::<Visitor>()
+                    .ok_or(AppError::NotFound)?;
+                return Ok(Tenant(Some(guest::tenant_id(visitor))));
+            }
+            None => return Err(AppError::NotFound),
+        };
+        // only tenants created by an admin resolve, unknown subdomains are a 404
+        let db = state.read().await;
+        if get(&db, id)?.is_none() {
+            return Err(AppError::NotFound);
+        }
+        // shared links and hooks carry their own token, there is no account to check then
+        if let Some(CurrentUser::Account(account)) = parts.extensions.get::<CurrentUser>() {
+            if !is_member(&db, id, account)? {
+                return Err(AppError::NotFound);
+            }
+        }
+        Ok(Tenant(Some(id.to_string())))
+    }
+}
+
+// resolved by `user::require` before any handler asks
+fn account_workspace(parts: &Parts, state: &AppState) -> Option<String> {
+    if state.config.auth_mode != AuthMode::Users {
+        return None;
+    }
+    match parts.extensions.get::<CurrentUser>()? {
+        CurrentUser::Account(name) => Some(accounts::workspace(name)),
+        CurrentUser::Owner => None,
+    }
+}
+
+fn host_name(host: &str) -> &str {
+    host.split(':').next().unwrap_or_default()
+}
+// `acme` for `acme.example.com` under `example.com`
+fn subdomain<'a>(host: &'a str, base_domain: &str) -> Option<&'a str> {
+    let id = host_name(host)
+        .strip_suffix(base_domain)?
+        .strip_suffix('.')?;
+    is_valid_id(id).then_some(id)
+}
+pub fn is_valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 63
+        && id
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
+
+// === Registry ===
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TenantRecord {
+    pub id: String,
+    pub name: String,
+}
+
+pub fn get(db: &Db, id: &str) -> Result<Option<TenantRecord>> {
+    db.get(format!("{}{}", REGISTRY_PREFIX, id))
+}
+pub fn list(db: &Db) -> Result<Vec<TenantRecord>> {
+    db.iter_prefix::<TenantRecord>(REGISTRY_PREFIX)?
+        .map(|item| item.map(|(_, record)| record))
+        .collect()
+}
+pub fn register(db: &Db, record: &TenantRecord) -> Result<()> {
+    db.insert(format!("{}{}", REGISTRY_PREFIX, record.id), record)
+}
+// Remove the tenant and erase all of its data.
+pub fn erase(db: &Db, id: &str) -> Result<()> {
+    db.drop_tenant(id)?;
+    let members = db
+        .iter_keys(&member_key(id, ""))
+        .collect::<Result<Vec<_>>>()?;
+    db.remove_all(members)?;
+    db.remove(format!("{}{}", REGISTRY_PREFIX, id))
+}
+
+// === Members ===
+fn member_key(id: &str, account: &str) -> String {
+    format!("{}{}:{}", MEMBER_PREFIX, id, account)
+}
+
+pub fn members(db: &Db, id: &str) -> Result<Vec<String>> {
+    let prefix = member_key(id, "");
+    db.iter_keys(&prefix)
+        .map(|key| key.map(|key| key[prefix.len()..].to_string()))
+        .collect()
+}
+pub fn is_member(db: &Db, id: &str, account: &str) -> Result<bool> {
+    Ok(db.get::<(), _>(member_key(id, account))?.is_some())
+}
+pub fn add_member(db: &Db, id: &str, account: &str) -> Result<()> {
+    db.insert(member_key(id, account), &())
+}
+pub fn remove_member(db: &Db, id: &str, account: &str) -> Result<()> {
+    db.remove(member_key(id, account))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::TestDb;
+
+    #[test]
+    fn test_subdomain() {
+        assert_eq!(subdomain("acme.example.com", "example.com"), Some("acme"));
+        assert_eq!(
+            subdomain("acme.example.com:3000", "example.com"),
+            Some("acme")
+        );
+        assert_eq!(subdomain("example.com", "example.com"), None);
+        assert_eq!(subdomain("acmeexample.com", "example.com"), None);
+        assert_eq!(subdomain("a.b.example.com", "example.com"), None);
+        assert_eq!(subdomain("ACME.example.com", "example.com"), None);
+    }
+
+    #[test]
+    fn test_members() -> Result<()> {
+        let db = TestDb::new("tenant_members")?;
+        let acme = TenantRecord {
+            id: "acme".into(),
+            name: "Acme".into(),
+        };
+        register(&db, &acme)?;
+        add_member(&db, "acme", "ada")?;
+        add_member(&db, "acme", "bob")?;
+        assert!(is_member(&db, "acme", "ada")?);
+        assert!(!is_member(&db, "acme", "eve")?);
+        // `acme` is not a prefix of `acme-labs`
+        assert!(!is_member(&db, "acme-labs", "ada")?);
+        remove_member(&db, "acme", "bob")?;
+        assert_eq!(members(&db, "acme")?, ["ada"]);
+        erase(&db, "acme")?;
+        assert!(members(&db, "acme")?.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,238 @@
+use std::{fmt::Write, str::FromStr};
+
+use anyhow::anyhow;
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use maud::{html, Markup};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    branding, db::driver::Db, error::AppError, extract::FormOrJson, routes, state::AppState,
+    tenant::Tenant, views,
+};
+
+// per workspace, everyone sharing it sees the same palette
+const THEME_KEY: &str = "theme";
+
+// === Themes ===
+// Markup uses the semantic `t-*` classes below instead of colors, `/theme.css` maps them to the
+// palette of the workspace's theme.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Theme {
+    #[default]
+    Default,
+    HighContrast,
+    // the Okabe-Ito palette, told apart with any kind of color blindness
+    ColorBlind,
+}
+impl Theme {
+    pub const ALL: [Theme; 3] = [Theme::Default, Theme::HighContrast, Theme::ColorBlind];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Theme::Default => "default",
+            Theme::HighContrast => "high-contrast",
+            Theme::ColorBlind => "color-blind",
+        }
+    }
+    pub fn label(&self) -> &'static str {
+        match self {
+            Theme::Default => "Default",
+            Theme::HighContrast => "High contrast",
+            Theme::ColorBlind => "Color-blind safe",
+        }
+    }
+    // the color browsers tint their own chrome with, see `pwa::manifest`
+    pub fn accent(&self) -> &'static str {
+        self.palette().accent
+    }
+
+    fn palette(&self) -> Palette {
+        match self {
+            Theme::Default => Palette {
+                text: "#374151",
+                muted: "#6b7280",
+                accent: "#2563eb",
+                accent_soft: "#dbeafe",
+                success: "#16a34a",
+                warning: "#854d0e",
+                warning_soft: "#fef08a",
+                danger: "#dc2626",
+            },
+            Theme::HighContrast => Palette {
+                text: "#000000",
+                muted: "#1f2937",
+                accent: "#0000ee",
+                accent_soft: "#ffffff",
+                success: "#006400",
+                warning: "#000000",
+                warning_soft: "#ffd700",
+                danger: "#b00000",
+            },
+            Theme::ColorBlind => Palette {
+                text: "#1f2937",
+                muted: "#4b5563",
+                accent: "#0072b2",
+                accent_soft: "#d6ebf7",
+                success: "#009e73",
+                warning: "#000000",
+                warning_soft: "#e69f00",
+                danger: "#d55e00",
+            },
+        }
+    }
+}
+impl FromStr for Theme {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|theme| theme.as_str() == s)
+            .ok_or_else(|| anyhow!("unknown theme `{}`", s))
+    }
+}
+
+struct Palette {
+    text: &'static str,
+    muted: &'static str,
+    accent: &'static str,
+    accent_soft: &'static str,
+    success: &'static str,
+    warning: &'static str,
+    warning_soft: &'static str,
+    danger: &'static str,
+}
+
+pub fn get(db: &Db) -> anyhow::Result<Theme> {
+    Ok(db.get(THEME_KEY)?.unwrap_or_default())
+}
+pub fn set(db: &Db, theme: Theme) -> anyhow::Result<()> {
+    db.insert(THEME_KEY, &theme)
+}
+
+// The tokens as custom properties and the classes using them. `accent` is the instance's from
+// `branding`, its soft variant a see-through tint of it.
+pub fn css(theme: Theme, accent: Option<&str>) -> String {
+    let palette = theme.palette();
+    let accent_soft = accent.map(|accent| format!("{}26", accent));
+    let mut css = String::from(":root {\n");
+    for (name, value) in [
+        ("text", palette.text),
+        ("muted", palette.muted),
+        ("accent", accent.unwrap_or(palette.accent)),
+        (
+            "accent-soft",
+            accent_soft.as_deref().unwrap_or(palette.accent_soft),
+        ),
+        ("success", palette.success),
+        ("warning", palette.warning),
+        ("warning-soft", palette.warning_soft),
+        ("danger", palette.danger),
+    ] {
+        let _ = writeln!(css, "    --t-{}: {};", name, value);
+    }
+    css.push_str(
+        r#"}
+.t-text { color: var(--t-text); }
+.t-muted { color: var(--t-muted); }
+.t-accent { color: var(--t-accent); }
+.t-success { color: var(--t-success); }
+.t-danger { color: var(--t-danger); }
+.t-chip { color: var(--t-accent); background: var(--t-accent-soft); border: 1px solid var(--t-accent); }
+.t-warning { color: var(--t-warning); background: var(--t-warning-soft); }
+"#,
+    );
+    if theme == Theme::HighContrast {
+        css.push_str("a:focus, button:focus, input:focus { outline: 3px solid var(--t-accent); outline-offset: 2px; }\n");
+    }
+    css
+}
+
+// === Components ===
+pub fn settings_html(current: Theme) -> Markup {
+    html! {
+        section id="theme" class="bg-white rounded-lg shadow-lg p-6 space-y-4" {
+            h2 class="text-2xl text-gray-700" { "Theme" }
+            form class="space-y-2" method="post" action=(routes::ThemeSetting::url())
+                hx-post=(routes::ThemeSetting::url()) hx-trigger="change" hx-target="#theme" hx-swap="outerHTML" {
+                @for theme in Theme::ALL {
+                    label class="flex items-center space-x-2" {
+                        input type="radio" name="theme" value=(theme.as_str()) checked[theme == current];
+                        span { (theme.label()) }
+                    }
+                }
+                noscript {
+                    button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" { "Save" }
+                }
+            }
+        }
+    }
+}
+
+// === Routes ===
+// linked from every page, the head of a page is rendered without the workspace at hand
+pub async fn stylesheet(
+    State(state): State<AppState>,
+    tenant: Tenant,
+) -> Result<Response, AppError> {
+    let db = state.read().await;
+    let theme = get(&db.for_tenant(tenant.id())?)?;
+    let accent = branding::accent(&db, theme)?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/css; charset=utf-8"),
+            // revalidated on every page, a changed theme shows up right away
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        css(theme, accent.as_deref()),
+    )
+        .into_response())
+}
+
+#[derive(Deserialize)]
+pub struct SetTheme {
+    theme: String,
+}
+pub async fn set_theme(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    FormOrJson(SetTheme { theme }): FormOrJson<SetTheme>,
+) -> Result<Response, AppError> {
+    let theme: Theme = theme
+        .parse()
+        .map_err(|err: anyhow::Error| AppError::Invalid(err.to_string()))?;
+    let guard = state.write().await;
+    set(&guard.for_tenant(tenant.id())?, theme)?;
+    let mut response =
+        views::fragment_or_redirect(&headers, settings_html(theme), &routes::Settings::url());
+    // the stylesheet is already loaded, the page has to fetch it again
+    response
+        .headers_mut()
+        .insert("hx-refresh", HeaderValue::from_static("true"));
+    Ok(response)
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_themes() {
+        for theme in Theme::ALL {
+            assert_eq!(theme.as_str().parse::<Theme>().unwrap(), theme);
+            let css = css(theme, None);
+            for class in [".t-muted", ".t-chip", ".t-warning", ".t-danger"] {
+                assert!(css.contains(class), "{} misses {}", theme.as_str(), class);
+            }
+        }
+        assert!(css(Theme::ColorBlind, None).contains("#e69f00"));
+        assert!(css(Theme::Default, Some("#ff6600")).contains("--t-accent-soft: #ff660026;"));
+        assert!("neon".parse::<Theme>().is_err());
+    }
+}
@@ -0,0 +1,231 @@
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    response::Response,
+};
+use maud::{html, Markup};
+use serde::Deserialize;
+use time::{Date, Duration};
+
+use crate::{
+    db::driver::Db,
+    error::AppError,
+    extract::FormOrJson,
+    history,
+    locale::Formatter,
+    method_override,
+    models::Todo,
+    repository::{
+        entity::Repository,
+        query::{Sort, TodoQuery},
+    },
+    routes, scheduled,
+    state::AppState,
+    tenant::Tenant,
+    views::{
+        self, dom,
+        nav::{self, Nav},
+    },
+};
+
+const TODAY_ID: &str = "today";
+
+// === Rescheduling ===
+// where a chip moves a todo's due date, from today in the workspace's timezone
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Chip {
+    Tomorrow,
+    // the coming monday
+    NextWeek,
+}
+impl Chip {
+    pub const ALL: [Chip; 2] = [Chip::Tomorrow, Chip::NextWeek];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Chip::Tomorrow => "tomorrow",
+            Chip::NextWeek => "next_week",
+        }
+    }
+    pub fn label(&self) -> &'static str {
+        match self {
+            Chip::Tomorrow => "Tomorrow",
+            Chip::NextWeek => "Next week",
+        }
+    }
+    pub fn due(&self, today: Date) -> Date {
+        match self {
+            Chip::Tomorrow => today + Duration::days(1),
+            Chip::NextWeek => {
+                today + Duration::days(7 - i64::from(today.weekday().number_days_from_monday()))
+            }
+        }
+    }
+}
+
+// Open todos due today or before, soonest first, so what slipped is on top.
+pub fn due_by(db: &Db, today: Date) -> anyhow::Result<Vec<Todo>> {
+    TodoQuery::new()
+        .completed(false)
+        .due_between(Date::MIN, today)
+        .sort(Sort::Due)
+        .list(db)
+}
+
+// what is due by today, then what starts today and is not due yet
+pub fn for_today(db: &Db, today: Date) -> anyhow::Result<Vec<Todo>> {
+    let mut todos = due_by(db, today)?;
+    let starting = scheduled::starting_on(db, today)?;
+    todos.extend(
+        starting
+            .into_iter()
+            .filter(|todo| !todo.due.is_some_and(|due| due <= today)),
+    );
+    Ok(todos)
+}
+
+// === Components ===
+fn todo_html(todo: &Todo, dates: &Formatter) -> Markup {
+    let url = routes::TodoDue::url(todo.id);
+    html! {
+        li id=(dom::today(todo.id)) class="flex justify-between items-center bg-white rounded p-2" {
+            div {
+                a class="text-gray-700 hover:text-blue-700" href=(routes::TodoDetail::url(todo.id)) { (todo.title) }
+                @if let Some(due) = todo.due {
+                    @if due < dates.today() {
+                        span class="ml-2 text-sm text-red-600" { (dates.due(due)) }
+                    }
+                }
+            }
+            div class="flex space-x-2" {
+                @for chip in Chip::ALL {
+                    // without javascript each chip is a form posting the override
+                    form method="post" action=(url) hx-patch=(url) hx-target=(dom::selector(&dom::today(todo.id))) hx-swap="outerHTML" {
+                        input type="hidden" name=(method_override::METHOD_FIELD) value="PATCH";
+                        input type="hidden" name="to" value=(chip.as_str());
+                        button class="text-sm bg-gray-100 hover:bg-gray-200 text-gray-700 rounded-full py-1 px-3" type="submit" {
+                            (chip.label())
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn today_html(todos: &[Todo], dates: &Formatter) -> Markup {
+    html! {
+        div id=(TODAY_ID) {
+            @if todos.is_empty() {
+                p class="text-center text-gray-500" { "Nothing due or starting today." }
+            } @else {
+                ul class="space-y-2" {
+                    @for todo in todos {
+                        (todo_html(todo, dates))
+                    }
+                }
+            }
+        }
+    }
+}
+
+// === Routes ===
+pub async fn index(
+    State(state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+) -> Result<Markup, AppError> {
+    let db = state.read().await.for_tenant(tenant.id())?;
+    let dates = Formatter::load(&db)?;
+    let todos = for_today(&db, dates.today())?;
+    let today = today_html(&todos, &dates);
+    if views::wants_fragment(&headers) {
+        return Ok(today);
+    }
+    Ok(views::page(
+        "Today",
+        html! {
+            (nav::navigation(&Nav::today()))
+            h1 class="text-4xl text-center text-gray-700 mb-2" { "Today" }
+            p class="text-center text-gray-500 mb-6" { (dates.date(dates.today())) }
+            (today)
+        },
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct Reschedule {
+    to: Chip,
+}
+// moves the due date, the todo leaves the page
+pub async fn reschedule(
+    State(mut app_state): State<AppState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Path(id): Path<u64>,
+    FormOrJson(Reschedule { to }): FormOrJson<Reschedule>,
+) -> Result<Response, AppError> {
+    let guard = app_state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    let todos = Repository::<Todo>::new(&db);
+    let dates = Formatter::load(&db)?;
+    let due = to.due(dates.today());
+    let todo = db.transaction(|tx| {
+        let Some(mut todo) = todos.get_in(tx, id)? else {
+            return Ok(None);
+        };
+        tx.apply_batch(history::record_ops(&db, &todo)?)?;
+        todo.due = Some(due);
+        todo.touch(&**db.clock());
+        todos.put_in(tx, &todo)?;
+        Ok(Some(todo))
+    })?;
+    let todo = todo.ok_or(AppError::NotFound)?;
+    Ok(views::fragment_or_redirect(
+        &headers,
+        views::notice_toast_oob(&format!("\"{}\" is now {}.", todo.title, dates.due(due))),
+        &routes::Today::url(),
+    ))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use time::macros::date;
+
+    use super::*;
+    use crate::fixtures::{TestDb, TodoFixture};
+
+    #[test]
+    fn test_chips() {
+        // a wednesday
+        let today = date!(2024 - 03 - 06);
+        assert_eq!(Chip::Tomorrow.due(today), date!(2024 - 03 - 07));
+        assert_eq!(Chip::NextWeek.due(today), date!(2024 - 03 - 11));
+        // on a monday next week is a week out
+        assert_eq!(
+            Chip::NextWeek.due(date!(2024 - 03 - 11)),
+            date!(2024 - 03 - 18)
+        );
+    }
+
+    #[test]
+    fn test_due_by() -> anyhow::Result<()> {
+        let db = TestDb::new("today")?;
+        let today = db.clock().now().date();
+        let due = TodoFixture::new().due_in_days(0).persist(&db)?;
+        let overdue = TodoFixture::new().due_in_days(-5).persist(&db)?;
+        TodoFixture::new().due_in_days(1).persist(&db)?;
+        TodoFixture::new().persist(&db)?;
+        TodoFixture::new().due_in_days(0).completed().persist(&db)?;
+        let ids = |todos: Vec<Todo>| todos.iter().map(|todo| todo.id).collect::<Vec<_>>();
+        assert_eq!(ids(due_by(&db, today)?), [overdue.id, due.id]);
+        let starting = TodoFixture::new().scheduled_in_days(0).persist(&db)?;
+        assert_eq!(
+            ids(for_today(&db, today)?),
+            [overdue.id, due.id, starting.id]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,172 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Request, State},
+    http::{header, HeaderMap},
+    middleware::Next,
+    response::Response,
+};
+use maud::{html, Markup};
+
+use crate::{db::loader::Loader, error::AppError, state::AppState, views};
+
+const TOOLBAR_ID: &str = "dev-toolbar";
+// reads above this in one request are flagged, usually a component scanning per item
+const READS_WARNING: u64 = 50;
+
+// === Metrics ===
+// With `DEV_MODE`, what the current request did: its db reads and writes come from the request's
+// `Loader`, the events it published are collected here. Outside a request nothing is kept.
+tokio::task_local! {
+    static EMITTED: Emitted;
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Emitted(Arc<Mutex<Vec<String>>>);
+impl Emitted {
+    pub fn names(&self) -> Vec<String> {
+        self.0.lock().expect("toolbar lock poisoned").clone()
+    }
+    pub async fn scope<F: std::future::Future>(&self, future: F) -> F::Output {
+        EMITTED.scope(self.clone(), future).await
+    }
+}
+
+// an event went out, see `Events::publish`
+pub(crate) fn emitted(name: &str) {
+    let _ = EMITTED.try_with(|emitted| {
+        emitted
+            .0
+            .lock()
+            .expect("toolbar lock poisoned")
+            .push(name.to_string())
+    });
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metrics {
+    pub reads: u64,
+    pub hits: u64,
+    pub writes: u64,
+    pub millis: u128,
+    pub events: Vec<String>,
+}
+
+// === Components ===
+pub fn toolbar_html(metrics: &Metrics, oob: bool) -> Markup {
+    let heavy = metrics.reads > READS_WARNING;
+    html! {
+        div id=(TOOLBAR_ID) hx-swap-oob=[oob.then_some("true")] role="status"
+            class="fixed bottom-0 right-0 m-2 px-3 py-1 rounded bg-gray-900/90 text-white text-xs font-mono space-x-3 z-50" {
+            span { (metrics.millis) "ms" }
+            span class=[heavy.then_some("text-red-400")]
+                title=[heavy.then_some("Many reads for one request, is something scanning per item?")] {
+                (metrics.reads) " reads"
+            }
+            span { (metrics.hits) " cached" }
+            span { (metrics.writes) " writes" }
+            span title=(metrics.events.join(", ")) { (metrics.events.len()) " events" }
+        }
+    }
+}
+
+// A page gets the toolbar before `</body>`, a fragment swaps it in out of band.
+fn append(body: &str, metrics: &Metrics) -> String {
+    match body.rfind("</body>") {
+        Some(end) => format!(
+            "{}{}{}",
+            &body[..end],
+            toolbar_html(metrics, false).into_string(),
+            &body[end..]
+        ),
+        None => format!("{}{}", body, toolbar_html(metrics, true).into_string()),
+    }
+}
+
+fn is_html(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/html"))
+}
+
+// === Middleware ===
+// Inside the loader's layer, whose counts it shows. Only html gets the toolbar, event streams
+// and everything else pass through.
+pub async fn layer(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    if !state.config.dev_mode || views::wants_json(request.headers()) {
+        return Ok(next.run(request).await);
+    }
+    let loader = request.extensions().get::<Loader>().cloned();
+    let collected = Emitted::default();
+    let started = Instant::now();
+    let response = collected.scope(next.run(request)).await;
+    let millis = started.elapsed().as_millis();
+    let Some(loader) = loader.filter(|_| is_html(response.headers())) else {
+        return Ok(response);
+    };
+    let (mut parts, body) = response.into_parts();
+    let body = to_bytes(body, usize::MAX)
+        .await
+        .map_err(|err| AppError::Invalid(err.to_string()))?;
+    let metrics = Metrics {
+        reads: loader.reads(),
+        hits: loader.hits(),
+        writes: loader.writes(),
+        millis,
+        events: collected.names(),
+    };
+    let body = append(&String::from_utf8_lossy(&body), &metrics);
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metrics() -> Metrics {
+        Metrics {
+            reads: 3,
+            hits: 1,
+            writes: 0,
+            millis: 12,
+            events: vec!["todos".into()],
+        }
+    }
+
+    #[test]
+    fn test_append() {
+        let page = append("<html><body><main></main></body></html>", &metrics());
+        assert!(page.contains("</main><div id=\"dev-toolbar\""));
+        assert!(!page.contains("hx-swap-oob"));
+        assert!(page.ends_with("</body></html>"));
+
+        let fragment = append("<li>Milk</li>", &metrics());
+        assert!(fragment.starts_with("<li>Milk</li><div id=\"dev-toolbar\" hx-swap-oob=\"true\""));
+        assert!(fragment.contains("3 reads"));
+    }
+
+    #[tokio::test]
+    async fn test_events_are_collected_in_scope() {
+        let collected = Emitted::default();
+        collected
+            .scope(async {
+                emitted("todos");
+                emitted("toast");
+            })
+            .await;
+        // outside a request nothing is kept
+        emitted("todos");
+        assert_eq!(collected.names(), ["todos", "toast"]);
+    }
+}
@@ -0,0 +1,103 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use tao::event_loop::{ControlFlow, EventLoopBuilder};
+use time::Date;
+use tokio::runtime::Handle;
+use tray_icon::{
+    menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem},
+    Icon, TrayIconBuilder,
+};
+
+use crate::{repository::query::TodoQuery, routes, state::AppState};
+
+// how often the count of todos left today is refreshed
+const REFRESH: Duration = Duration::from_secs(30);
+const ICON_SIZE: u32 = 32;
+
+// === Tray ===
+// `--tray`: the server runs on localhost in the background while this sits in the system tray,
+// showing how many todos are left today. Runs the platform event loop, so it has to be called
+// from the main thread, and never returns.
+pub fn run(state: AppState, url: String, runtime: Handle) -> Result<()> {
+    let event_loop = EventLoopBuilder::new().build();
+
+    let open = MenuItem::new("Open", true, None);
+    let quick_add = MenuItem::new("Quick add…", true, None);
+    let quit = MenuItem::new("Quit", true, None);
+    let menu = Menu::new();
+    menu.append_items(&[&open, &quick_add, &PredefinedMenuItem::separator(), &quit])?;
+    let tray = TrayIconBuilder::new()
+        .with_menu(Box::new(menu))
+        .with_tooltip("Todos")
+        .with_icon(icon()?)
+        .build()?;
+
+    if let Err(err) = open::that(&url) {
+        tracing::warn!(%err, "could not open the browser");
+    }
+
+    let mut refreshed: Option<Instant> = None;
+    event_loop.run(move |_event, _, control_flow| {
+        *control_flow = ControlFlow::WaitUntil(Instant::now() + Duration::from_millis(250));
+
+        if let Ok(event) = MenuEvent::receiver().try_recv() {
+            let page = if event.id() == open.id() {
+                Some(routes::Root::url())
+            } else if event.id() == quick_add.id() {
+                Some(routes::QuickAdd::url())
+            } else {
+                None
+            };
+            if let Some(page) = page {
+                if let Err(err) = open::that(format!("{}{}", url, page)) {
+                    tracing::warn!(%err, "could not open the browser");
+                }
+            }
+            if event.id() == quit.id() {
+                // nothing else flushes the database, the process just ends
+                if let Err(err) = runtime.block_on(async { state.read().await.flush() }) {
+                    tracing::error!(%err, "failed to flush the database");
+                }
+                *control_flow = ControlFlow::Exit;
+                return;
+            }
+        }
+
+        if refreshed.map_or(true, |at| at.elapsed() >= REFRESH) {
+            refreshed = Some(Instant::now());
+            let today = state.clock.now().date();
+            match runtime.block_on(left_today(&state, today)) {
+                Ok(left) => {
+                    let _ = tray.set_tooltip(Some(format!("{} left today", left)));
+                    tray.set_title(Some(left.to_string()));
+                }
+                Err(err) => tracing::error!(%err, "failed to count todos left today"),
+            }
+        }
+    })
+}
+
+// open todos of the default workspace due today or earlier
+async fn left_today(state: &AppState, today: Date) -> Result<usize> {
+    let todos = TodoQuery::new()
+        .completed(false)
+        .due_between(Date::MIN, today)
+        .list(&state.read().await)?;
+    Ok(todos.len())
+}
+
+// a filled blue circle
+fn icon() -> Result<Icon> {
+    let center = ICON_SIZE as f32 / 2.0;
+    let mut rgba = Vec::with_capacity((ICON_SIZE * ICON_SIZE * 4) as usize);
+    for y in 0..ICON_SIZE {
+        for x in 0..ICON_SIZE {
+            let distance =
+                ((x as f32 + 0.5 - center).powi(2) + (y as f32 + 0.5 - center).powi(2)).sqrt();
+            let alpha = if distance <= center - 1.0 { 255 } else { 0 };
+            rgba.extend_from_slice(&[59, 130, 246, alpha]);
+        }
+    }
+    Ok(Icon::from_rgba(rgba, ICON_SIZE, ICON_SIZE)?)
+}
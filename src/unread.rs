@@ -0,0 +1,187 @@
+use std::collections::{BTreeMap, HashSet};
+
+use anyhow::Result;
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Response},
+};
+use maud::{html, Markup};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    activity::{self, Entry},
+    db::driver::Db,
+    error::AppError,
+    lists, routes,
+    state::AppState,
+    tenant::Tenant,
+};
+
+// `list_seen:{member}:{list}`
+const SEEN_PREFIX: &str = "list_seen:";
+const BADGES_ID: &str = "list-badges";
+pub const DIVIDER_ID: &str = "unread-divider";
+
+// === Read marks ===
+// How far into the activity of a list each member has seen, by the last entry of the list they
+// had when they last looked at it. What others changed after it is new to them. Before their
+// first visit nothing is, a list doesn't start out with everything unread.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Mark {
+    // unix seconds and id of the entry, the order of the log
+    pub at: u64,
+    pub id: u64,
+}
+impl Mark {
+    fn of(entry: &Entry) -> Self {
+        Self {
+            at: entry.at,
+            id: entry.id,
+        }
+    }
+}
+
+fn seen_prefix(member: &str) -> String {
+    format!("{}{}:", SEEN_PREFIX, member)
+}
+fn seen_key(member: &str, list: &str) -> String {
+    format!("{}{}", seen_prefix(member), list)
+}
+
+pub fn seen(db: &Db, member: &str, list: &str) -> Result<Option<Mark>> {
+    db.get(seen_key(member, list))
+}
+
+// `member` has seen everything in `list` so far
+pub fn mark_seen(db: &Db, member: &str, list: &str) -> Result<()> {
+    let last = activity::in_list(db, list)?
+        .last()
+        .map(Mark::of)
+        .unwrap_or_default();
+    if seen(db, member, list)? == Some(last) {
+        return Ok(());
+    }
+    db.insert(seen_key(member, list), &last)
+}
+
+// what others did in `list` since `member` last looked
+pub fn unseen(db: &Db, member: &str, list: &str) -> Result<Vec<Entry>> {
+    let Some(mark) = seen(db, member, list)? else {
+        return Ok(Vec::new());
+    };
+    Ok(activity::in_list(db, list)?
+        .into_iter()
+        .filter(|entry| Mark::of(entry) > mark && entry.actor.as_deref() != Some(member))
+        .collect())
+}
+
+// the todos of those changes, for the divider in the list
+pub fn unseen_todos(db: &Db, member: &str, list: &str) -> Result<HashSet<u64>> {
+    Ok(unseen(db, member, list)?
+        .iter()
+        .map(|entry| entry.todo)
+        .collect())
+}
+
+// how many changes are new to `member`, per list they looked at before
+pub fn counts(db: &Db, member: &str) -> Result<BTreeMap<String, usize>> {
+    let prefix = seen_prefix(member);
+    let mut counts = BTreeMap::new();
+    for item in db.iter_prefix::<Mark>(&prefix)? {
+        let (key, _) = item?;
+        let list = key.trim_start_matches(&prefix);
+        let new = unseen(db, member, list)?.len();
+        if new > 0 {
+            counts.insert(list.to_string(), new);
+        }
+    }
+    Ok(counts)
+}
+
+// === Components ===
+// the lists with something new, in the navigation
+pub fn badges_html(counts: &BTreeMap<String, usize>, oob: bool) -> Markup {
+    html! {
+        li id=(BADGES_ID) class="flex space-x-2" hidden[counts.is_empty()] hx-swap-oob=[oob.then_some("true")] {
+            @for (list, new) in counts {
+                a class="text-xs rounded px-2 py-1 bg-blue-100 text-blue-700 hover:bg-blue-200" href=(lists::url(Some(list))) {
+                    (list) " · " (new) " new"
+                }
+            }
+        }
+    }
+}
+
+// Above the first item that changed since the last visit. Rendering it marks the list seen.
+pub fn divider_html() -> Markup {
+    html! {
+        li id=(DIVIDER_ID) role="separator" class="flex items-center my-2 text-xs text-blue-500" {
+            span class="flex-grow border-t border-blue-200" {}
+            span class="px-2" { "New since your last visit" }
+            span class="flex-grow border-t border-blue-200" {}
+        }
+    }
+}
+
+// Posts the visit once the list is on screen, which answers with the badges without it.
+pub fn seen_trigger(list: &str) -> Markup {
+    html! {
+        div hidden hx-post=(routes::ListSeen::url(list)) hx-trigger="load" hx-swap="none" {}
+    }
+}
+
+// === Routes ===
+pub async fn badges(State(state): State<AppState>, tenant: Tenant) -> Result<Markup, AppError> {
+    let Some(member) = activity::actor() else {
+        return Ok(badges_html(&BTreeMap::new(), false));
+    };
+    let db = state.read().await.for_tenant(tenant.id())?;
+    Ok(badges_html(&counts(&db, &member)?, false))
+}
+
+pub async fn mark(
+    State(mut state): State<AppState>,
+    tenant: Tenant,
+    Path(list): Path<String>,
+) -> Result<Response, AppError> {
+    let list = lists::parse(&list)?.ok_or(AppError::NotFound)?;
+    let Some(member) = activity::actor() else {
+        return Ok(html! {}.into_response());
+    };
+    let guard = state.write().await;
+    let db = guard.for_tenant(tenant.id())?;
+    mark_seen(&db, &member, &list)?;
+    Ok(badges_html(&counts(&db, &member)?, true).into_response())
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{domain::events::DomainEvent, fixtures::TestDb, models::Todo};
+
+    fn change(db: &Db, id: u64, actor: &str) -> Result<()> {
+        let mut todo = Todo::new(id, format!("todo {}", id));
+        todo.list = Some("groceries".into());
+        let event = DomainEvent::TodoCreated(todo);
+        db.apply_batch(activity::record_ops_by(db, &event, Some(actor))?)
+    }
+
+    #[test]
+    fn test_unseen_changes_of_others() -> Result<()> {
+        let db = TestDb::new("unread")?;
+        change(&db, 1, "ana")?;
+        // nothing is new before the first visit
+        assert!(unseen(&db, "bo", "groceries")?.is_empty());
+        mark_seen(&db, "bo", "groceries")?;
+        change(&db, 2, "ana")?;
+        change(&db, 3, "bo")?;
+        assert_eq!(unseen_todos(&db, "bo", "groceries")?, HashSet::from([2]));
+        assert_eq!(counts(&db, "bo")?.get("groceries"), Some(&1));
+        assert!(counts(&db, "ana")?.is_empty());
+
+        mark_seen(&db, "bo", "groceries")?;
+        assert!(counts(&db, "bo")?.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,144 @@
+use maud::{html, Markup};
+
+use super::dom;
+
+// Arrow keys move through the suggestions, Enter takes the highlighted one, Escape closes them.
+// Enter without a highlighted suggestion keeps the word as typed in a `multiple` field, so a new
+// value is added without leaving the keyboard. Only the word being typed is sent as `q`.
+pub(super) const COMBOBOX_SCRIPT: &str = r#"
+(function () {
+    const field = (elt) => elt.closest && elt.closest("[data-combobox]");
+    const listbox = (input) => document.getElementById(input.getAttribute("aria-controls"));
+    const options = (input) => Array.from(listbox(input).querySelectorAll("[role=option]"));
+    const multiple = (input) => field(input).dataset.combobox === "multiple";
+    const typed = (input) => multiple(input) ? input.value.split(/[\s,]+/).pop() : input.value.trim();
+    const close = function (input) {
+        listbox(input).innerHTML = "";
+        input.setAttribute("aria-expanded", "false");
+        input.removeAttribute("aria-activedescendant");
+    };
+    const highlight = function (input, option) {
+        options(input).forEach(function (other) {
+            other.setAttribute("aria-selected", other === option ? "true" : "false");
+            other.classList.toggle("bg-blue-100", other === option);
+        });
+        input.setAttribute("aria-activedescendant", option.id);
+        option.scrollIntoView({ block: "nearest" });
+    };
+    const pick = function (input, value) {
+        if (multiple(input)) {
+            const words = input.value.split(/[\s,]+/).filter(Boolean);
+            if (!/[\s,]$/.test(input.value)) words.pop();
+            words.push(value);
+            input.value = words.join(" ") + " ";
+        } else {
+            input.value = value;
+        }
+        close(input);
+        input.focus();
+    };
+    document.body.addEventListener("htmx:configRequest", function (evt) {
+        const input = evt.detail.elt;
+        if (!field(input) || input.getAttribute("role") !== "combobox") return;
+        // nothing else, not even what an enclosing form includes
+        const params = evt.detail.parameters;
+        Object.keys(params).forEach((name) => delete params[name]);
+        params.q = typed(input);
+    });
+    document.body.addEventListener("htmx:afterSwap", function (evt) {
+        const input = field(evt.detail.target) && field(evt.detail.target).querySelector("[role=combobox]");
+        if (!input) return;
+        // answered after the field was left
+        if (document.activeElement !== input) return close(input);
+        options(input).forEach(function (option, index) { option.id = evt.detail.target.id + "-" + index; });
+        input.setAttribute("aria-expanded", options(input).length > 0 ? "true" : "false");
+        input.removeAttribute("aria-activedescendant");
+    });
+    document.body.addEventListener("keydown", function (evt) {
+        const input = evt.target;
+        if (!field(input) || input.getAttribute("role") !== "combobox") return;
+        const all = options(input);
+        const current = all.findIndex((option) => option.getAttribute("aria-selected") === "true");
+        if ((evt.key === "ArrowDown" || evt.key === "ArrowUp") && all.length > 0) {
+            evt.preventDefault();
+            const step = evt.key === "ArrowDown" ? 1 : all.length - 1;
+            highlight(input, all[current < 0 ? (step === 1 ? 0 : all.length - 1) : (current + step) % all.length]);
+        } else if (evt.key === "Enter" && current >= 0) {
+            evt.preventDefault();
+            pick(input, all[current].dataset.value);
+        } else if (evt.key === "Enter" && multiple(input) && typed(input) !== "") {
+            evt.preventDefault();
+            pick(input, typed(input));
+        } else if (evt.key === "Escape" && all.length > 0) {
+            // the open suggestions, not the panel around them
+            evt.preventDefault();
+            evt.stopPropagation();
+            close(input);
+        }
+    });
+    // the field keeps focus while an option is clicked
+    document.body.addEventListener("mousedown", function (evt) {
+        if (evt.target.closest("[data-combobox] [role=option]")) evt.preventDefault();
+    });
+    document.body.addEventListener("click", function (evt) {
+        const option = evt.target.closest("[data-combobox] [role=option]");
+        if (option) pick(field(option).querySelector("[role=combobox]"), option.dataset.value);
+    });
+    document.body.addEventListener("focusout", function (evt) {
+        const input = evt.target;
+        if (field(input) && input.getAttribute("role") === "combobox") close(input);
+    });
+})();
+"#;
+
+// the listbox suggestions of the combobox `id` are swapped into
+pub fn listbox_id(id: &str) -> String {
+    format!("{}-options", id)
+}
+
+// === Components ===
+// A text field offering what `suggest_url` answers for the word being typed, see `option_html`.
+// A `multiple` field holds several values separated by spaces and completes the last one. It is
+// submitted like any text field, so forms work the same without javascript.
+pub fn combobox_html(
+    id: &str,
+    name: &str,
+    value: &str,
+    label: &str,
+    suggest_url: &str,
+    multiple: bool,
+) -> Markup {
+    let listbox = listbox_id(id);
+    html! {
+        div class="relative" data-combobox=(if multiple { "multiple" } else { "single" }) {
+            input id=(id) class="rounded p-2 border" type="text" name=(name) value=(value) placeholder=(label) aria-label=(label)
+                role="combobox" aria-autocomplete="list" aria-expanded="false" aria-controls=(listbox) autocomplete="off"
+                hx-get=(suggest_url) hx-trigger="input changed delay:150ms, focus" hx-target=(dom::selector(&listbox)) hx-swap="innerHTML" hx-sync="this:replace";
+            ul id=(listbox) class="absolute z-10 mt-1 w-full bg-white rounded shadow-lg max-h-60 overflow-y-auto empty:hidden" role="listbox" aria-label=(label) {}
+        }
+    }
+}
+
+// one suggestion, picking it puts `value` in the field
+pub fn option_html(value: &str, label: Markup) -> Markup {
+    html! {
+        li class="px-2 py-1 cursor-pointer hover:bg-blue-100" role="option" aria-selected="false" data-value=(value) { (label) }
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_combobox_html() {
+        let html =
+            combobox_html("tags-1", "tags", "home", "Tags", "/tag_suggestions", true).into_string();
+        // still a plain field of the form
+        assert!(html.contains(r#"name="tags" value="home""#));
+        assert!(html.contains(r#"aria-controls="tags-1-options""#));
+        assert!(html.contains(r##"hx-target="#tags-1-options""##));
+        assert!(html.contains(r#"data-combobox="multiple""#));
+    }
+}
@@ -0,0 +1,97 @@
+// === Element ids ===
+// The ids swaps address, built in one place. The component rendering an element and every
+// `hx-target`, out-of-band swap or script looking for it take the id from here, so they can't
+// drift apart, and an id stays the same across renders, which morphing and list diffing rely on:
+//
+//     li id=(dom::todo(todo.id)) { .. }
+//     Hx::post(routes::ToggleTodo::url()).target(Target::Element(dom::todo(todo.id)))
+//
+// Prefer these over `closest` targets, which break as soon as the markup around them moves.
+
+// the list of the index page, refreshed and swapped as a whole
+pub const TODOS: &str = "todos";
+
+// the quick-add form above the list
+pub const NEW_TODO: &str = "new-todo";
+
+// the todo lists of the index page, see `list_items`
+pub const OPEN_LIST: &str = "open";
+pub const COMPLETED_LIST: &str = "completed";
+
+// the line item of a todo
+pub fn todo(id: u64) -> String {
+    format!("todo-{}", id)
+}
+
+// the `ul` holding the items of a list
+pub fn list_items(list: &str) -> String {
+    format!("list-{}-items", list)
+}
+
+// the slot a todo's color palette loads into
+pub fn palette(todo: u64) -> String {
+    format!("palette-{}", todo)
+}
+
+// the reactions under a todo, also the name of the event updating them
+pub fn reactions(todo: u64) -> String {
+    format!("reactions-{}", todo)
+}
+
+// a todo on the today page
+pub fn today(todo: u64) -> String {
+    format!("today-{}", todo)
+}
+
+// a todo on the board
+pub fn card(todo: u64) -> String {
+    format!("card-{}", todo)
+}
+
+// the tag field of a todo's edit form
+pub fn tag_picker(todo: u64) -> String {
+    format!("tags-{}", todo)
+}
+
+// the slot a todo's list picker loads into, and the picker's field
+pub fn mover(todo: u64) -> String {
+    format!("move-{}", todo)
+}
+pub fn list_picker(todo: u64) -> String {
+    format!("list-picker-{}", todo)
+}
+
+pub fn goal(id: u64) -> String {
+    format!("goal-{}", id)
+}
+
+// the error summary of a form, and a field of it with the message under it, by the field's name
+pub fn error_summary(form: &str) -> String {
+    format!("{}-errors", form)
+}
+pub fn field(form: &str, name: &str) -> String {
+    format!("{}-{}", form, name)
+}
+pub fn field_error(form: &str, name: &str) -> String {
+    format!("{}-{}-error", form, name)
+}
+
+// `#{id}`, for attributes and scripts taking a css selector
+pub fn selector(id: &str) -> String {
+    format!("#{}", id)
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::views::hx::Target;
+
+    #[test]
+    fn test_ids_and_targets_agree() {
+        assert_eq!(todo(7), "todo-7");
+        assert_eq!(list_items(OPEN_LIST), "list-open-items");
+        assert_eq!(selector(&todo(7)), "#todo-7");
+        assert_eq!(Target::Element(todo(7)).to_string(), selector(&todo(7)));
+    }
+}
@@ -0,0 +1,127 @@
+use axum::http::StatusCode;
+use maud::{html, Markup};
+
+use super::dom;
+use crate::{error::FieldErrors, routes};
+
+// a full page for errors on regular navigations
+pub fn error_page(status: StatusCode, message: &str, request_id: Option<&str>) -> Markup {
+    let reason = status.canonical_reason().unwrap_or("Error");
+    super::page(
+        reason,
+        html! {
+            div class="bg-white rounded-lg shadow-lg p-8 text-center" {
+                h1 class="text-4xl text-gray-700 mb-4" { (status.as_u16()) " " (reason) }
+                p class="text-gray-600 mb-4" { (message) }
+                @if let Some(request_id) = request_id {
+                    p class="text-sm text-gray-400 mb-4" { "Request id: " code { (request_id) } }
+                }
+                @if status == StatusCode::NOT_FOUND {
+                    form class="flex justify-center mb-4" action=(routes::Search::url()) method="get" {
+                        input class="rounded p-2 mr-2" type="search" name="q" placeholder="Search your todos";
+                        button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded" type="submit" { "Search" }
+                    }
+                }
+                a href="/" class="text-blue-500 hover:text-blue-700" { "Back to your todos" }
+            }
+        },
+    )
+}
+
+// a toast appended to `#toasts` for errors on htmx requests
+pub fn error_toast(message: &str, request_id: Option<&str>) -> Markup {
+    html! {
+        div class="bg-red-500 text-white rounded-lg shadow-lg py-2 px-4 cursor-pointer" role="alert" onclick="this.remove()" {
+            p { (message) }
+            @if let Some(request_id) = request_id {
+                p class="text-xs opacity-75" { "Request id: " (request_id) }
+            }
+        }
+    }
+}
+
+// === Form errors ===
+// sent once the errors of a form are swapped in, with its id and the names of the fields
+pub const FORM_ERRORS_EVENT: &str = "form-errors";
+
+// Marks the fields with errors invalid and moves focus to the summary, so a screen reader reads
+// it out. A later successful submit clears what the last one left.
+pub const FORM_ERRORS_SCRIPT: &str = r#"
+document.body.addEventListener("form-errors", function (evt) {
+    const form = document.getElementById(evt.detail.form);
+    if (!form) return;
+    form.querySelectorAll("[aria-invalid]").forEach(function (field) { field.removeAttribute("aria-invalid"); });
+    evt.detail.fields.forEach(function (name) {
+        const field = form.elements.namedItem(name);
+        if (field && field.setAttribute) field.setAttribute("aria-invalid", "true");
+    });
+    const summary = document.getElementById(evt.detail.form + "-errors");
+    if (summary) summary.focus();
+});
+document.body.addEventListener("htmx:afterRequest", function (evt) {
+    const form = evt.detail.elt;
+    if (!evt.detail.successful || !form.querySelector || !form.querySelector("[data-error-summary]")) return;
+    form.querySelectorAll("[aria-invalid]").forEach(function (field) { field.removeAttribute("aria-invalid"); });
+    form.querySelectorAll("[data-error-summary], [data-field-error]").forEach(function (slot) { slot.replaceChildren(); });
+});
+"#;
+
+// Where the summary of a form's errors goes, first thing in the form. Empty until a submit fails.
+pub fn summary_slot(form: &str) -> Markup {
+    html! {
+        div id=(dom::error_summary(form)) tabindex="-1" data-error-summary {}
+    }
+}
+
+// where the error of a field goes, the field points at it with `aria-describedby`
+pub fn field_error_slot(form: &str, field: &str) -> Markup {
+    html! {
+        p id=(dom::field_error(form, field)) class="w-full text-sm text-red-600" data-field-error {}
+    }
+}
+
+fn summary_html(form: &str, errors: &FieldErrors) -> Markup {
+    let heading = format!("{}-heading", dom::error_summary(form));
+    html! {
+        div id=(dom::error_summary(form)) class="w-full border-2 border-red-500 rounded p-2 text-sm" tabindex="-1"
+            role="group" aria-labelledby=(heading) data-error-summary hx-swap-oob="true" {
+            h2 id=(heading) class="font-bold text-red-700" {
+                @if errors.0.len() == 1 { "There is a problem" } @else { "There are " (errors.0.len()) " problems" }
+            }
+            ul class="list-disc ml-4" {
+                @for (field, message) in &errors.0 {
+                    li { a class="text-red-700 underline" href=(dom::selector(&dom::field(form, field))) { (message) } }
+                }
+            }
+        }
+    }
+}
+
+// the summary and the message of every field with an error, out of band
+pub fn form_errors_oob(form: &str, errors: &FieldErrors) -> Markup {
+    html! {
+        (summary_html(form, errors))
+        @for (field, message) in &errors.0 {
+            p id=(dom::field_error(form, field)) class="w-full text-sm text-red-600" data-field-error hx-swap-oob="true" { (message) }
+        }
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_form_errors_oob() {
+        let mut errors = FieldErrors::default();
+        errors.add("title", "a todo needs a title");
+        errors.add("due", "`soon` is not a date");
+        let html = form_errors_oob("new-todo", &errors).into_string();
+        assert!(html.contains("There are 2 problems"));
+        // the summary links to the field, the field's own message sits where it points
+        assert!(html.contains(r##"href="#new-todo-due""##));
+        assert!(html.contains(r#"id="new-todo-due-error""#));
+        assert!(html.contains(r#"id="new-todo-errors""#));
+    }
+}
@@ -0,0 +1,30 @@
+use sha2::{Digest, Sha256};
+
+// === Fingerprints ===
+// A hash of rendered html, the same for the same bytes in every run and every build, unlike
+// `DefaultHasher`. Rendering is deterministic given the same data and clock: lists come in stored
+// order, maps reaching the markup are `BTreeMap`s or only looked up, attributes are written in
+// source order. List diffing and the `ETag` of html responses both go by it.
+pub fn hash(html: &[u8]) -> u64 {
+    let digest = Sha256::digest(html);
+    u64::from_be_bytes(digest[..8].try_into().expect("sha256 is 32 bytes"))
+}
+
+// the strong `ETag` of a response body
+pub fn etag(html: &[u8]) -> String {
+    format!("\"{:016x}\"", hash(html))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprints_are_stable() {
+        assert_eq!(hash(b"<li>Milk</li>"), hash(b"<li>Milk</li>"));
+        assert_ne!(hash(b"<li>Milk</li>"), hash(b"<li>Milk </li>"));
+        // pinned, a change here invalidates every cached page and digest out there
+        assert_eq!(etag(b""), "\"e3b0c44298fc1c14\"");
+    }
+}
@@ -0,0 +1,303 @@
+use std::fmt;
+
+use serde::Serialize;
+
+// Typed htmx attributes, so targets, swaps and `hx-vals` json are not hand-written strings.
+// maud cannot splice a set of attributes into a tag, so each attribute is read back as an
+// optional value:
+//
+//     @let hx = Hx::post(routes::ToggleTodo::url()).target(Closest::Li).swap(Swap::OuterHtml).vals(&ToggleTodo { id });
+//     input type="checkbox" hx-post=[hx.post_path()] hx-target=[hx.target_attr()] hx-swap=[hx.swap_attr()] hx-vals=[hx.vals_attr()];
+#[derive(Debug, Clone)]
+pub struct Hx {
+    verb: Verb,
+    path: String,
+    target: Option<Target>,
+    swap: Option<Swap>,
+    vals: Option<String>,
+    confirm: Option<String>,
+    push_url: bool,
+    transition: bool,
+    swap_delay_ms: Option<u32>,
+    show: Option<(String, Edge)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verb {
+    Get,
+    Post,
+    Put,
+    Patch,
+    Delete,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    This,
+    // `#{id}`
+    Id(&'static str),
+    // the same for an id built at runtime, see `dom`
+    Element(String),
+    Closest(Closest),
+    // any other css selector, e.g. `#todos ul`
+    Css(&'static str),
+}
+impl From<Closest> for Target {
+    fn from(closest: Closest) -> Self {
+        Target::Closest(closest)
+    }
+}
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::This => write!(f, "this"),
+            Target::Id(id) => write!(f, "#{}", id),
+            Target::Element(id) => write!(f, "#{}", id),
+            Target::Closest(closest) => write!(f, "closest {}", closest),
+            Target::Css(selector) => write!(f, "{}", selector),
+        }
+    }
+}
+
+// the elements `closest` targets walk up to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Closest {
+    Li,
+    Tr,
+    Form,
+    Div,
+    Section,
+}
+impl fmt::Display for Closest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Closest::Li => "li",
+            Closest::Tr => "tr",
+            Closest::Form => "form",
+            Closest::Div => "div",
+            Closest::Section => "section",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Swap {
+    InnerHtml,
+    OuterHtml,
+    BeforeBegin,
+    AfterBegin,
+    BeforeEnd,
+    AfterEnd,
+    Delete,
+    None,
+    // Merge the response into the target (idiomorph), keeping focus, scroll positions and
+    // running css transitions of elements whose ids match. Ids have to be stable for that.
+    Morph,
+    // the same for the target's children
+    MorphInner,
+}
+impl fmt::Display for Swap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Swap::InnerHtml => "innerHTML",
+            Swap::OuterHtml => "outerHTML",
+            Swap::BeforeBegin => "beforebegin",
+            Swap::AfterBegin => "afterbegin",
+            Swap::BeforeEnd => "beforeend",
+            Swap::AfterEnd => "afterend",
+            Swap::Delete => "delete",
+            Swap::None => "none",
+            Swap::Morph => "morph",
+            Swap::MorphInner => "morph:innerHTML",
+        })
+    }
+}
+
+// which side of an element `show` scrolls into view
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    Top,
+    Bottom,
+}
+impl fmt::Display for Edge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Edge::Top => "top",
+            Edge::Bottom => "bottom",
+        })
+    }
+}
+
+impl Hx {
+    pub fn new(verb: Verb, path: impl Into<String>) -> Self {
+        Self {
+            verb,
+            path: path.into(),
+            target: None,
+            swap: None,
+            vals: None,
+            confirm: None,
+            push_url: false,
+            transition: false,
+            swap_delay_ms: None,
+            show: None,
+        }
+    }
+    pub fn get(path: impl Into<String>) -> Self {
+        Self::new(Verb::Get, path)
+    }
+    pub fn post(path: impl Into<String>) -> Self {
+        Self::new(Verb::Post, path)
+    }
+    pub fn put(path: impl Into<String>) -> Self {
+        Self::new(Verb::Put, path)
+    }
+    pub fn patch(path: impl Into<String>) -> Self {
+        Self::new(Verb::Patch, path)
+    }
+    pub fn delete(path: impl Into<String>) -> Self {
+        Self::new(Verb::Delete, path)
+    }
+
+    pub fn target(mut self, target: impl Into<Target>) -> Self {
+        self.target = Some(target.into());
+        self
+    }
+    pub fn swap(mut self, swap: Swap) -> Self {
+        self.swap = Some(swap);
+        self
+    }
+    // the request body fields, serialized the same way the handler's `Form` deserializes them
+    pub fn vals<T: Serialize>(mut self, vals: &T) -> Self {
+        self.vals = Some(serde_json::to_string(vals).expect("hx-vals serialize to json"));
+        self
+    }
+    pub fn confirm(mut self, question: impl Into<String>) -> Self {
+        self.confirm = Some(question.into());
+        self
+    }
+    // keep the browser url in sync with the fetched path
+    pub fn push_url(mut self) -> Self {
+        self.push_url = true;
+        self
+    }
+    // swap inside a view transition where the browser has them, `transition:true`
+    pub fn transition(mut self) -> Self {
+        self.transition = true;
+        self
+    }
+    // leave the old content in place this long with `htmx-swapping` on it, for exit animations
+    pub fn swap_delay(mut self, ms: u32) -> Self {
+        self.swap_delay_ms = Some(ms);
+        self
+    }
+
+    // after the swap, scroll so the element with the id `element` has `edge` in view
+    pub fn show(mut self, element: String, edge: Edge) -> Self {
+        self.show = Some((element, edge));
+        self
+    }
+
+    // === Attribute values ===
+    // only the accessor for the builder's verb returns the path
+    pub fn get_path(&self) -> Option<&str> {
+        self.path_for(Verb::Get)
+    }
+    pub fn post_path(&self) -> Option<&str> {
+        self.path_for(Verb::Post)
+    }
+    pub fn put_path(&self) -> Option<&str> {
+        self.path_for(Verb::Put)
+    }
+    pub fn patch_path(&self) -> Option<&str> {
+        self.path_for(Verb::Patch)
+    }
+    pub fn delete_path(&self) -> Option<&str> {
+        self.path_for(Verb::Delete)
+    }
+    pub fn target_attr(&self) -> Option<String> {
+        self.target.as_ref().map(ToString::to_string)
+    }
+    pub fn swap_attr(&self) -> Option<String> {
+        let mut parts: Vec<String> = self.swap.iter().map(ToString::to_string).collect();
+        if let Some(ms) = self.swap_delay_ms {
+            parts.push(format!("swap:{}ms", ms));
+        }
+        if self.transition {
+            parts.push("transition:true".to_string());
+        }
+        if let Some((element, edge)) = &self.show {
+            parts.push(format!("show:#{}:{}", element, edge));
+        }
+        (!parts.is_empty()).then(|| parts.join(" "))
+    }
+    pub fn vals_attr(&self) -> Option<&str> {
+        self.vals.as_deref()
+    }
+    pub fn confirm_attr(&self) -> Option<&str> {
+        self.confirm.as_deref()
+    }
+    pub fn push_url_attr(&self) -> Option<&str> {
+        self.push_url.then_some("true")
+    }
+
+    fn path_for(&self, verb: Verb) -> Option<&str> {
+        (self.verb == verb).then_some(self.path.as_str())
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use maud::html;
+
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Toggle {
+        id: u64,
+    }
+
+    #[test]
+    fn test_renders_attributes() {
+        let hx = Hx::post("/toggle_todo")
+            .target(Closest::Li)
+            .swap(Swap::OuterHtml)
+            .vals(&Toggle { id: 7 });
+        let markup = html! {
+            input hx-get=[hx.get_path()] hx-post=[hx.post_path()] hx-target=[hx.target_attr()]
+                hx-swap=[hx.swap_attr()] hx-vals=[hx.vals_attr()];
+        };
+        assert_eq!(
+            markup.into_string(),
+            r#"<input hx-post="/toggle_todo" hx-target="closest li" hx-swap="outerHTML" hx-vals="{&quot;id&quot;:7}">"#
+        );
+    }
+
+    #[test]
+    fn test_swap_modifiers() {
+        let hx = Hx::delete("/remove_todo")
+            .swap(Swap::OuterHtml)
+            .swap_delay(200)
+            .transition();
+        assert_eq!(
+            hx.swap_attr().unwrap(),
+            "outerHTML swap:200ms transition:true"
+        );
+        let hx = Hx::get("/todos")
+            .swap(Swap::InnerHtml)
+            .show("todo-7".into(), Edge::Bottom);
+        assert_eq!(hx.swap_attr().unwrap(), "innerHTML show:#todo-7:bottom");
+        assert_eq!(
+            Hx::get("/").transition().swap_attr().unwrap(),
+            "transition:true"
+        );
+        assert_eq!(Hx::get("/").swap_attr(), None);
+    }
+
+    #[test]
+    fn test_morph_swaps() {
+        assert_eq!(Swap::Morph.to_string(), "morph");
+        assert_eq!(Swap::MorphInner.to_string(), "morph:innerHTML");
+    }
+}
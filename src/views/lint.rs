@@ -0,0 +1,308 @@
+use std::{collections::HashSet, fmt};
+
+use crate::routes;
+
+// the verbs htmx sends requests with
+const VERBS: [&str; 5] = ["hx-get", "hx-post", "hx-put", "hx-patch", "hx-delete"];
+const SWAP_STYLES: [&str; 11] = [
+    "innerHTML",
+    "outerHTML",
+    "textContent",
+    "beforebegin",
+    "afterbegin",
+    "beforeend",
+    "afterend",
+    "delete",
+    "none",
+    "morph",
+    "morph:innerHTML",
+];
+const SWAP_MODIFIERS: [&str; 7] = [
+    "swap:",
+    "settle:",
+    "transition:",
+    "scroll:",
+    "show:",
+    "focus-scroll:",
+    "ignoreTitle:",
+];
+// their content is not markup
+const RAW_TEXT: [&str; 2] = ["script", "style"];
+
+// === Lint ===
+// Checks the htmx attributes of rendered markup: requests go to a route of `routes`, `#id`
+// targets exist and swaps are ones htmx knows. Catches a renamed route or id that leaves a
+// button doing nothing. A fragment is swapped into a page, so its `#id` targets are not checked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    pub tag: String,
+    pub attribute: String,
+    pub value: String,
+    pub message: &'static str,
+}
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "<{} {}=\"{}\">: {}",
+            self.tag, self.attribute, self.value, self.message
+        )
+    }
+}
+
+pub fn lint_page(html: &str) -> Vec<Problem> {
+    lint(html, true)
+}
+pub fn lint_fragment(html: &str) -> Vec<Problem> {
+    lint(html, false)
+}
+
+fn lint(html: &str, whole_page: bool) -> Vec<Problem> {
+    let elements = elements(html);
+    let ids: HashSet<&str> = elements
+        .iter()
+        .flat_map(|element| element.attribute("id"))
+        .collect();
+    let mut problems = Vec::new();
+    for element in &elements {
+        for (name, value) in &element.attributes {
+            let message = match name.as_str() {
+                verb if VERBS.contains(&verb) => check_url(value),
+                "hx-target" => check_target(value, whole_page.then_some(&ids)),
+                "hx-swap" => check_swap(value),
+                "hx-swap-oob" => check_swap_oob(value),
+                _ => None,
+            };
+            if let Some(message) = message {
+                problems.push(Problem {
+                    tag: element.tag.clone(),
+                    attribute: name.clone(),
+                    value: value.clone(),
+                    message,
+                });
+            }
+        }
+    }
+    problems
+}
+
+fn check_url(url: &str) -> Option<&'static str> {
+    // elsewhere, nothing to check against
+    if url.contains("://") {
+        return None;
+    }
+    (!routes::is_route(url)).then_some("no route has this path")
+}
+
+fn check_target(target: &str, ids: Option<&HashSet<&str>>) -> Option<&'static str> {
+    let Some(selector) = target.strip_prefix('#') else {
+        // `this`, `closest li`, `next .suggestions` and other selectors
+        return None;
+    };
+    let id = selector
+        .split(|c: char| c.is_whitespace() || matches!(c, '.' | ':' | '['))
+        .next()
+        .unwrap_or_default();
+    match ids {
+        _ if id.is_empty() => Some("the target has no id"),
+        Some(ids) if !ids.contains(id) => Some("no element has this id"),
+        _ => None,
+    }
+}
+
+fn check_swap(swap: &str) -> Option<&'static str> {
+    let mut parts = swap.split_whitespace();
+    let style = parts.next().unwrap_or_default();
+    if !SWAP_STYLES.contains(&style)
+        && !SWAP_MODIFIERS
+            .iter()
+            .any(|modifier| style.starts_with(modifier))
+    {
+        return Some("htmx has no such swap");
+    }
+    let known = parts.all(|part| {
+        SWAP_MODIFIERS
+            .iter()
+            .any(|modifier| part.starts_with(modifier))
+    });
+    (!known).then_some("htmx has no such swap modifier")
+}
+
+// `true`, a swap style, or a style followed by `:` and a selector
+fn check_swap_oob(swap: &str) -> Option<&'static str> {
+    if swap == "true" || SWAP_STYLES.contains(&swap) {
+        return None;
+    }
+    let style = swap.split_once(':').map_or(swap, |(style, _)| style);
+    (!SWAP_STYLES.contains(&style)).then_some("htmx has no such out of band swap")
+}
+
+// === Markup ===
+// Just enough of an html tokenizer for the markup maud renders: start tags and their
+// attributes, skipping text, comments and the content of scripts and styles.
+#[derive(Debug)]
+struct Element {
+    tag: String,
+    attributes: Vec<(String, String)>,
+}
+impl Element {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(attribute, _)| attribute == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+fn elements(html: &str) -> Vec<Element> {
+    let mut elements = Vec::new();
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        if let Some(comment) = rest.strip_prefix("!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        if rest.starts_with(['!', '/', '?']) {
+            rest = rest.find('>').map_or("", |end| &rest[end + 1..]);
+            continue;
+        }
+        let (element, after) = start_tag(rest);
+        rest = after;
+        let Some(element) = element else { continue };
+        if RAW_TEXT.contains(&element.tag.as_str()) {
+            let close = format!("</{}", element.tag);
+            rest = rest.find(&close).map_or("", |end| &rest[end..]);
+        }
+        elements.push(element);
+    }
+    elements
+}
+
+// the element starting at `rest` (just past its `<`), and what follows the tag
+fn start_tag(rest: &str) -> (Option<Element>, &str) {
+    let name_end = rest
+        .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+        .unwrap_or(rest.len());
+    let tag = rest[..name_end].to_ascii_lowercase();
+    if tag.is_empty() || !tag.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return (None, rest);
+    }
+    let mut attributes = Vec::new();
+    let mut rest = &rest[name_end..];
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+        if rest.is_empty() {
+            break;
+        }
+        if let Some(after) = rest.strip_prefix('>') {
+            rest = after;
+            break;
+        }
+        let name_end = rest
+            .find(|c: char| c.is_whitespace() || matches!(c, '=' | '>' | '/'))
+            .unwrap_or(rest.len());
+        let name = rest[..name_end].to_ascii_lowercase();
+        rest = rest[name_end..].trim_start();
+        let value = match rest.strip_prefix('=') {
+            Some(after) => {
+                let after = after.trim_start();
+                let (value, after) = match after.chars().next() {
+                    Some(quote @ ('"' | '\'')) => {
+                        let inner = &after[1..];
+                        let end = inner.find(quote).unwrap_or(inner.len());
+                        (&inner[..end], inner.get(end + 1..).unwrap_or_default())
+                    }
+                    _ => {
+                        let end = after
+                            .find(|c: char| c.is_whitespace() || c == '>')
+                            .unwrap_or(after.len());
+                        (&after[..end], &after[end..])
+                    }
+                };
+                rest = after;
+                unescape(value)
+            }
+            None => String::new(),
+        };
+        attributes.push((name, value));
+    }
+    (Some(Element { tag, attributes }), rest)
+}
+
+// the escapes maud writes
+fn unescape(value: &str) -> String {
+    value
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use maud::html;
+
+    use super::*;
+    use crate::{
+        guest, maintenance,
+        views::{
+            self,
+            hx::{Closest, Hx, Swap},
+            nav::{self, Nav},
+        },
+    };
+
+    #[test]
+    fn test_components() {
+        let hx = Hx::post(routes::ToggleTodo::url())
+            .target(Closest::Li)
+            .swap(Swap::OuterHtml)
+            .transition();
+        let page = views::page(
+            "Lint",
+            html! {
+                (nav::navigation(&Nav::todos().with_todo(7, "Water the plants")))
+                (guest::banner_html())
+                (maintenance::banner_html(true))
+                (views::lazy(&routes::StatsSidebar::url(), "stats", Some(&routes::Stats::url())))
+                ul {
+                    li {
+                        input type="checkbox" hx-post=[hx.post_path()] hx-target=[hx.target_attr()] hx-swap=[hx.swap_attr()];
+                    }
+                }
+            },
+        );
+        assert_eq!(lint_page(&page.into_string()), Vec::new());
+    }
+
+    #[test]
+    fn test_problems() {
+        let html = r##"
+            <div id="todos">
+                <script>if (a < b) { x.innerHTML = '<button hx-get="/nowhere">'; }</script>
+                <button hx-post="/toggle_todo" hx-target="#todos ul" hx-swap="outerHTML swap:200ms">ok</button>
+                <button hx-delete="/todos/7" hx-target="#gone">missing id</button>
+                <button hx-get="/todo/7" hx-swap="outerHtml">renamed route, wrong case</button>
+                <a hx-get="/admin/tenants/acme/export?format=json" hx-swap="innerHTML scroll:top">ok</a>
+                <div hx-swap-oob="beforeend:#toasts"></div>
+            </div>
+        "##;
+        let problems: Vec<_> = lint_page(html)
+            .into_iter()
+            .map(|problem| (problem.attribute, problem.value))
+            .collect();
+        assert_eq!(
+            problems,
+            [
+                ("hx-target".to_string(), "#gone".to_string()),
+                ("hx-get".to_string(), "/todo/7".to_string()),
+                ("hx-swap".to_string(), "outerHtml".to_string()),
+            ]
+        );
+        // the page a fragment goes into has the ids
+        assert_eq!(lint_fragment(html).len(), 2);
+    }
+}
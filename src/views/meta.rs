@@ -0,0 +1,100 @@
+use maud::{html, Markup};
+
+// === Meta ===
+// What a page tells search engines and chat apps unfurling a link to it. Pages meant to be shared
+// set a canonical url, the OpenGraph and Twitter tags are only rendered for those.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Meta {
+    // absolute
+    pub canonical: Option<String>,
+    pub description: Option<String>,
+    // absolute
+    pub image: Option<String>,
+    pub site_name: Option<String>,
+    // secret links, e.g. kiosks, unfurl but stay out of search results
+    pub noindex: bool,
+}
+impl Meta {
+    pub fn canonical(url: impl Into<String>) -> Self {
+        Self {
+            canonical: Some(url.into()),
+            ..Self::default()
+        }
+    }
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+    pub fn image(mut self, url: impl Into<String>) -> Self {
+        self.image = Some(url.into());
+        self
+    }
+    pub fn site_name(mut self, name: impl Into<String>) -> Self {
+        self.site_name = Some(name.into());
+        self
+    }
+    pub fn noindex(mut self) -> Self {
+        self.noindex = true;
+        self
+    }
+}
+
+// `path` under the configured `PUBLIC_URL`
+pub fn absolute(public_url: &str, path: &str) -> String {
+    format!("{}{}", public_url.trim_end_matches('/'), path)
+}
+
+// the tags in the head of the page titled `title`
+pub fn tags_html(title: &str, meta: &Meta) -> Markup {
+    html! {
+        @if let Some(description) = &meta.description {
+            meta name="description" content=(description);
+        }
+        @if meta.noindex {
+            meta name="robots" content="noindex";
+        }
+        @if let Some(canonical) = &meta.canonical {
+            link rel="canonical" href=(canonical);
+            meta property="og:type" content="website";
+            meta property="og:url" content=(canonical);
+            meta property="og:title" content=(title);
+            @if let Some(description) = &meta.description {
+                meta property="og:description" content=(description);
+            }
+            @if let Some(site_name) = &meta.site_name {
+                meta property="og:site_name" content=(site_name);
+            }
+            @if let Some(image) = &meta.image {
+                meta property="og:image" content=(image);
+            }
+            meta name="twitter:card" content="summary";
+            meta name="twitter:title" content=(title);
+            @if let Some(description) = &meta.description {
+                meta name="twitter:description" content=(description);
+            }
+        }
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tags_need_a_canonical_url() {
+        let meta = Meta::default().description("3 of 4 todos done");
+        let tags = tags_html("Chores", &meta).into_string();
+        assert!(tags.contains(r#"name="description""#));
+        assert!(!tags.contains("og:title"));
+
+        let meta = Meta::canonical(absolute("https://todo.example/", "/kiosk/abc"))
+            .description("3 of 4 todos done")
+            .noindex();
+        let tags = tags_html("Chores & more", &meta).into_string();
+        assert!(tags.contains(r#"<link rel="canonical" href="https://todo.example/kiosk/abc">"#));
+        assert!(tags.contains(r#"<meta property="og:title" content="Chores &amp; more">"#));
+        assert!(tags.contains(r#"<meta name="twitter:description" content="3 of 4 todos done">"#));
+        assert!(tags.contains(r#"content="noindex""#));
+    }
+}
@@ -0,0 +1,82 @@
+use maud::{html, Markup};
+
+pub const SHEET_ID: &str = "create-sheet";
+
+pub(super) const SWIPE_STYLE: &str = r#"
+[data-swipe] { touch-action: pan-y; }
+[data-swipe].swiping { transition: none; }
+[data-swipe].swipe-complete { box-shadow: inset 6px 0 0 var(--t-success, #16a34a); }
+[data-swipe].swipe-delete { box-shadow: inset -6px 0 0 var(--t-danger, #dc2626); }
+"#;
+
+// Swiping a `data-swipe` item right clicks its `data-swipe-complete` control, left its
+// `data-swipe-delete` one, so the requests are the ones htmx sends for a click. Mostly vertical
+// drags are left to scrolling.
+pub(super) const SWIPE_SCRIPT: &str = r#"
+(function () {
+    const threshold = 80;
+    let item = null, startX = 0, startY = 0, dx = 0;
+    const reset = function () {
+        if (!item) return;
+        item.style.transform = "";
+        item.classList.remove("swiping", "swipe-complete", "swipe-delete");
+        item = null;
+    };
+    document.addEventListener("touchstart", function (evt) {
+        item = evt.target.closest("[data-swipe]");
+        if (!item || evt.target.closest("input, button, a")) { item = null; return; }
+        startX = evt.touches[0].clientX;
+        startY = evt.touches[0].clientY;
+        dx = 0;
+    }, { passive: true });
+    document.addEventListener("touchmove", function (evt) {
+        if (!item) return;
+        dx = evt.touches[0].clientX - startX;
+        const dy = evt.touches[0].clientY - startY;
+        if (Math.abs(dy) > Math.abs(dx)) { reset(); return; }
+        item.classList.add("swiping");
+        item.classList.toggle("swipe-complete", dx > threshold);
+        item.classList.toggle("swipe-delete", dx < -threshold);
+        item.style.transform = "translateX(" + dx + "px)";
+    }, { passive: true });
+    document.addEventListener("touchend", function () {
+        if (!item) return;
+        const target = dx > threshold ? item.querySelector("[data-swipe-complete]")
+            : dx < -threshold ? item.querySelector("[data-swipe-delete]") : null;
+        reset();
+        if (target) target.click();
+    });
+    document.addEventListener("touchcancel", reset);
+})();
+"#;
+
+// The create form in a sheet sliding up from the bottom on small screens, opened by a floating
+// button. Wider screens show the form above the list instead.
+pub fn create_sheet(form: Markup) -> Markup {
+    let open = format!("document.getElementById('{}').showModal()", SHEET_ID);
+    html! {
+        button class="md:hidden fixed bottom-6 right-6 z-30 w-14 h-14 rounded-full bg-blue-500 text-white text-3xl shadow-lg"
+            type="button" aria-label="New todo" onclick=(open) { "+" }
+        dialog id=(SHEET_ID) class="md:hidden fixed inset-x-0 bottom-0 top-auto m-0 w-full max-w-none rounded-t-lg p-4 shadow-xl"
+            aria-label="New todo" "hx-on::after-request"="this.close()" {
+            form method="dialog" class="flex justify-end" {
+                button class="text-gray-500 text-2xl" aria-label="Close" { "×" }
+            }
+            (form)
+        }
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_sheet() {
+        let sheet = create_sheet(html! { form id="new" {} }).into_string();
+        assert!(sheet.contains(r#"<dialog id="create-sheet""#));
+        assert!(sheet.contains(r#"<form id="new">"#));
+        assert!(sheet.contains("showModal()"));
+    }
+}
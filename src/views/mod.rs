@@ -0,0 +1,289 @@
+pub mod combobox;
+pub mod dom;
+pub mod error;
+pub mod fingerprint;
+pub mod hx;
+pub mod lint;
+pub mod meta;
+pub mod mobile;
+pub mod nav;
+pub mod offline;
+pub mod overrides;
+pub mod panel;
+
+use axum::{
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+};
+use maud::{html, Markup, PreEscaped, DOCTYPE};
+
+use crate::{assets, auth::csrf, branding, maintenance, routes};
+use meta::Meta;
+use overrides::Part;
+
+// htmx does not swap 4xx/5xx responses by default, let the retargeted error toasts through, and
+// the out of band errors of a form
+const ERROR_SWAP_SCRIPT: &str = r#"
+document.body.addEventListener("htmx:beforeSwap", function (evt) {
+    const xhr = evt.detail.xhr;
+    if (xhr.status >= 400 && (xhr.getResponseHeader("HX-Retarget") || xhr.getResponseHeader("HX-Reswap"))) {
+        evt.detail.shouldSwap = true;
+        evt.detail.isError = false;
+    }
+});
+"#;
+
+// Enter and exit animations of list items: htmx puts `htmx-added` on new content while it
+// settles and `htmx-swapping` on content about to go. Switched off by the system's reduced
+// motion setting or the preference on the settings page.
+const MOTION_STYLE: &str = r#"
+.todo-item { transition: opacity 200ms ease-out, transform 200ms ease-out; }
+.todo-item.htmx-added { opacity: 0; transform: translateY(-0.5rem); }
+.todo-item.htmx-swapping { opacity: 0; transform: translateX(1rem); }
+/* a daily goal reached */
+.confetti { animation: confetti 600ms ease-out 2; }
+@keyframes confetti {
+    0% { transform: scale(1); } 40% { transform: scale(1.4) rotate(-10deg); } 100% { transform: scale(1); }
+}
+@media (prefers-reduced-motion: reduce) {
+    *, ::view-transition-group(*), ::view-transition-old(*), ::view-transition-new(*) {
+        animation: none !important; transition: none !important;
+    }
+}
+html.reduce-motion *, html.reduce-motion::view-transition-group(*),
+html.reduce-motion::view-transition-old(*), html.reduce-motion::view-transition-new(*) {
+    animation: none !important; transition: none !important;
+}
+"#;
+// the preference lives in the browser, applied before anything renders
+const MOTION_KEY: &str = "reduce-motion";
+const MOTION_SCRIPT: &str = r#"
+document.documentElement.classList.toggle("reduce-motion", localStorage.getItem("reduce-motion") === "1");
+"#;
+
+// the html shell every full page is rendered into
+pub fn page(title: &str, content: Markup) -> Markup {
+    page_with_meta(title, &Meta::default(), content)
+}
+// A page reachable under several urls or shared as a link, pointing search engines and unfurls at
+// the url to use. See `meta::tags_html`.
+pub fn page_with_meta(title: &str, meta: &Meta, content: Markup) -> Markup {
+    html! {
+        (DOCTYPE)
+        html {
+            head {
+                meta charset="utf-8";
+                meta name="viewport" content="width=device-width, initial-scale=1";
+                link rel="manifest" href=(routes::Manifest::url());
+                link rel="icon" type="image/svg+xml" href=(routes::AppIcon::url());
+                title { (title) }
+                (meta::tags_html(title, meta))
+                @for script in &assets::SCRIPTS {
+                    script src=(script.url()) {}
+                }
+                // the workspace's palette for the `t-*` classes, see `theme::css`
+                link rel="stylesheet" href=(routes::ThemeCss::url());
+                style { (PreEscaped(MOTION_STYLE)) }
+                style { (PreEscaped(mobile::SWIPE_STYLE)) }
+                script { (PreEscaped(MOTION_SCRIPT)) }
+            }
+            // live updates from other pages of the workspace, see `events::stream`
+            body class="bg-gray-100 font-sans leading-normal tracking-normal" hx-ext="sse, morph" sse-connect=(routes::Events::url()) {
+                (maintenance::banner_slot())
+                (offline::banner_slot())
+                @match overrides::get(Part::Header) {
+                    Some(template) => { (overrides::render(template, &[("title", title)], &[])) }
+                    None => { (branding::header_slot()) }
+                }
+                // htmx snapshots this element for back/forward navigation
+                div class="container mx-auto p-8" hx-history-elt {
+                    (content)
+                }
+                @if let Some(template) = overrides::get(Part::Footer) {
+                    (overrides::render(template, &[("title", title)], &[]))
+                }
+                div id="toasts" class="fixed bottom-4 right-4 space-y-2" {}
+                (panel::panel_slot())
+                script { (PreEscaped(ERROR_SWAP_SCRIPT)) }
+                script { (PreEscaped(error::FORM_ERRORS_SCRIPT)) }
+                script { (PreEscaped(offline::RETRY_SCRIPT)) }
+                script { (PreEscaped(csrf::CSRF_SCRIPT)) }
+                script { (PreEscaped(panel::PANEL_SCRIPT)) }
+                script { (PreEscaped(mobile::SWIPE_SCRIPT)) }
+                script { (PreEscaped(combobox::COMBOBOX_SCRIPT)) }
+            }
+        }
+    }
+}
+
+// A region loaded after the page, for sections too expensive to hold up the first paint. Without
+// javascript `fallback` links to a page with the same data, if there is one.
+pub fn lazy(url: &str, label: &str, fallback: Option<&str>) -> Markup {
+    html! {
+        div hx-get=(url) hx-trigger="load" hx-swap="outerHTML" {
+            p class="text-gray-400 text-sm" { "Loading " (label) "…" }
+            @if let Some(fallback) = fallback {
+                noscript {
+                    a class="text-blue-500 hover:text-blue-700 text-sm" href=(fallback) { "Show " (label) }
+                }
+            }
+        }
+    }
+}
+
+// the settings section toggling animations for this browser
+pub fn motion_settings_html() -> Markup {
+    let toggle = format!(
+        "localStorage.setItem('{key}', this.checked ? '1' : '0'); document.documentElement.classList.toggle('{key}', this.checked)",
+        key = MOTION_KEY
+    );
+    html! {
+        section class="bg-white rounded-lg shadow-lg p-6 space-y-4" {
+            h2 class="text-2xl text-gray-700" { "Motion" }
+            label class="flex items-center space-x-2" {
+                input id="reduce-motion" type="checkbox" onchange=(toggle);
+                span { "Reduce animations in this browser" }
+            }
+            script { (PreEscaped(format!("document.getElementById('reduce-motion').checked = localStorage.getItem('{}') === '1';", MOTION_KEY))) }
+        }
+    }
+}
+
+// Whether a request should get a fragment instead of a full page: htmx requests, except
+// boosted navigations and history restores after a cache miss, which replace the whole body.
+pub fn wants_fragment(headers: &HeaderMap) -> bool {
+    headers.contains_key("hx-request")
+        && !headers.contains_key("hx-boosted")
+        && !headers.contains_key("hx-history-restore-request")
+}
+
+// Answer a write with the fragment htmx swaps in, or redirect a plain form post back to the
+// page (post/redirect/get) so the app keeps working without javascript.
+pub fn fragment_or_redirect(headers: &HeaderMap, fragment: Markup, location: &str) -> Response {
+    if headers.contains_key("hx-request") {
+        return fragment.into_response();
+    }
+    Redirect::to(location).into_response()
+}
+
+// long enough for the exit animation of a removed item
+pub const DELETE_SWAP_MS: u32 = 200;
+
+// Answer a deletion. htmx skips the swap on a 204, so it gets an empty 200 with `HX-Reswap:
+// delete` and removes the target whatever its `hx-swap` says. API clients get the 204, plain
+// form posts a redirect.
+pub fn deleted(headers: &HeaderMap, location: &str) -> Response {
+    if headers.contains_key("hx-request") {
+        let reswap = format!("delete swap:{}ms", DELETE_SWAP_MS);
+        return ([("hx-reswap", reswap)], html! {}).into_response();
+    }
+    if accepts_json(headers) {
+        return StatusCode::NO_CONTENT.into_response();
+    }
+    Redirect::to(location).into_response()
+}
+
+// Whether the caller wants data instead of markup, an API client rather than htmx or a browser.
+// The page handlers answer it with the todos their fragments render, in the shape of the v1 API.
+pub fn wants_json(headers: &HeaderMap) -> bool {
+    !headers.contains_key("hx-request") && accepts_json(headers)
+}
+
+fn accepts_json(headers: &HeaderMap) -> bool {
+    [header::ACCEPT, header::CONTENT_TYPE].iter().any(|name| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.contains("application/json"))
+    })
+}
+
+// a confirmation toast appended to `#toasts` out of band, next to the fragment it accompanies
+pub fn notice_toast_oob(message: &str) -> Markup {
+    toast_oob("bg-green-500", message)
+}
+// like `notice_toast_oob`, for something worth a second look
+pub fn warning_toast_oob(message: &str) -> Markup {
+    toast_oob("bg-yellow-500", message)
+}
+
+fn toast_oob(color: &str, message: &str) -> Markup {
+    html! {
+        div hx-swap-oob="beforeend:#toasts" {
+            div class={ (color) " text-white rounded-lg shadow-lg py-2 px-4 cursor-pointer" } role="status" onclick="this.remove()" {
+                p { (message) }
+            }
+        }
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(names: &[&'static str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for name in names {
+            headers.insert(*name, "true".parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_wants_fragment() {
+        assert!(!wants_fragment(&headers(&[])));
+        assert!(wants_fragment(&headers(&["hx-request"])));
+        assert!(!wants_fragment(&headers(&["hx-request", "hx-boosted"])));
+    }
+
+    #[test]
+    fn test_history_restore_gets_full_page() {
+        let restore = headers(&["hx-request", "hx-history-restore-request"]);
+        assert!(!wants_fragment(&restore));
+        let mut restore_into_panel = restore.clone();
+        restore_into_panel.insert("hx-target", "panel".parse().unwrap());
+        assert!(!panel::is_panel_request(&restore_into_panel));
+    }
+
+    #[test]
+    fn test_form_post_without_javascript_redirects() {
+        let response = fragment_or_redirect(&headers(&[]), html! { li { "Milk" } }, "/");
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(response.headers()[header::LOCATION], "/");
+
+        let response =
+            fragment_or_redirect(&headers(&["hx-request"]), html! { li { "Milk" } }, "/");
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn test_deleted() {
+        let response = deleted(&headers(&["hx-request"]), "/");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["hx-reswap"], "delete swap:200ms");
+
+        let mut api = HeaderMap::new();
+        api.insert(header::ACCEPT, "application/json".parse().unwrap());
+        let response = deleted(&api, "/");
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert!(!response.headers().contains_key("hx-reswap"));
+
+        let response = deleted(&headers(&[]), "/");
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    }
+
+    #[test]
+    fn test_wants_json() {
+        let mut api = HeaderMap::new();
+        api.insert(header::ACCEPT, "application/json".parse().unwrap());
+        assert!(wants_json(&api));
+        // htmx with the json-enc extension still swaps markup
+        let mut htmx = headers(&["hx-request"]);
+        htmx.insert(header::CONTENT_TYPE, "application/json".parse().unwrap());
+        assert!(!wants_json(&htmx));
+        let mut browser = HeaderMap::new();
+        browser.insert(header::ACCEPT, "text/html,*/*;q=0.8".parse().unwrap());
+        assert!(!wants_json(&browser));
+    }
+}
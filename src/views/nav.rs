@@ -0,0 +1,220 @@
+use maud::{html, Markup};
+
+use super::panel::PANEL_ID;
+use crate::routes;
+
+pub const NAV_ID: &str = "nav";
+
+// the top level areas the sidebar links to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Section {
+    #[default]
+    Todos,
+    Today,
+    Board,
+    Plan,
+    Calendar,
+    Goals,
+    Stats,
+    Activity,
+    Tags,
+    Review,
+    Someday,
+    Search,
+    Settings,
+}
+
+// Where the current page sits, threaded from handlers into the navigation they render.
+// Todos are the only level below a section so far, there are no lists or filters yet.
+#[derive(Debug, Clone, Default)]
+pub struct Nav {
+    pub section: Section,
+    pub todo: Option<(u64, String)>,
+}
+impl Nav {
+    pub fn todos() -> Self {
+        Self::default()
+    }
+    pub fn today() -> Self {
+        Self {
+            section: Section::Today,
+            todo: None,
+        }
+    }
+    pub fn board() -> Self {
+        Self {
+            section: Section::Board,
+            todo: None,
+        }
+    }
+    pub fn plan() -> Self {
+        Self {
+            section: Section::Plan,
+            todo: None,
+        }
+    }
+    pub fn calendar() -> Self {
+        Self {
+            section: Section::Calendar,
+            todo: None,
+        }
+    }
+    pub fn goals() -> Self {
+        Self {
+            section: Section::Goals,
+            todo: None,
+        }
+    }
+    pub fn stats() -> Self {
+        Self {
+            section: Section::Stats,
+            todo: None,
+        }
+    }
+    pub fn activity() -> Self {
+        Self {
+            section: Section::Activity,
+            todo: None,
+        }
+    }
+    pub fn tags() -> Self {
+        Self {
+            section: Section::Tags,
+            todo: None,
+        }
+    }
+    pub fn review() -> Self {
+        Self {
+            section: Section::Review,
+            todo: None,
+        }
+    }
+    pub fn someday() -> Self {
+        Self {
+            section: Section::Someday,
+            todo: None,
+        }
+    }
+    pub fn search() -> Self {
+        Self {
+            section: Section::Search,
+            todo: None,
+        }
+    }
+    pub fn settings() -> Self {
+        Self {
+            section: Section::Settings,
+            todo: None,
+        }
+    }
+    pub fn with_todo(mut self, id: u64, title: impl Into<String>) -> Self {
+        self.todo = Some((id, title.into()));
+        self
+    }
+
+    // (label, url) from the section down to the current page
+    pub fn crumbs(&self) -> Vec<(String, String)> {
+        let mut crumbs = match self.section {
+            Section::Todos => vec![("Todos".to_string(), routes::Root::url())],
+            Section::Today => vec![("Today".to_string(), routes::Today::url())],
+            Section::Board => vec![("Board".to_string(), routes::Board::url())],
+            Section::Plan => vec![("Plan".to_string(), routes::Plan::url())],
+            Section::Calendar => vec![("Calendar".to_string(), routes::Calendar::url())],
+            Section::Goals => vec![("Goals".to_string(), routes::Goals::url())],
+            Section::Stats => vec![("Stats".to_string(), routes::Stats::url())],
+            Section::Activity => vec![("Activity".to_string(), routes::Activity::url())],
+            Section::Tags => vec![("Tags".to_string(), routes::Tags::url())],
+            Section::Review => vec![("Review".to_string(), routes::Review::url())],
+            Section::Someday => vec![("Someday".to_string(), routes::Someday::url())],
+            Section::Search => vec![("Search".to_string(), routes::Search::url())],
+            Section::Settings => vec![("Settings".to_string(), routes::Settings::url())],
+        };
+        if let Some((id, title)) = &self.todo {
+            crumbs.push((title.clone(), routes::TodoDetail::url(id)));
+        }
+        crumbs
+    }
+}
+
+// Sidebar links and breadcrumbs. Links are boosted, so htmx swaps the body and pushes the url.
+pub fn navigation(nav: &Nav) -> Markup {
+    render(nav, false)
+}
+// the navigation replacing the current one out of band, for fragment responses
+pub fn navigation_oob(nav: &Nav) -> Markup {
+    render(nav, true)
+}
+
+fn render(nav: &Nav, oob: bool) -> Markup {
+    html! {
+        nav id=(NAV_ID) class="flex items-center justify-between mb-6" hx-boost="true" hx-swap-oob=[oob.then_some("true")] {
+            ol class="flex text-gray-500" aria-label="Breadcrumb" {
+                @let crumbs = nav.crumbs();
+                @for (index, (label, url)) in crumbs.iter().enumerate() {
+                    li {
+                        @if index > 0 { span class="mx-2" { "/" } }
+                        @if index + 1 == crumbs.len() {
+                            span class="text-gray-700" aria-current="page" { (label) }
+                        } @else {
+                            a class="hover:text-gray-700" href=(url) { (label) }
+                        }
+                    }
+                }
+            }
+            ul class="flex space-x-4" {
+                // a "review due" badge, when one is
+                li hx-get=(routes::ReviewNudge::url()) hx-trigger="load" hx-swap="outerHTML" {}
+                // a "someday report" badge, once a month
+                li hx-get=(routes::SomedayNudge::url()) hx-trigger="load" hx-swap="outerHTML" {}
+                // today's progress towards the daily goal
+                li hx-get=(routes::DailyGoalRing::url()) hx-trigger="load" hx-swap="outerHTML" {}
+                // "groceries · 3 new", the lists others changed since the visitor last looked
+                li hx-get=(routes::ListBadges::url()) hx-trigger="load" hx-swap="outerHTML" {}
+                (section_link(nav, Section::Todos, "Todos", &routes::Root::url(), false))
+                (section_link(nav, Section::Today, "Today", &routes::Today::url(), false))
+                (section_link(nav, Section::Board, "Board", &routes::Board::url(), false))
+                (section_link(nav, Section::Plan, "Plan", &routes::Plan::url(), false))
+                (section_link(nav, Section::Calendar, "Calendar", &routes::Calendar::url(), false))
+                (section_link(nav, Section::Goals, "Goals", &routes::Goals::url(), false))
+                (section_link(nav, Section::Stats, "Stats", &routes::Stats::url(), false))
+                (section_link(nav, Section::Activity, "Activity", &routes::Activity::url(), false))
+                (section_link(nav, Section::Tags, "Tags", &routes::Tags::url(), false))
+                (section_link(nav, Section::Someday, "Someday", &routes::Someday::url(), false))
+                (section_link(nav, Section::Search, "Search", &routes::Search::url(), false))
+                // from the list, settings slide over it instead of replacing it
+                (section_link(nav, Section::Settings, "Settings", &routes::Settings::url(), nav.section == Section::Todos))
+            }
+        }
+    }
+}
+
+fn section_link(nav: &Nav, section: Section, label: &str, url: &str, in_panel: bool) -> Markup {
+    let active = nav.section == section;
+    html! {
+        li {
+            a class={ @if active { "font-bold text-gray-900" } @else { "text-blue-500 hover:text-blue-700" } }
+                href=(url) aria-current=[active.then_some("page")]
+                hx-get=[in_panel.then_some(url)] hx-target=[in_panel.then(|| format!("#{}", PANEL_ID))]
+                hx-push-url=[in_panel.then_some("true")] { (label) }
+        }
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crumbs() {
+        let nav = Nav::todos().with_todo(7, "Milk");
+        assert_eq!(
+            nav.crumbs(),
+            vec![
+                ("Todos".to_string(), "/".to_string()),
+                ("Milk".to_string(), "/todos/7".to_string()),
+            ]
+        );
+        assert_eq!(Nav::settings().crumbs()[0].1, "/settings");
+    }
+}
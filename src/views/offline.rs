@@ -0,0 +1,92 @@
+use maud::{html, Markup};
+
+pub const BANNER_ID: &str = "offline-banner";
+
+// Requests that do not get through show the banner: the server out of reach, timed out or
+// answering 5xx. Failed GETs are sent again after 1s, 2s, 4s… up to 30s, or after the
+// `Retry-After` of a busy server when that is longer; writes are not repeated, they may have
+// reached the server. The next request that gets an answer hides the banner again.
+pub(super) const RETRY_SCRIPT: &str = r#"
+(function () {
+    const banner = document.getElementById("offline-banner");
+    const message = banner.querySelector("[data-offline-message]");
+    const countdown = banner.querySelector("[data-offline-retry]");
+    const first = 1000, longest = 30000;
+    let attempt = 0, timer = null, pending = null;
+    const retry = function () {
+        clearTimeout(timer);
+        const request = pending;
+        pending = null;
+        if (!request || !document.body.contains(request.source)) return;
+        htmx.ajax("GET", request.path, { source: request.source, target: request.target });
+    };
+    const failed = function (detail, text) {
+        message.textContent = text;
+        banner.classList.remove("hidden");
+        clearTimeout(timer);
+        if (detail.requestConfig.verb !== "get") {
+            pending = null;
+            countdown.textContent = "";
+            return;
+        }
+        const retryAfter = Number(detail.xhr.getResponseHeader("Retry-After")) * 1000 || 0;
+        const delay = Math.max(Math.min(first * Math.pow(2, attempt), longest), retryAfter);
+        attempt += 1;
+        pending = { path: detail.pathInfo.requestPath, source: detail.elt, target: detail.target };
+        countdown.textContent = "Trying again in " + Math.round(delay / 1000) + "s.";
+        timer = setTimeout(retry, delay);
+    };
+    const recovered = function () {
+        if (banner.classList.contains("hidden")) return;
+        clearTimeout(timer);
+        attempt = 0;
+        pending = null;
+        banner.classList.add("hidden");
+    };
+    document.body.addEventListener("htmx:sendError", function (evt) {
+        failed(evt.detail, "Can't reach the server, check your connection.");
+    });
+    document.body.addEventListener("htmx:timeout", function (evt) {
+        failed(evt.detail, "The server is taking too long to answer.");
+    });
+    document.body.addEventListener("htmx:afterRequest", function (evt) {
+        const status = evt.detail.xhr.status;
+        if (status >= 500) failed(evt.detail, "The server ran into a problem.");
+        else if (status > 0) recovered();
+    });
+    banner.querySelector("button").addEventListener("click", retry);
+    window.addEventListener("online", retry);
+})();
+"#;
+
+// === Components ===
+// The banner every page carries, hidden until a request fails. It is rendered here rather than
+// built by the script, so it looks like the maintenance banner above it.
+pub fn banner_slot() -> Markup {
+    html! {
+        div id=(BANNER_ID) class="hidden t-warning bg-red-100 text-center py-2 px-4" role="alert" aria-live="assertive" {
+            span data-offline-message {}
+            " "
+            span data-offline-retry {}
+            " "
+            button class="underline" type="button" { "Retry now" }
+        }
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_banner_slot() {
+        let banner = banner_slot().into_string();
+        assert!(banner.contains(r#"id="offline-banner" class="hidden"#));
+        // the script looks these up
+        assert!(RETRY_SCRIPT.contains(BANNER_ID));
+        for hook in ["data-offline-message", "data-offline-retry", "<button"] {
+            assert!(banner.contains(hook));
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use std::{collections::HashMap, fs, io::ErrorKind, path::Path, sync::OnceLock};
+
+use anyhow::{Context, Result};
+use maud::{html, Markup, PreEscaped};
+
+// what `TEMPLATE_DIR` put in place, read once at startup
+static OVERRIDES: OnceLock<Overrides> = OnceLock::new();
+
+// === Parts ===
+// The components a self-hoster may replace, each by an html file of its name. `{{name}}`
+// placeholders are filled in with escaped values, see `render`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Part {
+    // above every page, in place of the branding header
+    Header,
+    // below every page, there is none built in
+    Footer,
+    // a todo of the list, its root element has to carry `{{attributes}}`
+    TodoItem,
+}
+impl Part {
+    pub const ALL: [Part; 3] = [Part::Header, Part::Footer, Part::TodoItem];
+
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Part::Header => "header.html",
+            Part::Footer => "footer.html",
+            Part::TodoItem => "todo_item.html",
+        }
+    }
+    // what the template has to contain for htmx to keep working with it
+    fn required(self) -> &'static [&'static str] {
+        match self {
+            Part::TodoItem => &["{{attributes}}"],
+            _ => &[],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Overrides(HashMap<Part, String>);
+impl Overrides {
+    pub fn get(&self, part: Part) -> Option<&str> {
+        self.0.get(&part).map(String::as_str)
+    }
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+// Read the templates in `dir`, parts without a file keep the built-in component. Files that are
+// not a part are warned about, a template missing what it needs fails the start.
+pub fn load(dir: &Path) -> Result<Overrides> {
+    let mut overrides = Overrides::default();
+    for part in Part::ALL {
+        let path = dir.join(part.file_name());
+        let template = match fs::read_to_string(&path) {
+            Ok(template) => template,
+            Err(err) if err.kind() == ErrorKind::NotFound => continue,
+            Err(err) => {
+                return Err(err).with_context(|| format!("could not read {}", path.display()))
+            }
+        };
+        for needed in part.required() {
+            anyhow::ensure!(
+                template.contains(needed),
+                "{} has to contain {}",
+                path.display(),
+                needed
+            );
+        }
+        overrides.0.insert(part, template);
+    }
+    for entry in fs::read_dir(dir).with_context(|| format!("could not read {}", dir.display()))? {
+        let name = entry?.file_name();
+        let name = name.to_string_lossy();
+        if !Part::ALL.iter().any(|part| part.file_name() == name) {
+            tracing::warn!(file = %name, "not a template that can be overridden, ignored");
+        }
+    }
+    Ok(overrides)
+}
+
+// put `overrides` in place for every page, only the first call counts
+pub fn install(overrides: Overrides) {
+    let _ = OVERRIDES.set(overrides);
+}
+
+// the template standing in for `part`, if there is one
+pub fn get(part: Part) -> Option<&'static str> {
+    OVERRIDES.get()?.get(part)
+}
+
+// === Rendering ===
+// `template` with its placeholders filled in. Values are escaped, those named in `raw` are markup
+// built here and go in as they are. Unknown placeholders are left alone.
+pub fn render(template: &str, values: &[(&str, &str)], raw: &[(&str, &str)]) -> Markup {
+    // markup first, a value that reads like a placeholder stays text
+    let mut text = template.to_string();
+    for (name, markup) in raw {
+        text = text.replace(&format!("{{{{{}}}}}", name), markup);
+    }
+    for (name, value) in values {
+        let escaped = html! { (value) }.into_string();
+        text = text.replace(&format!("{{{{{}}}}}", name), &escaped);
+    }
+    PreEscaped(text)
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let rendered = render(
+            "<li {{attributes}}>{{title}} {{unknown}}</li>",
+            &[("title", "<b>Milk</b> & eggs")],
+            &[("attributes", r#"id="todo-1""#)],
+        );
+        assert_eq!(
+            rendered.into_string(),
+            r#"<li id="todo-1">&lt;b&gt;Milk&lt;/b&gt; &amp; eggs {{unknown}}</li>"#
+        );
+    }
+
+    #[test]
+    fn test_load() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("templates_{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join("footer.html"), "<footer>{{title}}</footer>")?;
+        fs::write(dir.join("sidebar.html"), "<aside></aside>")?;
+        let overrides = load(&dir)?;
+        assert_eq!(overrides.len(), 1);
+        assert_eq!(
+            overrides.get(Part::Footer),
+            Some("<footer>{{title}}</footer>")
+        );
+        assert_eq!(overrides.get(Part::Header), None);
+
+        // a todo item htmx could not swap is refused
+        fs::write(dir.join("todo_item.html"), "<li>{{title}}</li>")?;
+        assert!(load(&dir).is_err());
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
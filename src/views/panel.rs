@@ -0,0 +1,69 @@
+use axum::http::HeaderMap;
+use maud::{html, Markup, PreEscaped};
+
+use super::nav::{self, Nav};
+use crate::routes;
+
+pub const PANEL_ID: &str = "panel";
+
+// Escape closes the panel, Tab cycles inside it while open, focus moves in when it opens
+pub(super) const PANEL_SCRIPT: &str = r#"
+(function () {
+    const panel = document.getElementById("panel");
+    const focusable = () => panel.querySelectorAll("a[href], button, input, select, textarea, [tabindex]:not([tabindex='-1'])");
+    document.addEventListener("keydown", function (evt) {
+        if (!panel.firstElementChild) return;
+        if (evt.key === "Escape") {
+            panel.querySelector("[aria-label=Close]").click();
+        } else if (evt.key === "Tab") {
+            const items = focusable();
+            if (items.length === 0) return;
+            const first = items[0], last = items[items.length - 1];
+            if (evt.shiftKey && document.activeElement === first) { last.focus(); evt.preventDefault(); }
+            else if (!evt.shiftKey && document.activeElement === last) { first.focus(); evt.preventDefault(); }
+        }
+    });
+    document.body.addEventListener("htmx:afterSwap", function (evt) {
+        if (evt.detail.target === panel && focusable().length > 0) focusable()[0].focus();
+    });
+})();
+"#;
+
+// whether an htmx request asked for its response to be swapped into the panel
+pub fn is_panel_request(headers: &HeaderMap) -> bool {
+    super::wants_fragment(headers)
+        && headers
+            .get("hx-target")
+            .is_some_and(|target| target.as_bytes() == PANEL_ID.as_bytes())
+}
+
+// the empty slot every page carries, panels are swapped into it
+pub fn panel_slot() -> Markup {
+    html! {
+        div id=(PANEL_ID) {}
+    }
+}
+
+// A slide-over from the right with a backdrop, updating the navigation to `nav`. Clicking the
+// backdrop or the close button empties the slot again and goes back to the todo list.
+pub fn panel(title: &str, nav: &Nav, content: Markup) -> Markup {
+    html! {
+        (nav::navigation_oob(nav))
+        div class="fixed inset-0 z-40 bg-gray-900/50" hx-get=(routes::PanelClose::url()) hx-target={ "#" (PANEL_ID) }
+            hx-push-url=(routes::Root::url()) {}
+        aside class="fixed inset-y-0 right-0 z-50 w-full max-w-lg bg-white shadow-xl p-6 overflow-y-auto"
+            role="dialog" aria-modal="true" aria-labelledby="panel-title" {
+            div class="flex items-center justify-between mb-4" {
+                h2 id="panel-title" class="text-2xl text-gray-700" { (title) }
+                button class="text-gray-500 hover:text-gray-700 text-2xl" type="button" aria-label="Close"
+                    hx-get=(routes::PanelClose::url()) hx-target={ "#" (PANEL_ID) } hx-push-url=(routes::Root::url()) { (PreEscaped("&times;")) }
+            }
+            (content)
+        }
+    }
+}
+
+// === Routes ===
+pub async fn close() -> Markup {
+    nav::navigation_oob(&Nav::todos())
+}
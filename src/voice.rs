@@ -0,0 +1,176 @@
+use std::{fmt, future::Future, pin::Pin, time::Duration};
+
+use anyhow::Result;
+use maud::{html, Markup, PreEscaped};
+use rand::RngCore;
+use serde::Deserialize;
+
+use crate::{routes, views::dom};
+
+// transcribing a few seconds of speech on a small machine can take a while
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+pub type TranscribeFuture<'a> = Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>>;
+
+// Turns recorded speech into text, `content_type` is the recording's, e.g. `audio/webm`.
+pub trait Transcriber: fmt::Debug + Send + Sync {
+    fn transcribe<'a>(&'a self, audio: &'a [u8], content_type: &'a str) -> TranscribeFuture<'a>;
+}
+
+// === Whisper ===
+// The `/inference` endpoint of a whisper.cpp server. OpenAI-compatible servers take the same
+// upload at `/v1/audio/transcriptions`, point `TRANSCRIPTION_URL` at the full url for those.
+#[derive(Debug)]
+pub struct WhisperHttp {
+    client: reqwest::Client,
+    url: String,
+}
+impl WhisperHttp {
+    pub fn new(url: &str) -> Result<Self> {
+        let url = url.trim_end_matches('/');
+        let url = if url.ends_with("/inference") || url.ends_with("/transcriptions") {
+            url.to_string()
+        } else {
+            format!("{}/inference", url)
+        };
+        Ok(Self {
+            client: reqwest::Client::builder().timeout(TIMEOUT).build()?,
+            url,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct Transcript {
+    text: String,
+}
+
+impl Transcriber for WhisperHttp {
+    fn transcribe<'a>(&'a self, audio: &'a [u8], content_type: &'a str) -> TranscribeFuture<'a> {
+        Box::pin(async move {
+            let mut boundary = [0; 12];
+            rand::thread_rng().fill_bytes(&mut boundary);
+            let boundary = hex::encode(boundary);
+            let body = multipart(
+                &boundary,
+                &[("response_format", "json"), ("model", "whisper-1")],
+                ("file", "speech", content_type, audio),
+            );
+            let transcript: Transcript = self
+                .client
+                .post(&self.url)
+                .header(
+                    reqwest::header::CONTENT_TYPE,
+                    format!("multipart/form-data; boundary={}", boundary),
+                )
+                .body(body)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            Ok(transcript.text.trim().to_string())
+        })
+    }
+}
+
+// a multipart/form-data body of text `fields` and one file, `(name, filename, type, bytes)`
+fn multipart(
+    boundary: &str,
+    fields: &[(&str, &str)],
+    (name, filename, content_type, bytes): (&str, &str, &str, &[u8]),
+) -> Vec<u8> {
+    let mut body = Vec::new();
+    for (name, value) in fields {
+        body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                boundary, name, value
+            )
+            .as_bytes(),
+        );
+    }
+    body.extend_from_slice(
+        format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: {}\r\n\r\n",
+            boundary, name, filename, content_type
+        )
+        .as_bytes(),
+    );
+    body.extend_from_slice(bytes);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+    body
+}
+
+// === Components ===
+// Records while pressed once, uploads on the second press and adds the todo the server made of
+// it to the list, like the create form does.
+const MIC_SCRIPT: &str = r##"
+(function (button) {
+    let recorder = null;
+    button.addEventListener("click", async function () {
+        if (recorder) { recorder.stop(); return; }
+        const stream = await navigator.mediaDevices.getUserMedia({ audio: true });
+        const chunks = [];
+        recorder = new MediaRecorder(stream);
+        recorder.addEventListener("dataavailable", (evt) => chunks.push(evt.data));
+        recorder.addEventListener("stop", async function () {
+            stream.getTracks().forEach((track) => track.stop());
+            button.classList.remove("animate-pulse");
+            const blob = new Blob(chunks, { type: recorder.mimeType });
+            recorder = null;
+            const response = await fetch(button.dataset.url, {
+                method: "POST", body: blob, headers: { "Content-Type": blob.type, "HX-Request": "true" },
+            });
+            if (!response.ok) return;
+            const list = document.querySelector(button.dataset.list);
+            list.insertAdjacentHTML("beforeend", await response.text());
+            htmx.process(list.lastElementChild);
+        });
+        recorder.start();
+        button.classList.add("animate-pulse");
+    });
+})(document.currentScript.previousElementSibling);
+"##;
+
+// only rendered when a transcription backend is configured
+pub fn mic_button_html() -> Markup {
+    html! {
+        button class="rounded-full bg-gray-200 hover:bg-gray-300 p-2 mr-4" type="button" title="Add by voice"
+            aria-label="Add by voice" data-url=(routes::QuickAddAudio::url())
+            data-list=(dom::selector(&dom::list_items(dom::OPEN_LIST))) { "🎤" }
+        script { (PreEscaped(MIC_SCRIPT)) }
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multipart() {
+        let body = multipart(
+            "b0undary",
+            &[("response_format", "json")],
+            ("file", "speech", "audio/webm", b"\x1a\x45"),
+        );
+        let expected = b"--b0undary\r\nContent-Disposition: form-data; name=\"response_format\"\r\n\r\njson\r\n\
+--b0undary\r\nContent-Disposition: form-data; name=\"file\"; filename=\"speech\"\r\nContent-Type: audio/webm\r\n\r\n\
+\x1a\x45\r\n--b0undary--\r\n";
+        assert_eq!(body, expected.to_vec());
+    }
+
+    #[test]
+    fn test_inference_url() -> Result<()> {
+        assert_eq!(
+            WhisperHttp::new("http://localhost:8080/")?.url,
+            "http://localhost:8080/inference"
+        );
+        assert_eq!(
+            WhisperHttp::new("https://api.example.com/v1/audio/transcriptions")?.url,
+            "https://api.example.com/v1/audio/transcriptions"
+        );
+        Ok(())
+    }
+}
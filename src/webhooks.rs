@@ -0,0 +1,69 @@
+use anyhow::Result;
+use serde_json::json;
+
+use crate::{
+    db::{driver::Db, queue::WriteOp},
+    domain::events::DomainEvent,
+    state::AppState,
+    tasks::{self, TaskKind},
+};
+
+// The body posted to `WEBHOOK_URL`, only todos are delivered. Shared links are secrets and are
+// kept to this server.
+pub fn payload(event: &DomainEvent, tenant: Option<&str>) -> Option<String> {
+    let todo = event.todo()?;
+    Some(
+        json!({
+            "event": event.name(),
+            "tenant": tenant,
+            "todo": todo,
+        })
+        .to_string(),
+    )
+}
+
+// The delivery of `event` to `WEBHOOK_URL`, to go into the batch of the change itself. Lists
+// with a webhook of their own deliver through `integrations` instead.
+pub fn ops(
+    state: &AppState,
+    db: &Db,
+    tenant: Option<&str>,
+    event: &DomainEvent,
+) -> Result<Vec<WriteOp>> {
+    let (Some(url), Some(body)) = (&state.config.webhook_url, payload(event, tenant)) else {
+        return Ok(Vec::new());
+    };
+    Ok(vec![tasks::enqueue_op(
+        db,
+        TaskKind::Webhook {
+            url: url.clone(),
+            body,
+        },
+    )?])
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+    use crate::models::Todo;
+
+    #[test]
+    fn test_payload() -> Result<()> {
+        let mut todo = Todo::new(7, "Water the plants".into());
+        todo.set_completed(true);
+        let event = DomainEvent::toggled(todo);
+        let body: Value = serde_json::from_str(&payload(&event, None).unwrap())?;
+        assert_eq!(body["event"], "todo.completed");
+        assert_eq!(body["tenant"], Value::Null);
+        assert_eq!(body["todo"]["id"], 7);
+
+        let shared = DomainEvent::ListShared {
+            token: "abc".into(),
+        };
+        assert_eq!(payload(&shared, None), None);
+        Ok(())
+    }
+}
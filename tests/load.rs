@@ -0,0 +1,190 @@
+// Load test against a real server with a seeded database. It is slow and timing dependent, so
+// it only runs when asked for:
+//
+//     cargo test --release --test load -- --ignored
+//
+// Every simulated user lists, creates and toggles todos in a loop. The 95th percentile latency
+// of each route has to stay within its budget.
+
+use std::{
+    net::TcpListener,
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use rand::seq::SliceRandom;
+use serde::Deserialize;
+
+// === Budget ===
+const SEEDED_TODOS: usize = 500;
+const USERS: usize = 32;
+const ROUNDS: usize = 20;
+
+const LIST_BUDGET: Duration = Duration::from_millis(150);
+const CREATE_BUDGET: Duration = Duration::from_millis(100);
+const TOGGLE_BUDGET: Duration = Duration::from_millis(100);
+
+// === Server ===
+// the app running in its own directory, killed and cleaned up on drop
+struct Server {
+    child: Child,
+    dir: PathBuf,
+    url: String,
+}
+impl Server {
+    async fn start() -> Self {
+        let tick = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let dir = std::env::temp_dir().join(format!("test_load_{}", tick));
+        std::fs::create_dir_all(&dir).unwrap();
+        // the os picks a free port, released again right before the server binds it
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let child = Command::new(env!("CARGO_BIN_EXE_rust-htmx"))
+            .current_dir(&dir)
+            .env("LISTEN_ADDR", addr.to_string())
+            .env("CONCURRENCY_LIMIT", "256")
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+        let server = Self {
+            child,
+            dir,
+            url: format!("http://{}", addr),
+        };
+
+        let client = reqwest::Client::new();
+        let deadline = Instant::now() + Duration::from_secs(30);
+        while client.get(&server.url).send().await.is_err() {
+            assert!(Instant::now() < deadline, "server did not come up");
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        server
+    }
+}
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[derive(Deserialize)]
+struct TodoResource {
+    id: u64,
+}
+
+async fn create(client: &reqwest::Client, url: &str, title: &str) {
+    let response = client
+        .put(format!("{}/create_todo", url))
+        .header("HX-Request", "true")
+        .form(&[("title", title)])
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success(), "{}", response.status());
+}
+
+async fn seed(client: &reqwest::Client, url: &str) -> Vec<u64> {
+    for i in 0..SEEDED_TODOS {
+        create(client, url, &format!("Seeded todo {}", i)).await;
+    }
+    let todos: Vec<TodoResource> = client
+        .get(format!("{}/api/v1/todos?page_size=200", url))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    todos.into_iter().map(|todo| todo.id).collect()
+}
+
+// === Measurements ===
+#[derive(Default)]
+struct Timings {
+    list: Vec<Duration>,
+    create: Vec<Duration>,
+    toggle: Vec<Duration>,
+}
+
+async fn user(client: reqwest::Client, url: String, ids: Vec<u64>, user: usize) -> Timings {
+    let mut timings = Timings::default();
+    for round in 0..ROUNDS {
+        let start = Instant::now();
+        let response = client
+            .get(format!("{}/todos", url))
+            .header("HX-Request", "true")
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success(), "{}", response.status());
+        response.bytes().await.unwrap();
+        timings.list.push(start.elapsed());
+
+        let start = Instant::now();
+        create(&client, &url, &format!("Todo {} of user {}", round, user)).await;
+        timings.create.push(start.elapsed());
+
+        let id = *ids.choose(&mut rand::thread_rng()).unwrap();
+        let start = Instant::now();
+        let response = client
+            .post(format!("{}/toggle_todo", url))
+            .header("HX-Request", "true")
+            .form(&[("id", id.to_string())])
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success(), "{}", response.status());
+        timings.toggle.push(start.elapsed());
+    }
+    timings
+}
+
+fn p95(mut samples: Vec<Duration>) -> Duration {
+    samples.sort();
+    samples[(samples.len() * 95 / 100).min(samples.len() - 1)]
+}
+
+// Tests
+#[tokio::test(flavor = "multi_thread")]
+#[ignore]
+async fn test_p95_latency_within_budget() {
+    let server = Server::start().await;
+    let client = reqwest::Client::new();
+    let ids = seed(&client, &server.url).await;
+    assert!(!ids.is_empty());
+
+    let users: Vec<_> = (0..USERS)
+        .map(|i| tokio::spawn(user(client.clone(), server.url.clone(), ids.clone(), i)))
+        .collect();
+    let mut timings = Timings::default();
+    for user in users {
+        let user = user.await.unwrap();
+        timings.list.extend(user.list);
+        timings.create.extend(user.create);
+        timings.toggle.extend(user.toggle);
+    }
+
+    for (route, samples, budget) in [
+        ("list", timings.list, LIST_BUDGET),
+        ("create", timings.create, CREATE_BUDGET),
+        ("toggle", timings.toggle, TOGGLE_BUDGET),
+    ] {
+        let p95 = p95(samples);
+        println!("{}: p95 {:?}, budget {:?}", route, p95, budget);
+        assert!(
+            p95 <= budget,
+            "{} p95 {:?} over budget {:?}",
+            route,
+            p95,
+            budget
+        );
+    }
+}